@@ -0,0 +1,258 @@
+extern crate midi_graph;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use midi_graph::{
+    consts,
+    quick::midi_with_sf2_config,
+    util::{
+        add_buffer, add_interleaved, add_scaled_buffer, midi_builder_from_bytes, peak_of,
+        scale_buffer, soundfont_from_file, wav_from_file,
+    },
+    BufferConsumerNode, CombinerSource, Envelope, FileGraphLoader, GraphLoader, LfsrNoiseSource,
+    LoopRange, MixerSource, NodeEvent, NoteRange, SawtoothWaveSource, SoundFontBuilder,
+    SquareWaveSource, TriangleWaveSource,
+};
+
+const MIDI_FILE: &str = "resources/sample-in-c.mid";
+const WAV_FILE: &str = "resources/guitar-a2-48k-stereo.wav";
+
+// The bundled font holds a sampled guitar. Set MIDI_GRAPH_BENCH_SF2 to the path of another
+// SF2 file, and MIDI_GRAPH_BENCH_SF2_INSTRUMENT to an instrument index in it, to bench a
+// piano or any other instrument with the same graphs.
+const SF2_FILE: &str = "resources/demo-font.sf2";
+
+fn sf2_instrument() -> (String, usize) {
+    let path = std::env::var("MIDI_GRAPH_BENCH_SF2").unwrap_or_else(|_| SF2_FILE.to_owned());
+    let instrument_index = std::env::var("MIDI_GRAPH_BENCH_SF2_INSTRUMENT")
+        .ok()
+        .and_then(|index| index.parse().ok())
+        .unwrap_or(0);
+    (path, instrument_index)
+}
+
+// Notes spread over the middle of the keyboard, as many as there are voices to play them
+fn chord(voices: u8) -> Vec<u8> {
+    (0..voices).map(|voice| 36 + voice * 2).collect()
+}
+
+fn note_on(note: u8) -> NodeEvent {
+    NodeEvent::note_on(note, 1.0)
+}
+
+fn bench_node(
+    c: &mut Criterion,
+    name: &str,
+    mut node: Box<dyn BufferConsumerNode + Send + 'static>,
+    notes: &[u8],
+) {
+    for note in notes.iter() {
+        node.on_event(&note_on(*note));
+    }
+    let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+    c.bench_function(name, |b| {
+        b.iter(|| {
+            buffer.fill(0.0);
+            node.fill_buffer(black_box(&mut buffer));
+        })
+    });
+}
+
+// The mixing helpers on their own, over a whole buffer
+fn buffers(c: &mut Criterion) {
+    let data_points = consts::BUFFER_SIZE * consts::CHANNEL_COUNT;
+    let src: Vec<f32> = (0..data_points).map(|i| (i as f32).sin()).collect();
+    let mut dst = vec![0.0; data_points];
+    c.bench_function("add_buffer", |b| {
+        b.iter(|| add_buffer(black_box(&mut dst), black_box(&src)))
+    });
+    c.bench_function("add_scaled_buffer", |b| {
+        b.iter(|| add_scaled_buffer(black_box(&mut dst), black_box(&src), 0.5))
+    });
+    c.bench_function("scale_buffer", |b| {
+        b.iter(|| scale_buffer(black_box(&mut dst), 0.5))
+    });
+    c.bench_function("add_interleaved", |b| {
+        let (left, right) = src.split_at(consts::BUFFER_SIZE);
+        b.iter(|| add_interleaved(black_box(&mut dst), black_box(left), black_box(right), 0.5))
+    });
+    c.bench_function("peak_of", |b| b.iter(|| peak_of(black_box(&src))));
+}
+
+fn generators(c: &mut Criterion) {
+    bench_node(
+        c,
+        "square",
+        Box::new(SquareWaveSource::new(None, 0.5, 0.5)),
+        &[69],
+    );
+    bench_node(
+        c,
+        "triangle",
+        Box::new(TriangleWaveSource::new(None, 0.5)),
+        &[69],
+    );
+    bench_node(
+        c,
+        "sawtooth",
+        Box::new(SawtoothWaveSource::new(None, 0.5)),
+        &[69],
+    );
+    bench_node(
+        c,
+        "noise",
+        Box::new(LfsrNoiseSource::new(None, 0.5, false, 64)),
+        &[69],
+    );
+    let wav = wav_from_file(
+        WAV_FILE,
+        45,
+        Some(LoopRange::new_frame_range(2590, 6557)),
+        None,
+    )
+    .unwrap();
+    bench_node(c, "wav", Box::new(wav), &[57]);
+}
+
+fn wrappers(c: &mut Criterion) {
+    let envelope = Envelope::from_adsr(
+        None,
+        0.125,
+        0.25,
+        0.5,
+        0.125,
+        Box::new(SquareWaveSource::new(None, 0.5, 0.5)),
+    );
+    bench_node(c, "envelope", Box::new(envelope), &[69]);
+
+    let mixer = MixerSource::new(
+        None,
+        0.5,
+        Box::new(SquareWaveSource::new(None, 0.5, 0.5)),
+        Box::new(TriangleWaveSource::new(None, 0.5)),
+    );
+    bench_node(c, "mixer", Box::new(mixer), &[69]);
+
+    let sources: Vec<Box<dyn BufferConsumerNode + Send + 'static>> = (0..8)
+        .map(|_| {
+            Box::new(SquareWaveSource::new(None, 0.125, 0.5))
+                as Box<dyn BufferConsumerNode + Send + 'static>
+        })
+        .collect();
+    bench_node(
+        c,
+        "combiner_8",
+        Box::new(CombinerSource::new(None, sources)),
+        &[69],
+    );
+}
+
+fn fonts(c: &mut Criterion) {
+    let font = SoundFontBuilder::new(None)
+        .add_range(
+            NoteRange::new_full_range(),
+            Box::new(SquareWaveSource::new(None, 0.125, 0.5)),
+        )
+        .unwrap()
+        .build();
+    bench_node(c, "font_idle", Box::new(font), &[]);
+
+    let font = SoundFontBuilder::new(None)
+        .add_range(
+            NoteRange::new_full_range(),
+            Box::new(SquareWaveSource::new(None, 0.125, 0.5)),
+        )
+        .unwrap()
+        .build();
+    bench_node(
+        c,
+        "font_8_voices",
+        Box::new(font),
+        &[60, 62, 64, 65, 67, 69, 71, 72],
+    );
+
+    let font = SoundFontBuilder::new(None)
+        .with_max_voices(32)
+        .add_range(
+            NoteRange::new_full_range(),
+            Box::new(SquareWaveSource::new(None, 0.125, 0.5)),
+        )
+        .unwrap()
+        .build();
+    bench_node(c, "font_32_voices", Box::new(font), &chord(32));
+
+    let (sf2_path, instrument_index) = sf2_instrument();
+    for voices in [8, 32] {
+        let mut font = soundfont_from_file(None, &sf2_path, instrument_index).unwrap();
+        font.set_max_voices(voices as usize).unwrap();
+        let name = format!("sf2_{}_voices", voices);
+        bench_node(c, &name, Box::new(font), &chord(voices));
+    }
+}
+
+// How much of a song to render per iteration, which covers all of the MIDI file; a sampled
+// instrument may ring on past the end, so rendering stops here even if it is still active
+const SONG_SECONDS: usize = 16;
+
+// Render the song from its start each iteration, from a graph built afresh for it so that
+// no iteration runs past the end of the song
+fn bench_song<F>(c: &mut Criterion, name: &str, build: F)
+where
+    F: Fn() -> Box<dyn BufferConsumerNode + Send + 'static>,
+{
+    let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+    let mut group = c.benchmark_group("songs");
+    group.sample_size(10);
+    group.bench_function(name, |b| {
+        b.iter_batched(
+            &build,
+            |mut song| {
+                let song_buffers =
+                    SONG_SECONDS * consts::PLAYBACK_SAMPLE_RATE / consts::BUFFER_SIZE;
+                for _ in 0..song_buffers {
+                    if !song.is_active() {
+                        break;
+                    }
+                    buffer.fill(0.0);
+                    song.fill_buffer(black_box(&mut buffer));
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn songs(c: &mut Criterion) {
+    let midi_bytes = std::fs::read(MIDI_FILE).unwrap();
+    bench_song(c, "midi", || {
+        let midi = midi_builder_from_bytes(None, &midi_bytes)
+            .unwrap()
+            .add_channel_source(
+                0,
+                Box::new(
+                    SoundFontBuilder::new(None)
+                        .add_range(
+                            NoteRange::new_full_range(),
+                            Box::new(SquareWaveSource::new(None, 0.125, 0.5)),
+                        )
+                        .unwrap()
+                        .build(),
+                ),
+            )
+            .build()
+            .unwrap();
+        Box::new(midi)
+    });
+
+    // Every channel of the song played by the SF2 instrument, as quick::play_midi_with_sf2
+    // plays it
+    let (sf2_path, instrument_index) = sf2_instrument();
+    let config = midi_with_sf2_config(MIDI_FILE, &sf2_path, instrument_index).unwrap();
+    bench_song(c, "sf2_midi", || {
+        let (_, graph) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+        graph
+    });
+}
+
+criterion_group!(benches, buffers, generators, wrappers, fonts, songs);
+criterion_main!(benches);
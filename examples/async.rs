@@ -2,8 +2,8 @@ extern crate midi_graph;
 
 use crossbeam_channel::Sender;
 use midi_graph::{
-    AsyncEventReceiver, BaseMixer, Fader, MixerSource, NodeControlEvent, NodeEvent, NoteEvent,
-    NoteRange, SawtoothWaveSource, SoundFontBuilder, SquareWaveSource, TriangleWaveSource,
+    AsyncEventReceiver, BaseMixer, Fader, MixerSource, NodeControlEvent, NodeEvent, NoteRange,
+    SawtoothWaveSource, SoundFontBuilder, SquareWaveSource, TriangleWaveSource,
 };
 use std::{thread::sleep, time::Duration};
 
@@ -34,13 +34,7 @@ fn main() {
         BaseMixer::start_single_program(Box::new(receiver)).expect("Could not open stream");
     std::thread::spawn(move || {
         sleep(Duration::from_millis(50));
-        send_or_log(
-            &mut sender,
-            &NodeEvent::Note {
-                note: 69,
-                event: NoteEvent::NoteOn { vel: 1.0 },
-            },
-        );
+        send_or_log(&mut sender, &NodeEvent::note_on(69, 1.0));
         for _ in 0..10 {
             sleep(Duration::from_millis(100));
             send_or_log(
@@ -60,13 +54,7 @@ fn main() {
             );
         }
         sleep(Duration::from_millis(500));
-        send_or_log(
-            &mut sender,
-            &NodeEvent::Note {
-                note: 69,
-                event: NoteEvent::NoteOff { vel: 0.0 },
-            },
-        );
+        send_or_log(&mut sender, &NodeEvent::note_off(69, 0.0));
         send_or_log(
             &mut sender,
             &NodeEvent::NodeControl {
@@ -78,66 +66,18 @@ fn main() {
                 },
             },
         );
-        send_or_log(
-            &mut sender,
-            &NodeEvent::Note {
-                note: 73,
-                event: NoteEvent::NoteOn { vel: 0.375 },
-            },
-        );
+        send_or_log(&mut sender, &NodeEvent::note_on(73, 0.375));
         sleep(Duration::from_millis(500));
-        send_or_log(
-            &mut sender,
-            &NodeEvent::Note {
-                note: 73,
-                event: NoteEvent::NoteOff { vel: 0.0 },
-            },
-        );
-        send_or_log(
-            &mut sender,
-            &NodeEvent::Note {
-                note: 74,
-                event: NoteEvent::NoteOn { vel: 0.75 },
-            },
-        );
+        send_or_log(&mut sender, &NodeEvent::note_off(73, 0.0));
+        send_or_log(&mut sender, &NodeEvent::note_on(74, 0.75));
         sleep(Duration::from_millis(500));
-        send_or_log(
-            &mut sender,
-            &NodeEvent::Note {
-                note: 74,
-                event: NoteEvent::NoteOff { vel: 0.0 },
-            },
-        );
-        send_or_log(
-            &mut sender,
-            &NodeEvent::Note {
-                note: 71,
-                event: NoteEvent::NoteOn { vel: 0.375 },
-            },
-        );
+        send_or_log(&mut sender, &NodeEvent::note_off(74, 0.0));
+        send_or_log(&mut sender, &NodeEvent::note_on(71, 0.375));
         sleep(Duration::from_millis(500));
-        send_or_log(
-            &mut sender,
-            &NodeEvent::Note {
-                note: 71,
-                event: NoteEvent::NoteOff { vel: 0.0 },
-            },
-        );
-        send_or_log(
-            &mut sender,
-            &NodeEvent::Note {
-                note: 69,
-                event: NoteEvent::NoteOn { vel: 1.0 },
-            },
-        );
+        send_or_log(&mut sender, &NodeEvent::note_off(71, 0.0));
+        send_or_log(&mut sender, &NodeEvent::note_on(69, 1.0));
         sleep(Duration::from_millis(1000));
-        send_or_log(
-            &mut sender,
-            &NodeEvent::Note {
-                note: 69,
-                event: NoteEvent::NoteOff { vel: 0.0 },
-            },
-        );
+        send_or_log(&mut sender, &NodeEvent::note_off(69, 0.0));
     });
     sleep(Duration::from_secs(5));
 }
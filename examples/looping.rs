@@ -3,11 +3,12 @@ extern crate midi_graph;
 use crossbeam_channel::Sender;
 use midi_graph::{
     BaseMixer, FileGraphLoader, FontSource, GraphLoader, MidiDataSource, NodeControlEvent,
-    NodeEvent, RangeSource, SoundSource,
+    NodeEvent, OscillatorMode, ParamValue, PitchMotion, RangeSource, SoundSource, Unison,
+    VoiceStealing,
 };
 use std::{collections::HashMap, thread::sleep, time::Duration};
 
-const MIDI_FILE: &'static str = "resources/LoopingMidi.mid";
+const MIDI_FILE: &str = "resources/LoopingMidi.mid";
 
 const NOISE_CHANNEL: usize = 0;
 const LEAD_CHANNEL: usize = 1;
@@ -21,6 +22,7 @@ fn main() {
         source: Box::new(SoundSource::Midi {
             node_id: Some(MIDI_NODE_ID),
             source: MidiDataSource::FilePath(MIDI_FILE.to_owned()),
+            sequence_index: 0,
             channels: HashMap::from([
                 (
                     NOISE_CHANNEL,
@@ -29,17 +31,22 @@ fn main() {
                         config: FontSource::Ranges(vec![RangeSource {
                             source: SoundSource::Fader {
                                 node_id: Some(FADER_NODE_ID),
-                                initial_volume: 0.0,
+                                initial_volume: ParamValue::Fixed(0.0),
                                 source: Box::new(SoundSource::LfsrNoise {
                                     node_id: None,
-                                    amplitude: 0.5,
+                                    amplitude: ParamValue::Fixed(0.5),
                                     inside_feedback: true,
                                     note_for_16_shifts: 70,
+                                    gain: 0.0,
                                 }),
                             },
                             lower: 0,
                             upper: 127,
+                            release: None,
                         }]),
+                        max_voices: 8,
+                        voice_stealing: VoiceStealing::Oldest,
+                        crossfade: 0,
                     },
                 ),
                 (
@@ -49,28 +56,40 @@ fn main() {
                         config: FontSource::Ranges(vec![RangeSource {
                             source: SoundSource::SawtoothWave {
                                 node_id: None,
-                                amplitude: 0.5,
+                                amplitude: ParamValue::Fixed(0.5),
+                                oscillator: OscillatorMode::BandLimited,
+                                pitch: PitchMotion::NONE,
+                                unison: Unison::NONE,
+                                reset_phase: false,
+                                gain: 0.0,
                             },
                             lower: 0,
                             upper: 127,
+                            release: None,
                         }]),
+                        max_voices: 8,
+                        voice_stealing: VoiceStealing::Oldest,
+                        crossfade: 0,
                     },
                 ),
             ]),
+            swing: 0.0,
+            humanize_time: 0.0,
+            humanize_velocity: 0.0,
         }),
     };
-    let loader = FileGraphLoader::default();
+    let loader = FileGraphLoader;
     let (mut event_channels, source) = loader
         .load_source_recursive(&config)
         .expect("Could not create MIDI");
     let _mixer = BaseMixer::start_single_program(source).expect("Could not start stream");
     std::thread::spawn(move || {
-        let mut sender = event_channels
+        let sender = event_channels
             .first_mut()
             .expect("The event sender was not found");
         sleep(Duration::from_millis(500));
         send_or_log(
-            &mut sender,
+            sender,
             &NodeEvent::NodeControl {
                 node_id: FADER_NODE_ID,
                 event: NodeControlEvent::Fade {
@@ -82,7 +101,7 @@ fn main() {
         );
         sleep(Duration::from_secs(12));
         send_or_log(
-            &mut sender,
+            sender,
             &NodeEvent::NodeControl {
                 node_id: MIDI_NODE_ID,
                 event: NodeControlEvent::SeekWhenIdeal { to_anchor: Some(1) },
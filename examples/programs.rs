@@ -6,8 +6,8 @@ use midi_graph::{
 };
 use std::time::Duration;
 
-const MIDI_0_FILE: &'static str = "resources/sample-in-c.mid";
-const MIDI_1_FILE: &'static str = "resources/LoopingMidi.mid";
+const MIDI_0_FILE: &str = "resources/sample-in-c.mid";
+const MIDI_1_FILE: &str = "resources/LoopingMidi.mid";
 
 const PROGRAM_0: usize = 0;
 const PROGRAM_1: usize = 7;
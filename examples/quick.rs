@@ -0,0 +1,12 @@
+extern crate midi_graph;
+
+use midi_graph::quick::play_midi_with_sf2;
+use std::time::Duration;
+
+const MIDI_FILE: &str = "resources/sample-in-c.mid";
+const SF2_FILE: &str = "resources/demo-font.sf2";
+
+fn main() {
+    let _stream = play_midi_with_sf2(MIDI_FILE, SF2_FILE, 0).expect("Could not open stream");
+    std::thread::sleep(Duration::from_secs(16));
+}
@@ -6,8 +6,8 @@ use midi_graph::{
 };
 use std::time::Duration;
 
-const MIDI_FILE: &'static str = "resources/sample-in-c.mid";
-const SF2_FILE: &'static str = "resources/demo-font.sf2";
+const MIDI_FILE: &str = "resources/sample-in-c.mid";
+const SF2_FILE: &str = "resources/demo-font.sf2";
 
 const SOUNDFONT_0_CHANNEL: usize = 0;
 const SOUNDFONT_1_CHANNEL: usize = 1;
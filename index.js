@@ -1,12 +1,34 @@
+import init, { WorkletRenderer } from './pkg/midi_graph.js';
 
-import init from './pkg/midi_graph.js';
+const startWorklet = async (module) => {
+    const context = new AudioContext({ sampleRate: WorkletRenderer.sample_rate() });
+    await context.audioWorklet.addModule('./worklet.js');
+    const node = new AudioWorkletNode(context, 'midi-graph-processor', {
+        numberOfInputs: 0,
+        outputChannelCount: [2],
+        processorOptions: { module },
+    });
+    node.connect(context.destination);
+    return { context, node };
+};
 
 const run = async () => {
-    const wasm = await init();
+    await init();
+    const response = await fetch(new URL('./pkg/midi_graph_bg.wasm', import.meta.url));
+    const module = await WebAssembly.compile(await response.arrayBuffer());
     const button = document.getElementById('play-button');
+    let playback = null;
     button.disabled = false;
-    button.addEventListener('click', () => {
-        wasm.play_stream();
+    button.addEventListener('click', async () => {
+        if (playback) {
+            playback.node.port.postMessage({ type: 'stop' });
+            await playback.context.close();
+            playback = null;
+            button.textContent = 'Play';
+        } else {
+            playback = await startWorklet(module);
+            button.textContent = 'Stop';
+        }
     });
 };
 
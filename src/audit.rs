@@ -0,0 +1,133 @@
+// Allocation auditing for the audio thread, enabled by the alloc-audit feature.
+// Register AuditAllocator as the global allocator in the application:
+//
+//     #[global_allocator]
+//     static ALLOCATOR: midi_graph::audit::AuditAllocator = midi_graph::audit::AuditAllocator;
+//
+// Then any allocation or deallocation made while rendering audio is counted, and can be
+// checked with audio_thread_allocations(). To find where they happen, set_audit_mode can
+// have each one logged with a backtrace, or abort the process.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+static AUDIO_THREAD_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static AUDIT_MODE: AtomicU8 = AtomicU8::new(AuditMode::Count as u8);
+
+thread_local! {
+    static IS_RENDERING: Cell<bool> = const { Cell::new(false) };
+    static THREAD_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// What AuditAllocator does about an allocation made while rendering audio, besides
+/// counting it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AuditMode {
+    Count = 0,
+    /// Write a backtrace of the allocation to stderr.
+    Log = 1,
+    /// Write a backtrace of the allocation to stderr, then abort. Aborting rather than
+    /// panicking is needed since an allocator must not unwind.
+    Abort = 2,
+}
+
+pub fn set_audit_mode(mode: AuditMode) {
+    AUDIT_MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+pub fn audit_mode() -> AuditMode {
+    match AUDIT_MODE.load(Ordering::Relaxed) {
+        1 => AuditMode::Log,
+        2 => AuditMode::Abort,
+        _ => AuditMode::Count,
+    }
+}
+
+pub struct AuditAllocator;
+
+unsafe impl GlobalAlloc for AuditAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record_if_rendering();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        record_if_rendering();
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record_if_rendering();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record_if_rendering();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[inline]
+fn record_if_rendering() {
+    let is_rendering = IS_RENDERING.try_with(|flag| flag.get()).unwrap_or(false);
+    if is_rendering {
+        AUDIO_THREAD_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        let _ = THREAD_ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        let mode = audit_mode();
+        if mode != AuditMode::Count {
+            report_allocation(mode);
+        }
+    }
+}
+
+#[cold]
+fn report_allocation(mode: AuditMode) {
+    // Reporting allocates, so stop auditing while it runs
+    IS_RENDERING.with(|flag| flag.set(false));
+    {
+        let backtrace = std::backtrace::Backtrace::force_capture();
+        eprintln!("Allocation while rendering audio:\n{}", backtrace);
+        if mode == AuditMode::Abort {
+            std::process::abort();
+        }
+    }
+    IS_RENDERING.with(|flag| flag.set(true));
+}
+
+/// Number of allocations, reallocations and frees made while rendering audio.
+pub fn audio_thread_allocations() -> usize {
+    AUDIO_THREAD_ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+pub fn reset_audio_thread_allocations() {
+    AUDIO_THREAD_ALLOCATIONS.store(0, Ordering::Relaxed);
+}
+
+/// Mark the current thread as rendering audio for the lifetime of the returned guard, as
+/// the mixer does in its callback. Useful to audit graphs rendered some other way, such
+/// as offline or in tests.
+pub fn rendering_scope() -> RenderingScope {
+    IS_RENDERING.with(|flag| flag.set(true));
+    RenderingScope {
+        allocations_before: THREAD_ALLOCATIONS.with(|count| count.get()),
+    }
+}
+
+pub struct RenderingScope {
+    allocations_before: usize,
+}
+
+impl RenderingScope {
+    /// Number of allocations, reallocations and frees made on this thread since the scope
+    /// began.
+    pub fn allocations(&self) -> usize {
+        THREAD_ALLOCATIONS.with(|count| count.get()) - self.allocations_before
+    }
+}
+
+impl Drop for RenderingScope {
+    fn drop(&mut self) {
+        IS_RENDERING.with(|flag| flag.set(false));
+    }
+}
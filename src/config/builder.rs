@@ -0,0 +1,877 @@
+use crate::{
+    config::default_max_voices, Breakpoint, ChipDrumKind, Config, EnvelopeCurve, EnvelopeRetrigger,
+    Error, FmAlgorithm, FmOperator, FontSource, Loop, LoopMode, MidiDataSource, ModRoute,
+    ModulationTarget, NoiseColor, NoteMapping, OscillatorMode, ParamTarget, ParamValue,
+    PitchMotion, QuantizeDirection, RangeSource, ReleaseSource, RingModMode, Scale, SequencerStep,
+    SoundSource, TuningSource, Unison, Variation, VoiceStealing, CURRENT_CONFIG_VERSION,
+};
+use std::collections::{Bound, HashMap};
+use std::ops::RangeBounds;
+
+/// Fluent builder for configs, as an alternative to writing out SoundSource trees by hand.
+/// The functions in this module produce sources that nest directly inside each other:
+///
+/// ```
+/// use midi_graph::graph::{font, noise, square, Graph};
+///
+/// let config = Graph::new()
+///     .midi("resources/sample-in-c.mid")
+///     .channel(0, font(|f| f.range(..=50, square(0.125, 0.5)).range(51.., square(0.125, 0.875))))
+///     .channel(2, font(|f| f.range(.., noise(0.25, false, 50))))
+///     .build()
+///     .unwrap();
+/// ```
+///
+/// Node IDs are left to be generated when the graph is loaded, unless set with
+/// SoundSource::with_node_id.
+#[derive(Default)]
+pub struct Graph {
+    root: Option<SoundSource>,
+    params: HashMap<String, f32>,
+    snapshots: HashMap<String, HashMap<String, f32>>,
+    output_device: Option<String>,
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn root(mut self, source: SoundSource) -> Self {
+        self.root = Some(source);
+        self
+    }
+
+    /// Use a MIDI file as the root, with sources for its channels to follow.
+    pub fn midi(self, path: &str) -> MidiGraph {
+        MidiGraph {
+            graph: self,
+            node_id: None,
+            source: MidiDataSource::FilePath(path.to_owned()),
+            sequence_index: 0,
+            channels: HashMap::new(),
+            swing: 0.0,
+            humanize_time: 0.0,
+            humanize_velocity: 0.0,
+        }
+    }
+
+    /// Add a value that sources can use with param.
+    pub fn param(mut self, name: &str, value: f32) -> Self {
+        self.params.insert(name.to_owned(), value);
+        self
+    }
+
+    /// Add a snapshot of param values that can be recalled together at runtime.
+    pub fn snapshot(mut self, name: &str, values: &[(&str, f32)]) -> Self {
+        let values = values
+            .iter()
+            .map(|(param, value)| ((*param).to_owned(), *value))
+            .collect();
+        self.snapshots.insert(name.to_owned(), values);
+        self
+    }
+
+    pub fn output_device(mut self, name: &str) -> Self {
+        self.output_device = Some(name.to_owned());
+        self
+    }
+
+    pub fn build(self) -> Result<Config, Error> {
+        let root = self
+            .root
+            .ok_or_else(|| Error::User("Graph has no root source".to_owned()))?;
+        let mut config = Config {
+            version: CURRENT_CONFIG_VERSION,
+            root,
+            output_device: self.output_device,
+            params: self.params,
+            snapshots: self.snapshots,
+            definitions: HashMap::new(),
+        };
+        config.resolve_params()?;
+        Ok(config)
+    }
+}
+
+/// A graph with a MIDI file at its root, as returned by Graph::midi.
+pub struct MidiGraph {
+    graph: Graph,
+    node_id: Option<u64>,
+    source: MidiDataSource,
+    sequence_index: usize,
+    channels: HashMap<usize, SoundSource>,
+    swing: f32,
+    humanize_time: f32,
+    humanize_velocity: f32,
+}
+
+impl MidiGraph {
+    pub fn node_id(mut self, node_id: u64) -> Self {
+        self.node_id = Some(node_id);
+        self
+    }
+
+    /// Play one sequence of a type 2 file, which holds several independent sequences.
+    pub fn sequence(mut self, sequence_index: usize) -> Self {
+        self.sequence_index = sequence_index;
+        self
+    }
+
+    pub fn channel(mut self, channel: usize, source: SoundSource) -> Self {
+        self.channels.insert(channel, source);
+        self
+    }
+
+    /// Delay off-beat eighth notes by a fraction of an eighth note, up to 0.5.
+    pub fn swing(mut self, swing: f32) -> Self {
+        self.swing = swing;
+        self
+    }
+
+    /// Start notes late by up to some seconds and vary their velocities by up to a fraction.
+    pub fn humanize(mut self, time_seconds: f32, velocity_amount: f32) -> Self {
+        self.humanize_time = time_seconds;
+        self.humanize_velocity = velocity_amount;
+        self
+    }
+
+    pub fn param(mut self, name: &str, value: f32) -> Self {
+        self.graph = self.graph.param(name, value);
+        self
+    }
+
+    pub fn snapshot(mut self, name: &str, values: &[(&str, f32)]) -> Self {
+        self.graph = self.graph.snapshot(name, values);
+        self
+    }
+
+    pub fn build(self) -> Result<Config, Error> {
+        let root = SoundSource::Midi {
+            node_id: self.node_id,
+            source: self.source,
+            sequence_index: self.sequence_index,
+            channels: self.channels,
+            swing: self.swing,
+            humanize_time: self.humanize_time,
+            humanize_velocity: self.humanize_velocity,
+        };
+        self.graph.root(root).build()
+    }
+}
+
+/// Ranges of a font being built by the font function.
+pub struct FontRanges {
+    ranges: Vec<RangeSource>,
+    max_voices: Option<usize>,
+    voice_stealing: VoiceStealing,
+    crossfade: u8,
+}
+
+impl FontRanges {
+    /// Play the source for notes in the range. Open ranges extend to the lowest and highest
+    /// notes.
+    pub fn range(mut self, notes: impl RangeBounds<u8>, source: SoundSource) -> Self {
+        let lower = match notes.start_bound() {
+            Bound::Included(note) => *note,
+            Bound::Excluded(note) => note.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let upper = match notes.end_bound() {
+            Bound::Included(note) => *note,
+            Bound::Excluded(note) => note.saturating_sub(1),
+            Bound::Unbounded => 255,
+        };
+        self.ranges.push(RangeSource {
+            source,
+            lower,
+            upper,
+            release: None,
+        });
+        self
+    }
+
+    /// Play a source as each note of the last range added is released, at a level relative
+    /// to the notes, such as the key-off noise of a sampled piano.
+    pub fn release(mut self, source: SoundSource, level: f32) -> Self {
+        if let Some(range) = self.ranges.last_mut() {
+            range.release = Some(ReleaseSource { source, level });
+        }
+        self
+    }
+
+    pub fn max_voices(mut self, max_voices: usize) -> Self {
+        self.max_voices = Some(max_voices);
+        self
+    }
+
+    pub fn voice_stealing(mut self, voice_stealing: VoiceStealing) -> Self {
+        self.voice_stealing = voice_stealing;
+        self
+    }
+
+    /// Mix ranges that meet across some notes around each split.
+    pub fn crossfade(mut self, notes: u8) -> Self {
+        self.crossfade = notes;
+        self
+    }
+}
+
+/// Refer to a value from the graph's params, for any numeric argument.
+/// Tag a source, so that control events sent to the tag reach it along with every other
+/// source sharing the tag.
+pub fn tagged(tags: &[&str], source: SoundSource) -> SoundSource {
+    SoundSource::Tagged {
+        tags: tags.iter().map(|tag| (*tag).to_owned()).collect(),
+        source: Box::new(source),
+    }
+}
+
+/// Give a source a name, so that control events can be sent to it by name through
+/// NodeNames rather than by a node ID written into both the config and the game.
+pub fn named(name: &str, source: SoundSource) -> SoundSource {
+    SoundSource::Named {
+        name: name.to_owned(),
+        source: Box::new(source),
+    }
+}
+
+/// Play the notes of every source within this one in another tuning, such as a scale from
+/// Scala files, in place of any tuning given further out.
+pub fn tuned(tuning: TuningSource, source: SoundSource) -> SoundSource {
+    SoundSource::Tuned {
+        tuning,
+        source: Box::new(source),
+    }
+}
+
+pub fn param(name: &str) -> ParamValue {
+    ParamValue::Param(name.to_owned())
+}
+
+pub fn font(build: impl FnOnce(FontRanges) -> FontRanges) -> SoundSource {
+    let ranges = build(FontRanges {
+        ranges: vec![],
+        max_voices: None,
+        voice_stealing: VoiceStealing::default(),
+        crossfade: 0,
+    });
+    SoundSource::Font {
+        node_id: None,
+        config: FontSource::Ranges(ranges.ranges),
+        max_voices: ranges.max_voices.unwrap_or(default_max_voices()),
+        voice_stealing: ranges.voice_stealing,
+        crossfade: ranges.crossfade,
+    }
+}
+
+pub fn sf2(path: &str, instrument_index: usize) -> SoundSource {
+    SoundSource::Font {
+        node_id: None,
+        config: FontSource::Sf2FilePath {
+            path: path.to_owned(),
+            instrument_index,
+        },
+        max_voices: default_max_voices(),
+        voice_stealing: VoiceStealing::default(),
+        crossfade: 0,
+    }
+}
+
+pub fn square(amplitude: impl Into<ParamValue>, duty_cycle: impl Into<ParamValue>) -> SoundSource {
+    SoundSource::SquareWave {
+        node_id: None,
+        amplitude: amplitude.into(),
+        duty_cycle: duty_cycle.into(),
+        oscillator: OscillatorMode::Naive,
+        pitch: PitchMotion::NONE,
+        unison: Unison::NONE,
+        reset_phase: false,
+        gain: 0.0,
+    }
+}
+
+/// Switch a square or sawtooth oscillator to band-limited output, to avoid aliasing.
+pub fn band_limited(mut source: SoundSource) -> SoundSource {
+    if let SoundSource::SquareWave { oscillator, .. }
+    | SoundSource::SawtoothWave { oscillator, .. } = &mut source
+    {
+        *oscillator = OscillatorMode::BandLimited;
+    }
+    source
+}
+
+/// Add glide or a pitch envelope to a square, triangle or sawtooth oscillator.
+pub fn pitch_motion(mut source: SoundSource, motion: PitchMotion) -> SoundSource {
+    if let SoundSource::SquareWave { pitch, .. }
+    | SoundSource::TriangleWave { pitch, .. }
+    | SoundSource::SawtoothWave { pitch, .. } = &mut source
+    {
+        *pitch = motion;
+    }
+    source
+}
+
+/// Restart a square, triangle or sawtooth oscillator's waveform on each note, rather than
+/// running on continuously, for a consistent attack.
+pub fn reset_phase(mut source: SoundSource) -> SoundSource {
+    if let SoundSource::SquareWave { reset_phase, .. }
+    | SoundSource::TriangleWave { reset_phase, .. }
+    | SoundSource::SawtoothWave { reset_phase, .. } = &mut source
+    {
+        *reset_phase = true;
+    }
+    source
+}
+
+/// Thicken a square or sawtooth oscillator with detuned unison voices.
+pub fn unison(mut source: SoundSource, voices: u8, detune_cents: f32, spread: f32) -> SoundSource {
+    if let SoundSource::SquareWave { unison, .. } | SoundSource::SawtoothWave { unison, .. } =
+        &mut source
+    {
+        *unison = Unison {
+            voices,
+            detune_cents,
+            spread,
+        };
+    }
+    source
+}
+
+pub fn triangle(amplitude: impl Into<ParamValue>) -> SoundSource {
+    SoundSource::TriangleWave {
+        node_id: None,
+        amplitude: amplitude.into(),
+        pitch: PitchMotion::NONE,
+        reset_phase: false,
+        gain: 0.0,
+    }
+}
+
+pub fn sawtooth(amplitude: impl Into<ParamValue>) -> SoundSource {
+    SoundSource::SawtoothWave {
+        node_id: None,
+        amplitude: amplitude.into(),
+        oscillator: OscillatorMode::Naive,
+        pitch: PitchMotion::NONE,
+        unison: Unison::NONE,
+        reset_phase: false,
+        gain: 0.0,
+    }
+}
+
+pub fn fm(
+    amplitude: impl Into<ParamValue>,
+    algorithm: FmAlgorithm,
+    operators: impl IntoIterator<Item = FmOperator>,
+) -> SoundSource {
+    SoundSource::FmSynth {
+        node_id: None,
+        amplitude: amplitude.into(),
+        algorithm,
+        operators: operators.into_iter().collect(),
+        gain: 0.0,
+    }
+}
+
+/// Summed sines at the listed levels for each harmonic, from the fundamental up.
+pub fn additive(
+    amplitude: impl Into<ParamValue>,
+    harmonics: impl IntoIterator<Item = f32>,
+) -> SoundSource {
+    SoundSource::Additive {
+        node_id: None,
+        amplitude: amplitude.into(),
+        harmonics: harmonics.into_iter().collect(),
+        detune_cents: vec![],
+        gain: 0.0,
+    }
+}
+
+pub fn pluck(
+    amplitude: impl Into<ParamValue>,
+    decay: impl Into<ParamValue>,
+    brightness: impl Into<ParamValue>,
+) -> SoundSource {
+    SoundSource::PluckedString {
+        node_id: None,
+        amplitude: amplitude.into(),
+        decay: decay.into(),
+        brightness: brightness.into(),
+        gain: 0.0,
+    }
+}
+
+pub fn noise(
+    amplitude: impl Into<ParamValue>,
+    inside_feedback: bool,
+    note_for_16_shifts: u8,
+) -> SoundSource {
+    SoundSource::LfsrNoise {
+        node_id: None,
+        amplitude: amplitude.into(),
+        inside_feedback,
+        note_for_16_shifts,
+        gain: 0.0,
+    }
+}
+
+pub fn colored_noise(amplitude: impl Into<ParamValue>, color: NoiseColor) -> SoundSource {
+    SoundSource::ColoredNoise {
+        node_id: None,
+        amplitude: amplitude.into(),
+        color,
+        gain: 0.0,
+    }
+}
+
+/// A square wave jumping to random notes within the range around the played note.
+pub fn sample_hold(
+    amplitude: impl Into<ParamValue>,
+    rate_hz: impl Into<ParamValue>,
+    range_semitones: u8,
+) -> SoundSource {
+    SoundSource::SampleHold {
+        node_id: None,
+        amplitude: amplitude.into(),
+        rate_hz: rate_hz.into(),
+        range_semitones,
+        gain: 0.0,
+    }
+}
+
+/// A WAV sample, optionally looping between the given frames while each note is held.
+/// A preset chip-style drum, following the pitch of each note it plays.
+pub fn chip_drum(kind: ChipDrumKind, amplitude: impl Into<ParamValue>) -> SoundSource {
+    SoundSource::ChipDrum {
+        node_id: None,
+        kind,
+        amplitude: amplitude.into(),
+        length: 1.0,
+        gain: 0.0,
+    }
+}
+
+pub fn sample(path: &str, base_note: u8, looping: Option<(usize, usize)>) -> SoundSource {
+    SoundSource::SampleFilePath {
+        node_id: None,
+        path: path.to_owned(),
+        base_note,
+        looping: looping.map(|(start, end)| Loop {
+            start,
+            end,
+            mode: LoopMode::UntilRelease,
+        }),
+        retrigger: Default::default(),
+        variation: Variation::NONE,
+        bad_samples: Default::default(),
+        gain: 0.0,
+    }
+}
+
+pub fn one_shot(path: &str) -> SoundSource {
+    SoundSource::OneShotFilePath {
+        node_id: None,
+        path: path.to_owned(),
+        retrigger: Default::default(),
+        choke_group: None,
+        variation: Variation::NONE,
+        bad_samples: Default::default(),
+        gain: 0.0,
+    }
+}
+
+pub fn envelope(
+    attack_time: impl Into<ParamValue>,
+    decay_time: impl Into<ParamValue>,
+    sustain_multiplier: impl Into<ParamValue>,
+    release_time: impl Into<ParamValue>,
+    source: SoundSource,
+) -> SoundSource {
+    SoundSource::Envelope {
+        node_id: None,
+        attack_time: attack_time.into(),
+        decay_time: decay_time.into(),
+        sustain_multiplier: sustain_multiplier.into(),
+        release_time: release_time.into(),
+        key_tracking: 0.0,
+        velocity_tracking: 0.0,
+        attack_curve: EnvelopeCurve::Linear,
+        decay_curve: EnvelopeCurve::Linear,
+        release_curve: EnvelopeCurve::Linear,
+        retrigger: EnvelopeRetrigger::Reset,
+        delay_time: ParamValue::Fixed(0.0),
+        hold_time: ParamValue::Fixed(0.0),
+        source: Box::new(source),
+    }
+}
+
+/// Add a delay before an envelope's attack and a hold at its peak before the decay.
+pub fn delay_hold(
+    mut source: SoundSource,
+    delay: impl Into<ParamValue>,
+    hold: impl Into<ParamValue>,
+) -> SoundSource {
+    if let SoundSource::Envelope {
+        delay_time,
+        hold_time,
+        ..
+    } = &mut source
+    {
+        *delay_time = delay.into();
+        *hold_time = hold.into();
+    }
+    source
+}
+
+pub fn multi_stage_envelope(
+    points: Vec<Breakpoint>,
+    sustain_point: Option<usize>,
+    source: SoundSource,
+) -> SoundSource {
+    SoundSource::MultiStageEnvelope {
+        node_id: None,
+        points,
+        sustain_point,
+        target: None,
+        source: Box::new(source),
+    }
+}
+
+/// Have a multi-stage envelope drive a control on a node within its source, instead of
+/// shaping the source's amplitude.
+pub fn modulate(mut source: SoundSource, node_id: u64, control: ParamTarget) -> SoundSource {
+    if let SoundSource::MultiStageEnvelope { target, .. } = &mut source {
+        *target = Some(ModulationTarget { node_id, control });
+    }
+    source
+}
+
+/// Route modulation sources to controls on nodes within the source.
+pub fn mod_matrix(routes: Vec<ModRoute>, source: SoundSource) -> SoundSource {
+    SoundSource::ModMatrix {
+        node_id: None,
+        routes,
+        source: Box::new(source),
+    }
+}
+
+/// Shape the attack, decay and release of an envelope, and choose how it retriggers.
+pub fn curves(
+    mut source: SoundSource,
+    attack: EnvelopeCurve,
+    decay: EnvelopeCurve,
+    release: EnvelopeCurve,
+    retrigger: EnvelopeRetrigger,
+) -> SoundSource {
+    if let SoundSource::Envelope {
+        attack_curve,
+        decay_curve,
+        release_curve,
+        retrigger: envelope_retrigger,
+        ..
+    } = &mut source
+    {
+        *attack_curve = attack;
+        *decay_curve = decay;
+        *release_curve = release;
+        *envelope_retrigger = retrigger;
+    }
+    source
+}
+
+/// Scale an envelope's times with the note and velocity of each note it plays.
+pub fn tracking(mut source: SoundSource, key: f32, velocity: f32) -> SoundSource {
+    if let SoundSource::Envelope {
+        key_tracking,
+        velocity_tracking,
+        ..
+    } = &mut source
+    {
+        *key_tracking = key;
+        *velocity_tracking = velocity;
+    }
+    source
+}
+
+pub fn mixer(
+    balance: impl Into<ParamValue>,
+    source_0: SoundSource,
+    source_1: SoundSource,
+) -> SoundSource {
+    SoundSource::Mixer {
+        node_id: None,
+        balance: balance.into(),
+        source_0: Box::new(source_0),
+        source_1: Box::new(source_1),
+    }
+}
+
+pub fn crossfade(
+    position: impl Into<ParamValue>,
+    source_0: SoundSource,
+    source_1: SoundSource,
+) -> SoundSource {
+    SoundSource::Crossfade {
+        node_id: None,
+        position: position.into(),
+        source_0: Box::new(source_0),
+        source_1: Box::new(source_1),
+    }
+}
+
+/// Lower the music by a fraction whenever the priority source is heard.
+pub fn duck(
+    depth: impl Into<ParamValue>,
+    music: SoundSource,
+    priority: SoundSource,
+) -> SoundSource {
+    SoundSource::Duck {
+        node_id: None,
+        depth: depth.into(),
+        attack_time: ParamValue::Fixed(0.05),
+        release_time: ParamValue::Fixed(0.5),
+        threshold: 0.01,
+        music: Box::new(music),
+        priority: Box::new(priority),
+    }
+}
+
+/// Ring modulate a source with a sine oscillator at a fixed frequency.
+pub fn ring_mod(frequency: impl Into<ParamValue>, source: SoundSource) -> SoundSource {
+    SoundSource::RingMod {
+        node_id: None,
+        mode: RingModMode::Ring,
+        depth: ParamValue::Fixed(1.0),
+        frequency: frequency.into(),
+        note_ratio: None,
+        carrier: None,
+        source: Box::new(source),
+    }
+}
+
+/// Amplitude modulate a source with a sine oscillator at a fixed frequency, by a depth from
+/// 0 to 1.
+pub fn amplitude_mod(
+    frequency: impl Into<ParamValue>,
+    depth: impl Into<ParamValue>,
+    source: SoundSource,
+) -> SoundSource {
+    SoundSource::RingMod {
+        node_id: None,
+        mode: RingModMode::Amplitude,
+        depth: depth.into(),
+        frequency: frequency.into(),
+        note_ratio: None,
+        carrier: None,
+        source: Box::new(source),
+    }
+}
+
+/// Have a ring modulator's oscillator follow the pitch of each note at a ratio of its
+/// frequency.
+pub fn note_ratio(mut source: SoundSource, ratio: f32) -> SoundSource {
+    if let SoundSource::RingMod { note_ratio, .. } = &mut source {
+        *note_ratio = Some(ratio);
+    }
+    source
+}
+
+/// Have a ring modulator multiply by a second source instead of its oscillator.
+pub fn carrier(mut source: SoundSource, carrier_source: SoundSource) -> SoundSource {
+    if let SoundSource::RingMod { carrier, .. } = &mut source {
+        *carrier = Some(Box::new(carrier_source));
+    }
+    source
+}
+
+/// Shift the pitch of everything a source plays, by semitones and cents.
+pub fn pitch_shift(semitones: f32, cents: f32, source: SoundSource) -> SoundSource {
+    SoundSource::PitchShift {
+        node_id: None,
+        semitones,
+        cents,
+        grain_time: 0.05,
+        source: Box::new(source),
+    }
+}
+
+/// Convolve a source with the impulse response in a WAV file, for reverbs and speaker
+/// cabinets, blending in the dry source as the mix falls from 1 to 0.
+pub fn convolution(path: &str, mix: impl Into<ParamValue>, source: SoundSource) -> SoundSource {
+    SoundSource::Convolution {
+        node_id: None,
+        path: path.to_owned(),
+        mix: mix.into(),
+        pre_delay_time: 0.0,
+        source: Box::new(source),
+    }
+}
+
+/// Pulse the volume of a source at a rate in Hz, dipping by a depth from 0 to 1.
+pub fn tremolo(
+    rate: impl Into<ParamValue>,
+    depth: impl Into<ParamValue>,
+    source: SoundSource,
+) -> SoundSource {
+    SoundSource::Tremolo {
+        node_id: None,
+        rate: rate.into(),
+        depth: depth.into(),
+        source: Box::new(source),
+    }
+}
+
+/// Wobble the pitch of a source at a rate in Hz, by a depth in cents either side.
+pub fn vibrato(
+    rate: impl Into<ParamValue>,
+    depth_cents: impl Into<ParamValue>,
+    source: SoundSource,
+) -> SoundSource {
+    SoundSource::Vibrato {
+        node_id: None,
+        rate: rate.into(),
+        depth_cents: depth_cents.into(),
+        source: Box::new(source),
+    }
+}
+
+pub fn fader(initial_volume: impl Into<ParamValue>, source: SoundSource) -> SoundSource {
+    SoundSource::Fader {
+        node_id: None,
+        initial_volume: initial_volume.into(),
+        source: Box::new(source),
+    }
+}
+
+/// A looping pattern of steps played into a source, with four steps to the beat.
+pub fn sequencer(
+    bpm: f32,
+    steps: impl IntoIterator<Item = SequencerStep>,
+    source: SoundSource,
+) -> SoundSource {
+    SoundSource::Sequencer {
+        node_id: None,
+        bpm,
+        steps_per_beat: 4,
+        swing: 0.0,
+        looping: true,
+        steps: steps.into_iter().collect(),
+        source: Box::new(source),
+    }
+}
+
+/// Mute a source rhythmically, following a pattern of levels from 0 to 1 in sixteenth
+/// notes, in time with the MIDI track playing it.
+pub fn gate(pattern: impl IntoIterator<Item = f32>, source: SoundSource) -> SoundSource {
+    SoundSource::Gate {
+        node_id: None,
+        pattern: pattern.into_iter().collect(),
+        steps_per_beat: 4,
+        bpm: 120.0,
+        attack_time: 0.002,
+        release_time: 0.01,
+        source: Box::new(source),
+    }
+}
+
+/// Turn a source down further while it is below a threshold, by a ratio, to act as a
+/// downward expander or, with large ratios, a noise gate.
+pub fn gate_expander(
+    threshold: f32,
+    ratio: impl Into<ParamValue>,
+    source: SoundSource,
+) -> SoundSource {
+    SoundSource::GateExpander {
+        node_id: None,
+        threshold,
+        ratio: ratio.into(),
+        attack_time: ParamValue::Fixed(0.001),
+        release_time: ParamValue::Fixed(0.1),
+        source: Box::new(source),
+    }
+}
+
+/// Play Midi sources one after another, fading between them over some seconds.
+pub fn playlist(
+    crossfade_time: f32,
+    looping: bool,
+    songs: impl IntoIterator<Item = SoundSource>,
+) -> SoundSource {
+    SoundSource::Playlist {
+        node_id: None,
+        crossfade_time,
+        looping,
+        songs: songs.into_iter().collect(),
+    }
+}
+
+/// Send the notes reaching this source to an external MIDI output port, found by part of
+/// its name; needs the midir feature.
+pub fn midi_output(port: &str, channel: u8) -> SoundSource {
+    SoundSource::MidiOutput {
+        node_id: None,
+        port: port.to_owned(),
+        channel,
+        latency: crate::consts::BUFFER_SIZE as f32 / crate::consts::PLAYBACK_SAMPLE_RATE as f32,
+    }
+}
+
+/// Transpose, filter or change the velocities of the notes reaching a source.
+pub fn note_map(mapping: NoteMapping, source: SoundSource) -> SoundSource {
+    SoundSource::NoteMap {
+        node_id: None,
+        mapping,
+        source: Box::new(source),
+    }
+}
+
+/// Snap the notes reaching a source onto a scale built on the root pitch class.
+pub fn quantize(
+    scale: Scale,
+    root: u8,
+    direction: QuantizeDirection,
+    source: SoundSource,
+) -> SoundSource {
+    SoundSource::ScaleQuantizer {
+        node_id: None,
+        scale,
+        root,
+        direction,
+        source: Box::new(source),
+    }
+}
+
+pub fn combiner(sources: impl IntoIterator<Item = SoundSource>) -> SoundSource {
+    SoundSource::Combiner {
+        node_id: None,
+        sources: sources.into_iter().collect(),
+        hard_sync: false,
+        parallel: false,
+    }
+}
+
+/// Combine oscillators with every one hard-synced to the first, such as for sync leads.
+pub fn hard_sync(sources: impl IntoIterator<Item = SoundSource>) -> SoundSource {
+    SoundSource::Combiner {
+        node_id: None,
+        sources: sources.into_iter().collect(),
+        hard_sync: true,
+        parallel: false,
+    }
+}
+
+/// Combine sources rendered in parallel on a pool of worker threads.
+pub fn parallel(sources: impl IntoIterator<Item = SoundSource>) -> SoundSource {
+    SoundSource::Combiner {
+        node_id: None,
+        sources: sources.into_iter().collect(),
+        hard_sync: false,
+        parallel: true,
+    }
+}
+
+/// Receive events sent from outside the graph through the returned event channel.
+pub fn receiver(node_id: u64, source: SoundSource) -> SoundSource {
+    SoundSource::event_receiver(Some(node_id), source)
+}
@@ -0,0 +1,54 @@
+use crate::config::{
+    builder::{curves, envelope, mixer, noise, pitch_motion, reset_phase, square},
+    params::ParamValue,
+    EnvelopeCurve, EnvelopeRetrigger, PitchMotion, SoundSource,
+};
+use serde_derive::{Deserialize, Serialize};
+
+/// A drum played by a ChipDrum source, built in the style of old sound chips from a square
+/// wave, noise and envelopes. A kick is a square wave falling quickly in pitch, a snare a
+/// short pitched body under a burst of noise, and a hat a very short burst of metallic
+/// noise. Each follows the pitch of the note it plays, as on the chips themselves.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub enum ChipDrumKind {
+    Kick,
+    Snare,
+    Hat,
+}
+
+impl ChipDrumKind {
+    /// Build the graph of a drum, with every envelope time scaled by its length.
+    pub fn expand(self, amplitude: &ParamValue, length: f32) -> SoundSource {
+        let length = length.max(0.0);
+        let decaying = |decay: f32, release: f32, source: SoundSource| {
+            curves(
+                envelope(0.0, decay * length, 0.0, release * length, source),
+                EnvelopeCurve::Linear,
+                EnvelopeCurve::Exponential,
+                EnvelopeCurve::Exponential,
+                EnvelopeRetrigger::Reset,
+            )
+        };
+        let falling_square = |semitones: f32, seconds: f32| {
+            let motion = PitchMotion {
+                glide_time: 0.0,
+                envelope_offset: semitones,
+                envelope_time: seconds * length,
+            };
+            reset_phase(pitch_motion(square(amplitude.clone(), 0.5), motion))
+        };
+        match self {
+            ChipDrumKind::Kick => decaying(0.25, 0.05, falling_square(24.0, 0.06)),
+            ChipDrumKind::Snare => decaying(
+                0.18,
+                0.05,
+                mixer(
+                    0.6,
+                    decaying(0.06, 0.02, falling_square(12.0, 0.03)),
+                    noise(amplitude.clone(), false, 88),
+                ),
+            ),
+            ChipDrumKind::Hat => decaying(0.05, 0.03, noise(amplitude.clone(), true, 100)),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use crate::{Config, ConfigFormat, Error};
+
+/// Writes configs back out as text, for tools that edit a graph's config and want to
+/// persist the result. Built nodes do not keep the config they were created from, so
+/// edits should be made to the Config and the graph rebuilt from it.
+pub struct GraphExporter {
+    format: ConfigFormat,
+}
+
+impl GraphExporter {
+    pub fn new(format: ConfigFormat) -> Self {
+        Self { format }
+    }
+
+    pub fn format(&self) -> ConfigFormat {
+        self.format
+    }
+
+    pub fn to_string(&self, config: &Config) -> Result<String, Error> {
+        let text = match self.format {
+            ConfigFormat::Ron => {
+                let pretty = ron::ser::PrettyConfig::default().struct_names(false);
+                ron::ser::to_string_pretty(config, pretty)?
+            }
+            #[cfg(feature = "json")]
+            ConfigFormat::Json => serde_json::to_string_pretty(config)?,
+            #[cfg(feature = "yaml")]
+            ConfigFormat::Yaml => serde_yaml::to_string(config)?,
+        };
+        Ok(text)
+    }
+
+    /// Write the config to a file, using the exporter's format regardless of the
+    /// file's extension.
+    pub fn to_file(&self, config: &Config, path: &str) -> Result<(), Error> {
+        let text = self.to_string(config)?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{
+    util::{param_id, snapshot_id},
+    BroadcastControl, Error, NodeControlEvent, NodeEvent, NodeNames, NoteEvent,
+};
+use serde_derive::{Deserialize, Serialize};
+
+/// An event a host can send into a graph, written as data rather than built in code, for
+/// hosts that pass events across a boundary such as a WebAssembly module or a script. For
+/// example, in RON, `Control(target: Name("music"), event: Duck(seconds: 0.5))`.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub enum HostEvent {
+    NoteOn {
+        note: u8,
+        #[serde(default)]
+        voice_id: Option<u64>,
+        vel: f32,
+    },
+    NoteOff {
+        note: u8,
+        #[serde(default)]
+        voice_id: Option<u64>,
+        #[serde(default)]
+        vel: f32,
+    },
+    NotesOff,
+    Stop,
+    SetParam {
+        name: String,
+        value: f32,
+    },
+    RecallSnapshot {
+        name: String,
+        seconds: f32,
+    },
+    Controller {
+        controller: u8,
+        value: f32,
+    },
+    Control {
+        target: HostTarget,
+        event: HostControl,
+    },
+}
+
+/// The node a HostEvent::Control is for, by node ID or by the name of a Named source.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub enum HostTarget {
+    Id(u64),
+    Name(String),
+}
+
+/// The control events that can be written as data; see NodeControlEvent for each.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub enum HostControl {
+    MixerBalance(f32),
+    Volume(f32),
+    Fade { from: f32, to: f32, seconds: f32 },
+    SeekWhenIdeal { to_anchor: Option<u32> },
+    AbToggle,
+    AbSelect { use_b: bool },
+    PlayEffect { index: usize, volume: f32, pan: f32 },
+    MaxVoices(usize),
+    CrossfeedAmount(f32),
+    Crossfade { to: f32, seconds: f32 },
+    Duck { seconds: f32 },
+    TempoRamp { bpm: f32, bars: u32 },
+    PlaybackRate { rate: f32, seconds: f32 },
+    PitchShift { semitones: f32, cents: f32 },
+    SetBypass(bool),
+    SetWet(f32),
+    Transpose(i8),
+    MasterTune(f32),
+}
+
+impl HostEvent {
+    /// Make the event to send into a graph, finding any named target among its names.
+    pub fn to_node_event(&self, names: &NodeNames) -> Result<NodeEvent, Error> {
+        let event = match self {
+            HostEvent::NoteOn {
+                note,
+                voice_id,
+                vel,
+            } => NodeEvent::Note {
+                note: *note,
+                voice_id: *voice_id,
+                event: NoteEvent::NoteOn { vel: *vel },
+            },
+            HostEvent::NoteOff {
+                note,
+                voice_id,
+                vel,
+            } => NodeEvent::Note {
+                note: *note,
+                voice_id: *voice_id,
+                event: NoteEvent::NoteOff { vel: *vel },
+            },
+            HostEvent::NotesOff => NodeEvent::Broadcast(BroadcastControl::NotesOff),
+            HostEvent::Stop => NodeEvent::Broadcast(BroadcastControl::Stop),
+            HostEvent::SetParam { name, value } => {
+                NodeEvent::Broadcast(BroadcastControl::SetParam {
+                    param_id: param_id(name),
+                    value: *value,
+                })
+            }
+            HostEvent::RecallSnapshot { name, seconds } => {
+                NodeEvent::Broadcast(BroadcastControl::RecallSnapshot {
+                    snapshot_id: snapshot_id(name),
+                    seconds: *seconds,
+                })
+            }
+            HostEvent::Controller { controller, value } => {
+                NodeEvent::Broadcast(BroadcastControl::Controller {
+                    controller: *controller,
+                    value: *value,
+                })
+            }
+            HostEvent::Control { target, event } => NodeEvent::NodeControl {
+                node_id: match target {
+                    HostTarget::Id(node_id) => *node_id,
+                    HostTarget::Name(name) => names.node_id(name)?,
+                },
+                event: event.to_control_event(),
+            },
+        };
+        Ok(event)
+    }
+}
+
+impl HostControl {
+    fn to_control_event(&self) -> NodeControlEvent {
+        match *self {
+            HostControl::MixerBalance(balance) => NodeControlEvent::MixerBalance(balance),
+            HostControl::Volume(volume) => NodeControlEvent::Volume(volume),
+            HostControl::Fade { from, to, seconds } => NodeControlEvent::Fade { from, to, seconds },
+            HostControl::SeekWhenIdeal { to_anchor } => {
+                NodeControlEvent::SeekWhenIdeal { to_anchor }
+            }
+            HostControl::AbToggle => NodeControlEvent::AbToggle,
+            HostControl::AbSelect { use_b } => NodeControlEvent::AbSelect { use_b },
+            HostControl::PlayEffect { index, volume, pan } => {
+                NodeControlEvent::PlayEffect { index, volume, pan }
+            }
+            HostControl::MaxVoices(count) => NodeControlEvent::MaxVoices(count),
+            HostControl::CrossfeedAmount(amount) => NodeControlEvent::CrossfeedAmount(amount),
+            HostControl::Crossfade { to, seconds } => NodeControlEvent::Crossfade { to, seconds },
+            HostControl::Duck { seconds } => NodeControlEvent::Duck { seconds },
+            HostControl::TempoRamp { bpm, bars } => NodeControlEvent::TempoRamp { bpm, bars },
+            HostControl::PlaybackRate { rate, seconds } => {
+                NodeControlEvent::PlaybackRate { rate, seconds }
+            }
+            HostControl::PitchShift { semitones, cents } => {
+                NodeControlEvent::PitchShift { semitones, cents }
+            }
+            HostControl::SetBypass(bypass) => NodeControlEvent::SetBypass(bypass),
+            HostControl::SetWet(wet) => NodeControlEvent::SetWet(wet),
+            HostControl::Transpose(semitones) => NodeControlEvent::Transpose(semitones),
+            HostControl::MasterTune(cents) => NodeControlEvent::MasterTune(cents),
+        }
+    }
+}
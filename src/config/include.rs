@@ -0,0 +1,144 @@
+use crate::{AssetLoader, Config, ConfigFormat, Error, FileGraphLoader, GraphLoader, SoundSource};
+use std::collections::HashMap;
+
+impl Config {
+    /// Replace every Include with the root source of the config file it names, and every
+    /// Ref with its entry in the definitions table of the file it appears in. Include paths
+    /// are relative to the including file, whose own path is given here, and are read
+    /// through the asset loader. Asset paths inside included files are not rewritten.
+    pub fn resolve_includes(
+        &mut self,
+        config_path: &str,
+        assets: &dyn AssetLoader,
+    ) -> Result<(), Error> {
+        let mut include_stack = vec![config_path.to_owned()];
+        resolve_config(self, config_path, assets, &mut include_stack)
+    }
+}
+
+/// Get the path of an included file, given the path of the file including it.
+pub(crate) fn include_path(config_path: &str, include: &str) -> String {
+    if include.contains("://") || include.starts_with('/') {
+        return include.to_owned();
+    }
+    let mut segments: Vec<&str> = match config_path.rfind('/') {
+        Some(index) => config_path[..index].split('/').collect(),
+        None => vec![],
+    };
+    for segment in include.split('/') {
+        match segment {
+            "." => {}
+            ".." if segments.last().is_some_and(|last| *last != "..") => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/// Get the paths of the files included directly by a config, for fetching them ahead of
+/// resolving includes. Included files may include further files of their own.
+pub fn included_paths(config: &Config, config_path: &str) -> Vec<String> {
+    let mut paths = vec![];
+    collect_included_paths(&config.root, config_path, &mut paths);
+    for definition in config.definitions.values() {
+        collect_included_paths(definition, config_path, &mut paths);
+    }
+    paths
+}
+
+fn collect_included_paths(source: &SoundSource, config_path: &str, paths: &mut Vec<String>) {
+    if let SoundSource::Include(include) = source {
+        let path = include_path(config_path, include);
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    FileGraphLoader::traverse_sources(source, |child| {
+        if !std::ptr::eq(child, source) {
+            collect_included_paths(child, config_path, paths);
+        }
+    });
+}
+
+struct Context<'a> {
+    config_path: &'a str,
+    definitions: &'a HashMap<String, SoundSource>,
+    assets: &'a dyn AssetLoader,
+}
+
+fn resolve_config(
+    config: &mut Config,
+    config_path: &str,
+    assets: &dyn AssetLoader,
+    include_stack: &mut Vec<String>,
+) -> Result<(), Error> {
+    let definitions = std::mem::take(&mut config.definitions);
+    let context = Context {
+        config_path,
+        definitions: &definitions,
+        assets,
+    };
+    let result = resolve_source(&mut config.root, &context, include_stack, &mut vec![]);
+    config.definitions = definitions;
+    result
+}
+
+fn resolve_source(
+    source: &mut SoundSource,
+    context: &Context,
+    include_stack: &mut Vec<String>,
+    ref_stack: &mut Vec<String>,
+) -> Result<(), Error> {
+    match source {
+        SoundSource::Include(include) => {
+            let path = include_path(context.config_path, include);
+            if include_stack.contains(&path) {
+                return Err(Error::User(format!(
+                    "Config include cycle: {} -> {}",
+                    include_stack.join(" -> "),
+                    path
+                )));
+            }
+            let bytes = context.assets.load_asset_data(&path)?;
+            let mut included =
+                Config::from_bytes_in_format(&bytes, ConfigFormat::from_path(&path))?;
+            include_stack.push(path.clone());
+            resolve_config(&mut included, &path, context.assets, include_stack)?;
+            include_stack.pop();
+            *source = included.root;
+        }
+        SoundSource::Ref(name) => {
+            if ref_stack.contains(name) {
+                return Err(Error::User(format!(
+                    "Config reference cycle in {}: {} -> {}",
+                    context.config_path,
+                    ref_stack.join(" -> "),
+                    name
+                )));
+            }
+            let mut definition =
+                context
+                    .definitions
+                    .get(name.as_str())
+                    .cloned()
+                    .ok_or_else(|| {
+                        Error::User(format!(
+                            "No definition named {} in {}",
+                            name, context.config_path
+                        ))
+                    })?;
+            ref_stack.push(name.clone());
+            resolve_source(&mut definition, context, include_stack, ref_stack)?;
+            ref_stack.pop();
+            *source = definition;
+        }
+        _ => {
+            for child in source.children_mut() {
+                resolve_source(child, context, include_stack, ref_stack)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use crate::{Config, Error};
+
+/// The config format version written by this version of the crate.
+/// Configs without a version field are treated as version 0.
+pub const CURRENT_CONFIG_VERSION: u32 = 1;
+
+// Each step upgrades a config from the version at its index to the next version,
+// returning warnings about anything the author of the file may want to review.
+// Steps run on the parsed config, so they can change values, such as a default or a unit
+// that changed, but they never see the names fields were written under.
+type MigrationStep = fn(&mut Config) -> Vec<String>;
+
+const MIGRATION_STEPS: [MigrationStep; CURRENT_CONFIG_VERSION as usize] = [migrate_0_to_1];
+
+// Version 1 added the version field itself, along with defaulted fields for voice
+// limits, sample retriggering and output device preference, so nothing else changes
+fn migrate_0_to_1(_config: &mut Config) -> Vec<String> {
+    vec![]
+}
+
+impl Config {
+    /// Upgrade a config read from an older format version to the current one.
+    /// Return warnings describing the upgrade, or an error if the config was written for a
+    /// newer version of the format than this crate supports.
+    pub fn migrate(&mut self) -> Result<Vec<String>, Error> {
+        if self.version > CURRENT_CONFIG_VERSION {
+            return Err(Error::User(format!(
+                "Config version {} is newer than the supported version {}",
+                self.version, CURRENT_CONFIG_VERSION
+            )));
+        }
+        let mut warnings = vec![];
+        while self.version < CURRENT_CONFIG_VERSION {
+            let step = MIGRATION_STEPS[self.version as usize];
+            warnings.extend(step(self));
+            self.version += 1;
+        }
+        Ok(warnings)
+    }
+
+    // Migrate a freshly-parsed config, reporting any warnings to the console
+    pub(crate) fn migrate_with_warnings(mut self) -> Result<Self, Error> {
+        for warning in self.migrate()? {
+            log_warning!("Config", "{}", warning);
+        }
+        Ok(self)
+    }
+}
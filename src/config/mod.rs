@@ -1,131 +1,830 @@
-use crate::Error;
+pub mod builder;
+pub mod drums;
+pub mod export;
+pub mod host_event;
+pub mod include;
+pub mod migrate;
+pub mod notes;
+pub mod params;
+pub mod registry;
+pub mod validate;
+
+use crate::{util::BadSamplePolicy, Error, ParamTarget};
+use base64::Engine;
+use drums::ChipDrumKind;
+use notes::{NoteMapping, QuantizeDirection, Scale, SequencerStep};
+use params::ParamValue;
 use ron::de::from_bytes;
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 const fn none_id() -> Option<u64> {
     None
 }
 
-const fn default_amplitude() -> f32 {
-    0.5
+const fn default_fm_ratio() -> f32 {
+    1.0
+}
+
+const fn default_fm_level() -> f32 {
+    1.0
+}
+
+const fn default_fm_attack() -> f32 {
+    0.005
+}
+
+const fn default_fm_decay() -> f32 {
+    0.3
+}
+
+const fn default_fm_sustain() -> f32 {
+    0.6
+}
+
+const fn default_fm_release() -> f32 {
+    0.2
+}
+
+// A carrier with a modulator at twice its frequency, for a bright electric piano tone
+fn default_fm_operators() -> Vec<FmOperator> {
+    vec![
+        FmOperator::default(),
+        FmOperator {
+            ratio: 2.0,
+            level: 1.5,
+            ..FmOperator::default()
+        },
+    ]
+}
+
+fn default_harmonics() -> Vec<f32> {
+    vec![1.0]
+}
+
+const fn default_pluck_decay() -> ParamValue {
+    ParamValue::Fixed(0.996)
+}
+
+const fn default_pluck_brightness() -> ParamValue {
+    ParamValue::Fixed(0.5)
+}
+
+fn empty_custom_config() -> ron::Value {
+    ron::Value::Map(Default::default())
+}
+
+const fn default_sample_hold_rate() -> ParamValue {
+    ParamValue::Fixed(12.0)
+}
+
+const fn default_sample_hold_range() -> u8 {
+    12
+}
+
+const fn default_sequencer_bpm() -> f32 {
+    120.0
+}
+
+const fn default_steps_per_beat() -> u32 {
+    4
+}
+
+const fn default_gate_attack() -> f32 {
+    0.002
+}
+
+const fn default_gate_release() -> f32 {
+    0.01
+}
+
+const fn default_looping() -> bool {
+    true
+}
+
+const fn default_unison_voices() -> u8 {
+    1
 }
 
-const fn default_duty_cycle() -> f32 {
-    0.5
+const fn default_amplitude() -> ParamValue {
+    ParamValue::Fixed(0.5)
+}
+
+const fn default_duty_cycle() -> ParamValue {
+    ParamValue::Fixed(0.5)
 }
 
 const fn default_note_for_16_shifts() -> u8 {
     64
 }
 
-const fn default_attack() -> f32 {
-    0.125
+const fn default_drum_length() -> f32 {
+    1.0
+}
+
+const fn default_release_level() -> f32 {
+    1.0
+}
+
+const fn default_zero_time() -> ParamValue {
+    ParamValue::Fixed(0.0)
+}
+
+const fn default_attack() -> ParamValue {
+    ParamValue::Fixed(0.125)
+}
+
+const fn default_decay() -> ParamValue {
+    ParamValue::Fixed(0.25)
+}
+
+const fn default_sustain() -> ParamValue {
+    ParamValue::Fixed(0.5)
+}
+
+const fn default_release() -> ParamValue {
+    ParamValue::Fixed(0.125)
+}
+
+const fn default_zero_position() -> ParamValue {
+    ParamValue::Fixed(0.0)
+}
+
+const fn default_duck_depth() -> ParamValue {
+    ParamValue::Fixed(0.5)
+}
+
+const fn default_duck_attack() -> ParamValue {
+    ParamValue::Fixed(0.05)
+}
+
+const fn default_duck_release() -> ParamValue {
+    ParamValue::Fixed(0.5)
+}
+
+const fn default_duck_threshold() -> f32 {
+    0.01
+}
+
+const fn default_balance() -> ParamValue {
+    ParamValue::Fixed(0.5)
+}
+
+pub(crate) const fn default_max_voices() -> usize {
+    8
+}
+
+const fn default_ring_mod_depth() -> ParamValue {
+    ParamValue::Fixed(1.0)
+}
+
+const fn default_ring_mod_frequency() -> ParamValue {
+    ParamValue::Fixed(440.0)
+}
+
+const fn default_grain_time() -> f32 {
+    0.05
+}
+
+const fn default_modulation_rate() -> ParamValue {
+    ParamValue::Fixed(5.0)
+}
+
+const fn default_tremolo_depth() -> ParamValue {
+    ParamValue::Fixed(0.5)
+}
+
+const fn default_vibrato_depth() -> ParamValue {
+    ParamValue::Fixed(20.0)
+}
+
+const fn default_expander_ratio() -> ParamValue {
+    ParamValue::Fixed(10.0)
 }
 
-const fn default_decay() -> f32 {
-    0.25
+const fn default_expander_attack() -> ParamValue {
+    ParamValue::Fixed(0.001)
 }
 
-const fn default_sustain() -> f32 {
-    0.5
+const fn default_expander_release() -> ParamValue {
+    ParamValue::Fixed(0.1)
 }
 
-const fn default_release() -> f32 {
-    0.125
+const fn default_convolution_mix() -> ParamValue {
+    ParamValue::Fixed(1.0)
 }
 
-const fn default_balance() -> f32 {
-    0.5
+const fn default_crossfeed_amount() -> ParamValue {
+    ParamValue::Fixed(0.3)
 }
 
-#[derive(Deserialize, Clone)]
+const fn default_crossfeed_cutoff() -> ParamValue {
+    ParamValue::Fixed(700.0)
+}
+
+const fn default_crossfeed_delay() -> ParamValue {
+    ParamValue::Fixed(0.3)
+}
+
+// One buffer, the latency of the audio output at its default buffer size
+fn default_midi_output_latency() -> f32 {
+    crate::consts::BUFFER_SIZE as f32 / crate::consts::PLAYBACK_SAMPLE_RATE as f32
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Config {
+    /// Format version the config was written for; see Config::migrate.
+    #[serde(default)]
+    pub version: u32,
     pub root: SoundSource,
+    /// Name of the output device to prefer, as listed by BaseMixer::list_output_devices.
+    /// The default device is used if this is absent or the device is not available.
+    #[serde(default)]
+    pub output_device: Option<String>,
+    /// Named values that numeric fields in this file can use with Param.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub params: HashMap<String, f32>,
+    /// Named sets of param values, such as a muffled "underwater" mix, which can all be
+    /// moved to at once with BroadcastControl::RecallSnapshot.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub snapshots: HashMap<String, HashMap<String, f32>>,
+    /// Named sources that can be used any number of times in this file with Ref.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub definitions: HashMap<String, SoundSource>,
+}
+
+/// Text formats a Config can be read from, all using the same structure as RON. Enum
+/// variants are single-key maps in JSON, such as `{"SquareWave": {}}`, and tags in YAML,
+/// such as `!SquareWave {}`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ConfigFormat {
+    Ron,
+    #[cfg(feature = "json")]
+    Json,
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Choose a format from a file's extension, defaulting to RON.
+    pub fn from_path(path: &str) -> Self {
+        let extension = std::path::Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            #[cfg(feature = "json")]
+            Some("json") => ConfigFormat::Json,
+            #[cfg(feature = "yaml")]
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Ron,
+        }
+    }
 }
 
 impl Config {
     pub fn from_bytes(bytes: &[u8]) -> Result<Config, Error> {
-        let config = from_bytes(bytes)?;
+        Self::from_bytes_in_format(bytes, ConfigFormat::Ron)
+    }
+
+    pub fn from_bytes_in_format(bytes: &[u8], format: ConfigFormat) -> Result<Config, Error> {
+        let config: Config = match format {
+            ConfigFormat::Ron => from_bytes(bytes)?,
+            #[cfg(feature = "json")]
+            ConfigFormat::Json => serde_json::from_slice(bytes)?,
+            #[cfg(feature = "yaml")]
+            ConfigFormat::Yaml => serde_yaml::from_slice(bytes)?,
+        };
+        let mut config = config.migrate_with_warnings()?;
+        config.resolve_params()?;
         Ok(config)
     }
 }
 
-#[derive(Deserialize, Clone)]
+/// Asset data written directly into a config, so that small graphs can be entirely
+/// self-contained. Either a base64 string or a RON byte array, such as `Bytes([82, 73])`.
+#[derive(Deserialize, Serialize, Clone)]
+pub enum InlineData {
+    Base64(String),
+    Bytes(Vec<u8>),
+}
+
+impl InlineData {
+    pub fn decode(&self) -> Result<Cow<'_, [u8]>, Error> {
+        match self {
+            InlineData::Base64(text) => base64::engine::general_purpose::STANDARD
+                .decode(text.trim())
+                .map(Cow::Owned)
+                .map_err(|e| Error::User(format!("Invalid base64 asset data: {}", e))),
+            InlineData::Bytes(bytes) => Ok(Cow::Borrowed(bytes)),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub enum MidiDataSource {
     FilePath(String),
+    Inline(InlineData),
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 pub enum FontSource {
     Ranges(Vec<RangeSource>),
     Sf2FilePath {
         path: String,
         instrument_index: usize,
     },
+    Sf2Inline {
+        data: InlineData,
+        instrument_index: usize,
+    },
+}
+
+/// Tuning for the sources within a Tuned source. Cents offset keys from equal temperament,
+/// with either 12 values repeating every octave from C or 128 with one for every key.
+/// Scala files give a scale in a .scl file and optionally which keys play it in a .kbm.
+#[derive(Deserialize, Serialize, Clone)]
+pub enum TuningSource {
+    EqualTemperament,
+    Cents(Vec<f32>),
+    ScalaFilePath {
+        scl: String,
+        #[serde(default)]
+        kbm: Option<String>,
+    },
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct RangeSource {
     pub source: SoundSource,
     pub lower: u8,
     pub upper: u8,
+    #[serde(default)]
+    pub release: Option<ReleaseSource>,
+}
+
+/// A sound started as each note of a font range is released, such as the key-off noise of
+/// a sampled piano or harpsichord, at a level relative to the notes. It is started as a
+/// note of its own and should end by itself, as a sample that does not loop will.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ReleaseSource {
+    pub source: SoundSource,
+    #[serde(default = "default_release_level")]
+    pub level: f32,
+}
+
+/// How a font chooses a voice for a new note when all of its voices are busy.
+/// SameNote retriggers a voice already playing the same note, otherwise acting like Oldest.
+/// None drops the new note instead.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum VoiceStealing {
+    #[default]
+    Oldest,
+    Quietest,
+    SameNote,
+    None,
+}
+
+/// How square and sawtooth oscillators generate their waveforms. Naive waveforms switch
+/// instantly between levels, for the authentic grit of old sound chips, but alias harshly
+/// on high notes. BandLimited smooths each transition with PolyBLEP to avoid aliasing.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum OscillatorMode {
+    #[default]
+    Naive,
+    BandLimited,
+}
+
+/// How a ring modulator applies its carrier. Ring multiplies by the carrier as it swings
+/// between -1 and 1, and Amplitude by the carrier raised to swing between 0 and 1.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum RingModMode {
+    #[default]
+    Ring,
+    Amplitude,
+}
+
+/// The spectrum of a continuous noise source. White noise has equal energy at all
+/// frequencies, pink falls by 3dB per octave and brown by 6dB per octave, sounding
+/// progressively deeper, from hiss through rain to rumble.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum NoiseColor {
+    #[default]
+    White,
+    Pink,
+    Brown,
+}
+
+/// Pitch movement applied by tone generators on each note. A glide time in seconds
+/// slides from the previous note's pitch, for portamento. An envelope offset in
+/// semitones starts each note away from its pitch, returning over the envelope time in
+/// seconds, as in the falling pitch of an 808-style kick drum.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct PitchMotion {
+    #[serde(default)]
+    pub glide_time: f32,
+    #[serde(default)]
+    pub envelope_offset: f32,
+    #[serde(default)]
+    pub envelope_time: f32,
+}
+
+impl PitchMotion {
+    pub const NONE: Self = Self {
+        glide_time: 0.0,
+        envelope_offset: 0.0,
+        envelope_time: 0.0,
+    };
+}
+
+/// Several detuned copies of an oscillator played together for a thicker sound, as in a
+/// supersaw. Voices are spread evenly across the detune in cents either side of the note,
+/// and panned across the stereo spread from 0 (centred) to 1 (hard left and right).
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub struct Unison {
+    #[serde(default = "default_unison_voices")]
+    pub voices: u8,
+    #[serde(default)]
+    pub detune_cents: f32,
+    #[serde(default)]
+    pub spread: f32,
+}
+
+impl Unison {
+    pub const NONE: Self = Self {
+        voices: 1,
+        detune_cents: 0.0,
+        spread: 0.0,
+    };
+}
+
+impl Default for Unison {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// Random variation given to each playback of a sample, so that a sound repeated often,
+/// such as footsteps, does not sound mechanical. Pitch varies by up to the cents and level
+/// by up to the decibels either way, evenly distributed.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub struct Variation {
+    #[serde(default)]
+    pub cents: f32,
+    #[serde(default)]
+    pub decibels: f32,
+}
+
+impl Variation {
+    pub const NONE: Self = Self {
+        cents: 0.0,
+        decibels: 0.0,
+    };
+}
+
+impl Default for Variation {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// How the operators of an FM synth are connected, with operator 0 always heard:
+/// - Stack: each operator modulates the one before it, for the brightest tones
+/// - Pairs: operator 1 modulates 0 and operator 3 modulates 2, with 0 and 2 heard
+/// - Branch: every other operator modulates operator 0
+/// - Parallel: no modulation, with every operator heard, as an organ of sines
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum FmAlgorithm {
+    #[default]
+    Stack,
+    Pairs,
+    Branch,
+    Parallel,
+}
+
+/// One sine operator of an FM synth, running at a ratio of the note's frequency.
+/// The level is the modulation index for an operator that modulates another, and the
+/// volume for one that is heard. Envelope times are in seconds.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub struct FmOperator {
+    #[serde(default = "default_fm_ratio")]
+    pub ratio: f32,
+    #[serde(default = "default_fm_level")]
+    pub level: f32,
+    #[serde(default = "default_fm_attack")]
+    pub attack_time: f32,
+    #[serde(default = "default_fm_decay")]
+    pub decay_time: f32,
+    #[serde(default = "default_fm_sustain")]
+    pub sustain_level: f32,
+    #[serde(default = "default_fm_release")]
+    pub release_time: f32,
+}
+
+impl Default for FmOperator {
+    fn default() -> Self {
+        Self {
+            ratio: default_fm_ratio(),
+            level: default_fm_level(),
+            attack_time: default_fm_attack(),
+            decay_time: default_fm_decay(),
+            sustain_level: default_fm_sustain(),
+            release_time: default_fm_release(),
+        }
+    }
+}
+
+/// What a sample does when a note-on arrives while it is still playing.
+/// Restart jumps back to the start, Continue ignores the new note, and Overlap lets the
+/// previous playback ring out unlooped while the new note starts alongside it.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum Retrigger {
+    #[default]
+    Restart,
+    Continue,
+    Overlap,
+}
+
+/// How a sample plays its loop. Off plays straight through to the end of the data, ignoring
+/// the loop. UntilRelease loops while the note is held, then plays on from the loop to the
+/// end of the data once released. Continuous keeps looping after release until stopped, so
+/// is usually given an envelope to end it.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum LoopMode {
+    Off,
+    Continuous,
+    #[default]
+    UntilRelease,
+}
+
+/// The shape of an envelope segment. Exponential segments change slowly at first when
+/// rising and quickly at first when falling, as in analogue envelopes, while logarithmic
+/// segments do the opposite.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum EnvelopeCurve {
+    #[default]
+    Linear,
+    Exponential,
+    Logarithmic,
+}
+
+/// What an envelope does when a note-on arrives while it is still sounding.
+/// Reset restarts the attack from silence, FromCurrentLevel restarts the attack from the
+/// level already reached, and Legato carries on unchanged unless the note was released.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum EnvelopeRetrigger {
+    #[default]
+    Reset,
+    FromCurrentLevel,
+    Legato,
+}
+
+/// One point of a multi-stage envelope, reached a time in seconds after the point before it
+/// (or after the note-on, for the first point), along a segment of the given shape.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub struct Breakpoint {
+    pub time: f32,
+    pub level: f32,
+    #[serde(default)]
+    pub curve: EnvelopeCurve,
+}
+
+/// A control on a node within a multi-stage envelope's source, driven by the envelope's
+/// level rather than having the envelope shape the source's amplitude.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub struct ModulationTarget {
+    pub node_id: u64,
+    pub control: ParamTarget,
+}
+
+/// Waveform of a modulation matrix LFO, swinging between -1 and 1.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum LfoShape {
+    #[default]
+    Sine,
+    Triangle,
+    Square,
+    Sawtooth,
+}
+
+/// A signal driving routes in a modulation matrix:
+/// - Lfo: a free-running oscillator at a frequency in Hz, from -1 to 1
+/// - Envelope: a linear ADSR from 0 to 1 following note ons and offs, with times in
+///   seconds and a sustain level from 0 to 1
+/// - Velocity: the velocity of the latest note on, from 0 to 1
+/// - NoteNumber: the latest note on, from 0 to 1 across MIDI notes 0 to 127
+/// - Controller: the latest value of a MIDI controller, from 0 to 1
+/// - Random: a new value from -1 to 1 on each note on
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub enum ModSource {
+    Lfo {
+        frequency: f32,
+        #[serde(default)]
+        shape: LfoShape,
+    },
+    Envelope {
+        attack: f32,
+        decay: f32,
+        sustain: f32,
+        release: f32,
+    },
+    Velocity,
+    NoteNumber,
+    Controller(u8),
+    Random,
+}
+
+/// One routing in a modulation matrix, sending offset + depth * source to a control on a
+/// node within the matrix's source.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub struct ModRoute {
+    pub source: ModSource,
+    pub target: ModulationTarget,
+    pub depth: f32,
+    #[serde(default)]
+    pub offset: f32,
 }
 
 /// Loop range, defined as the inclusive start and exclusive end.
 /// These points are specified in frames, not data points.
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Loop {
     pub start: usize,
     pub end: usize,
+    #[serde(default)]
+    pub mode: LoopMode,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 pub enum SoundSource {
     Midi {
         #[serde(default = "none_id")]
         node_id: Option<u64>,
         source: MidiDataSource,
+        #[serde(default)]
+        sequence_index: usize,
         channels: HashMap<usize, SoundSource>,
+        #[serde(default)]
+        swing: f32,
+        #[serde(default)]
+        humanize_time: f32,
+        #[serde(default)]
+        humanize_velocity: f32,
     },
     EventReceiver {
         #[serde(default = "none_id")]
         node_id: Option<u64>,
         source: Box<SoundSource>,
     },
+    Tagged {
+        tags: Vec<String>,
+        source: Box<SoundSource>,
+    },
+    Named {
+        name: String,
+        source: Box<SoundSource>,
+    },
+    Tuned {
+        tuning: TuningSource,
+        source: Box<SoundSource>,
+    },
     Font {
         #[serde(default = "none_id")]
         node_id: Option<u64>,
         config: FontSource,
+        #[serde(default = "default_max_voices")]
+        max_voices: usize,
+        #[serde(default)]
+        voice_stealing: VoiceStealing,
+        #[serde(default)]
+        crossfade: u8,
     },
     SquareWave {
         #[serde(default = "none_id")]
         node_id: Option<u64>,
         #[serde(default = "default_amplitude")]
-        amplitude: f32,
+        amplitude: ParamValue,
         #[serde(default = "default_duty_cycle")]
-        duty_cycle: f32,
+        duty_cycle: ParamValue,
+        #[serde(default)]
+        oscillator: OscillatorMode,
+        #[serde(default)]
+        pitch: PitchMotion,
+        #[serde(default)]
+        unison: Unison,
+        #[serde(default)]
+        reset_phase: bool,
+        #[serde(default)]
+        gain: f32,
     },
     TriangleWave {
         #[serde(default = "none_id")]
         node_id: Option<u64>,
         #[serde(default = "default_amplitude")]
-        amplitude: f32,
+        amplitude: ParamValue,
+        #[serde(default)]
+        pitch: PitchMotion,
+        #[serde(default)]
+        reset_phase: bool,
+        #[serde(default)]
+        gain: f32,
     },
     SawtoothWave {
         #[serde(default = "none_id")]
         node_id: Option<u64>,
         #[serde(default = "default_amplitude")]
-        amplitude: f32,
+        amplitude: ParamValue,
+        #[serde(default)]
+        oscillator: OscillatorMode,
+        #[serde(default)]
+        pitch: PitchMotion,
+        #[serde(default)]
+        unison: Unison,
+        #[serde(default)]
+        reset_phase: bool,
+        #[serde(default)]
+        gain: f32,
+    },
+    FmSynth {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default = "default_amplitude")]
+        amplitude: ParamValue,
+        #[serde(default)]
+        algorithm: FmAlgorithm,
+        #[serde(default = "default_fm_operators")]
+        operators: Vec<FmOperator>,
+        #[serde(default)]
+        gain: f32,
+    },
+    Additive {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default = "default_amplitude")]
+        amplitude: ParamValue,
+        #[serde(default = "default_harmonics")]
+        harmonics: Vec<f32>,
+        #[serde(default)]
+        detune_cents: Vec<f32>,
+        #[serde(default)]
+        gain: f32,
+    },
+    PluckedString {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default = "default_amplitude")]
+        amplitude: ParamValue,
+        #[serde(default = "default_pluck_decay")]
+        decay: ParamValue,
+        #[serde(default = "default_pluck_brightness")]
+        brightness: ParamValue,
+        #[serde(default)]
+        gain: f32,
     },
     LfsrNoise {
         #[serde(default = "none_id")]
         node_id: Option<u64>,
         #[serde(default = "default_amplitude")]
-        amplitude: f32,
+        amplitude: ParamValue,
         inside_feedback: bool,
         #[serde(default = "default_note_for_16_shifts")]
         note_for_16_shifts: u8,
+        #[serde(default)]
+        gain: f32,
+    },
+    ColoredNoise {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default = "default_amplitude")]
+        amplitude: ParamValue,
+        #[serde(default)]
+        color: NoiseColor,
+        #[serde(default)]
+        gain: f32,
+    },
+    SampleHold {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default = "default_amplitude")]
+        amplitude: ParamValue,
+        #[serde(default = "default_sample_hold_rate")]
+        rate_hz: ParamValue,
+        #[serde(default = "default_sample_hold_range")]
+        range_semitones: u8,
+        #[serde(default)]
+        gain: f32,
+    },
+    ChipDrum {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        kind: ChipDrumKind,
+        #[serde(default = "default_amplitude")]
+        amplitude: ParamValue,
+        #[serde(default = "default_drum_length")]
+        length: f32,
+        #[serde(default)]
+        gain: f32,
     },
     SampleFilePath {
         #[serde(default = "none_id")]
@@ -133,52 +832,527 @@ pub enum SoundSource {
         path: String,
         base_note: u8,
         looping: Option<Loop>,
+        #[serde(default)]
+        retrigger: Retrigger,
+        #[serde(default)]
+        variation: Variation,
+        #[serde(default)]
+        bad_samples: BadSamplePolicy,
+        #[serde(default)]
+        gain: f32,
+    },
+    SampleInline {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        data: InlineData,
+        base_note: u8,
+        looping: Option<Loop>,
+        #[serde(default)]
+        retrigger: Retrigger,
+        #[serde(default)]
+        variation: Variation,
+        #[serde(default)]
+        gain: f32,
     },
     OneShotFilePath {
         #[serde(default = "none_id")]
         node_id: Option<u64>,
         path: String,
+        #[serde(default)]
+        retrigger: Retrigger,
+        #[serde(default)]
+        variation: Variation,
+        #[serde(default)]
+        choke_group: Option<String>,
+        #[serde(default)]
+        bad_samples: BadSamplePolicy,
+        #[serde(default)]
+        gain: f32,
+    },
+    OneShotInline {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        data: InlineData,
+        #[serde(default)]
+        retrigger: Retrigger,
+        #[serde(default)]
+        variation: Variation,
+        #[serde(default)]
+        choke_group: Option<String>,
+        #[serde(default)]
+        gain: f32,
     },
     Envelope {
         #[serde(default = "none_id")]
         node_id: Option<u64>,
         #[serde(default = "default_attack")]
-        attack_time: f32,
+        attack_time: ParamValue,
         #[serde(default = "default_decay")]
-        decay_time: f32,
+        decay_time: ParamValue,
         #[serde(default = "default_sustain")]
-        sustain_multiplier: f32,
+        sustain_multiplier: ParamValue,
         #[serde(default = "default_release")]
-        release_time: f32,
+        release_time: ParamValue,
+        #[serde(default)]
+        key_tracking: f32,
+        #[serde(default)]
+        velocity_tracking: f32,
+        #[serde(default)]
+        attack_curve: EnvelopeCurve,
+        #[serde(default)]
+        decay_curve: EnvelopeCurve,
+        #[serde(default)]
+        release_curve: EnvelopeCurve,
+        #[serde(default)]
+        retrigger: EnvelopeRetrigger,
+        #[serde(default = "default_zero_time")]
+        delay_time: ParamValue,
+        #[serde(default = "default_zero_time")]
+        hold_time: ParamValue,
+        source: Box<SoundSource>,
+    },
+    MultiStageEnvelope {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        points: Vec<Breakpoint>,
+        #[serde(default)]
+        sustain_point: Option<usize>,
+        #[serde(default)]
+        target: Option<ModulationTarget>,
+        source: Box<SoundSource>,
+    },
+    ModMatrix {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        routes: Vec<ModRoute>,
         source: Box<SoundSource>,
     },
     Combiner {
         #[serde(default = "none_id")]
         node_id: Option<u64>,
         sources: Vec<SoundSource>,
+        #[serde(default)]
+        hard_sync: bool,
+        /// Render the sources in parallel on a pool of worker threads sized to the available
+        /// cores, for large graphs of independent subtrees. Cannot be combined with hard_sync.
+        #[serde(default)]
+        parallel: bool,
     },
     Mixer {
         #[serde(default = "none_id")]
         node_id: Option<u64>,
         #[serde(default = "default_balance")]
-        balance: f32,
+        balance: ParamValue,
+        source_0: Box<SoundSource>,
+        source_1: Box<SoundSource>,
+    },
+    Crossfade {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default = "default_zero_position")]
+        position: ParamValue,
         source_0: Box<SoundSource>,
         source_1: Box<SoundSource>,
     },
+    Duck {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default = "default_duck_depth")]
+        depth: ParamValue,
+        #[serde(default = "default_duck_attack")]
+        attack_time: ParamValue,
+        #[serde(default = "default_duck_release")]
+        release_time: ParamValue,
+        #[serde(default = "default_duck_threshold")]
+        threshold: f32,
+        music: Box<SoundSource>,
+        priority: Box<SoundSource>,
+    },
     Fader {
         #[serde(default = "none_id")]
         node_id: Option<u64>,
-        initial_volume: f32,
+        initial_volume: ParamValue,
+        source: Box<SoundSource>,
+    },
+    RingMod {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default)]
+        mode: RingModMode,
+        #[serde(default = "default_ring_mod_depth")]
+        depth: ParamValue,
+        #[serde(default = "default_ring_mod_frequency")]
+        frequency: ParamValue,
+        #[serde(default)]
+        note_ratio: Option<f32>,
+        #[serde(default)]
+        carrier: Option<Box<SoundSource>>,
         source: Box<SoundSource>,
     },
+    PitchShift {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default)]
+        semitones: f32,
+        #[serde(default)]
+        cents: f32,
+        #[serde(default = "default_grain_time")]
+        grain_time: f32,
+        source: Box<SoundSource>,
+    },
+    Tremolo {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default = "default_modulation_rate")]
+        rate: ParamValue,
+        #[serde(default = "default_tremolo_depth")]
+        depth: ParamValue,
+        source: Box<SoundSource>,
+    },
+    Vibrato {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default = "default_modulation_rate")]
+        rate: ParamValue,
+        #[serde(default = "default_vibrato_depth")]
+        depth_cents: ParamValue,
+        source: Box<SoundSource>,
+    },
+    Convolution {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        path: String,
+        #[serde(default = "default_convolution_mix")]
+        mix: ParamValue,
+        #[serde(default)]
+        pre_delay_time: f32,
+        source: Box<SoundSource>,
+    },
+    Crossfeed {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default = "default_crossfeed_amount")]
+        amount: ParamValue,
+        #[serde(default = "default_crossfeed_cutoff")]
+        cutoff_hz: ParamValue,
+        #[serde(default = "default_crossfeed_delay")]
+        delay_ms: ParamValue,
+        source: Box<SoundSource>,
+    },
+    Sequencer {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default = "default_sequencer_bpm")]
+        bpm: f32,
+        #[serde(default = "default_steps_per_beat")]
+        steps_per_beat: u32,
+        #[serde(default)]
+        swing: f32,
+        #[serde(default = "default_looping")]
+        looping: bool,
+        steps: Vec<SequencerStep>,
+        source: Box<SoundSource>,
+    },
+    Gate {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        pattern: Vec<f32>,
+        #[serde(default = "default_steps_per_beat")]
+        steps_per_beat: u32,
+        #[serde(default = "default_sequencer_bpm")]
+        bpm: f32,
+        #[serde(default = "default_gate_attack")]
+        attack_time: f32,
+        #[serde(default = "default_gate_release")]
+        release_time: f32,
+        source: Box<SoundSource>,
+    },
+    GateExpander {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default = "default_duck_threshold")]
+        threshold: f32,
+        #[serde(default = "default_expander_ratio")]
+        ratio: ParamValue,
+        #[serde(default = "default_expander_attack")]
+        attack_time: ParamValue,
+        #[serde(default = "default_expander_release")]
+        release_time: ParamValue,
+        source: Box<SoundSource>,
+    },
+    Playlist {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default)]
+        crossfade_time: f32,
+        #[serde(default)]
+        looping: bool,
+        songs: Vec<SoundSource>,
+    },
+    NoteMap {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        #[serde(default)]
+        mapping: NoteMapping,
+        source: Box<SoundSource>,
+    },
+    ScaleQuantizer {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        scale: Scale,
+        #[serde(default)]
+        root: u8,
+        #[serde(default)]
+        direction: QuantizeDirection,
+        source: Box<SoundSource>,
+    },
+    TestSignal {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+    },
+    /// Sends notes to an external MIDI output port instead of rendering audio; needs the
+    /// midir feature.
+    MidiOutput {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        port: String,
+        #[serde(default)]
+        channel: u8,
+        #[serde(default = "default_midi_output_latency")]
+        latency: f32,
+    },
+    /// A node of a type registered by another crate; see NodeConfig.
+    Custom {
+        #[serde(default = "none_id")]
+        node_id: Option<u64>,
+        name: String,
+        #[serde(default = "empty_custom_config")]
+        config: ron::Value,
+        #[serde(default)]
+        sources: Vec<SoundSource>,
+    },
+    /// The root source of another config file, with a path relative to this file.
+    /// Replaced by that source when includes are resolved; see Config::resolve_includes.
+    Include(String),
+    /// A source from this file's definitions table, replaced when includes are resolved.
+    Ref(String),
 }
 
 impl SoundSource {
+    /// Give this source a fixed node ID, for sending it control events. Tags pass the ID
+    /// on to the source they hold, while includes and references have no ID of their own
+    /// and are returned unchanged.
+    pub fn with_node_id(mut self, id: u64) -> Self {
+        match &mut self {
+            SoundSource::Midi { node_id, .. }
+            | SoundSource::EventReceiver { node_id, .. }
+            | SoundSource::Font { node_id, .. }
+            | SoundSource::SquareWave { node_id, .. }
+            | SoundSource::TriangleWave { node_id, .. }
+            | SoundSource::SawtoothWave { node_id, .. }
+            | SoundSource::LfsrNoise { node_id, .. }
+            | SoundSource::ColoredNoise { node_id, .. }
+            | SoundSource::SampleHold { node_id, .. }
+            | SoundSource::ChipDrum { node_id, .. }
+            | SoundSource::FmSynth { node_id, .. }
+            | SoundSource::Additive { node_id, .. }
+            | SoundSource::PluckedString { node_id, .. }
+            | SoundSource::SampleFilePath { node_id, .. }
+            | SoundSource::SampleInline { node_id, .. }
+            | SoundSource::OneShotFilePath { node_id, .. }
+            | SoundSource::OneShotInline { node_id, .. }
+            | SoundSource::Envelope { node_id, .. }
+            | SoundSource::MultiStageEnvelope { node_id, .. }
+            | SoundSource::ModMatrix { node_id, .. }
+            | SoundSource::Combiner { node_id, .. }
+            | SoundSource::Mixer { node_id, .. }
+            | SoundSource::Crossfade { node_id, .. }
+            | SoundSource::Duck { node_id, .. }
+            | SoundSource::Fader { node_id, .. }
+            | SoundSource::RingMod { node_id, .. }
+            | SoundSource::PitchShift { node_id, .. }
+            | SoundSource::Tremolo { node_id, .. }
+            | SoundSource::Vibrato { node_id, .. }
+            | SoundSource::Convolution { node_id, .. }
+            | SoundSource::Crossfeed { node_id, .. }
+            | SoundSource::Sequencer { node_id, .. }
+            | SoundSource::Gate { node_id, .. }
+            | SoundSource::GateExpander { node_id, .. }
+            | SoundSource::Playlist { node_id, .. }
+            | SoundSource::NoteMap { node_id, .. }
+            | SoundSource::ScaleQuantizer { node_id, .. }
+            | SoundSource::Custom { node_id, .. }
+            | SoundSource::MidiOutput { node_id, .. }
+            | SoundSource::TestSignal { node_id } => *node_id = Some(id),
+            SoundSource::Tagged { source, .. }
+            | SoundSource::Named { source, .. }
+            | SoundSource::Tuned { source, .. } => {
+                let inner = std::mem::replace(source.as_mut(), SoundSource::Ref(String::new()));
+                **source = inner.with_node_id(id);
+            }
+            SoundSource::Include(_) | SoundSource::Ref(_) => {}
+        }
+        self
+    }
+
+    // Gain in decibels of a generator or sample, applied to its output after everything
+    // else, which is 0 for every other source
+    pub(crate) fn gain(&self) -> f32 {
+        match self {
+            SoundSource::SquareWave { gain, .. }
+            | SoundSource::TriangleWave { gain, .. }
+            | SoundSource::SawtoothWave { gain, .. }
+            | SoundSource::FmSynth { gain, .. }
+            | SoundSource::Additive { gain, .. }
+            | SoundSource::PluckedString { gain, .. }
+            | SoundSource::LfsrNoise { gain, .. }
+            | SoundSource::ColoredNoise { gain, .. }
+            | SoundSource::SampleHold { gain, .. }
+            | SoundSource::ChipDrum { gain, .. }
+            | SoundSource::SampleFilePath { gain, .. }
+            | SoundSource::SampleInline { gain, .. }
+            | SoundSource::OneShotFilePath { gain, .. }
+            | SoundSource::OneShotInline { gain, .. } => *gain,
+            _ => 0.0,
+        }
+    }
+
+    // The numeric fields of this source that may be taken from params
+    pub(crate) fn param_values_mut(&mut self) -> Vec<&mut ParamValue> {
+        match self {
+            SoundSource::SquareWave {
+                amplitude,
+                duty_cycle,
+                ..
+            } => vec![amplitude, duty_cycle],
+            SoundSource::TriangleWave { amplitude, .. }
+            | SoundSource::SawtoothWave { amplitude, .. }
+            | SoundSource::LfsrNoise { amplitude, .. }
+            | SoundSource::ColoredNoise { amplitude, .. }
+            | SoundSource::ChipDrum { amplitude, .. }
+            | SoundSource::FmSynth { amplitude, .. }
+            | SoundSource::Additive { amplitude, .. } => vec![amplitude],
+            SoundSource::Envelope {
+                attack_time,
+                decay_time,
+                sustain_multiplier,
+                release_time,
+                delay_time,
+                hold_time,
+                ..
+            } => vec![
+                attack_time,
+                decay_time,
+                sustain_multiplier,
+                release_time,
+                delay_time,
+                hold_time,
+            ],
+            SoundSource::PluckedString {
+                amplitude,
+                decay,
+                brightness,
+                ..
+            } => vec![amplitude, decay, brightness],
+            SoundSource::SampleHold {
+                amplitude, rate_hz, ..
+            } => vec![amplitude, rate_hz],
+            SoundSource::Mixer { balance, .. } => vec![balance],
+            SoundSource::Crossfade { position, .. } => vec![position],
+            SoundSource::Duck {
+                depth,
+                attack_time,
+                release_time,
+                ..
+            } => vec![depth, attack_time, release_time],
+            SoundSource::GateExpander {
+                ratio,
+                attack_time,
+                release_time,
+                ..
+            } => vec![ratio, attack_time, release_time],
+            SoundSource::Fader { initial_volume, .. } => vec![initial_volume],
+            SoundSource::Tremolo { rate, depth, .. } => vec![rate, depth],
+            SoundSource::Vibrato {
+                rate, depth_cents, ..
+            } => vec![rate, depth_cents],
+            SoundSource::RingMod {
+                depth, frequency, ..
+            } => vec![depth, frequency],
+            SoundSource::Convolution { mix, .. } => vec![mix],
+            SoundSource::Crossfeed {
+                amount,
+                cutoff_hz,
+                delay_ms,
+                ..
+            } => vec![amount, cutoff_hz, delay_ms],
+            _ => vec![],
+        }
+    }
+
+    // The sources directly nested in this one
+    pub(crate) fn children_mut(&mut self) -> Vec<&mut SoundSource> {
+        match self {
+            SoundSource::Midi { channels, .. } => channels.values_mut().collect(),
+            SoundSource::EventReceiver { source, .. }
+            | SoundSource::Tagged { source, .. }
+            | SoundSource::Named { source, .. }
+            | SoundSource::Tuned { source, .. }
+            | SoundSource::Envelope { source, .. }
+            | SoundSource::MultiStageEnvelope { source, .. }
+            | SoundSource::ModMatrix { source, .. }
+            | SoundSource::Fader { source, .. }
+            | SoundSource::PitchShift { source, .. }
+            | SoundSource::Tremolo { source, .. }
+            | SoundSource::Vibrato { source, .. }
+            | SoundSource::Convolution { source, .. }
+            | SoundSource::Crossfeed { source, .. }
+            | SoundSource::Sequencer { source, .. }
+            | SoundSource::Gate { source, .. }
+            | SoundSource::GateExpander { source, .. }
+            | SoundSource::NoteMap { source, .. }
+            | SoundSource::ScaleQuantizer { source, .. } => vec![source.as_mut()],
+            SoundSource::Font {
+                config: FontSource::Ranges(ranges),
+                ..
+            } => ranges
+                .iter_mut()
+                .flat_map(|range| {
+                    let release = range.release.as_mut().map(|release| &mut release.source);
+                    std::iter::once(&mut range.source).chain(release)
+                })
+                .collect(),
+            SoundSource::Combiner { sources, .. }
+            | SoundSource::Playlist { songs: sources, .. }
+            | SoundSource::Custom { sources, .. } => sources.iter_mut().collect(),
+            SoundSource::Mixer {
+                source_0, source_1, ..
+            }
+            | SoundSource::Crossfade {
+                source_0, source_1, ..
+            } => vec![source_0.as_mut(), source_1.as_mut()],
+            SoundSource::Duck {
+                music, priority, ..
+            } => vec![music.as_mut(), priority.as_mut()],
+            SoundSource::RingMod {
+                source, carrier, ..
+            } => std::iter::once(source.as_mut())
+                .chain(carrier.as_mut().map(|carrier| carrier.as_mut()))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
     pub const fn stock_square_wave() -> Self {
         SoundSource::SquareWave {
             node_id: none_id(),
             amplitude: default_amplitude(),
             duty_cycle: default_duty_cycle(),
+            oscillator: OscillatorMode::Naive,
+            pitch: PitchMotion::NONE,
+            unison: Unison::NONE,
+            reset_phase: false,
+            gain: 0.0,
         }
     }
 
@@ -186,6 +1360,9 @@ impl SoundSource {
         SoundSource::TriangleWave {
             node_id: none_id(),
             amplitude: default_amplitude(),
+            pitch: PitchMotion::NONE,
+            reset_phase: false,
+            gain: 0.0,
         }
     }
 
@@ -193,6 +1370,11 @@ impl SoundSource {
         SoundSource::SawtoothWave {
             node_id: none_id(),
             amplitude: default_amplitude(),
+            oscillator: OscillatorMode::Naive,
+            pitch: PitchMotion::NONE,
+            unison: Unison::NONE,
+            reset_phase: false,
+            gain: 0.0,
         }
     }
 
@@ -209,6 +1391,7 @@ impl SoundSource {
             amplitude: default_amplitude(),
             inside_feedback: inside_feedback_mode,
             note_for_16_shifts: default_note_for_16_shifts(),
+            gain: 0.0,
         }
     }
 
@@ -219,6 +1402,14 @@ impl SoundSource {
             decay_time: default_decay(),
             sustain_multiplier: default_sustain(),
             release_time: default_release(),
+            key_tracking: 0.0,
+            velocity_tracking: 0.0,
+            attack_curve: EnvelopeCurve::Linear,
+            decay_curve: EnvelopeCurve::Linear,
+            release_curve: EnvelopeCurve::Linear,
+            retrigger: EnvelopeRetrigger::Reset,
+            delay_time: default_zero_time(),
+            hold_time: default_zero_time(),
             source: Box::new(inner),
         }
     }
@@ -235,7 +1426,17 @@ impl SoundSource {
     pub fn stock_fader(inner: SoundSource) -> Self {
         SoundSource::Fader {
             node_id: none_id(),
-            initial_volume: 1.0,
+            initial_volume: ParamValue::Fixed(1.0),
+            source: Box::new(inner),
+        }
+    }
+
+    pub fn stock_crossfeed(inner: SoundSource) -> Self {
+        SoundSource::Crossfeed {
+            node_id: none_id(),
+            amount: default_crossfeed_amount(),
+            cutoff_hz: default_crossfeed_cutoff(),
+            delay_ms: default_crossfeed_delay(),
             source: Box::new(inner),
         }
     }
@@ -247,7 +1448,11 @@ impl SoundSource {
                 source,
                 lower: 0,
                 upper: 127,
+                release: None,
             }]),
+            max_voices: default_max_voices(),
+            voice_stealing: VoiceStealing::Oldest,
+            crossfade: 0,
         }
     }
 }
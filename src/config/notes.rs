@@ -0,0 +1,168 @@
+use serde_derive::{Deserialize, Serialize};
+
+const MAX_NOTE: u8 = 127;
+
+/// A musical scale, as the semitones above its root that belong to it. Custom scales list
+/// those semitones directly, each in the range 0 to 11.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug, Default)]
+pub enum Scale {
+    #[default]
+    Chromatic,
+    Major,
+    Minor,
+    HarmonicMinor,
+    Dorian,
+    Mixolydian,
+    MajorPentatonic,
+    MinorPentatonic,
+    Blues,
+    Custom(Vec<u8>),
+}
+
+impl Scale {
+    pub fn intervals(&self) -> &[u8] {
+        match self {
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Blues => &[0, 3, 5, 6, 7, 10],
+            Scale::Custom(intervals) => intervals,
+        }
+    }
+
+    /// Whether the note belongs to this scale when built on the given root, where the
+    /// root is a pitch class from 0 (C) to 11 (B).
+    pub fn contains(&self, root: u8, note: u8) -> bool {
+        let degree = (note as i32 - root as i32).rem_euclid(12) as u8;
+        self.intervals().contains(&degree)
+    }
+
+    /// The note of this scale that the given note snaps to, or None if there is no scale
+    /// note in that direction within the MIDI note range.
+    pub fn quantize(&self, root: u8, note: u8, direction: QuantizeDirection) -> Option<u8> {
+        let below = (0..=note).rev().find(|note| self.contains(root, *note));
+        let above = (note..=MAX_NOTE).find(|note| self.contains(root, *note));
+        match direction {
+            QuantizeDirection::Up => above,
+            QuantizeDirection::Down => below,
+            QuantizeDirection::Nearest => match (below, above) {
+                (Some(below), Some(above)) => match above - note < note - below {
+                    true => Some(above),
+                    false => Some(below),
+                },
+                (below, above) => below.or(above),
+            },
+        }
+    }
+}
+
+/// Which way a note outside a scale moves to reach it. Nearest breaks ties downwards.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum QuantizeDirection {
+    #[default]
+    Nearest,
+    Up,
+    Down,
+}
+
+/// How a velocity is remapped, with velocities in the range 0 to 1. Power raises the
+/// velocity to the given exponent, so that values above 1 soften quieter notes and values
+/// below 1 bring them up. Fixed plays every note at the same velocity.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum VelocityCurve {
+    #[default]
+    Linear,
+    Power(f32),
+    Fixed(f32),
+}
+
+impl VelocityCurve {
+    pub fn apply(&self, vel: f32) -> f32 {
+        match self {
+            VelocityCurve::Linear => vel,
+            VelocityCurve::Power(exponent) => vel.max(0.0).powf(*exponent),
+            VelocityCurve::Fixed(fixed) => *fixed,
+        }
+    }
+}
+
+const fn default_step_velocity() -> f32 {
+    1.0
+}
+
+const fn default_step_gate() -> f32 {
+    0.5
+}
+
+/// One step of a sequencer pattern. Notes have a velocity from 0 to 1 and a gate length
+/// measured in steps, which may run on past the step to tie into the ones following.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub enum SequencerStep {
+    Rest,
+    Note {
+        note: u8,
+        #[serde(default = "default_step_velocity")]
+        velocity: f32,
+        #[serde(default = "default_step_gate")]
+        gate: f32,
+    },
+}
+
+const fn default_upper() -> u8 {
+    MAX_NOTE
+}
+
+/// Changes made to the notes passing through a NoteMap. Incoming notes outside the range
+/// from lower to upper are dropped, the rest are transposed by some semitones, and then
+/// any that fall outside the scale on the given root are dropped as well.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub struct NoteMapping {
+    #[serde(default)]
+    pub transpose: i8,
+    #[serde(default)]
+    pub velocity: VelocityCurve,
+    #[serde(default)]
+    pub scale: Scale,
+    #[serde(default)]
+    pub root: u8,
+    #[serde(default)]
+    pub lower: u8,
+    #[serde(default = "default_upper")]
+    pub upper: u8,
+}
+
+impl Default for NoteMapping {
+    fn default() -> Self {
+        Self {
+            transpose: 0,
+            velocity: VelocityCurve::Linear,
+            scale: Scale::Chromatic,
+            root: 0,
+            lower: 0,
+            upper: MAX_NOTE,
+        }
+    }
+}
+
+impl NoteMapping {
+    /// The note and velocity that an incoming note becomes, or None if it is dropped.
+    pub fn map(&self, note: u8, vel: f32) -> Option<(u8, f32)> {
+        if note < self.lower || note > self.upper {
+            return None;
+        }
+        let mapped = note as i32 + self.transpose as i32;
+        if !(0..=MAX_NOTE as i32).contains(&mapped) {
+            return None;
+        }
+        let mapped = mapped as u8;
+        if !self.scale.contains(self.root, mapped) {
+            return None;
+        }
+        Some((mapped, self.velocity.apply(vel)))
+    }
+}
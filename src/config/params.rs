@@ -0,0 +1,220 @@
+use crate::{Config, Error, SoundSource};
+use serde::de::{self, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde::ser::Serializer;
+use std::collections::HashMap;
+
+/// A number in a config, either written directly or named from the config's params table,
+/// such as `initial_volume: Param("master_volume")`. Params are resolved when the config is
+/// read; the resolved value keeps its name so that nodes can be re-bound at runtime with
+/// BroadcastControl::SetParam and the config can be written back out unchanged, along with
+/// the param's value in each of the config's snapshots by snapshot ID.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ParamValue {
+    Fixed(f32),
+    Param(String),
+    Bound {
+        name: String,
+        value: f32,
+        snapshots: Vec<(u64, f32)>,
+    },
+}
+
+impl ParamValue {
+    /// The number to use when building a node, which fails for unresolved params.
+    pub fn value(&self) -> Result<f32, Error> {
+        match self {
+            ParamValue::Fixed(value) => Ok(*value),
+            ParamValue::Bound { value, .. } => Ok(*value),
+            ParamValue::Param(name) => Err(Error::User(format!(
+                "Param {} must be resolved before loading",
+                name
+            ))),
+        }
+    }
+
+    /// The name of the param this value was taken from, if any.
+    pub fn param_name(&self) -> Option<&str> {
+        match self {
+            ParamValue::Fixed(_) => None,
+            ParamValue::Param(name) => Some(name),
+            ParamValue::Bound { name, .. } => Some(name),
+        }
+    }
+
+    /// The values of the param in the config's snapshots, empty if not taken from a param.
+    pub fn snapshot_values(&self) -> &[(u64, f32)] {
+        match self {
+            ParamValue::Bound { snapshots, .. } => snapshots,
+            _ => &[],
+        }
+    }
+
+    fn resolve(
+        &mut self,
+        params: &HashMap<String, f32>,
+        snapshots: &HashMap<String, HashMap<String, f32>>,
+    ) -> Result<(), Error> {
+        let Some(name) = self.param_name() else {
+            return Ok(());
+        };
+        let value = *params
+            .get(name)
+            .ok_or_else(|| Error::User(format!("No param named {} in config", name)))?;
+        let snapshot_values = snapshots
+            .iter()
+            .filter_map(|(snapshot, values)| {
+                values
+                    .get(name)
+                    .map(|value| (snapshot_id(snapshot), *value))
+            })
+            .collect();
+        *self = ParamValue::Bound {
+            name: name.to_owned(),
+            value,
+            snapshots: snapshot_values,
+        };
+        Ok(())
+    }
+}
+
+impl From<f32> for ParamValue {
+    fn from(value: f32) -> Self {
+        ParamValue::Fixed(value)
+    }
+}
+
+/// Get the identifier used for a param in BroadcastControl::SetParam events.
+pub fn param_id(name: &str) -> u64 {
+    // FNV-1a, so that identifiers are stable between runs and builds
+    name.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Get the identifier used for a snapshot in BroadcastControl::RecallSnapshot events.
+pub fn snapshot_id(name: &str) -> u64 {
+    param_id(name)
+}
+
+/// Get the node ID used to send control events to every node with a tag.
+pub fn tag_id(name: &str) -> u64 {
+    param_id(name)
+}
+
+/// Get the node ID used to send control events to a Named source. This differs from the
+/// tag_id of the same string, so that a name and a tag may share one.
+pub fn name_id(name: &str) -> u64 {
+    param_id(&format!("name:{}", name))
+}
+
+impl Config {
+    /// Replace every Param value with its entry in the params table, keeping the name
+    /// for re-binding at runtime. This is done automatically when a config is read.
+    pub fn resolve_params(&mut self) -> Result<(), Error> {
+        for (snapshot, values) in self.snapshots.iter() {
+            if let Some(name) = values.keys().find(|name| !self.params.contains_key(*name)) {
+                return Err(Error::User(format!(
+                    "Snapshot {} sets {}, which is not a param in config",
+                    snapshot, name
+                )));
+            }
+        }
+        resolve_source(&mut self.root, &self.params, &self.snapshots)?;
+        for definition in self.definitions.values_mut() {
+            resolve_source(definition, &self.params, &self.snapshots)?;
+        }
+        Ok(())
+    }
+}
+
+fn resolve_source(
+    source: &mut SoundSource,
+    params: &HashMap<String, f32>,
+    snapshots: &HashMap<String, HashMap<String, f32>>,
+) -> Result<(), Error> {
+    for value in source.param_values_mut() {
+        value.resolve(params, snapshots)?;
+    }
+    for child in source.children_mut() {
+        resolve_source(child, params, snapshots)?;
+    }
+    Ok(())
+}
+
+impl serde::Serialize for ParamValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ParamValue::Fixed(value) => serializer.serialize_f32(*value),
+            ParamValue::Param(name) | ParamValue::Bound { name, .. } => {
+                serializer.serialize_newtype_variant("ParamValue", 1, "Param", name)
+            }
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ParamValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ParamValueVisitor)
+    }
+}
+
+struct ParamValueVisitor;
+
+impl<'de> Visitor<'de> for ParamValueVisitor {
+    type Value = ParamValue;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a number or Param(name)")
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<ParamValue, E> {
+        Ok(ParamValue::Fixed(value as f32))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<ParamValue, E> {
+        Ok(ParamValue::Fixed(value as f32))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<ParamValue, E> {
+        Ok(ParamValue::Fixed(value as f32))
+    }
+
+    // RON presents Param("name") as a sequence holding the name
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ParamValue, A::Error> {
+        let name: String = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        if seq.next_element::<de::IgnoredAny>()?.is_some() {
+            return Err(de::Error::invalid_length(2, &self));
+        }
+        Ok(ParamValue::Param(name))
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<ParamValue, D::Error> {
+        let name = <String as serde::Deserialize>::deserialize(deserializer)?;
+        Ok(ParamValue::Param(name))
+    }
+
+    // JSON writes Param("name") as {"Param": "name"}
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<ParamValue, A::Error> {
+        let key: String = map
+            .next_key()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        if key != "Param" {
+            return Err(de::Error::unknown_variant(&key, &["Param"]));
+        }
+        Ok(ParamValue::Param(map.next_value()?))
+    }
+
+    // YAML writes Param("name") as !Param name
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<ParamValue, A::Error> {
+        let (variant, access): (String, _) = data.variant()?;
+        if variant != "Param" {
+            return Err(de::Error::unknown_variant(&variant, &["Param"]));
+        }
+        Ok(ParamValue::Param(access.newtype_variant()?))
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{BufferConsumerNode, Error};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+/// Config for a node type defined outside this crate. Register the type with
+/// register_node_type, and it can then appear in config files as a Custom source:
+///
+/// ```ron
+/// Custom(name: "Bitcrusher", config: (bits: 4), sources: [SquareWave()])
+/// ```
+///
+/// The config is deserialized into the registered type, and the sources are loaded and
+/// passed to build as the node's inputs.
+pub trait NodeConfig: DeserializeOwned {
+    fn build(
+        &self,
+        node_id: Option<u64>,
+        sources: Vec<Box<dyn BufferConsumerNode + Send + 'static>>,
+    ) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error>;
+}
+
+type BuildFn = fn(
+    &str,
+    Option<u64>,
+    ron::Value,
+    Vec<Box<dyn BufferConsumerNode + Send + 'static>>,
+) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error>;
+
+fn registry() -> &'static RwLock<HashMap<String, BuildFn>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, BuildFn>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+fn build_registered<C: NodeConfig>(
+    name: &str,
+    node_id: Option<u64>,
+    config: ron::Value,
+    sources: Vec<Box<dyn BufferConsumerNode + Send + 'static>>,
+) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+    let config: C = config
+        .into_rust()
+        .map_err(|e| Error::User(format!("Invalid config for custom node {}: {}", name, e)))?;
+    config.build(node_id, sources)
+}
+
+/// Make a node type available to configs under the given name, replacing any type
+/// previously registered with that name.
+pub fn register_node_type<C: NodeConfig>(name: &str) {
+    let mut registry = registry().write().unwrap_or_else(|e| e.into_inner());
+    registry.insert(name.to_owned(), build_registered::<C>);
+}
+
+pub fn is_node_type_registered(name: &str) -> bool {
+    let registry = registry().read().unwrap_or_else(|e| e.into_inner());
+    registry.contains_key(name)
+}
+
+pub(crate) fn build_custom_node(
+    name: &str,
+    node_id: Option<u64>,
+    config: &ron::Value,
+    sources: Vec<Box<dyn BufferConsumerNode + Send + 'static>>,
+) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+    let build = {
+        let registry = registry().read().unwrap_or_else(|e| e.into_inner());
+        *registry
+            .get(name)
+            .ok_or_else(|| Error::User(format!("No custom node type registered as {}", name)))?
+    };
+    build(name, node_id, config.clone(), sources)
+}
@@ -0,0 +1,984 @@
+use crate::{
+    config::notes::{NoteMapping, Scale, SequencerStep, VelocityCurve},
+    config::registry::is_node_type_registered,
+    source::{fm::MAX_FM_OPERATORS, unison::MAX_UNISON_VOICES},
+    AssetLoader, Config, Error, FontSource, InlineData, Loop, MidiDataSource, ModSource,
+    ParamValue, PitchMotion, SoundSource, TuningSource, Unison, Variation,
+};
+
+const MAX_NOTE: u8 = 127;
+const MIDI_CHANNEL_COUNT: usize = 16;
+
+/// A problem found in a config, with the path to the offending field, such as
+/// `root.Midi.channels[1].Font.config.Ranges[2].source.SquareWave.duty_cycle`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ValidationError {
+    pub path: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "{}: {}", self.path, self.message)
+    }
+}
+
+impl Config {
+    /// Check the config for values that parse but cannot be played, such as notes above 127
+    /// or empty loops. If an asset loader is given, also check that every asset it names
+    /// is available from it.
+    pub fn validate(&self, assets: Option<&dyn AssetLoader>) -> Result<(), Error> {
+        let errors = self.validation_errors(assets);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(errors))
+        }
+    }
+
+    /// As validate, but returning every problem found rather than an error.
+    pub fn validation_errors(&self, assets: Option<&dyn AssetLoader>) -> Vec<ValidationError> {
+        let mut validator = Validator {
+            assets,
+            errors: vec![],
+            names: vec![],
+        };
+        validator.check_source("root", &self.root);
+        validator.errors
+    }
+}
+
+struct Validator<'a> {
+    assets: Option<&'a dyn AssetLoader>,
+    errors: Vec<ValidationError>,
+    names: Vec<String>,
+}
+
+impl Validator<'_> {
+    fn report(&mut self, path: &str, message: String) {
+        self.errors.push(ValidationError {
+            path: path.to_owned(),
+            message,
+        });
+    }
+
+    fn number(&mut self, path: &str, value: &ParamValue) -> Option<f32> {
+        match value.value() {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.report(path, e.to_string());
+                None
+            }
+        }
+    }
+
+    fn check_range(&mut self, path: &str, value: &ParamValue, min: f32, max: f32) {
+        let Some(value) = self.number(path, value) else {
+            return;
+        };
+        if !(min..=max).contains(&value) {
+            self.report(
+                path,
+                format!("{} is outside the range {} to {}", value, min, max),
+            );
+        }
+    }
+
+    fn check_non_negative(&mut self, path: &str, value: &ParamValue) {
+        let Some(value) = self.number(path, value) else {
+            return;
+        };
+        if value.is_nan() || value < 0.0 {
+            self.report(path, format!("{} must not be negative", value));
+        }
+    }
+
+    fn check_pitch_motion(&mut self, path: &str, pitch: &PitchMotion) {
+        self.check_non_negative(&format!("{}.glide_time", path), &pitch.glide_time.into());
+        self.check_non_negative(
+            &format!("{}.envelope_time", path),
+            &pitch.envelope_time.into(),
+        );
+    }
+
+    fn check_unison(&mut self, path: &str, unison: &Unison) {
+        if unison.voices == 0 || unison.voices as usize > MAX_UNISON_VOICES {
+            self.report(
+                &format!("{}.voices", path),
+                format!("Between 1 and {} voices are needed", MAX_UNISON_VOICES),
+            );
+        }
+        self.check_non_negative(
+            &format!("{}.detune_cents", path),
+            &unison.detune_cents.into(),
+        );
+        self.check_range(&format!("{}.spread", path), &unison.spread.into(), 0.0, 1.0);
+    }
+
+    fn check_variation(&mut self, path: &str, variation: &Variation) {
+        self.check_non_negative(&format!("{}.cents", path), &variation.cents.into());
+        self.check_non_negative(&format!("{}.decibels", path), &variation.decibels.into());
+    }
+
+    fn check_note_mapping(&mut self, path: &str, mapping: &NoteMapping) {
+        self.check_note(&format!("{}.lower", path), mapping.lower);
+        self.check_note(&format!("{}.upper", path), mapping.upper);
+        if mapping.lower > mapping.upper {
+            self.report(
+                path,
+                format!(
+                    "Lower note {} is above upper note {}",
+                    mapping.lower, mapping.upper
+                ),
+            );
+        }
+        self.check_scale(path, &mapping.scale, mapping.root);
+        match mapping.velocity {
+            VelocityCurve::Linear => {}
+            VelocityCurve::Power(exponent) => {
+                if exponent.is_nan() || exponent <= 0.0 {
+                    self.report(
+                        &format!("{}.velocity", path),
+                        format!("Exponent {} must be above zero", exponent),
+                    );
+                }
+            }
+            VelocityCurve::Fixed(vel) => {
+                self.check_range(&format!("{}.velocity", path), &vel.into(), 0.0, 1.0);
+            }
+        }
+    }
+
+    fn check_scale(&mut self, path: &str, scale: &Scale, root: u8) {
+        if root > 11 {
+            self.report(
+                &format!("{}.root", path),
+                format!("Root {} is not a pitch class from 0 to 11", root),
+            );
+        }
+        if scale.intervals().is_empty() {
+            self.report(
+                &format!("{}.scale", path),
+                "A scale needs at least one note".to_owned(),
+            );
+        }
+        if let Some(interval) = scale.intervals().iter().find(|interval| **interval > 11) {
+            self.report(
+                &format!("{}.scale", path),
+                format!("Interval {} is outside the octave", interval),
+            );
+        }
+    }
+
+    fn check_note(&mut self, path: &str, note: u8) {
+        if note > MAX_NOTE {
+            self.report(path, format!("Note {} is above {}", note, MAX_NOTE));
+        }
+    }
+
+    fn check_asset(&mut self, path: &str, asset_path: &str) {
+        if let Some(assets) = self.assets {
+            if !assets.has_asset(asset_path) {
+                self.report(path, format!("Asset {} was not found", asset_path));
+            }
+        }
+    }
+
+    fn check_inline(&mut self, path: &str, data: &InlineData) {
+        if let Err(e) = data.decode() {
+            self.report(path, e.to_string());
+        }
+    }
+
+    fn check_loop(&mut self, path: &str, looping: &Option<Loop>) {
+        if let Some(Loop { start, end, .. }) = looping {
+            if start >= end {
+                self.report(
+                    path,
+                    format!("Loop start {} is not before its end {}", start, end),
+                );
+            }
+        }
+    }
+
+    fn check_source(&mut self, path: &str, source: &SoundSource) {
+        match source {
+            SoundSource::Midi {
+                source,
+                channels,
+                swing,
+                humanize_time,
+                humanize_velocity,
+                ..
+            } => {
+                let path = format!("{}.Midi", path);
+                self.check_range(&format!("{}.swing", path), &(*swing).into(), 0.0, 0.5);
+                self.check_non_negative(
+                    &format!("{}.humanize_time", path),
+                    &(*humanize_time).into(),
+                );
+                self.check_range(
+                    &format!("{}.humanize_velocity", path),
+                    &(*humanize_velocity).into(),
+                    0.0,
+                    1.0,
+                );
+                match source {
+                    MidiDataSource::FilePath(file) => {
+                        self.check_asset(&format!("{}.source.FilePath", path), file)
+                    }
+                    MidiDataSource::Inline(data) => {
+                        self.check_inline(&format!("{}.source.Inline", path), data)
+                    }
+                }
+                let mut channel_numbers: Vec<&usize> = channels.keys().collect();
+                channel_numbers.sort();
+                for channel in channel_numbers {
+                    let channel_path = format!("{}.channels[{}]", path, channel);
+                    if *channel >= MIDI_CHANNEL_COUNT {
+                        self.report(
+                            &channel_path,
+                            format!("MIDI channel must be below {}", MIDI_CHANNEL_COUNT),
+                        );
+                    }
+                    self.check_source(&channel_path, &channels[channel]);
+                }
+            }
+            SoundSource::EventReceiver { source, .. } => {
+                self.check_source(&format!("{}.EventReceiver.source", path), source);
+            }
+            SoundSource::Tagged { tags, source } => {
+                let path = format!("{}.Tagged", path);
+                for (index, tag) in tags.iter().enumerate() {
+                    if tag.is_empty() {
+                        self.report(
+                            &format!("{}.tags[{}]", path, index),
+                            "Tags must not be empty".to_owned(),
+                        );
+                    }
+                }
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::Named { name, source } => {
+                let path = format!("{}.Named", path);
+                if name.is_empty() {
+                    self.report(
+                        &format!("{}.name", path),
+                        "Names must not be empty".to_owned(),
+                    );
+                } else if self.names.contains(name) {
+                    self.report(
+                        &format!("{}.name", path),
+                        format!("The name {} is given to more than one source", name),
+                    );
+                } else {
+                    self.names.push(name.clone());
+                }
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::Tuned { tuning, source } => {
+                let path = format!("{}.Tuned", path);
+                match tuning {
+                    TuningSource::EqualTemperament => {}
+                    TuningSource::Cents(cents) => {
+                        if cents.len() != 12 && cents.len() != 128 {
+                            self.report(
+                                &format!("{}.tuning.Cents", path),
+                                format!("Expected 12 or 128 offsets, but got {}", cents.len()),
+                            );
+                        }
+                    }
+                    TuningSource::ScalaFilePath { scl, kbm } => {
+                        self.check_asset(&format!("{}.tuning.ScalaFilePath.scl", path), scl);
+                        if let Some(kbm) = kbm {
+                            self.check_asset(&format!("{}.tuning.ScalaFilePath.kbm", path), kbm);
+                        }
+                    }
+                }
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::Font {
+                config, max_voices, ..
+            } => {
+                let path = format!("{}.Font", path);
+                if *max_voices == 0 {
+                    self.report(
+                        &format!("{}.max_voices", path),
+                        "At least one voice is required".to_owned(),
+                    );
+                }
+                match config {
+                    FontSource::Ranges(ranges) => {
+                        for (index, range) in ranges.iter().enumerate() {
+                            let range_path = format!("{}.config.Ranges[{}]", path, index);
+                            // Range bounds may exceed 127, as an upper bound of 255 is
+                            // commonly used to cover every note
+                            if range.lower > range.upper {
+                                self.report(
+                                    &range_path,
+                                    format!(
+                                        "Lower note {} is above upper note {}",
+                                        range.lower, range.upper
+                                    ),
+                                );
+                            }
+                            self.check_source(&format!("{}.source", range_path), &range.source);
+                            if let Some(release) = range.release.as_ref() {
+                                let release_path = format!("{}.release", range_path);
+                                self.check_non_negative(
+                                    &format!("{}.level", release_path),
+                                    &release.level.into(),
+                                );
+                                self.check_source(
+                                    &format!("{}.source", release_path),
+                                    &release.source,
+                                );
+                            }
+                        }
+                    }
+                    FontSource::Sf2FilePath { path: file, .. } => {
+                        self.check_asset(&format!("{}.config.Sf2FilePath.path", path), file)
+                    }
+                    FontSource::Sf2Inline { data, .. } => {
+                        self.check_inline(&format!("{}.config.Sf2Inline.data", path), data)
+                    }
+                }
+            }
+            SoundSource::SquareWave {
+                amplitude,
+                duty_cycle,
+                pitch,
+                unison,
+                ..
+            } => {
+                let path = format!("{}.SquareWave", path);
+                self.check_non_negative(&format!("{}.amplitude", path), amplitude);
+                self.check_range(&format!("{}.duty_cycle", path), duty_cycle, 0.0, 1.0);
+                self.check_pitch_motion(&format!("{}.pitch", path), pitch);
+                self.check_unison(&format!("{}.unison", path), unison);
+            }
+            SoundSource::TriangleWave {
+                amplitude, pitch, ..
+            } => {
+                self.check_non_negative(&format!("{}.TriangleWave.amplitude", path), amplitude);
+                self.check_pitch_motion(&format!("{}.TriangleWave.pitch", path), pitch);
+            }
+            SoundSource::SawtoothWave {
+                amplitude,
+                pitch,
+                unison,
+                ..
+            } => {
+                let path = format!("{}.SawtoothWave", path);
+                self.check_non_negative(&format!("{}.amplitude", path), amplitude);
+                self.check_pitch_motion(&format!("{}.pitch", path), pitch);
+                self.check_unison(&format!("{}.unison", path), unison);
+            }
+            SoundSource::FmSynth {
+                amplitude,
+                operators,
+                ..
+            } => {
+                let path = format!("{}.FmSynth", path);
+                self.check_non_negative(&format!("{}.amplitude", path), amplitude);
+                if operators.is_empty() || operators.len() > MAX_FM_OPERATORS {
+                    self.report(
+                        &format!("{}.operators", path),
+                        format!("Between 1 and {} operators are needed", MAX_FM_OPERATORS),
+                    );
+                }
+                for (index, operator) in operators.iter().enumerate() {
+                    let path = format!("{}.operators[{}]", path, index);
+                    if operator.ratio.is_nan() || operator.ratio <= 0.0 {
+                        self.report(
+                            &format!("{}.ratio", path),
+                            format!("{} must be above zero", operator.ratio),
+                        );
+                    }
+                    let fields = [
+                        ("level", operator.level),
+                        ("attack_time", operator.attack_time),
+                        ("decay_time", operator.decay_time),
+                        ("release_time", operator.release_time),
+                    ];
+                    for (field, value) in fields {
+                        self.check_non_negative(&format!("{}.{}", path, field), &value.into());
+                    }
+                    self.check_range(
+                        &format!("{}.sustain_level", path),
+                        &operator.sustain_level.into(),
+                        0.0,
+                        1.0,
+                    );
+                }
+            }
+            SoundSource::Additive {
+                amplitude,
+                harmonics,
+                detune_cents,
+                ..
+            } => {
+                let path = format!("{}.Additive", path);
+                self.check_non_negative(&format!("{}.amplitude", path), amplitude);
+                if harmonics.is_empty() {
+                    self.report(
+                        &format!("{}.harmonics", path),
+                        "At least one harmonic is needed".to_owned(),
+                    );
+                }
+                if detune_cents.len() > harmonics.len() {
+                    self.report(
+                        &format!("{}.detune_cents", path),
+                        format!(
+                            "{} detune values given for {} harmonics",
+                            detune_cents.len(),
+                            harmonics.len()
+                        ),
+                    );
+                }
+            }
+            SoundSource::PluckedString {
+                amplitude,
+                decay,
+                brightness,
+                ..
+            } => {
+                let path = format!("{}.PluckedString", path);
+                self.check_non_negative(&format!("{}.amplitude", path), amplitude);
+                self.check_range(&format!("{}.decay", path), decay, 0.0, 1.0);
+                self.check_range(&format!("{}.brightness", path), brightness, 0.0, 1.0);
+            }
+            SoundSource::LfsrNoise {
+                amplitude,
+                note_for_16_shifts,
+                ..
+            } => {
+                self.check_non_negative(&format!("{}.LfsrNoise.amplitude", path), amplitude);
+                self.check_note(
+                    &format!("{}.LfsrNoise.note_for_16_shifts", path),
+                    *note_for_16_shifts,
+                );
+            }
+            SoundSource::ColoredNoise { amplitude, .. } => {
+                self.check_non_negative(&format!("{}.ColoredNoise.amplitude", path), amplitude);
+            }
+            SoundSource::SampleHold {
+                amplitude,
+                rate_hz,
+                range_semitones,
+                ..
+            } => {
+                let path = format!("{}.SampleHold", path);
+                self.check_non_negative(&format!("{}.amplitude", path), amplitude);
+                self.check_range(&format!("{}.rate_hz", path), rate_hz, 0.01, 1000.0);
+                self.check_range(
+                    &format!("{}.range_semitones", path),
+                    &(*range_semitones as f32).into(),
+                    0.0,
+                    127.0,
+                );
+            }
+            SoundSource::ChipDrum {
+                amplitude, length, ..
+            } => {
+                let path = format!("{}.ChipDrum", path);
+                self.check_non_negative(&format!("{}.amplitude", path), amplitude);
+                self.check_range(&format!("{}.length", path), &(*length).into(), 0.1, 10.0);
+            }
+            SoundSource::SampleFilePath {
+                path: file,
+                base_note,
+                looping,
+                variation,
+                ..
+            } => {
+                let path = format!("{}.SampleFilePath", path);
+                self.check_asset(&format!("{}.path", path), file);
+                self.check_note(&format!("{}.base_note", path), *base_note);
+                self.check_loop(&format!("{}.looping", path), looping);
+                self.check_variation(&format!("{}.variation", path), variation);
+            }
+            SoundSource::SampleInline {
+                data,
+                base_note,
+                looping,
+                variation,
+                ..
+            } => {
+                let path = format!("{}.SampleInline", path);
+                self.check_inline(&format!("{}.data", path), data);
+                self.check_note(&format!("{}.base_note", path), *base_note);
+                self.check_loop(&format!("{}.looping", path), looping);
+                self.check_variation(&format!("{}.variation", path), variation);
+            }
+            SoundSource::OneShotFilePath {
+                path: file,
+                variation,
+                ..
+            } => {
+                let path = format!("{}.OneShotFilePath", path);
+                self.check_asset(&format!("{}.path", path), file);
+                self.check_variation(&format!("{}.variation", path), variation);
+            }
+            SoundSource::OneShotInline {
+                data, variation, ..
+            } => {
+                let path = format!("{}.OneShotInline", path);
+                self.check_inline(&format!("{}.data", path), data);
+                self.check_variation(&format!("{}.variation", path), variation);
+            }
+            SoundSource::Envelope {
+                attack_time,
+                decay_time,
+                sustain_multiplier,
+                release_time,
+                key_tracking,
+                velocity_tracking,
+                delay_time,
+                hold_time,
+                source,
+                ..
+            } => {
+                let path = format!("{}.Envelope", path);
+                self.check_non_negative(&format!("{}.delay_time", path), delay_time);
+                self.check_non_negative(&format!("{}.hold_time", path), hold_time);
+                self.check_non_negative(&format!("{}.attack_time", path), attack_time);
+                self.check_non_negative(&format!("{}.decay_time", path), decay_time);
+                self.check_range(
+                    &format!("{}.sustain_multiplier", path),
+                    sustain_multiplier,
+                    0.0,
+                    1.0,
+                );
+                self.check_non_negative(&format!("{}.release_time", path), release_time);
+                self.check_range(
+                    &format!("{}.key_tracking", path),
+                    &(*key_tracking).into(),
+                    -1.0,
+                    1.0,
+                );
+                self.check_range(
+                    &format!("{}.velocity_tracking", path),
+                    &(*velocity_tracking).into(),
+                    -1.0,
+                    1.0,
+                );
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::MultiStageEnvelope {
+                points,
+                sustain_point,
+                source,
+                ..
+            } => {
+                let path = format!("{}.MultiStageEnvelope", path);
+                if points.is_empty() {
+                    self.report(
+                        &format!("{}.points", path),
+                        "At least one point is needed".to_owned(),
+                    );
+                }
+                for (index, point) in points.iter().enumerate() {
+                    self.check_non_negative(
+                        &format!("{}.points[{}].time", path, index),
+                        &point.time.into(),
+                    );
+                }
+                if let Some(sustain_point) = sustain_point {
+                    if *sustain_point >= points.len() {
+                        self.report(
+                            &format!("{}.sustain_point", path),
+                            format!("{} is past the last point", sustain_point),
+                        );
+                    }
+                }
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::ModMatrix { routes, source, .. } => {
+                let path = format!("{}.ModMatrix", path);
+                for (index, route) in routes.iter().enumerate() {
+                    let path = format!("{}.routes[{}].source", path, index);
+                    match route.source {
+                        ModSource::Lfo { frequency, .. } => {
+                            self.check_non_negative(
+                                &format!("{}.Lfo.frequency", path),
+                                &frequency.into(),
+                            );
+                        }
+                        ModSource::Envelope {
+                            attack,
+                            decay,
+                            sustain,
+                            release,
+                        } => {
+                            let path = format!("{}.Envelope", path);
+                            self.check_non_negative(&format!("{}.attack", path), &attack.into());
+                            self.check_non_negative(&format!("{}.decay", path), &decay.into());
+                            self.check_range(
+                                &format!("{}.sustain", path),
+                                &sustain.into(),
+                                0.0,
+                                1.0,
+                            );
+                            self.check_non_negative(&format!("{}.release", path), &release.into());
+                        }
+                        ModSource::Controller(controller) if controller > 127 => {
+                            self.report(
+                                &format!("{}.Controller", path),
+                                format!("Controller {} must be below 128", controller),
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::Combiner {
+                sources,
+                hard_sync,
+                parallel,
+                ..
+            } => {
+                if *hard_sync && *parallel {
+                    self.report(
+                        &format!("{}.Combiner.parallel", path),
+                        "Sources rendered in parallel cannot be hard-synced".to_owned(),
+                    );
+                }
+                for (index, source) in sources.iter().enumerate() {
+                    self.check_source(&format!("{}.Combiner.sources[{}]", path, index), source);
+                }
+            }
+            SoundSource::Mixer {
+                balance,
+                source_0,
+                source_1,
+                ..
+            } => {
+                let path = format!("{}.Mixer", path);
+                self.check_range(&format!("{}.balance", path), balance, 0.0, 1.0);
+                self.check_source(&format!("{}.source_0", path), source_0);
+                self.check_source(&format!("{}.source_1", path), source_1);
+            }
+            SoundSource::Crossfade {
+                position,
+                source_0,
+                source_1,
+                ..
+            } => {
+                let path = format!("{}.Crossfade", path);
+                self.check_range(&format!("{}.position", path), position, 0.0, 1.0);
+                self.check_source(&format!("{}.source_0", path), source_0);
+                self.check_source(&format!("{}.source_1", path), source_1);
+            }
+            SoundSource::Duck {
+                depth,
+                attack_time,
+                release_time,
+                threshold,
+                music,
+                priority,
+                ..
+            } => {
+                let path = format!("{}.Duck", path);
+                self.check_range(&format!("{}.depth", path), depth, 0.0, 1.0);
+                self.check_non_negative(&format!("{}.attack_time", path), attack_time);
+                self.check_non_negative(&format!("{}.release_time", path), release_time);
+                self.check_non_negative(&format!("{}.threshold", path), &(*threshold).into());
+                self.check_source(&format!("{}.music", path), music);
+                self.check_source(&format!("{}.priority", path), priority);
+            }
+            SoundSource::Fader {
+                initial_volume,
+                source,
+                ..
+            } => {
+                let path = format!("{}.Fader", path);
+                self.check_non_negative(&format!("{}.initial_volume", path), initial_volume);
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::RingMod {
+                depth,
+                frequency,
+                note_ratio,
+                carrier,
+                source,
+                ..
+            } => {
+                let path = format!("{}.RingMod", path);
+                self.check_range(&format!("{}.depth", path), depth, 0.0, 1.0);
+                self.check_non_negative(&format!("{}.frequency", path), frequency);
+                if let Some(note_ratio) = note_ratio {
+                    self.check_non_negative(&format!("{}.note_ratio", path), &(*note_ratio).into());
+                }
+                if let Some(carrier) = carrier {
+                    self.check_source(&format!("{}.carrier", path), carrier);
+                }
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::PitchShift {
+                grain_time, source, ..
+            } => {
+                let path = format!("{}.PitchShift", path);
+                self.check_range(
+                    &format!("{}.grain_time", path),
+                    &(*grain_time).into(),
+                    0.005,
+                    1.0,
+                );
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::Tremolo {
+                rate,
+                depth,
+                source,
+                ..
+            } => {
+                let path = format!("{}.Tremolo", path);
+                self.check_non_negative(&format!("{}.rate", path), rate);
+                self.check_range(&format!("{}.depth", path), depth, 0.0, 1.0);
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::Vibrato {
+                rate,
+                depth_cents,
+                source,
+                ..
+            } => {
+                let path = format!("{}.Vibrato", path);
+                self.check_non_negative(&format!("{}.rate", path), rate);
+                self.check_range(&format!("{}.depth_cents", path), depth_cents, 0.0, 1200.0);
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::Convolution {
+                path: file,
+                mix,
+                pre_delay_time,
+                source,
+                ..
+            } => {
+                let path = format!("{}.Convolution", path);
+                self.check_asset(&format!("{}.path", path), file);
+                self.check_range(&format!("{}.mix", path), mix, 0.0, 1.0);
+                self.check_range(
+                    &format!("{}.pre_delay_time", path),
+                    &(*pre_delay_time).into(),
+                    0.0,
+                    1.0,
+                );
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::Crossfeed {
+                amount,
+                cutoff_hz,
+                delay_ms,
+                source,
+                ..
+            } => {
+                let path = format!("{}.Crossfeed", path);
+                self.check_range(&format!("{}.amount", path), amount, 0.0, 1.0);
+                let cutoff_path = format!("{}.cutoff_hz", path);
+                if let Some(cutoff_hz) = self.number(&cutoff_path, cutoff_hz) {
+                    if cutoff_hz.is_nan() || cutoff_hz <= 0.0 {
+                        self.report(&cutoff_path, format!("{} must be above zero", cutoff_hz));
+                    }
+                }
+                self.check_non_negative(&format!("{}.delay_ms", path), delay_ms);
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::Gate {
+                pattern,
+                steps_per_beat,
+                bpm,
+                attack_time,
+                release_time,
+                source,
+                ..
+            } => {
+                let path = format!("{}.Gate", path);
+                if pattern.is_empty() {
+                    self.report(
+                        &format!("{}.pattern", path),
+                        "At least one step is needed".to_owned(),
+                    );
+                }
+                for (index, level) in pattern.iter().enumerate() {
+                    self.check_range(
+                        &format!("{}.pattern[{}]", path, index),
+                        &(*level).into(),
+                        0.0,
+                        1.0,
+                    );
+                }
+                if *steps_per_beat == 0 {
+                    self.report(
+                        &format!("{}.steps_per_beat", path),
+                        "0 must be above zero".to_owned(),
+                    );
+                }
+                if bpm.is_nan() || *bpm <= 0.0 {
+                    self.report(
+                        &format!("{}.bpm", path),
+                        format!("{} must be above zero", bpm),
+                    );
+                }
+                self.check_non_negative(&format!("{}.attack_time", path), &(*attack_time).into());
+                self.check_non_negative(&format!("{}.release_time", path), &(*release_time).into());
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::GateExpander {
+                threshold,
+                ratio,
+                attack_time,
+                release_time,
+                source,
+                ..
+            } => {
+                let path = format!("{}.GateExpander", path);
+                self.check_non_negative(&format!("{}.threshold", path), &(*threshold).into());
+                let ratio_path = format!("{}.ratio", path);
+                if let Some(ratio) = self.number(&ratio_path, ratio) {
+                    if ratio.is_nan() || ratio < 1.0 {
+                        self.report(&ratio_path, format!("{} must be at least 1", ratio));
+                    }
+                }
+                self.check_non_negative(&format!("{}.attack_time", path), attack_time);
+                self.check_non_negative(&format!("{}.release_time", path), release_time);
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::Sequencer {
+                bpm,
+                steps_per_beat,
+                swing,
+                steps,
+                source,
+                ..
+            } => {
+                let path = format!("{}.Sequencer", path);
+                if bpm.is_nan() || *bpm <= 0.0 {
+                    self.report(
+                        &format!("{}.bpm", path),
+                        format!("{} must be above zero", bpm),
+                    );
+                }
+                if *steps_per_beat == 0 {
+                    self.report(
+                        &format!("{}.steps_per_beat", path),
+                        "0 must be above zero".to_owned(),
+                    );
+                }
+                self.check_range(&format!("{}.swing", path), &(*swing).into(), 0.0, 0.5);
+                for (index, step) in steps.iter().enumerate() {
+                    if let SequencerStep::Note {
+                        note,
+                        velocity,
+                        gate,
+                    } = step
+                    {
+                        let path = format!("{}.steps[{}].Note", path, index);
+                        self.check_note(&format!("{}.note", path), *note);
+                        self.check_range(
+                            &format!("{}.velocity", path),
+                            &(*velocity).into(),
+                            0.0,
+                            1.0,
+                        );
+                        if gate.is_nan() || *gate <= 0.0 {
+                            self.report(
+                                &format!("{}.gate", path),
+                                format!("{} must be above zero", gate),
+                            );
+                        }
+                    }
+                }
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::Playlist {
+                crossfade_time,
+                songs,
+                ..
+            } => {
+                let path = format!("{}.Playlist", path);
+                self.check_non_negative(
+                    &format!("{}.crossfade_time", path),
+                    &(*crossfade_time).into(),
+                );
+                if songs.is_empty() {
+                    self.report(
+                        &format!("{}.songs", path),
+                        "A playlist needs at least one song".to_owned(),
+                    );
+                }
+                for (index, song) in songs.iter().enumerate() {
+                    let path = format!("{}.songs[{}]", path, index);
+                    if !matches!(song, SoundSource::Midi { .. }) {
+                        self.report(&path, "Playlist songs must be Midi sources".to_owned());
+                    }
+                    self.check_source(&path, song);
+                }
+            }
+            SoundSource::NoteMap {
+                mapping, source, ..
+            } => {
+                let path = format!("{}.NoteMap", path);
+                self.check_note_mapping(&format!("{}.mapping", path), mapping);
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::ScaleQuantizer {
+                scale,
+                root,
+                source,
+                ..
+            } => {
+                let path = format!("{}.ScaleQuantizer", path);
+                self.check_scale(&path, scale, *root);
+                self.check_source(&format!("{}.source", path), source);
+            }
+            SoundSource::TestSignal { .. } => {}
+            SoundSource::MidiOutput {
+                port,
+                channel,
+                latency,
+                ..
+            } => {
+                let path = format!("{}.MidiOutput", path);
+                if port.is_empty() {
+                    self.report(
+                        &format!("{}.port", path),
+                        "A port name is needed".to_owned(),
+                    );
+                }
+                if *channel as usize >= MIDI_CHANNEL_COUNT {
+                    self.report(
+                        &format!("{}.channel", path),
+                        format!("MIDI channel must be below {}", MIDI_CHANNEL_COUNT),
+                    );
+                }
+                self.check_non_negative(&format!("{}.latency", path), &(*latency).into());
+            }
+            SoundSource::Custom { name, sources, .. } => {
+                if !is_node_type_registered(name) {
+                    self.report(
+                        &format!("{}.Custom.name", path),
+                        format!("No custom node type registered as {}", name),
+                    );
+                }
+                for (index, source) in sources.iter().enumerate() {
+                    self.check_source(&format!("{}.Custom.sources[{}]", path, index), source);
+                }
+            }
+            SoundSource::Include(include) => {
+                self.report(
+                    &format!("{}.Include", path),
+                    format!("Include of {} has not been resolved", include),
+                );
+            }
+            SoundSource::Ref(name) => {
+                self.report(
+                    &format!("{}.Ref", path),
+                    format!("Reference to {} has not been resolved", name),
+                );
+            }
+        }
+    }
+}
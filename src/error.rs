@@ -1,13 +1,29 @@
 #[derive(Debug)]
 pub enum Error {
     User(String),
+    Validation(Vec<crate::ValidationError>),
     Io(std::io::Error),
     Ron(ron::error::SpannedError),
+    RonWrite(ron::Error),
+    #[cfg(feature = "json")]
+    Json(serde_json::Error),
+    #[cfg(feature = "yaml")]
+    Yaml(serde_yaml::Error),
     Midly(midly::Error),
     Hound(hound::Error),
     Soundfont(soundfont::Error),
+    #[cfg(feature = "driver-cpal")]
     CpalBuild(cpal::BuildStreamError),
+    #[cfg(feature = "driver-cpal")]
     CpalPlay(cpal::PlayStreamError),
+    #[cfg(feature = "driver-cpal")]
+    CpalPause(cpal::PauseStreamError),
+    #[cfg(feature = "driver-cpal")]
+    CpalDevices(cpal::DevicesError),
+    #[cfg(feature = "midir")]
+    MidirInit(midir::InitError),
+    #[cfg(feature = "midir")]
+    MidirConnect(midir::ConnectErrorKind),
     NoDevice,
 }
 
@@ -15,13 +31,37 @@ impl std::fmt::Display for Error {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::User(e) => e.fmt(fmt),
+            Error::Validation(errors) => {
+                for (index, error) in errors.iter().enumerate() {
+                    if index > 0 {
+                        fmt.write_str("\n")?;
+                    }
+                    error.fmt(fmt)?;
+                }
+                Ok(())
+            }
             Error::Io(e) => e.fmt(fmt),
             Error::Ron(e) => e.fmt(fmt),
+            Error::RonWrite(e) => e.fmt(fmt),
+            #[cfg(feature = "json")]
+            Error::Json(e) => e.fmt(fmt),
+            #[cfg(feature = "yaml")]
+            Error::Yaml(e) => e.fmt(fmt),
             Error::Midly(e) => e.fmt(fmt),
             Error::Hound(e) => e.fmt(fmt),
             Error::Soundfont(e) => fmt.write_fmt(format_args!("{:?}", e)),
+            #[cfg(feature = "driver-cpal")]
             Error::CpalBuild(e) => e.fmt(fmt),
+            #[cfg(feature = "driver-cpal")]
             Error::CpalPlay(e) => e.fmt(fmt),
+            #[cfg(feature = "driver-cpal")]
+            Error::CpalPause(e) => e.fmt(fmt),
+            #[cfg(feature = "driver-cpal")]
+            Error::CpalDevices(e) => e.fmt(fmt),
+            #[cfg(feature = "midir")]
+            Error::MidirInit(e) => e.fmt(fmt),
+            #[cfg(feature = "midir")]
+            Error::MidirConnect(e) => e.fmt(fmt),
             Error::NoDevice => "No audio device available".fmt(fmt),
         }
     }
@@ -41,6 +81,26 @@ impl From<ron::error::SpannedError> for Error {
     }
 }
 
+impl From<ron::Error> for Error {
+    fn from(value: ron::Error) -> Self {
+        Error::RonWrite(value)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Error::Json(value)
+    }
+}
+
+#[cfg(feature = "yaml")]
+impl From<serde_yaml::Error> for Error {
+    fn from(value: serde_yaml::Error) -> Self {
+        Error::Yaml(value)
+    }
+}
+
 impl From<hound::Error> for Error {
     fn from(value: hound::Error) -> Self {
         Error::Hound(value)
@@ -59,14 +119,37 @@ impl From<soundfont::Error> for Error {
     }
 }
 
+#[cfg(feature = "driver-cpal")]
 impl From<cpal::BuildStreamError> for Error {
     fn from(value: cpal::BuildStreamError) -> Self {
         Error::CpalBuild(value)
     }
 }
 
+#[cfg(feature = "driver-cpal")]
 impl From<cpal::PlayStreamError> for Error {
     fn from(value: cpal::PlayStreamError) -> Self {
         Error::CpalPlay(value)
     }
 }
+
+#[cfg(feature = "driver-cpal")]
+impl From<cpal::PauseStreamError> for Error {
+    fn from(value: cpal::PauseStreamError) -> Self {
+        Error::CpalPause(value)
+    }
+}
+
+#[cfg(feature = "driver-cpal")]
+impl From<cpal::DevicesError> for Error {
+    fn from(value: cpal::DevicesError) -> Self {
+        Error::CpalDevices(value)
+    }
+}
+
+#[cfg(feature = "midir")]
+impl From<midir::InitError> for Error {
+    fn from(value: midir::InitError) -> Self {
+        Error::MidirInit(value)
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{
+    asset_paths, AssetLoader, BufferConsumerNode, Error, EventChannel, GraphLoader, SampleCache,
+    SoundSource,
+};
+use crossbeam_channel::{bounded, Receiver, TryRecvError};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use std::task::{Context, Poll, Waker};
+use std::time::SystemTime;
+
+type LoadedGraph = (
+    Vec<EventChannel>,
+    Box<dyn BufferConsumerNode + Send + 'static>,
+);
+
+/// How far a background graph build has got, counting the distinct assets the graph uses.
+/// Assets already in the loader's sample cache are only counted once the build finishes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LoadProgress {
+    pub assets_loaded: usize,
+    pub asset_count: usize,
+}
+
+impl LoadProgress {
+    /// Progress from 0 to 1, suitable for a loading bar.
+    pub fn fraction(&self) -> f32 {
+        match self.asset_count {
+            0 => 1.0,
+            count => self.assets_loaded.min(count) as f32 / count as f32,
+        }
+    }
+}
+
+// State shared between a handle and the worker thread building its graph
+struct LoadState {
+    assets_loaded: AtomicUsize,
+    loaded_paths: Mutex<Vec<String>>,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl LoadState {
+    fn wake(&self) {
+        if let Some(waker) = self.waker.lock().expect("Could not lock load state").take() {
+            waker.wake();
+        }
+    }
+}
+
+// Asset loader counting each distinct asset it is asked for
+struct ProgressAssetLoader<A: AssetLoader> {
+    loader: A,
+    state: Arc<LoadState>,
+}
+
+impl<A: AssetLoader> AssetLoader for ProgressAssetLoader<A> {
+    fn load_asset_data(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let bytes = self.loader.load_asset_data(path)?;
+        let mut loaded_paths = self
+            .state
+            .loaded_paths
+            .lock()
+            .expect("Could not lock load state");
+        if !loaded_paths.iter().any(|loaded| loaded == path) {
+            loaded_paths.push(path.to_owned());
+            self.state.assets_loaded.fetch_add(1, Ordering::Release);
+            self.state.wake();
+        }
+        Ok(bytes)
+    }
+
+    fn has_asset(&self, path: &str) -> bool {
+        self.loader.has_asset(path)
+    }
+
+    fn on_asset_error(&self, path: &str, error: Error) -> Result<(), Error> {
+        self.loader.on_asset_error(path, error)
+    }
+
+    fn sample_cache(&self) -> Option<&SampleCache> {
+        self.loader.sample_cache()
+    }
+
+    fn asset_modified(&self, path: &str) -> Option<SystemTime> {
+        self.loader.asset_modified(path)
+    }
+}
+
+/// A graph being built on a worker thread, started by GraphLoader::load_async. Poll it
+/// from a game loop with progress and try_take, block on it with wait, or await it from
+/// any async runtime.
+pub struct GraphLoadHandle {
+    asset_count: usize,
+    state: Arc<LoadState>,
+    receiver: Receiver<Result<LoadedGraph, Error>>,
+}
+
+impl GraphLoadHandle {
+    pub(crate) fn spawn<A: AssetLoader + Send + 'static>(loader: A, source: SoundSource) -> Self {
+        let asset_count = asset_paths(&source).len();
+        let state = Arc::new(LoadState {
+            assets_loaded: AtomicUsize::new(0),
+            loaded_paths: Mutex::new(vec![]),
+            waker: Mutex::new(None),
+        });
+        let (sender, receiver) = bounded(1);
+        let worker_state = state.clone();
+        std::thread::spawn(move || {
+            let loader = ProgressAssetLoader {
+                loader,
+                state: worker_state.clone(),
+            };
+            let result = loader.load_source_recursive(&source);
+            worker_state
+                .assets_loaded
+                .store(asset_count, Ordering::Release);
+            // The handle may have been dropped, in which case the graph is discarded
+            let _ = sender.send(result);
+            worker_state.wake();
+        });
+        Self {
+            asset_count,
+            state,
+            receiver,
+        }
+    }
+
+    pub fn progress(&self) -> LoadProgress {
+        LoadProgress {
+            assets_loaded: self.state.assets_loaded.load(Ordering::Acquire),
+            asset_count: self.asset_count,
+        }
+    }
+
+    /// Take the built graph or the error that stopped the build, if it has finished. The
+    /// result can only be taken once; later calls give an error.
+    pub fn try_take(&self) -> Option<Result<LoadedGraph, Error>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(Self::worker_lost())),
+        }
+    }
+
+    /// Block until the build finishes.
+    pub fn wait(self) -> Result<LoadedGraph, Error> {
+        self.receiver
+            .recv()
+            .unwrap_or_else(|_| Err(Self::worker_lost()))
+    }
+
+    fn worker_lost() -> Error {
+        Error::User("Graph loading thread stopped without a result".to_owned())
+    }
+}
+
+impl Future for GraphLoadHandle {
+    type Output = Result<LoadedGraph, Error>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        // Register before checking, so that a result sent in between still wakes the task
+        *self.state.waker.lock().expect("Could not lock load state") =
+            Some(context.waker().clone());
+        match self.try_take() {
+            Some(result) => Poll::Ready(result),
+            None => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{
+    file::{
+        font::{sf2_zones_from_bytes, Sf2Zone},
+        loader::load_asset,
+        wav::{wav_data_from_bytes_with_policy, BadSamplePolicy},
+    },
+    AssetLoader, Error, FileGraphLoader, FontSource, GraphLoader, SoundSource,
+};
+use hound::WavSpec;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+type CachedWav = (WavSpec, Arc<[f32]>);
+
+// Path of a WAV file and how it was decoded, since a truncated decode must not stand in
+// for a graph that wants bad samples to fail
+type WavKey = (String, BadSamplePolicy);
+
+// Path and instrument index of an SF2 instrument
+type FontKey = (String, usize);
+
+// Decoded data along with when the asset it came from last changed, where known
+struct Entry<T> {
+    modified: Option<SystemTime>,
+    data: T,
+}
+
+/// Decoded sample data kept by asset path, so that graph entries referencing the same WAV
+/// or SF2 file share one decoded copy rather than each reloading it. Loaders provide a
+/// cache through AssetLoader::sample_cache. Where the loader can tell when an asset last
+/// changed, as FileGraphLoader can from the file's modified time, data from an older
+/// version is decoded again and replaced; otherwise entries stay until evicted or cleared.
+/// Decoding happens outside the cache's locks, so loads on other threads are not held up.
+#[derive(Default)]
+pub struct SampleCache {
+    wavs: Mutex<HashMap<WavKey, Entry<CachedWav>>>,
+    fonts: Mutex<HashMap<FontKey, Entry<Arc<[Sf2Zone]>>>>,
+}
+
+// Find data decoded from the asset as it is now, or else decode it and keep it. The lock
+// is released while decoding, so two threads may both decode the same asset at once, in
+// which case the later result is kept.
+fn get_or_decode<K: Eq + Hash, T: Clone>(
+    entries: &Mutex<HashMap<K, Entry<T>>>,
+    key: K,
+    modified: Option<SystemTime>,
+    decode: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+    {
+        let entries = entries.lock().expect("Could not lock the sample cache");
+        if let Some(entry) = entries.get(&key) {
+            if entry.modified == modified {
+                return Ok(entry.data.clone());
+            }
+        }
+    }
+    let data = decode()?;
+    let entry = Entry {
+        modified,
+        data: data.clone(),
+    };
+    entries
+        .lock()
+        .expect("Could not lock the sample cache")
+        .insert(key, entry);
+    Ok(data)
+}
+
+impl SampleCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop all cached data. Sources already built keep the data they use.
+    pub fn clear(&self) {
+        self.wavs
+            .lock()
+            .expect("Could not lock the sample cache")
+            .clear();
+        self.fonts
+            .lock()
+            .expect("Could not lock the sample cache")
+            .clear();
+    }
+
+    /// Drop the data decoded from one asset path, such as when the asset is replaced, so
+    /// that it is decoded again the next time it is used.
+    pub fn evict(&self, path: &str) {
+        self.wavs
+            .lock()
+            .expect("Could not lock the sample cache")
+            .retain(|(wav_path, _), _| wav_path != path);
+        self.fonts
+            .lock()
+            .expect("Could not lock the sample cache")
+            .retain(|(font_path, _), _| font_path != path);
+    }
+
+    /// Number of WAV files and SF2 instruments held, counting a WAV file once for each
+    /// BadSamplePolicy it was decoded with.
+    pub fn len(&self) -> usize {
+        let wavs = self.wavs.lock().expect("Could not lock the sample cache");
+        let fonts = self.fonts.lock().expect("Could not lock the sample cache");
+        wavs.len() + fonts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn wav(
+        &self,
+        path: &str,
+        policy: BadSamplePolicy,
+        modified: Option<SystemTime>,
+        decode: impl FnOnce() -> Result<CachedWav, Error>,
+    ) -> Result<CachedWav, Error> {
+        let key = (path.to_owned(), policy);
+        get_or_decode(&self.wavs, key, modified, decode)
+    }
+
+    fn font_zones(
+        &self,
+        path: &str,
+        instrument_index: usize,
+        modified: Option<SystemTime>,
+        decode: impl FnOnce() -> Result<Arc<[Sf2Zone]>, Error>,
+    ) -> Result<Arc<[Sf2Zone]>, Error> {
+        let key = (path.to_owned(), instrument_index);
+        get_or_decode(&self.fonts, key, modified, decode)
+    }
+}
+
+// Decoded data of a WAV asset, from the loader's cache if it has one
+pub(crate) fn wav_asset<A: AssetLoader>(
+    loader: &A,
+    path: &str,
+    policy: BadSamplePolicy,
+) -> Result<CachedWav, Error> {
+    let decode = || {
+        let bytes = load_asset(loader, path)?;
+        let (spec, data) = wav_data_from_bytes_with_policy(&bytes, policy)?;
+        Ok((spec, data.into()))
+    };
+    match loader.sample_cache() {
+        Some(cache) => cache.wav(path, policy, loader.asset_modified(path), decode),
+        None => decode(),
+    }
+}
+
+// Decoded zones of an instrument in an SF2 asset, from the loader's cache if it has one
+pub(crate) fn sf2_asset<A: AssetLoader>(
+    loader: &A,
+    path: &str,
+    instrument_index: usize,
+) -> Result<Arc<[Sf2Zone]>, Error> {
+    let decode = || {
+        let bytes = load_asset(loader, path)?;
+        Ok(sf2_zones_from_bytes(&bytes, instrument_index)?.into())
+    };
+    match loader.sample_cache() {
+        Some(cache) => {
+            cache.font_zones(path, instrument_index, loader.asset_modified(path), decode)
+        }
+        None => decode(),
+    }
+}
+
+/// Decode every WAV and SF2 file a source uses into the loader's sample cache, so that
+/// building graphs from it later does no decoding. Does nothing for loaders without a cache.
+pub fn prewarm_sample_cache<A: AssetLoader>(loader: &A, source: &SoundSource) -> Result<(), Error> {
+    if loader.sample_cache().is_none() {
+        return Ok(());
+    }
+    match source {
+        SoundSource::SampleFilePath {
+            path, bad_samples, ..
+        }
+        | SoundSource::OneShotFilePath {
+            path, bad_samples, ..
+        } => {
+            wav_asset(loader, path, *bad_samples)?;
+        }
+        SoundSource::Convolution { path, .. } => {
+            wav_asset(loader, path, BadSamplePolicy::Fail)?;
+        }
+        SoundSource::Font {
+            config:
+                FontSource::Sf2FilePath {
+                    path,
+                    instrument_index,
+                },
+            ..
+        } => {
+            sf2_asset(loader, path, *instrument_index)?;
+        }
+        _ => {}
+    }
+    let mut result = Ok(());
+    FileGraphLoader::traverse_sources(source, |child| {
+        if result.is_ok() && !std::ptr::eq(child, source) {
+            result = prewarm_sample_cache(loader, child);
+        }
+    });
+    result
+}
@@ -0,0 +1,114 @@
+use crate::{
+    asset_paths, included_paths, AssetLoader, BufferConsumerNode, Config, ConfigFormat, Error,
+    EventChannel, GraphLoader, MemoryAssetLoader, SampleCache,
+};
+use js_sys::Uint8Array;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::Response;
+
+/// Loads assets over HTTP for use in the browser, where there is no filesystem.
+/// Fetching is asynchronous, so every asset a config needs is fetched up front and the
+/// graph is only built once they have all arrived. Paths are relative to the base URL.
+pub struct FetchAssetLoader {
+    base_url: String,
+    assets: MemoryAssetLoader,
+}
+
+impl FetchAssetLoader {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            assets: MemoryAssetLoader::new(),
+        }
+    }
+
+    /// Fetch a config along with any config files it includes, and resolve the includes.
+    pub async fn fetch_config(&mut self, path: &str) -> Result<Config, Error> {
+        let bytes = self.fetch_bytes(path).await?;
+        let mut config = Config::from_bytes_in_format(&bytes, ConfigFormat::from_path(path))?;
+        let mut pending = included_paths(&config, path);
+        while let Some(include) = pending.pop() {
+            if self.assets.contains(&include) {
+                continue;
+            }
+            let bytes = self.fetch_bytes(&include).await?;
+            let included = Config::from_bytes_in_format(&bytes, ConfigFormat::from_path(&include))?;
+            pending.extend(included_paths(&included, &include));
+            self.assets.insert(&include, bytes);
+        }
+        config.resolve_includes(path, &self.assets)?;
+        Ok(config)
+    }
+
+    /// Fetch every asset used by the config that has not already been fetched.
+    pub async fn fetch_assets(&mut self, config: &Config) -> Result<(), Error> {
+        for path in asset_paths(&config.root) {
+            if self.assets.contains(&path) {
+                continue;
+            }
+            let bytes = self.fetch_bytes(&path).await?;
+            self.assets.insert(&path, bytes);
+        }
+        Ok(())
+    }
+
+    /// Fetch the assets used by the config, then build its graph.
+    pub async fn fetch_graph(
+        &mut self,
+        config: &Config,
+    ) -> Result<
+        (
+            Vec<EventChannel>,
+            Box<dyn BufferConsumerNode + Send + 'static>,
+        ),
+        Error,
+    > {
+        self.fetch_assets(config).await?;
+        self.load_source_recursive(&config.root)
+    }
+
+    /// Take the fetched assets, for building further graphs without fetching again.
+    pub fn into_memory_loader(self) -> MemoryAssetLoader {
+        self.assets
+    }
+
+    async fn fetch_bytes(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let url = match path.contains("://") {
+            true => path.to_owned(),
+            false => format!("{}/{}", self.base_url, path.trim_start_matches('/')),
+        };
+        let window =
+            web_sys::window().ok_or_else(|| Error::User("Fetch needs a window".to_owned()))?;
+        let response = JsFuture::from(window.fetch_with_str(&url))
+            .await
+            .map_err(|e| fetch_error(&url, e))?;
+        let response: Response = response.dyn_into().map_err(|e| fetch_error(&url, e))?;
+        if !response.ok() {
+            return Err(Error::User(format!(
+                "Fetching {} failed with status {}",
+                url,
+                response.status()
+            )));
+        }
+        let buffer = response.array_buffer().map_err(|e| fetch_error(&url, e))?;
+        let buffer = JsFuture::from(buffer)
+            .await
+            .map_err(|e| fetch_error(&url, e))?;
+        Ok(Uint8Array::new(&buffer).to_vec())
+    }
+}
+
+fn fetch_error(url: &str, error: JsValue) -> Error {
+    Error::User(format!("Fetching {} failed: {:?}", url, error))
+}
+
+impl AssetLoader for FetchAssetLoader {
+    fn load_asset_data(&self, path: &str) -> Result<Vec<u8>, Error> {
+        self.assets.load_asset_data(path)
+    }
+
+    fn sample_cache(&self) -> Option<&SampleCache> {
+        self.assets.sample_cache()
+    }
+}
@@ -1,12 +1,17 @@
-use crate::{util::wav_from_i16_samples, Error, NoteRange, SoundFont, SoundFontBuilder};
+use crate::{
+    file::wav::samples_from_i16, BufferConsumerNode, Envelope, Error, LoopMode, NoteRange,
+    SoundFont, SoundFontBuilder, WavSource,
+};
 use byteorder::{LittleEndian, ReadBytesExt};
 use soundfont::{
-    data::{GeneratorAmount, GeneratorType},
+    data::{GeneratorAmount, GeneratorType, SampleHeader},
     SfEnum, SoundFont2, Zone,
 };
 use std::{
+    collections::HashMap,
     fs::File,
     io::{BufReader, Cursor, Read, Seek, SeekFrom},
+    sync::Arc,
 };
 
 pub fn soundfont_from_file(
@@ -29,10 +34,61 @@ pub fn soundfont_from_bytes(
 }
 
 fn soundfont_from_reader<R>(
-    mut reader: R,
+    reader: R,
     node_id: Option<u64>,
     instrument_index: usize,
 ) -> Result<SoundFont, Error>
+where
+    R: Read + Seek,
+{
+    let zones = sf2_zones_from_reader(reader, instrument_index)?;
+    soundfont_from_zones(node_id, &zones)
+}
+
+// One zone of an SF2 instrument with its decoded sample data
+pub(crate) struct Sf2Zone {
+    note_range: NoteRange,
+    loop_mode: LoopMode,
+    release_time: f32,
+    sample_header: SampleHeader,
+    sample_data: Arc<[f32]>,
+}
+
+pub(crate) fn sf2_zones_from_bytes(
+    bytes: &[u8],
+    instrument_index: usize,
+) -> Result<Vec<Sf2Zone>, Error> {
+    sf2_zones_from_reader(Cursor::new(bytes), instrument_index)
+}
+
+pub(crate) fn soundfont_from_zones(
+    node_id: Option<u64>,
+    zones: &[Sf2Zone],
+) -> Result<SoundFont, Error> {
+    let mut soundfont_builder = SoundFontBuilder::new(node_id);
+    for zone in zones.iter() {
+        let mut source =
+            WavSource::new_from_raw_sf2_data(&zone.sample_header, zone.sample_data.clone())?;
+        source.set_loop_mode(zone.loop_mode);
+
+        // A sample looping continuously is ended by the zone's volume envelope release
+        let source: Box<dyn BufferConsumerNode + Send + 'static> = match zone.loop_mode {
+            LoopMode::Continuous => Box::new(Envelope::from_adsr(
+                None,
+                0.0,
+                0.0,
+                1.0,
+                zone.release_time,
+                Box::new(source),
+            )),
+            _ => Box::new(source),
+        };
+        soundfont_builder = soundfont_builder.add_range(zone.note_range.clone(), source)?;
+    }
+    Ok(soundfont_builder.build())
+}
+
+fn sf2_zones_from_reader<R>(mut reader: R, instrument_index: usize) -> Result<Vec<Sf2Zone>, Error>
 where
     R: Read + Seek,
 {
@@ -53,30 +109,49 @@ where
         )));
     };
     #[cfg(debug_assertions)]
-    println!("SF2: Using instrument from file: {:?}", &instrument.header);
+    log_info!(
+        "SF2",
+        "Using instrument from file: {:?}",
+        &instrument.header
+    );
 
-    let mut soundfont_builder = SoundFontBuilder::new(node_id);
+    let mut zones = vec![];
+    let mut sample_data_cache: HashMap<u16, Arc<[f32]>> = HashMap::new();
     for zone in instrument.zones.iter() {
         let Some(sample_index) = zone.sample() else {
-            println!("WARNING: SF2: Sample index not found for instrument zone");
+            log_warning!("SF2", "Sample index not found for instrument zone");
             continue;
         };
         let Some(sample_header) = sf2.sample_headers.get(*sample_index as usize) else {
-            println!(
-                "WARNING: SF2: Sample index {} not found matching instrument zone",
+            log_warning!(
+                "SF2",
+                "Sample index {} not found matching instrument zone",
                 sample_index
             );
             continue;
         };
 
-        let sample_file_offset = sample_chunk_metadata.offset + sample_header.start as u64;
-        let sample_length = sample_header.end as u64 - sample_file_offset;
-        let sample_data = load_sample(&mut reader, sample_file_offset, sample_length)?;
-        let note_range = note_range_for_zone(zone)?;
-        let source = wav_from_i16_samples(sample_header, &sample_data)?;
-        soundfont_builder = soundfont_builder.add_range(note_range, Box::new(source))?;
+        // Zones playing the same sample share one copy of its data
+        let sample_data = match sample_data_cache.get(sample_index) {
+            Some(data) => data.clone(),
+            None => {
+                let sample_file_offset = sample_chunk_metadata.offset + sample_header.start as u64;
+                let sample_length = sample_header.end as u64 - sample_file_offset;
+                let raw_data = load_sample(&mut reader, sample_file_offset, sample_length)?;
+                let data: Arc<[f32]> = samples_from_i16(&raw_data).into();
+                sample_data_cache.insert(*sample_index, data.clone());
+                data
+            }
+        };
+        zones.push(Sf2Zone {
+            note_range: note_range_for_zone(zone)?,
+            loop_mode: loop_mode_for_zone(zone),
+            release_time: release_time_for_zone(zone),
+            sample_header: sample_header.clone(),
+            sample_data,
+        });
     }
-    Ok(soundfont_builder.build())
+    Ok(zones)
 }
 
 fn validate_sf2_file(sf2: &SoundFont2) -> Result<(), Error> {
@@ -88,7 +163,7 @@ fn validate_sf2_file(sf2: &SoundFont2) -> Result<(), Error> {
     }
 
     if !sf2.presets.is_empty() {
-        println!("WARNING: SF2: File has presets; these will be ignored");
+        log_warning!("SF2", "File has presets; these will be ignored");
     }
     if sf2.instruments.is_empty() {
         return Err(Error::User(
@@ -126,10 +201,38 @@ fn note_range_for_zone(zone: &Zone) -> Result<NoteRange, Error> {
     ))
 }
 
+// The SampleModes generator, where 1 loops continuously, 3 loops until release, and 0 or
+// the unused 2 play without looping
+fn loop_mode_for_zone(zone: &Zone) -> LoopMode {
+    let mode = generator_value(zone, GeneratorType::SampleModes).unwrap_or(0);
+    match mode & 3 {
+        1 => LoopMode::Continuous,
+        3 => LoopMode::UntilRelease,
+        _ => LoopMode::Off,
+    }
+}
+
+// The ReleaseVolEnv generator in seconds, given in timecents where the default of -12000
+// is about a millisecond
+fn release_time_for_zone(zone: &Zone) -> f32 {
+    let timecents = generator_value(zone, GeneratorType::ReleaseVolEnv).unwrap_or(-12000);
+    2.0f32.powf(timecents as f32 / 1200.0)
+}
+
+fn generator_value(zone: &Zone, generator_type: GeneratorType) -> Option<i16> {
+    zone.gen_list
+        .iter()
+        .find_map(|generator| match generator.ty {
+            SfEnum::Value(ty) if ty == generator_type => generator.amount.as_i16().copied(),
+            _ => None,
+        })
+}
+
 #[cfg(debug_assertions)]
 fn log_opened_sf2(sf2: &SoundFont2) {
-    println!(
-        "SF2: Contains {} presets, {} instruments and {} samples",
+    log_info!(
+        "SF2",
+        "Contains {} presets, {} instruments and {} samples",
         sf2.presets.len(),
         sf2.instruments.len(),
         sf2.sample_headers.len()
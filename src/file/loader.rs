@@ -1,24 +1,74 @@
 use crate::{
-    util, AsyncEventReceiver, BufferConsumerNode, CombinerSource, Config, Envelope, Error,
-    EventChannel, Fader, FontSource, GraphLoader, LfsrNoiseSource, LoopRange, MidiDataSource,
-    MixerSource, NoteRange, SawtoothWaveSource, SoundFontBuilder, SoundSource, SquareWaveSource,
-    TriangleWaveSource,
+    config::registry::build_custom_node,
+    file::{
+        cache::{sf2_asset, wav_asset},
+        font::soundfont_from_zones,
+    },
+    util::{self, name_id, param_id, tag_id, BadSamplePolicy},
+    AdditiveSource, AssetLoader, AsyncEventReceiver, BufferConsumerNode, ChokeGroup,
+    ColoredNoiseSource, CombinerSource, Config, ConfigFormat, ConvolutionNode, CrossfadeSource,
+    Crossfeed, DuckSource, Envelope, Error, EventChannel, Fader, FmSynthSource, FontSource,
+    GainNode, GateExpanderNode, GateNode, GraphLoader, LfsrNoiseSource, LoopRange, MidiDataSource,
+    MidiSource, MixerSource, ModMatrix, MultiStageEnvelope, NoteMap, NoteRange, NullSource,
+    OneShotSource, ParallelCombinerSource, ParamBinding, ParamTarget, ParamValue, PitchShiftNode,
+    PlaylistNode, PluckedStringSource, RingModNode, SampleCache, SampleHoldSource,
+    SawtoothWaveSource, ScaleQuantizer, SequencerSource, SoundFontBuilder, SoundSource,
+    SquareWaveSource, TagBinding, TestSignalSource, TremoloNode, TriangleWaveSource, TuningNode,
+    TuningSource, TuningTable, VibratoNode, WavSource,
 };
-use ron::de::from_reader;
-use std::fs::File;
+use std::sync::{Arc, LazyLock};
+use std::time::SystemTime;
+
+// Decoded samples shared by every FileGraphLoader
+static FILE_SAMPLE_CACHE: LazyLock<SampleCache> = LazyLock::new(SampleCache::default);
 
 #[derive(Default)]
 pub struct FileGraphLoader;
 
 impl FileGraphLoader {
+    /// Read a config, choosing between RON, JSON and YAML by the file's extension.
+    /// Includes are resolved relative to the file, and the config is then validated,
+    /// including checking that its asset files exist.
     pub fn config_from_file(&self, file_name: &str) -> Result<Config, Error> {
-        let file = File::open(file_name)?;
-        let config = from_reader(&file)?;
+        let format = ConfigFormat::from_path(file_name);
+        self.config_from_file_in_format(file_name, format)
+    }
+
+    pub fn config_from_file_in_format(
+        &self,
+        file_name: &str,
+        format: ConfigFormat,
+    ) -> Result<Config, Error> {
+        let bytes = std::fs::read(file_name)?;
+        let mut config = Config::from_bytes_in_format(&bytes, format)?;
+        config.resolve_includes(file_name, self)?;
+        config.validate(Some(self))?;
         Ok(config)
     }
 }
 
-impl GraphLoader for FileGraphLoader {
+impl AssetLoader for FileGraphLoader {
+    fn load_asset_data(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let bytes = std::fs::read(path)?;
+        Ok(bytes)
+    }
+
+    fn has_asset(&self, path: &str) -> bool {
+        std::path::Path::new(path).is_file()
+    }
+
+    fn sample_cache(&self) -> Option<&SampleCache> {
+        Some(&FILE_SAMPLE_CACHE)
+    }
+
+    fn asset_modified(&self, path: &str) -> Option<SystemTime> {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+}
+
+impl<A: AssetLoader> GraphLoader for A {
     fn load_source_recursive(
         &self,
         source: &SoundSource,
@@ -29,24 +79,12 @@ impl GraphLoader for FileGraphLoader {
         ),
         Error,
     > {
+        let _span = trace_span!("graph_build");
         let (event_channels, consumer) = match source {
-            SoundSource::Midi {
-                node_id,
-                source,
-                channels,
-            } => {
-                let mut midi_builder = match source {
-                    MidiDataSource::FilePath(file) => {
-                        util::midi_builder_from_file(*node_id, file.as_str())?
-                    }
+            SoundSource::Midi { node_id, .. } => {
+                let Some((event_channels, source)) = load_midi(self, source)? else {
+                    return Ok((vec![], placeholder(*node_id)));
                 };
-                let mut event_channels = vec![];
-                for (channel, source) in channels.iter() {
-                    let (channels, font) = self.load_source_recursive(source)?;
-                    event_channels.extend(channels);
-                    midi_builder = midi_builder.add_channel_source(*channel, font);
-                }
-                let source = midi_builder.build()?;
                 let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
                 (event_channels, source)
             }
@@ -57,47 +95,176 @@ impl GraphLoader for FileGraphLoader {
                 let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
                 (channels, source)
             }
-            SoundSource::Font { node_id, config } => match config {
-                FontSource::Ranges(ranges) => {
-                    let mut all_channels = vec![];
-                    let mut font_builder = SoundFontBuilder::new(*node_id);
-                    for range in ranges {
-                        let note_range = NoteRange::new_inclusive_range(range.lower, range.upper);
-                        let (channels, source) = self.load_source_recursive(&range.source)?;
-                        all_channels.extend(channels);
-                        font_builder = font_builder.add_range(note_range, source)?;
+            SoundSource::Tagged { tags, source } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let tag_ids = tags.iter().map(|tag| tag_id(tag)).collect();
+                let source = TagBinding::new(tag_ids, source);
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (channels, source)
+            }
+            SoundSource::Named { name, source } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let source = TagBinding::new(vec![name_id(name)], source);
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (channels, source)
+            }
+            SoundSource::Tuned { tuning, source } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let source: Box<dyn BufferConsumerNode + Send + 'static> =
+                    match load_tuning(self, tuning)? {
+                        Some(tuning) => Box::new(TuningNode::new(Arc::new(tuning), source)),
+                        None => source,
+                    };
+                (channels, source)
+            }
+            SoundSource::Font {
+                node_id,
+                config,
+                max_voices,
+                voice_stealing,
+                crossfade,
+            } => {
+                let (channels, mut font) = match config {
+                    FontSource::Ranges(ranges) => {
+                        let mut all_channels = vec![];
+                        let mut font_builder = SoundFontBuilder::new(*node_id);
+                        for range in ranges {
+                            let note_range =
+                                NoteRange::new_inclusive_range(range.lower, range.upper);
+                            let (channels, source) = self.load_source_recursive(&range.source)?;
+                            all_channels.extend(channels);
+                            font_builder = match range.release.as_ref() {
+                                Some(release) => {
+                                    let (channels, release_source) =
+                                        self.load_source_recursive(&release.source)?;
+                                    all_channels.extend(channels);
+                                    font_builder.add_range_with_release(
+                                        note_range,
+                                        source,
+                                        release_source,
+                                        release.level,
+                                    )?
+                                }
+                                None => font_builder.add_range(note_range, source)?,
+                            };
+                        }
+                        (all_channels, font_builder.build())
                     }
-                    let source: Box<dyn BufferConsumerNode + Send + 'static> =
-                        Box::new(font_builder.build());
-                    (all_channels, source)
-                }
-                FontSource::Sf2FilePath {
-                    path,
-                    instrument_index,
-                } => {
-                    let source =
-                        util::soundfont_from_file(*node_id, path.as_str(), *instrument_index)?;
-                    let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
-                    (vec![], source)
-                }
-            },
+                    FontSource::Sf2FilePath {
+                        path,
+                        instrument_index,
+                    } => {
+                        let font = asset_or_placeholder(self, path, || {
+                            let zones = sf2_asset(self, path, *instrument_index)?;
+                            soundfont_from_zones(*node_id, &zones)
+                        })?;
+                        let Some(font) = font else {
+                            return Ok((vec![], placeholder(*node_id)));
+                        };
+                        (vec![], font)
+                    }
+                    FontSource::Sf2Inline {
+                        data,
+                        instrument_index,
+                    } => {
+                        let bytes = data.decode()?;
+                        let font = util::soundfont_from_bytes(*node_id, &bytes, *instrument_index)?;
+                        (vec![], font)
+                    }
+                };
+                font.set_max_voices(*max_voices)?;
+                font.set_voice_stealing(*voice_stealing);
+                font.set_crossfade(*crossfade);
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(font);
+                (channels, source)
+            }
             SoundSource::SquareWave {
                 node_id,
                 amplitude,
                 duty_cycle,
+                oscillator,
+                pitch,
+                unison,
+                reset_phase,
+                ..
             } => {
-                let source = SquareWaveSource::new(*node_id, *amplitude, *duty_cycle);
-                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                let mut source =
+                    SquareWaveSource::new(*node_id, amplitude.value()?, duty_cycle.value()?);
+                source.set_oscillator_mode(*oscillator);
+                source.set_pitch_motion(*pitch);
+                source.set_unison(*unison);
+                source.set_phase_reset(*reset_phase);
+                let source = bind_param(amplitude, ParamTarget::Volume, Box::new(source));
                 (vec![], source)
             }
-            SoundSource::TriangleWave { node_id, amplitude } => {
-                let source = TriangleWaveSource::new(*node_id, *amplitude);
-                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+            SoundSource::TriangleWave {
+                node_id,
+                amplitude,
+                pitch,
+                reset_phase,
+                ..
+            } => {
+                let mut source = TriangleWaveSource::new(*node_id, amplitude.value()?);
+                source.set_pitch_motion(*pitch);
+                source.set_phase_reset(*reset_phase);
+                let source = bind_param(amplitude, ParamTarget::Volume, Box::new(source));
                 (vec![], source)
             }
-            SoundSource::SawtoothWave { node_id, amplitude } => {
-                let source = SawtoothWaveSource::new(*node_id, *amplitude);
-                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+            SoundSource::SawtoothWave {
+                node_id,
+                amplitude,
+                oscillator,
+                pitch,
+                unison,
+                reset_phase,
+                ..
+            } => {
+                let mut source = SawtoothWaveSource::new(*node_id, amplitude.value()?);
+                source.set_oscillator_mode(*oscillator);
+                source.set_pitch_motion(*pitch);
+                source.set_unison(*unison);
+                source.set_phase_reset(*reset_phase);
+                let source = bind_param(amplitude, ParamTarget::Volume, Box::new(source));
+                (vec![], source)
+            }
+            SoundSource::FmSynth {
+                node_id,
+                amplitude,
+                algorithm,
+                operators,
+                ..
+            } => {
+                let source =
+                    FmSynthSource::new(*node_id, amplitude.value()?, *algorithm, operators)?;
+                let source = bind_param(amplitude, ParamTarget::Volume, Box::new(source));
+                (vec![], source)
+            }
+            SoundSource::Additive {
+                node_id,
+                amplitude,
+                harmonics,
+                detune_cents,
+                ..
+            } => {
+                let source =
+                    AdditiveSource::new(*node_id, amplitude.value()?, harmonics, detune_cents)?;
+                let source = bind_param(amplitude, ParamTarget::Volume, Box::new(source));
+                (vec![], source)
+            }
+            SoundSource::PluckedString {
+                node_id,
+                amplitude,
+                decay,
+                brightness,
+                ..
+            } => {
+                let source = PluckedStringSource::new(
+                    *node_id,
+                    amplitude.value()?,
+                    decay.value()?,
+                    brightness.value()?,
+                );
+                let source = bind_param(amplitude, ParamTarget::Volume, Box::new(source));
                 (vec![], source)
             }
             SoundSource::LfsrNoise {
@@ -105,29 +272,193 @@ impl GraphLoader for FileGraphLoader {
                 amplitude,
                 inside_feedback,
                 note_for_16_shifts,
+                ..
             } => {
                 let source = LfsrNoiseSource::new(
                     *node_id,
-                    *amplitude,
+                    amplitude.value()?,
                     *inside_feedback,
                     *note_for_16_shifts,
                 );
-                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                let source = bind_param(amplitude, ParamTarget::Volume, Box::new(source));
                 (vec![], source)
             }
+            SoundSource::ColoredNoise {
+                node_id,
+                amplitude,
+                color,
+                ..
+            } => {
+                let source = ColoredNoiseSource::new(*node_id, amplitude.value()?, *color);
+                let source = bind_param(amplitude, ParamTarget::Volume, Box::new(source));
+                (vec![], source)
+            }
+            SoundSource::SampleHold {
+                node_id,
+                amplitude,
+                rate_hz,
+                range_semitones,
+                ..
+            } => {
+                let source = SampleHoldSource::new(
+                    *node_id,
+                    amplitude.value()?,
+                    rate_hz.value()?,
+                    *range_semitones,
+                );
+                let source = bind_param(amplitude, ParamTarget::Volume, Box::new(source));
+                (vec![], source)
+            }
+            SoundSource::ChipDrum {
+                node_id,
+                kind,
+                amplitude,
+                length,
+                ..
+            } => {
+                let drum = kind.expand(amplitude, *length);
+                let drum = match node_id {
+                    Some(node_id) => drum.with_node_id(*node_id),
+                    None => drum,
+                };
+                self.load_source_recursive(&drum)?
+            }
             SoundSource::SampleFilePath {
                 node_id,
                 path,
                 base_note,
                 looping,
+                retrigger,
+                variation,
+                bad_samples,
+                ..
+            } => {
+                let loop_range = looping.as_ref().map(LoopRange::from_config);
+                let source = asset_or_placeholder(self, path, || {
+                    let (spec, data) = wav_asset(self, path, *bad_samples)?;
+                    WavSource::new_from_data(spec, *base_note, data, loop_range, *node_id)
+                })?;
+                let Some(mut source) = source else {
+                    return Ok((vec![], placeholder(*node_id)));
+                };
+                source.set_retrigger(*retrigger);
+                source.set_variation(*variation);
+                source.set_loop_mode(
+                    looping
+                        .as_ref()
+                        .map(|looping| looping.mode)
+                        .unwrap_or_default(),
+                );
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (vec![], source)
+            }
+            SoundSource::SampleInline {
+                node_id,
+                data,
+                base_note,
+                looping,
+                retrigger,
+                variation,
+                ..
             } => {
                 let loop_range = looping.as_ref().map(LoopRange::from_config);
-                let source = util::wav_from_file(path.as_str(), *base_note, loop_range, *node_id)?;
+                let bytes = data.decode()?;
+                let mut source = util::wav_from_bytes(&bytes, *base_note, loop_range, *node_id)?;
+                source.set_retrigger(*retrigger);
+                source.set_variation(*variation);
+                source.set_loop_mode(
+                    looping
+                        .as_ref()
+                        .map(|looping| looping.mode)
+                        .unwrap_or_default(),
+                );
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (vec![], source)
+            }
+            SoundSource::Custom {
+                node_id,
+                name,
+                config,
+                sources,
+            } => {
+                let mut event_channels: Vec<EventChannel> = vec![];
+                let mut inner_sources: Vec<Box<dyn BufferConsumerNode + Send + 'static>> = vec![];
+                for source in sources.iter() {
+                    let (channels, source) = self.load_source_recursive(source)?;
+                    event_channels.extend(channels);
+                    inner_sources.push(source);
+                }
+                let source = build_custom_node(name, *node_id, config, inner_sources)?;
+                (event_channels, source)
+            }
+            SoundSource::Include(path) => {
+                return Err(Error::User(format!(
+                    "Include of {} must be resolved before loading",
+                    path
+                )));
+            }
+            SoundSource::Ref(name) => {
+                return Err(Error::User(format!(
+                    "Reference to {} must be resolved before loading",
+                    name
+                )));
+            }
+            SoundSource::TestSignal { node_id } => {
+                let source = TestSignalSource::new(*node_id);
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (vec![], source)
+            }
+            #[cfg(all(feature = "midir", not(target_arch = "wasm32")))]
+            SoundSource::MidiOutput {
+                node_id,
+                port,
+                channel,
+                latency,
+            } => {
+                let source = crate::MidiOutputNode::connect(*node_id, port, *channel, *latency)?;
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (vec![], source)
+            }
+            #[cfg(not(all(feature = "midir", not(target_arch = "wasm32"))))]
+            SoundSource::MidiOutput { .. } => {
+                return Err(Error::User(
+                    "MidiOutput nodes need the midir feature on a native target".to_owned(),
+                ));
+            }
+            SoundSource::OneShotFilePath {
+                node_id,
+                path,
+                retrigger,
+                choke_group,
+                variation,
+                bad_samples,
+                ..
+            } => {
+                let source = asset_or_placeholder(self, path, || {
+                    let (spec, data) = wav_asset(self, path, *bad_samples)?;
+                    OneShotSource::new_from_data(spec, data, *node_id)
+                })?;
+                let Some(mut source) = source else {
+                    return Ok((vec![], placeholder(*node_id)));
+                };
+                source.set_retrigger(*retrigger);
+                source.set_choke_group(choke_group.as_deref().map(ChokeGroup::named));
+                source.set_variation(*variation);
                 let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
                 (vec![], source)
             }
-            SoundSource::OneShotFilePath { node_id, path } => {
-                let source = util::one_shot_from_file(path.as_str(), *node_id)?;
+            SoundSource::OneShotInline {
+                node_id,
+                data,
+                retrigger,
+                choke_group,
+                variation,
+                ..
+            } => {
+                let mut source = util::one_shot_from_bytes(&data.decode()?, *node_id)?;
+                source.set_retrigger(*retrigger);
+                source.set_choke_group(choke_group.as_deref().map(ChokeGroup::named));
+                source.set_variation(*variation);
                 let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
                 (vec![], source)
             }
@@ -137,21 +468,66 @@ impl GraphLoader for FileGraphLoader {
                 decay_time,
                 sustain_multiplier,
                 release_time,
+                key_tracking,
+                velocity_tracking,
+                attack_curve,
+                decay_curve,
+                release_curve,
+                retrigger,
+                delay_time,
+                hold_time,
                 source,
             } => {
                 let (channels, source) = self.load_source_recursive(source)?;
-                let source = Envelope::from_adsr(
+                let mut source = Envelope::from_adsr(
                     *node_id,
-                    *attack_time,
-                    *decay_time,
-                    *sustain_multiplier,
-                    *release_time,
+                    attack_time.value()?,
+                    decay_time.value()?,
+                    sustain_multiplier.value()?,
+                    release_time.value()?,
                     source,
                 );
+                source.set_tracking(*key_tracking, *velocity_tracking);
+                source.set_curves(*attack_curve, *decay_curve, *release_curve);
+                source.set_retrigger(*retrigger);
+                source.set_delay_and_hold(delay_time.value()?, hold_time.value()?);
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (channels, source)
+            }
+            SoundSource::MultiStageEnvelope {
+                node_id,
+                points,
+                sustain_point,
+                target,
+                source,
+            } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let source = MultiStageEnvelope::new(
+                    *node_id,
+                    points.clone(),
+                    *sustain_point,
+                    *target,
+                    source,
+                )?;
                 let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
                 (channels, source)
             }
-            SoundSource::Combiner { node_id, sources } => {
+            SoundSource::ModMatrix {
+                node_id,
+                routes,
+                source,
+            } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let source = ModMatrix::new(*node_id, routes.clone(), source);
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (channels, source)
+            }
+            SoundSource::Combiner {
+                node_id,
+                sources,
+                hard_sync,
+                parallel,
+            } => {
                 let mut event_channels: Vec<EventChannel> = vec![];
                 let mut inner_sources: Vec<Box<dyn BufferConsumerNode + Send + 'static>> = vec![];
                 for source in sources.iter() {
@@ -159,8 +535,14 @@ impl GraphLoader for FileGraphLoader {
                     event_channels.extend(channels);
                     inner_sources.push(source);
                 }
-                let source = CombinerSource::new(*node_id, inner_sources);
-                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = match parallel {
+                    true => Box::new(ParallelCombinerSource::new(*node_id, inner_sources)?),
+                    false => {
+                        let mut source = CombinerSource::new(*node_id, inner_sources);
+                        source.set_hard_sync(*hard_sync);
+                        Box::new(source)
+                    }
+                };
                 (event_channels, source)
             }
             SoundSource::Mixer {
@@ -171,7 +553,44 @@ impl GraphLoader for FileGraphLoader {
             } => {
                 let (mut channels, source_0) = self.load_source_recursive(source_0)?;
                 let (more_channels, source_1) = self.load_source_recursive(source_1)?;
-                let source = MixerSource::new(*node_id, *balance, source_0, source_1);
+                let source = MixerSource::new(*node_id, balance.value()?, source_0, source_1);
+                channels.extend(more_channels);
+                let source = bind_param(balance, ParamTarget::MixerBalance, Box::new(source));
+                (channels, source)
+            }
+            SoundSource::Crossfade {
+                node_id,
+                position,
+                source_0,
+                source_1,
+            } => {
+                let (mut channels, source_0) = self.load_source_recursive(source_0)?;
+                let (more_channels, source_1) = self.load_source_recursive(source_1)?;
+                let source = CrossfadeSource::new(*node_id, position.value()?, source_0, source_1);
+                channels.extend(more_channels);
+                let source = bind_param(position, ParamTarget::CrossfadePosition, Box::new(source));
+                (channels, source)
+            }
+            SoundSource::Duck {
+                node_id,
+                depth,
+                attack_time,
+                release_time,
+                threshold,
+                music,
+                priority,
+            } => {
+                let (mut channels, music) = self.load_source_recursive(music)?;
+                let (more_channels, priority) = self.load_source_recursive(priority)?;
+                let source = DuckSource::new(
+                    *node_id,
+                    depth.value()?,
+                    attack_time.value()?,
+                    release_time.value()?,
+                    *threshold,
+                    music,
+                    priority,
+                );
                 channels.extend(more_channels);
                 let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
                 (channels, source)
@@ -182,11 +601,331 @@ impl GraphLoader for FileGraphLoader {
                 source,
             } => {
                 let (channels, source) = self.load_source_recursive(source)?;
-                let source = Fader::new(*node_id, *initial_volume, source);
+                let source = Fader::new(*node_id, initial_volume.value()?, source);
+                let source = bind_param(initial_volume, ParamTarget::FaderVolume, Box::new(source));
+                (channels, source)
+            }
+            SoundSource::RingMod {
+                node_id,
+                mode,
+                depth,
+                frequency,
+                note_ratio,
+                carrier,
+                source,
+            } => {
+                let (mut channels, source) = self.load_source_recursive(source)?;
+                let mut source =
+                    RingModNode::new(*node_id, *mode, depth.value()?, frequency.value()?, source);
+                source.set_note_ratio(*note_ratio);
+                if let Some(carrier) = carrier {
+                    let (carrier_channels, carrier) = self.load_source_recursive(carrier)?;
+                    channels.extend(carrier_channels);
+                    source.set_carrier(carrier);
+                }
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (channels, source)
+            }
+            SoundSource::PitchShift {
+                node_id,
+                semitones,
+                cents,
+                grain_time,
+                source,
+            } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let source = PitchShiftNode::new(*node_id, *semitones, *cents, *grain_time, source);
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (channels, source)
+            }
+            SoundSource::Tremolo {
+                node_id,
+                rate,
+                depth,
+                source,
+            } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let source = TremoloNode::new(*node_id, rate.value()?, depth.value()?, source);
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (channels, source)
+            }
+            SoundSource::Vibrato {
+                node_id,
+                rate,
+                depth_cents,
+                source,
+            } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let source =
+                    VibratoNode::new(*node_id, rate.value()?, depth_cents.value()?, source);
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (channels, source)
+            }
+            SoundSource::Convolution {
+                node_id,
+                path,
+                mix,
+                pre_delay_time,
+                source,
+            } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let (spec, impulse) = wav_asset(self, path, BadSamplePolicy::Fail)?;
+                let source = ConvolutionNode::new(
+                    *node_id,
+                    spec,
+                    &impulse,
+                    mix.value()?,
+                    *pre_delay_time,
+                    source,
+                )?;
+                let source = bind_param(mix, ParamTarget::EffectMix, Box::new(source));
+                (channels, source)
+            }
+            SoundSource::Crossfeed {
+                node_id,
+                amount,
+                cutoff_hz,
+                delay_ms,
+                source,
+            } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let source = Crossfeed::new(
+                    *node_id,
+                    amount.value()?,
+                    cutoff_hz.value()?,
+                    delay_ms.value()?,
+                    source,
+                );
+                let source = bind_param(amount, ParamTarget::CrossfeedAmount, Box::new(source));
+                (channels, source)
+            }
+            SoundSource::Sequencer {
+                node_id,
+                bpm,
+                steps_per_beat,
+                swing,
+                looping,
+                steps,
+                source,
+            } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let source = SequencerSource::new(
+                    *node_id,
+                    *bpm,
+                    *steps_per_beat,
+                    *swing,
+                    *looping,
+                    steps.clone(),
+                    source,
+                )?;
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (channels, source)
+            }
+            SoundSource::Gate {
+                node_id,
+                pattern,
+                steps_per_beat,
+                bpm,
+                attack_time,
+                release_time,
+                source,
+            } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let source = GateNode::new(
+                    *node_id,
+                    pattern.clone(),
+                    *steps_per_beat,
+                    *bpm,
+                    *attack_time,
+                    *release_time,
+                    source,
+                )?;
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (channels, source)
+            }
+            SoundSource::GateExpander {
+                node_id,
+                threshold,
+                ratio,
+                attack_time,
+                release_time,
+                source,
+            } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let source = GateExpanderNode::new(
+                    *node_id,
+                    *threshold,
+                    ratio.value()?,
+                    attack_time.value()?,
+                    release_time.value()?,
+                    source,
+                );
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (channels, source)
+            }
+            SoundSource::Playlist {
+                node_id,
+                crossfade_time,
+                looping,
+                songs,
+            } => {
+                let mut event_channels = vec![];
+                let mut midi_sources = vec![];
+                for song in songs.iter() {
+                    // Songs whose files failed to load leniently are left out
+                    if let Some((channels, source)) = load_midi(self, song)? {
+                        event_channels.extend(channels);
+                        midi_sources.push(source);
+                    }
+                }
+                if midi_sources.is_empty() {
+                    return Ok((vec![], placeholder(*node_id)));
+                }
+                let source = PlaylistNode::new(*node_id, midi_sources, *crossfade_time, *looping)?;
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (event_channels, source)
+            }
+            SoundSource::NoteMap {
+                node_id,
+                mapping,
+                source,
+            } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let source = NoteMap::new(*node_id, mapping.clone(), source);
+                let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
+                (channels, source)
+            }
+            SoundSource::ScaleQuantizer {
+                node_id,
+                scale,
+                root,
+                direction,
+                source,
+            } => {
+                let (channels, source) = self.load_source_recursive(source)?;
+                let source =
+                    ScaleQuantizer::new(*node_id, scale.clone(), *root, *direction, source);
                 let source: Box<dyn BufferConsumerNode + Send + 'static> = Box::new(source);
                 (channels, source)
             }
         };
+        let gain = source.gain();
+        let consumer: Box<dyn BufferConsumerNode + Send + 'static> = match gain == 0.0 {
+            true => consumer,
+            false => Box::new(GainNode::new(gain, consumer)),
+        };
         Ok((event_channels, consumer))
     }
 }
+
+pub(crate) fn load_asset<A: AssetLoader>(loader: &A, path: &str) -> Result<Vec<u8>, Error> {
+    let _span = trace_span!("asset_load", path);
+    loader.load_asset_data(path)
+}
+
+// Build something from an asset, giving None instead if that fails and the loader accepts
+// the failure, in which case the caller puts a placeholder in place of the node
+// Build a MIDI source and its channels, or None if its file failed to load leniently
+fn load_midi<A: AssetLoader>(
+    loader: &A,
+    midi: &SoundSource,
+) -> Result<Option<(Vec<EventChannel>, MidiSource)>, Error> {
+    let SoundSource::Midi {
+        node_id,
+        source,
+        sequence_index,
+        channels,
+        swing,
+        humanize_time,
+        humanize_velocity,
+    } = midi
+    else {
+        return Err(Error::User("Expected a Midi source".to_owned()));
+    };
+    let node_id = *node_id;
+    let mut midi_builder = match source {
+        MidiDataSource::FilePath(file) => {
+            let builder = asset_or_placeholder(loader, file, || {
+                util::midi_sequence_builder_from_bytes(
+                    node_id,
+                    &load_asset(loader, file)?,
+                    *sequence_index,
+                )
+            })?;
+            let Some(builder) = builder else {
+                return Ok(None);
+            };
+            builder
+        }
+        MidiDataSource::Inline(data) => {
+            util::midi_sequence_builder_from_bytes(node_id, &data.decode()?, *sequence_index)?
+        }
+    };
+    let mut event_channels = vec![];
+    for (channel, source) in channels.iter() {
+        let (channels, font) = loader.load_source_recursive(source)?;
+        event_channels.extend(channels);
+        midi_builder = midi_builder.add_channel_source(*channel, font);
+    }
+    let source = midi_builder
+        .swing(*swing)
+        .humanize(*humanize_time, *humanize_velocity)
+        .build()?;
+    Ok(Some((event_channels, source)))
+}
+
+fn asset_or_placeholder<A: AssetLoader, T>(
+    loader: &A,
+    path: &str,
+    build: impl FnOnce() -> Result<T, Error>,
+) -> Result<Option<T>, Error> {
+    match build() {
+        Ok(built) => Ok(Some(built)),
+        Err(error) => {
+            loader.on_asset_error(path, error)?;
+            Ok(None)
+        }
+    }
+}
+
+// Build the table for a Tuned source, or None if its Scala files failed to load
+// leniently, in which case its sources keep whatever tuning they are given from outside
+fn load_tuning<A: AssetLoader>(
+    loader: &A,
+    tuning: &TuningSource,
+) -> Result<Option<TuningTable>, Error> {
+    match tuning {
+        TuningSource::EqualTemperament => Ok(Some(TuningTable::equal_temperament())),
+        TuningSource::Cents(cents) => TuningTable::from_cents(cents).map(Some),
+        TuningSource::ScalaFilePath { scl, kbm } => asset_or_placeholder(loader, scl, || {
+            let scl_data = load_asset(loader, scl)?;
+            let kbm_data = match kbm {
+                Some(kbm) => Some(load_asset(loader, kbm)?),
+                None => None,
+            };
+            util::tuning_from_scala_bytes(&scl_data, kbm_data.as_deref())
+        }),
+    }
+}
+
+fn placeholder(node_id: Option<u64>) -> Box<dyn BufferConsumerNode + Send + 'static> {
+    Box::new(NullSource::new(node_id))
+}
+
+// Wrap a node built from a param so that it follows SetParam broadcasts for that param
+fn bind_param(
+    value: &ParamValue,
+    target: ParamTarget,
+    source: Box<dyn BufferConsumerNode + Send + 'static>,
+) -> Box<dyn BufferConsumerNode + Send + 'static> {
+    match value.param_name() {
+        Some(name) => {
+            let mut binding = ParamBinding::new(param_id(name), target, source);
+            if let Ok(initial_value) = value.value() {
+                binding.set_snapshots(initial_value, value.snapshot_values().to_vec());
+            }
+            Box::new(binding)
+        }
+        None => source,
+    }
+}
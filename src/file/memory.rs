@@ -0,0 +1,68 @@
+use crate::{AssetLoader, Error, SampleCache};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Serves assets from memory, keyed by the paths used in configs, so that graphs can be
+/// built with no filesystem access. Embedded data such as from include_bytes! is served
+/// without being copied into the loader. Decoded samples are cached in the loader.
+#[derive(Default)]
+pub struct MemoryAssetLoader {
+    assets: HashMap<String, Cow<'static, [u8]>>,
+    sample_cache: SampleCache,
+}
+
+impl MemoryAssetLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_map(assets: HashMap<String, Vec<u8>>) -> Self {
+        Self {
+            assets: assets
+                .into_iter()
+                .map(|(path, bytes)| (path, Cow::Owned(bytes)))
+                .collect(),
+            sample_cache: SampleCache::default(),
+        }
+    }
+
+    /// Add embedded data, for example `.with_static("song.mid", include_bytes!("song.mid"))`.
+    pub fn with_static(mut self, path: &str, bytes: &'static [u8]) -> Self {
+        self.assets.insert(path.to_owned(), Cow::Borrowed(bytes));
+        self
+    }
+
+    /// Add an asset, returning whether one already existed at that path and was replaced.
+    /// Data decoded from a replaced asset is dropped from the cache.
+    pub fn insert(&mut self, path: &str, bytes: Vec<u8>) -> bool {
+        let replaced = self
+            .assets
+            .insert(path.to_owned(), Cow::Owned(bytes))
+            .is_some();
+        if replaced {
+            self.sample_cache.evict(path);
+        }
+        replaced
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.assets.contains_key(path)
+    }
+}
+
+impl AssetLoader for MemoryAssetLoader {
+    fn load_asset_data(&self, path: &str) -> Result<Vec<u8>, Error> {
+        self.assets
+            .get(path)
+            .map(|bytes| bytes.to_vec())
+            .ok_or_else(|| Error::User(format!("No asset in memory at {}", path)))
+    }
+
+    fn has_asset(&self, path: &str) -> bool {
+        self.contains(path)
+    }
+
+    fn sample_cache(&self) -> Option<&SampleCache> {
+        Some(&self.sample_cache)
+    }
+}
@@ -17,3 +17,24 @@ pub fn midi_builder_from_bytes(
     let midi_builder = MidiSourceBuilder::new(node_id, smf)?;
     Ok(midi_builder)
 }
+
+/// Get a builder for one sequence of a type 2 MIDI file.
+pub fn midi_sequence_builder_from_file(
+    node_id: Option<u64>,
+    file_name: &str,
+    sequence_index: usize,
+) -> Result<MidiSourceBuilder, Error> {
+    let bytes = std::fs::read(file_name)?;
+    midi_sequence_builder_from_bytes(node_id, &bytes, sequence_index)
+}
+
+/// Get a builder for one sequence of a type 2 MIDI file.
+pub fn midi_sequence_builder_from_bytes(
+    node_id: Option<u64>,
+    bytes: &[u8],
+    sequence_index: usize,
+) -> Result<MidiSourceBuilder, Error> {
+    let smf = Smf::parse(bytes)?;
+    let midi_builder = MidiSourceBuilder::with_sequence(node_id, smf, sequence_index)?;
+    Ok(midi_builder)
+}
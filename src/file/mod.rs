@@ -1,4 +1,11 @@
+#[cfg(not(target_arch = "wasm32"))]
+pub mod background;
+pub mod cache;
+#[cfg(target_arch = "wasm32")]
+pub mod fetch;
 pub mod font;
 pub mod loader;
+pub mod memory;
 pub mod midi;
+pub mod scala;
 pub mod wav;
@@ -0,0 +1,228 @@
+use crate::{util, Error, TuningTable};
+
+// Keyboard mapping used when a scale comes without a .kbm file: the scale runs up the keys
+// in order, with its first degree on middle C at its equal-tempered frequency
+const DEFAULT_MIDDLE_NOTE: i32 = 60;
+
+// A scale from a .scl file, in cents above its first degree, which is not listed
+struct Scale {
+    pitches: Vec<f64>,
+}
+
+impl Scale {
+    fn period(&self) -> f64 {
+        self.pitches[self.pitches.len() - 1]
+    }
+
+    // Cents of a degree of the scale, counting whole periods for degrees past its end
+    fn cents_of(&self, degree: i32) -> f64 {
+        let count = self.pitches.len() as i32;
+        let periods = degree.div_euclid(count) as f64;
+        let within = match degree.rem_euclid(count) {
+            0 => 0.0,
+            index => self.pitches[index as usize - 1],
+        };
+        periods * self.period() + within
+    }
+}
+
+// Which degree of the scale each key plays, from a .kbm file
+struct KeyboardMapping {
+    first_note: i32,
+    last_note: i32,
+    middle_note: i32,
+    reference_note: i32,
+    reference_frequency: f64,
+    octave_degree: i32,
+    mapping: Vec<Option<i32>>,
+}
+
+impl KeyboardMapping {
+    fn linear(scale: &Scale) -> Self {
+        Self {
+            first_note: 0,
+            last_note: 127,
+            middle_note: DEFAULT_MIDDLE_NOTE,
+            reference_note: DEFAULT_MIDDLE_NOTE,
+            reference_frequency: util::frequency_of(DEFAULT_MIDDLE_NOTE as u8) as f64,
+            octave_degree: scale.pitches.len() as i32,
+            mapping: vec![],
+        }
+    }
+
+    // Cents of a key above the middle note, or None for a key left unmapped
+    fn cents_of(&self, scale: &Scale, key: i32) -> Option<f64> {
+        let steps = key - self.middle_note;
+        if self.mapping.is_empty() {
+            return Some(scale.cents_of(steps));
+        }
+        let size = self.mapping.len() as i32;
+        let repeats = steps.div_euclid(size);
+        let degree = self.mapping[steps.rem_euclid(size) as usize]?;
+        Some(repeats as f64 * scale.cents_of(self.octave_degree) + scale.cents_of(degree))
+    }
+}
+
+/// Get a tuning table from a Scala scale file, with an optional keyboard mapping file.
+/// Without a mapping, the scale runs up the keys in order from middle C (key 60) at its
+/// usual frequency. Keys that the mapping leaves out keep their equal-tempered frequencies.
+pub fn tuning_from_scala_files(
+    scl_file_name: &str,
+    kbm_file_name: Option<&str>,
+) -> Result<TuningTable, Error> {
+    let scl = std::fs::read(scl_file_name)?;
+    let kbm = match kbm_file_name {
+        Some(file_name) => Some(std::fs::read(file_name)?),
+        None => None,
+    };
+    tuning_from_scala_bytes(&scl, kbm.as_deref())
+}
+
+/// Get a tuning table from the contents of a Scala scale file, with an optional keyboard
+/// mapping file; see tuning_from_scala_files.
+pub fn tuning_from_scala_bytes(scl: &[u8], kbm: Option<&[u8]>) -> Result<TuningTable, Error> {
+    let scale = parse_scale(&String::from_utf8_lossy(scl))?;
+    let mapping = match kbm {
+        Some(kbm) => parse_keyboard_mapping(&String::from_utf8_lossy(kbm))?,
+        None => KeyboardMapping::linear(&scale),
+    };
+    let reference_cents = mapping
+        .cents_of(&scale, mapping.reference_note)
+        .ok_or_else(|| Error::User("Scala reference note is not mapped".to_owned()))?;
+
+    let mut frequencies = [0.0; 128];
+    for (key, frequency) in frequencies.iter_mut().enumerate() {
+        let key = key as i32;
+        let cents = match key >= mapping.first_note && key <= mapping.last_note {
+            true => mapping.cents_of(&scale, key),
+            false => None,
+        };
+        *frequency = match cents {
+            Some(cents) => {
+                let ratio = 2.0f64.powf((cents - reference_cents) / 1200.0);
+                (mapping.reference_frequency * ratio) as f32
+            }
+            None => util::frequency_of(key as u8),
+        };
+    }
+    TuningTable::from_frequencies(frequencies)
+}
+
+// Lines of a Scala file that are not comments
+fn content_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.starts_with('!'))
+}
+
+// The first word of a line, which is all that Scala reads from most lines
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+fn parse_scale(text: &str) -> Result<Scale, Error> {
+    let mut lines = content_lines(text);
+
+    // The description may be left blank, but its line must be present
+    lines
+        .next()
+        .ok_or_else(|| Error::User("Scala scale has no description line".to_owned()))?;
+    let count = lines
+        .next()
+        .and_then(|line| first_word(line).parse::<usize>().ok())
+        .ok_or_else(|| Error::User("Scala scale has no note count".to_owned()))?;
+    if count == 0 {
+        return Err(Error::User("Scala scale has no pitches".to_owned()));
+    }
+
+    let pitches = lines
+        .filter(|line| !line.is_empty())
+        .take(count)
+        .map(|line| parse_pitch(first_word(line)))
+        .collect::<Result<Vec<f64>, Error>>()?;
+    if pitches.len() != count {
+        return Err(Error::User(format!(
+            "Scala scale lists {} pitches but has {}",
+            count,
+            pitches.len()
+        )));
+    }
+    let scale = Scale { pitches };
+    if scale.period() <= 0.0 {
+        return Err(Error::User(
+            "Scala scale must end on a pitch above its first".to_owned(),
+        ));
+    }
+    Ok(scale)
+}
+
+// A pitch in cents if written with a decimal point, otherwise a ratio or whole number
+fn parse_pitch(word: &str) -> Result<f64, Error> {
+    let invalid = || Error::User(format!("Invalid Scala pitch: {}", word));
+    if word.contains('.') {
+        return word.parse::<f64>().map_err(|_| invalid());
+    }
+    let (numerator, denominator) = match word.split_once('/') {
+        Some((numerator, denominator)) => (numerator, denominator),
+        None => (word, "1"),
+    };
+    let numerator = numerator.parse::<u64>().map_err(|_| invalid())?;
+    let denominator = denominator.parse::<u64>().map_err(|_| invalid())?;
+    if numerator == 0 || denominator == 0 {
+        return Err(invalid());
+    }
+    Ok(1200.0 * (numerator as f64 / denominator as f64).log2())
+}
+
+fn parse_keyboard_mapping(text: &str) -> Result<KeyboardMapping, Error> {
+    let mut words = content_lines(text)
+        .filter(|line| !line.is_empty())
+        .map(first_word);
+    let mut next_number = |name: &str| {
+        words
+            .next()
+            .and_then(|word| word.parse::<i32>().ok())
+            .ok_or_else(|| Error::User(format!("Scala keyboard mapping has no {}", name)))
+    };
+    let size = next_number("map size")?;
+    let first_note = next_number("first note")?;
+    let last_note = next_number("last note")?;
+    let middle_note = next_number("middle note")?;
+    let reference_note = next_number("reference note")?;
+    let reference_frequency = words
+        .next()
+        .and_then(|word| word.parse::<f64>().ok())
+        .filter(|frequency| *frequency > 0.0)
+        .ok_or_else(|| {
+            Error::User("Scala keyboard mapping has no reference frequency".to_owned())
+        })?;
+    let octave_degree = words
+        .next()
+        .and_then(|word| word.parse::<i32>().ok())
+        .ok_or_else(|| Error::User("Scala keyboard mapping has no octave degree".to_owned()))?;
+    if size < 0 {
+        return Err(Error::User(
+            "Scala keyboard mapping has a negative size".to_owned(),
+        ));
+    }
+
+    // Entries missing from the end of the mapping are unmapped
+    let mut mapping = vec![None; size as usize];
+    for (entry, word) in mapping.iter_mut().zip(words) {
+        *entry = match word {
+            "x" | "X" => None,
+            _ => Some(word.parse::<i32>().map_err(|_| {
+                Error::User(format!("Invalid Scala keyboard mapping entry: {}", word))
+            })?),
+        };
+    }
+    Ok(KeyboardMapping {
+        first_note,
+        last_note,
+        middle_note,
+        reference_note,
+        reference_frequency,
+        octave_degree,
+        mapping,
+    })
+}
@@ -1,8 +1,42 @@
 use crate::{Error, LoopRange, OneShotSource, WavSource};
-use hound::WavReader;
+use hound::{WavReader, WavSpec};
+use serde_derive::{Deserialize, Serialize};
 use soundfont::data::SampleHeader;
 
-use std::io::Cursor;
+use std::io::{Cursor, Read};
+
+/// What to do on reaching a sample that cannot be decoded, such as in a truncated file.
+/// Configs choose one for each WAV sample and one-shot with their bad_samples field.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BadSamplePolicy {
+    /// Fail with the decoding error.
+    #[default]
+    Fail,
+    /// Keep the whole frames before the bad sample and print a warning.
+    Truncate,
+}
+
+fn read_samples<R: Read>(wav: WavReader<R>, policy: BadSamplePolicy) -> Result<Vec<f32>, Error> {
+    let channels = wav.spec().channels as usize;
+    let mut data = Vec::with_capacity(wav.len() as usize);
+    for sample in wav.into_samples::<f32>() {
+        match sample {
+            Ok(sample) => data.push(sample),
+            Err(error) if policy == BadSamplePolicy::Truncate => {
+                data.truncate(data.len() - data.len() % channels.max(1));
+                log_warning!(
+                    "WAV",
+                    "Truncated after {} samples at a bad sample: {}",
+                    data.len(),
+                    error
+                );
+                break;
+            }
+            Err(error) => return Err(error.into()),
+        }
+    }
+    Ok(data)
+}
 
 /// Make a WavSource. The source note is a MIDI notes, where 69 is A440.
 pub fn wav_from_file(
@@ -10,10 +44,27 @@ pub fn wav_from_file(
     source_note: u8,
     loop_range: Option<LoopRange>,
     node_id: Option<u64>,
+) -> Result<WavSource, Error> {
+    wav_from_file_with_policy(
+        file_name,
+        source_note,
+        loop_range,
+        node_id,
+        BadSamplePolicy::Fail,
+    )
+}
+
+/// Make a WavSource, choosing how to handle samples that cannot be decoded.
+pub fn wav_from_file_with_policy(
+    file_name: &str,
+    source_note: u8,
+    loop_range: Option<LoopRange>,
+    node_id: Option<u64>,
+    policy: BadSamplePolicy,
 ) -> Result<WavSource, Error> {
     let wav = WavReader::open(file_name)?;
     let spec = wav.spec();
-    let data: Vec<f32> = wav.into_samples().map(|s| s.unwrap()).collect();
+    let data = read_samples(wav, policy)?;
     WavSource::new_from_data(spec, source_note, data, loop_range, node_id)
 }
 
@@ -23,37 +74,75 @@ pub fn wav_from_bytes(
     source_note: u8,
     loop_range: Option<LoopRange>,
     node_id: Option<u64>,
+) -> Result<WavSource, Error> {
+    wav_from_bytes_with_policy(
+        bytes,
+        source_note,
+        loop_range,
+        node_id,
+        BadSamplePolicy::Fail,
+    )
+}
+
+/// Make a WavSource, choosing how to handle samples that cannot be decoded.
+pub fn wav_from_bytes_with_policy(
+    bytes: &[u8],
+    source_note: u8,
+    loop_range: Option<LoopRange>,
+    node_id: Option<u64>,
+    policy: BadSamplePolicy,
 ) -> Result<WavSource, Error> {
     let cursor = Cursor::new(bytes);
     let wav = WavReader::new(cursor)?;
     let spec = wav.spec();
-    let data: Vec<f32> = wav.into_samples().map(|s| s.unwrap()).collect();
+    let data = read_samples(wav, policy)?;
     WavSource::new_from_data(spec, source_note, data, loop_range, node_id)
 }
 
+pub(crate) fn samples_from_i16(source_data: &[i16]) -> Vec<f32> {
+    source_data
+        .iter()
+        .map(|sample| *sample as f32 / 32768.0)
+        .collect()
+}
+
 pub fn wav_from_i16_samples(
     header: &SampleHeader,
     source_data: &[i16],
 ) -> Result<WavSource, Error> {
-    let mut data: Vec<f32> = vec![0.0; source_data.len()];
-    for (i, sample) in source_data.iter().enumerate() {
-        data[i] = *sample as f32 / 32768.0;
-    }
-    WavSource::new_from_raw_sf2_data(header, data)
+    WavSource::new_from_raw_sf2_data(header, samples_from_i16(source_data))
 }
 
 pub fn one_shot_from_file(file_name: &str, node_id: Option<u64>) -> Result<OneShotSource, Error> {
+    one_shot_from_file_with_policy(file_name, node_id, BadSamplePolicy::Fail)
+}
+
+/// Make a OneShotSource, choosing how to handle samples that cannot be decoded.
+pub fn one_shot_from_file_with_policy(
+    file_name: &str,
+    node_id: Option<u64>,
+    policy: BadSamplePolicy,
+) -> Result<OneShotSource, Error> {
     let wav = WavReader::open(file_name)?;
     let spec = wav.spec();
-    let data: Vec<f32> = wav.into_samples().map(|s| s.unwrap()).collect();
+    let data = read_samples(wav, policy)?;
     OneShotSource::new_from_data(spec, data, node_id)
 }
 
 pub fn one_shot_from_bytes(bytes: &[u8], node_id: Option<u64>) -> Result<OneShotSource, Error> {
+    one_shot_from_bytes_with_policy(bytes, node_id, BadSamplePolicy::Fail)
+}
+
+/// Make a OneShotSource, choosing how to handle samples that cannot be decoded.
+pub fn one_shot_from_bytes_with_policy(
+    bytes: &[u8],
+    node_id: Option<u64>,
+    policy: BadSamplePolicy,
+) -> Result<OneShotSource, Error> {
     let cursor = Cursor::new(bytes);
     let wav = WavReader::new(cursor)?;
     let spec = wav.spec();
-    let data: Vec<f32> = wav.into_samples().map(|s| s.unwrap()).collect();
+    let data = read_samples(wav, policy)?;
     OneShotSource::new_from_data(spec, data, node_id)
 }
 
@@ -61,9 +150,45 @@ pub fn one_shot_from_i16_samples(
     header: &SampleHeader,
     source_data: &[i16],
 ) -> Result<OneShotSource, Error> {
-    let mut data: Vec<f32> = vec![0.0; source_data.len()];
-    for (i, sample) in source_data.iter().enumerate() {
-        data[i] = *sample as f32 / 32768.0;
-    }
-    OneShotSource::new_from_raw_sf2_data(header, data)
+    OneShotSource::new_from_raw_sf2_data(header, samples_from_i16(source_data))
+}
+
+/// Read raw sample data, such as for adding to a SoundEffectPoolBuilder.
+pub fn wav_data_from_file(file_name: &str) -> Result<(WavSpec, Vec<f32>), Error> {
+    let wav = WavReader::open(file_name)?;
+    let spec = wav.spec();
+    let data = read_samples(wav, BadSamplePolicy::Fail)?;
+    Ok((spec, data))
+}
+
+/// Read raw sample data, such as for adding to a SoundEffectPoolBuilder.
+pub fn wav_data_from_bytes(bytes: &[u8]) -> Result<(WavSpec, Vec<f32>), Error> {
+    let cursor = Cursor::new(bytes);
+    let wav = WavReader::new(cursor)?;
+    let spec = wav.spec();
+    let data = read_samples(wav, BadSamplePolicy::Fail)?;
+    Ok((spec, data))
+}
+
+/// Read raw sample data, choosing how to handle samples that cannot be decoded.
+pub fn wav_data_from_file_with_policy(
+    file_name: &str,
+    policy: BadSamplePolicy,
+) -> Result<(WavSpec, Vec<f32>), Error> {
+    let wav = WavReader::open(file_name)?;
+    let spec = wav.spec();
+    let data = read_samples(wav, policy)?;
+    Ok((spec, data))
+}
+
+/// Read raw sample data, choosing how to handle samples that cannot be decoded.
+pub fn wav_data_from_bytes_with_policy(
+    bytes: &[u8],
+    policy: BadSamplePolicy,
+) -> Result<(WavSpec, Vec<f32>), Error> {
+    let cursor = Cursor::new(bytes);
+    let wav = WavReader::new(cursor)?;
+    let spec = wav.spec();
+    let data = read_samples(wav, policy)?;
+    Ok((spec, data))
 }
@@ -0,0 +1,268 @@
+use crate::{
+    util::name_id, Error, EventChannel, ExternalClock, FadeChain, FadeStep, FileGraphLoader,
+    GainReductionMeter, GraphLoader, MidiActivity, NodeControlEvent, NodeEvent, SoundSource,
+};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::collections::HashMap;
+
+// Whether the graph holds a source, searching from the given one inwards
+fn graph_contains(source: &SoundSource, is_match: &dyn Fn(&SoundSource) -> bool) -> bool {
+    if is_match(source) {
+        return true;
+    }
+    let mut found = false;
+    FileGraphLoader::traverse_sources(source, |child| {
+        if !found && !std::ptr::eq(child, source) {
+            found = graph_contains(child, is_match);
+        }
+    });
+    found
+}
+
+/// The node IDs of the Named sources in a graph, by name, for sending control events by
+/// name. Returned along with the event channels by GraphLoader::load_source_with_names.
+#[derive(Clone, Default, Debug)]
+pub struct NodeNames {
+    ids: HashMap<String, u64>,
+}
+
+impl NodeNames {
+    pub fn of(source: &SoundSource) -> Self {
+        let mut names = Self::default();
+        names.collect(source);
+        names
+    }
+
+    fn collect(&mut self, source: &SoundSource) {
+        if let SoundSource::Named { name, .. } = source {
+            self.ids.insert(name.clone(), name_id(name));
+        }
+        FileGraphLoader::traverse_sources(source, |child| {
+            if !std::ptr::eq(child, source) {
+                self.collect(child);
+            }
+        });
+    }
+
+    pub fn node_id(&self, name: &str) -> Result<u64, Error> {
+        self.ids
+            .get(name)
+            .copied()
+            .ok_or_else(|| Error::User(format!("No source named {} in graph", name)))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.ids.keys().map(String::as_str)
+    }
+
+    /// Send a control event to the source with the given name, through whichever of the
+    /// graph's event channels leads to it.
+    pub fn send(
+        &self,
+        channels: &[EventChannel],
+        name: &str,
+        event: NodeControlEvent,
+    ) -> Result<(), Error> {
+        let node_id = self.node_id(name)?;
+        let mut sent = false;
+        for channel in channels.iter() {
+            sent |= channel
+                .send(NodeEvent::NodeControl {
+                    node_id,
+                    event: event.clone(),
+                })
+                .is_ok();
+        }
+        if sent {
+            Ok(())
+        } else {
+            Err(Error::User(
+                "Graph is no longer receiving events".to_owned(),
+            ))
+        }
+    }
+}
+
+// A sender for events to one node, checked against the graph the channel feeds
+#[derive(Clone)]
+struct NodeSender {
+    node_id: u64,
+    sender: Sender<NodeEvent>,
+}
+
+impl NodeSender {
+    fn new(
+        channel: &EventChannel,
+        graph: &SoundSource,
+        node_id: u64,
+        type_name: &str,
+        is_match: &dyn Fn(&SoundSource) -> bool,
+    ) -> Result<Self, Error> {
+        if !graph_contains(graph, is_match) {
+            return Err(Error::User(format!(
+                "No {} with node ID {} in graph",
+                type_name, node_id
+            )));
+        }
+        Ok(Self {
+            node_id,
+            sender: channel.sender.clone(),
+        })
+    }
+
+    fn control(&self, event: NodeControlEvent) -> Result<(), Error> {
+        self.sender
+            .send(NodeEvent::NodeControl {
+                node_id: self.node_id,
+                event,
+            })
+            .map_err(|_| Error::User("Graph is no longer receiving events".to_owned()))
+    }
+}
+
+/// Sends control events to a Fader in a loaded graph, through the event channel of an
+/// EventReceiver holding it. The fader is checked for when the handle is made.
+#[derive(Clone)]
+pub struct FaderHandle(NodeSender);
+
+impl FaderHandle {
+    pub fn new(channel: &EventChannel, graph: &SoundSource, node_id: u64) -> Result<Self, Error> {
+        let is_match = |source: &SoundSource| matches!(source, SoundSource::Fader { node_id: Some(id), .. } if *id == node_id);
+        NodeSender::new(channel, graph, node_id, "Fader", &is_match).map(Self)
+    }
+
+    pub fn set_volume(&self, volume: f32) -> Result<(), Error> {
+        self.fade(volume, volume, 0.0)
+    }
+
+    pub fn fade(&self, from: f32, to: f32, seconds: f32) -> Result<(), Error> {
+        self.0.control(NodeControlEvent::Fade { from, to, seconds })
+    }
+
+    /// Run up to MAX_FADE_STEPS steps in order, replacing any chain already running.
+    pub fn fade_chain(&self, steps: Vec<FadeStep>) -> Result<(), Error> {
+        self.0
+            .control(NodeControlEvent::FadeChain(FadeChain::new(steps)?))
+    }
+
+    /// Get a receiver that is sent the fader's node ID each time a fade completes.
+    pub fn completions(&self) -> Result<Receiver<u64>, Error> {
+        let (sender, receiver) = unbounded();
+        self.0
+            .control(NodeControlEvent::NotifyFadeComplete(sender))?;
+        Ok(receiver)
+    }
+}
+
+/// Sends control events to a Mixer in a loaded graph.
+#[derive(Clone)]
+pub struct MixerHandle(NodeSender);
+
+impl MixerHandle {
+    pub fn new(channel: &EventChannel, graph: &SoundSource, node_id: u64) -> Result<Self, Error> {
+        let is_match = |source: &SoundSource| matches!(source, SoundSource::Mixer { node_id: Some(id), .. } if *id == node_id);
+        NodeSender::new(channel, graph, node_id, "Mixer", &is_match).map(Self)
+    }
+
+    pub fn set_balance(&self, balance: f32) -> Result<(), Error> {
+        self.0.control(NodeControlEvent::MixerBalance(balance))
+    }
+}
+
+/// Sends control events to a Crossfade in a loaded graph.
+#[derive(Clone)]
+pub struct CrossfadeHandle(NodeSender);
+
+impl CrossfadeHandle {
+    pub fn new(channel: &EventChannel, graph: &SoundSource, node_id: u64) -> Result<Self, Error> {
+        let is_match = |source: &SoundSource| matches!(source, SoundSource::Crossfade { node_id: Some(id), .. } if *id == node_id);
+        NodeSender::new(channel, graph, node_id, "Crossfade", &is_match).map(Self)
+    }
+
+    pub fn crossfade_to(&self, position: f32, seconds: f32) -> Result<(), Error> {
+        self.0.control(NodeControlEvent::Crossfade {
+            to: position,
+            seconds,
+        })
+    }
+}
+
+/// Sends control events to a GateExpander in a loaded graph.
+#[derive(Clone)]
+pub struct GateExpanderHandle(NodeSender);
+
+impl GateExpanderHandle {
+    pub fn new(channel: &EventChannel, graph: &SoundSource, node_id: u64) -> Result<Self, Error> {
+        let is_match = |source: &SoundSource| matches!(source, SoundSource::GateExpander { node_id: Some(id), .. } if *id == node_id);
+        NodeSender::new(channel, graph, node_id, "GateExpander", &is_match).map(Self)
+    }
+
+    /// Get a meter of how far the source is turned down, from the next buffer on.
+    pub fn meter(&self) -> Result<GainReductionMeter, Error> {
+        let meter = GainReductionMeter::new();
+        self.0
+            .control(NodeControlEvent::MeterGainReduction(meter.clone()))?;
+        Ok(meter)
+    }
+}
+
+/// Sends control events to a Midi source in a loaded graph.
+#[derive(Clone)]
+pub struct MidiHandle(NodeSender);
+
+impl MidiHandle {
+    pub fn new(channel: &EventChannel, graph: &SoundSource, node_id: u64) -> Result<Self, Error> {
+        let is_match = |source: &SoundSource| matches!(source, SoundSource::Midi { node_id: Some(id), .. } if *id == node_id);
+        NodeSender::new(channel, graph, node_id, "Midi", &is_match).map(Self)
+    }
+
+    /// Jump to an anchor, or back to the start for None, at the next ideal point.
+    pub fn seek(&self, to_anchor: Option<u32>) -> Result<(), Error> {
+        self.0
+            .control(NodeControlEvent::SeekWhenIdeal { to_anchor })
+    }
+
+    pub fn tempo_ramp(&self, bpm: f32, bars: u32) -> Result<(), Error> {
+        self.0.control(NodeControlEvent::TempoRamp { bpm, bars })
+    }
+
+    /// Play faster or slower than the track's own tempo, reaching the rate over some seconds.
+    /// A rate of 0.5 plays at half speed without lowering the pitch of any notes.
+    pub fn set_playback_rate(&self, rate: f32, seconds: f32) -> Result<(), Error> {
+        self.0
+            .control(NodeControlEvent::PlaybackRate { rate, seconds })
+    }
+
+    /// Play at the tempo of an external clock, or at the file's own tempo again for None.
+    pub fn follow_clock(&self, clock: Option<ExternalClock>) -> Result<(), Error> {
+        self.0.control(NodeControlEvent::FollowClock(clock))
+    }
+
+    /// Shift every note the track plays by some semitones, from the next note on. Notes on
+    /// the percussion channel, channel 10, keep their keys.
+    pub fn transpose(&self, semitones: i8) -> Result<(), Error> {
+        self.0.control(NodeControlEvent::Transpose(semitones))
+    }
+
+    /// Raise or lower the pitch of every channel's sources by some cents, such as to match
+    /// live instruments tuned slightly away from A440.
+    pub fn master_tune(&self, cents: f32) -> Result<(), Error> {
+        self.0.control(NodeControlEvent::MasterTune(cents))
+    }
+
+    /// Get a view of the notes the source is playing, for visualization or debugging.
+    pub fn activity(&self) -> Result<MidiActivity, Error> {
+        let activity = MidiActivity::new();
+        self.0
+            .control(NodeControlEvent::TrackActivity(activity.clone()))?;
+        Ok(activity)
+    }
+
+    /// Get a receiver that is sent the source's node ID when the end of the track is reached.
+    pub fn completions(&self) -> Result<Receiver<u64>, Error> {
+        let (sender, receiver) = unbounded();
+        self.0
+            .control(NodeControlEvent::NotifyPlaybackComplete(sender))?;
+        Ok(receiver)
+    }
+}
@@ -9,45 +9,139 @@ mod tests;
 
 #[cfg(target_arch = "wasm32")]
 mod wasm_demo;
+#[cfg(target_arch = "wasm32")]
+mod wasm_worklet;
+
+#[cfg(feature = "alloc-audit")]
+pub mod audit;
+
+#[macro_use]
+mod trace;
 
 mod config;
 mod error;
 mod file;
+mod handle;
 mod loader;
 mod mix;
+pub mod quick;
 mod source;
 
-pub use config::{Config, FontSource, Loop, MidiDataSource, RangeSource, SoundSource};
+pub use config::{
+    drums::ChipDrumKind,
+    export::GraphExporter,
+    host_event::{HostControl, HostEvent, HostTarget},
+    include::included_paths,
+    migrate::CURRENT_CONFIG_VERSION,
+    notes::{NoteMapping, QuantizeDirection, Scale, SequencerStep, VelocityCurve},
+    params::ParamValue,
+    registry::{is_node_type_registered, register_node_type, NodeConfig},
+    validate::ValidationError,
+    Breakpoint, Config, ConfigFormat, EnvelopeCurve, EnvelopeRetrigger, FmAlgorithm, FmOperator,
+    FontSource, InlineData, LfoShape, Loop, LoopMode, MidiDataSource, ModRoute, ModSource,
+    ModulationTarget, NoiseColor, OscillatorMode, PitchMotion, RangeSource, ReleaseSource,
+    Retrigger, RingModMode, SoundSource, TuningSource, Unison, Variation, VoiceStealing,
+};
 pub use error::Error;
-pub use file::loader::FileGraphLoader;
-pub use loader::GraphLoader;
+
+pub use handle::{
+    CrossfadeHandle, FaderHandle, GateExpanderHandle, MidiHandle, MixerHandle, NodeNames,
+};
+
+pub use file::{
+    cache::{prewarm_sample_cache, SampleCache},
+    loader::FileGraphLoader,
+    memory::MemoryAssetLoader,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use file::background::{GraphLoadHandle, LoadProgress};
+#[cfg(target_arch = "wasm32")]
+pub use file::fetch::FetchAssetLoader;
+pub use loader::{asset_paths, load_source_lenient, AssetLoader, GraphLoader};
+#[cfg(feature = "driver-cpal")]
 pub use mix::base::BaseMixer;
+#[cfg(feature = "driver-cpal")]
+pub use mix::builder::{BaseMixerBuilder, RunningStream};
+pub use mix::{
+    layout::ChannelLayout,
+    stats::RenderStats,
+    sync::{ClockOffset, SyncFollower, SyncLeader, SyncTransport},
+};
+#[cfg(target_arch = "wasm32")]
+pub use wasm_worklet::{WorkletAssets, WorkletRenderer};
+
+#[cfg(feature = "rodio")]
+pub use mix::rodio_source::GraphSource;
+#[cfg(all(feature = "midir", not(target_arch = "wasm32")))]
+pub use source::midi_out::MidiOutputNode;
 pub use source::{
+    ab_compare::{AbBlindMapping, AbCompareSource},
+    additive::AdditiveSource,
     async_receiver::{AsyncEventReceiver, EventChannel},
     combiner::CombinerSource,
+    convolution::ConvolutionNode,
+    crossfade::CrossfadeSource,
+    crossfeed::Crossfeed,
+    duck::DuckSource,
+    effect_mix::EffectMix,
+    effect_pool::{SoundEffectPool, SoundEffectPoolBuilder, SoundEffectPoolHandle},
     envelope::Envelope,
-    fader::Fader,
+    fader::{FadeChain, FadeStep, Fader, MAX_FADE_STEPS},
+    fm::FmSynthSource,
     font::{SoundFont, SoundFontBuilder},
+    gain::GainNode,
+    gate::GateNode,
+    gate_expander::GateExpanderNode,
+    meter::{report_gain_reduction, take_gain_reduction, GainReductionMeter},
     midi::{
-        cue::{Cue, TimelineCue},
+        activity::{MidiActivity, SoundingNote},
+        clock::ExternalClock,
+        cue::{Cue, TimedCue, TimelineCue},
+        meter::TimeSignature,
         MidiSource, MidiSourceBuilder,
     },
     mixer::MixerSource,
-    noise::LfsrNoiseSource,
+    mod_matrix::ModMatrix,
+    multi_stage::MultiStageEnvelope,
+    noise::{ColoredNoiseSource, LfsrNoiseSource, SampleHoldSource},
+    note_map::NoteMap,
     null::NullSource,
-    one_shot::OneShotSource,
+    one_shot::{ChokeGroup, OneShotSource},
+    parallel::ParallelCombinerSource,
+    param::{ParamBinding, ParamTarget},
+    pitch_shift::PitchShiftNode,
+    playlist::PlaylistNode,
+    pluck::PluckedStringSource,
+    quantizer::ScaleQuantizer,
+    ring_mod::RingModNode,
+    routing::EventRoutes,
     sawtooth::SawtoothWaveSource,
+    scope::{ScopeNode, ScopeReader},
+    sequencer::SequencerSource,
     square::SquareWaveSource,
+    tag::TagBinding,
+    test_signal::{TestSignal, TestSignalSource},
+    tremolo::TremoloNode,
     triangle::TriangleWaveSource,
+    tuning::{TuningNode, TuningTable},
+    vibrato::VibratoNode,
     wav::WavSource,
     BroadcastControl, BufferConsumer, BufferConsumerNode, LoopRange, Node, NodeControlEvent,
     NodeEvent, NoteEvent, NoteRange,
 };
 
+pub mod graph {
+    pub use crate::config::builder::*;
+}
+
 pub mod util {
+    pub use crate::config::params::{name_id, param_id, snapshot_id, tag_id};
     pub use crate::file::font::*;
     pub use crate::file::midi::*;
+    pub use crate::file::scala::*;
     pub use crate::file::wav::*;
+    pub use crate::source::buffer::*;
     pub use crate::source::midi::util::*;
     pub use crate::source::util::*;
 }
@@ -1,4 +1,139 @@
-use crate::{config::SoundSource, BufferConsumerNode, Error, EventChannel, FontSource};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::GraphLoadHandle;
+use crate::{
+    config::SoundSource, BufferConsumerNode, Error, EventChannel, FileGraphLoader, FontSource,
+    MidiDataSource, NodeNames, SampleCache, TuningSource,
+};
+use std::cell::RefCell;
+use std::time::SystemTime;
+
+/// Provides the raw bytes of assets named in a config, such as MIDI, WAV and SF2 files.
+/// Every AssetLoader is also a GraphLoader, building graphs from the assets it provides.
+pub trait AssetLoader {
+    fn load_asset_data(&self, path: &str) -> Result<Vec<u8>, Error>;
+
+    // Whether an asset is available, used when validating configs; loaders that can
+    // check without reading the whole asset should override this
+    fn has_asset(&self, path: &str) -> bool {
+        self.load_asset_data(path).is_ok()
+    }
+
+    // Called when an asset fails to load or decode while building a graph. Returning Ok
+    // puts a silent NullSource in place of the node that needed it rather than failing the
+    // whole graph; see load_source_lenient
+    fn on_asset_error(&self, _path: &str, error: Error) -> Result<(), Error> {
+        Err(error)
+    }
+
+    // Cache of decoded WAV and SF2 data shared by every graph built through this loader,
+    // or None to decode assets each time they are used
+    fn sample_cache(&self) -> Option<&SampleCache> {
+        None
+    }
+
+    // When an asset last changed, if the loader can tell, so that data cached from an
+    // earlier version of it is decoded again rather than reused
+    fn asset_modified(&self, _path: &str) -> Option<SystemTime> {
+        None
+    }
+}
+
+// Asset loader accepting every failure, noting each one as a warning
+struct LenientAssetLoader<'a, A: AssetLoader> {
+    loader: &'a A,
+    warnings: RefCell<Vec<String>>,
+}
+
+impl<A: AssetLoader> AssetLoader for LenientAssetLoader<'_, A> {
+    fn load_asset_data(&self, path: &str) -> Result<Vec<u8>, Error> {
+        self.loader.load_asset_data(path)
+    }
+
+    fn has_asset(&self, path: &str) -> bool {
+        self.loader.has_asset(path)
+    }
+
+    fn sample_cache(&self) -> Option<&SampleCache> {
+        self.loader.sample_cache()
+    }
+
+    fn asset_modified(&self, path: &str) -> Option<SystemTime> {
+        self.loader.asset_modified(path)
+    }
+
+    fn on_asset_error(&self, path: &str, error: Error) -> Result<(), Error> {
+        let warning = format!(
+            "Could not load {}, using a silent placeholder: {}",
+            path, error
+        );
+        log_warning!("Loader", "{}", warning);
+        self.warnings.borrow_mut().push(warning);
+        Ok(())
+    }
+}
+
+/// Build a graph like GraphLoader::load_source_recursive, except that a MIDI, WAV, SF2 or
+/// Scala asset that cannot be loaded or decoded does not fail the build. The node needing it is
+/// replaced by a silent NullSource with the same node ID, and the returned warnings say
+/// what was replaced. Sources inside a replaced MIDI node are not loaded at all.
+#[allow(clippy::type_complexity)]
+pub fn load_source_lenient<A: AssetLoader>(
+    loader: &A,
+    source: &SoundSource,
+) -> Result<
+    (
+        Vec<EventChannel>,
+        Box<dyn BufferConsumerNode + Send + 'static>,
+        Vec<String>,
+    ),
+    Error,
+> {
+    let lenient = LenientAssetLoader {
+        loader,
+        warnings: RefCell::new(vec![]),
+    };
+    let (channels, source) = lenient.load_source_recursive(source)?;
+    Ok((channels, source, lenient.warnings.into_inner()))
+}
+
+/// Get the paths of all assets that loading the given source will request,
+/// without duplicates, so that they can be fetched or checked ahead of time.
+pub fn asset_paths(source: &SoundSource) -> Vec<String> {
+    let mut paths = vec![];
+    collect_asset_paths(source, &mut paths);
+    paths
+}
+
+fn collect_asset_paths(source: &SoundSource, paths: &mut Vec<String>) {
+    let source_paths = match source {
+        SoundSource::Midi {
+            source: MidiDataSource::FilePath(path),
+            ..
+        } => vec![path],
+        SoundSource::Font {
+            config: FontSource::Sf2FilePath { path, .. },
+            ..
+        } => vec![path],
+        SoundSource::SampleFilePath { path, .. } => vec![path],
+        SoundSource::OneShotFilePath { path, .. } => vec![path],
+        SoundSource::Convolution { path, .. } => vec![path],
+        SoundSource::Tuned {
+            tuning: TuningSource::ScalaFilePath { scl, kbm },
+            ..
+        } => std::iter::once(scl).chain(kbm).collect(),
+        _ => vec![],
+    };
+    for path in source_paths {
+        if !paths.contains(path) {
+            paths.push(path.clone());
+        }
+    }
+    FileGraphLoader::traverse_sources(source, |child| {
+        if !std::ptr::eq(child, source) {
+            collect_asset_paths(child, paths);
+        }
+    });
+}
 
 pub trait GraphLoader {
     fn load_source_recursive(
@@ -12,6 +147,35 @@ pub trait GraphLoader {
         Error,
     >;
 
+    /// As load_source_recursive, also returning the node IDs of the graph's Named sources
+    /// so that events can be sent to them by name.
+    #[allow(clippy::type_complexity)]
+    fn load_source_with_names(
+        &self,
+        source: &SoundSource,
+    ) -> Result<
+        (
+            Vec<EventChannel>,
+            NodeNames,
+            Box<dyn BufferConsumerNode + Send + 'static>,
+        ),
+        Error,
+    > {
+        let (channels, consumer) = self.load_source_recursive(source)?;
+        Ok((channels, NodeNames::of(source), consumer))
+    }
+
+    /// Build a graph on a worker thread, returning a handle that reports progress through
+    /// the graph's assets and gives the graph once built. Not available on WebAssembly,
+    /// which has no threads; FetchAssetLoader::fetch_graph does the slow part there.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_async(self, source: SoundSource) -> GraphLoadHandle
+    where
+        Self: AssetLoader + Sized + Send + 'static,
+    {
+        GraphLoadHandle::spawn(self, source)
+    }
+
     fn traverse_sources(root: &SoundSource, mut yield_source: impl FnMut(&SoundSource)) {
         yield_source(root);
         match root {
@@ -23,23 +187,59 @@ pub trait GraphLoader {
             SoundSource::EventReceiver { source, .. } => {
                 yield_source(source.as_ref());
             }
+            SoundSource::Tagged { source, .. } => {
+                yield_source(source.as_ref());
+            }
+            SoundSource::Named { source, .. } => {
+                yield_source(source.as_ref());
+            }
+            SoundSource::Tuned { source, .. } => {
+                yield_source(source.as_ref());
+            }
             SoundSource::Font { config, .. } => match config {
                 FontSource::Ranges(ranges) => {
                     for range in ranges.iter() {
                         yield_source(&range.source);
+                        if let Some(release) = range.release.as_ref() {
+                            yield_source(&release.source);
+                        }
                     }
                 }
                 FontSource::Sf2FilePath { .. } => {}
+                FontSource::Sf2Inline { .. } => {}
             },
             SoundSource::SquareWave { .. } => {}
             SoundSource::TriangleWave { .. } => {}
             SoundSource::SawtoothWave { .. } => {}
             SoundSource::LfsrNoise { .. } => {}
+            SoundSource::ColoredNoise { .. } => {}
+            SoundSource::SampleHold { .. } => {}
+            SoundSource::ChipDrum { .. } => {}
+            SoundSource::FmSynth { .. } => {}
+            SoundSource::Additive { .. } => {}
+            SoundSource::PluckedString { .. } => {}
             SoundSource::SampleFilePath { .. } => {}
+            SoundSource::SampleInline { .. } => {}
             SoundSource::OneShotFilePath { .. } => {}
+            SoundSource::OneShotInline { .. } => {}
+            SoundSource::TestSignal { .. } => {}
+            SoundSource::MidiOutput { .. } => {}
+            SoundSource::Custom { sources, .. } => {
+                for source in sources.iter() {
+                    yield_source(source);
+                }
+            }
+            SoundSource::Include(_) => {}
+            SoundSource::Ref(_) => {}
             SoundSource::Envelope { source, .. } => {
                 yield_source(source);
             }
+            SoundSource::MultiStageEnvelope { source, .. } => {
+                yield_source(source);
+            }
+            SoundSource::ModMatrix { source, .. } => {
+                yield_source(source);
+            }
             SoundSource::Combiner { sources, .. } => {
                 for source in sources.iter() {
                     yield_source(source);
@@ -51,9 +251,64 @@ pub trait GraphLoader {
                 yield_source(source_0);
                 yield_source(source_1);
             }
+            SoundSource::Crossfade {
+                source_0, source_1, ..
+            } => {
+                yield_source(source_0);
+                yield_source(source_1);
+            }
+            SoundSource::Duck {
+                music, priority, ..
+            } => {
+                yield_source(music);
+                yield_source(priority);
+            }
             SoundSource::Fader { source, .. } => {
                 yield_source(source);
             }
+            SoundSource::RingMod {
+                source, carrier, ..
+            } => {
+                yield_source(source);
+                if let Some(carrier) = carrier {
+                    yield_source(carrier);
+                }
+            }
+            SoundSource::PitchShift { source, .. } => {
+                yield_source(source);
+            }
+            SoundSource::Tremolo { source, .. } => {
+                yield_source(source);
+            }
+            SoundSource::Vibrato { source, .. } => {
+                yield_source(source);
+            }
+            SoundSource::Convolution { source, .. } => {
+                yield_source(source);
+            }
+            SoundSource::Crossfeed { source, .. } => {
+                yield_source(source);
+            }
+            SoundSource::Sequencer { source, .. } => {
+                yield_source(source);
+            }
+            SoundSource::Gate { source, .. } => {
+                yield_source(source);
+            }
+            SoundSource::GateExpander { source, .. } => {
+                yield_source(source);
+            }
+            SoundSource::Playlist { songs, .. } => {
+                for song in songs.iter() {
+                    yield_source(song);
+                }
+            }
+            SoundSource::NoteMap { source, .. } => {
+                yield_source(source);
+            }
+            SoundSource::ScaleQuantizer { source, .. } => {
+                yield_source(source);
+            }
         }
     }
 }
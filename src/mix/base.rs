@@ -1,31 +1,95 @@
-use crate::{consts, BufferConsumerNode, Config, Error, EventChannel, GraphLoader, NullSource};
+use crate::{
+    consts, AbBlindMapping, AbCompareSource, AsyncEventReceiver, BroadcastControl,
+    BufferConsumerNode, ChannelLayout, Config, Error, EventChannel, GraphLoader, NodeEvent,
+    NullSource, RenderStats,
+};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Stream, StreamConfig};
+use cpal::{Device, Stream, StreamConfig};
+use crossbeam_channel::Sender;
 use std::collections::HashMap;
 use std::sync::{
-    atomic::{AtomicPtr, Ordering},
+    atomic::{AtomicBool, AtomicPtr, Ordering},
     Arc, Mutex,
 };
+use std::time::{Duration, SystemTime};
 
 enum ConsumerCell {
     Source(Box<dyn BufferConsumerNode + Send + 'static>),
     Placeholder,
 }
 
+// State shared between the mixer and its audio callback, kept across stream reopening
+#[derive(Clone)]
+struct StreamShared {
+    silence: Arc<super::silence::SilenceMonitor>,
+    clock: Arc<super::clock::StreamClock>,
+    transport: Arc<super::transport::Transport>,
+    channel_gains: Arc<super::layout::ChannelGains>,
+    conditioning: Arc<super::conditioning::OutputConditioning>,
+    render_stats: Arc<super::stats::RenderStatsRecorder>,
+    layers: Arc<Mutex<super::layers::LayerSet>>,
+    root_fader: Arc<Mutex<super::root_swap::RootFader>>,
+    stream_failed: Arc<AtomicBool>,
+}
+
+impl StreamShared {
+    fn new(
+        layout: ChannelLayout,
+        layers: super::layers::LayerSet,
+        root_fader: super::root_swap::RootFader,
+    ) -> Self {
+        Self {
+            silence: Arc::new(super::silence::SilenceMonitor::default()),
+            clock: Arc::new(super::clock::StreamClock::default()),
+            transport: Arc::new(super::transport::Transport::default()),
+            channel_gains: Arc::new(super::layout::ChannelGains::new(layout)),
+            conditioning: Arc::new(super::conditioning::OutputConditioning::default()),
+            render_stats: Arc::new(super::stats::RenderStatsRecorder::default()),
+            layers: Arc::new(Mutex::new(layers)),
+            root_fader: Arc::new(Mutex::new(root_fader)),
+            stream_failed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+/// Plays a graph through an output device. The audio stream can only be paused and
+/// resumed from the thread that opened it, so the mixer cannot do so by itself: while
+/// auto-suspend is enabled, the host must call update_auto_suspend regularly, or the
+/// stream is never suspended or woken. Likewise, recover_stream must be called regularly
+/// for a failed stream to be reopened.
 pub struct BaseMixer {
-    stream: Mutex<Stream>,
+    stream: Mutex<Option<Stream>>,
+    device_name: Option<String>,
     program_sources: HashMap<usize, ConsumerCell>,
     consumer: super::swap::SwappableConsumer,
+    shared: StreamShared,
+    layout: ChannelLayout,
+    buffer_frames: usize,
+    is_suspended: bool,
+    wake_senders: Vec<Sender<NodeEvent>>,
+    layer_commands: Sender<super::layers::LayerCommand>,
+    layer_ids: Vec<u64>,
+    next_layer_id: u64,
+    root_replacements: Sender<super::root_swap::RootReplacement>,
 }
 
 impl Drop for BaseMixer {
     fn drop(&mut self) {
         let stream = self.stream.lock().expect("Could not lock the audio stream");
-        stream.pause().expect("Could not pause the stream");
+        if let Some(stream) = stream.as_ref() {
+            if !self.shared.stream_failed.load(Ordering::Acquire) {
+                stream.pause().expect("Could not pause the stream");
+            }
+        }
     }
 }
 
 impl BaseMixer {
+    // Choose a graph and stream options, then start them together; see BaseMixerBuilder.
+    pub fn builder() -> super::builder::BaseMixerBuilder {
+        super::builder::BaseMixerBuilder::new()
+    }
+
     pub fn start_empty() -> Result<Self, Error> {
         let consumer = Box::new(NullSource::new(None));
         Self::start_single_program(consumer)
@@ -34,13 +98,73 @@ impl BaseMixer {
     pub fn start_single_program(
         consumer: Box<dyn BufferConsumerNode + Send + 'static>,
     ) -> Result<Self, Error> {
+        Self::start_single_program_with_layout(consumer, None)
+    }
+
+    // Start playing through a specific output channel layout, or pass None to use the
+    // layout of the device's default configuration where it is supported (else stereo).
+    pub fn start_single_program_with_layout(
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+        layout: Option<ChannelLayout>,
+    ) -> Result<Self, Error> {
+        Self::start_single_program_on_device(consumer, None, layout)
+    }
+
+    // Start playing on the output device with the given name, as listed by
+    // list_output_devices, or on the default output device if no name is given.
+    pub fn start_single_program_on_device(
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+        device_name: Option<&str>,
+        layout: Option<ChannelLayout>,
+    ) -> Result<Self, Error> {
+        Self::start_with_options(consumer, device_name, layout, consts::BUFFER_SIZE)
+    }
+
+    // As start_single_program_on_device, also requesting that the device ask for the given
+    // number of frames per callback. Rendering is still done in blocks of BUFFER_SIZE.
+    pub(crate) fn start_with_options(
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+        device_name: Option<&str>,
+        layout: Option<ChannelLayout>,
+        buffer_frames: usize,
+    ) -> Result<Self, Error> {
+        if buffer_frames == 0 {
+            return Err(Error::User(
+                "The stream needs a buffer of at least one frame".to_owned(),
+            ));
+        }
+        let device = Self::find_output_device(device_name)?;
+        let layout = layout.unwrap_or_else(|| Self::negotiate_layout(&device));
         let swappable = super::swap::SwappableConsumer::new(consumer);
-        let stream = Self::open_stream(swappable.take_consumer())?;
+        let (layer_commands, command_receiver) =
+            crossbeam_channel::bounded(super::layers::MAX_LAYERS);
+        let garbage = super::garbage::GarbageSender::spawn_collector()?;
+        let layers = super::layers::LayerSet::new(command_receiver, garbage.clone());
+        let (root_replacements, replacement_receiver) = crossbeam_channel::bounded(4);
+        let root_fader = super::root_swap::RootFader::new(replacement_receiver, garbage);
+        let shared = StreamShared::new(layout, layers, root_fader);
+        let stream = Self::open_stream(
+            &device,
+            layout,
+            buffer_frames,
+            swappable.take_consumer(),
+            shared.clone(),
+        )?;
         stream.play()?;
         Ok(Self {
-            stream: Mutex::new(stream),
+            stream: Mutex::new(Some(stream)),
+            device_name: device_name.map(str::to_owned),
             program_sources: HashMap::new(),
             consumer: swappable,
+            shared,
+            layout,
+            buffer_frames,
+            is_suspended: false,
+            wake_senders: vec![],
+            layer_commands,
+            layer_ids: vec![],
+            next_layer_id: 0,
+            root_replacements,
         })
     }
 
@@ -50,17 +174,72 @@ impl BaseMixer {
         config: &Config,
     ) -> Result<(Vec<EventChannel>, Self), Error> {
         let (channels, source) = loader.load_source_recursive(&config.root)?;
+        let device_name = config
+            .output_device
+            .as_deref()
+            .filter(|name| Self::has_output_device(name));
         if let Some(program_no) = &program_no {
-            let mut mixer = Self::start_empty()?;
+            let empty = Box::new(NullSource::new(None));
+            let mut mixer = Self::start_single_program_on_device(empty, device_name, None)?;
             mixer.store_program(*program_no, source);
             mixer.change_program(*program_no)?;
             Ok((channels, mixer))
         } else {
-            let mixer = Self::start_single_program(source)?;
+            let mixer = Self::start_single_program_on_device(source, device_name, None)?;
             Ok((channels, mixer))
         }
     }
 
+    // Load two configs and play them through the same stream, hearing only one at a time.
+    // The returned channel targets the comparison node; send it AbToggle or AbSelect events
+    // using the given node ID to switch between the two without restarting playback.
+    pub fn start_ab_comparison_from_configs<L: GraphLoader>(
+        loader: &L,
+        node_id: Option<u64>,
+        config_a: &Config,
+        config_b: &Config,
+    ) -> Result<(EventChannel, Vec<EventChannel>, Self), Error> {
+        let (channels, source_a, source_b) = Self::load_ab_pair(loader, config_a, config_b)?;
+        let comparison = AbCompareSource::new(node_id, false, source_a, source_b);
+        let (ab_channel, receiver) = AsyncEventReceiver::new(None, Box::new(comparison));
+        let mixer = Self::start_single_program(Box::new(receiver))?;
+        Ok((ab_channel, channels, mixer))
+    }
+
+    // As start_ab_comparison_from_configs, but A and B are each config with equal chance.
+    // Which config is which stays hidden in the returned mapping until it is revealed.
+    pub fn start_blind_ab_comparison_from_configs<L: GraphLoader>(
+        loader: &L,
+        node_id: Option<u64>,
+        config_a: &Config,
+        config_b: &Config,
+    ) -> Result<(EventChannel, Vec<EventChannel>, AbBlindMapping, Self), Error> {
+        let (channels, source_a, source_b) = Self::load_ab_pair(loader, config_a, config_b)?;
+        let (comparison, mapping) = AbCompareSource::new_blind(node_id, source_a, source_b);
+        let (ab_channel, receiver) = AsyncEventReceiver::new(None, Box::new(comparison));
+        let mixer = Self::start_single_program(Box::new(receiver))?;
+        Ok((ab_channel, channels, mapping, mixer))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn load_ab_pair<L: GraphLoader>(
+        loader: &L,
+        config_a: &Config,
+        config_b: &Config,
+    ) -> Result<
+        (
+            Vec<EventChannel>,
+            Box<dyn BufferConsumerNode + Send + 'static>,
+            Box<dyn BufferConsumerNode + Send + 'static>,
+        ),
+        Error,
+    > {
+        let (mut channels, source_a) = loader.load_source_recursive(&config_a.root)?;
+        let (more_channels, source_b) = loader.load_source_recursive(&config_b.root)?;
+        channels.extend(more_channels);
+        Ok((channels, source_a, source_b))
+    }
+
     // Store a program at a given index.
     // Return whether a program already existed in that index (and will be replaced).
     pub fn store_program(
@@ -98,6 +277,7 @@ impl BaseMixer {
             }
         };
 
+        self.resume_stream()?;
         self.program_sources
             .insert(program_no, ConsumerCell::Placeholder);
         if let Some(previous_program) = self.consumer.swap_consumer(new_program) {
@@ -110,6 +290,189 @@ impl BaseMixer {
         Ok(())
     }
 
+    // Switch to a stored program, starting it exactly at the given stream frame.
+    // Output is silent from now until that frame.
+    pub fn change_program_at(&mut self, program_no: usize, stream_frame: u64) -> Result<(), Error> {
+        self.start_at(stream_frame);
+        self.change_program(program_no)
+    }
+
+    // Hold the current program back, neither filling nor advancing it, until the stream
+    // reaches the given frame. Playback then begins from that exact frame.
+    pub fn start_at(&self, stream_frame: u64) {
+        self.shared.clock.schedule_start(stream_frame);
+    }
+
+    // Hold the current program back until the given wall-clock time, such as a start time
+    // agreed between networked clients (see ClockOffset). Accuracy is limited by how closely
+    // the stream's rendered frames track real time, which is within about one buffer.
+    // Return the stream frame that playback was scheduled for.
+    pub fn start_at_system_time(&self, when: SystemTime) -> u64 {
+        let frames_from_now = match when.duration_since(SystemTime::now()) {
+            Ok(delay) => (delay.as_secs_f64() * consts::PLAYBACK_SAMPLE_RATE as f64) as u64,
+            Err(_) => 0,
+        };
+        let stream_frame = self.stream_frame() + frames_from_now;
+        self.start_at(stream_frame);
+        stream_frame
+    }
+
+    // Keep playback started by start_at_system_time in step with the wall clock. Sound cards
+    // run slightly fast or slow, so over minutes networked clients drift apart. Once the
+    // program is further than the tolerance from where the time since the start says it
+    // should be, it is held back or skipped ahead, which is heard as a short gap or jump.
+    // Call regularly with the start time and the frame start_at_system_time returned; a
+    // SyncFollower's start_time may be passed each time, as its estimate improves.
+    // Return the adjustment made in frames, positive if the program was held back.
+    pub fn correct_drift(&self, start: SystemTime, start_frame: u64, tolerance: Duration) -> i64 {
+        self.shared
+            .clock
+            .correct_drift(SystemTime::now(), start, start_frame, tolerance)
+    }
+
+    // Get the number of frames the stream has rendered so far, for use with start_at.
+    pub fn stream_frame(&self) -> u64 {
+        self.shared.clock.frames_rendered()
+    }
+
+    // Replace whatever is playing with a new root, cross-fading from the old root to the new
+    // one over the given time so that scene changes do not click. The new root should be
+    // fully built beforehand, as by replace_root_from_config. The old root is dropped on a
+    // background thread rather than on the audio thread. Any program that was playing is
+    // no longer stored.
+    pub fn replace_root(
+        &mut self,
+        new_root: Box<dyn BufferConsumerNode + Send + 'static>,
+        crossfade_seconds: f32,
+    ) -> Result<(), Error> {
+        if crossfade_seconds.is_nan() || crossfade_seconds < 0.0 {
+            return Err(Error::User(format!(
+                "Crossfade time {} must not be negative",
+                crossfade_seconds
+            )));
+        }
+        let fade_frames = (crossfade_seconds * consts::PLAYBACK_SAMPLE_RATE as f32) as usize;
+        let replacement = super::root_swap::RootReplacement {
+            root: Box::new(new_root),
+            fade_frames,
+        };
+        self.root_replacements
+            .try_send(replacement)
+            .map_err(|_| Error::User("Too many root replacements are waiting".to_owned()))?;
+        if let Some(program_no) = self.get_current_program_no() {
+            self.program_sources.remove(&program_no);
+        }
+        self.resume_stream()
+    }
+
+    // Load a config on this thread and cross-fade to it as the new root; see replace_root.
+    pub fn replace_root_from_config<L: GraphLoader>(
+        &mut self,
+        loader: &L,
+        config: &Config,
+        crossfade_seconds: f32,
+    ) -> Result<Vec<EventChannel>, Error> {
+        let (channels, source) = loader.load_source_recursive(&config.root)?;
+        self.replace_root(source, crossfade_seconds)?;
+        Ok(channels)
+    }
+
+    // Play another graph alongside the current program and any other layers, such as UI
+    // sounds over music, until it is removed with remove_layer. Layers are unaffected by
+    // program changes and scheduled starts, but follow pause, resume and stop.
+    // Return an ID for the layer.
+    pub fn add_layer(
+        &mut self,
+        layer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Result<u64, Error> {
+        if self.layer_ids.len() >= super::layers::MAX_LAYERS {
+            return Err(Error::User(format!(
+                "Cannot play more than {} layers at once",
+                super::layers::MAX_LAYERS
+            )));
+        }
+        let layer_id = self.next_layer_id;
+        self.layer_commands
+            .try_send(super::layers::LayerCommand::Add(layer_id, layer))
+            .map_err(|_| Error::User("Too many layer changes are waiting".to_owned()))?;
+        self.next_layer_id += 1;
+        self.layer_ids.push(layer_id);
+        self.resume_stream()?;
+        Ok(layer_id)
+    }
+
+    // Stop playing a layer added with add_layer. It is dropped on a background thread rather
+    // than on the audio thread.
+    // Return whether a layer with that ID was playing.
+    pub fn remove_layer(&mut self, layer_id: u64) -> bool {
+        let Some(index) = self.layer_ids.iter().position(|id| *id == layer_id) else {
+            return false;
+        };
+        if self
+            .layer_commands
+            .try_send(super::layers::LayerCommand::Remove(layer_id))
+            .is_err()
+        {
+            return false;
+        }
+        self.layer_ids.remove(index);
+        true
+    }
+
+    // IDs of the layers currently playing, in the order they were added
+    pub fn layer_ids(&self) -> &[u64] {
+        &self.layer_ids
+    }
+
+    // Pause musical time across the whole graph. Nothing is rendered until resume is
+    // called, so sequences, envelopes and all other nodes continue exactly where they were.
+    pub fn pause(&self) {
+        self.shared.transport.pause();
+    }
+
+    pub fn resume(&self) {
+        self.shared.transport.resume();
+    }
+
+    // Silence the graph and rewind it to the start. Playback restarts on resume.
+    pub fn stop(&self) {
+        self.shared.transport.stop();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.transport.is_paused()
+    }
+
+    pub fn channel_layout(&self) -> ChannelLayout {
+        self.layout
+    }
+
+    // Set the gain of each output channel, in the order of the channel layout.
+    // This generalises left/right balance to layouts with any number of channels.
+    pub fn set_channel_gains(&self, gains: &[f32]) -> Result<(), Error> {
+        self.shared.channel_gains.set(gains)
+    }
+
+    // Remove any DC offset from the output with a high-pass filter below the audible range.
+    pub fn set_dc_blocking(&self, enabled: bool) {
+        self.shared.conditioning.set_dc_blocking(enabled);
+    }
+
+    // Have the audio thread treat denormal numbers as zero, so that tails decaying towards
+    // silence do not slow processing down.
+    pub fn set_flush_denormals(&self, enabled: bool) {
+        self.shared.conditioning.set_flush_denormals(enabled);
+    }
+
+    // Timing of the audio callback, measured against how long each rendered buffer plays for
+    pub fn render_stats(&self) -> RenderStats {
+        self.shared.render_stats.snapshot()
+    }
+
+    pub fn reset_render_stats(&self) {
+        self.shared.render_stats.reset();
+    }
+
     pub fn get_current_program_no(&self) -> Option<usize> {
         self.program_sources.iter().find_map(|(k, v)| match v {
             &ConsumerCell::Placeholder => Some(*k),
@@ -117,29 +480,281 @@ impl BaseMixer {
         })
     }
 
+    // Allow the stream to be paused once the output has stayed at or below the threshold
+    // for the given time. Events sent through any of the given channels will wake it up
+    // again, as will changing programs. Nothing happens unless update_auto_suspend is
+    // then polled from the host thread.
+    pub fn enable_auto_suspend(
+        &mut self,
+        threshold: f32,
+        idle_seconds: f32,
+        wake_channels: &[EventChannel],
+    ) {
+        self.shared.silence.configure(threshold, idle_seconds);
+        self.wake_senders = wake_channels
+            .iter()
+            .map(|channel| channel.sender.clone())
+            .collect();
+    }
+
+    pub fn disable_auto_suspend(&mut self) -> Result<(), Error> {
+        self.shared.silence.disable();
+        self.wake_senders.clear();
+        self.resume_stream()
+    }
+
+    // Must be called regularly from the host thread while auto-suspend is enabled, such as
+    // once per frame of a game loop. Pauses the stream if it has gone idle, or resumes it if events are waiting.
+    // Return whether the stream is suspended afterwards.
+    pub fn update_auto_suspend(&mut self) -> Result<bool, Error> {
+        if self.is_suspended {
+            let has_pending_events = self.wake_senders.iter().any(|sender| !sender.is_empty());
+            if has_pending_events {
+                self.resume_stream()?;
+            }
+        } else if self.shared.silence.is_idle() {
+            let stream = self.stream.lock().expect("Could not lock the audio stream");
+            if let Some(stream) = stream.as_ref() {
+                stream.pause()?;
+            }
+            self.is_suspended = true;
+        }
+        Ok(self.is_suspended)
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.is_suspended
+    }
+
+    pub fn resume_stream(&mut self) -> Result<(), Error> {
+        self.shared.silence.reset();
+        if !self.is_suspended {
+            return Ok(());
+        }
+        let stream = self.stream.lock().expect("Could not lock the audio stream");
+        if let Some(stream) = stream.as_ref() {
+            stream.play()?;
+        }
+        self.is_suspended = false;
+        Ok(())
+    }
+
+    // Whether the stream has reported an error, such as its device being disconnected.
+    // Audio stays stopped until recover_stream succeeds.
+    pub fn has_stream_failed(&self) -> bool {
+        self.shared.stream_failed.load(Ordering::Acquire)
+    }
+
+    // Should be called regularly from the host thread. If the stream has failed, reopen it
+    // on the originally chosen device if it is still present, else on the current default
+    // device. The graph carries on from where it was, so no playback state is lost. If the
+    // device has a different layout, the gains of front left and right are kept and those of
+    // other channels reset to unity. Return whether the stream was reopened; on an error the
+    // stream stays failed, and the next call tries again.
+    pub fn recover_stream(&mut self) -> Result<bool, Error> {
+        if !self.has_stream_failed() {
+            return Ok(false);
+        }
+        let mut stream = self.stream.lock().expect("Could not lock the audio stream");
+
+        // The old stream must be gone before a new callback may use the consumer
+        drop(stream.take());
+
+        let device_name = self
+            .device_name
+            .as_deref()
+            .filter(|name| Self::has_output_device(name));
+        let device = Self::find_output_device(device_name)?;
+        let layout = Self::negotiate_layout(&device);
+        let mut shared = self.shared.clone();
+        if layout != self.layout {
+            shared.channel_gains = Arc::new(self.shared.channel_gains.for_layout(layout));
+        }
+        let new_stream = Self::open_stream(
+            &device,
+            layout,
+            self.buffer_frames,
+            self.consumer.take_consumer(),
+            shared.clone(),
+        )?;
+        if !self.is_suspended {
+            new_stream.play()?;
+        }
+        *stream = Some(new_stream);
+        if layout != self.layout {
+            log_warning!(
+                "Stream",
+                "Output layout changed from {:?} to {:?}; gains of channels other than front left and right were reset",
+                self.layout,
+                layout
+            );
+            self.layout = layout;
+        }
+        self.shared = shared;
+
+        // Only now is the stream back, so a failed attempt leaves it to be tried again
+        self.shared.stream_failed.store(false, Ordering::Release);
+        Ok(true)
+    }
+
+    // Get the names of all output devices available on the default host
+    pub fn list_output_devices() -> Result<Vec<String>, Error> {
+        let host = cpal::default_host();
+        let names = host
+            .output_devices()?
+            .filter_map(|device| device.name().ok())
+            .collect();
+        Ok(names)
+    }
+
+    pub(crate) fn has_output_device(name: &str) -> bool {
+        Self::list_output_devices().is_ok_and(|names| names.iter().any(|n| n == name))
+    }
+
+    fn find_output_device(device_name: Option<&str>) -> Result<Device, Error> {
+        let host = cpal::default_host();
+        let Some(device_name) = device_name else {
+            return host.default_output_device().ok_or(Error::NoDevice);
+        };
+        host.output_devices()?
+            .find(|device| device.name().is_ok_and(|name| name == device_name))
+            .ok_or_else(|| Error::User(format!("No output device named {}", device_name)))
+    }
+
+    fn negotiate_layout(device: &Device) -> ChannelLayout {
+        device
+            .default_output_config()
+            .ok()
+            .and_then(|config| ChannelLayout::from_channel_count(config.channels() as usize))
+            .unwrap_or(ChannelLayout::Stereo)
+    }
+
     fn open_stream(
+        device: &Device,
+        layout: ChannelLayout,
+        buffer_frames: usize,
         consumer: Arc<AtomicPtr<Box<dyn BufferConsumerNode + Send + 'static>>>,
+        shared: StreamShared,
     ) -> Result<Stream, Error> {
-        let host = cpal::default_host();
-        let device = host.default_output_device().ok_or(Error::NoDevice)?;
+        let StreamShared {
+            silence,
+            clock,
+            transport,
+            channel_gains,
+            conditioning,
+            render_stats,
+            layers,
+            root_fader,
+            stream_failed,
+        } = shared;
+        let output_channels = layout.channel_count();
         let required_config = StreamConfig {
-            buffer_size: cpal::BufferSize::Fixed(consts::BUFFER_SIZE as u32),
-            channels: consts::CHANNEL_COUNT as u16,
+            buffer_size: cpal::BufferSize::Fixed(buffer_frames as u32),
+            channels: output_channels as u16,
             sample_rate: cpal::SampleRate(consts::PLAYBACK_SAMPLE_RATE as u32),
         };
+        let mut stereo_buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+        let mut device_channels = vec![0.0; consts::BUFFER_SIZE * output_channels];
+        let mut device_channels_written = false;
+        let mut dc_blocker = super::conditioning::DcBlocker::default();
         let stream = device.build_output_stream(
             &required_config,
             move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                data.fill(0.0);
+                #[cfg(feature = "alloc-audit")]
+                let _rendering = crate::audit::rendering_scope();
+                let timer = super::stats::RenderTimer::start();
+                clock.mark_callback(SystemTime::now());
+                let gains = channel_gains.load();
+                super::conditioning::set_flush_to_zero(conditioning.flush_denormals());
+                let is_dc_blocking = conditioning.dc_blocking();
+                let mut root_fader = root_fader.try_lock().ok();
+                if let Some(root_fader) = root_fader.as_mut() {
+                    root_fader.apply_replacements(&consumer);
+                }
                 let consumer_ptr = consumer.load(Ordering::SeqCst);
-                if !consumer_ptr.is_null() {
-                    unsafe {
-                        (*consumer_ptr).fill_buffer(data);
+                let mut layers = layers.try_lock().ok();
+                if let Some(layers) = layers.as_mut() {
+                    layers.apply_commands();
+                }
+                if transport.take_stop_request() {
+                    let stop = NodeEvent::Broadcast(BroadcastControl::Stop);
+                    if !consumer_ptr.is_null() {
+                        unsafe {
+                            (*consumer_ptr).on_event(&stop);
+                        }
+                    }
+                    if let Some(root_fader) = root_fader.as_mut() {
+                        root_fader.on_event(&stop);
+                    }
+                    if let Some(layers) = layers.as_mut() {
+                        layers.on_event(&stop);
+                    }
+                }
+                let is_paused = transport.is_paused();
+                for output in data.chunks_mut(consts::BUFFER_SIZE * output_channels) {
+                    let frames = output.len() / output_channels;
+                    let stereo = &mut stereo_buffer[0..frames * consts::CHANNEL_COUNT];
+                    stereo.fill(0.0);
+                    if device_channels_written {
+                        device_channels.fill(0.0);
+                    }
+                    super::layout::lend_device_channels(&mut device_channels, output_channels);
+                    if let Some(offset) = clock.advance(stereo.len()) {
+                        if !consumer_ptr.is_null() && !is_paused {
+                            let root = unsafe { &mut **consumer_ptr };
+                            let mut fill_root = |buffer: &mut [f32]| match root_fader.as_mut() {
+                                Some(root_fader) => root_fader.fill_buffer(root, buffer),
+                                None => root.fill_buffer(buffer),
+                            };
+
+                            // Render and discard frames to skip ahead after drifting behind
+                            let skip_frames = clock.take_skip_frames(frames);
+                            if skip_frames > 0 {
+                                super::layout::set_device_frame_offset(None);
+                                fill_root(&mut stereo[0..skip_frames * consts::CHANNEL_COUNT]);
+                                stereo.fill(0.0);
+                            }
+                            super::layout::set_device_frame_offset(Some(
+                                offset / consts::CHANNEL_COUNT,
+                            ));
+                            fill_root(&mut stereo[offset..]);
+                        }
+                    }
+                    if let (Some(layers), false) = (layers.as_mut(), is_paused) {
+                        super::layout::set_device_frame_offset(Some(0));
+                        layers.fill_buffer(stereo);
+                    }
+                    device_channels_written =
+                        super::layout::return_device_channels(&mut device_channels);
+                    if is_dc_blocking {
+                        dc_blocker.process(stereo);
+                    }
+                    let device_channels = device_channels_written
+                        .then(|| &device_channels[0..frames * output_channels]);
+                    silence.observe(stereo, device_channels);
+                    layout.write_from_stereo(stereo, output, &gains);
+                    if let Some(device_channels) = device_channels {
+                        layout.add_device_channels(device_channels, output, &gains);
+                    }
+                }
+                render_stats.record_gain_reduction(crate::take_gain_reduction());
+                if let Some(render_seconds) = timer.elapsed_seconds() {
+                    let frames = data.len() / output_channels;
+                    let buffer_seconds = frames as f32 / consts::PLAYBACK_SAMPLE_RATE as f32;
+                    if render_stats.record(render_seconds, buffer_seconds) {
+                        #[cfg(feature = "tracing")]
+                        log_warning!(
+                            "Stream",
+                            "Rendering {} frames took {} s, risking an underrun",
+                            frames,
+                            render_seconds
+                        );
                     }
                 }
             },
             move |err| {
-                println!("ERROR: Stream: {:?}", err);
+                log_error!("Stream", "{:?}", err);
+                stream_failed.store(true, Ordering::Release);
             },
             None,
         )?;
@@ -0,0 +1,143 @@
+use crate::{
+    consts, BaseMixer, BufferConsumerNode, ChannelLayout, Config, Error, EventChannel,
+    FileGraphLoader, GraphLoader, NodeControlEvent, NodeNames, NullSource,
+};
+use std::ops::{Deref, DerefMut};
+
+enum GraphInput {
+    Empty,
+    ConfigPath(String),
+    Config(Box<Config>),
+    Root(Box<dyn BufferConsumerNode + Send + 'static>),
+}
+
+/// Loads a graph and starts playing it in one call, in place of reading a config, building
+/// the graph and starting a BaseMixer separately. The graph may be given as the path of a
+/// config file, a Config or an already built root; configs are loaded by FileGraphLoader.
+pub struct BaseMixerBuilder {
+    graph: GraphInput,
+    device_name: Option<String>,
+    layout: Option<ChannelLayout>,
+    buffer_frames: usize,
+}
+
+impl Default for BaseMixerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BaseMixerBuilder {
+    pub fn new() -> Self {
+        Self {
+            graph: GraphInput::Empty,
+            device_name: None,
+            layout: None,
+            buffer_frames: consts::BUFFER_SIZE,
+        }
+    }
+
+    /// Play the config in a file, read as by FileGraphLoader::config_from_file.
+    pub fn config_path(mut self, path: &str) -> Self {
+        self.graph = GraphInput::ConfigPath(path.to_owned());
+        self
+    }
+
+    pub fn config(mut self, config: Config) -> Self {
+        self.graph = GraphInput::Config(Box::new(config));
+        self
+    }
+
+    pub fn root(mut self, root: Box<dyn BufferConsumerNode + Send + 'static>) -> Self {
+        self.graph = GraphInput::Root(root);
+        self
+    }
+
+    /// Play on the output device with the given name, as listed by
+    /// BaseMixer::list_output_devices. Otherwise the config's output device is used if it
+    /// is present, else the default device.
+    pub fn device(mut self, name: &str) -> Self {
+        self.device_name = Some(name.to_owned());
+        self
+    }
+
+    pub fn layout(mut self, layout: ChannelLayout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    /// Ask the device for this many frames per callback, trading latency against the risk
+    /// of underruns. The graph is still rendered at consts::PLAYBACK_SAMPLE_RATE, in blocks
+    /// of at most consts::BUFFER_SIZE frames.
+    pub fn buffer_frames(mut self, frames: usize) -> Self {
+        self.buffer_frames = frames;
+        self
+    }
+
+    pub fn start(self) -> Result<RunningStream, Error> {
+        let (config, root) = match self.graph {
+            GraphInput::Empty => (None, None),
+            GraphInput::ConfigPath(path) => (
+                Some(Box::new(FileGraphLoader.config_from_file(&path)?)),
+                None,
+            ),
+            GraphInput::Config(config) => (Some(config), None),
+            GraphInput::Root(root) => (None, Some(root)),
+        };
+        let (channels, names, root) = match (&config, root) {
+            (Some(config), _) => FileGraphLoader.load_source_with_names(&config.root)?,
+            (None, Some(root)) => (vec![], NodeNames::default(), root),
+            (None, None) => {
+                let root: Box<dyn BufferConsumerNode + Send + 'static> =
+                    Box::new(NullSource::new(None));
+                (vec![], NodeNames::default(), root)
+            }
+        };
+        let device_name = self.device_name.or_else(|| {
+            config
+                .and_then(|config| config.output_device)
+                .filter(|name| BaseMixer::has_output_device(name))
+        });
+        let mixer = BaseMixer::start_with_options(
+            root,
+            device_name.as_deref(),
+            self.layout,
+            self.buffer_frames,
+        )?;
+        Ok(RunningStream {
+            mixer,
+            channels,
+            names,
+        })
+    }
+}
+
+/// A mixer started by BaseMixerBuilder, along with the event channels of its graph and the
+/// node IDs of its Named sources. Dereferences to the mixer, for pause, resume, stop and
+/// everything else it does.
+pub struct RunningStream {
+    pub mixer: BaseMixer,
+    pub channels: Vec<EventChannel>,
+    pub names: NodeNames,
+}
+
+impl RunningStream {
+    /// Send a control event to the Named source with the given name.
+    pub fn send(&self, name: &str, event: NodeControlEvent) -> Result<(), Error> {
+        self.names.send(&self.channels, name, event)
+    }
+}
+
+impl Deref for RunningStream {
+    type Target = BaseMixer;
+
+    fn deref(&self) -> &BaseMixer {
+        &self.mixer
+    }
+}
+
+impl DerefMut for RunningStream {
+    fn deref_mut(&mut self) -> &mut BaseMixer {
+        &mut self.mixer
+    }
+}
@@ -0,0 +1,117 @@
+use super::sync::micros_since_epoch;
+use crate::consts;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+/// Counts frames rendered by the audio callback, and holds back the program until
+/// a scheduled frame so that playback can start at an exact point in the stream. Once
+/// playing, the program can be held back or skipped ahead by a number of frames, to keep
+/// it in step with a wall clock.
+#[derive(Default)]
+pub struct StreamClock {
+    frames_rendered: AtomicU64,
+    start_frame: AtomicU64,
+    callback_frame: AtomicU64,
+    callback_micros: AtomicI64,
+    pending_adjustment: AtomicI64,
+    total_adjustment: AtomicI64,
+}
+
+impl StreamClock {
+    pub fn frames_rendered(&self) -> u64 {
+        self.frames_rendered.load(Ordering::Acquire)
+    }
+
+    pub fn schedule_start(&self, stream_frame: u64) {
+        self.start_frame.store(stream_frame, Ordering::Release);
+    }
+
+    // Called from the audio callback as it begins, so that the stream's position can be
+    // estimated between callbacks
+    pub fn mark_callback(&self, now: SystemTime) {
+        self.callback_frame
+            .store(self.frames_rendered(), Ordering::Release);
+        self.callback_micros
+            .store(micros_since_epoch(now), Ordering::Release);
+    }
+
+    // The frame being rendered at the given time, from the last callback and the time since
+    pub fn estimated_frame(&self, now: SystemTime) -> u64 {
+        let callback_frame = self.callback_frame.load(Ordering::Acquire);
+        let since_callback = micros_since_epoch(now) - self.callback_micros.load(Ordering::Acquire);
+        let frames_since =
+            since_callback.max(0) as f64 * consts::PLAYBACK_SAMPLE_RATE as f64 / 1_000_000.0;
+        callback_frame + frames_since as u64
+    }
+
+    // Hold the program back by a number of frames, or skip it ahead if negative
+    pub fn adjust(&self, frames: i64) {
+        self.pending_adjustment.fetch_add(frames, Ordering::AcqRel);
+        self.total_adjustment.fetch_add(frames, Ordering::AcqRel);
+    }
+
+    // Compare how far the program has played since the start frame with how long it is
+    // since the start time, and hold it back or skip it ahead by the difference if that is
+    // beyond the tolerance. Return the adjustment made.
+    pub fn correct_drift(
+        &self,
+        now: SystemTime,
+        start: SystemTime,
+        start_frame: u64,
+        tolerance: Duration,
+    ) -> i64 {
+        let Ok(since_start) = now.duration_since(start) else {
+            return 0;
+        };
+        let expected = (since_start.as_secs_f64() * consts::PLAYBACK_SAMPLE_RATE as f64) as i64;
+        let played = self.estimated_frame(now) as i64
+            - start_frame as i64
+            - self.total_adjustment.load(Ordering::Acquire);
+        let drift = played - expected;
+        let tolerance_frames =
+            (tolerance.as_secs_f64() * consts::PLAYBACK_SAMPLE_RATE as f64) as i64;
+        if drift.abs() <= tolerance_frames {
+            return 0;
+        }
+        self.adjust(drift);
+        drift
+    }
+
+    // Called from the audio callback for each buffer. Returns the offset into the buffer,
+    // in data points, from which the program should be filled, or None to skip it entirely.
+    pub fn advance(&self, data_points: usize) -> Option<usize> {
+        let frames = (data_points / consts::CHANNEL_COUNT) as u64;
+        let buffer_start = self.frames_rendered.fetch_add(frames, Ordering::AcqRel);
+        let start_frame = self.start_frame.load(Ordering::Acquire);
+        if start_frame >= buffer_start + frames {
+            return None;
+        }
+        let mut offset_frames = start_frame.saturating_sub(buffer_start);
+
+        // Hold back for as much of the rest of the buffer as is owed
+        let pending = self.pending_adjustment.load(Ordering::Acquire);
+        if pending > 0 {
+            let held = (pending as u64).min(frames - offset_frames);
+            self.pending_adjustment
+                .fetch_sub(held as i64, Ordering::AcqRel);
+            offset_frames += held;
+            if offset_frames == frames {
+                return None;
+            }
+        }
+        Some(offset_frames as usize * consts::CHANNEL_COUNT)
+    }
+
+    // Called from the audio callback before filling the program. Returns how many frames,
+    // up to the maximum, should be rendered and thrown away to skip it ahead.
+    pub fn take_skip_frames(&self, max_frames: usize) -> usize {
+        let pending = self.pending_adjustment.load(Ordering::Acquire);
+        if pending >= 0 {
+            return 0;
+        }
+        let skipped = (pending.unsigned_abs() as usize).min(max_frames);
+        self.pending_adjustment
+            .fetch_add(skipped as i64, Ordering::AcqRel);
+        skipped
+    }
+}
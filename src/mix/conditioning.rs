@@ -0,0 +1,114 @@
+use crate::consts;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Pole of the DC-blocking filter, putting its cutoff at about 7.6 Hz
+const DC_BLOCKER_POLE: f32 = 0.999;
+
+// Filter state below this is flushed to zero rather than decaying through denormals
+const STATE_FLOOR: f32 = 1.0e-20;
+
+/// Output stage options shared between the mixer and the audio callback, both off by
+/// default: removing DC offset from the output, and having the audio thread's processor
+/// treat denormal numbers as zero so that decaying tails do not spike CPU use.
+#[derive(Default)]
+pub struct OutputConditioning {
+    dc_blocking: AtomicBool,
+    flush_denormals: AtomicBool,
+}
+
+impl OutputConditioning {
+    pub fn set_dc_blocking(&self, enabled: bool) {
+        self.dc_blocking.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn set_flush_denormals(&self, enabled: bool) {
+        self.flush_denormals.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn dc_blocking(&self) -> bool {
+        self.dc_blocking.load(Ordering::Relaxed)
+    }
+
+    pub fn flush_denormals(&self) -> bool {
+        self.flush_denormals.load(Ordering::Relaxed)
+    }
+}
+
+/// First-order high-pass filter removing DC offset from interleaved stereo.
+#[derive(Default)]
+pub struct DcBlocker {
+    previous_input: [f32; consts::CHANNEL_COUNT],
+    previous_output: [f32; consts::CHANNEL_COUNT],
+}
+
+impl DcBlocker {
+    pub fn process(&mut self, buffer: &mut [f32]) {
+        for frame in buffer.chunks_exact_mut(consts::CHANNEL_COUNT) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let input = *sample;
+                let mut output = input - self.previous_input[channel]
+                    + DC_BLOCKER_POLE * self.previous_output[channel];
+                if output.abs() < STATE_FLOOR {
+                    output = 0.0;
+                }
+                self.previous_input[channel] = input;
+                self.previous_output[channel] = output;
+                *sample = output;
+            }
+        }
+    }
+}
+
+/// Set whether the calling thread flushes denormal results to zero and treats denormal
+/// inputs as zero. Has no effect on targets without such a mode.
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse"
+))]
+pub fn set_flush_to_zero(enabled: bool) {
+    // Flush-to-zero and denormals-are-zero bits of MXCSR
+    const FTZ_DAZ: u32 = 0x8040;
+    let mut mxcsr: u32 = 0;
+    unsafe {
+        std::arch::asm!("stmxcsr [{}]", in(reg) &mut mxcsr, options(nostack));
+    }
+    let updated = if enabled {
+        mxcsr | FTZ_DAZ
+    } else {
+        mxcsr & !FTZ_DAZ
+    };
+    if updated != mxcsr {
+        unsafe {
+            std::arch::asm!("ldmxcsr [{}]", in(reg) &updated, options(nostack));
+        }
+    }
+}
+
+/// Set whether the calling thread flushes denormal results to zero and treats denormal
+/// inputs as zero. Has no effect on targets without such a mode.
+#[cfg(target_arch = "aarch64")]
+pub fn set_flush_to_zero(enabled: bool) {
+    // Flush-to-zero bit of FPCR
+    const FZ: u64 = 1 << 24;
+    let fpcr: u64;
+    unsafe {
+        std::arch::asm!("mrs {}, fpcr", out(reg) fpcr, options(nomem, nostack));
+    }
+    let updated = if enabled { fpcr | FZ } else { fpcr & !FZ };
+    if updated != fpcr {
+        unsafe {
+            std::arch::asm!("msr fpcr, {}", in(reg) updated, options(nomem, nostack));
+        }
+    }
+}
+
+/// Set whether the calling thread flushes denormal results to zero and treats denormal
+/// inputs as zero. Has no effect on targets without such a mode.
+#[cfg(not(any(
+    all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        target_feature = "sse"
+    ),
+    target_arch = "aarch64"
+)))]
+pub fn set_flush_to_zero(_enabled: bool) {}
@@ -0,0 +1,53 @@
+use crate::{mix::root_swap::RootBox, BufferConsumerNode, Error};
+use crossbeam_channel::{bounded, Sender, TrySendError};
+
+// Retired graphs that may wait to be dropped at once before the audio thread has to drop
+// them itself
+const GARBAGE_CAPACITY: usize = 64;
+
+/// Graphs retired by the audio thread, such as removed layers and replaced roots. They are
+/// only held until they are dropped.
+pub enum Garbage {
+    Node(#[allow(dead_code)] Box<dyn BufferConsumerNode + Send + 'static>),
+    Root(#[allow(dead_code)] RootBox),
+}
+
+/// Sends retired graphs to a background thread to be dropped, so that freeing large graphs,
+/// such as those holding SoundFont sample data, does not hold up the audio thread.
+#[derive(Clone)]
+pub struct GarbageSender {
+    sender: Sender<Garbage>,
+}
+
+impl GarbageSender {
+    /// Start a thread that drops everything sent to it, running until every sender is gone.
+    pub fn spawn_collector() -> Result<Self, Error> {
+        let (sender, receiver) = bounded(GARBAGE_CAPACITY);
+        std::thread::Builder::new()
+            .name("midi-graph-garbage".to_owned())
+            .spawn(move || {
+                for garbage in receiver.iter() {
+                    drop(garbage);
+                }
+            })?;
+        Ok(Self::with_sender(sender))
+    }
+
+    /// Send retired graphs through a channel of the caller's own, to be dropped where the
+    /// receiver chooses.
+    pub fn with_sender(sender: Sender<Garbage>) -> Self {
+        Self { sender }
+    }
+
+    // Never blocks, so may be called from the audio callback
+    pub fn retire(&self, garbage: Garbage) {
+        // Dropped here only if the collector has fallen far behind or has gone
+        if let Err(TrySendError::Full(garbage) | TrySendError::Disconnected(garbage)) =
+            self.sender.try_send(garbage)
+        {
+            #[cfg(feature = "tracing")]
+            log_warning!("Stream", "Dropping a retired graph on the audio thread");
+            drop(garbage);
+        }
+    }
+}
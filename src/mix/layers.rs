@@ -0,0 +1,66 @@
+use crate::{
+    mix::garbage::{Garbage, GarbageSender},
+    BufferConsumerNode, NodeEvent,
+};
+use crossbeam_channel::Receiver;
+
+// Most layers that may play at once, so that the audio callback never grows its list
+pub const MAX_LAYERS: usize = 16;
+
+pub enum LayerCommand {
+    Add(u64, Box<dyn BufferConsumerNode + Send + 'static>),
+    Remove(u64),
+}
+
+/// Graphs playing alongside the mixer's program, each independent of the others and of the
+/// program, such as UI sounds over music. Owned by the audio callback, which applies the
+/// commands sent by the mixer at the start of each callback and sends removed layers to be
+/// dropped off the audio thread.
+pub struct LayerSet {
+    layers: Vec<(u64, Box<dyn BufferConsumerNode + Send + 'static>)>,
+    commands: Receiver<LayerCommand>,
+    garbage: GarbageSender,
+}
+
+impl LayerSet {
+    pub fn new(commands: Receiver<LayerCommand>, garbage: GarbageSender) -> Self {
+        Self {
+            layers: Vec::with_capacity(MAX_LAYERS),
+            commands,
+            garbage,
+        }
+    }
+
+    // Called from the audio callback
+    pub fn apply_commands(&mut self) {
+        while let Ok(command) = self.commands.try_recv() {
+            match command {
+                LayerCommand::Add(layer_id, layer) => {
+                    if self.layers.len() < MAX_LAYERS {
+                        self.layers.push((layer_id, layer));
+                    } else {
+                        self.garbage.retire(Garbage::Node(layer));
+                    }
+                }
+                LayerCommand::Remove(layer_id) => {
+                    if let Some(index) = self.layers.iter().position(|(id, _)| *id == layer_id) {
+                        let (_, layer) = self.layers.swap_remove(index);
+                        self.garbage.retire(Garbage::Node(layer));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn on_event(&mut self, event: &NodeEvent) {
+        for (_, layer) in self.layers.iter_mut() {
+            layer.on_event(event);
+        }
+    }
+
+    pub fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        for (_, layer) in self.layers.iter_mut() {
+            layer.fill_buffer(buffer);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use crate::consts;
+#[cfg(feature = "driver-cpal")]
+use crate::Error;
+use std::cell::RefCell;
+#[cfg(feature = "driver-cpal")]
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[cfg(feature = "driver-cpal")]
+const MAX_OUTPUT_CHANNELS: usize = 6;
+
+thread_local! {
+    // Device channels lent by the audio callback running on this thread while it renders
+    static DEVICE_CHANNELS: RefCell<DeviceChannels> = const { RefCell::new(DeviceChannels::new()) };
+}
+
+/// Speaker layout of the output device. Graphs always render in stereo; the mixer maps
+/// that onto the device's channels, in the usual interleaving order for each layout:
+/// - Mono: the average of left and right
+/// - Stereo: left, right
+/// - Quad: front left, front right, rear left, rear right (rear repeats front)
+/// - Surround51: front left, front right, centre, LFE, surround left, surround right,
+///   with the centre taking the average of left and right and the LFE left silent
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChannelLayout {
+    Mono,
+    Stereo,
+    Quad,
+    Surround51,
+}
+
+impl ChannelLayout {
+    pub fn channel_count(&self) -> usize {
+        match self {
+            ChannelLayout::Mono => 1,
+            ChannelLayout::Stereo => 2,
+            ChannelLayout::Quad => 4,
+            ChannelLayout::Surround51 => 6,
+        }
+    }
+
+    pub fn from_channel_count(channels: usize) -> Option<Self> {
+        match channels {
+            1 => Some(ChannelLayout::Mono),
+            2 => Some(ChannelLayout::Stereo),
+            4 => Some(ChannelLayout::Quad),
+            6 => Some(ChannelLayout::Surround51),
+            _ => None,
+        }
+    }
+
+    // Write interleaved stereo frames into the device buffer, applying per-channel gains
+    pub fn write_from_stereo(&self, stereo: &[f32], output: &mut [f32], gains: &[f32]) {
+        let channels = self.channel_count();
+        for (input, frame) in stereo
+            .chunks_exact(consts::CHANNEL_COUNT)
+            .zip(output.chunks_exact_mut(channels))
+        {
+            let (left, right) = (input[0], input[1]);
+            let middle = 0.5 * (left + right);
+            match self {
+                ChannelLayout::Mono => {
+                    frame[0] = middle * gains[0];
+                }
+                ChannelLayout::Stereo => {
+                    frame[0] = left * gains[0];
+                    frame[1] = right * gains[1];
+                }
+                ChannelLayout::Quad => {
+                    frame[0] = left * gains[0];
+                    frame[1] = right * gains[1];
+                    frame[2] = left * gains[2];
+                    frame[3] = right * gains[3];
+                }
+                ChannelLayout::Surround51 => {
+                    frame[0] = left * gains[0];
+                    frame[1] = right * gains[1];
+                    frame[2] = middle * gains[2];
+                    frame[3] = 0.0;
+                    frame[4] = left * gains[4];
+                    frame[5] = right * gains[5];
+                }
+            }
+        }
+    }
+
+    // Add frames already in this layout into the device buffer, applying per-channel gains,
+    // for signals rendered to particular speakers rather than mapped from stereo
+    pub fn add_device_channels(&self, device_channels: &[f32], output: &mut [f32], gains: &[f32]) {
+        let channels = self.channel_count();
+        for (input, frame) in device_channels
+            .chunks_exact(channels)
+            .zip(output.chunks_exact_mut(channels))
+        {
+            for ((output, sample), gain) in frame.iter_mut().zip(input).zip(gains) {
+                *output += sample * gain;
+            }
+        }
+    }
+}
+
+/// Interleaved frames in the output device's own channel layout, lent by the audio callback
+/// while it renders, for nodes that address one speaker directly rather than the graph's
+/// stereo. The callback adds them into the device's output after mapping the stereo.
+pub(crate) struct DeviceChannels {
+    samples: Vec<f32>,
+    channel_count: usize,
+    frame_offset: Option<usize>,
+    is_written: bool,
+}
+
+impl DeviceChannels {
+    const fn new() -> Self {
+        Self {
+            samples: Vec::new(),
+            channel_count: 0,
+            frame_offset: None,
+            is_written: false,
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    // Add a sample to one channel, counting frames from the start of the buffer being filled
+    pub fn add(&mut self, frame: usize, channel: usize, sample: f32) {
+        let Some(frame_offset) = self.frame_offset else {
+            return;
+        };
+        if channel >= self.channel_count {
+            return;
+        }
+        let index = (frame_offset + frame) * self.channel_count + channel;
+        if let Some(output) = self.samples.get_mut(index) {
+            *output += sample;
+            self.is_written = true;
+        }
+    }
+}
+
+// Run a closure with the device channels of the buffer being rendered, or with None when
+// not rendering inside a mixer's audio callback, such as on a parallel Combiner's workers
+pub(crate) fn with_device_channels<R>(f: impl FnOnce(Option<&mut DeviceChannels>) -> R) -> R {
+    DEVICE_CHANNELS.with(|channels| match channels.try_borrow_mut() {
+        Ok(mut channels) if channels.channel_count > 0 && channels.frame_offset.is_some() => {
+            f(Some(&mut channels))
+        }
+        _ => f(None),
+    })
+}
+
+// Lend the callback's buffer of device channels to nodes rendering on this thread. The
+// buffer must already be cleared; nodes' frames start at its first frame until moved.
+#[cfg(feature = "driver-cpal")]
+pub(crate) fn lend_device_channels(samples: &mut Vec<f32>, channel_count: usize) {
+    DEVICE_CHANNELS.with(|channels| {
+        let mut channels = channels.borrow_mut();
+        std::mem::swap(&mut channels.samples, samples);
+        channels.channel_count = channel_count;
+        channels.frame_offset = Some(0);
+        channels.is_written = false;
+    });
+}
+
+// Set the frame of the lent buffer at which the next node's buffer starts, or None to hide
+// it from nodes whose output is about to be discarded
+#[cfg(feature = "driver-cpal")]
+pub(crate) fn set_device_frame_offset(frame_offset: Option<usize>) {
+    DEVICE_CHANNELS.with(|channels| channels.borrow_mut().frame_offset = frame_offset);
+}
+
+// Take back the lent buffer, returning whether any node wrote to it
+#[cfg(feature = "driver-cpal")]
+pub(crate) fn return_device_channels(samples: &mut Vec<f32>) -> bool {
+    DEVICE_CHANNELS.with(|channels| {
+        let mut channels = channels.borrow_mut();
+        std::mem::swap(&mut channels.samples, samples);
+        channels.channel_count = 0;
+        channels.frame_offset = None;
+        channels.is_written
+    })
+}
+
+/// Gain for each output channel, shared between the mixer and the audio callback.
+#[cfg(feature = "driver-cpal")]
+pub struct ChannelGains {
+    gain_bits: [AtomicU32; MAX_OUTPUT_CHANNELS],
+    channel_count: usize,
+}
+
+#[cfg(feature = "driver-cpal")]
+impl ChannelGains {
+    pub fn new(layout: ChannelLayout) -> Self {
+        Self {
+            gain_bits: std::array::from_fn(|_| AtomicU32::new(1.0f32.to_bits())),
+            channel_count: layout.channel_count(),
+        }
+    }
+
+    pub fn set(&self, gains: &[f32]) -> Result<(), Error> {
+        if gains.len() != self.channel_count {
+            return Err(Error::User(format!(
+                "Expected {} channel gains but got {}",
+                self.channel_count,
+                gains.len()
+            )));
+        }
+        for (bits, gain) in self.gain_bits.iter().zip(gains.iter()) {
+            bits.store(gain.to_bits(), Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    // Gains for another layout, keeping those of front left and right when both layouts
+    // have them, and starting every other channel at unity
+    pub fn for_layout(&self, layout: ChannelLayout) -> Self {
+        let gains = Self::new(layout);
+        if self.channel_count >= 2 && gains.channel_count >= 2 {
+            for index in 0..2 {
+                gains.gain_bits[index].store(
+                    self.gain_bits[index].load(Ordering::Relaxed),
+                    Ordering::Relaxed,
+                );
+            }
+        }
+        gains
+    }
+
+    pub fn load(&self) -> [f32; MAX_OUTPUT_CHANNELS] {
+        std::array::from_fn(|index| f32::from_bits(self.gain_bits[index].load(Ordering::Relaxed)))
+    }
+}
@@ -1,2 +1,25 @@
+#[cfg(feature = "driver-cpal")]
 pub mod base;
+#[cfg(feature = "driver-cpal")]
+pub mod builder;
+#[cfg(feature = "driver-cpal")]
+pub mod clock;
+#[cfg(feature = "driver-cpal")]
+pub mod conditioning;
+#[cfg(feature = "driver-cpal")]
+pub mod garbage;
+#[cfg(feature = "driver-cpal")]
+pub mod layers;
+pub mod layout;
+#[cfg(feature = "rodio")]
+pub mod rodio_source;
+#[cfg(feature = "driver-cpal")]
+pub mod root_swap;
+#[cfg(feature = "driver-cpal")]
+pub mod silence;
+pub mod stats;
+#[cfg(feature = "driver-cpal")]
 pub mod swap;
+pub mod sync;
+#[cfg(feature = "driver-cpal")]
+pub mod transport;
@@ -0,0 +1,60 @@
+use crate::{consts, BufferConsumerNode};
+use std::time::Duration;
+
+/// Plays a graph through a rodio output that the application already owns, instead of
+/// having BaseMixer open a stream. The graph is rendered one buffer at a time as rodio
+/// pulls samples from it, and plays indefinitely.
+pub struct GraphSource {
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl GraphSource {
+    pub fn new(consumer: Box<dyn BufferConsumerNode + Send + 'static>) -> Self {
+        let buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+        let position = buffer.len();
+        Self {
+            consumer,
+            buffer,
+            position,
+        }
+    }
+}
+
+impl Iterator for GraphSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position == self.buffer.len() {
+            self.buffer.fill(0.0);
+            self.consumer.fill_buffer(&mut self.buffer);
+            self.position = 0;
+        }
+        let sample = self.buffer[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl rodio::Source for GraphSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        // A new buffer is rendered when the current one runs out
+        match self.buffer.len() - self.position {
+            0 => Some(self.buffer.len()),
+            remaining => Some(remaining),
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        consts::CHANNEL_COUNT as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        consts::PLAYBACK_SAMPLE_RATE as u32
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{
+    consts,
+    mix::garbage::{Garbage, GarbageSender},
+    source::crossfade::equal_power_gains,
+    BufferConsumerNode, NodeEvent,
+};
+use crossbeam_channel::Receiver;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+// Roots are boxed twice so that the callback can hand them to the stream's consumer pointer
+// without allocating
+pub type RootBox = Box<Box<dyn BufferConsumerNode + Send + 'static>>;
+
+pub struct RootReplacement {
+    pub root: RootBox,
+    pub fade_frames: usize,
+}
+
+/// Replaces the root playing in a stream, cross-fading from the old root to the new one.
+/// Owned by the audio callback, which takes each replacement sent by the mixer at the start
+/// of a callback and swaps it into the stream's consumer pointer. The old root keeps
+/// playing while it fades out, after which it is sent to be dropped off the audio thread.
+/// A replacement arriving during a fade cuts the fading root off and fades out the root
+/// that was fading in.
+pub struct RootFader {
+    replacements: Receiver<RootReplacement>,
+    garbage: GarbageSender,
+    outgoing: Option<RootBox>,
+    fade_frames: usize,
+    progress_frames: usize,
+    intermediate_buffer: Vec<f32>,
+}
+
+impl RootFader {
+    pub fn new(replacements: Receiver<RootReplacement>, garbage: GarbageSender) -> Self {
+        Self {
+            replacements,
+            garbage,
+            outgoing: None,
+            fade_frames: 0,
+            progress_frames: 0,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        }
+    }
+
+    // Called from the audio callback, before it loads the consumer pointer
+    pub fn apply_replacements(
+        &mut self,
+        consumer: &AtomicPtr<Box<dyn BufferConsumerNode + Send + 'static>>,
+    ) {
+        while let Ok(RootReplacement { root, fade_frames }) = self.replacements.try_recv() {
+            let old_ptr = consumer.swap(Box::into_raw(root), Ordering::SeqCst);
+            if let Some(outgoing) = self.outgoing.take() {
+                self.retire(outgoing);
+            }
+            if !old_ptr.is_null() {
+                let old_root = unsafe { Box::from_raw(old_ptr) };
+                match fade_frames {
+                    0 => self.retire(old_root),
+                    _ => self.outgoing = Some(old_root),
+                }
+            }
+            self.fade_frames = fade_frames;
+            self.progress_frames = 0;
+        }
+    }
+
+    fn retire(&self, root: RootBox) {
+        self.garbage.retire(Garbage::Root(root));
+    }
+
+    pub fn on_event(&mut self, event: &NodeEvent) {
+        if let Some(outgoing) = self.outgoing.as_mut() {
+            outgoing.on_event(event);
+        }
+    }
+
+    /// Fill the current root into the buffer, along with the outgoing root while a fade is
+    /// in progress.
+    pub fn fill_buffer(
+        &mut self,
+        root: &mut (dyn BufferConsumerNode + Send + 'static),
+        buffer: &mut [f32],
+    ) {
+        let Some(outgoing) = self.outgoing.as_mut() else {
+            root.fill_buffer(buffer);
+            return;
+        };
+        let buffer_size = buffer.len();
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+
+        // Currently only-supported channel configuration
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        let outgoing: &mut (dyn BufferConsumerNode + Send + 'static) = &mut ***outgoing;
+        for (index, consumer) in [outgoing, root].into_iter().enumerate() {
+            intermediate_slice.fill(0.0);
+            consumer.fill_buffer(intermediate_slice);
+            for frame_index in 0..buffer_size / consts::CHANNEL_COUNT {
+                let frame = (self.progress_frames + frame_index).min(self.fade_frames);
+                let gains = equal_power_gains(frame as f32 / self.fade_frames as f32);
+                let gain = match index {
+                    0 => gains.0,
+                    _ => gains.1,
+                };
+                let i = frame_index * consts::CHANNEL_COUNT;
+                buffer[i] += gain * intermediate_slice[i];
+                buffer[i + 1] += gain * intermediate_slice[i + 1];
+            }
+        }
+        self.progress_frames += buffer_size / consts::CHANNEL_COUNT;
+        if self.progress_frames >= self.fade_frames {
+            if let Some(outgoing) = self.outgoing.take() {
+                self.retire(outgoing);
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{consts, source::buffer};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// Shared between the audio callback and the mixer, tracking how long the output
+/// has stayed below a threshold. Disabled while the required idle time is zero.
+pub struct SilenceMonitor {
+    threshold_bits: AtomicU32,
+    idle_frames_required: AtomicUsize,
+    silent_frames: AtomicUsize,
+}
+
+impl Default for SilenceMonitor {
+    fn default() -> Self {
+        Self {
+            threshold_bits: AtomicU32::new(0.0f32.to_bits()),
+            idle_frames_required: AtomicUsize::new(0),
+            silent_frames: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl SilenceMonitor {
+    pub fn configure(&self, threshold: f32, idle_seconds: f32) {
+        let idle_frames = (idle_seconds * consts::PLAYBACK_SAMPLE_RATE as f32) as usize;
+        self.threshold_bits
+            .store(threshold.abs().to_bits(), Ordering::Relaxed);
+        self.idle_frames_required
+            .store(idle_frames, Ordering::Relaxed);
+        self.reset();
+    }
+
+    pub fn disable(&self) {
+        self.idle_frames_required.store(0, Ordering::Relaxed);
+        self.reset();
+    }
+
+    pub fn reset(&self) {
+        self.silent_frames.store(0, Ordering::Relaxed);
+    }
+
+    // Called from the audio callback with the buffer just rendered, and with whatever nodes
+    // wrote to the device's channels directly
+    pub fn observe(&self, data: &[f32], device_channels: Option<&[f32]>) {
+        if self.idle_frames_required.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let threshold = f32::from_bits(self.threshold_bits.load(Ordering::Relaxed));
+        let is_silent = buffer::peak_of(data) <= threshold
+            && device_channels.is_none_or(|samples| buffer::peak_of(samples) <= threshold);
+        if is_silent {
+            let frames = data.len() / consts::CHANNEL_COUNT;
+            self.silent_frames.fetch_add(frames, Ordering::Relaxed);
+        } else {
+            self.silent_frames.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        let required = self.idle_frames_required.load(Ordering::Relaxed);
+        required > 0 && self.silent_frames.load(Ordering::Relaxed) >= required
+    }
+}
@@ -0,0 +1,137 @@
+#[cfg(feature = "driver-cpal")]
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+// Weight of the latest callback in the rolling load, averaging over roughly 20 callbacks
+#[cfg(feature = "driver-cpal")]
+const LOAD_SMOOTHING: f32 = 0.05;
+
+// Measures the time taken by one callback
+#[cfg(feature = "driver-cpal")]
+pub struct RenderTimer {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+    #[cfg(target_arch = "wasm32")]
+    start_millis: Option<f64>,
+}
+
+#[cfg(feature = "driver-cpal")]
+impl RenderTimer {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start() -> Self {
+        Self {
+            start: std::time::Instant::now(),
+        }
+    }
+
+    // Web Audio callbacks run on the main thread, where the window's clock is available
+    #[cfg(target_arch = "wasm32")]
+    pub fn start() -> Self {
+        Self {
+            start_millis: Self::now_millis(),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn now_millis() -> Option<f64> {
+        web_sys::window()?
+            .performance()
+            .map(|performance| performance.now())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn elapsed_seconds(&self) -> Option<f32> {
+        Some(self.start.elapsed().as_secs_f32())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn elapsed_seconds(&self) -> Option<f32> {
+        let start_millis = self.start_millis?;
+        let now_millis = Self::now_millis()?;
+        Some(((now_millis - start_millis) / 1000.0) as f32)
+    }
+}
+
+/// Timing of the audio callback at one moment, as returned by BaseMixer::render_stats.
+/// Load is the time spent rendering as a percentage of the time the rendered audio plays
+/// for, averaged over recent callbacks; anything near 100 will soon cause underruns.
+/// Gain reduction is the most that any dynamics node turned its source down, in decibels,
+/// during the last callback; nodes rendered on a parallel Combiner's workers may not be seen.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    pub load_percent: f32,
+    pub worst_render_seconds: f32,
+    pub underruns: u64,
+    pub callbacks: u64,
+    pub gain_reduction_db: f32,
+}
+
+/// Shared between the audio callback, which records how long each callback took, and the
+/// mixer, which reads the totals. Only the callback writes, so no update is lost.
+#[cfg(feature = "driver-cpal")]
+#[derive(Default)]
+pub struct RenderStatsRecorder {
+    load_percent_bits: AtomicU32,
+    worst_render_seconds_bits: AtomicU32,
+    underruns: AtomicU64,
+    callbacks: AtomicU64,
+    gain_reduction_db_bits: AtomicU32,
+}
+
+#[cfg(feature = "driver-cpal")]
+impl RenderStatsRecorder {
+    // Called from the audio callback; returns whether rendering was slower than real time
+    pub fn record(&self, render_seconds: f32, buffer_seconds: f32) -> bool {
+        if buffer_seconds <= 0.0 {
+            return false;
+        }
+        let load_percent = 100.0 * render_seconds / buffer_seconds;
+        let callbacks = self.callbacks.load(Ordering::Relaxed);
+        let previous_load = f32::from_bits(self.load_percent_bits.load(Ordering::Relaxed));
+        let smoothed_load = match callbacks {
+            0 => load_percent,
+            _ => previous_load + LOAD_SMOOTHING * (load_percent - previous_load),
+        };
+        self.load_percent_bits
+            .store(smoothed_load.to_bits(), Ordering::Relaxed);
+        let worst = f32::from_bits(self.worst_render_seconds_bits.load(Ordering::Relaxed));
+        if render_seconds > worst {
+            self.worst_render_seconds_bits
+                .store(render_seconds.to_bits(), Ordering::Relaxed);
+        }
+        let is_underrun = render_seconds > buffer_seconds;
+        if is_underrun {
+            self.underruns.fetch_add(1, Ordering::Relaxed);
+        }
+        self.callbacks.store(callbacks + 1, Ordering::Relaxed);
+        is_underrun
+    }
+
+    // Called from the audio callback with the greatest gain reduction applied during it
+    pub fn record_gain_reduction(&self, reduction_db: f32) {
+        self.gain_reduction_db_bits
+            .store(reduction_db.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> RenderStats {
+        RenderStats {
+            load_percent: f32::from_bits(self.load_percent_bits.load(Ordering::Relaxed)),
+            worst_render_seconds: f32::from_bits(
+                self.worst_render_seconds_bits.load(Ordering::Relaxed),
+            ),
+            underruns: self.underruns.load(Ordering::Relaxed),
+            callbacks: self.callbacks.load(Ordering::Relaxed),
+            gain_reduction_db: f32::from_bits(self.gain_reduction_db_bits.load(Ordering::Relaxed)),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.load_percent_bits
+            .store(0.0f32.to_bits(), Ordering::Relaxed);
+        self.worst_render_seconds_bits
+            .store(0.0f32.to_bits(), Ordering::Relaxed);
+        self.underruns.store(0, Ordering::Relaxed);
+        self.callbacks.store(0, Ordering::Relaxed);
+        self.gain_reduction_db_bits
+            .store(0.0f32.to_bits(), Ordering::Relaxed);
+    }
+}
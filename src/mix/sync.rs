@@ -0,0 +1,328 @@
+use crate::Error;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::time::{Duration, Instant, SystemTime};
+
+// Largest message sent, so that a receive buffer of this size never truncates one
+const MAX_MESSAGE_BYTES: usize = 64;
+
+// Round trips kept by a follower, of which the fastest gives the best estimate of the offset
+const ROUND_TRIPS_KEPT: usize = 8;
+
+// How often a follower asks the leader for the time, more often until it has an estimate
+const FIRST_REQUEST_INTERVAL: Duration = Duration::from_millis(50);
+const REQUEST_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Offset between this machine's clock and a server's clock, for agreeing on a
+/// shared start time between clients. SyncLeader and SyncFollower make the exchange over
+/// a transport; this does the arithmetic of an NTP-style round trip.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ClockOffset {
+    server_ahead_micros: i64,
+}
+
+impl ClockOffset {
+    /// Estimate the offset from one request/response, where the request left this
+    /// machine at `sent`, the server stamped its reply with `server_time`, and the reply
+    /// arrived back at `received`. Assumes the network delay is the same both ways.
+    pub fn from_round_trip(
+        sent: SystemTime,
+        server_time: SystemTime,
+        received: SystemTime,
+    ) -> Self {
+        let sent = micros_since_epoch(sent);
+        let received = micros_since_epoch(received);
+        let server_time = micros_since_epoch(server_time);
+        let local_midpoint = sent + (received - sent) / 2;
+        Self {
+            server_ahead_micros: server_time - local_midpoint,
+        }
+    }
+
+    /// Average several estimates, such as from repeated round trips.
+    pub fn average(offsets: &[ClockOffset]) -> Self {
+        if offsets.is_empty() {
+            return Self::default();
+        }
+        let total: i64 = offsets.iter().map(|o| o.server_ahead_micros).sum();
+        Self {
+            server_ahead_micros: total / offsets.len() as i64,
+        }
+    }
+
+    /// Convert a time on the server's clock to the same moment on this machine's clock.
+    pub fn to_local_time(&self, server_time: SystemTime) -> SystemTime {
+        let micros = self.server_ahead_micros.unsigned_abs();
+        match self.server_ahead_micros >= 0 {
+            true => server_time - Duration::from_micros(micros),
+            false => server_time + Duration::from_micros(micros),
+        }
+    }
+}
+
+pub(crate) fn micros_since_epoch(time: SystemTime) -> i64 {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(duration) => duration.as_micros() as i64,
+        Err(error) => -(error.duration().as_micros() as i64),
+    }
+}
+
+fn time_from_micros(micros: i64) -> SystemTime {
+    match micros >= 0 {
+        true => SystemTime::UNIX_EPOCH + Duration::from_micros(micros as u64),
+        false => SystemTime::UNIX_EPOCH - Duration::from_micros(micros.unsigned_abs()),
+    }
+}
+
+/// Carries messages between a SyncLeader and its SyncFollowers, such as a UDP socket or a
+/// game's own networking. Whatever one instance sends should reach every other. Messages
+/// are small and each stands alone, so losing or reordering some does no harm.
+pub trait SyncTransport {
+    fn send(&mut self, message: &[u8]) -> Result<(), Error>;
+
+    /// The next message to have arrived, or None if there is none, without blocking.
+    fn try_receive(&mut self) -> Result<Option<Vec<u8>>, Error>;
+}
+
+/// A socket connected to the other instance, or to a broadcast address for several. It
+/// must be set to non-blocking.
+impl SyncTransport for UdpSocket {
+    fn send(&mut self, message: &[u8]) -> Result<(), Error> {
+        UdpSocket::send(self, message)?;
+        Ok(())
+    }
+
+    fn try_receive(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let mut buffer = [0; MAX_MESSAGE_BYTES];
+        match self.recv(&mut buffer) {
+            Ok(length) => Ok(Some(buffer[0..length].to_vec())),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+// Times are in microseconds since the Unix epoch, on the clock of the instance named
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum SyncMessage {
+    TimeRequest {
+        follower_id: u32,
+        follower_sent: i64,
+    },
+    TimeResponse {
+        follower_id: u32,
+        follower_sent: i64,
+        leader_time: i64,
+        leader_start: Option<i64>,
+    },
+    Start {
+        leader_start: i64,
+    },
+}
+
+impl SyncMessage {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(MAX_MESSAGE_BYTES);
+        let written: std::io::Result<()> = (|| match *self {
+            SyncMessage::TimeRequest {
+                follower_id,
+                follower_sent,
+            } => {
+                bytes.write_u8(0)?;
+                bytes.write_u32::<LittleEndian>(follower_id)?;
+                bytes.write_i64::<LittleEndian>(follower_sent)
+            }
+            SyncMessage::TimeResponse {
+                follower_id,
+                follower_sent,
+                leader_time,
+                leader_start,
+            } => {
+                bytes.write_u8(1)?;
+                bytes.write_u32::<LittleEndian>(follower_id)?;
+                bytes.write_i64::<LittleEndian>(follower_sent)?;
+                bytes.write_i64::<LittleEndian>(leader_time)?;
+                bytes.write_u8(leader_start.is_some() as u8)?;
+                bytes.write_i64::<LittleEndian>(leader_start.unwrap_or(0))
+            }
+            SyncMessage::Start { leader_start } => {
+                bytes.write_u8(2)?;
+                bytes.write_i64::<LittleEndian>(leader_start)
+            }
+        })();
+        written.expect("Writing to a Vec cannot fail");
+        bytes
+    }
+
+    fn decode(mut bytes: &[u8]) -> Result<Self, Error> {
+        let message = match bytes.read_u8()? {
+            0 => SyncMessage::TimeRequest {
+                follower_id: bytes.read_u32::<LittleEndian>()?,
+                follower_sent: bytes.read_i64::<LittleEndian>()?,
+            },
+            1 => SyncMessage::TimeResponse {
+                follower_id: bytes.read_u32::<LittleEndian>()?,
+                follower_sent: bytes.read_i64::<LittleEndian>()?,
+                leader_time: bytes.read_i64::<LittleEndian>()?,
+                leader_start: {
+                    let has_start = bytes.read_u8()? != 0;
+                    let start = bytes.read_i64::<LittleEndian>()?;
+                    has_start.then_some(start)
+                },
+            },
+            2 => SyncMessage::Start {
+                leader_start: bytes.read_i64::<LittleEndian>()?,
+            },
+            tag => {
+                return Err(Error::User(format!("Unknown sync message type {}", tag)));
+            }
+        };
+        Ok(message)
+    }
+}
+
+/// The instance whose clock every other follows. It answers followers' requests for the
+/// time and tells them when playback starts. Call poll regularly, every few milliseconds,
+/// since the time taken to answer counts against the followers' estimates.
+pub struct SyncLeader<T: SyncTransport> {
+    transport: T,
+    start: Option<SystemTime>,
+}
+
+impl<T: SyncTransport> SyncLeader<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            start: None,
+        }
+    }
+
+    /// Start playback this far from now on every instance, announcing the time to the
+    /// followers. Followers that join later learn it when they next ask for the time.
+    /// Return the start time, for BaseMixer::start_at_system_time on this instance.
+    pub fn schedule_start(&mut self, delay: Duration) -> Result<SystemTime, Error> {
+        let start = SystemTime::now() + delay;
+        self.start = Some(start);
+        let message = SyncMessage::Start {
+            leader_start: micros_since_epoch(start),
+        };
+        self.transport.send(&message.encode())?;
+        Ok(start)
+    }
+
+    pub fn start_time(&self) -> Option<SystemTime> {
+        self.start
+    }
+
+    /// Answer every request for the time that has arrived.
+    pub fn poll(&mut self) -> Result<(), Error> {
+        while let Some(bytes) = self.transport.try_receive()? {
+            let Ok(SyncMessage::TimeRequest {
+                follower_id,
+                follower_sent,
+            }) = SyncMessage::decode(&bytes)
+            else {
+                continue;
+            };
+            let response = SyncMessage::TimeResponse {
+                follower_id,
+                follower_sent,
+                leader_time: micros_since_epoch(SystemTime::now()),
+                leader_start: self.start.map(micros_since_epoch),
+            };
+            self.transport.send(&response.encode())?;
+        }
+        Ok(())
+    }
+}
+
+/// An instance following a SyncLeader's clock. It asks the leader for the time every so
+/// often, keeping the offset found over the fastest of its recent round trips, which is the
+/// one least skewed by network delay. Call poll regularly, every few milliseconds, and
+/// start the mixer with start_at_system_time once start_time is known. Followers sharing a
+/// transport need ids of their own.
+pub struct SyncFollower<T: SyncTransport> {
+    transport: T,
+    follower_id: u32,
+    round_trips: VecDeque<(Duration, ClockOffset)>,
+    last_request: Option<Instant>,
+    leader_start: Option<SystemTime>,
+}
+
+impl<T: SyncTransport> SyncFollower<T> {
+    pub fn new(transport: T, follower_id: u32) -> Self {
+        Self {
+            transport,
+            follower_id,
+            round_trips: VecDeque::with_capacity(ROUND_TRIPS_KEPT),
+            last_request: None,
+            leader_start: None,
+        }
+    }
+
+    /// Ask the leader for the time if it is due, and take in whatever it has sent.
+    pub fn poll(&mut self) -> Result<(), Error> {
+        let interval = match self.round_trips.len() < ROUND_TRIPS_KEPT {
+            true => FIRST_REQUEST_INTERVAL,
+            false => REQUEST_INTERVAL,
+        };
+        if self
+            .last_request
+            .is_none_or(|last_request| last_request.elapsed() >= interval)
+        {
+            self.last_request = Some(Instant::now());
+            let request = SyncMessage::TimeRequest {
+                follower_id: self.follower_id,
+                follower_sent: micros_since_epoch(SystemTime::now()),
+            };
+            self.transport.send(&request.encode())?;
+        }
+        while let Some(bytes) = self.transport.try_receive()? {
+            let received = SystemTime::now();
+            match SyncMessage::decode(&bytes) {
+                Ok(SyncMessage::TimeResponse {
+                    follower_id,
+                    follower_sent,
+                    leader_time,
+                    leader_start,
+                }) if follower_id == self.follower_id => {
+                    let sent = time_from_micros(follower_sent);
+                    let round_trip = received.duration_since(sent).unwrap_or_default();
+                    let offset =
+                        ClockOffset::from_round_trip(sent, time_from_micros(leader_time), received);
+                    if self.round_trips.len() == ROUND_TRIPS_KEPT {
+                        self.round_trips.pop_front();
+                    }
+                    self.round_trips.push_back((round_trip, offset));
+                    if let Some(leader_start) = leader_start {
+                        self.leader_start = Some(time_from_micros(leader_start));
+                    }
+                }
+                Ok(SyncMessage::Start { leader_start }) => {
+                    self.leader_start = Some(time_from_micros(leader_start));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The best estimate of the leader's clock offset so far, once any response has arrived.
+    pub fn offset(&self) -> Option<ClockOffset> {
+        self.round_trips
+            .iter()
+            .min_by_key(|(round_trip, _)| *round_trip)
+            .map(|(_, offset)| *offset)
+    }
+
+    /// When playback starts, on this machine's clock, once both the start and the offset are
+    /// known. The estimate improves as round trips are made, so pass the latest to
+    /// BaseMixer::correct_drift.
+    pub fn start_time(&self) -> Option<SystemTime> {
+        let offset = self.offset()?;
+        self.leader_start
+            .map(|leader_start| offset.to_local_time(leader_start))
+    }
+}
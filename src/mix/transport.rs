@@ -0,0 +1,33 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Transport state shared between the mixer and the audio callback. While paused the
+/// graph is not filled at all, so musical time and every node's state stand still.
+#[derive(Default)]
+pub struct Transport {
+    is_paused: AtomicBool,
+    stop_requested: AtomicBool,
+}
+
+impl Transport {
+    pub fn pause(&self) {
+        self.is_paused.store(true, Ordering::Release);
+    }
+
+    pub fn resume(&self) {
+        self.is_paused.store(false, Ordering::Release);
+    }
+
+    pub fn stop(&self) {
+        self.is_paused.store(true, Ordering::Release);
+        self.stop_requested.store(true, Ordering::Release);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused.load(Ordering::Acquire)
+    }
+
+    // Called from the audio callback; return whether a stop is waiting to be delivered
+    pub fn take_stop_request(&self) -> bool {
+        self.stop_requested.swap(false, Ordering::AcqRel)
+    }
+}
@@ -0,0 +1,46 @@
+use crate::{
+    graph::{sf2, Graph},
+    Config, Error,
+};
+#[cfg(feature = "driver-cpal")]
+use crate::{BaseMixer, RunningStream};
+
+const MIDI_CHANNEL_COUNT: usize = 16;
+
+/// Build the config that play_midi_with_sf2 plays: a MIDI file with the given instrument
+/// from an SF2 file on each of its channels, including the percussion channel. Useful as
+/// a starting point to extend with other sources, or to play through another mixer.
+pub fn midi_with_sf2_config(
+    midi_path: &str,
+    sf2_path: &str,
+    instrument_index: usize,
+) -> Result<Config, Error> {
+    (0..MIDI_CHANNEL_COUNT)
+        .fold(Graph::new().midi(midi_path), |midi, channel| {
+            midi.channel(channel, sf2(sf2_path, instrument_index))
+        })
+        .build()
+}
+
+/// Play a MIDI file on the default output device, with every channel played by the given
+/// instrument from an SF2 file. Playback stops when the returned stream is dropped.
+///
+/// ```no_run
+/// let stream = midi_graph::quick::play_midi_with_sf2(
+///     "resources/sample-in-c.mid",
+///     "resources/demo-font.sf2",
+///     0,
+/// )
+/// .unwrap();
+/// std::thread::sleep(std::time::Duration::from_secs(16));
+/// drop(stream);
+/// ```
+#[cfg(feature = "driver-cpal")]
+pub fn play_midi_with_sf2(
+    midi_path: &str,
+    sf2_path: &str,
+    instrument_index: usize,
+) -> Result<RunningStream, Error> {
+    let config = midi_with_sf2_config(midi_path, sf2_path, instrument_index)?;
+    BaseMixer::builder().config(config).start()
+}
@@ -0,0 +1,228 @@
+use crate::{
+    consts,
+    source::{buffer, crossfade::equal_power_gains},
+    BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent, NodeEvent,
+};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+// Length of the cross-fade when switching, short enough to sound immediate but long
+// enough not to click
+const SWITCH_SECONDS: f32 = 0.01;
+
+/// The hidden pairing of a blind comparison, kept by the host until the listener has
+/// made their choice. Its Debug output does not show the pairing.
+pub struct AbBlindMapping {
+    swapped: bool,
+}
+
+impl AbBlindMapping {
+    /// Whether the pairing was swapped, so that selecting A played the second of the two
+    /// sources given and selecting B played the first.
+    pub fn reveal(self) -> bool {
+        self.swapped
+    }
+}
+
+impl std::fmt::Debug for AbBlindMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AbBlindMapping").finish_non_exhaustive()
+    }
+}
+
+/// Renders two sources side by side and outputs only one of them at a time.
+/// Both sources receive every event and are filled every buffer, so their
+/// playback positions stay matched when switching between them. Each switch
+/// is a short equal-power cross-fade. In a blind comparison, A and B may be
+/// swapped at random, which is only known to whoever holds the AbBlindMapping.
+pub struct AbCompareSource {
+    node_id: u64,
+    use_b: bool,
+    swapped: bool,
+    position: f32,
+    step_per_frame: f32,
+    consumer_a: Box<dyn BufferConsumerNode + Send + 'static>,
+    consumer_b: Box<dyn BufferConsumerNode + Send + 'static>,
+    intermediate_buffer: Vec<f32>,
+}
+
+impl AbCompareSource {
+    pub fn new(
+        node_id: Option<u64>,
+        use_b: bool,
+        consumer_a: Box<dyn BufferConsumerNode + Send + 'static>,
+        consumer_b: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        Self::new_with_mapping(node_id, use_b, false, consumer_a, consumer_b)
+    }
+
+    /// Make a comparison starting on A, which has a one in two chance of being each of
+    /// the given sources. The pairing is decided from the system's random seed, and is
+    /// returned to be revealed once the listener has chosen.
+    pub fn new_blind(
+        node_id: Option<u64>,
+        consumer_a: Box<dyn BufferConsumerNode + Send + 'static>,
+        consumer_b: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> (Self, AbBlindMapping) {
+        let swapped = RandomState::new().build_hasher().finish() & 1 == 1;
+        let source = Self::new_with_mapping(node_id, false, swapped, consumer_a, consumer_b);
+        (source, AbBlindMapping { swapped })
+    }
+
+    fn new_with_mapping(
+        node_id: Option<u64>,
+        use_b: bool,
+        swapped: bool,
+        consumer_a: Box<dyn BufferConsumerNode + Send + 'static>,
+        consumer_b: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        let mut source = Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            use_b,
+            swapped,
+            position: 0.0,
+            step_per_frame: 1.0 / (SWITCH_SECONDS * consts::PLAYBACK_SAMPLE_RATE as f32),
+            consumer_a,
+            consumer_b,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        };
+        source.position = source.target_position();
+        source
+    }
+
+    // Position of the cross-fade for the current selection, 0 for the first source given
+    // and 1 for the second
+    #[inline]
+    fn target_position(&self) -> f32 {
+        match self.use_b != self.swapped {
+            true => 1.0,
+            false => 0.0,
+        }
+    }
+
+    // Position of the cross-fade after moving the given number of frames towards the target
+    #[inline]
+    fn position_after(&self, frames: usize, target: f32) -> f32 {
+        let distance = frames as f32 * self.step_per_frame;
+        match target > self.position {
+            true => (self.position + distance).min(target),
+            false => (self.position - distance).max(target),
+        }
+    }
+
+    // Fill one source into the buffer while cross-fading, taking its gain from the pair for
+    // each frame
+    fn fill_from(&mut self, use_second: bool, target: f32, buffer: &mut [f32]) {
+        let buffer_size = buffer.len();
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+        intermediate_slice.fill(0.0);
+        if use_second {
+            self.consumer_b.fill_buffer(intermediate_slice);
+        } else {
+            self.consumer_a.fill_buffer(intermediate_slice);
+        }
+        let gain_of = |position: f32| {
+            let (gain_a, gain_b) = equal_power_gains(position);
+            if use_second {
+                gain_b
+            } else {
+                gain_a
+            }
+        };
+
+        let frames_to_fade = ((target - self.position).abs() / self.step_per_frame).ceil() as usize;
+        let frames_to_fade = frames_to_fade.min(buffer_size / consts::CHANNEL_COUNT);
+        for i in 0..frames_to_fade {
+            let gain = gain_of(self.position_after(i + 1, target));
+            buffer[2 * i] += self.intermediate_buffer[2 * i] * gain;
+            buffer[2 * i + 1] += self.intermediate_buffer[2 * i + 1] * gain;
+        }
+        // Once faded, only the selected source is heard, at full gain
+        let faded_data_points = 2 * frames_to_fade;
+        if use_second == (target == 1.0) {
+            buffer::add_buffer(
+                &mut buffer[faded_data_points..],
+                &self.intermediate_buffer[faded_data_points..buffer_size],
+            );
+        }
+    }
+}
+
+impl BufferConsumerNode for AbCompareSource {}
+
+impl Node for AbCompareSource {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer_a.collect_node_ids(ids) && self.consumer_b.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if let NodeEvent::NodeControl { node_id, event } = event {
+            if *node_id == self.node_id {
+                match event {
+                    NodeControlEvent::AbToggle => {
+                        self.use_b = !self.use_b;
+                        return;
+                    }
+                    NodeControlEvent::AbSelect { use_b } => {
+                        self.use_b = *use_b;
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.consumer_a.on_event(event);
+        self.consumer_b.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer_a.is_active() || self.consumer_b.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        let buffer_size = buffer.len();
+        let target = self.target_position();
+
+        // Settled on one source, which can be filled straight into the buffer
+        if self.position == target {
+            let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+            let (selected, silent) = match target == 1.0 {
+                true => (&mut self.consumer_b, &mut self.consumer_a),
+                false => (&mut self.consumer_a, &mut self.consumer_b),
+            };
+            intermediate_slice.fill(0.0);
+            silent.fill_buffer(intermediate_slice);
+            selected.fill_buffer(buffer);
+            return;
+        }
+
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        self.fill_from(false, target, buffer);
+        self.fill_from(true, target, buffer);
+        let frame_count = buffer_size / consts::CHANNEL_COUNT;
+        self.position = self.position_after(frame_count, target);
+    }
+}
+
+impl BufferConsumer for AbCompareSource {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer_a = self.consumer_a.duplicate()?;
+        let consumer_b = self.consumer_b.duplicate()?;
+        let mut source = Self::new_with_mapping(
+            Some(self.node_id),
+            self.use_b,
+            self.swapped,
+            consumer_a,
+            consumer_b,
+        );
+        source.position = self.position;
+        Ok(Box::new(source))
+    }
+}
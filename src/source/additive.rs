@@ -0,0 +1,189 @@
+use crate::{
+    consts, source::tuning::NoteTuning, util, BroadcastControl, BufferConsumer, BufferConsumerNode,
+    Error, Node, NodeControlEvent, NodeEvent, NoteEvent,
+};
+
+#[derive(Clone, Copy)]
+struct Partial {
+    level: f32,
+    ratio: f32,
+    phase: f32,
+}
+
+/// Additive synthesizer summing sine partials at whole-number multiples of the note's
+/// frequency, as in a drawbar organ. Harmonic levels are listed from the fundamental up,
+/// each optionally detuned by some cents. The output is scaled by the sum of the levels
+/// so that it never exceeds the amplitude, and partials above the Nyquist limit are muted.
+pub struct AdditiveSource {
+    node_id: u64,
+    is_on: bool,
+    current_note: u8,
+    current_voice_id: Option<u64>,
+    current_amplitude: f32,
+    peak_amplitude: f32,
+    harmonics: Vec<f32>,
+    detune_cents: Vec<f32>,
+    partials: Vec<Partial>,
+    level_scale: f32,
+    tuning: NoteTuning,
+}
+
+impl AdditiveSource {
+    pub fn new(
+        node_id: Option<u64>,
+        amplitude: f32,
+        harmonics: &[f32],
+        detune_cents: &[f32],
+    ) -> Result<Self, Error> {
+        if harmonics.is_empty() {
+            return Err(Error::User(
+                "Additive synth needs at least one harmonic".to_owned(),
+            ));
+        }
+        if detune_cents.len() > harmonics.len() {
+            return Err(Error::User(format!(
+                "Additive synth has {} harmonics but detune for {}",
+                harmonics.len(),
+                detune_cents.len()
+            )));
+        }
+        let partials = harmonics
+            .iter()
+            .enumerate()
+            .map(|(index, level)| {
+                let cents = detune_cents.get(index).copied().unwrap_or(0.0);
+                Partial {
+                    level: *level,
+                    ratio: (index + 1) as f32 * 2.0f32.powf(cents / 1200.0),
+                    phase: 0.0,
+                }
+            })
+            .collect::<Vec<_>>();
+        let level_sum: f32 = harmonics.iter().map(|level| level.abs()).sum();
+        Ok(Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            is_on: false,
+            current_note: 0,
+            current_voice_id: None,
+            current_amplitude: 0.0,
+            peak_amplitude: amplitude,
+            harmonics: harmonics.to_vec(),
+            detune_cents: detune_cents.to_vec(),
+            partials,
+            level_scale: match level_sum > 0.0 {
+                true => 1.0 / level_sum,
+                false => 0.0,
+            },
+            tuning: NoteTuning::new(),
+        })
+    }
+}
+
+impl BufferConsumerNode for AdditiveSource {}
+
+impl Node for AdditiveSource {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        true
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        match event {
+            NodeEvent::Broadcast(BroadcastControl::NotesOff | BroadcastControl::Stop) => {
+                self.is_on = false;
+            }
+            NodeEvent::Broadcast(
+                BroadcastControl::SetParam { .. }
+                | BroadcastControl::RecallSnapshot { .. }
+                | BroadcastControl::Controller { .. }
+                | BroadcastControl::TransportPosition { .. },
+            ) => {}
+            NodeEvent::Broadcast(
+                control @ (BroadcastControl::Tuning(_) | BroadcastControl::MasterTune { .. }),
+            ) => {
+                self.tuning.on_broadcast(control);
+            }
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event,
+            } => match event {
+                NoteEvent::NoteOn { vel } => {
+                    self.is_on = true;
+                    self.current_note = *note;
+                    self.current_voice_id = *voice_id;
+                    self.current_amplitude = self.peak_amplitude * *vel;
+                    for partial in self.partials.iter_mut() {
+                        partial.phase = 0.0;
+                    }
+                }
+                NoteEvent::NoteOff { vel: _ } => {
+                    if self.current_note == *note
+                        && util::is_same_voice(self.current_voice_id, *voice_id)
+                    {
+                        self.is_on = false;
+                    }
+                }
+            },
+            NodeEvent::NodeControl {
+                node_id,
+                event: NodeControlEvent::Volume(volume),
+            } => {
+                if *node_id == self.node_id {
+                    self.peak_amplitude = *volume;
+                }
+            }
+            NodeEvent::NodeControl { .. } => {}
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_on
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        if !self.is_on {
+            return;
+        }
+
+        #[cfg(debug_assertions)]
+        assert_eq!(buffer.len() % consts::CHANNEL_COUNT, 0);
+
+        let base_increment =
+            self.tuning.frequency_of(self.current_note) / consts::PLAYBACK_SAMPLE_RATE as f32;
+        let gain = self.current_amplitude * self.level_scale;
+        for frame in buffer.chunks_exact_mut(consts::CHANNEL_COUNT) {
+            let mut value = 0.0;
+            for partial in self.partials.iter_mut() {
+                let increment = base_increment * partial.ratio;
+                if increment >= 0.5 {
+                    continue;
+                }
+                value += (std::f32::consts::TAU * partial.phase).sin() * partial.level;
+                partial.phase += increment;
+                partial.phase -= partial.phase.floor();
+            }
+            let sample = value * gain;
+            for channel in frame.iter_mut() {
+                *channel += sample;
+            }
+        }
+    }
+}
+
+impl BufferConsumer for AdditiveSource {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let mut source = Self::new(
+            Some(self.node_id),
+            self.peak_amplitude,
+            &self.harmonics,
+            &self.detune_cents,
+        )?;
+        source.tuning = self.tuning.clone();
+        Ok(Box::new(source))
+    }
+}
@@ -53,8 +53,17 @@ impl Node for AsyncEventReceiver {
         self.node_id
     }
 
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
     fn on_event(&mut self, _event: &NodeEvent) {}
 
+    fn is_active(&self) -> bool {
+        !self.receiver.is_empty() || self.consumer.is_active()
+    }
+
     fn fill_buffer(&mut self, buffer: &mut [f32]) {
         for event in self.receiver.try_iter() {
             self.consumer.on_event(&event);
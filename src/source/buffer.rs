@@ -0,0 +1,331 @@
+// Buffer mixing helpers. These process four samples at a time with SIMD instructions,
+// SSE on x86 and NEON on aarch64, then handle any remainder one at a time. Targets
+// without either fall back to plain arrays of four, which the compiler may still vectorise.
+
+use lanes::F32x4;
+
+const LANES: usize = 4;
+
+// Frames rendered into a block of separate channels before being interleaved into the
+// buffer, small enough for each block to live on the stack
+const BLOCK_FRAMES: usize = 64;
+
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse"
+))]
+mod lanes {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    #[derive(Clone, Copy)]
+    pub struct F32x4(__m128);
+
+    impl F32x4 {
+        #[inline(always)]
+        pub fn load(from: &[f32]) -> Self {
+            assert!(from.len() >= super::LANES);
+            // SAFETY: the slice holds at least four samples, and the load is unaligned
+            Self(unsafe { _mm_loadu_ps(from.as_ptr()) })
+        }
+
+        #[inline(always)]
+        pub fn store(self, to: &mut [f32]) {
+            assert!(to.len() >= super::LANES);
+            // SAFETY: the slice holds at least four samples, and the store is unaligned
+            unsafe { _mm_storeu_ps(to.as_mut_ptr(), self.0) }
+        }
+
+        #[inline(always)]
+        pub fn splat(value: f32) -> Self {
+            // SAFETY: this module is only built where SSE is enabled
+            Self(unsafe { _mm_set1_ps(value) })
+        }
+
+        #[inline(always)]
+        pub fn add(self, other: Self) -> Self {
+            // SAFETY: as above
+            Self(unsafe { _mm_add_ps(self.0, other.0) })
+        }
+
+        #[inline(always)]
+        pub fn mul(self, other: Self) -> Self {
+            // SAFETY: as above
+            Self(unsafe { _mm_mul_ps(self.0, other.0) })
+        }
+
+        #[inline(always)]
+        pub fn max(self, other: Self) -> Self {
+            // SAFETY: as above
+            Self(unsafe { _mm_max_ps(self.0, other.0) })
+        }
+
+        // Clear the sign bits
+        #[inline(always)]
+        pub fn abs(self) -> Self {
+            // SAFETY: as above
+            Self(unsafe { _mm_andnot_ps(_mm_set1_ps(-0.0), self.0) })
+        }
+
+        // Pair up the lanes of two vectors, giving (a0 b0 a1 b1) and (a2 b2 a3 b3)
+        #[inline(always)]
+        pub fn interleave(self, other: Self) -> (Self, Self) {
+            // SAFETY: as above
+            unsafe {
+                (
+                    Self(_mm_unpacklo_ps(self.0, other.0)),
+                    Self(_mm_unpackhi_ps(self.0, other.0)),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod lanes {
+    use std::arch::aarch64::*;
+
+    #[derive(Clone, Copy)]
+    pub struct F32x4(float32x4_t);
+
+    impl F32x4 {
+        #[inline(always)]
+        pub fn load(from: &[f32]) -> Self {
+            assert!(from.len() >= super::LANES);
+            // SAFETY: the slice holds at least four samples
+            Self(unsafe { vld1q_f32(from.as_ptr()) })
+        }
+
+        #[inline(always)]
+        pub fn store(self, to: &mut [f32]) {
+            assert!(to.len() >= super::LANES);
+            // SAFETY: the slice holds at least four samples
+            unsafe { vst1q_f32(to.as_mut_ptr(), self.0) }
+        }
+
+        #[inline(always)]
+        pub fn splat(value: f32) -> Self {
+            // SAFETY: NEON is always present on aarch64
+            Self(unsafe { vdupq_n_f32(value) })
+        }
+
+        #[inline(always)]
+        pub fn add(self, other: Self) -> Self {
+            // SAFETY: as above
+            Self(unsafe { vaddq_f32(self.0, other.0) })
+        }
+
+        #[inline(always)]
+        pub fn mul(self, other: Self) -> Self {
+            // SAFETY: as above
+            Self(unsafe { vmulq_f32(self.0, other.0) })
+        }
+
+        #[inline(always)]
+        pub fn max(self, other: Self) -> Self {
+            // SAFETY: as above
+            Self(unsafe { vmaxq_f32(self.0, other.0) })
+        }
+
+        #[inline(always)]
+        pub fn abs(self) -> Self {
+            // SAFETY: as above
+            Self(unsafe { vabsq_f32(self.0) })
+        }
+
+        // Pair up the lanes of two vectors, giving (a0 b0 a1 b1) and (a2 b2 a3 b3)
+        #[inline(always)]
+        pub fn interleave(self, other: Self) -> (Self, Self) {
+            // SAFETY: as above
+            unsafe {
+                (
+                    Self(vzip1q_f32(self.0, other.0)),
+                    Self(vzip2q_f32(self.0, other.0)),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(not(any(
+    all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        target_feature = "sse"
+    ),
+    target_arch = "aarch64"
+)))]
+mod lanes {
+    #[derive(Clone, Copy)]
+    pub struct F32x4([f32; 4]);
+
+    impl F32x4 {
+        #[inline(always)]
+        pub fn load(from: &[f32]) -> Self {
+            Self([from[0], from[1], from[2], from[3]])
+        }
+
+        #[inline(always)]
+        pub fn store(self, to: &mut [f32]) {
+            to[0..4].copy_from_slice(&self.0);
+        }
+
+        #[inline(always)]
+        pub fn splat(value: f32) -> Self {
+            Self([value; 4])
+        }
+
+        #[inline(always)]
+        pub fn add(self, other: Self) -> Self {
+            Self(std::array::from_fn(|lane| self.0[lane] + other.0[lane]))
+        }
+
+        #[inline(always)]
+        pub fn mul(self, other: Self) -> Self {
+            Self(std::array::from_fn(|lane| self.0[lane] * other.0[lane]))
+        }
+
+        #[inline(always)]
+        pub fn max(self, other: Self) -> Self {
+            Self(std::array::from_fn(|lane| self.0[lane].max(other.0[lane])))
+        }
+
+        #[inline(always)]
+        pub fn abs(self) -> Self {
+            Self(self.0.map(f32::abs))
+        }
+
+        // Pair up the lanes of two vectors, giving (a0 b0 a1 b1) and (a2 b2 a3 b3)
+        #[inline(always)]
+        pub fn interleave(self, other: Self) -> (Self, Self) {
+            let (a, b) = (self.0, other.0);
+            (
+                Self([a[0], b[0], a[1], b[1]]),
+                Self([a[2], b[2], a[3], b[3]]),
+            )
+        }
+    }
+}
+
+// Add each sample of the source into the destination
+#[inline]
+pub fn add_buffer(dst: &mut [f32], src: &[f32]) {
+    let length = dst.len().min(src.len());
+    let (dst, src) = (&mut dst[0..length], &src[0..length]);
+    let mut dst_chunks = dst.chunks_exact_mut(LANES);
+    let mut src_chunks = src.chunks_exact(LANES);
+    for (d, s) in (&mut dst_chunks).zip(&mut src_chunks) {
+        F32x4::load(d).add(F32x4::load(s)).store(d);
+    }
+    for (d, s) in dst_chunks
+        .into_remainder()
+        .iter_mut()
+        .zip(src_chunks.remainder())
+    {
+        *d += *s;
+    }
+}
+
+// Add each sample of the source, multiplied by a gain, into the destination
+#[inline]
+pub fn add_scaled_buffer(dst: &mut [f32], src: &[f32], gain: f32) {
+    let length = dst.len().min(src.len());
+    let (dst, src) = (&mut dst[0..length], &src[0..length]);
+    let gains = F32x4::splat(gain);
+    let mut dst_chunks = dst.chunks_exact_mut(LANES);
+    let mut src_chunks = src.chunks_exact(LANES);
+    for (d, s) in (&mut dst_chunks).zip(&mut src_chunks) {
+        F32x4::load(d).add(F32x4::load(s).mul(gains)).store(d);
+    }
+    for (d, s) in dst_chunks
+        .into_remainder()
+        .iter_mut()
+        .zip(src_chunks.remainder())
+    {
+        *d += *s * gain;
+    }
+}
+
+// Multiply each sample in the buffer by a gain
+#[inline]
+pub fn scale_buffer(buffer: &mut [f32], gain: f32) {
+    let gains = F32x4::splat(gain);
+    let mut chunks = buffer.chunks_exact_mut(LANES);
+    for chunk in &mut chunks {
+        F32x4::load(chunk).mul(gains).store(chunk);
+    }
+    for sample in chunks.into_remainder() {
+        *sample *= gain;
+    }
+}
+
+// Add a pair of separate channels, multiplied by a gain, into an interleaved stereo
+// destination, for as many frames as all three hold
+#[inline]
+pub fn add_interleaved(dst: &mut [f32], left: &[f32], right: &[f32], gain: f32) {
+    let frames = (dst.len() / 2).min(left.len()).min(right.len());
+    let (dst, left, right) = (&mut dst[0..frames * 2], &left[0..frames], &right[0..frames]);
+    let gains = F32x4::splat(gain);
+    let mut dst_chunks = dst.chunks_exact_mut(LANES * 2);
+    let mut left_chunks = left.chunks_exact(LANES);
+    let mut right_chunks = right.chunks_exact(LANES);
+    for ((d, l), r) in (&mut dst_chunks)
+        .zip(&mut left_chunks)
+        .zip(&mut right_chunks)
+    {
+        let (low, high) = F32x4::load(l)
+            .mul(gains)
+            .interleave(F32x4::load(r).mul(gains));
+        let (d_low, d_high) = d.split_at_mut(LANES);
+        F32x4::load(d_low).add(low).store(d_low);
+        F32x4::load(d_high).add(high).store(d_high);
+    }
+    for ((d, l), r) in dst_chunks
+        .into_remainder()
+        .chunks_exact_mut(2)
+        .zip(left_chunks.remainder())
+        .zip(right_chunks.remainder())
+    {
+        d[0] += *l * gain;
+        d[1] += *r * gain;
+    }
+}
+
+// Render stereo frames one at a time into blocks of separate channels, then add each block,
+// multiplied by a gain, into the interleaved destination. The renderer is given the index of
+// each frame within the destination and returns its left and right samples.
+#[inline]
+pub fn add_rendered_frames<F>(dst: &mut [f32], gain: f32, mut render: F)
+where
+    F: FnMut(usize) -> (f32, f32),
+{
+    let mut left = [0.0; BLOCK_FRAMES];
+    let mut right = [0.0; BLOCK_FRAMES];
+    for (block_index, block) in dst.chunks_mut(BLOCK_FRAMES * 2).enumerate() {
+        let frames = block.len() / 2;
+        for frame in 0..frames {
+            (left[frame], right[frame]) = render(block_index * BLOCK_FRAMES + frame);
+        }
+        add_interleaved(block, &left[0..frames], &right[0..frames], gain);
+    }
+}
+
+// Get the largest absolute sample value in the buffer
+#[inline]
+pub fn peak_of(buffer: &[f32]) -> f32 {
+    let mut peaks = F32x4::splat(0.0);
+    let mut chunks = buffer.chunks_exact(LANES);
+    for chunk in &mut chunks {
+        peaks = peaks.max(F32x4::load(chunk).abs());
+    }
+    let remainder_peak = chunks
+        .remainder()
+        .iter()
+        .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+    let mut lanes = [0.0; LANES];
+    peaks.store(&mut lanes);
+    lanes
+        .iter()
+        .fold(remainder_peak, |peak, lane| peak.max(*lane))
+}
@@ -1,8 +1,14 @@
-use crate::{consts, BufferConsumer, BufferConsumerNode, Error, Node, NodeEvent};
+use crate::{
+    consts,
+    source::{buffer, routing::EventRoutes},
+    BufferConsumer, BufferConsumerNode, Error, Node, NodeEvent,
+};
 
 pub struct CombinerSource {
     node_id: u64,
     consumers: Vec<Box<dyn BufferConsumerNode + Send + 'static>>,
+    routes: EventRoutes<usize>,
+    hard_sync: bool,
     intermediate_buffer: Vec<f32>,
 }
 
@@ -11,12 +17,27 @@ impl CombinerSource {
         node_id: Option<u64>,
         consumers: Vec<Box<dyn BufferConsumerNode + Send + 'static>>,
     ) -> Self {
+        let routes = EventRoutes::new(
+            consumers
+                .iter()
+                .enumerate()
+                .map(|(index, consumer)| (index, consumer.as_ref())),
+        );
         Self {
             node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
             consumers,
+            routes,
+            hard_sync: false,
             intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
         }
     }
+
+    /// Hard-sync every source to the first, restarting their oscillators each time the
+    /// first one's begins a new cycle, such as for sync leads. Only oscillators given
+    /// directly as sources are synced.
+    pub fn set_hard_sync(&mut self, hard_sync: bool) {
+        self.hard_sync = hard_sync;
+    }
 }
 
 impl BufferConsumerNode for CombinerSource {}
@@ -26,24 +47,52 @@ impl Node for CombinerSource {
         self.node_id
     }
 
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.routes.collect_node_ids(ids)
+    }
+
     fn on_event(&mut self, event: &NodeEvent) {
+        if let NodeEvent::NodeControl { node_id, .. } = event {
+            for index in self.routes.targets(*node_id) {
+                self.consumers[index].on_event(event);
+            }
+            return;
+        }
         for consumer in self.consumers.iter_mut() {
             consumer.on_event(event);
         }
     }
 
+    fn is_active(&self) -> bool {
+        self.consumers.iter().any(|consumer| consumer.is_active())
+    }
+
     fn fill_buffer(&mut self, buffer: &mut [f32]) {
         let buffer_size = buffer.len();
-        let sample_count = buffer_size / consts::CHANNEL_COUNT;
         let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
-        for consumer in self.consumers.iter_mut() {
+        let Some((leader, followers)) = self.consumers.split_first_mut() else {
+            return;
+        };
+        let is_leader_active = leader.is_active();
+        if is_leader_active {
             intermediate_slice.fill(0.0);
-            consumer.fill_buffer(intermediate_slice);
-            for i in 0..sample_count {
-                let index = i * 2;
-                buffer[index] += intermediate_slice[index];
-                buffer[index + 1] += intermediate_slice[index + 1];
+            leader.fill_buffer(intermediate_slice);
+            buffer::add_buffer(buffer, intermediate_slice);
+        }
+        for consumer in followers.iter_mut() {
+            if !consumer.is_active() {
+                continue;
             }
+            if self.hard_sync {
+                match is_leader_active {
+                    true => consumer.sync_to(leader.cycle_starts()),
+                    false => consumer.sync_to(&[]),
+                }
+            }
+            intermediate_slice.fill(0.0);
+            consumer.fill_buffer(intermediate_slice);
+            buffer::add_buffer(buffer, intermediate_slice);
         }
     }
 }
@@ -52,7 +101,8 @@ impl BufferConsumer for CombinerSource {
     fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
         let consumers: Result<Vec<Box<dyn BufferConsumerNode + Send + 'static>>, Error> =
             self.consumers.iter().map(|c| c.duplicate()).collect();
-        let combiner = Self::new(Some(self.node_id), consumers?);
+        let mut combiner = Self::new(Some(self.node_id), consumers?);
+        combiner.set_hard_sync(self.hard_sync);
         Ok(Box::new(combiner))
     }
 }
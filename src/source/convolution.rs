@@ -0,0 +1,376 @@
+use crate::{
+    consts, BufferConsumer, BufferConsumerNode, EffectMix, Error, Node, NodeEvent, OneShotSource,
+};
+use hound::WavSpec;
+use std::{
+    f32::consts::TAU,
+    ops::{Add, Mul, Sub},
+    sync::Arc,
+};
+
+// Frames in each partition of the impulse response, and so the latency of the node
+const BLOCK_FRAMES: usize = 256;
+
+// Transform size, holding the previous block of input as well as the current one
+const FFT_SIZE: usize = 2 * BLOCK_FRAMES;
+
+// Spectra of real signals are symmetric, so only the bins up to half the size are kept
+const BIN_COUNT: usize = BLOCK_FRAMES + 1;
+
+#[derive(Clone, Copy, Default)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    fn conj(self) -> Self {
+        Self {
+            re: self.re,
+            im: -self.im,
+        }
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            re: self.re + other.re,
+            im: self.im + other.im,
+        }
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            re: self.re - other.re,
+            im: self.im - other.im,
+        }
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+}
+
+// Radix-2 transform of a fixed size
+struct Fft {
+    twiddles: Vec<Complex>,
+    bit_reversed: Vec<usize>,
+}
+
+impl Fft {
+    fn new(size: usize) -> Self {
+        let bits = size.trailing_zeros();
+        Self {
+            twiddles: (0..size / 2)
+                .map(|k| {
+                    let angle = -TAU * k as f32 / size as f32;
+                    Complex {
+                        re: angle.cos(),
+                        im: angle.sin(),
+                    }
+                })
+                .collect(),
+            bit_reversed: (0..size)
+                .map(|i| i.reverse_bits() >> (usize::BITS - bits))
+                .collect(),
+        }
+    }
+
+    fn forward(&self, data: &mut [Complex]) {
+        self.transform(data, false);
+    }
+
+    fn inverse(&self, data: &mut [Complex]) {
+        self.transform(data, true);
+        let scale = 1.0 / data.len() as f32;
+        for value in data.iter_mut() {
+            value.re *= scale;
+            value.im *= scale;
+        }
+    }
+
+    fn transform(&self, data: &mut [Complex], inverse: bool) {
+        let size = data.len();
+        for (i, &j) in self.bit_reversed.iter().enumerate() {
+            if i < j {
+                data.swap(i, j);
+            }
+        }
+        let mut span = 2;
+        while span <= size {
+            let half = span / 2;
+            let step = size / span;
+            for start in (0..size).step_by(span) {
+                for k in 0..half {
+                    let twiddle = match inverse {
+                        true => self.twiddles[k * step].conj(),
+                        false => self.twiddles[k * step],
+                    };
+                    let even = data[start + k];
+                    let odd = data[start + k + half] * twiddle;
+                    data[start + k] = even + odd;
+                    data[start + k + half] = even - odd;
+                }
+            }
+            span *= 2;
+        }
+    }
+}
+
+/// Convolves its source with an impulse response, such as a recording of a room for
+/// reverb or of a speaker cabinet for guitar and chip sounds. The response is split into
+/// blocks of 256 frames, each convolved through an FFT, which keeps long responses
+/// affordable but delays the output by one block, about 5ms; the dry signal is delayed by
+/// as much so that the two stay aligned. A mono response is used for both channels, and a
+/// stereo one for each channel separately. The response is used as it is, without
+/// normalising its level. The mix blends between the dry source at 0 and only the
+/// convolved sound at 1, and may be changed by SetWet control events; a pre-delay holds
+/// back the convolved sound, for reverbs. The node stays active until the response has
+/// rung out after its source stops.
+pub struct ConvolutionNode {
+    node_id: u64,
+    effect_mix: EffectMix,
+    fft: Arc<Fft>,
+    partitions: Arc<[Vec<Complex>]>,
+    partition_count: usize,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    intermediate_buffer: Vec<f32>,
+    input_blocks: [Vec<f32>; consts::CHANNEL_COUNT],
+    input_spectra: [Vec<Complex>; consts::CHANNEL_COUNT],
+    output_blocks: [Vec<f32>; consts::CHANNEL_COUNT],
+    scratch: Vec<Complex>,
+    spectrum_slot: usize,
+    block_frame: usize,
+    tail_frames_left: usize,
+}
+
+impl ConvolutionNode {
+    /// Make a node convolving with interleaved samples of an impulse response, in the
+    /// format described by the spec.
+    pub fn new(
+        node_id: Option<u64>,
+        spec: WavSpec,
+        impulse: &[f32],
+        mix: f32,
+        pre_delay_seconds: f32,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Result<Self, Error> {
+        OneShotSource::validate_spec(&spec)?;
+        let channels = spec.channels as usize;
+        let frame_count = impulse.len() / channels;
+        if frame_count == 0 {
+            return Err(Error::User("Impulse response has no samples".to_owned()));
+        }
+        let pre_delay_frames =
+            (pre_delay_seconds.max(0.0) * consts::PLAYBACK_SAMPLE_RATE as f32).round() as usize;
+        let partition_count = (pre_delay_frames + frame_count).div_ceil(BLOCK_FRAMES);
+
+        // Transform each block of each channel of the delayed response, zero-padded to
+        // the transform size
+        let fft = Fft::new(FFT_SIZE);
+        let mut scratch = vec![Complex::default(); FFT_SIZE];
+        let partitions = (0..channels)
+            .map(|channel| {
+                let mut spectra = Vec::with_capacity(partition_count * BIN_COUNT);
+                for partition in 0..partition_count {
+                    for (i, value) in scratch.iter_mut().enumerate() {
+                        let frame = (partition * BLOCK_FRAMES + i).checked_sub(pre_delay_frames);
+                        let re = match (i < BLOCK_FRAMES, frame) {
+                            (true, Some(frame)) if frame < frame_count => {
+                                impulse[frame * channels + channel]
+                            }
+                            _ => 0.0,
+                        };
+                        *value = Complex { re, im: 0.0 };
+                    }
+                    fft.forward(&mut scratch);
+                    spectra.extend_from_slice(&scratch[0..BIN_COUNT]);
+                }
+                spectra
+            })
+            .collect();
+
+        Ok(Self::from_partitions(
+            node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            mix,
+            Arc::new(fft),
+            partitions,
+            partition_count,
+            consumer,
+        ))
+    }
+
+    fn from_partitions(
+        node_id: u64,
+        mix: f32,
+        fft: Arc<Fft>,
+        partitions: Arc<[Vec<Complex>]>,
+        partition_count: usize,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        Self {
+            node_id,
+            effect_mix: EffectMix::new(mix),
+            fft,
+            partitions,
+            partition_count,
+            consumer,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+            input_blocks: std::array::from_fn(|_| vec![0.0; FFT_SIZE]),
+            input_spectra: std::array::from_fn(|_| {
+                vec![Complex::default(); partition_count * BIN_COUNT]
+            }),
+            output_blocks: std::array::from_fn(|_| vec![0.0; BLOCK_FRAMES]),
+            scratch: vec![Complex::default(); FFT_SIZE],
+            spectrum_slot: 0,
+            block_frame: 0,
+            tail_frames_left: 0,
+        }
+    }
+
+    // Frames the node keeps sounding after its source stops: the block of latency, and
+    // then the whole response
+    fn tail_frames(&self) -> usize {
+        (self.partition_count + 1) * BLOCK_FRAMES
+    }
+
+    // Convolve the block of input just completed, filling the next block of output
+    fn process_block(&mut self) {
+        let slot_offset = self.spectrum_slot * BIN_COUNT;
+        for channel in 0..consts::CHANNEL_COUNT {
+            let input = &mut self.input_blocks[channel];
+            for (value, sample) in self.scratch.iter_mut().zip(input.iter()) {
+                *value = Complex {
+                    re: *sample,
+                    im: 0.0,
+                };
+            }
+            input.copy_within(BLOCK_FRAMES..FFT_SIZE, 0);
+            self.fft.forward(&mut self.scratch);
+            let spectra = &mut self.input_spectra[channel];
+            spectra[slot_offset..slot_offset + BIN_COUNT]
+                .copy_from_slice(&self.scratch[0..BIN_COUNT]);
+
+            // Each partition of the response meets the input from as many blocks ago
+            let response = &self.partitions[channel.min(self.partitions.len() - 1)];
+            let accumulated = &mut self.scratch[0..BIN_COUNT];
+            accumulated.fill(Complex::default());
+            for partition in 0..self.partition_count {
+                let slot =
+                    (self.spectrum_slot + self.partition_count - partition) % self.partition_count;
+                let input_bins = &spectra[slot * BIN_COUNT..(slot + 1) * BIN_COUNT];
+                let response_bins = &response[partition * BIN_COUNT..(partition + 1) * BIN_COUNT];
+                for ((sum, input), response) in accumulated
+                    .iter_mut()
+                    .zip(input_bins.iter())
+                    .zip(response_bins.iter())
+                {
+                    *sum = *sum + *input * *response;
+                }
+            }
+            for bin in 1..BLOCK_FRAMES {
+                self.scratch[FFT_SIZE - bin] = self.scratch[bin].conj();
+            }
+            self.fft.inverse(&mut self.scratch);
+
+            // The first half of the result wraps around, leaving only the second half
+            for (output, value) in self.output_blocks[channel]
+                .iter_mut()
+                .zip(self.scratch[BLOCK_FRAMES..].iter())
+            {
+                *output = value.re;
+            }
+        }
+        self.spectrum_slot = (self.spectrum_slot + 1) % self.partition_count;
+    }
+}
+
+impl BufferConsumerNode for ConvolutionNode {}
+
+impl Node for ConvolutionNode {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if self.effect_mix.on_event(self.node_id, event) {
+            return;
+        }
+        self.consumer.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active() || self.tail_frames_left > 0
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        let buffer_size = buffer.len();
+        // Ring out once the source stops, so that a combiner or font keeps filling this
+        if self.consumer.is_active() {
+            self.tail_frames_left = self.tail_frames() + buffer_size / consts::CHANNEL_COUNT;
+        }
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+        intermediate_slice.fill(0.0);
+        self.consumer.fill_buffer(intermediate_slice);
+
+        // Currently only-supported channel configuration
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        for frame_index in 0..buffer_size / consts::CHANNEL_COUNT {
+            let i = frame_index * consts::CHANNEL_COUNT;
+            let wet = self.effect_mix.next_wet();
+            for channel in 0..consts::CHANNEL_COUNT {
+                let input = &mut self.input_blocks[channel];
+                let dry = input[self.block_frame];
+                input[BLOCK_FRAMES + self.block_frame] = self.intermediate_buffer[i + channel];
+                let convolved = self.output_blocks[channel][self.block_frame];
+                buffer[i + channel] += EffectMix::blend(wet, dry, convolved);
+            }
+            self.block_frame += 1;
+            if self.block_frame == BLOCK_FRAMES {
+                self.process_block();
+                self.block_frame = 0;
+            }
+        }
+
+        self.tail_frames_left = self
+            .tail_frames_left
+            .saturating_sub(buffer_size / consts::CHANNEL_COUNT);
+    }
+}
+
+impl BufferConsumer for ConvolutionNode {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        let mut node = Self::from_partitions(
+            self.node_id,
+            self.effect_mix.mix(),
+            self.fft.clone(),
+            self.partitions.clone(),
+            self.partition_count,
+            consumer,
+        );
+        node.effect_mix = self.effect_mix;
+        Ok(Box::new(node))
+    }
+}
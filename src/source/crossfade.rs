@@ -0,0 +1,156 @@
+use crate::{
+    consts, source::buffer, BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent,
+    NodeEvent,
+};
+use std::f32::consts::FRAC_PI_2;
+
+// Gains of the two sources at a position, following the equal-power law so that the
+// overall loudness holds steady through the fade
+#[inline]
+pub(crate) fn equal_power_gains(position: f32) -> (f32, f32) {
+    let angle = position.clamp(0.0, 1.0) * FRAC_PI_2;
+    (angle.cos(), angle.sin())
+}
+
+/// Cross-fades between two sources, both of which keep playing throughout. A position of
+/// 0 is only the first source and 1 is only the second; the Crossfade control event moves
+/// the position there over a number of seconds.
+pub struct CrossfadeSource {
+    node_id: u64,
+    from_position: f32,
+    to_position: f32,
+    duration_frames: usize,
+    progress_frames: usize,
+    consumer_0: Box<dyn BufferConsumerNode + Send + 'static>,
+    consumer_1: Box<dyn BufferConsumerNode + Send + 'static>,
+    intermediate_buffer: Vec<f32>,
+}
+
+impl CrossfadeSource {
+    pub fn new(
+        node_id: Option<u64>,
+        position: f32,
+        consumer_0: Box<dyn BufferConsumerNode + Send + 'static>,
+        consumer_1: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            from_position: position,
+            to_position: position,
+            duration_frames: 0,
+            progress_frames: 0,
+            consumer_0,
+            consumer_1,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        }
+    }
+
+    #[inline]
+    fn position_at(&self, frame: usize) -> f32 {
+        if frame >= self.duration_frames {
+            return self.to_position;
+        }
+        self.from_position
+            + (self.to_position - self.from_position) * frame as f32 / self.duration_frames as f32
+    }
+
+    // Fill one source into the buffer, taking its gain from the pair for each frame
+    fn fill_from(&mut self, use_second: bool, buffer: &mut [f32]) {
+        let buffer_size = buffer.len();
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+        intermediate_slice.fill(0.0);
+        if use_second {
+            self.consumer_1.fill_buffer(intermediate_slice);
+        } else {
+            self.consumer_0.fill_buffer(intermediate_slice);
+        }
+        let gain_of = |position: f32| {
+            let (gain_0, gain_1) = equal_power_gains(position);
+            if use_second {
+                gain_1
+            } else {
+                gain_0
+            }
+        };
+
+        let frames_to_fade = self
+            .duration_frames
+            .saturating_sub(self.progress_frames)
+            .min(buffer_size / consts::CHANNEL_COUNT);
+        for i in 0..frames_to_fade {
+            let gain = gain_of(self.position_at(self.progress_frames + i));
+            buffer[2 * i] += self.intermediate_buffer[2 * i] * gain;
+            buffer[2 * i + 1] += self.intermediate_buffer[2 * i + 1] * gain;
+        }
+        let faded_data_points = 2 * frames_to_fade;
+        buffer::add_scaled_buffer(
+            &mut buffer[faded_data_points..],
+            &self.intermediate_buffer[faded_data_points..buffer_size],
+            gain_of(self.to_position),
+        );
+    }
+}
+
+impl BufferConsumerNode for CrossfadeSource {}
+
+impl Node for CrossfadeSource {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer_0.collect_node_ids(ids) && self.consumer_1.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if let NodeEvent::NodeControl {
+            node_id,
+            event: NodeControlEvent::Crossfade { to, seconds },
+        } = event
+        {
+            if *node_id == self.node_id {
+                self.from_position = self.position_at(self.progress_frames);
+                self.to_position = *to;
+                self.duration_frames = (seconds * consts::PLAYBACK_SAMPLE_RATE as f32) as usize;
+                self.progress_frames = 0;
+                return;
+            }
+        }
+        self.consumer_0.on_event(event);
+        self.consumer_1.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer_0.is_active() || self.consumer_1.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        // Currently only-supported channel configuration
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        self.fill_from(false, buffer);
+        self.fill_from(true, buffer);
+        self.progress_frames =
+            (self.progress_frames + buffer.len() / consts::CHANNEL_COUNT).min(self.duration_frames);
+    }
+}
+
+impl BufferConsumer for CrossfadeSource {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer_0 = self.consumer_0.duplicate()?;
+        let consumer_1 = self.consumer_1.duplicate()?;
+        let crossfade = Self {
+            node_id: self.node_id,
+            from_position: self.from_position,
+            to_position: self.to_position,
+            duration_frames: self.duration_frames,
+            progress_frames: self.progress_frames,
+            consumer_0,
+            consumer_1,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        };
+        Ok(Box::new(crossfade))
+    }
+}
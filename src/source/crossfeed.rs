@@ -0,0 +1,124 @@
+use crate::{
+    consts, BufferConsumer, BufferConsumerNode, EffectMix, Error, Node, NodeControlEvent, NodeEvent,
+};
+
+/// Headphone crossfeed. Blends a low-passed, slightly delayed copy of each channel
+/// into the opposite channel, approximating how speakers are heard by both ears.
+pub struct Crossfeed {
+    node_id: u64,
+    effect_mix: EffectMix,
+    amount: f32,
+    cutoff_hz: f32,
+    delay_ms: f32,
+    lowpass_coefficient: f32,
+    lowpass_state: [f32; 2],
+    delay_line: Vec<[f32; 2]>,
+    delay_index: usize,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    intermediate_buffer: Vec<f32>,
+}
+
+impl Crossfeed {
+    pub fn new(
+        node_id: Option<u64>,
+        amount: f32,
+        cutoff_hz: f32,
+        delay_ms: f32,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        let sample_rate = consts::PLAYBACK_SAMPLE_RATE as f32;
+        let lowpass_coefficient =
+            1.0 - (-2.0 * std::f32::consts::PI * cutoff_hz / sample_rate).exp();
+        let delay_frames = ((delay_ms * 0.001 * sample_rate).round() as usize).max(1);
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            effect_mix: EffectMix::default(),
+            amount: amount.clamp(0.0, 1.0),
+            cutoff_hz,
+            delay_ms,
+            lowpass_coefficient,
+            lowpass_state: [0.0; 2],
+            delay_line: vec![[0.0; 2]; delay_frames],
+            delay_index: 0,
+            consumer,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        }
+    }
+}
+
+impl BufferConsumerNode for Crossfeed {}
+
+impl Node for Crossfeed {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if self.effect_mix.on_event(self.node_id, event) {
+            return;
+        }
+        if let NodeEvent::NodeControl {
+            node_id,
+            event: NodeControlEvent::CrossfeedAmount(amount),
+        } = event
+        {
+            if *node_id == self.node_id {
+                self.amount = amount.clamp(0.0, 1.0);
+                return;
+            }
+        }
+        self.consumer.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer.len()];
+        intermediate_slice.fill(0.0);
+        self.consumer.fill_buffer(intermediate_slice);
+
+        // Currently only-supported channel configuration
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        let normalisation = 1.0 / (1.0 + self.amount);
+        for (frame, output) in intermediate_slice
+            .chunks_exact(2)
+            .zip(buffer.chunks_exact_mut(2))
+        {
+            let (left, right) = (frame[0], frame[1]);
+            self.lowpass_state[0] += self.lowpass_coefficient * (left - self.lowpass_state[0]);
+            self.lowpass_state[1] += self.lowpass_coefficient * (right - self.lowpass_state[1]);
+            let delayed = self.delay_line[self.delay_index];
+            self.delay_line[self.delay_index] = self.lowpass_state;
+            self.delay_index = (self.delay_index + 1) % self.delay_line.len();
+            let wet = self.effect_mix.next_wet();
+            let crossfed_left = (left + self.amount * delayed[1]) * normalisation;
+            let crossfed_right = (right + self.amount * delayed[0]) * normalisation;
+            output[0] += EffectMix::blend(wet, left, crossfed_left);
+            output[1] += EffectMix::blend(wet, right, crossfed_right);
+        }
+    }
+}
+
+impl BufferConsumer for Crossfeed {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        let mut crossfeed = Self::new(
+            Some(self.node_id),
+            self.amount,
+            self.cutoff_hz,
+            self.delay_ms,
+            consumer,
+        );
+        crossfeed.effect_mix = self.effect_mix;
+        Ok(Box::new(crossfeed))
+    }
+}
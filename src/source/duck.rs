@@ -0,0 +1,141 @@
+use crate::{consts, BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent, NodeEvent};
+
+// How long the music stays lowered after the priority source was last above the threshold,
+// so that it is not released each time a waveform crosses zero
+const SIGNAL_HOLD_FRAMES: usize = consts::PLAYBACK_SAMPLE_RATE / 100;
+
+/// Plays music alongside a priority source, such as stingers or dialogue, and lowers the
+/// music while the priority source is heard above a threshold or while held by a Duck
+/// control event. The depth is the fraction by which the music is lowered, reached over
+/// the attack time and recovered from over the release time.
+pub struct DuckSource {
+    node_id: u64,
+    depth: f32,
+    attack_time: f32,
+    release_time: f32,
+    threshold: f32,
+    reduction: f32,
+    hold_frames: usize,
+    music: Box<dyn BufferConsumerNode + Send + 'static>,
+    priority: Box<dyn BufferConsumerNode + Send + 'static>,
+    music_buffer: Vec<f32>,
+    priority_buffer: Vec<f32>,
+}
+
+impl DuckSource {
+    pub fn new(
+        node_id: Option<u64>,
+        depth: f32,
+        attack_time: f32,
+        release_time: f32,
+        threshold: f32,
+        music: Box<dyn BufferConsumerNode + Send + 'static>,
+        priority: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            depth,
+            attack_time,
+            release_time,
+            threshold,
+            reduction: 0.0,
+            hold_frames: 0,
+            music,
+            priority,
+            music_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+            priority_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        }
+    }
+
+    // Change in reduction per frame for a time to cover the full depth, or a jump if instant
+    fn step_for(&self, seconds: f32) -> f32 {
+        let frames = seconds * consts::PLAYBACK_SAMPLE_RATE as f32;
+        if frames < 1.0 {
+            return f32::INFINITY;
+        }
+        self.depth / frames
+    }
+}
+
+impl BufferConsumerNode for DuckSource {}
+
+impl Node for DuckSource {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.music.collect_node_ids(ids) && self.priority.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if let NodeEvent::NodeControl {
+            node_id,
+            event: NodeControlEvent::Duck { seconds },
+        } = event
+        {
+            if *node_id == self.node_id {
+                self.hold_frames = (seconds * consts::PLAYBACK_SAMPLE_RATE as f32) as usize;
+                return;
+            }
+        }
+        self.music.on_event(event);
+        self.priority.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.music.is_active() || self.priority.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        let buffer_size = buffer.len();
+        let music_slice = &mut self.music_buffer[0..buffer_size];
+        music_slice.fill(0.0);
+        self.music.fill_buffer(music_slice);
+        let priority_slice = &mut self.priority_buffer[0..buffer_size];
+        priority_slice.fill(0.0);
+        self.priority.fill_buffer(priority_slice);
+
+        // Currently only-supported channel configuration
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        let attack_step = self.step_for(self.attack_time);
+        let release_step = self.step_for(self.release_time);
+        for frame_index in 0..buffer_size / consts::CHANNEL_COUNT {
+            let i = frame_index * consts::CHANNEL_COUNT;
+            let (left, right) = (self.priority_buffer[i], self.priority_buffer[i + 1]);
+            if left.abs().max(right.abs()) > self.threshold {
+                self.hold_frames = self.hold_frames.max(SIGNAL_HOLD_FRAMES);
+            }
+            let is_held = self.hold_frames > 0;
+            self.hold_frames = self.hold_frames.saturating_sub(1);
+            self.reduction = if is_held {
+                (self.reduction + attack_step).min(self.depth)
+            } else {
+                (self.reduction - release_step).max(0.0)
+            };
+            let music_gain = 1.0 - self.reduction;
+            buffer[i] += self.music_buffer[i] * music_gain + left;
+            buffer[i + 1] += self.music_buffer[i + 1] * music_gain + right;
+        }
+    }
+}
+
+impl BufferConsumer for DuckSource {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let music = self.music.duplicate()?;
+        let priority = self.priority.duplicate()?;
+        let duck = Self::new(
+            Some(self.node_id),
+            self.depth,
+            self.attack_time,
+            self.release_time,
+            self.threshold,
+            music,
+            priority,
+        );
+        Ok(Box::new(duck))
+    }
+}
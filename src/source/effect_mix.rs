@@ -0,0 +1,94 @@
+use crate::{consts, NodeControlEvent, NodeEvent};
+
+// Time taken to move to a new mix, or in and out of bypass, so that changes do not click
+const MIX_RAMP_SECONDS: f32 = 0.01;
+
+/// Bypass and dry/wet state shared by effect nodes, which blend their processed sound with
+/// their source's dry sound. Effects handle SetBypass and SetWet control events through
+/// on_event, and ask next_wet for the fraction of processed sound at each frame. Changes
+/// ramp over a few milliseconds. A bypassed effect keeps processing, passing on only the
+/// dry sound, so that it comes back without replaying stale state.
+#[derive(Clone, Copy)]
+pub struct EffectMix {
+    bypassed: bool,
+    mix: f32,
+    wet: f32,
+}
+
+impl Default for EffectMix {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+impl EffectMix {
+    pub fn new(mix: f32) -> Self {
+        let mix = mix.clamp(0.0, 1.0);
+        Self {
+            bypassed: false,
+            mix,
+            wet: mix,
+        }
+    }
+
+    pub fn set_bypass(&mut self, bypassed: bool) {
+        self.bypassed = bypassed;
+    }
+
+    /// Set the fraction of processed sound, from the dry source only at 0 to the processed
+    /// sound only at 1.
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    pub fn mix(&self) -> f32 {
+        self.mix
+    }
+
+    pub fn is_bypassed(&self) -> bool {
+        self.bypassed
+    }
+
+    /// Handle a SetBypass or SetWet control event for the effect with the given node ID,
+    /// returning whether the event was one of them.
+    pub fn on_event(&mut self, node_id: u64, event: &NodeEvent) -> bool {
+        let NodeEvent::NodeControl {
+            node_id: target_id,
+            event,
+        } = event
+        else {
+            return false;
+        };
+        if *target_id != node_id {
+            return false;
+        }
+        match event {
+            NodeControlEvent::SetBypass(bypassed) => self.set_bypass(*bypassed),
+            NodeControlEvent::SetWet(mix) => self.set_mix(*mix),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Fraction of processed sound for the next frame, moving towards the mix, or towards
+    /// none while bypassed.
+    #[inline]
+    pub fn next_wet(&mut self) -> f32 {
+        let target = match self.bypassed {
+            true => 0.0,
+            false => self.mix,
+        };
+        let step = 1.0 / (MIX_RAMP_SECONDS * consts::PLAYBACK_SAMPLE_RATE as f32);
+        self.wet = match target > self.wet {
+            true => (self.wet + step).min(target),
+            false => (self.wet - step).max(target),
+        };
+        self.wet
+    }
+
+    /// Blend a dry sample with its processed sample by the fraction of processed sound.
+    #[inline]
+    pub fn blend(wet: f32, dry: f32, processed: f32) -> f32 {
+        dry + (processed - dry) * wet
+    }
+}
@@ -0,0 +1,239 @@
+use crate::{
+    consts, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent,
+    NodeEvent, OneShotSource,
+};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use hound::WavSpec;
+use std::collections::HashMap;
+
+struct EffectData {
+    channel_count: usize,
+    data: Vec<f32>,
+}
+
+#[derive(Clone, Copy)]
+struct Voice {
+    effect_index: usize,
+    data_position: usize,
+    left_volume: f32,
+    right_volume: f32,
+    is_playing: bool,
+}
+
+impl Voice {
+    const IDLE: Voice = Voice {
+        effect_index: 0,
+        data_position: 0,
+        left_volume: 0.0,
+        right_volume: 0.0,
+        is_playing: false,
+    };
+}
+
+pub struct SoundEffectPoolBuilder {
+    node_id: Option<u64>,
+    voice_count: usize,
+    effect_indices: HashMap<String, usize>,
+    effects: Vec<EffectData>,
+}
+
+impl SoundEffectPoolBuilder {
+    pub fn new(node_id: Option<u64>, voice_count: usize) -> Self {
+        Self {
+            node_id,
+            voice_count,
+            effect_indices: HashMap::new(),
+            effects: vec![],
+        }
+    }
+
+    /// Add a named sound effect. Data in the spec will be checked for compatibility.
+    /// Adding another effect with the same name replaces the previous one.
+    pub fn add_effect(mut self, name: &str, spec: WavSpec, data: Vec<f32>) -> Result<Self, Error> {
+        OneShotSource::validate_spec(&spec)?;
+        let effect = EffectData {
+            channel_count: spec.channels as usize,
+            data,
+        };
+        match self.effect_indices.get(name) {
+            Some(index) => self.effects[*index] = effect,
+            None => {
+                self.effect_indices
+                    .insert(name.to_owned(), self.effects.len());
+                self.effects.push(effect);
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn build(self) -> Result<(SoundEffectPoolHandle, SoundEffectPool), Error> {
+        if self.voice_count == 0 {
+            return Err(Error::User(
+                "A sound effect pool needs at least one voice".to_owned(),
+            ));
+        }
+        let (sender, receiver) = unbounded();
+        let pool = SoundEffectPool {
+            node_id: self
+                .node_id
+                .unwrap_or_else(<SoundEffectPool as Node>::new_node_id),
+            receiver,
+            effects: self.effects,
+            voices: vec![Voice::IDLE; self.voice_count],
+        };
+        let handle = SoundEffectPoolHandle {
+            node_id: pool.node_id,
+            effect_indices: self.effect_indices,
+            sender,
+        };
+        Ok((handle, pool))
+    }
+}
+
+/// Host-side handle for firing effects at a SoundEffectPool from any thread.
+#[derive(Clone)]
+pub struct SoundEffectPoolHandle {
+    node_id: u64,
+    effect_indices: HashMap<String, usize>,
+    sender: Sender<NodeEvent>,
+}
+
+impl SoundEffectPoolHandle {
+    /// Play the named effect on a free voice. Pan ranges from -1.0 (left) to 1.0 (right).
+    pub fn play(&self, name: &str, volume: f32, pan: f32) -> Result<(), Error> {
+        let Some(index) = self.effect_indices.get(name) else {
+            return Err(Error::User(format!("No sound effect named {}", name)));
+        };
+        let event = NodeEvent::NodeControl {
+            node_id: self.node_id,
+            event: NodeControlEvent::PlayEffect {
+                index: *index,
+                volume,
+                pan,
+            },
+        };
+        self.sender
+            .send(event)
+            .map_err(|_| Error::User("Sound effect pool is no longer playing".to_owned()))
+    }
+
+    pub fn stop_all(&self) -> Result<(), Error> {
+        self.sender
+            .send(NodeEvent::Broadcast(BroadcastControl::NotesOff))
+            .map_err(|_| Error::User("Sound effect pool is no longer playing".to_owned()))
+    }
+}
+
+/// Holds preloaded one-shot samples and plays them on a fixed number of voices.
+/// Voices are recycled as soon as their sample has finished playing; when all are
+/// busy, the voice that has played the furthest is taken over.
+pub struct SoundEffectPool {
+    node_id: u64,
+    receiver: Receiver<NodeEvent>,
+    effects: Vec<EffectData>,
+    voices: Vec<Voice>,
+}
+
+impl SoundEffectPool {
+    fn play(&mut self, effect_index: usize, volume: f32, pan: f32) {
+        if effect_index >= self.effects.len() {
+            return;
+        }
+        let voice_index = match self.voices.iter().position(|voice| !voice.is_playing) {
+            Some(index) => index,
+            None => self
+                .voices
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, voice)| voice.data_position)
+                .map(|(index, _)| index)
+                .unwrap_or(0),
+        };
+        let pan = pan.clamp(-1.0, 1.0);
+        self.voices[voice_index] = Voice {
+            effect_index,
+            data_position: 0,
+            left_volume: volume * (1.0 - pan).min(1.0),
+            right_volume: volume * (1.0 + pan).min(1.0),
+            is_playing: true,
+        };
+    }
+}
+
+impl BufferConsumerNode for SoundEffectPool {}
+
+impl Node for SoundEffectPool {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        match event {
+            NodeEvent::Broadcast(BroadcastControl::NotesOff | BroadcastControl::Stop) => {
+                for voice in self.voices.iter_mut() {
+                    voice.is_playing = false;
+                }
+            }
+            NodeEvent::NodeControl {
+                node_id,
+                event: NodeControlEvent::PlayEffect { index, volume, pan },
+            } => {
+                if *node_id != self.node_id {
+                    return;
+                }
+                self.play(*index, *volume, *pan);
+            }
+            _ => {}
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        !self.receiver.is_empty() || self.voices.iter().any(|voice| voice.is_playing)
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        while let Ok(event) = self.receiver.try_recv() {
+            self.on_event(&event);
+        }
+
+        #[cfg(debug_assertions)]
+        assert_eq!(buffer.len() % consts::CHANNEL_COUNT, 0);
+
+        let frames_in_buffer = buffer.len() / consts::CHANNEL_COUNT;
+        for voice in self.voices.iter_mut() {
+            if !voice.is_playing {
+                continue;
+            }
+            let effect = &self.effects[voice.effect_index];
+            let src = &effect.data[voice.data_position..];
+            let frames_to_fill = frames_in_buffer.min(src.len() / effect.channel_count);
+            match effect.channel_count {
+                1 => {
+                    for i in 0..frames_to_fill {
+                        buffer[i * 2] += src[i] * voice.left_volume;
+                        buffer[i * 2 + 1] += src[i] * voice.right_volume;
+                    }
+                }
+                2 => {
+                    for i in 0..frames_to_fill {
+                        buffer[i * 2] += src[i * 2] * voice.left_volume;
+                        buffer[i * 2 + 1] += src[i * 2 + 1] * voice.right_volume;
+                    }
+                }
+                _ => {}
+            }
+            voice.data_position += frames_to_fill * effect.channel_count;
+            if effect.data.len() - voice.data_position < effect.channel_count {
+                voice.is_playing = false;
+            }
+        }
+    }
+}
+
+impl BufferConsumer for SoundEffectPool {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        Err(Error::User(
+            "SoundEffectPool cannot be duplicated".to_owned(),
+        ))
+    }
+}
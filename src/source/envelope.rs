@@ -1,27 +1,72 @@
 use crate::{
-    consts, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node, NodeEvent, NoteEvent,
+    consts, util, BroadcastControl, BufferConsumer, BufferConsumerNode, EffectMix, EnvelopeCurve,
+    EnvelopeRetrigger, Error, Node, NodeEvent, NoteEvent,
 };
 
 const PEAK_AMPLITUDE: f32 = 1.0;
 
+// The note at which key tracking leaves envelope times unchanged, middle C
+const KEY_TRACKING_CENTRE: f32 = 60.0;
+
+// How sharply exponential and logarithmic segments bend
+const CURVE_STEEPNESS: f32 = 5.0;
+
+#[derive(Clone, Copy, PartialEq)]
 enum EnvelopeMode {
+    Delay,
     Attack,
+    Hold,
     Decay,
     Sustain,
     Release,
     Finished,
 }
 
+// Fraction of the way through a segment's change in level, at a fraction of its time.
+// Exponential segments bend like a capacitor charging, changing slowly at first when rising
+// and quickly at first when falling; logarithmic segments bend the other way.
+#[inline]
+pub(crate) fn curve_progress(curve: EnvelopeCurve, time_progress: f32, rising: bool) -> f32 {
+    let slow_start =
+        || ((CURVE_STEEPNESS * time_progress).exp() - 1.0) / (CURVE_STEEPNESS.exp() - 1.0);
+    let fast_start =
+        || (1.0 - (-CURVE_STEEPNESS * time_progress).exp()) / (1.0 - (-CURVE_STEEPNESS).exp());
+    match (curve, rising) {
+        (EnvelopeCurve::Linear, _) => time_progress,
+        (EnvelopeCurve::Exponential, true) | (EnvelopeCurve::Logarithmic, false) => slow_start(),
+        (EnvelopeCurve::Exponential, false) | (EnvelopeCurve::Logarithmic, true) => fast_start(),
+    }
+}
+
+/// DAHDSR amplitude envelope; the delay before the attack and the hold at the peak are both
+/// zero unless set. Times may follow the note and velocity of each note on: with a key
+/// tracking of 1, times halve for each octave above middle C and double for each octave below,
+/// and with a velocity tracking of 1, times halve at full velocity and double at zero. Each
+/// segment may be curved, and the release always takes the release time from whatever
+/// level had been reached.
 pub struct Envelope {
     node_id: u64,
-    attack_gradient: f32,
-    decay_gradient: f32,
+    effect_mix: EffectMix,
+    delay_time: f32,
+    attack_time: f32,
+    hold_time: f32,
+    decay_time: f32,
     sustain_multiplier: f32,
-    release_gradient: f32,
+    release_time: f32,
+    key_tracking: f32,
+    velocity_tracking: f32,
+    curves: [EnvelopeCurve; 3],
+    retrigger: EnvelopeRetrigger,
+    time_scale: f32,
     consumer: Box<dyn BufferConsumerNode + Send + 'static>,
     intermediate_buffer: Vec<f32>,
     mode: EnvelopeMode,
-    samples_progress_in_mode: isize,
+    level: f32,
+    mode_start_level: f32,
+    mode_samples: f32,
+    mode_progress_samples: f32,
+    held_voice_id: Option<u64>,
+    pending_note_off: Option<(u8, Option<u64>, f32)>,
 }
 
 impl Envelope {
@@ -33,43 +78,145 @@ impl Envelope {
         release_time: f32,
         consumer: Box<dyn BufferConsumerNode + Send + 'static>,
     ) -> Self {
-        let attack_gradient = PEAK_AMPLITUDE / (attack_time * consts::PLAYBACK_SAMPLE_RATE as f32);
-        let decay_gradient = (sustain_multiplier - PEAK_AMPLITUDE)
-            / (decay_time * consts::PLAYBACK_SAMPLE_RATE as f32);
-        let release_gradient =
-            (0.0 - sustain_multiplier) / (release_time * consts::PLAYBACK_SAMPLE_RATE as f32);
-        Self {
+        let mut envelope = Self {
             node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
-            attack_gradient,
-            decay_gradient,
+            effect_mix: EffectMix::default(),
+            delay_time: 0.0,
+            attack_time,
+            hold_time: 0.0,
+            decay_time,
             sustain_multiplier,
-            release_gradient,
+            release_time,
+            key_tracking: 0.0,
+            velocity_tracking: 0.0,
+            curves: [EnvelopeCurve::Linear; 3],
+            retrigger: EnvelopeRetrigger::Reset,
+            time_scale: 1.0,
             consumer,
             intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
             mode: EnvelopeMode::Attack,
-            samples_progress_in_mode: 0,
-        }
+            level: 0.0,
+            mode_start_level: 0.0,
+            mode_samples: 0.0,
+            mode_progress_samples: 0.0,
+            held_voice_id: None,
+            pending_note_off: None,
+        };
+        envelope.enter_mode(EnvelopeMode::Attack);
+        envelope
+    }
+
+    pub fn set_delay_and_hold(&mut self, delay_time: f32, hold_time: f32) {
+        self.delay_time = delay_time;
+        self.hold_time = hold_time;
+    }
+
+    pub fn set_tracking(&mut self, key_tracking: f32, velocity_tracking: f32) {
+        self.key_tracking = key_tracking;
+        self.velocity_tracking = velocity_tracking;
+    }
+
+    pub fn set_curves(
+        &mut self,
+        attack: EnvelopeCurve,
+        decay: EnvelopeCurve,
+        release: EnvelopeCurve,
+    ) {
+        self.curves = [attack, decay, release];
+    }
+
+    pub fn set_retrigger(&mut self, retrigger: EnvelopeRetrigger) {
+        self.retrigger = retrigger;
+    }
+
+    // Factor applied to the envelope times for a note
+    fn time_scale_for(&self, note: u8, vel: f32) -> f32 {
+        let octaves_from_centre = (note as f32 - KEY_TRACKING_CENTRE) / 12.0;
+        let velocity_from_centre = 2.0 * vel - 1.0;
+        let exponent =
+            self.key_tracking * octaves_from_centre + self.velocity_tracking * velocity_from_centre;
+        2.0f32.powf(-exponent)
+    }
+
+    // Begin a mode from the current level. An attack from part way up takes only the
+    // share of the attack time needed to cover the remaining distance.
+    fn enter_mode(&mut self, mode: EnvelopeMode) {
+        let seconds = match mode {
+            EnvelopeMode::Delay => self.delay_time,
+            EnvelopeMode::Attack => self.attack_time * (PEAK_AMPLITUDE - self.level).max(0.0),
+            EnvelopeMode::Hold => self.hold_time,
+            EnvelopeMode::Decay => self.decay_time,
+            EnvelopeMode::Release => self.release_time,
+            EnvelopeMode::Sustain | EnvelopeMode::Finished => 0.0,
+        };
+        self.mode = mode;
+        self.mode_start_level = self.level;
+        self.mode_samples = seconds * self.time_scale * consts::PLAYBACK_SAMPLE_RATE as f32;
+        self.mode_progress_samples = 0.0;
     }
 
     fn release(&mut self) {
-        self.samples_progress_in_mode = match self.mode {
-            EnvelopeMode::Attack => {
-                let current_multiplier =
-                    self.samples_progress_in_mode as f32 * self.attack_gradient;
-                ((current_multiplier - self.sustain_multiplier) / self.release_gradient) as isize
-            }
-            EnvelopeMode::Decay => {
-                let current_multiplier =
-                    PEAK_AMPLITUDE + self.samples_progress_in_mode as f32 * self.decay_gradient;
-                ((current_multiplier - self.sustain_multiplier) / self.release_gradient) as isize
-            }
-            EnvelopeMode::Sustain => 0,
-            EnvelopeMode::Release => self.samples_progress_in_mode,
-            EnvelopeMode::Finished => {
-                (self.release_gradient * self.sustain_multiplier * PEAK_AMPLITUDE) as isize
-            }
+        if self.mode == EnvelopeMode::Finished || self.mode == EnvelopeMode::Release {
+            return;
+        }
+        self.enter_mode(EnvelopeMode::Release);
+    }
+
+    fn trigger(&mut self, note: u8, vel: f32) {
+        let is_held = matches!(
+            self.mode,
+            EnvelopeMode::Delay
+                | EnvelopeMode::Attack
+                | EnvelopeMode::Hold
+                | EnvelopeMode::Decay
+                | EnvelopeMode::Sustain
+        );
+        match self.retrigger {
+            EnvelopeRetrigger::Legato if is_held && self.level > 0.0 => return,
+            EnvelopeRetrigger::Reset => self.level = 0.0,
+            _ => {}
+        }
+        self.time_scale = self.time_scale_for(note, vel);
+        self.enter_mode(EnvelopeMode::Delay);
+    }
+
+    // Level for the next sample, moving on to the next mode when one is complete
+    #[inline]
+    fn next_level(&mut self) -> f32 {
+        let (target, curve) = match self.mode {
+            EnvelopeMode::Delay | EnvelopeMode::Hold => (self.level, EnvelopeCurve::Linear),
+            EnvelopeMode::Attack => (PEAK_AMPLITUDE, self.curves[0]),
+            EnvelopeMode::Decay => (self.sustain_multiplier, self.curves[1]),
+            EnvelopeMode::Release => (0.0, self.curves[2]),
+            EnvelopeMode::Sustain => return self.sustain_multiplier,
+            EnvelopeMode::Finished => return 0.0,
         };
-        self.mode = EnvelopeMode::Release;
+        if self.mode_progress_samples >= self.mode_samples {
+            self.level = target;
+            match self.mode {
+                EnvelopeMode::Delay => self.enter_mode(EnvelopeMode::Attack),
+                EnvelopeMode::Attack => self.enter_mode(EnvelopeMode::Hold),
+                EnvelopeMode::Hold => self.enter_mode(EnvelopeMode::Decay),
+                EnvelopeMode::Decay => self.enter_mode(EnvelopeMode::Sustain),
+                _ => {
+                    self.enter_mode(EnvelopeMode::Finished);
+                    if let Some((note, voice_id, vel)) = self.pending_note_off.take() {
+                        self.consumer.on_event(&NodeEvent::Note {
+                            note,
+                            voice_id,
+                            event: NoteEvent::NoteOff { vel },
+                        });
+                    }
+                }
+            }
+            return self.level;
+        }
+        let time_progress = self.mode_progress_samples / self.mode_samples;
+        let rising = target > self.mode_start_level;
+        let progress = curve_progress(curve, time_progress, rising);
+        self.level = self.mode_start_level + (target - self.mode_start_level) * progress;
+        self.mode_progress_samples += 1.0;
+        self.level
     }
 }
 
@@ -80,19 +227,54 @@ impl Node for Envelope {
         self.node_id
     }
 
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
     fn on_event(&mut self, event: &NodeEvent) {
+        if self.effect_mix.on_event(self.node_id, event) {
+            return;
+        }
         match event {
             NodeEvent::Broadcast(BroadcastControl::NotesOff) => {
                 self.release();
             }
-            NodeEvent::Note { note: _, event } => {
+            NodeEvent::Broadcast(BroadcastControl::Stop) => {
+                self.level = 0.0;
+                self.enter_mode(EnvelopeMode::Finished);
+                self.pending_note_off = None;
+            }
+            NodeEvent::Broadcast(
+                BroadcastControl::SetParam { .. }
+                | BroadcastControl::RecallSnapshot { .. }
+                | BroadcastControl::Controller { .. }
+                | BroadcastControl::TransportPosition { .. }
+                | BroadcastControl::Tuning(_)
+                | BroadcastControl::MasterTune { .. },
+            ) => {}
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event,
+            } => {
                 match event {
-                    NoteEvent::NoteOn { .. } => {
-                        self.mode = EnvelopeMode::Attack;
-                        self.samples_progress_in_mode = 0;
+                    NoteEvent::NoteOn { vel } => {
+                        self.trigger(*note, *vel);
+                        self.held_voice_id = *voice_id;
+                        self.pending_note_off = None;
                     }
-                    NoteEvent::NoteOff { .. } => {
+                    NoteEvent::NoteOff { vel } => {
+                        // A note-off for a voice since retriggered leaves the new one alone
+                        if !util::is_same_voice(self.held_voice_id, *voice_id) {
+                            return;
+                        }
+
+                        // Keep the source sounding through the release; it is sent the
+                        // note-off once the envelope has finished
                         self.release();
+                        self.pending_note_off = Some((*note, *voice_id, *vel));
+                        return;
                     }
                 };
             }
@@ -104,91 +286,29 @@ impl Node for Envelope {
         self.consumer.on_event(event);
     }
 
+    fn is_active(&self) -> bool {
+        self.mode != EnvelopeMode::Finished && self.consumer.is_active()
+    }
+
     fn fill_buffer(&mut self, buffer: &mut [f32]) {
         let buffer_size = buffer.len();
-        let samples_in_buffer = buffer_size / consts::CHANNEL_COUNT;
-
         let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
         intermediate_slice.fill(0.0);
         self.consumer.fill_buffer(intermediate_slice);
 
-        let mut samples_available = samples_in_buffer;
-        while samples_available > 0 {
-            let samples_left_in_mode: usize = match self.mode {
-                EnvelopeMode::Attack => ((PEAK_AMPLITUDE / self.attack_gradient) as isize
-                    - self.samples_progress_in_mode)
-                    .max(0) as usize,
-                EnvelopeMode::Decay => ((PEAK_AMPLITUDE * (self.sustain_multiplier - 1.0)
-                    / self.decay_gradient) as isize
-                    - self.samples_progress_in_mode)
-                    .max(0) as usize,
-                EnvelopeMode::Sustain => usize::MAX,
-                EnvelopeMode::Release => ((PEAK_AMPLITUDE * self.sustain_multiplier
-                    / self.release_gradient) as isize
-                    - self.samples_progress_in_mode)
-                    .max(0) as usize,
-                EnvelopeMode::Finished => usize::MAX,
-            };
-            let samples_to_fill = samples_left_in_mode.min(samples_available);
-            let buffer_index = consts::CHANNEL_COUNT * (samples_in_buffer - samples_available);
-            let buffer_slice = &mut buffer[buffer_index..];
-            let intermediate_slice = &self.intermediate_buffer[buffer_index..];
-            match self.mode {
-                EnvelopeMode::Attack => {
-                    for i in 0..samples_to_fill {
-                        let multiplier = (self.samples_progress_in_mode + i as isize) as f32
-                            * self.attack_gradient;
-                        buffer_slice[2 * i] += multiplier * intermediate_slice[2 * i];
-                        buffer_slice[2 * i + 1] += multiplier * intermediate_slice[2 * i + 1];
-                    }
-                    if samples_to_fill == samples_left_in_mode {
-                        self.mode = EnvelopeMode::Decay;
-                        self.samples_progress_in_mode = 0;
-                    } else {
-                        self.samples_progress_in_mode += samples_to_fill as isize;
-                    }
-                }
-                EnvelopeMode::Decay => {
-                    for i in 0..samples_to_fill {
-                        let multiplier = PEAK_AMPLITUDE
-                            + (self.samples_progress_in_mode + i as isize) as f32
-                                * self.decay_gradient;
-                        buffer_slice[2 * i] += multiplier * intermediate_slice[2 * i];
-                        buffer_slice[2 * i + 1] += multiplier * intermediate_slice[2 * i + 1];
-                    }
-                    if samples_to_fill == samples_left_in_mode {
-                        self.mode = EnvelopeMode::Sustain;
-                        self.samples_progress_in_mode = 0;
-                    } else {
-                        self.samples_progress_in_mode += samples_to_fill as isize;
-                    }
-                }
-                EnvelopeMode::Sustain => {
-                    for i in 0..samples_to_fill {
-                        let multiplier = self.sustain_multiplier;
-                        buffer_slice[2 * i] += multiplier * intermediate_slice[2 * i];
-                        buffer_slice[2 * i + 1] += multiplier * intermediate_slice[2 * i + 1];
-                    }
-                    self.samples_progress_in_mode += samples_to_fill as isize;
-                }
-                EnvelopeMode::Release => {
-                    for i in 0..samples_to_fill {
-                        let multiplier = self.sustain_multiplier
-                            + (self.samples_progress_in_mode + i as isize) as f32
-                                * self.release_gradient;
-                        buffer_slice[2 * i] += multiplier * intermediate_slice[2 * i];
-                        buffer_slice[2 * i + 1] += multiplier * intermediate_slice[2 * i + 1];
-                    }
-                    if samples_to_fill == samples_left_in_mode {
-                        self.mode = EnvelopeMode::Finished;
-                        self.samples_progress_in_mode = 0;
-                    } else {
-                        self.samples_progress_in_mode += samples_to_fill as isize;
-                    }
-                }
-                EnvelopeMode::Finished => {}
-            };
-            samples_available -= samples_to_fill;
+        // Currently only-supported channel configuration
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        for frame_index in 0..buffer_size / consts::CHANNEL_COUNT {
+            let wet = self.effect_mix.next_wet();
+            if self.mode == EnvelopeMode::Finished && wet == 1.0 {
+                break;
+            }
+            let multiplier = EffectMix::blend(wet, 1.0, self.next_level());
+            let i = frame_index * consts::CHANNEL_COUNT;
+            buffer[i] += multiplier * self.intermediate_buffer[i];
+            buffer[i + 1] += multiplier * self.intermediate_buffer[i + 1];
         }
     }
 }
@@ -196,17 +316,19 @@ impl Node for Envelope {
 impl BufferConsumer for Envelope {
     fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
         let consumer = self.consumer.duplicate()?;
-        let envelope = Self {
-            node_id: self.node_id,
-            attack_gradient: self.attack_gradient,
-            decay_gradient: self.decay_gradient,
-            sustain_multiplier: self.sustain_multiplier,
-            release_gradient: self.release_gradient,
+        let mut envelope = Self::from_adsr(
+            Some(self.node_id),
+            self.attack_time,
+            self.decay_time,
+            self.sustain_multiplier,
+            self.release_time,
             consumer,
-            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
-            mode: EnvelopeMode::Attack,
-            samples_progress_in_mode: 0,
-        };
+        );
+        envelope.set_delay_and_hold(self.delay_time, self.hold_time);
+        envelope.set_tracking(self.key_tracking, self.velocity_tracking);
+        envelope.curves = self.curves;
+        envelope.set_retrigger(self.retrigger);
+        envelope.effect_mix = self.effect_mix;
         Ok(Box::new(envelope))
     }
 }
@@ -1,11 +1,82 @@
-use crate::{consts, BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent, NodeEvent};
+use crate::{
+    consts, source::buffer, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node,
+    NodeControlEvent, NodeEvent,
+};
+use crossbeam_channel::Sender;
 
+/// Most steps a FadeChain may hold.
+pub const MAX_FADE_STEPS: usize = 4;
+
+/// One step of a chain of fader commands. Each step starts as soon as the fade before it
+/// has completed, so a chain can fade out, release the notes of the source, and then fade
+/// back in for whatever plays next.
+#[derive(Clone, Debug)]
+pub enum FadeStep {
+    Fade {
+        from: f32,
+        to: f32,
+        seconds: f32,
+    },
+    /// Send a broadcast, such as NotesOff, to the source of the fader
+    Broadcast(BroadcastControl),
+}
+
+/// Steps for a fader to run in order, held in place rather than on the heap so that a
+/// chain can be sent to a fader, cloned and dropped on the audio thread without
+/// allocating. Holds up to MAX_FADE_STEPS steps, enough to fade out, send a broadcast and
+/// fade back in.
+#[derive(Clone, Debug, Default)]
+pub struct FadeChain {
+    steps: [Option<FadeStep>; MAX_FADE_STEPS],
+    next: usize,
+}
+
+impl FadeChain {
+    pub fn new(steps: impl IntoIterator<Item = FadeStep>) -> Result<Self, Error> {
+        let mut chain = Self::default();
+        for (index, step) in steps.into_iter().enumerate() {
+            let Some(slot) = chain.steps.get_mut(index) else {
+                return Err(Error::User(format!(
+                    "A fade chain cannot have more than {} steps",
+                    MAX_FADE_STEPS
+                )));
+            };
+            *slot = Some(step);
+        }
+        Ok(chain)
+    }
+
+    /// Number of steps yet to run.
+    pub fn len(&self) -> usize {
+        self.steps[self.next..]
+            .iter()
+            .take_while(|step| step.is_some())
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn pop_front(&mut self) -> Option<FadeStep> {
+        let step = self.steps.get_mut(self.next)?.take()?;
+        self.next += 1;
+        Some(step)
+    }
+}
+
+/// Scales its source by a volume which may be faded over time. The host may be sent the
+/// fader's node ID whenever a fade of any length completes, through a channel given in
+/// a NotifyFadeComplete control event or to set_completion_sender.
 pub struct Fader {
     node_id: u64,
-    duration_seconds: f32,
+    duration_frames: usize,
     from_volume: f32,
     to_volume: f32,
-    progress_seconds: f32,
+    progress_frames: usize,
+    completion_pending: bool,
+    queued_steps: FadeChain,
+    completion_sender: Option<Sender<u64>>,
     consumer: Box<dyn BufferConsumerNode + Send + 'static>,
     intermediate_buffer: Vec<f32>,
 }
@@ -18,14 +89,87 @@ impl Fader {
     ) -> Self {
         Self {
             node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
-            duration_seconds: 0.0,
+            duration_frames: 0,
             from_volume: initial_volume,
             to_volume: initial_volume,
-            progress_seconds: 0.0,
+            progress_frames: 0,
+            completion_pending: false,
+            queued_steps: FadeChain::default(),
+            completion_sender: None,
             consumer,
             intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
         }
     }
+
+    pub fn set_completion_sender(&mut self, sender: Sender<u64>) {
+        self.completion_sender = Some(sender);
+    }
+
+    fn start_fade(&mut self, from: f32, to: f32, seconds: f32) {
+        self.from_volume = from;
+        self.to_volume = to;
+        self.duration_frames = (seconds * consts::PLAYBACK_SAMPLE_RATE as f32) as usize;
+        self.progress_frames = 0;
+        self.completion_pending = true;
+    }
+
+    // Notify the host of each fade that has completed, then run queued steps up to and
+    // including the next fade that takes any time
+    fn advance_steps(&mut self) {
+        while self.progress_frames >= self.duration_frames {
+            if self.completion_pending {
+                self.completion_pending = false;
+                if let Some(sender) = &self.completion_sender {
+                    let _ = sender.try_send(self.node_id);
+                }
+            }
+            match self.queued_steps.pop_front() {
+                Some(FadeStep::Fade { from, to, seconds }) => self.start_fade(from, to, seconds),
+                Some(FadeStep::Broadcast(control)) => {
+                    self.consumer.on_event(&NodeEvent::Broadcast(control));
+                }
+                None => return,
+            }
+        }
+    }
+
+    #[inline]
+    fn volume_at(&self, frame: usize) -> f32 {
+        if frame >= self.duration_frames {
+            return self.to_volume;
+        }
+        self.from_volume
+            + (self.to_volume - self.from_volume) * frame as f32 / self.duration_frames as f32
+    }
+
+    // Fill part of a buffer, which must not run past the end of the current fade if there
+    // are steps waiting on it
+    fn fill_segment(&mut self, buffer: &mut [f32]) {
+        let buffer_size = buffer.len();
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+        intermediate_slice.fill(0.0);
+        self.consumer.fill_buffer(intermediate_slice);
+
+        let frame_count = buffer_size / consts::CHANNEL_COUNT;
+        let frames_to_fade = self
+            .duration_frames
+            .saturating_sub(self.progress_frames)
+            .min(frame_count);
+        for i in 0..frames_to_fade {
+            let volume = self.volume_at(self.progress_frames + i);
+            buffer[2 * i] += self.intermediate_buffer[2 * i] * volume;
+            buffer[2 * i + 1] += self.intermediate_buffer[2 * i + 1] * volume;
+        }
+
+        let faded_data_points = 2 * frames_to_fade;
+        buffer::add_scaled_buffer(
+            &mut buffer[faded_data_points..],
+            &self.intermediate_buffer[faded_data_points..buffer_size],
+            self.to_volume,
+        );
+
+        self.progress_frames = (self.progress_frames + frame_count).min(self.duration_frames);
+    }
 }
 
 impl BufferConsumerNode for Fader {}
@@ -35,58 +179,63 @@ impl Node for Fader {
         self.node_id
     }
 
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
     fn on_event(&mut self, event: &NodeEvent) {
-        if let NodeEvent::NodeControl {
-            node_id,
-            event: NodeControlEvent::Fade { from, to, seconds },
-        } = event
-        {
+        if let NodeEvent::NodeControl { node_id, event } = event {
             if *node_id == self.node_id {
-                self.from_volume = *from;
-                self.to_volume = *to;
-                self.duration_seconds = *seconds;
-                self.progress_seconds = 0.0;
-                return;
+                match event {
+                    NodeControlEvent::Fade { from, to, seconds } => {
+                        self.queued_steps = FadeChain::default();
+                        self.start_fade(*from, *to, *seconds);
+                        return;
+                    }
+                    NodeControlEvent::FadeChain(steps) => {
+                        self.queued_steps = steps.clone();
+                        self.progress_frames = self.duration_frames;
+                        self.completion_pending = false;
+                        self.advance_steps();
+                        return;
+                    }
+                    NodeControlEvent::NotifyFadeComplete(sender) => {
+                        self.completion_sender = Some(sender.clone());
+                        return;
+                    }
+                    _ => {}
+                }
             }
         }
         self.consumer.on_event(event);
     }
 
+    // Stay active until every fade has run and been reported, even once the source is
+    // silent, so that parents which skip inactive nodes do not stall the fade
+    fn is_active(&self) -> bool {
+        self.progress_frames < self.duration_frames
+            || self.completion_pending
+            || !self.queued_steps.is_empty()
+            || self.consumer.is_active()
+    }
+
     fn fill_buffer(&mut self, buffer: &mut [f32]) {
-        self.intermediate_buffer.fill(0.0);
-        self.consumer
-            .fill_buffer(self.intermediate_buffer.as_mut_slice());
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
 
-        if self.progress_seconds >= self.duration_seconds {
-            for (i, data) in buffer.iter_mut().enumerate() {
-                *data += self.intermediate_buffer[i] * self.to_volume;
+        let mut start = 0;
+        while start < buffer.len() {
+            // Stop at the end of the fade if anything happens there
+            let mut end = buffer.len();
+            let fade_frames_left = self.duration_frames - self.progress_frames;
+            if fade_frames_left > 0 && (self.completion_pending || !self.queued_steps.is_empty()) {
+                end = end.min(start + fade_frames_left * consts::CHANNEL_COUNT);
             }
-            return;
+            self.fill_segment(&mut buffer[start..end]);
+            self.advance_steps();
+            start = end;
         }
-
-        let samples_to_fade = (((self.duration_seconds - self.progress_seconds)
-            * consts::PLAYBACK_SAMPLE_RATE as f32) as usize)
-            .min(buffer.len() / consts::CHANNEL_COUNT);
-        let fade_gradient_per_sample = (self.to_volume - self.from_volume)
-            / self.duration_seconds
-            / (consts::PLAYBACK_SAMPLE_RATE as f32);
-        let base_volume = self.from_volume
-            + (self.progress_seconds / self.duration_seconds) * (self.to_volume - self.from_volume);
-
-        for i in 0..samples_to_fade {
-            let volume = base_volume + (i as f32) * fade_gradient_per_sample;
-            buffer[2 * i] += self.intermediate_buffer[2 * i] * volume;
-            buffer[2 * i + 1] += self.intermediate_buffer[2 * i + 1] * volume;
-        }
-
-        for (i, data) in buffer.iter_mut().enumerate().skip(2 * samples_to_fade) {
-            *data += self.intermediate_buffer[i] * self.to_volume;
-        }
-
-        self.progress_seconds = (self.progress_seconds
-            + ((buffer.len() / consts::CHANNEL_COUNT) as f32)
-                / consts::PLAYBACK_SAMPLE_RATE as f32)
-            .min(self.duration_seconds);
     }
 }
 
@@ -95,10 +244,13 @@ impl BufferConsumer for Fader {
         let consumer = self.consumer.duplicate()?;
         let fader = Self {
             node_id: self.node_id,
-            duration_seconds: self.duration_seconds,
+            duration_frames: self.duration_frames,
             from_volume: self.from_volume,
             to_volume: self.to_volume,
-            progress_seconds: self.progress_seconds,
+            progress_frames: self.progress_frames,
+            completion_pending: self.completion_pending,
+            queued_steps: self.queued_steps.clone(),
+            completion_sender: self.completion_sender.clone(),
             consumer,
             intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
         };
@@ -0,0 +1,269 @@
+use crate::{
+    consts, source::tuning::NoteTuning, util, BroadcastControl, BufferConsumer, BufferConsumerNode,
+    Error, FmAlgorithm, FmOperator, Node, NodeControlEvent, NodeEvent, NoteEvent,
+};
+
+pub const MAX_FM_OPERATORS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+// Linear ADSR for a single operator, advanced one sample at a time
+#[derive(Clone, Copy)]
+struct OperatorState {
+    phase: f32,
+    level: f32,
+    stage: Stage,
+    release_step: f32,
+}
+
+impl OperatorState {
+    const OFF: Self = Self {
+        phase: 0.0,
+        level: 0.0,
+        stage: Stage::Off,
+        release_step: 0.0,
+    };
+
+    fn release(&mut self, operator: &FmOperator) {
+        if self.stage == Stage::Off {
+            return;
+        }
+        self.stage = Stage::Release;
+        self.release_step = self.level * samples_rate(operator.release_time);
+    }
+
+    #[inline]
+    fn next_level(&mut self, operator: &FmOperator) -> f32 {
+        match self.stage {
+            Stage::Attack => {
+                self.level += samples_rate(operator.attack_time);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= samples_rate(operator.decay_time) * (1.0 - operator.sustain_level);
+                if self.level <= operator.sustain_level {
+                    self.level = operator.sustain_level;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => {}
+            Stage::Release => {
+                self.level -= self.release_step;
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    self.stage = Stage::Off;
+                }
+            }
+            Stage::Off => {}
+        }
+        self.level
+    }
+}
+
+// The fraction of a full-scale envelope segment covered per sample
+#[inline]
+fn samples_rate(seconds: f32) -> f32 {
+    1.0 / (seconds * consts::PLAYBACK_SAMPLE_RATE as f32).max(1.0)
+}
+
+/// Frequency modulation synthesizer with up to four sine operators, in the style of the
+/// Genesis and DX sound chips. Each operator runs at a ratio of the note's frequency with
+/// its own envelope; the algorithm decides which operators modulate which, and which are
+/// heard. A modulator's level is its modulation index, and a carrier's level its volume.
+pub struct FmSynthSource {
+    node_id: u64,
+    peak_amplitude: f32,
+    current_amplitude: f32,
+    current_note: u8,
+    current_voice_id: Option<u64>,
+    algorithm: FmAlgorithm,
+    operator_count: usize,
+    operators: [FmOperator; MAX_FM_OPERATORS],
+    states: [OperatorState; MAX_FM_OPERATORS],
+    tuning: NoteTuning,
+}
+
+impl FmSynthSource {
+    pub fn new(
+        node_id: Option<u64>,
+        amplitude: f32,
+        algorithm: FmAlgorithm,
+        operators: &[FmOperator],
+    ) -> Result<Self, Error> {
+        if operators.is_empty() || operators.len() > MAX_FM_OPERATORS {
+            return Err(Error::User(format!(
+                "FM synth needs 1 to {} operators, but got {}",
+                MAX_FM_OPERATORS,
+                operators.len()
+            )));
+        }
+        let mut operator_array = [FmOperator::default(); MAX_FM_OPERATORS];
+        operator_array[..operators.len()].copy_from_slice(operators);
+        Ok(Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            peak_amplitude: amplitude,
+            current_amplitude: 0.0,
+            current_note: 0,
+            current_voice_id: None,
+            algorithm,
+            operator_count: operators.len(),
+            operators: operator_array,
+            states: [OperatorState::OFF; MAX_FM_OPERATORS],
+            tuning: NoteTuning::new(),
+        })
+    }
+
+    // The operator that the given operator modulates, or None if it is a carrier
+    fn target_of(&self, operator: usize) -> Option<usize> {
+        match self.algorithm {
+            FmAlgorithm::Stack => operator.checked_sub(1),
+            FmAlgorithm::Pairs => (operator % 2 == 1).then(|| operator - 1),
+            FmAlgorithm::Branch => (operator > 0).then_some(0),
+            FmAlgorithm::Parallel => None,
+        }
+    }
+
+    fn carrier_count(&self) -> usize {
+        (0..self.operator_count)
+            .filter(|operator| self.target_of(*operator).is_none())
+            .count()
+    }
+
+    fn release(&mut self) {
+        for (state, operator) in self.states.iter_mut().zip(self.operators.iter()) {
+            state.release(operator);
+        }
+    }
+}
+
+impl BufferConsumerNode for FmSynthSource {}
+
+impl Node for FmSynthSource {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        true
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        match event {
+            NodeEvent::Broadcast(BroadcastControl::NotesOff) => self.release(),
+            NodeEvent::Broadcast(BroadcastControl::Stop) => {
+                self.states = [OperatorState::OFF; MAX_FM_OPERATORS];
+            }
+            NodeEvent::Broadcast(
+                BroadcastControl::SetParam { .. }
+                | BroadcastControl::RecallSnapshot { .. }
+                | BroadcastControl::Controller { .. }
+                | BroadcastControl::TransportPosition { .. },
+            ) => {}
+            NodeEvent::Broadcast(
+                control @ (BroadcastControl::Tuning(_) | BroadcastControl::MasterTune { .. }),
+            ) => {
+                self.tuning.on_broadcast(control);
+            }
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event,
+            } => match event {
+                NoteEvent::NoteOn { vel } => {
+                    self.current_note = *note;
+                    self.current_voice_id = *voice_id;
+                    self.current_amplitude = self.peak_amplitude * vel;
+                    for state in self.states.iter_mut() {
+                        *state = OperatorState {
+                            stage: Stage::Attack,
+                            ..OperatorState::OFF
+                        };
+                    }
+                }
+                NoteEvent::NoteOff { vel: _ } => {
+                    if self.current_note == *note
+                        && util::is_same_voice(self.current_voice_id, *voice_id)
+                    {
+                        self.release();
+                    }
+                }
+            },
+            NodeEvent::NodeControl {
+                node_id,
+                event: NodeControlEvent::Volume(volume),
+            } => {
+                if *node_id == self.node_id {
+                    self.peak_amplitude = *volume;
+                }
+            }
+            NodeEvent::NodeControl { .. } => {}
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        (0..self.operator_count).any(|operator| {
+            self.target_of(operator).is_none() && self.states[operator].stage != Stage::Off
+        })
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        if !self.is_active() {
+            return;
+        }
+
+        #[cfg(debug_assertions)]
+        assert_eq!(buffer.len() % consts::CHANNEL_COUNT, 0);
+
+        let base_increment =
+            self.tuning.frequency_of(self.current_note) / consts::PLAYBACK_SAMPLE_RATE as f32;
+        let output_gain = self.current_amplitude / self.carrier_count() as f32;
+        for frame in buffer.chunks_exact_mut(consts::CHANNEL_COUNT) {
+            let mut modulation = [0.0f32; MAX_FM_OPERATORS];
+            let mut output = 0.0;
+
+            // Modulators always have higher indices than the operators they modulate
+            for operator in (0..self.operator_count).rev() {
+                let config = self.operators[operator];
+                let state = &mut self.states[operator];
+                let level = state.next_level(&config);
+                let angle = std::f32::consts::TAU * state.phase + modulation[operator];
+                let value = angle.sin() * level * config.level;
+                state.phase += base_increment * config.ratio;
+                state.phase -= state.phase.floor();
+                match self.target_of(operator) {
+                    Some(target) => modulation[target] += value,
+                    None => output += value,
+                }
+            }
+
+            let sample = output * output_gain;
+            for channel in frame.iter_mut() {
+                *channel += sample;
+            }
+        }
+    }
+}
+
+impl BufferConsumer for FmSynthSource {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let mut source = Self::new(
+            Some(self.node_id),
+            self.peak_amplitude,
+            self.algorithm,
+            &self.operators[..self.operator_count],
+        )?;
+        source.tuning = self.tuning.clone();
+        Ok(Box::new(source))
+    }
+}
@@ -1,12 +1,19 @@
 mod range;
 
-use crate::{BufferConsumer, BufferConsumerNode, Error, Node, NodeEvent, NoteRange};
+use crate::{
+    BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent, NodeEvent,
+    NoteEvent, NoteRange, VoiceStealing,
+};
 use range::RangeData;
 
+// Voices each range holds unless a builder is given another number
 const SOURCE_CAPACITY: usize = 8;
 
 pub struct SoundFontBuilder {
     node_id: Option<u64>,
+    max_voices: usize,
+    voice_stealing: VoiceStealing,
+    crossfade: u8,
     ranges: Vec<RangeData>,
 }
 
@@ -20,31 +27,86 @@ impl SoundFontBuilder {
     pub fn new(node_id: Option<u64>) -> Self {
         Self {
             node_id,
+            max_voices: SOURCE_CAPACITY,
+            voice_stealing: VoiceStealing::default(),
+            crossfade: 0,
             ranges: vec![],
         }
     }
 
+    /// Set how many voices each range added after this holds and may play at once.
+    pub fn with_max_voices(mut self, max_voices: usize) -> Self {
+        self.max_voices = max_voices;
+        self
+    }
+
+    pub fn with_voice_stealing(mut self, voice_stealing: VoiceStealing) -> Self {
+        self.voice_stealing = voice_stealing;
+        self
+    }
+
+    /// Crossfade between ranges that meet across some notes; see SoundFont::set_crossfade.
+    pub fn with_crossfade(mut self, notes: u8) -> Self {
+        self.crossfade = notes;
+        self
+    }
+
     pub fn add_range(
         mut self,
         range: NoteRange,
         consumer: Box<dyn BufferConsumerNode + Send + 'static>,
     ) -> Result<Self, Error> {
-        let mut consumers = Vec::new();
-        for _ in 0..SOURCE_CAPACITY {
+        let voices = self.voices_of(consumer.as_ref())?;
+        self.ranges.push(RangeData::new(range, voices));
+        Ok(self)
+    }
+
+    /// Add a range that also plays a release sound, such as a key-off noise, as each of its
+    /// notes is released, at a level relative to the notes. The release sound should end
+    /// by itself.
+    pub fn add_range_with_release(
+        mut self,
+        range: NoteRange,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+        release: Box<dyn BufferConsumerNode + Send + 'static>,
+        release_level: f32,
+    ) -> Result<Self, Error> {
+        let mut range_data = RangeData::new(range, self.voices_of(consumer.as_ref())?);
+        range_data.set_release(self.voices_of(release.as_ref())?, release_level);
+        self.ranges.push(range_data);
+        Ok(self)
+    }
+
+    fn voices_of(
+        &self,
+        consumer: &(dyn BufferConsumerNode + Send + 'static),
+    ) -> Result<Vec<Box<dyn BufferConsumerNode + Send + 'static>>, Error> {
+        if self.max_voices == 0 {
+            return Err(Error::User("A range needs at least one voice".to_owned()));
+        }
+        let mut consumers = Vec::with_capacity(self.max_voices);
+        for _ in 0..self.max_voices {
             consumers.push(consumer.duplicate()?);
         }
-        self.ranges.push(RangeData::new(range, consumers));
-        Ok(self)
+        Ok(consumers)
     }
 
     pub fn build(self) -> SoundFont {
-        SoundFont::new(self.node_id, self.ranges)
+        let mut font = SoundFont::new(self.node_id, self.ranges);
+        font.set_voice_stealing(self.voice_stealing);
+        font.set_crossfade(self.crossfade);
+        font
     }
 }
 
+/// Plays each note on the voices of the range holding it. A Transpose control event shifts
+/// the notes it is sent by some semitones before choosing their range, and a MasterTune
+/// control event retunes every voice by some cents.
 pub struct SoundFont {
     node_id: u64,
     ranges: Vec<RangeData>,
+    transpose: i8,
+    note_transposes: [i8; 128],
 }
 
 impl SoundFont {
@@ -52,8 +114,62 @@ impl SoundFont {
         Self {
             node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
             ranges,
+            transpose: 0,
+            note_transposes: [0; 128],
         }
     }
+
+    /// Set how many voices each range may play at once, making more voices if needed.
+    pub fn set_max_voices(&mut self, max_voices: usize) -> Result<(), Error> {
+        for range_data in self.ranges.iter_mut() {
+            range_data.set_max_voices(max_voices)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_voice_stealing(&mut self, voice_stealing: VoiceStealing) {
+        for range_data in self.ranges.iter_mut() {
+            range_data.set_voice_stealing(voice_stealing);
+        }
+    }
+
+    /// Mix ranges that meet, where one ends on the note below another's first, across some
+    /// notes centred on the split, rather than jumping from one to the other. Notes there
+    /// play on both ranges at complementary levels. Edges not meeting another range are
+    /// left as they are.
+    pub fn set_crossfade(&mut self, notes: u8) {
+        let bounds = self
+            .ranges
+            .iter()
+            .map(|range_data| range_data.range.clone())
+            .collect::<Vec<_>>();
+        for range_data in self.ranges.iter_mut() {
+            let range = &range_data.range;
+            let meets_below = bounds
+                .iter()
+                .any(|other| other.upper_inclusive as u16 + 1 == range.lower_inclusive as u16);
+            let meets_above = bounds
+                .iter()
+                .any(|other| range.upper_inclusive as u16 + 1 == other.lower_inclusive as u16);
+            let width = |meets: bool| match meets {
+                true => notes,
+                false => 0,
+            };
+            range_data.set_crossfades(width(meets_below), width(meets_above));
+        }
+    }
+
+    // The note an incoming note plays, shifted by the transpose in effect when it began so
+    // that its note-off ends it even if the transpose has changed since, or None if that
+    // is beyond the keyboard
+    fn transposed(&mut self, note: u8, event: &NoteEvent) -> Option<u8> {
+        let key = note as usize % 128;
+        if matches!(event, NoteEvent::NoteOn { vel } if *vel > 0.0) {
+            self.note_transposes[key] = self.transpose;
+        }
+        let transposed = note as i32 + self.note_transposes[key] as i32;
+        (0..=127).contains(&transposed).then_some(transposed as u8)
+    }
 }
 
 impl BufferConsumerNode for SoundFont {}
@@ -64,13 +180,69 @@ impl Node for SoundFont {
     }
 
     fn on_event(&mut self, event: &NodeEvent) {
+        match event {
+            NodeEvent::NodeControl { node_id, event } if *node_id == self.node_id => match event {
+                NodeControlEvent::MaxVoices(max_voices) => {
+                    // Voices cannot be made on the audio thread, so only those held are used
+                    for range_data in self.ranges.iter_mut() {
+                        if !range_data.limit_voices(*max_voices) {
+                            log_warning!(
+                                "Font",
+                                "Cannot play {} voices at once in a range holding {}",
+                                max_voices,
+                                range_data.consumers.len()
+                            );
+                        }
+                    }
+                    return;
+                }
+                NodeControlEvent::Transpose(semitones) => {
+                    self.transpose = *semitones;
+                    return;
+                }
+                NodeControlEvent::MasterTune(cents) => {
+                    let tune = NodeEvent::Broadcast(BroadcastControl::MasterTune { cents: *cents });
+                    for range_data in self.ranges.iter_mut() {
+                        range_data.on_event(&tune);
+                    }
+                    return;
+                }
+                _ => {}
+            },
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event,
+            } => {
+                let Some(note) = self.transposed(*note, event) else {
+                    return;
+                };
+                let event = NodeEvent::Note {
+                    note,
+                    voice_id: *voice_id,
+                    event: *event,
+                };
+                for range_data in self.ranges.iter_mut() {
+                    range_data.on_event(&event);
+                }
+                return;
+            }
+            _ => {}
+        }
         for range_data in self.ranges.iter_mut() {
             range_data.on_event(event);
         }
     }
 
+    fn is_active(&self) -> bool {
+        self.ranges.iter().any(|range_data| range_data.is_active())
+    }
+
     fn fill_buffer(&mut self, buffer: &mut [f32]) {
         for range_data in self.ranges.iter_mut() {
+            if !range_data.is_active() {
+                continue;
+            }
             range_data.fill_buffer(buffer);
         }
     }
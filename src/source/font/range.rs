@@ -1,10 +1,78 @@
-use crate::{BufferConsumer, BufferConsumerNode, Error, Node, NodeEvent, NoteEvent, NoteRange};
+use crate::{
+    consts, source::buffer, util, BroadcastControl, BufferConsumer, BufferConsumerNode, Error,
+    Node, NodeEvent, NoteEvent, NoteRange, VoiceStealing,
+};
+
+#[derive(Clone, Copy, Default)]
+struct VoiceState {
+    held_note: Option<u8>,
+    held_voice_id: Option<u64>,
+    last_note: u8,
+    started_at: u64,
+    last_peak: f32,
+    gain: f32,
+    vel: f32,
+}
+
+#[derive(Clone, Copy, Default)]
+struct ReleaseVoice {
+    started_at: u64,
+    gain: f32,
+}
+
+// Voices started as notes of the range are released, such as the key-off noise of a
+// sampled piano, mixed at a level of their own
+struct ReleaseLayer {
+    consumers: Vec<Box<dyn BufferConsumerNode + Send + 'static>>,
+    voices: Vec<ReleaseVoice>,
+    level: f32,
+}
+
+impl ReleaseLayer {
+    fn new(consumers: Vec<Box<dyn BufferConsumerNode + Send + 'static>>, level: f32) -> Self {
+        let voice_count = consumers.len();
+        Self {
+            consumers,
+            voices: vec![ReleaseVoice::default(); voice_count],
+            level,
+        }
+    }
+
+    // Start the release sound on a voice that has ended, or else the one started longest ago
+    fn trigger(&mut self, note: u8, vel: f32, gain: f32, started_at: u64) {
+        let index = (0..self.consumers.len())
+            .find(|index| !self.consumers[*index].is_active())
+            .or_else(|| {
+                self.voices
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, voice)| voice.started_at)
+                    .map(|(index, _)| index)
+            });
+        let Some(index) = index else {
+            return;
+        };
+        self.consumers[index].on_event(&NodeEvent::Note {
+            note,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel },
+        });
+        self.voices[index] = ReleaseVoice { started_at, gain };
+    }
+}
 
 pub struct RangeData {
     node_id: u64,
     pub range: NoteRange,
-    pub next_on_index: usize,
     pub consumers: Vec<Box<dyn BufferConsumerNode + Send + 'static>>,
+    voices: Vec<VoiceState>,
+    max_voices: usize,
+    voice_stealing: VoiceStealing,
+    notes_started: u64,
+    fade_below: f32,
+    fade_above: f32,
+    release: Option<ReleaseLayer>,
+    intermediate_buffer: Vec<f32>,
 }
 
 impl RangeData {
@@ -12,36 +80,167 @@ impl RangeData {
         range: NoteRange,
         consumers: Vec<Box<dyn BufferConsumerNode + Send + 'static>>,
     ) -> Self {
+        let voice_count = consumers.len();
         Self {
             node_id: <Self as Node>::new_node_id(),
             range,
-            next_on_index: 0,
             consumers,
+            voices: vec![VoiceState::default(); voice_count],
+            max_voices: voice_count,
+            voice_stealing: VoiceStealing::default(),
+            notes_started: 0,
+            fade_below: 0.0,
+            fade_above: 0.0,
+            release: None,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        }
+    }
+
+    /// Set how many voices may play at once, duplicating the first voice to hold more if
+    /// needed. Voices above the limit are allowed to finish but will not be given new notes.
+    pub fn set_max_voices(&mut self, max_voices: usize) -> Result<(), Error> {
+        if max_voices == 0 {
+            return Err(Error::User("A range needs at least one voice".to_owned()));
+        }
+        while self.consumers.len() < max_voices {
+            let Some(first_voice) = self.consumers.first() else {
+                return Err(Error::User(
+                    "A range without voices cannot be given more".to_owned(),
+                ));
+            };
+            let voice = first_voice.duplicate()?;
+            self.consumers.push(voice);
+            self.voices.push(VoiceState::default());
+        }
+        self.max_voices = max_voices;
+        Ok(())
+    }
+
+    // Limit how many of the voices already held may play at once, as on the audio thread,
+    // where no more can be made. Return whether the limit could be applied.
+    pub fn limit_voices(&mut self, max_voices: usize) -> bool {
+        if max_voices == 0 || max_voices > self.consumers.len() {
+            return false;
         }
+        self.max_voices = max_voices;
+        true
     }
 
-    fn turn_note_on(&mut self, note: u8, vel: f32) {
-        if !self.range.contains(note) {
+    pub fn set_voice_stealing(&mut self, voice_stealing: VoiceStealing) {
+        self.voice_stealing = voice_stealing;
+    }
+
+    /// Fade the range in across some notes centred on its lower bound, and out across some
+    /// notes centred on its upper bound, or play up to the bound at full level for 0.
+    pub fn set_crossfades(&mut self, notes_below: u8, notes_above: u8) {
+        self.fade_below = notes_below as f32;
+        self.fade_above = notes_above as f32;
+    }
+
+    /// Play a sound as each note of the range is released, on voices of its own, at a level
+    /// relative to the notes themselves. The sound is started as a note and should end by
+    /// itself, as a sample that does not loop will.
+    pub fn set_release(
+        &mut self,
+        consumers: Vec<Box<dyn BufferConsumerNode + Send + 'static>>,
+        level: f32,
+    ) {
+        self.release = Some(ReleaseLayer::new(consumers, level));
+    }
+
+    // Level at which the range plays a note, following an equal-power curve through each
+    // crossfade, so that a range meeting this one at the same crossfade width plays the
+    // complementary level
+    fn gain_for(&self, note: u8) -> f32 {
+        let note = note as f32;
+        let edge_position = |edge: f32, width: f32| match width > 0.0 {
+            true => ((note - edge + width * 0.5) / width).clamp(0.0, 1.0),
+            false => match note > edge {
+                true => 1.0,
+                false => 0.0,
+            },
+        };
+        let lower_edge = self.range.lower_inclusive as f32 - 0.5;
+        let upper_edge = self.range.upper_inclusive as f32 + 0.5;
+        let fade_in = edge_position(lower_edge, self.fade_below);
+        let fade_out = edge_position(upper_edge, self.fade_above);
+        let quarter_turn = std::f32::consts::FRAC_PI_2;
+        (fade_in * quarter_turn).sin() * (fade_out * quarter_turn).cos()
+    }
+
+    // A voice is free once its note is released and its release tail has ended
+    #[inline]
+    fn is_voice_free(&self, index: usize) -> bool {
+        self.voices[index].held_note.is_none() && !self.consumers[index].is_active()
+    }
+
+    fn choose_voice(&self, note: u8) -> Option<usize> {
+        let voices = &self.voices[0..self.max_voices];
+        if self.voice_stealing == VoiceStealing::SameNote {
+            let same_note = (0..self.max_voices)
+                .find(|index| !self.is_voice_free(*index) && voices[*index].last_note == note);
+            if same_note.is_some() {
+                return same_note;
+            }
+        }
+        if let Some(index) = (0..self.max_voices).find(|index| self.is_voice_free(*index)) {
+            return Some(index);
+        }
+        let indexed_voices = voices.iter().enumerate();
+        match self.voice_stealing {
+            VoiceStealing::None => None,
+            VoiceStealing::Oldest | VoiceStealing::SameNote => indexed_voices
+                .min_by_key(|(_, voice)| voice.started_at)
+                .map(|(index, _)| index),
+            VoiceStealing::Quietest => indexed_voices
+                .min_by(|(_, a), (_, b)| a.last_peak.total_cmp(&b.last_peak))
+                .map(|(index, _)| index),
+        }
+    }
+
+    fn turn_note_on(&mut self, note: u8, voice_id: Option<u64>, vel: f32) {
+        let gain = self.gain_for(note);
+        if gain <= 0.0 {
             return;
         }
+        let Some(index) = self.choose_voice(note) else {
+            return;
+        };
         let event = NodeEvent::Note {
             note,
+            voice_id,
             event: NoteEvent::NoteOn { vel },
         };
-        self.consumers[self.next_on_index].on_event(&event);
-        self.next_on_index = (self.next_on_index + 1) % self.consumers.len();
+        self.consumers[index].on_event(&event);
+        self.notes_started += 1;
+        self.voices[index] = VoiceState {
+            held_note: Some(note),
+            held_voice_id: voice_id,
+            last_note: note,
+            started_at: self.notes_started,
+            last_peak: self.voices[index].last_peak,
+            gain,
+            vel,
+        };
     }
 
-    fn turn_note_off(&mut self, note: u8, vel: f32) {
-        if !self.range.contains(note) {
-            return;
-        }
+    // Release the voice started with the given voice ID, or every voice playing the note
+    // when no ID is given, starting the release sound for each at its note-on velocity
+    fn turn_note_off(&mut self, note: u8, voice_id: Option<u64>, vel: f32) {
         let event = NodeEvent::Note {
             note,
+            voice_id,
             event: NoteEvent::NoteOff { vel },
         };
-        for consumer in self.consumers.iter_mut() {
-            consumer.on_event(&event);
+        for (consumer, voice) in self.consumers.iter_mut().zip(self.voices.iter_mut()) {
+            if voice.held_note == Some(note) && util::is_same_voice(voice.held_voice_id, voice_id) {
+                consumer.on_event(&event);
+                voice.held_note = None;
+                if let Some(release) = self.release.as_mut() {
+                    self.notes_started += 1;
+                    release.trigger(note, voice.vel, voice.gain, self.notes_started);
+                }
+            }
         }
     }
 }
@@ -55,14 +254,28 @@ impl Node for RangeData {
 
     fn on_event(&mut self, event: &NodeEvent) {
         match event {
-            NodeEvent::Broadcast(_) => {
+            NodeEvent::Broadcast(control) => {
                 for source in self.consumers.iter_mut() {
                     source.on_event(event);
                 }
+                if let Some(release) = self.release.as_mut() {
+                    for source in release.consumers.iter_mut() {
+                        source.on_event(event);
+                    }
+                }
+                if matches!(control, BroadcastControl::NotesOff | BroadcastControl::Stop) {
+                    for voice in self.voices.iter_mut() {
+                        voice.held_note = None;
+                    }
+                }
             }
-            NodeEvent::Note { note, event } => match event {
-                NoteEvent::NoteOn { vel } => self.turn_note_on(*note, *vel),
-                NoteEvent::NoteOff { vel } => self.turn_note_off(*note, *vel),
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event,
+            } => match event {
+                NoteEvent::NoteOn { vel } => self.turn_note_on(*note, *voice_id, *vel),
+                NoteEvent::NoteOff { vel } => self.turn_note_off(*note, *voice_id, *vel),
             },
             NodeEvent::NodeControl { .. } => {
                 for consumer in self.consumers.iter_mut() {
@@ -72,9 +285,42 @@ impl Node for RangeData {
         }
     }
 
+    fn is_active(&self) -> bool {
+        let release_active = self.release.as_ref().is_some_and(|release| {
+            release
+                .consumers
+                .iter()
+                .any(|consumer| consumer.is_active())
+        });
+        release_active || self.consumers.iter().any(|consumer| consumer.is_active())
+    }
+
     fn fill_buffer(&mut self, buffer: &mut [f32]) {
-        for consumer in self.consumers.iter_mut() {
-            consumer.fill_buffer(buffer);
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer.len()];
+        for (consumer, voice) in self.consumers.iter_mut().zip(self.voices.iter_mut()) {
+            if !consumer.is_active() {
+                voice.last_peak = 0.0;
+                continue;
+            }
+            intermediate_slice.fill(0.0);
+            consumer.fill_buffer(intermediate_slice);
+            match voice.gain == 1.0 {
+                true => buffer::add_buffer(buffer, intermediate_slice),
+                false => buffer::add_scaled_buffer(buffer, intermediate_slice, voice.gain),
+            }
+            voice.last_peak = buffer::peak_of(intermediate_slice) * voice.gain;
+        }
+        let Some(release) = self.release.as_mut() else {
+            return;
+        };
+        for (consumer, voice) in release.consumers.iter_mut().zip(release.voices.iter()) {
+            if !consumer.is_active() {
+                continue;
+            }
+            intermediate_slice.fill(0.0);
+            consumer.fill_buffer(intermediate_slice);
+            let gain = release.level * voice.gain;
+            buffer::add_scaled_buffer(buffer, intermediate_slice, gain);
         }
     }
 }
@@ -85,12 +331,19 @@ impl BufferConsumer for RangeData {
         for consumer in self.consumers.iter() {
             consumers.push(consumer.duplicate()?);
         }
-        let source = Self {
-            node_id: self.node_id,
-            range: self.range.clone(),
-            next_on_index: 0,
-            consumers,
-        };
+        let mut source = Self::new(self.range.clone(), consumers);
+        source.node_id = self.node_id;
+        source.max_voices = self.max_voices;
+        source.voice_stealing = self.voice_stealing;
+        source.fade_below = self.fade_below;
+        source.fade_above = self.fade_above;
+        if let Some(release) = self.release.as_ref() {
+            let mut consumers = vec![];
+            for consumer in release.consumers.iter() {
+                consumers.push(consumer.duplicate()?);
+            }
+            source.set_release(consumers, release.level);
+        }
         Ok(Box::new(source))
     }
 }
@@ -0,0 +1,55 @@
+use crate::{consts, source::buffer, BufferConsumer, BufferConsumerNode, Error, Node, NodeEvent};
+
+/// Scales a node's output by a fixed gain in decibels, as given to generators and samples
+/// in the config to balance their levels. Otherwise transparent, sharing the node's ID.
+pub struct GainNode {
+    gain_db: f32,
+    gain: f32,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    intermediate_buffer: Vec<f32>,
+}
+
+impl GainNode {
+    pub fn new(gain_db: f32, consumer: Box<dyn BufferConsumerNode + Send + 'static>) -> Self {
+        Self {
+            gain_db,
+            gain: 10.0f32.powf(gain_db / 20.0),
+            consumer,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        }
+    }
+}
+
+impl BufferConsumerNode for GainNode {}
+
+impl Node for GainNode {
+    fn get_node_id(&self) -> u64 {
+        self.consumer.get_node_id()
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        self.consumer.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer.len()];
+        intermediate_slice.fill(0.0);
+        self.consumer.fill_buffer(intermediate_slice);
+        buffer::add_scaled_buffer(buffer, intermediate_slice, self.gain);
+    }
+}
+
+impl BufferConsumer for GainNode {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        Ok(Box::new(Self::new(self.gain_db, consumer)))
+    }
+}
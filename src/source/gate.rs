@@ -0,0 +1,159 @@
+use crate::{
+    consts, BroadcastControl, BufferConsumer, BufferConsumerNode, EffectMix, Error, Node, NodeEvent,
+};
+
+/// Rhythmically mutes its source following a repeating pattern of step levels from 0 to 1,
+/// for trance gates and choppy chip-style pads. Inside a MIDI channel the pattern keeps
+/// time with the track, following the position and tempo the MIDI player sends at every
+/// buffer; elsewhere it runs at its own tempo from when it is loaded. Each change of level
+/// is smoothed over the attack time when rising and the release time when falling, to
+/// avoid clicks. A Stop broadcast returns to the first step.
+pub struct GateNode {
+    node_id: u64,
+    effect_mix: EffectMix,
+    pattern: Vec<f32>,
+    steps_per_beat: u32,
+    bpm: f64,
+    attack_frames: f32,
+    release_frames: f32,
+    beat: f64,
+    level: f32,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    intermediate_buffer: Vec<f32>,
+}
+
+impl GateNode {
+    pub fn new(
+        node_id: Option<u64>,
+        pattern: Vec<f32>,
+        steps_per_beat: u32,
+        bpm: f32,
+        attack_seconds: f32,
+        release_seconds: f32,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Result<Self, Error> {
+        if pattern.is_empty() || steps_per_beat == 0 {
+            return Err(Error::User(format!(
+                "Gate needs at least one step and a positive number of steps per beat, but got {} and {}",
+                pattern.len(),
+                steps_per_beat
+            )));
+        }
+        if bpm.is_nan() || bpm <= 0.0 {
+            return Err(Error::User(format!(
+                "Gate tempo {} must be above zero",
+                bpm
+            )));
+        }
+        let frames = |seconds: f32| seconds.max(0.0) * consts::PLAYBACK_SAMPLE_RATE as f32;
+        Ok(Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            effect_mix: EffectMix::default(),
+            pattern: pattern.iter().map(|level| level.clamp(0.0, 1.0)).collect(),
+            steps_per_beat,
+            bpm: bpm as f64,
+            attack_frames: frames(attack_seconds),
+            release_frames: frames(release_seconds),
+            beat: 0.0,
+            level: 0.0,
+            consumer,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        })
+    }
+
+    fn step_level(&self) -> f32 {
+        let step = (self.beat * self.steps_per_beat as f64).floor() as usize;
+        self.pattern[step % self.pattern.len()]
+    }
+
+    // Move the level one frame closer to the current step's level
+    #[inline]
+    fn next_level(&mut self) -> f32 {
+        let target = self.step_level();
+        let (frames, rising) = match target > self.level {
+            true => (self.attack_frames, true),
+            false => (self.release_frames, false),
+        };
+        self.level = match frames < 1.0 {
+            true => target,
+            false if rising => (self.level + 1.0 / frames).min(target),
+            false => (self.level - 1.0 / frames).max(target),
+        };
+        self.level
+    }
+}
+
+impl BufferConsumerNode for GateNode {}
+
+impl Node for GateNode {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if self.effect_mix.on_event(self.node_id, event) {
+            return;
+        }
+        match event {
+            NodeEvent::Broadcast(BroadcastControl::TransportPosition { beat, bpm, .. }) => {
+                self.beat = *beat;
+                self.bpm = *bpm;
+            }
+            NodeEvent::Broadcast(BroadcastControl::Stop) => {
+                self.beat = 0.0;
+            }
+            _ => {}
+        }
+        self.consumer.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        let buffer_size = buffer.len();
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+        intermediate_slice.fill(0.0);
+        self.consumer.fill_buffer(intermediate_slice);
+
+        // Currently only-supported channel configuration
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        let beats_per_frame = self.bpm / (60.0 * consts::PLAYBACK_SAMPLE_RATE as f64);
+        for frame_index in 0..buffer_size / consts::CHANNEL_COUNT {
+            let level = self.next_level();
+            let gain = EffectMix::blend(self.effect_mix.next_wet(), 1.0, level);
+            self.beat += beats_per_frame;
+            let i = frame_index * consts::CHANNEL_COUNT;
+            buffer[i] += gain * self.intermediate_buffer[i];
+            buffer[i + 1] += gain * self.intermediate_buffer[i + 1];
+        }
+    }
+}
+
+impl BufferConsumer for GateNode {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        let mut gate = Self::new(
+            Some(self.node_id),
+            self.pattern.clone(),
+            self.steps_per_beat,
+            self.bpm as f32,
+            0.0,
+            0.0,
+            consumer,
+        )?;
+        gate.effect_mix = self.effect_mix;
+        gate.attack_frames = self.attack_frames;
+        gate.release_frames = self.release_frames;
+        gate.beat = self.beat;
+        Ok(Box::new(gate))
+    }
+}
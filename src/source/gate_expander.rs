@@ -0,0 +1,177 @@
+use crate::{
+    consts, BufferConsumer, BufferConsumerNode, EffectMix, Error, GainReductionMeter, Node,
+    NodeControlEvent, NodeEvent,
+};
+
+// How long the detected level takes to fall away after the source goes quiet, so that the
+// gate does not close each time a waveform crosses zero
+const DETECTOR_RELEASE_SECONDS: f32 = 0.01;
+
+// Fraction of the remaining distance covered per frame by a smoothing time, or all of it
+// when instant
+fn smoothing_coefficient(seconds: f32) -> f32 {
+    let frames = seconds * consts::PLAYBACK_SAMPLE_RATE as f32;
+    if frames < 1.0 {
+        return 1.0;
+    }
+    1.0 - (-1.0 / frames).exp()
+}
+
+/// Turns its source down further while it is quiet, to suppress the low-level hiss of
+/// layered noise and samples, or to cut reverb tails short for gated drums. Above the
+/// threshold the source passes unchanged; below it, each step down in level becomes the
+/// ratio times as large a step, so that a ratio of 2 is a gentle expander and large ratios
+/// act as a noise gate. The gain rises to open over the attack time and falls to close over
+/// the release time.
+pub struct GateExpanderNode {
+    node_id: u64,
+    effect_mix: EffectMix,
+    threshold: f32,
+    ratio: f32,
+    attack_coefficient: f32,
+    release_coefficient: f32,
+    detector_decay: f32,
+    level: f32,
+    gain: f32,
+    meter: Option<GainReductionMeter>,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    intermediate_buffer: Vec<f32>,
+}
+
+impl GateExpanderNode {
+    pub fn new(
+        node_id: Option<u64>,
+        threshold: f32,
+        ratio: f32,
+        attack_time: f32,
+        release_time: f32,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            effect_mix: EffectMix::default(),
+            threshold: threshold.max(0.0),
+            ratio: ratio.max(1.0),
+            attack_coefficient: smoothing_coefficient(attack_time),
+            release_coefficient: smoothing_coefficient(release_time),
+            detector_decay: 1.0 - smoothing_coefficient(DETECTOR_RELEASE_SECONDS),
+            level: 0.0,
+            gain: 0.0,
+            meter: None,
+            consumer,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        }
+    }
+
+    /// Get a meter of the gain reduction applied from here on, which may also be had by
+    /// sending a MeterGainReduction control event.
+    pub fn meter(&mut self) -> GainReductionMeter {
+        self.meter
+            .get_or_insert_with(|| GainReductionMeter::new().for_new_voice())
+            .clone()
+    }
+
+    // Gain for the detected level, found from the level's ratio to the threshold so that
+    // each decibel below it becomes ratio decibels
+    #[inline]
+    fn target_gain(&self) -> f32 {
+        if self.level >= self.threshold {
+            return 1.0;
+        }
+        (self.level / self.threshold).powf(self.ratio - 1.0)
+    }
+}
+
+impl BufferConsumerNode for GateExpanderNode {}
+
+impl Node for GateExpanderNode {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if self.effect_mix.on_event(self.node_id, event) {
+            return;
+        }
+        if let NodeEvent::NodeControl {
+            node_id,
+            event: NodeControlEvent::MeterGainReduction(meter),
+        } = event
+        {
+            if *node_id == self.node_id {
+                self.meter = Some(meter.for_new_voice());
+                return;
+            }
+        }
+        self.consumer.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        let buffer_size = buffer.len();
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+        intermediate_slice.fill(0.0);
+        self.consumer.fill_buffer(intermediate_slice);
+
+        // Currently only-supported channel configuration
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        let mut min_gain: f32 = 1.0;
+        let mut gain = 1.0;
+        for frame_index in 0..buffer_size / consts::CHANNEL_COUNT {
+            let i = frame_index * consts::CHANNEL_COUNT;
+            let (left, right) = (self.intermediate_buffer[i], self.intermediate_buffer[i + 1]);
+            self.level = left
+                .abs()
+                .max(right.abs())
+                .max(self.level * self.detector_decay);
+            let target = self.target_gain();
+            let coefficient = match target > self.gain {
+                true => self.attack_coefficient,
+                false => self.release_coefficient,
+            };
+            self.gain += (target - self.gain) * coefficient;
+            gain = EffectMix::blend(self.effect_mix.next_wet(), 1.0, self.gain);
+            min_gain = min_gain.min(gain);
+            buffer[i] += gain * left;
+            buffer[i + 1] += gain * right;
+        }
+        // A voice that has finished holds no reading, so that it does not mask the others
+        if !self.consumer.is_active() {
+            gain = 1.0;
+        }
+        let peak_reduction_db = match self.meter.as_ref() {
+            Some(meter) => meter.record(gain, min_gain),
+            None => GainReductionMeter::reduction_db_of(min_gain),
+        };
+        crate::report_gain_reduction(peak_reduction_db);
+    }
+}
+
+impl BufferConsumer for GateExpanderNode {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        let mut node = Self::new(
+            Some(self.node_id),
+            self.threshold,
+            self.ratio,
+            0.0,
+            0.0,
+            consumer,
+        );
+        node.effect_mix = self.effect_mix;
+        node.meter = self.meter.as_ref().map(GainReductionMeter::for_new_voice);
+        node.attack_coefficient = self.attack_coefficient;
+        node.release_coefficient = self.release_coefficient;
+        Ok(Box::new(node))
+    }
+}
@@ -1,20 +1,20 @@
 use midly::{num::u24, Fps, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
 
 pub fn log_loaded_midi(smf: &Smf) {
-    println!("MIDI: {}", get_log_for_header(smf));
+    log_info!("MIDI", "{}", get_log_for_header(smf));
     for track_events in smf.tracks.iter() {
         for event in track_events.iter() {
             if let Some(message) = get_log_for_event(event) {
-                println!("MIDI: {}", message);
+                log_info!("MIDI", "{}", message);
             }
         }
     }
-    println!("MIDI: File loaded.");
+    log_info!("MIDI", "File loaded.");
     if smf.tracks.is_empty() {
-        println!("WARNING: MIDI: There are no tracks to play.");
+        log_warning!("MIDI", "There are no tracks to play.");
     } else {
         for (index, track) in smf.tracks.iter().enumerate() {
-            println!("MIDI: Track {} has {} events.", index, track.len());
+            log_info!("MIDI", "Track {} has {} events.", index, track.len());
         }
     }
 }
@@ -0,0 +1,134 @@
+use std::cell::Cell;
+use std::sync::{
+    atomic::{AtomicU32, AtomicUsize, Ordering},
+    Arc,
+};
+
+// Voices of one node that are metered separately; any beyond this share the last
+const MAX_METERED_VOICES: usize = 32;
+
+thread_local! {
+    // Greatest gain reduction reported by a node while rendering on this thread
+    static RENDER_GAIN_REDUCTION_DB: Cell<f32> = const { Cell::new(0.0) };
+}
+
+/// Called by dynamics nodes as they fill a buffer with the peak gain reduction they applied,
+/// whether or not they have a meter, so that the greatest reduction applied anywhere in the
+/// graph can be found with take_gain_reduction.
+pub fn report_gain_reduction(reduction_db: f32) {
+    RENDER_GAIN_REDUCTION_DB.with(|greatest| greatest.set(greatest.get().max(reduction_db)));
+}
+
+/// Called on the thread rendering a graph, after filling a buffer, to get the greatest gain
+/// reduction reported while doing so. Reporting starts again from nothing for the next.
+pub fn take_gain_reduction() -> f32 {
+    RENDER_GAIN_REDUCTION_DB.with(|greatest| greatest.replace(0.0))
+}
+
+// Bits of non-negative f32 values, whose order as integers matches their order as floats
+#[derive(Debug, Default)]
+struct VoiceReduction {
+    reduction_db: AtomicU32,
+    peak_reduction_db: AtomicU32,
+}
+
+#[derive(Debug)]
+struct MeterVoices {
+    voices: [VoiceReduction; MAX_METERED_VOICES],
+    voice_count: AtomicUsize,
+}
+
+/// Reports how far a dynamics node is turning its source down, in decibels, for metering.
+/// Dynamics nodes take one from a MeterGainReduction control event sent to them, and
+/// record into it once per buffer; it may be read from any thread without blocking.
+/// Each voice of the node, such as in a SoundFont, records its own reading; the meter
+/// reports the greatest, and each voice's on request. Custom dynamics nodes may record
+/// into one too, taking a place for each voice with for_new_voice.
+#[derive(Clone, Debug)]
+pub struct GainReductionMeter {
+    voices: Arc<MeterVoices>,
+    voice: usize,
+}
+
+impl Default for GainReductionMeter {
+    fn default() -> Self {
+        Self {
+            voices: Arc::new(MeterVoices {
+                voices: std::array::from_fn(|_| VoiceReduction::default()),
+                voice_count: AtomicUsize::new(0),
+            }),
+            voice: 0,
+        }
+    }
+}
+
+impl GainReductionMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The gain reduction at the end of the last buffer, 0 when the source passes unchanged.
+    pub fn reduction_db(&self) -> f32 {
+        self.voices()
+            .iter()
+            .map(|voice| f32::from_bits(voice.reduction_db.load(Ordering::Relaxed)))
+            .fold(0.0, f32::max)
+    }
+
+    /// The greatest gain reduction since this was last called, resetting it to 0.
+    pub fn take_peak_reduction_db(&self) -> f32 {
+        self.voices()
+            .iter()
+            .map(|voice| f32::from_bits(voice.peak_reduction_db.swap(0, Ordering::Relaxed)))
+            .fold(0.0, f32::max)
+    }
+
+    /// The number of voices metered, each with a reading of its own.
+    pub fn voice_count(&self) -> usize {
+        self.voices().len()
+    }
+
+    /// The gain reduction of one voice at the end of the last buffer it played.
+    pub fn voice_reduction_db(&self, voice: usize) -> Option<f32> {
+        self.voices()
+            .get(voice)
+            .map(|voice| f32::from_bits(voice.reduction_db.load(Ordering::Relaxed)))
+    }
+
+    fn voices(&self) -> &[VoiceReduction] {
+        let count = self.voices.voice_count.load(Ordering::Relaxed);
+        &self.voices.voices[0..count.min(MAX_METERED_VOICES)]
+    }
+
+    /// A meter sharing these readings, for a voice of its own to record into.
+    pub fn for_new_voice(&self) -> Self {
+        let voice = self.voices.voice_count.fetch_add(1, Ordering::Relaxed);
+        Self {
+            voices: Arc::clone(&self.voices),
+            voice: voice.min(MAX_METERED_VOICES - 1),
+        }
+    }
+
+    pub(crate) fn reduction_db_of(gain: f32) -> f32 {
+        match gain < 1.0 {
+            true => -20.0 * gain.max(1e-10).log10(),
+            false => 0.0,
+        }
+    }
+
+    /// Record the gain applied by a voice at the end of a buffer and the lowest gain it
+    /// applied during it, where a gain of 1 passes the source unchanged. Return the peak
+    /// reduction, to be passed on to report_gain_reduction.
+    pub fn record(&self, last_gain: f32, min_gain: f32) -> f32 {
+        let voice = &self.voices.voices[self.voice];
+        voice.reduction_db.store(
+            Self::reduction_db_of(last_gain).to_bits(),
+            Ordering::Relaxed,
+        );
+        let peak_reduction_db = Self::reduction_db_of(min_gain);
+        voice
+            .peak_reduction_db
+            .fetch_max(peak_reduction_db.to_bits(), Ordering::Relaxed);
+        peak_reduction_db
+    }
+}
@@ -0,0 +1,129 @@
+use crate::consts;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+const CHANNELS: usize = 16;
+const NOTES: usize = 128;
+
+// Each slot packs the velocity into the top byte, with zero meaning silent, and the frame
+// the note started on into the rest
+const FRAME_BITS: u32 = 56;
+const FRAME_MASK: u64 = (1 << FRAME_BITS) - 1;
+
+/// A note the MIDI player has started and not yet stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SoundingNote {
+    pub channel: usize,
+    pub note: u8,
+    pub velocity: f32,
+    pub start_seconds: f64,
+}
+
+#[derive(Debug)]
+struct ActivityState {
+    notes: Vec<AtomicU64>,
+    frames_played: AtomicU64,
+}
+
+/// Host-side view of which notes a MidiSource is playing, usable from any thread for
+/// visualization or debugging. Get one from MidiSource::activity, or from
+/// MidiHandle::activity once the source is inside a graph. Only notes the player itself
+/// sends are tracked, and times count from the start of playback in real time, so they
+/// keep counting through seeks, loops and playback rate changes.
+#[derive(Clone, Debug)]
+pub struct MidiActivity {
+    state: Arc<ActivityState>,
+}
+
+impl Default for MidiActivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiActivity {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(ActivityState {
+                notes: (0..CHANNELS * NOTES).map(|_| AtomicU64::new(0)).collect(),
+                frames_played: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Seconds of playback so far, as of the last buffer filled.
+    pub fn position_seconds(&self) -> f64 {
+        let frames = self.state.frames_played.load(Ordering::Relaxed);
+        frames as f64 / consts::PLAYBACK_SAMPLE_RATE as f64
+    }
+
+    /// All notes sounding now, ordered by channel and then note.
+    pub fn sounding_notes(&self) -> Vec<SoundingNote> {
+        (0..CHANNELS)
+            .flat_map(|channel| self.sounding_notes_on(channel))
+            .collect()
+    }
+
+    /// Notes sounding now on one channel, ordered by note.
+    pub fn sounding_notes_on(&self, channel: usize) -> Vec<SoundingNote> {
+        if channel >= CHANNELS {
+            return vec![];
+        }
+        let slots = &self.state.notes[channel * NOTES..(channel + 1) * NOTES];
+        slots
+            .iter()
+            .enumerate()
+            .filter_map(|(note, slot)| {
+                let packed = slot.load(Ordering::Relaxed);
+                let velocity = (packed >> FRAME_BITS) as u8;
+                if velocity == 0 {
+                    return None;
+                }
+                Some(SoundingNote {
+                    channel,
+                    note: note as u8,
+                    velocity: velocity as f32 / 127.0,
+                    start_seconds: (packed & FRAME_MASK) as f64
+                        / consts::PLAYBACK_SAMPLE_RATE as f64,
+                })
+            })
+            .collect()
+    }
+
+    // Called from the audio thread as the player sends notes
+    pub(crate) fn note_on(&self, channel: usize, note: u8, velocity: f32, frame: u64) {
+        let Some(slot) = self.slot(channel, note) else {
+            return;
+        };
+        let velocity = ((velocity * 127.0).round() as u64).clamp(1, 127);
+        slot.store(
+            (velocity << FRAME_BITS) | (frame & FRAME_MASK),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub(crate) fn note_off(&self, channel: usize, note: u8) {
+        if let Some(slot) = self.slot(channel, note) {
+            slot.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn all_notes_off(&self) {
+        for slot in self.state.notes.iter() {
+            slot.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn set_frames_played(&self, frames: u64) {
+        self.state.frames_played.store(frames, Ordering::Relaxed);
+    }
+
+    fn slot(&self, channel: usize, note: u8) -> Option<&AtomicU64> {
+        if channel >= CHANNELS || note as usize >= NOTES {
+            return None;
+        }
+        Some(&self.state.notes[channel * NOTES + note as usize])
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+const TIMING_CLOCK: u8 = 0xf8;
+const START: u8 = 0xfa;
+const CONTINUE: u8 = 0xfb;
+const STOP: u8 = 0xfc;
+
+// MIDI clock sends 24 pulses per quarter note
+const PULSES_PER_BEAT: f64 = 24.0;
+
+// A longer gap between pulses means the sender paused, so measuring starts over
+const MAX_PULSE_GAP_MICROS: u64 = 500_000;
+
+// Weight of each new pulse interval in the running average, settling over about a beat
+const SMOOTHING: f64 = 1.0 / PULSES_PER_BEAT;
+
+#[derive(Debug, Default)]
+struct PulseTiming {
+    last_pulse_micros: Option<u64>,
+    average_interval_micros: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct ClockState {
+    // Bits of an f64, with zero meaning no tempo is known yet
+    bpm_bits: AtomicU64,
+    is_running: AtomicBool,
+    timing: Mutex<PulseTiming>,
+}
+
+/// A tempo set from outside the graph, which MidiSource can follow in place of the tempo in
+/// its file so that it plays in time with other music software. Feed it MIDI clock messages
+/// with receive_message, or connect it straight to an input port with the midir feature.
+/// Other sync sources, such as an Ableton Link session, can drive it through set_bpm.
+/// Only the tempo is followed; the host decides what to do with start and stop messages,
+/// which is_running reports.
+#[derive(Clone, Debug, Default)]
+pub struct ExternalClock {
+    state: Arc<ClockState>,
+}
+
+impl ExternalClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The latest tempo, or None until one has been measured or set.
+    pub fn bpm(&self) -> Option<f64> {
+        match f64::from_bits(self.state.bpm_bits.load(Ordering::Acquire)) {
+            bpm if bpm > 0.0 => Some(bpm),
+            _ => None,
+        }
+    }
+
+    pub fn set_bpm(&self, bpm: f64) {
+        if !bpm.is_finite() || bpm <= 0.0 {
+            log_warning!("MIDI", "Clock tempo {} must be above zero", bpm);
+            return;
+        }
+        self.state.bpm_bits.store(bpm.to_bits(), Ordering::Release);
+    }
+
+    /// Whether the last transport message received was Start or Continue, rather than Stop.
+    pub fn is_running(&self) -> bool {
+        self.state.is_running.load(Ordering::Acquire)
+    }
+
+    /// Handle a message from a MIDI input, stamped with the time it arrived in
+    /// microseconds from any fixed point. Messages other than clock and transport messages
+    /// are ignored.
+    pub fn receive_message(&self, message: &[u8], timestamp_micros: u64) {
+        let Some(&status) = message.first() else {
+            return;
+        };
+        let mut timing = self
+            .state
+            .timing
+            .lock()
+            .expect("Could not lock clock timing");
+        match status {
+            TIMING_CLOCK => {
+                if let Some(last_pulse) = timing.last_pulse_micros {
+                    let interval = timestamp_micros.saturating_sub(last_pulse);
+                    if interval > 0 && interval < MAX_PULSE_GAP_MICROS {
+                        let average = match timing.average_interval_micros {
+                            Some(average) => average + (interval as f64 - average) * SMOOTHING,
+                            None => interval as f64,
+                        };
+                        timing.average_interval_micros = Some(average);
+                        self.set_bpm(60_000_000.0 / (average * PULSES_PER_BEAT));
+                    }
+                }
+                timing.last_pulse_micros = Some(timestamp_micros);
+            }
+            START | CONTINUE => {
+                timing.last_pulse_micros = None;
+                self.state.is_running.store(true, Ordering::Release);
+            }
+            STOP => {
+                timing.last_pulse_micros = None;
+                self.state.is_running.store(false, Ordering::Release);
+            }
+            _ => {}
+        }
+    }
+
+    /// Feed the clock from the first input port whose name contains the given text. The
+    /// clock follows the port for as long as the returned connection is kept.
+    #[cfg(all(feature = "midir", not(target_arch = "wasm32")))]
+    pub fn connect(&self, port_name: &str) -> Result<midir::MidiInputConnection<()>, crate::Error> {
+        let mut input = midir::MidiInput::new("midi-graph")?;
+        input.ignore(midir::Ignore::None);
+        let ports = input.ports();
+        let port = ports
+            .iter()
+            .find(|port| {
+                input
+                    .port_name(port)
+                    .is_ok_and(|name| name.contains(port_name))
+            })
+            .ok_or_else(|| {
+                crate::Error::User(format!("No MIDI input port matching {}", port_name))
+            })?;
+        let clock = self.clone();
+        input
+            .connect(
+                port,
+                "midi-graph-clock",
+                move |timestamp, message, _| clock.receive_message(message, timestamp),
+                (),
+            )
+            .map_err(|error| crate::Error::MidirConnect(error.kind()))
+    }
+}
@@ -1,4 +1,4 @@
-use crate::Error;
+use crate::{consts, source::midi::util, Error};
 use midly::{MetaMessage, Smf, TrackEventKind};
 
 #[derive(Copy, Clone, Debug)]
@@ -7,6 +7,18 @@ pub struct TimelineCue {
     pub cue: Cue,
 }
 
+/// A timeline cue with its position in the track resolved, as played at the file's own
+/// tempo; tempo ramps and playback rate changes made while playing are not included.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TimedCue {
+    pub event_index: usize,
+    pub cue: Cue,
+    pub tick: u64,
+    /// Position in quarter notes, if the file uses metrical timing
+    pub beats: Option<f64>,
+    pub seconds: f64,
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Cue {
     Anchor(u32),
@@ -38,7 +50,7 @@ impl TimelineCue {
                                 break;
                             }
                             if end_index == start_index {
-                                println!("WARNING: MIDI: Cannot parse anchor label");
+                                log_warning!("MIDI", "Cannot parse anchor label");
                             } else {
                                 let anchor_index =
                                     &string[start_index..end_index].parse().map_err(|_| {
@@ -64,7 +76,7 @@ impl TimelineCue {
                                 break;
                             }
                             if end_index == start_index {
-                                println!("WARNING: MIDI: Cannot parse seek label");
+                                log_warning!("MIDI", "Cannot parse seek label");
                             } else {
                                 let anchor_index =
                                     &string[start_index..end_index].parse().map_err(|_| {
@@ -85,7 +97,7 @@ impl TimelineCue {
                             index += 1;
                         }
                         _ => {
-                            println!("WARNING: MIDI: Unknown data in cue point label");
+                            log_warning!("MIDI", "Unknown data in cue point label");
                             break;
                         }
                     }
@@ -94,4 +106,30 @@ impl TimelineCue {
         }
         Ok(cues)
     }
+
+    /// Find the positions of cues in a track of the file they were read from.
+    pub fn resolve(smf: &Smf, track_index: usize, cues: &[Self]) -> Result<Vec<TimedCue>, Error> {
+        let samples_per_tick = util::get_samples_per_tick(smf, track_index)?;
+        let ticks_per_beat = util::get_ticks_per_beat(smf);
+        let mut event_ticks = Vec::with_capacity(smf.tracks[track_index].len());
+        let mut tick = 0;
+        for event in smf.tracks[track_index].iter() {
+            tick += u32::from(event.delta) as u64;
+            event_ticks.push(tick);
+        }
+        cues.iter()
+            .map(|cue| {
+                let tick = *event_ticks.get(cue.event_index).ok_or_else(|| {
+                    Error::User(format!("MIDI: No event {} for cue", cue.event_index))
+                })?;
+                Ok(TimedCue {
+                    event_index: cue.event_index,
+                    cue: cue.cue,
+                    tick,
+                    beats: ticks_per_beat.map(|ticks| tick as f64 / ticks),
+                    seconds: tick as f64 * samples_per_tick / consts::PLAYBACK_SAMPLE_RATE as f64,
+                })
+            })
+            .collect()
+    }
 }
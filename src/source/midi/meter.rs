@@ -0,0 +1,125 @@
+use crate::source::midi::util;
+use midly::{MetaMessage, Smf, TrackEventKind};
+
+/// A time signature from a MIDI file, such as 3/4 or 6/8.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TimeSignature {
+    pub numerator: u8,
+    pub denominator: u8,
+}
+
+impl TimeSignature {
+    /// Get the length of a bar in quarter notes, such as 3 for 3/4 and 3 for 6/8.
+    pub fn quarter_notes_per_bar(&self) -> f64 {
+        self.numerator as f64 * 4.0 / self.denominator as f64
+    }
+}
+
+/// Common time, assumed until a file gives a time signature.
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self {
+            numerator: 4,
+            denominator: 4,
+        }
+    }
+}
+
+// A time signature taking effect at some tick, with the number of bars before it
+#[derive(Copy, Clone)]
+struct MeterChange {
+    tick: f64,
+    bar: f64,
+    time_signature: TimeSignature,
+}
+
+// The time signatures through a track, for finding where its bars fall
+pub(crate) struct MeterMap {
+    ticks_per_beat: f64,
+    changes: Vec<MeterChange>,
+}
+
+impl MeterMap {
+    // Read the time signatures played alongside a track, in a file with metrical timing
+    pub fn from_smf(smf: &Smf, track_index: usize, ticks_per_beat: f64) -> Self {
+        let mut found: Vec<(u64, TimeSignature)> = vec![];
+        for track in util::get_tracks_played_with(smf, track_index) {
+            let mut tick = 0;
+            for event in track.iter() {
+                tick += u32::from(event.delta) as u64;
+                if let TrackEventKind::Meta(MetaMessage::TimeSignature(
+                    numerator,
+                    denominator_power,
+                    _,
+                    _,
+                )) = event.kind
+                {
+                    if numerator == 0 || denominator_power > 7 {
+                        log_warning!("MIDI", "Ignoring invalid time signature");
+                        continue;
+                    }
+                    let time_signature = TimeSignature {
+                        numerator,
+                        denominator: 1 << denominator_power,
+                    };
+                    found.push((tick, time_signature));
+                }
+            }
+        }
+        found.sort_by_key(|(tick, _)| *tick);
+
+        let mut map = Self {
+            ticks_per_beat,
+            changes: vec![MeterChange {
+                tick: 0.0,
+                bar: 0.0,
+                time_signature: TimeSignature::default(),
+            }],
+        };
+        for (tick, time_signature) in found.into_iter() {
+            let tick = tick as f64;
+            let bar = map.bar_at(tick);
+            match map.changes.last_mut() {
+                Some(last) if last.tick == tick => last.time_signature = time_signature,
+                _ => map.changes.push(MeterChange {
+                    tick,
+                    bar,
+                    time_signature,
+                }),
+            }
+        }
+        map
+    }
+
+    fn ticks_per_bar(&self, change: &MeterChange) -> f64 {
+        self.ticks_per_beat * change.time_signature.quarter_notes_per_bar()
+    }
+
+    fn change_at_tick(&self, tick: f64) -> &MeterChange {
+        self.changes
+            .iter()
+            .rev()
+            .find(|change| change.tick <= tick)
+            .unwrap_or(&self.changes[0])
+    }
+
+    pub fn time_signature_at(&self, tick: f64) -> TimeSignature {
+        self.change_at_tick(tick).time_signature
+    }
+
+    // Position in bars from the start of the track
+    pub fn bar_at(&self, tick: f64) -> f64 {
+        let change = self.change_at_tick(tick);
+        change.bar + (tick - change.tick) / self.ticks_per_bar(change)
+    }
+
+    pub fn tick_of_bar(&self, bar: f64) -> f64 {
+        let change = self
+            .changes
+            .iter()
+            .rev()
+            .find(|change| change.bar <= bar)
+            .unwrap_or(&self.changes[0]);
+        change.tick + (bar - change.bar) * self.ticks_per_bar(change)
+    }
+}
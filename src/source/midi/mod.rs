@@ -1,14 +1,26 @@
+pub mod activity;
+pub mod clock;
 pub mod cue;
+pub mod meter;
 pub mod util;
 
 use crate::{
-    consts, BroadcastControl, BufferConsumer, BufferConsumerNode, Cue, Error, Node,
-    NodeControlEvent, NodeEvent, NoteEvent, TimelineCue,
+    consts,
+    source::{midi::meter::MeterMap, noise::Xorshift32, routing::EventRoutes},
+    BroadcastControl, BufferConsumer, BufferConsumerNode, Cue, Error, ExternalClock, MidiActivity,
+    Node, NodeControlEvent, NodeEvent, NoteEvent, TimeSignature, TimedCue, TimelineCue,
 };
-use midly::{MetaMessage, MidiMessage, Smf, TrackEvent, TrackEventKind};
+use crossbeam_channel::Sender;
+use midly::{Format, MetaMessage, MidiMessage, Smf, TrackEvent, TrackEventKind};
 use std::cell::RefCell;
 use std::collections::HashMap;
 
+// Slots for every note on every MIDI channel, for numbering the voices played on each
+const VOICE_SLOT_COUNT: usize = 16 * 128;
+
+// Channel 10 in General MIDI, which plays percussion and so is never transposed
+const PERCUSSION_CHANNEL: usize = 9;
+
 #[cfg(debug_assertions)]
 use crate::source::log;
 
@@ -24,26 +36,46 @@ enum EventAction {
     },
 }
 
+// Timing and velocity changes applied to the track as it plays
+#[derive(Clone, Copy, Default)]
+struct Feel {
+    swing: f32,
+    humanize_time: f32,
+    humanize_velocity: f32,
+}
+
 pub struct MidiSourceBuilder {
     node_id: Option<u64>,
     smf: Smf<'static>,
     track_no: usize,
     timeline_cues: Vec<TimelineCue>,
     channel_sources: HashMap<usize, Box<dyn BufferConsumerNode + Send + 'static>>,
+    feel: Feel,
 }
 
 impl MidiSourceBuilder {
     /// Capture a non-static Smf, extracting MIDI event that contain text strings.
     /// Do not call to_static() on the Smf object before passing it in here!
     pub fn new(node_id: Option<u64>, smf: Smf) -> Result<Self, Error> {
+        Self::with_sequence(node_id, smf, 0)
+    }
+
+    /// As for new, but playing one sequence of a type 2 file, which holds several
+    /// independent sequences rather than tracks played together. Other files have only the
+    /// sequence at index 0.
+    pub fn with_sequence(
+        node_id: Option<u64>,
+        smf: Smf,
+        sequence_index: usize,
+    ) -> Result<Self, Error> {
         #[cfg(debug_assertions)]
         log::log_loaded_midi(&smf);
 
-        let track_no = util::choose_track_index(&smf)?;
+        let track_no = util::choose_sequence_track(&smf, sequence_index)?;
         let timeline_cues = TimelineCue::from_smf(&smf, track_no)?;
         let static_smf = smf.to_static();
-        if smf.tracks.len() > track_no + 1 {
-            println!("WARNING: MIDI: Only the first track containing notes will be used");
+        if smf.header.format != Format::Sequential && smf.tracks.len() > track_no + 1 {
+            log_warning!("MIDI", "Only the first track containing notes will be used");
         }
         Ok(Self {
             node_id,
@@ -51,6 +83,7 @@ impl MidiSourceBuilder {
             track_no,
             timeline_cues,
             channel_sources: HashMap::new(),
+            feel: Feel::default(),
         })
     }
 
@@ -63,6 +96,21 @@ impl MidiSourceBuilder {
         self
     }
 
+    /// Delay every off-beat eighth note by a fraction of an eighth note, from 0 for
+    /// straight timing up to 0.5 for a hard shuffle. Needs metrical timing in the file.
+    pub fn swing(mut self, swing: f32) -> Self {
+        self.feel.swing = swing.clamp(0.0, 0.5);
+        self
+    }
+
+    /// Start each note late by a random time up to the given seconds, and scale its
+    /// velocity by a random amount up to the given fraction either way.
+    pub fn humanize(mut self, time_seconds: f32, velocity_amount: f32) -> Self {
+        self.feel.humanize_time = time_seconds.max(0.0);
+        self.feel.humanize_velocity = velocity_amount.clamp(0.0, 1.0);
+        self
+    }
+
     pub fn build(self) -> Result<MidiSource, Error> {
         MidiSource::new(
             self.node_id,
@@ -70,10 +118,19 @@ impl MidiSourceBuilder {
             self.track_no,
             self.timeline_cues,
             self.channel_sources,
+            self.feel,
         )
     }
 }
 
+/// Plays the notes of a MIDI track into the sources for its channels. The host may be sent
+/// the node ID when the end of the track is reached, through a channel given in a
+/// NotifyPlaybackComplete control event or to set_completion_sender. A PlaybackRate
+/// control event speeds up or slows down the whole track without changing its pitch.
+/// Given an ExternalClock to follow, the track plays at the clock's tempo whenever it has
+/// one, taking priority over the file's tempo and any tempo ramp. A Transpose control
+/// event shifts the notes of every channel but the percussion channel by some semitones,
+/// and a MasterTune control event retunes every channel's sources by some cents.
 pub struct MidiSource {
     smf: RefCell<Smf<'static>>,
     node_id: u64,
@@ -81,10 +138,46 @@ pub struct MidiSource {
     timeline_cues: Vec<TimelineCue>,
     queued_ideal_seek: Option<u32>,
     channel_sources: HashMap<usize, Box<dyn BufferConsumerNode + Send + 'static>>,
+    routes: EventRoutes<usize>,
     has_finished: bool,
+    completion_sender: Option<Sender<u64>>,
+    activity: Option<MidiActivity>,
+    clock: Option<ExternalClock>,
     samples_per_tick: f64,
+    ticks_per_beat: Option<f64>,
+    meter: Option<MeterMap>,
+    tempo_ramp: Option<TempoRamp>,
+    playback_rate: f64,
+    rate_ramp: Option<RateRamp>,
+    frames_played: u64,
+    feel: Feel,
+    random: Xorshift32,
+    last_event_offset_ticks: f64,
+    next_event_offset_ticks: Option<f64>,
     next_event_index: usize,
-    event_ticks_progress: isize,
+    event_ticks_progress: f64,
+    song_ticks_at_last_event: u64,
+    ticks_played: f64,
+    voices_started: Vec<u32>,
+    voices_ended: Vec<u32>,
+    transpose: i8,
+    note_transposes: Vec<i8>,
+}
+
+// Tempo change measured against ticks played, which keeps counting through seeks
+struct TempoRamp {
+    start_tick: f64,
+    end_tick: f64,
+    from_bpm: f64,
+    to_bpm: f64,
+}
+
+// Playback rate change measured against frames played, which keeps counting through seeks
+struct RateRamp {
+    start_frame: u64,
+    end_frame: u64,
+    from_rate: f64,
+    to_rate: f64,
 }
 
 impl MidiSource {
@@ -94,17 +187,28 @@ impl MidiSource {
         track_no: usize,
         timeline_cues: Vec<TimelineCue>,
         channel_sources: HashMap<usize, Box<dyn BufferConsumerNode + Send + 'static>>,
+        feel: Feel,
     ) -> Result<Self, Error> {
-        let samples_per_tick = util::get_samples_per_tick(&smf)?;
+        let samples_per_tick = util::get_samples_per_tick(&smf, track_no)?;
+        let ticks_per_beat = util::get_ticks_per_beat(&smf);
+        let meter = ticks_per_beat.map(|ticks| MeterMap::from_smf(&smf, track_no, ticks));
         let mut sources: HashMap<usize, Box<dyn BufferConsumerNode + Send + 'static>> =
             HashMap::new();
 
         for (channel, source) in channel_sources.into_iter() {
             if sources.insert(channel, source).is_some() {
-                println!("WARNING: MIDI: Channel specified again will overwrite previous value");
+                log_warning!(
+                    "MIDI",
+                    "Channel specified again will overwrite previous value"
+                );
             }
         }
 
+        let routes = EventRoutes::new(
+            sources
+                .iter()
+                .map(|(channel, source)| (*channel, source.as_ref())),
+        );
         Ok(Self {
             smf: RefCell::new(smf),
             node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
@@ -112,13 +216,360 @@ impl MidiSource {
             timeline_cues,
             queued_ideal_seek: None,
             channel_sources: sources,
+            routes,
             has_finished: false,
+            completion_sender: None,
+            activity: None,
+            clock: None,
             samples_per_tick,
+            ticks_per_beat,
+            meter,
+            tempo_ramp: None,
+            playback_rate: 1.0,
+            rate_ramp: None,
+            frames_played: 0,
+            feel,
+            random: Xorshift32::new(0x3c6ef372),
+            last_event_offset_ticks: 0.0,
+            next_event_offset_ticks: None,
             next_event_index: 0,
-            event_ticks_progress: 0,
+            event_ticks_progress: 0.0,
+            song_ticks_at_last_event: 0,
+            ticks_played: 0.0,
+            voices_started: vec![0; VOICE_SLOT_COUNT],
+            voices_ended: vec![0; VOICE_SLOT_COUNT],
+            transpose: 0,
+            note_transposes: vec![0; VOICE_SLOT_COUNT],
         })
     }
 
+    fn current_bpm(&self, ticks_per_beat: f64) -> f64 {
+        60.0 * consts::PLAYBACK_SAMPLE_RATE as f64 / (self.samples_per_tick * ticks_per_beat)
+    }
+
+    fn song_tick(&self) -> f64 {
+        self.song_ticks_at_last_event as f64 + self.event_ticks_progress
+    }
+
+    /// Get the ticks per quarter note of the file, if it uses metrical timing.
+    pub fn ticks_per_beat(&self) -> Option<f64> {
+        self.ticks_per_beat
+    }
+
+    /// Get the time signature where the track has reached, if the file uses metrical
+    /// timing. This is 4/4 until the file gives one.
+    pub fn time_signature(&self) -> Option<TimeSignature> {
+        self.meter
+            .as_ref()
+            .map(|meter| meter.time_signature_at(self.song_tick()))
+    }
+
+    // Tell the channel sources where the track has reached, for nodes keeping time with it
+    fn send_transport_position(&mut self) {
+        let (Some(ticks_per_beat), Some(meter)) = (self.ticks_per_beat, &self.meter) else {
+            return;
+        };
+        let song_tick = self.song_tick();
+        let event = NodeEvent::Broadcast(BroadcastControl::TransportPosition {
+            beat: song_tick / ticks_per_beat,
+            bar: meter.bar_at(song_tick),
+            bpm: self.current_bpm(ticks_per_beat) * self.playback_rate,
+            time_signature: meter.time_signature_at(song_tick),
+            ticks_per_beat,
+        });
+        for source in self.channel_sources.values_mut() {
+            source.on_event(&event);
+        }
+    }
+
+    // Begin changing tempo at the next bar line, reaching the new tempo after the given
+    // number of bars. Changes are applied at buffer and event boundaries.
+    fn schedule_tempo_ramp(&mut self, bpm: f32, bars: u32) {
+        if bpm.is_nan() || bpm <= 0.0 {
+            log_warning!("MIDI", "Tempo {} must be above zero", bpm);
+            return;
+        }
+        let (Some(ticks_per_beat), Some(meter)) = (self.ticks_per_beat, &self.meter) else {
+            log_warning!("MIDI", "Tempo ramps need metrical timing");
+            return;
+        };
+        let song_tick = self.song_tick();
+        let next_bar = meter.bar_at(song_tick).ceil();
+        let next_bar_tick = meter.tick_of_bar(next_bar);
+        let ramp_ticks = meter.tick_of_bar(next_bar + bars as f64) - next_bar_tick;
+        let start_tick = self.ticks_played + next_bar_tick - song_tick;
+        self.tempo_ramp = Some(TempoRamp {
+            start_tick,
+            end_tick: start_tick + ramp_ticks,
+            from_bpm: self.current_bpm(ticks_per_beat),
+            to_bpm: bpm as f64,
+        });
+    }
+
+    fn update_tempo(&mut self) {
+        if let (Some(clock), Some(ticks_per_beat)) = (&self.clock, self.ticks_per_beat) {
+            if let Some(bpm) = clock.bpm() {
+                let samples_per_beat = 60.0 * consts::PLAYBACK_SAMPLE_RATE as f64 / bpm;
+                self.samples_per_tick = samples_per_beat / ticks_per_beat;
+                return;
+            }
+        }
+        let (Some(ramp), Some(ticks_per_beat)) = (&self.tempo_ramp, self.ticks_per_beat) else {
+            return;
+        };
+        if self.ticks_played < ramp.start_tick {
+            return;
+        }
+        let progress = match ramp.end_tick > ramp.start_tick {
+            true => {
+                ((self.ticks_played - ramp.start_tick) / (ramp.end_tick - ramp.start_tick)).min(1.0)
+            }
+            false => 1.0,
+        };
+        let bpm = ramp.from_bpm + (ramp.to_bpm - ramp.from_bpm) * progress;
+        let samples_per_beat = 60.0 * consts::PLAYBACK_SAMPLE_RATE as f64 / bpm;
+        self.samples_per_tick = samples_per_beat / ticks_per_beat;
+        if progress >= 1.0 {
+            self.tempo_ramp = None;
+        }
+    }
+
+    // Move the playback rate to a new value over some seconds, scaling the time every tick
+    // takes; changes are applied at buffer and event boundaries
+    fn schedule_rate_ramp(&mut self, rate: f32, seconds: f32) {
+        if rate.is_nan() || rate <= 0.0 {
+            log_warning!("MIDI", "Playback rate {} must be above zero", rate);
+            return;
+        }
+        let duration_frames = (seconds.max(0.0) * consts::PLAYBACK_SAMPLE_RATE as f32) as u64;
+        self.rate_ramp = Some(RateRamp {
+            start_frame: self.frames_played,
+            end_frame: self.frames_played + duration_frames,
+            from_rate: self.playback_rate,
+            to_rate: rate as f64,
+        });
+        self.update_rate();
+    }
+
+    fn update_rate(&mut self) {
+        let Some(ramp) = &self.rate_ramp else {
+            return;
+        };
+        let progress = match ramp.end_frame > ramp.start_frame {
+            true => ((self.frames_played - ramp.start_frame) as f64
+                / (ramp.end_frame - ramp.start_frame) as f64)
+                .min(1.0),
+            false => 1.0,
+        };
+        self.playback_rate = ramp.from_rate + (ramp.to_rate - ramp.from_rate) * progress;
+        if progress >= 1.0 {
+            self.rate_ramp = None;
+        }
+    }
+
+    // Position of a song tick once swing and any humanizing delay are applied
+    fn timing_tick(&self, song_tick: u64, offset_ticks: f64) -> f64 {
+        let tick = song_tick as f64;
+        let Some(ticks_per_beat) = self.ticks_per_beat else {
+            return tick + offset_ticks;
+        };
+        if self.feel.swing <= 0.0 {
+            return tick + offset_ticks;
+        }
+
+        // The first eighth note of each beat stretches and the second shrinks to match
+        let swing = self.feel.swing as f64;
+        let half_beat = ticks_per_beat / 2.0;
+        let beat_start = (tick / ticks_per_beat).floor() * ticks_per_beat;
+        let position = tick - beat_start;
+        let swung_position = match position < half_beat {
+            true => position * (1.0 + swing),
+            false => half_beat * (1.0 + swing) + (position - half_beat) * (1.0 - swing),
+        };
+        beat_start + swung_position + offset_ticks
+    }
+
+    // Choose the humanizing delay of the next event the first time it is looked at
+    fn prepare_next_event_offset(&mut self) {
+        if self.next_event_offset_ticks.is_some() {
+            return;
+        }
+        let is_note_on = {
+            let smf = self.smf.borrow();
+            let next_event = &smf.tracks[self.track_no][self.next_event_index];
+            matches!(
+                next_event.kind,
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { vel, .. },
+                    ..
+                } if u8::from(vel) > 0
+            )
+        };
+        let offset_ticks = match is_note_on && self.feel.humanize_time > 0.0 {
+            true => {
+                let max_ticks = self.feel.humanize_time as f64
+                    * consts::PLAYBACK_SAMPLE_RATE as f64
+                    / self.samples_per_tick;
+                0.5 * (self.random.next_bipolar() as f64 + 1.0) * max_ticks
+            }
+            false => 0.0,
+        };
+        self.next_event_offset_ticks = Some(offset_ticks);
+    }
+
+    fn humanize_velocity(&mut self, action: &mut Option<EventAction>) {
+        if self.feel.humanize_velocity <= 0.0 {
+            return;
+        }
+        if let Some(EventAction::ChannelNodeEvent {
+            event:
+                NodeEvent::Note {
+                    event: NoteEvent::NoteOn { vel },
+                    ..
+                },
+            ..
+        }) = action
+        {
+            if *vel > 0.0 {
+                let scale = 1.0 + self.feel.humanize_velocity * self.random.next_bipolar();
+                *vel = (*vel * scale).clamp(1.0 / 127.0, 1.0);
+            }
+        }
+    }
+
+    // Number each note with a voice ID made from its channel, key and the count of notes
+    // started on that key so far; a note-off takes the ID of the oldest voice still held
+    // on its key, so that overlapping notes of the same key end in the order they began
+    fn assign_voice_id(&mut self, action: &mut Option<EventAction>) {
+        let Some(EventAction::ChannelNodeEvent {
+            channel,
+            event:
+                NodeEvent::Note {
+                    note,
+                    voice_id,
+                    event,
+                },
+        }) = action
+        else {
+            return;
+        };
+        let slot = (*channel % 16) * 128 + (*note as usize % 128);
+        let is_note_on = matches!(event, NoteEvent::NoteOn { vel } if *vel > 0.0);
+        let count = match is_note_on {
+            true => &mut self.voices_started[slot],
+            false => {
+                if self.voices_ended[slot] == self.voices_started[slot] {
+                    return;
+                }
+                &mut self.voices_ended[slot]
+            }
+        };
+        *voice_id = Some(((slot as u64) << 32) | *count as u64);
+        *count = count.wrapping_add(1);
+    }
+
+    // Shift a note by the transpose in effect when it began, so that a note-off ends the
+    // note it belongs to even if the transpose has changed since, and drop notes shifted
+    // beyond the keyboard
+    fn transpose_note(&mut self, action: &mut Option<EventAction>) {
+        let Some(EventAction::ChannelNodeEvent {
+            channel,
+            event: NodeEvent::Note { note, event, .. },
+        }) = action
+        else {
+            return;
+        };
+        if *channel == PERCUSSION_CHANNEL {
+            return;
+        }
+        let slot = (*channel % 16) * 128 + (*note as usize % 128);
+        if matches!(event, NoteEvent::NoteOn { vel } if *vel > 0.0) {
+            self.note_transposes[slot] = self.transpose;
+        }
+        let transposed = *note as i32 + self.note_transposes[slot] as i32;
+        match (0..=127).contains(&transposed) {
+            true => *note = transposed as u8,
+            false => *action = None,
+        }
+    }
+
+    // Forget held voices, once every note has been silenced
+    fn clear_voices(&mut self) {
+        self.voices_started.fill(0);
+        self.voices_ended.fill(0);
+    }
+
+    /// Get the cues of the track with their positions, such as for a scrub bar.
+    pub fn timed_cues(&self) -> Result<Vec<TimedCue>, Error> {
+        TimelineCue::resolve(&self.smf.borrow(), self.track_no, &self.timeline_cues)
+    }
+
+    /// Get a view of the notes this source is playing, which stays valid once the source
+    /// is moved to the audio thread.
+    pub fn activity(&mut self) -> MidiActivity {
+        self.activity.get_or_insert_with(MidiActivity::new).clone()
+    }
+
+    // Record a note the player is sending for the activity view, if there is one
+    fn track_activity(&self, channel: usize, event: &NodeEvent) {
+        let Some(activity) = &self.activity else {
+            return;
+        };
+        match event {
+            NodeEvent::Note {
+                note,
+                event: NoteEvent::NoteOn { vel },
+                ..
+            } if *vel > 0.0 => activity.note_on(channel, *note, *vel, self.frames_played),
+            NodeEvent::Note { note, .. } => activity.note_off(channel, *note),
+            _ => {}
+        }
+    }
+
+    fn clear_activity(&self) {
+        if let Some(activity) = &self.activity {
+            activity.all_notes_off();
+        }
+    }
+
+    /// Play at the tempo of an external clock, or at the file's own tempo again for None.
+    pub fn follow_clock(&mut self, clock: Option<ExternalClock>) {
+        if clock.is_some() && self.ticks_per_beat.is_none() {
+            log_warning!("MIDI", "Following a clock needs metrical timing");
+        }
+        self.clock = clock;
+    }
+
+    pub fn set_completion_sender(&mut self, sender: Sender<u64>) {
+        self.completion_sender = Some(sender);
+    }
+
+    pub(crate) fn has_finished(&self) -> bool {
+        self.has_finished
+    }
+
+    // Fill only the channel sources, such as to let notes ring out after the track has ended
+    pub(crate) fn fill_channel_tails(&mut self, buffer: &mut [f32]) {
+        for source in self.channel_sources.values_mut() {
+            source.fill_buffer(buffer);
+        }
+    }
+
+    // Return to the start of the track at its original tempo, with all channels silenced
+    pub(crate) fn rewind(&mut self) -> Result<(), Error> {
+        self.samples_per_tick = util::get_samples_per_tick(&self.smf.borrow(), self.track_no)?;
+        self.queued_ideal_seek = None;
+        self.tempo_ramp = None;
+        self.has_finished = false;
+        self.next_event_index = 0;
+        self.event_ticks_progress = 0.0;
+        self.last_event_offset_ticks = 0.0;
+        self.next_event_offset_ticks = None;
+        self.song_ticks_at_last_event = 0;
+        self.clear_voices();
+        Ok(())
+    }
+
     fn seek_to_anchor(&mut self, anchor: u32) {
         self.queued_ideal_seek = None;
         if let Some(index) = self.timeline_cues.iter().find_map(|c| match c {
@@ -131,12 +582,21 @@ impl MidiSource {
             },
             _ => None,
         }) {
-            self.event_ticks_progress = 0;
+            self.event_ticks_progress = 0.0;
+            self.last_event_offset_ticks = 0.0;
+            self.next_event_offset_ticks = None;
             self.next_event_index = index + 1;
+            self.song_ticks_at_last_event = self.smf.borrow().tracks[self.track_no]
+                [0..self.next_event_index]
+                .iter()
+                .map(|event| u32::from(event.delta) as u64)
+                .sum();
             let broadcast_cutoff = NodeEvent::Broadcast(BroadcastControl::NotesOff);
             for (_, source) in self.channel_sources.iter_mut() {
                 source.on_event(&broadcast_cutoff);
             }
+            self.clear_activity();
+            self.clear_voices();
         };
     }
 
@@ -144,6 +604,7 @@ impl MidiSource {
         match event {
             None => {}
             Some(EventAction::ChannelNodeEvent { channel, event }) => {
+                self.track_activity(*channel, event);
                 let Some(source) = self.channel_sources.get_mut(channel) else {
                     return;
                 };
@@ -179,6 +640,7 @@ impl MidiSource {
                 channel: u8::from(channel) as usize,
                 event: NodeEvent::Note {
                     note: u8::from(key),
+                    voice_id: None,
                     event: NoteEvent::NoteOn {
                         vel: u8::from(vel) as f32 / 127.0,
                     },
@@ -191,11 +653,22 @@ impl MidiSource {
                 channel: u8::from(channel) as usize,
                 event: NodeEvent::Note {
                     note: u8::from(key),
+                    voice_id: None,
                     event: NoteEvent::NoteOff {
                         vel: u8::from(vel) as f32 / 127.0,
                     },
                 },
             }),
+            TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::Controller { controller, value },
+            } => Some(EventAction::ChannelNodeEvent {
+                channel: u8::from(channel) as usize,
+                event: NodeEvent::Broadcast(BroadcastControl::Controller {
+                    controller: u8::from(controller),
+                    value: u8::from(value) as f32 / 127.0,
+                }),
+            }),
             TrackEventKind::Meta(MetaMessage::CuePoint(_)) => {
                 let is_ideal_point = self.timeline_cues.iter().any(|c| match c {
                     TimelineCue {
@@ -226,10 +699,13 @@ impl MidiSource {
         }
     }
 
-    fn fill_all_channels(&mut self, buffer: &mut [f32]) {
+    // Fill the buffer up to the end of the track, returning the number of data points
+    // played, which is the whole buffer unless the track ends within it
+    pub(crate) fn fill_all_channels(&mut self, buffer: &mut [f32]) -> usize {
         if self.has_finished {
-            return;
+            return 0;
         }
+        let buffer_size = buffer.len();
         #[cfg(debug_assertions)]
         assert_eq!(buffer.len() % consts::CHANNEL_COUNT, 0);
 
@@ -237,42 +713,80 @@ impl MidiSource {
         #[cfg(debug_assertions)]
         assert_eq!(consts::CHANNEL_COUNT, 2);
 
+        self.update_tempo();
+        self.update_rate();
+        self.send_transport_position();
+
+        let mut remaining_buffer = buffer;
         loop {
-            let reached_note_event = {
+            self.update_tempo();
+            self.update_rate();
+            self.prepare_next_event_offset();
+            let samples_per_tick = self.samples_per_tick / self.playback_rate;
+            let (data_points_filled, mut reached_note_event) = {
                 let smf = self.smf.borrow();
                 let track_data = &smf.tracks[self.track_no];
                 let next_event = &track_data[self.next_event_index];
-                let event_ticks_delta = u32::from(next_event.delta) as isize;
-                let ticks_until_event = event_ticks_delta - self.event_ticks_progress;
-                let samples_until_event =
-                    (ticks_until_event as f64 * self.samples_per_tick) as usize;
-                let samples_available_per_channel = buffer.len() / consts::CHANNEL_COUNT;
+                let event_ticks_delta = u32::from(next_event.delta);
+                let next_event_offset_ticks = self.next_event_offset_ticks.unwrap_or(0.0);
+                let timing_ticks_delta = self.timing_tick(
+                    self.song_ticks_at_last_event + event_ticks_delta as u64,
+                    next_event_offset_ticks,
+                ) - self
+                    .timing_tick(self.song_ticks_at_last_event, self.last_event_offset_ticks);
+                let ticks_until_event = (timing_ticks_delta - self.event_ticks_progress).max(0.0);
+                let samples_until_event = (ticks_until_event * samples_per_tick) as usize;
+                let samples_available_per_channel = remaining_buffer.len() / consts::CHANNEL_COUNT;
 
                 {
                     if samples_until_event > samples_available_per_channel {
                         for (_, source) in self.channel_sources.iter_mut() {
-                            source.fill_buffer(buffer);
+                            source.fill_buffer(remaining_buffer);
                         }
-                        self.event_ticks_progress +=
-                            (samples_available_per_channel as f64 / self.samples_per_tick) as isize;
-                        return;
+                        let ticks_filled = samples_available_per_channel as f64 / samples_per_tick;
+                        self.event_ticks_progress += ticks_filled;
+                        self.ticks_played += ticks_filled;
+                        self.frames_played += samples_available_per_channel as u64;
+                        if let Some(activity) = &self.activity {
+                            activity.set_frames_played(self.frames_played);
+                        }
+                        return buffer_size;
                     }
 
                     let buffer_samples_to_fill = samples_until_event * consts::CHANNEL_COUNT;
                     for (_, source) in self.channel_sources.iter_mut() {
-                        source.fill_buffer(&mut buffer[0..buffer_samples_to_fill]);
+                        source.fill_buffer(&mut remaining_buffer[0..buffer_samples_to_fill]);
                     }
+                    self.ticks_played += ticks_until_event;
+                    self.frames_played += samples_until_event as u64;
                 }
 
-                self.event_ticks_progress = 0;
+                self.event_ticks_progress = 0.0;
+                self.song_ticks_at_last_event += event_ticks_delta as u64;
+                self.last_event_offset_ticks = next_event_offset_ticks;
+                self.next_event_offset_ticks = None;
                 self.next_event_index += 1;
                 if self.next_event_index >= track_data.len() {
                     self.has_finished = true;
-                    return;
+                    if let Some(sender) = &self.completion_sender {
+                        let _ = sender.try_send(self.node_id);
+                    }
+                    if let Some(activity) = &self.activity {
+                        activity.set_frames_played(self.frames_played);
+                    }
+                    return buffer_size - remaining_buffer.len()
+                        + samples_until_event * consts::CHANNEL_COUNT;
                 }
 
-                self.note_event_from_midi_event(self.next_event_index - 1, next_event)
+                (
+                    samples_until_event * consts::CHANNEL_COUNT,
+                    self.note_event_from_midi_event(self.next_event_index - 1, next_event),
+                )
             };
+            remaining_buffer = &mut std::mem::take(&mut remaining_buffer)[data_points_filled..];
+            self.humanize_velocity(&mut reached_note_event);
+            self.assign_voice_id(&mut reached_note_event);
+            self.transpose_note(&mut reached_note_event);
             self.on_event_reached(&reached_note_event);
         }
     }
@@ -285,22 +799,87 @@ impl Node for MidiSource {
         self.node_id
     }
 
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.routes.collect_node_ids(ids)
+    }
+
     fn on_event(&mut self, event: &NodeEvent) {
+        if let NodeEvent::Broadcast(BroadcastControl::Stop) = event {
+            if let Err(error) = self.rewind() {
+                log_error!("MIDI", "Could not rewind: {}", error);
+            }
+        }
+        if let NodeEvent::Broadcast(BroadcastControl::NotesOff | BroadcastControl::Stop) = event {
+            self.clear_activity();
+            self.clear_voices();
+        }
         if let NodeEvent::NodeControl {
             node_id,
-            event: NodeControlEvent::SeekWhenIdeal { to_anchor },
+            event: control,
         } = event
         {
             if *node_id == self.node_id {
-                self.queued_ideal_seek = *to_anchor;
-                return;
+                match control {
+                    NodeControlEvent::SeekWhenIdeal { to_anchor } => {
+                        self.queued_ideal_seek = *to_anchor;
+                        return;
+                    }
+                    NodeControlEvent::TempoRamp { bpm, bars } => {
+                        self.schedule_tempo_ramp(*bpm, *bars);
+                        return;
+                    }
+                    NodeControlEvent::NotifyPlaybackComplete(sender) => {
+                        self.completion_sender = Some(sender.clone());
+                        return;
+                    }
+                    NodeControlEvent::PlaybackRate { rate, seconds } => {
+                        self.schedule_rate_ramp(*rate, *seconds);
+                        return;
+                    }
+                    NodeControlEvent::TrackActivity(activity) => {
+                        self.activity = Some(activity.clone());
+                        return;
+                    }
+                    NodeControlEvent::FollowClock(clock) => {
+                        self.follow_clock(clock.clone());
+                        return;
+                    }
+                    NodeControlEvent::Transpose(semitones) => {
+                        self.transpose = *semitones;
+                        return;
+                    }
+                    NodeControlEvent::MasterTune(cents) => {
+                        let tune =
+                            NodeEvent::Broadcast(BroadcastControl::MasterTune { cents: *cents });
+                        for source in self.channel_sources.values_mut() {
+                            source.on_event(&tune);
+                        }
+                        return;
+                    }
+                    _ => {}
+                }
             }
+            for channel in self.routes.targets(*node_id) {
+                if let Some(source) = self.channel_sources.get_mut(&channel) {
+                    source.on_event(event);
+                }
+            }
+            return;
         }
         for (_, source) in self.channel_sources.iter_mut() {
             source.on_event(event);
         }
     }
 
+    fn is_active(&self) -> bool {
+        !self.has_finished
+            || self
+                .channel_sources
+                .values()
+                .any(|source| source.is_active())
+    }
+
     fn fill_buffer(&mut self, buffer: &mut [f32]) {
         self.fill_all_channels(buffer);
     }
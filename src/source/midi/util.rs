@@ -1,11 +1,13 @@
-use crate::{consts::PLAYBACK_SAMPLE_RATE, Error};
-use midly::{Fps, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+use crate::{consts::PLAYBACK_SAMPLE_RATE, Error, TimedCue, TimelineCue};
+use midly::{
+    Format, Fps, MetaMessage, MidiMessage, Smf, Timing, Track, TrackEvent, TrackEventKind,
+};
 
-pub fn get_samples_per_tick(smf: &Smf) -> Result<f64, Error> {
+pub fn get_samples_per_tick(smf: &Smf, track_index: usize) -> Result<f64, Error> {
     match smf.header.timing {
         Timing::Metrical(ticks_per_beat) => {
             let found_micros_per_beat: Option<f64> =
-                scan_for_data(smf, |event_kind| match event_kind {
+                scan_for_data(smf, track_index, |event_kind| match event_kind {
                     TrackEventKind::Meta(MetaMessage::Tempo(micros)) => {
                         Some(u32::from(*micros) as f64)
                     }
@@ -16,7 +18,7 @@ pub fn get_samples_per_tick(smf: &Smf) -> Result<f64, Error> {
                 None => {
                     // TODO - This is a fallback for Ardour not exporting
                     // tempo meta events. This is not ideal.
-                    println!("WARNING: MIDI: Tempo meta event not found, assuming 120 BPM");
+                    log_warning!("MIDI", "Tempo meta event not found, assuming 120 BPM");
                     1000000.0 / (120.0 / 60.0)
                 }
             };
@@ -40,8 +42,51 @@ pub fn get_samples_per_tick(smf: &Smf) -> Result<f64, Error> {
     }
 }
 
-fn scan_for_data<T>(smf: &Smf, extractor: fn(&TrackEventKind) -> Option<T>) -> Option<T> {
-    for track in smf.tracks.iter() {
+/// Get ticks per quarter note, if the file uses metrical timing.
+pub fn get_ticks_per_beat(smf: &Smf) -> Option<f64> {
+    match smf.header.timing {
+        Timing::Metrical(ticks_per_beat) => Some(u16::from(ticks_per_beat) as f64),
+        Timing::Timecode(_, _) => None,
+    }
+}
+
+/// Get the length of a bar in quarter notes, from the first time signature found.
+/// Assumes 4/4 time if there is none.
+pub fn get_quarter_notes_per_bar(smf: &Smf, track_index: usize) -> f64 {
+    let found_time_signature: Option<(u8, u8)> =
+        scan_for_data(smf, track_index, |event_kind| match event_kind {
+            TrackEventKind::Meta(MetaMessage::TimeSignature(
+                numerator,
+                denominator_power,
+                _,
+                _,
+            )) => Some((*numerator, *denominator_power)),
+            _ => None,
+        });
+    match found_time_signature {
+        Some((numerator, denominator_power)) => {
+            numerator as f64 * 4.0 / 2.0f64.powi(denominator_power as i32)
+        }
+        None => 4.0,
+    }
+}
+
+/// Get the tracks played alongside the given one, whose tempo and time signature events
+/// apply to it. In a type 2 file this is only that track, since each is a sequence of its
+/// own.
+pub fn get_tracks_played_with<'a, 'b>(smf: &'b Smf<'a>, track_index: usize) -> &'b [Track<'a>] {
+    match smf.header.format {
+        Format::Sequential => &smf.tracks[track_index..=track_index],
+        Format::SingleTrack | Format::Parallel => &smf.tracks[..],
+    }
+}
+
+fn scan_for_data<T>(
+    smf: &Smf,
+    track_index: usize,
+    extractor: fn(&TrackEventKind) -> Option<T>,
+) -> Option<T> {
+    for track in get_tracks_played_with(smf, track_index).iter() {
         for event in track.iter() {
             if let Some(value) = extractor(&event.kind) {
                 return Some(value);
@@ -51,6 +96,14 @@ fn scan_for_data<T>(smf: &Smf, extractor: fn(&TrackEventKind) -> Option<T>) -> O
     None
 }
 
+/// Get the cues of the track a MidiSource would play from the file, with their positions.
+/// For a type 2 file, this is the first sequence.
+pub fn get_timed_cues(smf: &Smf) -> Result<Vec<TimedCue>, Error> {
+    let track_index = choose_sequence_track(smf, 0)?;
+    let cues = TimelineCue::from_smf(smf, track_index)?;
+    TimelineCue::resolve(smf, track_index, &cues)
+}
+
 pub fn choose_track_index(smf: &Smf) -> Result<usize, Error> {
     if smf.tracks.is_empty() {
         return Err(Error::User("MIDI: No tracks in MIDI file".to_owned()));
@@ -76,3 +129,29 @@ pub fn choose_track_index(smf: &Smf) -> Result<usize, Error> {
         "MIDI: No tracks found with key on events".to_owned(),
     ))
 }
+
+/// Get the number of sequences in the file, each of which can be played by a MidiSource of
+/// its own. Only type 2 files have more than one.
+pub fn get_sequence_count(smf: &Smf) -> usize {
+    match smf.header.format {
+        Format::Sequential => smf.tracks.len(),
+        Format::SingleTrack | Format::Parallel => 1,
+    }
+}
+
+/// Get the index of the track to play for a sequence of the file. Each track of a type 2
+/// file is a sequence of its own, while other files have one sequence, played from their
+/// first track with notes in it.
+pub fn choose_sequence_track(smf: &Smf, sequence_index: usize) -> Result<usize, Error> {
+    let sequence_count = get_sequence_count(smf);
+    if sequence_index >= sequence_count {
+        return Err(Error::User(format!(
+            "MIDI: Sequence {} not found in file with {} sequences",
+            sequence_index, sequence_count
+        )));
+    }
+    match smf.header.format {
+        Format::Sequential => Ok(sequence_index),
+        Format::SingleTrack | Format::Parallel => choose_track_index(smf),
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{
+    consts, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node, NodeEvent, NoteEvent,
+};
+use crossbeam_channel::{bounded, Sender};
+use std::time::{Duration, Instant};
+
+// Messages waiting to be sent; any beyond this are dropped rather than block the audio thread
+const QUEUE_CAPACITY: usize = 1024;
+
+const CONTROL_CHANGE: u8 = 0xb0;
+const ALL_SOUND_OFF: u8 = 120;
+const ALL_NOTES_OFF: u8 = 123;
+
+// A short MIDI message and the time it should leave
+struct TimedMessage {
+    at: Instant,
+    bytes: [u8; 3],
+}
+
+/// Sends the notes it receives to an external MIDI output, such as a hardware synth or a
+/// DAW, rather than rendering any audio. Place one as the source for a MIDI channel to
+/// hear that channel through another instrument.
+///
+/// Each message is timed by the frame it arrives on, counted from the first buffer this
+/// node fills, and delayed by a latency which should match that of the audio output so
+/// that external instruments stay in time with the rest of the graph. Messages are sent
+/// from a worker thread, so the audio thread never waits on the port. Controller
+/// broadcasts are sent as control changes, and NotesOff and Stop broadcasts as All Notes
+/// Off and All Sound Off control changes.
+pub struct MidiOutputNode {
+    node_id: u64,
+    channel: u8,
+    latency: Duration,
+    frames_filled: u64,
+    started_at: Option<Instant>,
+    sender: Sender<TimedMessage>,
+}
+
+impl MidiOutputNode {
+    /// Connect to the first output port whose name contains the given text, sending on a
+    /// MIDI channel from 0 to 15.
+    pub fn connect(
+        node_id: Option<u64>,
+        port_name: &str,
+        channel: u8,
+        latency_seconds: f32,
+    ) -> Result<Self, Error> {
+        let output = midir::MidiOutput::new("midi-graph")?;
+        let ports = output.ports();
+        let port = ports
+            .iter()
+            .find(|port| {
+                output
+                    .port_name(port)
+                    .is_ok_and(|name| name.contains(port_name))
+            })
+            .ok_or_else(|| Error::User(format!("No MIDI output port matching {}", port_name)))?;
+        let mut connection = output
+            .connect(port, "midi-graph-out")
+            .map_err(|error| Error::MidirConnect(error.kind()))?;
+        Self::new(node_id, channel, latency_seconds, move |bytes| {
+            if let Err(error) = connection.send(bytes) {
+                log_warning!("MIDI", "Could not send to output port: {}", error);
+            }
+        })
+    }
+
+    /// Send messages through the given function instead of a port, such as to pass them
+    /// to another MIDI library. It is called from a worker thread.
+    pub fn new(
+        node_id: Option<u64>,
+        channel: u8,
+        latency_seconds: f32,
+        mut send: impl FnMut(&[u8]) + Send + 'static,
+    ) -> Result<Self, Error> {
+        if channel > 15 {
+            return Err(Error::User(format!(
+                "MIDI channel {} must be below 16",
+                channel
+            )));
+        }
+        let (sender, receiver) = bounded::<TimedMessage>(QUEUE_CAPACITY);
+        std::thread::spawn(move || {
+            while let Ok(message) = receiver.recv() {
+                let now = Instant::now();
+                if message.at > now {
+                    std::thread::sleep(message.at - now);
+                }
+                send(&message.bytes);
+            }
+
+            // Leave nothing hanging on the external instrument once the node is dropped
+            send(&[CONTROL_CHANGE | channel, ALL_NOTES_OFF, 0]);
+        });
+        Ok(Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            channel,
+            latency: Duration::from_secs_f32(latency_seconds.max(0.0)),
+            frames_filled: 0,
+            started_at: None,
+            sender,
+        })
+    }
+
+    fn queue(&mut self, status: u8, data_1: u8, data_2: u8) {
+        let started_at = *self.started_at.get_or_insert_with(Instant::now);
+        let offset = self.frames_filled as f64 / consts::PLAYBACK_SAMPLE_RATE as f64;
+        let message = TimedMessage {
+            at: started_at + Duration::from_secs_f64(offset) + self.latency,
+            bytes: [status | self.channel, data_1 & 0x7f, data_2 & 0x7f],
+        };
+        if self.sender.try_send(message).is_err() {
+            log_warning!("MIDI", "Output queue is full; dropping a message");
+        }
+    }
+}
+
+impl BufferConsumerNode for MidiOutputNode {}
+
+impl Node for MidiOutputNode {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        true
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        let velocity_byte = |vel: f32| (vel.clamp(0.0, 1.0) * 127.0).round() as u8;
+        match event {
+            NodeEvent::Note {
+                note,
+                event: NoteEvent::NoteOn { vel },
+                ..
+            } => self.queue(0x90, *note, velocity_byte(*vel)),
+            NodeEvent::Note {
+                note,
+                event: NoteEvent::NoteOff { vel },
+                ..
+            } => self.queue(0x80, *note, velocity_byte(*vel)),
+            NodeEvent::Broadcast(BroadcastControl::Controller { controller, value }) => {
+                self.queue(CONTROL_CHANGE, *controller, velocity_byte(*value));
+            }
+            NodeEvent::Broadcast(BroadcastControl::NotesOff) => {
+                self.queue(CONTROL_CHANGE, ALL_NOTES_OFF, 0);
+            }
+            NodeEvent::Broadcast(BroadcastControl::Stop) => {
+                self.queue(CONTROL_CHANGE, ALL_SOUND_OFF, 0);
+                self.queue(CONTROL_CHANGE, ALL_NOTES_OFF, 0);
+            }
+            _ => {}
+        }
+    }
+
+    // Always filled, since the frame count times the messages
+    fn is_active(&self) -> bool {
+        true
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        self.started_at.get_or_insert_with(Instant::now);
+        self.frames_filled += (buffer.len() / consts::CHANNEL_COUNT) as u64;
+    }
+}
+
+impl BufferConsumer for MidiOutputNode {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        Err(Error::User(
+            "MidiOutputNode cannot be duplicated".to_owned(),
+        ))
+    }
+}
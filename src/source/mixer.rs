@@ -1,4 +1,7 @@
-use crate::{consts, BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent, NodeEvent};
+use crate::{
+    consts, source::buffer, BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent,
+    NodeEvent,
+};
 
 pub struct MixerSource {
     node_id: u64,
@@ -32,6 +35,11 @@ impl Node for MixerSource {
         self.node_id
     }
 
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer_0.collect_node_ids(ids) && self.consumer_1.collect_node_ids(ids)
+    }
+
     fn on_event(&mut self, event: &NodeEvent) {
         if let NodeEvent::NodeControl {
             node_id,
@@ -47,25 +55,19 @@ impl Node for MixerSource {
         self.consumer_1.on_event(event);
     }
 
+    fn is_active(&self) -> bool {
+        self.consumer_0.is_active() || self.consumer_1.is_active()
+    }
+
     fn fill_buffer(&mut self, buffer: &mut [f32]) {
         let buffer_size = buffer.len();
-        let sample_count = buffer_size / consts::CHANNEL_COUNT;
         let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
         intermediate_slice.fill(0.0);
         self.consumer_0.fill_buffer(intermediate_slice);
-        let multiplier_0 = 1.0 - self.balance;
-        for i in 0..sample_count {
-            let index = i * 2;
-            buffer[index] += multiplier_0 * intermediate_slice[index];
-            buffer[index + 1] += multiplier_0 * intermediate_slice[index + 1];
-        }
+        buffer::add_scaled_buffer(buffer, intermediate_slice, 1.0 - self.balance);
         intermediate_slice.fill(0.0);
         self.consumer_1.fill_buffer(intermediate_slice);
-        for i in 0..sample_count {
-            let index = i * 2;
-            buffer[index] += self.balance * intermediate_slice[index];
-            buffer[index + 1] += self.balance * intermediate_slice[index + 1];
-        }
+        buffer::add_scaled_buffer(buffer, intermediate_slice, self.balance);
     }
 }
 
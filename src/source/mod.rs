@@ -1,33 +1,141 @@
+pub mod ab_compare;
+pub mod additive;
 pub mod async_receiver;
+pub mod buffer;
 pub mod combiner;
+pub mod convolution;
+pub mod crossfade;
+pub mod crossfeed;
+pub mod duck;
+pub mod effect_mix;
+pub mod effect_pool;
 pub mod envelope;
 pub mod fader;
+pub mod fm;
 pub mod font;
+pub mod gain;
+pub mod gate;
+pub mod gate_expander;
+pub mod meter;
 pub mod midi;
+#[cfg(all(feature = "midir", not(target_arch = "wasm32")))]
+pub mod midi_out;
 pub mod mixer;
+pub mod mod_matrix;
+pub mod multi_stage;
 pub mod noise;
+pub mod note_map;
 pub mod null;
 pub mod one_shot;
+pub mod parallel;
+pub mod param;
+pub mod pitch;
+pub mod pitch_shift;
+pub mod playlist;
+pub mod pluck;
+pub mod quantizer;
+pub mod ring_mod;
+pub mod routing;
 pub mod sawtooth;
+pub mod scope;
+pub mod sequencer;
 pub mod square;
+pub mod sync;
+pub mod tag;
+pub mod test_signal;
+pub mod tremolo;
 pub mod triangle;
+pub mod tuning;
+pub mod unison;
 pub mod util;
+pub mod vibrato;
 pub mod wav;
 
 #[cfg(debug_assertions)]
 pub mod log;
 
-use crate::{Error, Loop, RangeSource};
-use std::sync::atomic::{AtomicU64, Ordering};
+use crate::{
+    ChokeGroup, Error, ExternalClock, FadeChain, Loop, MidiActivity, NoteMapping, RangeSource,
+    Retrigger, Scale, TestSignal, TimeSignature, TuningTable,
+};
+use crossbeam_channel::Sender;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 
 const START_GENERATED_NODE_IDS: u64 = 0x10000;
 static NEXT_ID: AtomicU64 = AtomicU64::new(START_GENERATED_NODE_IDS);
 
+/// A node in an audio graph. This trait, along with BufferConsumer and BufferConsumerNode,
+/// is the stable surface for nodes defined outside this crate: new methods are only added
+/// with default implementations, so that custom nodes keep compiling across minor
+/// versions. A custom node implements the three required methods, BufferConsumer (whose
+/// duplicate may be left as its default) and the empty BufferConsumerNode:
+///
+/// ```
+/// use midi_graph::{BufferConsumer, BufferConsumerNode, Node, NodeEvent};
+///
+/// struct Silence {
+///     node_id: u64,
+/// }
+///
+/// impl BufferConsumerNode for Silence {}
+///
+/// impl Node for Silence {
+///     fn get_node_id(&self) -> u64 {
+///         self.node_id
+///     }
+///
+///     fn on_event(&mut self, _event: &NodeEvent) {}
+///
+///     fn fill_buffer(&mut self, _buffer: &mut [f32]) {}
+/// }
+///
+/// impl BufferConsumer for Silence {}
+/// ```
 pub trait Node {
+    /// ID by which control events address this node. Nodes made without a given ID take
+    /// one from new_node_id, which never collides with IDs chosen in configs.
     fn get_node_id(&self) -> u64;
+
+    /// Handle an event, then pass it on to any child nodes. Control events may still reach
+    /// nodes other than their target, so a node must ignore those addressed to other IDs.
     fn on_event(&mut self, event: &NodeEvent);
+
+    /// Add this node's output into the buffer of interleaved stereo samples, which holds
+    /// at most consts::BUFFER_SIZE frames. Called on the audio thread, so it should
+    /// neither block nor allocate.
     fn fill_buffer(&mut self, buffer: &mut [f32]);
 
+    /// Whether this node may still produce output. Once this returns false, such as when
+    /// a voice's release tail has fully decayed, filling it can be skipped until the next
+    /// event it receives.
+    fn is_active(&self) -> bool {
+        true
+    }
+
+    /// Add the IDs of this node and of every node beneath it, so that nodes with several
+    /// children can send control events only down the branch holding their target (see
+    /// EventRoutes). Return false if they cannot all be listed, such as where voices are
+    /// made while playing; that branch then receives every control event. The default
+    /// lists nothing and returns false, which is always safe.
+    fn collect_node_ids(&self, _ids: &mut Vec<u64>) -> bool {
+        false
+    }
+
+    /// Fractional frames of the last buffer at which this node's oscillator began a new
+    /// cycle, for hard-syncing other oscillators to it (see sync_to). Nodes without an
+    /// oscillator return none.
+    fn cycle_starts(&self) -> &[f32] {
+        &[]
+    }
+
+    /// Restart this node's oscillator at the given fractional frames of the next buffer it
+    /// fills, as taken from the cycle_starts of an oscillator it is hard-synced to. Nodes
+    /// without an oscillator ignore this.
+    fn sync_to(&mut self, _cycle_starts: &[f32]) {}
+
     fn new_node_id() -> u64
     where
         Self: Sized,
@@ -37,9 +145,17 @@ pub trait Node {
 }
 
 pub trait BufferConsumer {
-    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error>;
+    /// Make an independent copy of this node and its children, keeping the same node IDs,
+    /// such as to give each voice of a polyphonic graph its own copy. Nodes that cannot be
+    /// copied, such as those holding the receiving end of a channel, return an error, as
+    /// does the default implementation.
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        Err(Error::User("This node cannot be duplicated".to_owned()))
+    }
 }
 
+/// Any node that can be placed in a graph. Implement it, with no methods, for every type
+/// implementing both Node and BufferConsumer.
 pub trait BufferConsumerNode: BufferConsumer + Node {}
 
 #[derive(Clone)]
@@ -78,8 +194,15 @@ impl NoteRange {
 #[derive(Clone, Debug)]
 pub enum NodeEvent {
     Broadcast(BroadcastControl),
+    /// A note starting or ending. A voice ID given with a NoteOn should be given again
+    /// with the matching NoteOff, so that when the same note is started again before it
+    /// ends, the NoteOff ends only the voice it was started with. A NoteOff without a voice
+    /// ID ends every voice playing the note. Outside this crate, make one with note_on or
+    /// note_off, so that fields added later do not break the code making it.
+    #[non_exhaustive]
     Note {
         note: u8,
+        voice_id: Option<u64>,
         event: NoteEvent,
     },
     NodeControl {
@@ -88,9 +211,68 @@ pub enum NodeEvent {
     },
 }
 
-#[derive(PartialEq, Copy, Clone, Debug)]
+impl NodeEvent {
+    pub fn note_on(note: u8, vel: f32) -> Self {
+        NodeEvent::Note {
+            note,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel },
+        }
+    }
+
+    pub fn note_off(note: u8, vel: f32) -> Self {
+        NodeEvent::Note {
+            note,
+            voice_id: None,
+            event: NoteEvent::NoteOff { vel },
+        }
+    }
+
+    /// Give a note event the ID of the voice it starts or ends. Other events are unchanged.
+    pub fn with_voice_id(self, voice_id: u64) -> Self {
+        match self {
+            NodeEvent::Note { note, event, .. } => NodeEvent::Note {
+                note,
+                voice_id: Some(voice_id),
+                event,
+            },
+            event => event,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
 pub enum BroadcastControl {
+    /// Release all playing notes, allowing them to fade out naturally.
     NotesOff,
+    /// Silence everything immediately and return to the start of any sequence.
+    Stop,
+    /// Set a config param on every node bound to it; see ParamValue and util::param_id.
+    SetParam { param_id: u64, value: f32 },
+    /// Move every param named in a config snapshot to its value there over some seconds;
+    /// see Config::snapshots and util::snapshot_id.
+    RecallSnapshot { snapshot_id: u64, seconds: f32 },
+    /// A MIDI controller moved to a value from 0 to 1, as played from a MIDI file's
+    /// channel or sent by the host.
+    Controller { controller: u8, value: f32 },
+    /// Where a playing MIDI track has reached, in beats and bars from its start, and its
+    /// tempo including any playback rate, sent to each channel's source at the start of
+    /// every buffer for nodes that keep time with the track. Also gives the time signature
+    /// at that point and the ticks per quarter note of the file.
+    TransportPosition {
+        beat: f64,
+        bar: f64,
+        bpm: f64,
+        time_signature: TimeSignature,
+        ticks_per_beat: f64,
+    },
+    /// Play notes at the frequencies of a tuning table from here on, sent by a TuningNode
+    /// to the generators within it.
+    Tuning(Arc<TuningTable>),
+    /// Raise or lower every note by some cents from the tuning it would otherwise play,
+    /// as sent to its channels or voices by a MIDI source or font given a MasterTune
+    /// control event.
+    MasterTune { cents: f32 },
 }
 
 #[derive(PartialEq, Copy, Clone, Debug)]
@@ -104,7 +286,32 @@ pub enum NodeControlEvent {
     MixerBalance(f32),
     Volume(f32),
     Fade { from: f32, to: f32, seconds: f32 },
+    FadeChain(FadeChain),
+    NotifyFadeComplete(Sender<u64>),
+    NotifyPlaybackComplete(Sender<u64>),
     SeekWhenIdeal { to_anchor: Option<u32> },
+    AbToggle,
+    AbSelect { use_b: bool },
+    MeterGainReduction(meter::GainReductionMeter),
+    PlayEffect { index: usize, volume: f32, pan: f32 },
+    MaxVoices(usize),
+    CrossfeedAmount(f32),
+    Crossfade { to: f32, seconds: f32 },
+    Duck { seconds: f32 },
+    TempoRamp { bpm: f32, bars: u32 },
+    PlaybackRate { rate: f32, seconds: f32 },
+    PitchShift { semitones: f32, cents: f32 },
+    SetBypass(bool),
+    SetWet(f32),
+    TrackActivity(MidiActivity),
+    FollowClock(Option<ExternalClock>),
+    TestSignal(TestSignal),
+    NoteMap(NoteMapping),
+    SetScale { scale: Scale, root: u8 },
+    Transpose(i8),
+    MasterTune(f32),
+    Retrigger(Retrigger),
+    ChokeGroup(Option<ChokeGroup>),
     Unknown,
 }
 
@@ -0,0 +1,281 @@
+use crate::{
+    consts,
+    source::{noise::Xorshift32, param::CONTROL_BLOCK_FRAMES},
+    util, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, LfoShape, ModRoute,
+    ModSource, ModulationTarget, Node, NodeEvent, NoteEvent,
+};
+use std::f32::consts::TAU;
+
+const CONTROLLER_COUNT: usize = 128;
+
+#[derive(Clone, Copy, PartialEq)]
+enum EnvelopeStage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+// Running state of one route's source; only the LFO and envelope sources need any
+#[derive(Clone, Copy)]
+struct RouteState {
+    phase: f32,
+    stage: EnvelopeStage,
+    level: f32,
+}
+
+impl Default for RouteState {
+    fn default() -> Self {
+        Self {
+            phase: 0.0,
+            stage: EnvelopeStage::Idle,
+            level: 0.0,
+        }
+    }
+}
+
+#[inline]
+fn lfo_value(shape: LfoShape, phase: f32) -> f32 {
+    match shape {
+        LfoShape::Sine => (phase * TAU).sin(),
+        LfoShape::Triangle => 4.0 * ((phase + 0.75).fract() - 0.5).abs() - 1.0,
+        LfoShape::Square => match phase < 0.5 {
+            true => 1.0,
+            false => -1.0,
+        },
+        LfoShape::Sawtooth => 2.0 * phase - 1.0,
+    }
+}
+
+// Move an envelope on by some frames, returning its level
+fn advance_envelope(
+    state: &mut RouteState,
+    frames: f32,
+    attack: f32,
+    decay: f32,
+    sustain: f32,
+    release: f32,
+) -> f32 {
+    let rate = |seconds: f32| match seconds > 0.0 {
+        true => frames / (seconds * consts::PLAYBACK_SAMPLE_RATE as f32),
+        false => f32::INFINITY,
+    };
+    match state.stage {
+        EnvelopeStage::Idle | EnvelopeStage::Sustain => {}
+        EnvelopeStage::Attack => {
+            state.level += rate(attack);
+            if state.level >= 1.0 {
+                state.level = 1.0;
+                state.stage = EnvelopeStage::Decay;
+            }
+        }
+        EnvelopeStage::Decay => {
+            state.level -= (1.0 - sustain) * rate(decay);
+            if state.level <= sustain {
+                state.level = sustain;
+                state.stage = EnvelopeStage::Sustain;
+            }
+        }
+        EnvelopeStage::Release => {
+            state.level -= rate(release);
+            if state.level <= 0.0 {
+                state.level = 0.0;
+                state.stage = EnvelopeStage::Idle;
+            }
+        }
+    }
+    state.level
+}
+
+/// Routes modulation sources to controls on nodes within its source, each with a depth
+/// and offset, so that many modulations can be declared together rather than wiring up
+/// control events by hand. Routes to the same control add together, offsets included,
+/// and are sent to it as a control event every few milliseconds. Note and controller
+/// sources follow the events passing through this node, which are then passed on to the
+/// source unchanged, as is its audio.
+pub struct ModMatrix {
+    node_id: u64,
+    routes: Vec<ModRoute>,
+    route_states: Vec<RouteState>,
+    targets: Vec<ModulationTarget>,
+    route_target_indices: Vec<usize>,
+    target_values: Vec<f32>,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    held_note: Option<u8>,
+    held_voice_id: Option<u64>,
+    note: u8,
+    velocity: f32,
+    controllers: [f32; CONTROLLER_COUNT],
+    random: Xorshift32,
+    random_value: f32,
+}
+
+impl ModMatrix {
+    pub fn new(
+        node_id: Option<u64>,
+        routes: Vec<ModRoute>,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        let mut targets: Vec<ModulationTarget> = vec![];
+        let route_target_indices = routes
+            .iter()
+            .map(|route| {
+                targets
+                    .iter()
+                    .position(|target| *target == route.target)
+                    .unwrap_or_else(|| {
+                        targets.push(route.target);
+                        targets.len() - 1
+                    })
+            })
+            .collect();
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            route_states: vec![RouteState::default(); routes.len()],
+            target_values: vec![0.0; targets.len()],
+            routes,
+            targets,
+            route_target_indices,
+            consumer,
+            held_note: None,
+            held_voice_id: None,
+            note: 0,
+            velocity: 0.0,
+            controllers: [0.0; CONTROLLER_COUNT],
+            random: Xorshift32::new(0x9e3779b9),
+            random_value: 0.0,
+        }
+    }
+
+    fn note_on(&mut self, note: u8, vel: f32) {
+        self.held_note = Some(note);
+        self.note = note;
+        self.velocity = vel;
+        self.random_value = self.random.next_bipolar();
+        for (route, state) in self.routes.iter().zip(self.route_states.iter_mut()) {
+            if let ModSource::Envelope { .. } = route.source {
+                state.stage = EnvelopeStage::Attack;
+            }
+        }
+    }
+
+    fn release(&mut self) {
+        self.held_note = None;
+        for state in self.route_states.iter_mut() {
+            if state.stage != EnvelopeStage::Idle {
+                state.stage = EnvelopeStage::Release;
+            }
+        }
+    }
+
+    // Value of a route's source, moving it on by some frames
+    fn source_value(&mut self, route_index: usize, frames: f32) -> f32 {
+        let state = &mut self.route_states[route_index];
+        match self.routes[route_index].source {
+            ModSource::Lfo { frequency, shape } => {
+                let value = lfo_value(shape, state.phase);
+                state.phase += frequency * frames / consts::PLAYBACK_SAMPLE_RATE as f32;
+                state.phase -= state.phase.floor();
+                value
+            }
+            ModSource::Envelope {
+                attack,
+                decay,
+                sustain,
+                release,
+            } => advance_envelope(state, frames, attack, decay, sustain, release),
+            ModSource::Velocity => self.velocity,
+            ModSource::NoteNumber => self.note as f32 / 127.0,
+            ModSource::Controller(controller) => self
+                .controllers
+                .get(controller as usize)
+                .copied()
+                .unwrap_or(0.0),
+            ModSource::Random => self.random_value,
+        }
+    }
+
+    fn send_modulation(&mut self, frames: f32) {
+        self.target_values.fill(0.0);
+        for route_index in 0..self.routes.len() {
+            let route = self.routes[route_index];
+            let value = route.offset + route.depth * self.source_value(route_index, frames);
+            self.target_values[self.route_target_indices[route_index]] += value;
+        }
+        for (target, value) in self.targets.iter().zip(self.target_values.iter()) {
+            self.consumer.on_event(&NodeEvent::NodeControl {
+                node_id: target.node_id,
+                event: target.control.control_event(*value),
+            });
+        }
+    }
+}
+
+impl BufferConsumerNode for ModMatrix {}
+
+impl Node for ModMatrix {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        match event {
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event: NoteEvent::NoteOn { vel },
+            } => {
+                self.held_voice_id = *voice_id;
+                self.note_on(*note, *vel);
+            }
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event: NoteEvent::NoteOff { .. },
+            } if self.held_note == Some(*note)
+                && util::is_same_voice(self.held_voice_id, *voice_id) =>
+            {
+                self.release()
+            }
+            NodeEvent::Broadcast(BroadcastControl::NotesOff) => self.release(),
+            NodeEvent::Broadcast(BroadcastControl::Stop) => {
+                self.held_note = None;
+                for state in self.route_states.iter_mut() {
+                    *state = RouteState::default();
+                }
+            }
+            NodeEvent::Broadcast(BroadcastControl::Controller { controller, value }) => {
+                if let Some(slot) = self.controllers.get_mut(*controller as usize) {
+                    *slot = *value;
+                }
+            }
+            _ => {}
+        }
+        self.consumer.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        for chunk in buffer.chunks_mut(CONTROL_BLOCK_FRAMES * consts::CHANNEL_COUNT) {
+            self.send_modulation((chunk.len() / consts::CHANNEL_COUNT) as f32);
+            self.consumer.fill_buffer(chunk);
+        }
+    }
+}
+
+impl BufferConsumer for ModMatrix {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        let matrix = Self::new(Some(self.node_id), self.routes.clone(), consumer);
+        Ok(Box::new(matrix))
+    }
+}
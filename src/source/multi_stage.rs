@@ -0,0 +1,256 @@
+use crate::{
+    consts,
+    source::{envelope::curve_progress, param::CONTROL_BLOCK_FRAMES},
+    util, Breakpoint, BroadcastControl, BufferConsumer, BufferConsumerNode, EffectMix, Error,
+    ModulationTarget, Node, NodeEvent, NoteEvent,
+};
+
+/// Envelope moving through any number of breakpoints from silence on each note on. If there
+/// is a sustain point, the envelope holds there until the note off and then carries on
+/// through the points after it; without one, it runs through every point regardless.
+/// With no modulation target, the envelope shapes the amplitude of its source and falls
+/// silent after the last point, which should usually have a level of zero. With a target,
+/// the source is heard unchanged, and the envelope's level is sent to the targeted control
+/// of a node within the source instead.
+pub struct MultiStageEnvelope {
+    node_id: u64,
+    effect_mix: EffectMix,
+    points: Vec<Breakpoint>,
+    sustain_point: Option<usize>,
+    target: Option<ModulationTarget>,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    intermediate_buffer: Vec<f32>,
+    stage: usize,
+    level: f32,
+    stage_start_level: f32,
+    stage_samples: f32,
+    stage_progress_samples: f32,
+    released: bool,
+    held_voice_id: Option<u64>,
+    pending_note_off: Option<(u8, Option<u64>, f32)>,
+}
+
+impl MultiStageEnvelope {
+    pub fn new(
+        node_id: Option<u64>,
+        points: Vec<Breakpoint>,
+        sustain_point: Option<usize>,
+        target: Option<ModulationTarget>,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Result<Self, Error> {
+        if points.is_empty() {
+            return Err(Error::User(
+                "Multi-stage envelope needs at least one point".to_owned(),
+            ));
+        }
+        if let Some(sustain_point) = sustain_point {
+            if sustain_point >= points.len() {
+                return Err(Error::User(format!(
+                    "Sustain point {} is past the last of {} envelope points",
+                    sustain_point,
+                    points.len()
+                )));
+            }
+        }
+        let mut envelope = Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            effect_mix: EffectMix::default(),
+            points,
+            sustain_point,
+            target,
+            consumer,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+            stage: 0,
+            level: 0.0,
+            stage_start_level: 0.0,
+            stage_samples: 0.0,
+            stage_progress_samples: 0.0,
+            released: false,
+            held_voice_id: None,
+            pending_note_off: None,
+        };
+        envelope.trigger();
+        Ok(envelope)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.stage >= self.points.len()
+    }
+
+    // Begin moving from the current level towards a point, or finish if there are no more
+    fn enter_stage(&mut self, stage: usize) {
+        self.stage = stage;
+        self.stage_start_level = self.level;
+        self.stage_samples = self
+            .points
+            .get(stage)
+            .map(|point| point.time * consts::PLAYBACK_SAMPLE_RATE as f32)
+            .unwrap_or(0.0);
+        self.stage_progress_samples = 0.0;
+        if self.is_finished() {
+            if let Some((note, voice_id, vel)) = self.pending_note_off.take() {
+                self.consumer.on_event(&NodeEvent::Note {
+                    note,
+                    voice_id,
+                    event: NoteEvent::NoteOff { vel },
+                });
+            }
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.level = 0.0;
+        self.released = false;
+        self.enter_stage(0);
+    }
+
+    // Leave the sustain point, or skip to the stage after it if it has not been reached yet
+    fn release(&mut self) {
+        if self.released || self.is_finished() {
+            return;
+        }
+        self.released = true;
+        if let Some(sustain_point) = self.sustain_point {
+            if self.stage <= sustain_point {
+                self.enter_stage(sustain_point + 1);
+            }
+        }
+    }
+
+    // Level for the next sample, moving on to the next point when one is reached
+    #[inline]
+    fn next_level(&mut self) -> f32 {
+        let Some(point) = self.points.get(self.stage).copied() else {
+            return self.level;
+        };
+        if self.stage_progress_samples >= self.stage_samples {
+            self.level = point.level;
+            let is_sustaining = !self.released && self.sustain_point == Some(self.stage);
+            if !is_sustaining {
+                self.enter_stage(self.stage + 1);
+            }
+            return self.level;
+        }
+        let time_progress = self.stage_progress_samples / self.stage_samples;
+        let rising = point.level > self.stage_start_level;
+        let progress = curve_progress(point.curve, time_progress, rising);
+        self.level = self.stage_start_level + (point.level - self.stage_start_level) * progress;
+        self.stage_progress_samples += 1.0;
+        self.level
+    }
+}
+
+impl BufferConsumerNode for MultiStageEnvelope {}
+
+impl Node for MultiStageEnvelope {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if self.effect_mix.on_event(self.node_id, event) {
+            return;
+        }
+        match event {
+            NodeEvent::Broadcast(BroadcastControl::NotesOff) => {
+                self.release();
+            }
+            NodeEvent::Broadcast(BroadcastControl::Stop) => {
+                self.level = 0.0;
+                self.pending_note_off = None;
+                self.enter_stage(self.points.len());
+            }
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event,
+            } => match event {
+                NoteEvent::NoteOn { .. } => {
+                    self.held_voice_id = *voice_id;
+                    self.pending_note_off = None;
+                    self.trigger();
+                }
+                NoteEvent::NoteOff { vel } => {
+                    // A note-off for a voice since retriggered leaves the new one alone
+                    if !util::is_same_voice(self.held_voice_id, *voice_id) {
+                        return;
+                    }
+                    // When shaping amplitude, keep the source sounding through the release;
+                    // it is sent the note-off once the envelope has finished
+                    if self.target.is_none() && !self.is_finished() {
+                        self.pending_note_off = Some((*note, *voice_id, *vel));
+                        self.release();
+                        return;
+                    }
+                    self.release();
+                }
+            },
+            _ => {}
+        }
+        self.consumer.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        match self.target {
+            Some(_) => self.consumer.is_active(),
+            None => !self.is_finished() && self.consumer.is_active(),
+        }
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        if let Some(target) = self.target {
+            for chunk in buffer.chunks_mut(CONTROL_BLOCK_FRAMES * consts::CHANNEL_COUNT) {
+                let level = self.next_level();
+                for _ in 1..chunk.len() / consts::CHANNEL_COUNT {
+                    self.next_level();
+                }
+                self.consumer.on_event(&NodeEvent::NodeControl {
+                    node_id: target.node_id,
+                    event: target.control.control_event(level),
+                });
+                self.consumer.fill_buffer(chunk);
+            }
+            return;
+        }
+
+        let buffer_size = buffer.len();
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+        intermediate_slice.fill(0.0);
+        self.consumer.fill_buffer(intermediate_slice);
+
+        // Currently only-supported channel configuration
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        for frame_index in 0..buffer_size / consts::CHANNEL_COUNT {
+            let wet = self.effect_mix.next_wet();
+            if self.is_finished() && wet == 1.0 {
+                break;
+            }
+            let multiplier = EffectMix::blend(wet, 1.0, self.next_level());
+            let i = frame_index * consts::CHANNEL_COUNT;
+            buffer[i] += multiplier * self.intermediate_buffer[i];
+            buffer[i + 1] += multiplier * self.intermediate_buffer[i + 1];
+        }
+    }
+}
+
+impl BufferConsumer for MultiStageEnvelope {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        let mut envelope = Self::new(
+            Some(self.node_id),
+            self.points.clone(),
+            self.sustain_point,
+            self.target,
+            consumer,
+        )?;
+        envelope.effect_mix = self.effect_mix;
+        Ok(Box::new(envelope))
+    }
+}
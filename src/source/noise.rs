@@ -1,19 +1,104 @@
 use crate::{
-    consts, util, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node,
-    NodeControlEvent, NodeEvent, NoteEvent,
+    consts,
+    source::{buffer, tuning::NoteTuning},
+    util, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent,
+    NodeEvent, NoiseColor, NoteEvent, Variation,
 };
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// Conventional gains bringing filtered noise to a level comparable with white noise
+const PINK_NOISE_SCALE: f32 = 0.3;
+const BROWN_NOISE_SCALE: f32 = 3.5;
+
+// Xorshift generator for audio-rate white noise
+#[derive(Clone, Copy)]
+pub(crate) struct Xorshift32(u32);
+
+impl Xorshift32 {
+    pub(crate) const fn new(seed: u32) -> Self {
+        Self(seed)
+    }
+
+    // Uniformly distributed in the range -1 to 1
+    #[inline]
+    pub(crate) fn next_bipolar(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+static NEXT_VARIATION_SEED: AtomicU32 = AtomicU32::new(0x2545f491);
+
+// Random pitch and level for each playback of a sample, seeded differently for every
+// source and duplicate so that the voices of a font do not vary in step
+#[derive(Clone, Copy)]
+pub(crate) struct PlaybackVariation {
+    variation: Variation,
+    random: Xorshift32,
+}
+
+impl PlaybackVariation {
+    pub(crate) fn new(variation: Variation) -> Self {
+        let seed = NEXT_VARIATION_SEED.fetch_add(0x9e3779b9, Ordering::Relaxed) | 1;
+        Self {
+            variation,
+            random: Xorshift32::new(seed),
+        }
+    }
+
+    pub(crate) fn variation(&self) -> Variation {
+        self.variation
+    }
+
+    // Pitch ratio and gain for the next playback
+    pub(crate) fn next(&mut self) -> (f64, f32) {
+        if self.variation == Variation::NONE {
+            return (1.0, 1.0);
+        }
+        let cents = self.variation.cents * self.random.next_bipolar();
+        let decibels = self.variation.decibels * self.random.next_bipolar();
+        (
+            2.0f64.powf(cents as f64 / 1200.0),
+            10.0f32.powf(decibels / 20.0),
+        )
+    }
+}
+
+// Filters white noise to -3dB/octave (Paul Kellet's method), with unscaled output
+#[derive(Clone, Copy, Default)]
+pub(crate) struct PinkFilter([f32; 7]);
+
+impl PinkFilter {
+    #[inline]
+    pub(crate) fn next(&mut self, white: f32) -> f32 {
+        let b = &mut self.0;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink
+    }
+}
 
 pub struct LfsrNoiseSource {
     node_id: u64,
     is_on: bool,
     note_of_16_shifts: u8,
     current_note: u8,
+    current_voice_id: Option<u64>,
     current_amplitude: f32,
     current_lfsr: u16,
     feedback_mask: u16,
     cycle_progress_samples: f32,
     cycle_samples_a440: f32,
     peak_amplitude: f32,
+    tuning: NoteTuning,
 }
 
 impl LfsrNoiseSource {
@@ -39,12 +124,14 @@ impl LfsrNoiseSource {
             is_on: false,
             note_of_16_shifts,
             current_note: 0,
+            current_voice_id: None,
             current_amplitude: 0.0,
             current_lfsr: 0x0001,
             feedback_mask,
             cycle_progress_samples: 0.0,
             cycle_samples_a440,
             peak_amplitude: amplitude,
+            tuning: NoteTuning::new(),
         }
     }
 
@@ -70,19 +157,42 @@ impl Node for LfsrNoiseSource {
         self.node_id
     }
 
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        true
+    }
+
     fn on_event(&mut self, event: &NodeEvent) {
         match event {
-            NodeEvent::Broadcast(BroadcastControl::NotesOff) => {
+            NodeEvent::Broadcast(BroadcastControl::NotesOff | BroadcastControl::Stop) => {
                 self.is_on = false;
             }
-            NodeEvent::Note { note, event } => match event {
+            NodeEvent::Broadcast(
+                BroadcastControl::SetParam { .. }
+                | BroadcastControl::RecallSnapshot { .. }
+                | BroadcastControl::Controller { .. }
+                | BroadcastControl::TransportPosition { .. },
+            ) => {}
+            NodeEvent::Broadcast(
+                control @ (BroadcastControl::Tuning(_) | BroadcastControl::MasterTune { .. }),
+            ) => {
+                self.tuning.on_broadcast(control);
+            }
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event,
+            } => match event {
                 NoteEvent::NoteOn { vel } => {
                     self.is_on = true;
                     self.current_note = *note;
+                    self.current_voice_id = *voice_id;
                     self.current_amplitude = self.peak_amplitude * *vel;
                 }
                 NoteEvent::NoteOff { vel: _ } => {
-                    if self.current_note != *note {
+                    if self.current_note != *note
+                        || !util::is_same_voice(self.current_voice_id, *voice_id)
+                    {
                         return;
                     }
                     self.is_on = false;
@@ -104,12 +214,16 @@ impl Node for LfsrNoiseSource {
         }
     }
 
+    fn is_active(&self) -> bool {
+        self.is_on
+    }
+
     fn fill_buffer(&mut self, buffer: &mut [f32]) {
         if !self.is_on {
             return;
         }
         let size = buffer.len();
-        let note_frequency = util::frequency_of(self.current_note);
+        let note_frequency = self.tuning.frequency_of(self.current_note);
         let pitch_cycle_samples = consts::PLAYBACK_SAMPLE_RATE as f32 / note_frequency;
         let mut stretched_progress =
             self.cycle_progress_samples * pitch_cycle_samples / self.cycle_samples_a440;
@@ -122,16 +236,15 @@ impl Node for LfsrNoiseSource {
         assert_eq!(consts::CHANNEL_COUNT, 2);
 
         let mut current_value = self.value();
-        for i in (0..size).step_by(consts::CHANNEL_COUNT) {
+        buffer::add_rendered_frames(buffer, 1.0, |_| {
             stretched_progress += 1.0;
             if stretched_progress >= pitch_cycle_samples {
                 stretched_progress -= pitch_cycle_samples;
                 self.shift();
                 current_value = self.value();
             }
-            buffer[i] += current_value;
-            buffer[i + 1] += current_value;
-        }
+            (current_value, current_value)
+        });
 
         self.cycle_progress_samples =
             stretched_progress * self.cycle_samples_a440 / pitch_cycle_samples;
@@ -147,12 +260,291 @@ impl BufferConsumer for LfsrNoiseSource {
                 return Err(Error::User("Unexpected feedback mask".to_owned()));
             }
         };
-        let source = Self::new(
+        let mut source = Self::new(
             Some(self.node_id),
             self.peak_amplitude,
             inside_feedback,
             self.note_of_16_shifts,
         );
+        source.tuning = self.tuning.clone();
+        Ok(Box::new(source))
+    }
+}
+
+/// Continuous white, pink or brown noise, played at the same level for any note.
+pub struct ColoredNoiseSource {
+    node_id: u64,
+    is_on: bool,
+    current_note: u8,
+    current_voice_id: Option<u64>,
+    current_amplitude: f32,
+    peak_amplitude: f32,
+    color: NoiseColor,
+    random: Xorshift32,
+    pink_filter: PinkFilter,
+    brown_state: f32,
+}
+
+impl ColoredNoiseSource {
+    pub fn new(node_id: Option<u64>, amplitude: f32, color: NoiseColor) -> Self {
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            is_on: false,
+            current_note: 0,
+            current_voice_id: None,
+            current_amplitude: 0.0,
+            peak_amplitude: amplitude,
+            color,
+            random: Xorshift32::new(0x2545f491),
+            pink_filter: PinkFilter::default(),
+            brown_state: 0.0,
+        }
+    }
+
+    #[inline]
+    fn next_value(&mut self) -> f32 {
+        let white = self.random.next_bipolar();
+        match self.color {
+            NoiseColor::White => white,
+            NoiseColor::Pink => self.pink_filter.next(white) * PINK_NOISE_SCALE,
+            NoiseColor::Brown => {
+                // Leaky integration, so the level wanders without drifting away
+                self.brown_state = (self.brown_state + 0.02 * white) / 1.02;
+                self.brown_state * BROWN_NOISE_SCALE
+            }
+        }
+    }
+}
+
+impl BufferConsumerNode for ColoredNoiseSource {}
+
+impl Node for ColoredNoiseSource {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        true
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        match event {
+            NodeEvent::Broadcast(BroadcastControl::NotesOff | BroadcastControl::Stop) => {
+                self.is_on = false;
+            }
+            NodeEvent::Broadcast(
+                BroadcastControl::SetParam { .. }
+                | BroadcastControl::RecallSnapshot { .. }
+                | BroadcastControl::Controller { .. }
+                | BroadcastControl::TransportPosition { .. }
+                | BroadcastControl::Tuning(_)
+                | BroadcastControl::MasterTune { .. },
+            ) => {}
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event,
+            } => match event {
+                NoteEvent::NoteOn { vel } => {
+                    self.is_on = true;
+                    self.current_note = *note;
+                    self.current_voice_id = *voice_id;
+                    self.current_amplitude = self.peak_amplitude * *vel;
+                }
+                NoteEvent::NoteOff { vel: _ } => {
+                    if self.current_note == *note
+                        && util::is_same_voice(self.current_voice_id, *voice_id)
+                    {
+                        self.is_on = false;
+                    }
+                }
+            },
+            NodeEvent::NodeControl {
+                node_id,
+                event: NodeControlEvent::Volume(volume),
+            } => {
+                if *node_id == self.node_id {
+                    self.peak_amplitude = *volume;
+                }
+            }
+            NodeEvent::NodeControl { .. } => {}
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_on
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        if !self.is_on {
+            return;
+        }
+
+        #[cfg(debug_assertions)]
+        assert_eq!(buffer.len() % consts::CHANNEL_COUNT, 0);
+
+        buffer::add_rendered_frames(buffer, self.current_amplitude, |_| {
+            let value = self.next_value();
+            (value, value)
+        });
+    }
+}
+
+impl BufferConsumer for ColoredNoiseSource {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let source = Self::new(Some(self.node_id), self.peak_amplitude, self.color);
+        Ok(Box::new(source))
+    }
+}
+
+/// Sample-and-hold "random arpeggio" in the style of old analogue synths: a square wave
+/// that jumps to a random pitch within a range of semitones around the played note at a
+/// steady rate. With a range of zero it repeats the note at that rate instead.
+pub struct SampleHoldSource {
+    node_id: u64,
+    is_on: bool,
+    current_note: u8,
+    current_voice_id: Option<u64>,
+    current_amplitude: f32,
+    peak_amplitude: f32,
+    rate_hz: f32,
+    range_semitones: u8,
+    random: Xorshift32,
+    held_note: u8,
+    samples_until_step: f32,
+    phase: f32,
+    tuning: NoteTuning,
+}
+
+impl SampleHoldSource {
+    pub fn new(node_id: Option<u64>, amplitude: f32, rate_hz: f32, range_semitones: u8) -> Self {
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            is_on: false,
+            current_note: 0,
+            current_voice_id: None,
+            current_amplitude: 0.0,
+            peak_amplitude: amplitude,
+            rate_hz: rate_hz.max(0.01),
+            range_semitones,
+            random: Xorshift32::new(0x68e31da4),
+            held_note: 0,
+            samples_until_step: 0.0,
+            phase: 0.0,
+            tuning: NoteTuning::new(),
+        }
+    }
+
+    fn step(&mut self) {
+        let range = self.range_semitones as f32;
+        let offset = (self.random.next_bipolar() * (range + 0.5)).round();
+        self.held_note = (self.current_note as f32 + offset).clamp(0.0, 127.0) as u8;
+        self.samples_until_step += consts::PLAYBACK_SAMPLE_RATE as f32 / self.rate_hz;
+        self.phase = 0.0;
+    }
+}
+
+impl BufferConsumerNode for SampleHoldSource {}
+
+impl Node for SampleHoldSource {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        true
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        match event {
+            NodeEvent::Broadcast(BroadcastControl::NotesOff | BroadcastControl::Stop) => {
+                self.is_on = false;
+            }
+            NodeEvent::Broadcast(
+                BroadcastControl::SetParam { .. }
+                | BroadcastControl::RecallSnapshot { .. }
+                | BroadcastControl::Controller { .. }
+                | BroadcastControl::TransportPosition { .. },
+            ) => {}
+            NodeEvent::Broadcast(
+                control @ (BroadcastControl::Tuning(_) | BroadcastControl::MasterTune { .. }),
+            ) => {
+                self.tuning.on_broadcast(control);
+            }
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event,
+            } => match event {
+                NoteEvent::NoteOn { vel } => {
+                    self.is_on = true;
+                    self.current_note = *note;
+                    self.current_voice_id = *voice_id;
+                    self.current_amplitude = self.peak_amplitude * *vel;
+                    self.samples_until_step = 0.0;
+                }
+                NoteEvent::NoteOff { vel: _ } => {
+                    if self.current_note == *note
+                        && util::is_same_voice(self.current_voice_id, *voice_id)
+                    {
+                        self.is_on = false;
+                    }
+                }
+            },
+            NodeEvent::NodeControl {
+                node_id,
+                event: NodeControlEvent::Volume(volume),
+            } => {
+                if *node_id == self.node_id {
+                    self.peak_amplitude = *volume;
+                }
+            }
+            NodeEvent::NodeControl { .. } => {}
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_on
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        if !self.is_on {
+            return;
+        }
+
+        #[cfg(debug_assertions)]
+        assert_eq!(buffer.len() % consts::CHANNEL_COUNT, 0);
+
+        let sample_rate = consts::PLAYBACK_SAMPLE_RATE as f32;
+        let mut phase_increment = self.tuning.frequency_of(self.held_note) / sample_rate;
+        buffer::add_rendered_frames(buffer, self.current_amplitude, |_| {
+            if self.samples_until_step <= 0.0 {
+                self.step();
+                phase_increment = self.tuning.frequency_of(self.held_note) / sample_rate;
+            }
+            self.samples_until_step -= 1.0;
+            self.phase += phase_increment;
+            self.phase -= self.phase.floor();
+            let level = match self.phase < 0.5 {
+                true => 1.0,
+                false => -1.0,
+            };
+            (level, level)
+        });
+    }
+}
+
+impl BufferConsumer for SampleHoldSource {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let mut source = Self::new(
+            Some(self.node_id),
+            self.peak_amplitude,
+            self.rate_hz,
+            self.range_semitones,
+        );
+        source.tuning = self.tuning.clone();
         Ok(Box::new(source))
     }
 }
@@ -0,0 +1,118 @@
+use crate::{
+    util, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent,
+    NodeEvent, NoteEvent, NoteMapping,
+};
+
+/// Transforms the notes passing through it to its source, transposing them, remapping
+/// their velocities, and dropping those outside a range or scale. The mapping may be
+/// replaced while playing with a NoteMap control event; notes already sounding are still
+/// released at the pitch they started on.
+pub struct NoteMap {
+    node_id: u64,
+    mapping: NoteMapping,
+    sounding_notes: [Option<(u8, Option<u64>)>; 128],
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+}
+
+impl NoteMap {
+    pub fn new(
+        node_id: Option<u64>,
+        mapping: NoteMapping,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            mapping,
+            sounding_notes: [None; 128],
+            consumer,
+        }
+    }
+}
+
+impl BufferConsumerNode for NoteMap {}
+
+impl Node for NoteMap {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        match event {
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event,
+            } => {
+                let Some(slot) = self.sounding_notes.get_mut(*note as usize) else {
+                    return;
+                };
+                match event {
+                    NoteEvent::NoteOn { vel } => {
+                        // Release whatever a repeated note on was previously mapped to
+                        if let Some((previous, previous_voice_id)) = slot.take() {
+                            self.consumer.on_event(&NodeEvent::Note {
+                                note: previous,
+                                voice_id: previous_voice_id,
+                                event: NoteEvent::NoteOff { vel: 0.0 },
+                            });
+                        }
+                        if let Some((mapped, vel)) = self.mapping.map(*note, *vel) {
+                            *slot = Some((mapped, *voice_id));
+                            self.consumer.on_event(&NodeEvent::Note {
+                                note: mapped,
+                                voice_id: *voice_id,
+                                event: NoteEvent::NoteOn { vel },
+                            });
+                        }
+                    }
+                    NoteEvent::NoteOff { vel } => {
+                        // A note-off for a voice since retriggered leaves the new one alone
+                        let Some((mapped, held_voice_id)) = *slot else {
+                            return;
+                        };
+                        if util::is_same_voice(held_voice_id, *voice_id) {
+                            *slot = None;
+                            self.consumer.on_event(&NodeEvent::Note {
+                                note: mapped,
+                                voice_id: held_voice_id,
+                                event: NoteEvent::NoteOff { vel: *vel },
+                            });
+                        }
+                    }
+                }
+            }
+            NodeEvent::NodeControl {
+                node_id,
+                event: NodeControlEvent::NoteMap(mapping),
+            } if *node_id == self.node_id => {
+                self.mapping = mapping.clone();
+            }
+            NodeEvent::Broadcast(BroadcastControl::NotesOff | BroadcastControl::Stop) => {
+                self.sounding_notes = [None; 128];
+                self.consumer.on_event(event);
+            }
+            _ => self.consumer.on_event(event),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        self.consumer.fill_buffer(buffer);
+    }
+}
+
+impl BufferConsumer for NoteMap {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        let note_map = Self::new(Some(self.node_id), self.mapping.clone(), consumer);
+        Ok(Box::new(note_map))
+    }
+}
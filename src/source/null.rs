@@ -19,8 +19,17 @@ impl Node for NullSource {
         self.node_id
     }
 
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        true
+    }
+
     fn on_event(&mut self, _event: &NodeEvent) {}
 
+    fn is_active(&self) -> bool {
+        false
+    }
+
     fn fill_buffer(&mut self, _buffer: &mut [f32]) {}
 }
 
@@ -1,20 +1,98 @@
 use crate::{
-    consts, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent,
-    NodeEvent, NoteEvent,
+    consts, source::noise::PlaybackVariation, BroadcastControl, BufferConsumer, BufferConsumerNode,
+    Error, Node, NodeControlEvent, NodeEvent, NoteEvent, Retrigger, Variation,
 };
+use crossbeam_channel::Sender;
 use hound::{SampleFormat, WavSpec};
 use soundfont::raw::{SampleHeader, SampleLink};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, Mutex,
+    },
+};
+
+const MAX_OVERLAPPING_TAILS: usize = 4;
+
+static NAMED_CHOKE_GROUPS: LazyLock<Mutex<HashMap<String, ChokeGroup>>> =
+    LazyLock::new(Default::default);
+
+/// One-shots sharing a choke group cut each other off, so that only the one started last
+/// keeps playing, as a closed hi-hat silences an open one. A one-shot in a group also cuts
+/// off its own earlier playback when started again, even when set to overlap.
+#[derive(Clone, Debug)]
+pub struct ChokeGroup {
+    latest_start: Arc<AtomicU64>,
+}
+
+impl Default for ChokeGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChokeGroup {
+    pub fn new() -> Self {
+        Self {
+            latest_start: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// The group with the given name, shared by every one-shot given the same name, as
+    /// those configured with a choke_group are.
+    pub fn named(name: &str) -> Self {
+        let mut groups = NAMED_CHOKE_GROUPS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        groups.entry(name.to_owned()).or_default().clone()
+    }
+
+    // Record a new start in the group, returning its count
+    fn start(&self) -> u64 {
+        self.latest_start.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn is_latest(&self, start: u64) -> bool {
+        self.latest_start.load(Ordering::Relaxed) == start
+    }
+}
+
+// A playback through the sample, at a rate and gain varied for each one
+#[derive(Clone, Copy)]
+struct Playback {
+    data_position: usize,
+    rate: f64,
+    gain: f32,
+}
 
+/// Plays its sample through once for each note on. The host may be sent the node ID
+/// whenever the sample plays through to its end, through a channel given in a
+/// NotifyPlaybackComplete control event or to set_completion_sender; duplicates made for
+/// polyphony notify through the same channel. A note on arriving while the sample plays
+/// is handled as its Retrigger setting says, which may also be sent in a Retrigger control
+/// event, and a ChokeGroup given to set_choke_group or in a ChokeGroup control event lets
+/// other one-shots cut it off. A Variation given to set_variation changes the pitch and
+/// level of each playback at random.
 pub struct OneShotSource {
     node_id: u64,
     source_channel_count: usize,
     volume: f32,
-    data_position: usize,
-    source_data: Vec<f32>,
+    playback: Playback,
+    source_data: Arc<[f32]>,
+    completion_sender: Option<Sender<u64>>,
+    retrigger: Retrigger,
+    variation: PlaybackVariation,
+    tails: Vec<Playback>,
+    choke_group: Option<ChokeGroup>,
+    choke_start: u64,
 }
 
 impl OneShotSource {
-    pub fn new_from_raw_sf2_data(header: &SampleHeader, data: Vec<f32>) -> Result<Self, Error> {
+    pub fn new_from_raw_sf2_data(
+        header: &SampleHeader,
+        data: impl Into<Arc<[f32]>>,
+    ) -> Result<Self, Error> {
         Self::validate_header(header)?;
         let source_channel_count = match header.sample_type {
             SampleLink::MonoSample => 1,
@@ -25,35 +103,127 @@ impl OneShotSource {
                 )));
             }
         };
-        Ok(Self::new(None, source_channel_count, data))
+        Ok(Self::new(None, source_channel_count, data.into()))
     }
 
-    /// Make a new OneShotSource holding the given sample data.
+    /// Make a new OneShotSource holding the given sample data, which is shared with duplicates.
     /// Data in the spec will be checked for compatibility.
     /// The note is a MIDI key, where A440 is 69.
     pub fn new_from_data(
         spec: WavSpec,
-        data: Vec<f32>,
+        data: impl Into<Arc<[f32]>>,
         node_id: Option<u64>,
     ) -> Result<Self, Error> {
         Self::validate_spec(&spec)?;
-        Ok(Self::new(node_id, spec.channels as usize, data))
+        Ok(Self::new(node_id, spec.channels as usize, data.into()))
     }
 
-    fn new(node_id: Option<u64>, channels: usize, data: Vec<f32>) -> Self {
+    fn new(node_id: Option<u64>, channels: usize, data: Arc<[f32]>) -> Self {
         Self {
             node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
             source_channel_count: channels,
             volume: 1.0,
-            data_position: data.len(),
+            playback: Playback {
+                data_position: data.len(),
+                rate: 1.0,
+                gain: 1.0,
+            },
             source_data: data,
+            completion_sender: None,
+            retrigger: Retrigger::default(),
+            variation: PlaybackVariation::new(Variation::NONE),
+            tails: Vec::with_capacity(MAX_OVERLAPPING_TAILS),
+            choke_group: None,
+            choke_start: 0,
+        }
+    }
+
+    pub fn set_completion_sender(&mut self, sender: Sender<u64>) {
+        self.completion_sender = Some(sender);
+    }
+
+    pub fn set_retrigger(&mut self, retrigger: Retrigger) {
+        self.retrigger = retrigger;
+    }
+
+    pub fn set_choke_group(&mut self, choke_group: Option<ChokeGroup>) {
+        self.choke_group = choke_group;
+    }
+
+    /// Vary the pitch and level of each playback at random.
+    pub fn set_variation(&mut self, variation: Variation) {
+        self.variation = PlaybackVariation::new(variation);
+    }
+
+    fn is_playing(&self) -> bool {
+        self.playback.data_position < self.source_data.len()
+    }
+
+    fn note_on(&mut self) {
+        if self.is_playing() {
+            match self.retrigger {
+                Retrigger::Restart => {}
+                Retrigger::Continue => return,
+                Retrigger::Overlap => {
+                    if self.choke_group.is_none() {
+                        if self.tails.len() == MAX_OVERLAPPING_TAILS {
+                            self.tails.remove(0);
+                        }
+                        self.tails.push(self.playback);
+                    }
+                }
+            }
+        }
+        if let Some(choke_group) = &self.choke_group {
+            self.choke_start = choke_group.start();
+            self.tails.clear();
+        }
+        let (rate, gain) = self.variation.next();
+        self.playback = Playback {
+            data_position: 0,
+            rate,
+            gain,
+        };
+    }
+
+    fn stop(&mut self) {
+        self.playback.data_position = self.source_data.len();
+        self.tails.clear();
+    }
+
+    // Add a playback's sample data into the buffer, stepping through the data at its rate,
+    // returning the data points used
+    fn fill_from(&self, playback: &Playback, buffer: &mut [f32]) -> usize {
+        let channels = self.source_channel_count;
+        let src = &self.source_data[playback.data_position..];
+        let src_frames = src.len() / channels;
+        let volume = self.volume * playback.gain;
+        let mut dst_frames = 0;
+        for dst in buffer.chunks_exact_mut(consts::CHANNEL_COUNT) {
+            let src_frame = (dst_frames as f64 * playback.rate) as usize;
+            if src_frame >= src_frames {
+                return src.len();
+            }
+            let (left, right) = match channels {
+                1 => (src[src_frame], src[src_frame]),
+                _ => (src[src_frame * 2], src[src_frame * 2 + 1]),
+            };
+            dst[0] += left * volume;
+            dst[1] += right * volume;
+            dst_frames += 1;
+        }
+        let src_frames_used = (dst_frames as f64 * playback.rate) as usize;
+        match src_frames_used >= src_frames {
+            true => src.len(),
+            false => src_frames_used * channels,
         }
     }
 
     fn validate_header(header: &SampleHeader) -> Result<(), Error> {
         if header.sample_rate as usize != consts::PLAYBACK_SAMPLE_RATE {
-            println!(
-                "WARNING: SF2: Sample rate {} should match playback rate of {}",
+            log_warning!(
+                "SF2",
+                "Sample rate {} should match playback rate of {}",
                 header.sample_rate,
                 consts::PLAYBACK_SAMPLE_RATE
             );
@@ -67,7 +237,7 @@ impl OneShotSource {
         }
     }
 
-    fn validate_spec(spec: &WavSpec) -> Result<(), Error> {
+    pub(crate) fn validate_spec(spec: &WavSpec) -> Result<(), Error> {
         if spec.channels == 0 || spec.channels > 2 {
             return Err(Error::User(format!(
                 "{} channels is not supported",
@@ -87,8 +257,9 @@ impl OneShotSource {
             )));
         }
         if spec.sample_rate as usize != consts::PLAYBACK_SAMPLE_RATE {
-            println!(
-                "WARNING: SF2: Sample rate {} should match playback rate of {}",
+            log_warning!(
+                "SF2",
+                "Sample rate {} should match playback rate of {}",
                 spec.sample_rate,
                 consts::PLAYBACK_SAMPLE_RATE
             );
@@ -104,17 +275,30 @@ impl Node for OneShotSource {
         self.node_id
     }
 
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        true
+    }
+
     fn on_event(&mut self, event: &NodeEvent) {
         match event {
-            NodeEvent::Broadcast(BroadcastControl::NotesOff) => {
-                self.data_position = self.source_data.len();
+            NodeEvent::Broadcast(BroadcastControl::NotesOff | BroadcastControl::Stop) => {
+                self.stop();
             }
-            NodeEvent::Note { note: _, event } => match event {
+            NodeEvent::Broadcast(
+                BroadcastControl::SetParam { .. }
+                | BroadcastControl::RecallSnapshot { .. }
+                | BroadcastControl::Controller { .. }
+                | BroadcastControl::TransportPosition { .. }
+                | BroadcastControl::Tuning(_)
+                | BroadcastControl::MasterTune { .. },
+            ) => {}
+            NodeEvent::Note { event, .. } => match event {
                 NoteEvent::NoteOn { vel: _ } => {
-                    self.data_position = 0;
+                    self.note_on();
                 }
                 NoteEvent::NoteOff { vel: _ } => {
-                    self.data_position = self.source_data.len();
+                    self.stop();
                 }
             },
             NodeEvent::NodeControl {
@@ -126,6 +310,33 @@ impl Node for OneShotSource {
                 }
                 self.volume = *volume;
             }
+            NodeEvent::NodeControl {
+                node_id,
+                event: NodeControlEvent::NotifyPlaybackComplete(sender),
+            } => {
+                if *node_id != self.node_id {
+                    return;
+                }
+                self.completion_sender = Some(sender.clone());
+            }
+            NodeEvent::NodeControl {
+                node_id,
+                event: NodeControlEvent::Retrigger(retrigger),
+            } => {
+                if *node_id != self.node_id {
+                    return;
+                }
+                self.retrigger = *retrigger;
+            }
+            NodeEvent::NodeControl {
+                node_id,
+                event: NodeControlEvent::ChokeGroup(choke_group),
+            } => {
+                if *node_id != self.node_id {
+                    return;
+                }
+                self.choke_group = choke_group.clone();
+            }
             NodeEvent::NodeControl {
                 node_id: _,
                 event: _,
@@ -133,48 +344,55 @@ impl Node for OneShotSource {
         }
     }
 
+    fn is_active(&self) -> bool {
+        self.is_playing() || !self.tails.is_empty()
+    }
+
     fn fill_buffer(&mut self, buffer: &mut [f32]) {
         if buffer.is_empty() {
             return;
         }
 
-        if self.data_position >= self.source_data.len() {
-            return;
+        // Cut off once another one-shot in the group has started since this one
+        if let Some(choke_group) = &self.choke_group {
+            if self.is_playing() && !choke_group.is_latest(self.choke_start) {
+                self.stop();
+            }
         }
 
         #[cfg(debug_assertions)]
         assert_eq!(buffer.len() % consts::CHANNEL_COUNT, 0);
 
-        let src = &self.source_data[self.data_position..];
-        match self.source_channel_count {
-            1 => {
-                let src_data_points = (buffer.len() / 2).min(src.len());
-                for src_data_index in 0..src_data_points {
-                    let sample = src[src_data_index] * self.volume;
-                    buffer[src_data_index * 2] += sample;
-                    buffer[src_data_index * 2 + 1] += sample;
-                }
-                self.data_position += src_data_points;
-            }
-            2 => {
-                let src_data_points = buffer.len().min(src.len());
-                for src_data_index in 0..src_data_points {
-                    buffer[src_data_index] += src[src_data_index] * self.volume;
-                }
-                self.data_position += src_data_points;
+        let mut tails = std::mem::take(&mut self.tails);
+        for tail in tails.iter_mut() {
+            tail.data_position += self.fill_from(tail, buffer);
+        }
+        tails.retain(|tail| tail.data_position < self.source_data.len());
+        self.tails = tails;
+
+        if !self.is_playing() {
+            return;
+        }
+        self.playback.data_position += self.fill_from(&self.playback, buffer);
+        if !self.is_playing() {
+            if let Some(sender) = &self.completion_sender {
+                let _ = sender.try_send(self.node_id);
             }
-            _ => {}
         }
     }
 }
 
 impl BufferConsumer for OneShotSource {
     fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
-        let source = Self::new(
+        let mut source = Self::new(
             Some(self.node_id),
             self.source_channel_count,
             self.source_data.clone(),
         );
+        source.completion_sender = self.completion_sender.clone();
+        source.retrigger = self.retrigger;
+        source.set_variation(self.variation.variation());
+        source.choke_group = self.choke_group.clone();
         Ok(Box::new(source))
     }
 }
@@ -0,0 +1,196 @@
+use crate::{consts, source::buffer, BufferConsumer, BufferConsumerNode, Error, Node, NodeEvent};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex, MutexGuard, PoisonError,
+};
+
+// Times the audio thread checks whether the workers have finished before it starts giving
+// up its time slice between checks, a few microseconds at most
+const JOIN_SPIN_LIMIT: usize = 1000;
+
+struct Subtree {
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    buffer: Vec<f32>,
+}
+
+// Shared between the audio thread and the workers. For each buffer, every thread claims
+// subtrees to render by taking the next index, so that a subtree is only locked by the one
+// thread rendering it, and by none between buffers.
+struct SharedRender {
+    subtrees: Vec<Mutex<Subtree>>,
+    data_points: AtomicUsize,
+    next_index: AtomicUsize,
+    completed: AtomicUsize,
+}
+
+// Counts a claimed subtree as rendered once dropped, even if rendering it panicked, so
+// that the audio thread is never left waiting for it
+struct Completion<'a>(&'a AtomicUsize);
+
+impl Drop for Completion<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl SharedRender {
+    // A subtree whose render panicked carries on from the state it was left in
+    fn lock(&self, index: usize) -> MutexGuard<'_, Subtree> {
+        self.subtrees[index]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Render subtrees into their own buffers until none are left unclaimed
+    fn render_unclaimed(&self) {
+        loop {
+            let index = self.next_index.fetch_add(1, Ordering::AcqRel);
+            if index >= self.subtrees.len() {
+                return;
+            }
+            let _completion = Completion(&self.completed);
+            let data_points = self.data_points.load(Ordering::Relaxed);
+            let mut subtree = self.lock(index);
+            let Subtree { consumer, buffer } = &mut *subtree;
+            let slice = &mut buffer[0..data_points];
+            slice.fill(0.0);
+            if consumer.is_active() {
+                consumer.fill_buffer(slice);
+            }
+        }
+    }
+}
+
+fn spawn_worker(shared: Arc<SharedRender>, wake: Receiver<()>) -> Result<(), Error> {
+    std::thread::Builder::new()
+        .name("midi-graph-worker".to_owned())
+        .spawn(move || {
+            // Runs until the owning node is dropped and the wake channel disconnects
+            for () in wake.iter() {
+                #[cfg(feature = "alloc-audit")]
+                let _rendering = crate::audit::rendering_scope();
+                shared.render_unclaimed();
+            }
+        })?;
+    Ok(())
+}
+
+/// Renders its sources in parallel, as a Combiner configured with parallel set does.
+/// Worthwhile when the sources are independent and each is expensive to fill. A fixed pool
+/// of worker threads, one fewer than the available cores, shares the sources out between
+/// them and the audio thread for each buffer. A source no worker has reached by the time
+/// the audio thread is free is rendered there instead, and a source being rendered is
+/// always waited for, so no source is ever dropped from a buffer. Events are passed to the
+/// sources on the audio thread between buffers.
+pub struct ParallelCombinerSource {
+    node_id: u64,
+    shared: Arc<SharedRender>,
+    wake_senders: Vec<Sender<()>>,
+}
+
+impl ParallelCombinerSource {
+    pub fn new(
+        node_id: Option<u64>,
+        consumers: Vec<Box<dyn BufferConsumerNode + Send + 'static>>,
+    ) -> Result<Self, Error> {
+        let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+        Self::with_threads(node_id, consumers, cores - 1)
+    }
+
+    /// Make a combiner rendering on the given number of worker threads as well as the audio
+    /// thread. No more are started than there are sources besides the audio thread's.
+    pub fn with_threads(
+        node_id: Option<u64>,
+        consumers: Vec<Box<dyn BufferConsumerNode + Send + 'static>>,
+        threads: usize,
+    ) -> Result<Self, Error> {
+        let threads = threads.min(consumers.len().saturating_sub(1));
+        let subtrees = consumers
+            .into_iter()
+            .map(|consumer| {
+                Mutex::new(Subtree {
+                    consumer,
+                    buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+                })
+            })
+            .collect::<Vec<_>>();
+        let shared = Arc::new(SharedRender {
+            next_index: AtomicUsize::new(subtrees.len()),
+            completed: AtomicUsize::new(subtrees.len()),
+            subtrees,
+            data_points: AtomicUsize::new(0),
+        });
+        let mut wake_senders = Vec::with_capacity(threads);
+        for _ in 0..threads {
+            // One wake-up may wait while the worker is still busy with the buffer before
+            let (sender, receiver) = bounded(1);
+            spawn_worker(shared.clone(), receiver)?;
+            wake_senders.push(sender);
+        }
+        Ok(Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            shared,
+            wake_senders,
+        })
+    }
+}
+
+impl BufferConsumerNode for ParallelCombinerSource {}
+
+impl Node for ParallelCombinerSource {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    // Every subtree is free between buffers, so events are applied directly
+    fn on_event(&mut self, event: &NodeEvent) {
+        for index in 0..self.shared.subtrees.len() {
+            self.shared.lock(index).consumer.on_event(event);
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        (0..self.shared.subtrees.len()).any(|index| self.shared.lock(index).consumer.is_active())
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        let data_points = buffer.len();
+        let subtree_count = self.shared.subtrees.len();
+        self.shared
+            .data_points
+            .store(data_points, Ordering::Relaxed);
+        self.shared.completed.store(0, Ordering::Relaxed);
+        self.shared.next_index.store(0, Ordering::Release);
+
+        // A full channel means the worker has a wake-up waiting already
+        for sender in self.wake_senders.iter() {
+            let _ = sender.try_send(());
+        }
+        self.shared.render_unclaimed();
+
+        let mut spins = 0;
+        while self.shared.completed.load(Ordering::Acquire) < subtree_count {
+            if spins < JOIN_SPIN_LIMIT {
+                std::hint::spin_loop();
+                spins += 1;
+            } else {
+                std::thread::yield_now();
+            }
+        }
+
+        // Summed in order, so that the output does not depend on which thread rendered what
+        for index in 0..subtree_count {
+            let subtree = self.shared.lock(index);
+            buffer::add_buffer(buffer, &subtree.buffer[0..data_points]);
+        }
+    }
+}
+
+impl BufferConsumer for ParallelCombinerSource {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        Err(Error::User(
+            "ParallelCombinerSource cannot be duplicated".to_owned(),
+        ))
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{
+    consts, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent,
+    NodeEvent,
+};
+use serde_derive::{Deserialize, Serialize};
+
+/// The control a param drives on the node it is bound to.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub enum ParamTarget {
+    Volume,
+    FaderVolume,
+    MixerBalance,
+    CrossfeedAmount,
+    CrossfadePosition,
+    EffectMix,
+}
+
+impl ParamTarget {
+    pub(crate) fn control_event(&self, value: f32) -> NodeControlEvent {
+        match self {
+            ParamTarget::Volume => NodeControlEvent::Volume(value),
+            ParamTarget::FaderVolume => NodeControlEvent::Fade {
+                from: value,
+                to: value,
+                seconds: 0.0,
+            },
+            ParamTarget::MixerBalance => NodeControlEvent::MixerBalance(value),
+            ParamTarget::CrossfeedAmount => NodeControlEvent::CrossfeedAmount(value),
+            ParamTarget::CrossfadePosition => NodeControlEvent::Crossfade {
+                to: value,
+                seconds: 0.0,
+            },
+            ParamTarget::EffectMix => NodeControlEvent::SetWet(value),
+        }
+    }
+}
+
+// Frames between the control events sent while a value moves over time
+pub(crate) const CONTROL_BLOCK_FRAMES: usize = 64;
+
+/// Binds a node to a named config param, turning SetParam broadcasts for that param into
+/// the matching control event for the node. A binding may also hold the param's value in
+/// each of the config's snapshots, moving to it over time when a RecallSnapshot broadcast
+/// names one of them. Otherwise transparent, sharing the node's ID.
+pub struct ParamBinding {
+    param_id: u64,
+    target: ParamTarget,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    snapshots: Vec<(u64, f32)>,
+    from_value: f32,
+    to_value: f32,
+    duration_frames: usize,
+    progress_frames: usize,
+}
+
+impl ParamBinding {
+    pub fn new(
+        param_id: u64,
+        target: ParamTarget,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        Self {
+            param_id,
+            target,
+            consumer,
+            snapshots: vec![],
+            from_value: 0.0,
+            to_value: 0.0,
+            duration_frames: 0,
+            progress_frames: 0,
+        }
+    }
+
+    /// Set the param's value as loaded, and its value in each snapshot by snapshot ID.
+    pub fn set_snapshots(&mut self, value: f32, snapshots: Vec<(u64, f32)>) {
+        self.from_value = value;
+        self.to_value = value;
+        self.snapshots = snapshots;
+    }
+
+    fn value_at(&self, frame: usize) -> f32 {
+        if frame >= self.duration_frames {
+            return self.to_value;
+        }
+        self.from_value
+            + (self.to_value - self.from_value) * frame as f32 / self.duration_frames as f32
+    }
+
+    fn send_value(&mut self, value: f32) {
+        self.consumer.on_event(&NodeEvent::NodeControl {
+            node_id: self.consumer.get_node_id(),
+            event: self.target.control_event(value),
+        });
+    }
+}
+
+impl BufferConsumerNode for ParamBinding {}
+
+impl Node for ParamBinding {
+    fn get_node_id(&self) -> u64 {
+        self.consumer.get_node_id()
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        match event {
+            NodeEvent::Broadcast(BroadcastControl::SetParam { param_id, value })
+                if *param_id == self.param_id =>
+            {
+                self.from_value = *value;
+                self.to_value = *value;
+                self.duration_frames = 0;
+                self.send_value(*value);
+            }
+            NodeEvent::Broadcast(BroadcastControl::RecallSnapshot {
+                snapshot_id,
+                seconds,
+            }) => {
+                let snapshot_value = self
+                    .snapshots
+                    .iter()
+                    .find(|(id, _)| id == snapshot_id)
+                    .map(|(_, value)| *value);
+                if let Some(value) = snapshot_value {
+                    self.from_value = self.value_at(self.progress_frames);
+                    self.to_value = value;
+                    self.duration_frames = (seconds * consts::PLAYBACK_SAMPLE_RATE as f32) as usize;
+                    self.progress_frames = 0;
+                    if self.duration_frames == 0 {
+                        self.send_value(value);
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.consumer.on_event(event);
+    }
+
+    // Stay active while moving to a snapshot's value, so the node reaches it even if it
+    // is silent until then
+    fn is_active(&self) -> bool {
+        self.progress_frames < self.duration_frames || self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        if self.progress_frames >= self.duration_frames {
+            self.consumer.fill_buffer(buffer);
+            return;
+        }
+        for chunk in buffer.chunks_mut(CONTROL_BLOCK_FRAMES * consts::CHANNEL_COUNT) {
+            let frame_count = chunk.len() / consts::CHANNEL_COUNT;
+            self.progress_frames = (self.progress_frames + frame_count).min(self.duration_frames);
+            self.send_value(self.value_at(self.progress_frames));
+            self.consumer.fill_buffer(chunk);
+        }
+    }
+}
+
+impl BufferConsumer for ParamBinding {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        let mut binding = Self::new(self.param_id, self.target, consumer);
+        binding.snapshots = self.snapshots.clone();
+        binding.from_value = self.from_value;
+        binding.to_value = self.to_value;
+        binding.duration_frames = self.duration_frames;
+        binding.progress_frames = self.progress_frames;
+        Ok(Box::new(binding))
+    }
+}
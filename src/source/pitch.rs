@@ -0,0 +1,97 @@
+use crate::{consts, source::tuning::NoteTuning, BroadcastControl, PitchMotion};
+
+// Follows the pitch of a tone generator through glides and pitch envelopes, in semitones
+// relative to MIDI note numbers so that slides sound even across octaves
+#[derive(Clone)]
+pub(crate) struct PitchTracker {
+    motion: PitchMotion,
+    current_pitch: f32,
+    target_pitch: f32,
+    glide_step: f32,
+    envelope_offset: f32,
+    envelope_step: f32,
+    has_played: bool,
+    frequency: f32,
+    tuning: NoteTuning,
+}
+
+impl PitchTracker {
+    pub(crate) fn new(motion: PitchMotion) -> Self {
+        Self {
+            motion,
+            current_pitch: 0.0,
+            target_pitch: 0.0,
+            glide_step: 0.0,
+            envelope_offset: 0.0,
+            envelope_step: 0.0,
+            has_played: false,
+            frequency: 0.0,
+            tuning: NoteTuning::new(),
+        }
+    }
+
+    pub(crate) fn motion(&self) -> PitchMotion {
+        self.motion
+    }
+
+    pub(crate) fn set_motion(&mut self, motion: PitchMotion) {
+        self.motion = motion;
+    }
+
+    pub(crate) fn tuning(&self) -> &NoteTuning {
+        &self.tuning
+    }
+
+    pub(crate) fn set_tuning(&mut self, tuning: NoteTuning) {
+        self.tuning = tuning;
+    }
+
+    // Follow a Tuning or MasterTune broadcast, retuning any note already playing
+    pub(crate) fn on_tuning(&mut self, control: &BroadcastControl) {
+        self.tuning.on_broadcast(control);
+        if self.has_played {
+            self.frequency = self.frequency_of(self.current_pitch + self.envelope_offset);
+        }
+    }
+
+    pub(crate) fn note_on(&mut self, note: u8) {
+        let sample_rate = consts::PLAYBACK_SAMPLE_RATE as f32;
+        self.target_pitch = note as f32;
+        if self.has_played && self.motion.glide_time > 0.0 {
+            let glide_samples = (self.motion.glide_time * sample_rate).max(1.0);
+            self.glide_step = (self.target_pitch - self.current_pitch).abs() / glide_samples;
+        } else {
+            self.current_pitch = self.target_pitch;
+        }
+        self.envelope_offset = self.motion.envelope_offset;
+        let envelope_samples = (self.motion.envelope_time * sample_rate).max(1.0);
+        self.envelope_step = self.envelope_offset.abs() / envelope_samples;
+        self.has_played = true;
+        self.frequency = self.frequency_of(self.current_pitch + self.envelope_offset);
+    }
+
+    #[inline]
+    fn frequency_of(&self, pitch: f32) -> f32 {
+        self.tuning.frequency_of_pitch(pitch)
+    }
+
+    // Frequency for the next sample, moving the pitch on by one sample
+    #[inline]
+    pub(crate) fn next_frequency(&mut self) -> f32 {
+        if self.current_pitch == self.target_pitch && self.envelope_offset == 0.0 {
+            return self.frequency;
+        }
+        if self.current_pitch < self.target_pitch {
+            self.current_pitch = (self.current_pitch + self.glide_step).min(self.target_pitch);
+        } else if self.current_pitch > self.target_pitch {
+            self.current_pitch = (self.current_pitch - self.glide_step).max(self.target_pitch);
+        }
+        if self.envelope_offset > 0.0 {
+            self.envelope_offset = (self.envelope_offset - self.envelope_step).max(0.0);
+        } else if self.envelope_offset < 0.0 {
+            self.envelope_offset = (self.envelope_offset + self.envelope_step).min(0.0);
+        }
+        self.frequency = self.frequency_of(self.current_pitch + self.envelope_offset);
+        self.frequency
+    }
+}
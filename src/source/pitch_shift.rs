@@ -0,0 +1,171 @@
+use crate::{
+    consts, BufferConsumer, BufferConsumerNode, EffectMix, Error, Node, NodeControlEvent, NodeEvent,
+};
+use std::f32::consts::PI;
+
+// Shortest grain allowed, below which the shifter sounds like a buzz rather than a shift
+const MIN_GRAIN_SECONDS: f32 = 0.005;
+
+fn pitch_ratio(semitones: f32, cents: f32) -> f32 {
+    2.0f32.powf((semitones + cents / 100.0) / 12.0)
+}
+
+/// Shifts the pitch of everything its source plays without changing its timing, for
+/// sounds that cannot simply be retuned, such as one-shots, noise or whole mixes. The
+/// source is recorded into a short delay line and read back by two grains, one half a
+/// grain behind the other, each sweeping through the delay line at the shifted speed and
+/// fading in and out so that their jumps back are not heard. Longer grains smear
+/// transients but keep low notes smoother. A PitchShift control event changes the shift.
+pub struct PitchShiftNode {
+    node_id: u64,
+    effect_mix: EffectMix,
+    ratio: f32,
+    grain_frames: f32,
+    phase: f32,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    intermediate_buffer: Vec<f32>,
+    delay_line: Vec<f32>,
+    write_frame: usize,
+    tail_frames_left: usize,
+}
+
+impl PitchShiftNode {
+    pub fn new(
+        node_id: Option<u64>,
+        semitones: f32,
+        cents: f32,
+        grain_seconds: f32,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        let grain_frames =
+            grain_seconds.max(MIN_GRAIN_SECONDS) * consts::PLAYBACK_SAMPLE_RATE as f32;
+        let delay_frames = grain_frames.ceil() as usize + 2;
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            effect_mix: EffectMix::default(),
+            ratio: pitch_ratio(semitones, cents),
+            grain_frames,
+            phase: 0.0,
+            consumer,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+            delay_line: vec![0.0; delay_frames * consts::CHANNEL_COUNT],
+            write_frame: 0,
+            tail_frames_left: 0,
+        }
+    }
+
+    pub fn set_shift(&mut self, semitones: f32, cents: f32) {
+        self.ratio = pitch_ratio(semitones, cents);
+    }
+
+    // Read a channel from the delay line some fractional number of frames behind the
+    // frame last written
+    #[inline]
+    fn read_delayed(&self, delay_frames: f32, channel: usize) -> f32 {
+        let frame_count = self.delay_line.len() / consts::CHANNEL_COUNT;
+        let whole_frames = delay_frames.floor();
+        let fraction = delay_frames - whole_frames;
+        let newer = (self.write_frame + frame_count - whole_frames as usize) % frame_count;
+        let older = (newer + frame_count - 1) % frame_count;
+        let newer_sample = self.delay_line[newer * consts::CHANNEL_COUNT + channel];
+        let older_sample = self.delay_line[older * consts::CHANNEL_COUNT + channel];
+        newer_sample + (older_sample - newer_sample) * fraction
+    }
+}
+
+impl BufferConsumerNode for PitchShiftNode {}
+
+impl Node for PitchShiftNode {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if self.effect_mix.on_event(self.node_id, event) {
+            return;
+        }
+        if let NodeEvent::NodeControl {
+            node_id,
+            event: NodeControlEvent::PitchShift { semitones, cents },
+        } = event
+        {
+            if *node_id == self.node_id {
+                self.set_shift(*semitones, *cents);
+                return;
+            }
+        }
+        self.consumer.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active() || self.tail_frames_left > 0
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        let buffer_size = buffer.len();
+        // Play out the delay line once the source stops
+        if self.consumer.is_active() {
+            self.tail_frames_left =
+                self.delay_line.len() / consts::CHANNEL_COUNT + buffer_size / consts::CHANNEL_COUNT;
+        }
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+        intermediate_slice.fill(0.0);
+        self.consumer.fill_buffer(intermediate_slice);
+
+        // Currently only-supported channel configuration
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        // Each grain's delay shrinks by the amount the ratio speeds up reading
+        let phase_increment = (1.0 - self.ratio) / self.grain_frames;
+        let frame_count = self.delay_line.len() / consts::CHANNEL_COUNT;
+        for frame_index in 0..buffer_size / consts::CHANNEL_COUNT {
+            let i = frame_index * consts::CHANNEL_COUNT;
+            self.write_frame = (self.write_frame + 1) % frame_count;
+            let write_index = self.write_frame * consts::CHANNEL_COUNT;
+            self.delay_line[write_index] = self.intermediate_buffer[i];
+            self.delay_line[write_index + 1] = self.intermediate_buffer[i + 1];
+
+            // Grains half a cycle apart, with squared-sine windows summing to one
+            let mut shifted = [0.0; consts::CHANNEL_COUNT];
+            for grain_phase in [self.phase, (self.phase + 0.5).fract()] {
+                let window = (grain_phase * PI).sin().powi(2);
+                let delay = grain_phase * self.grain_frames;
+                shifted[0] += window * self.read_delayed(delay, 0);
+                shifted[1] += window * self.read_delayed(delay, 1);
+            }
+            let wet = self.effect_mix.next_wet();
+            buffer[i] += EffectMix::blend(wet, self.intermediate_buffer[i], shifted[0]);
+            buffer[i + 1] += EffectMix::blend(wet, self.intermediate_buffer[i + 1], shifted[1]);
+            self.phase = (self.phase + phase_increment).rem_euclid(1.0);
+        }
+
+        self.tail_frames_left = self
+            .tail_frames_left
+            .saturating_sub(buffer_size / consts::CHANNEL_COUNT);
+    }
+}
+
+impl BufferConsumer for PitchShiftNode {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        let node = Self {
+            node_id: self.node_id,
+            effect_mix: self.effect_mix,
+            ratio: self.ratio,
+            grain_frames: self.grain_frames,
+            phase: 0.0,
+            consumer,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+            delay_line: vec![0.0; self.delay_line.len()],
+            write_frame: 0,
+            tail_frames_left: 0,
+        };
+        Ok(Box::new(node))
+    }
+}
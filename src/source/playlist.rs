@@ -0,0 +1,244 @@
+use crate::{
+    consts, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, MidiSource, Node,
+    NodeControlEvent, NodeEvent,
+};
+use crossbeam_channel::Sender;
+use std::f32::consts::FRAC_PI_2;
+
+// Gain of a song a number of frames into a transition; with no crossfade, the incoming
+// song starts at full volume and the outgoing one rings out at full volume
+#[inline]
+fn transition_gain(frame: usize, crossfade_frames: usize, fading_in: bool) -> f32 {
+    if crossfade_frames == 0 {
+        return 1.0;
+    }
+    let angle = (frame as f32 / crossfade_frames as f32).min(1.0) * FRAC_PI_2;
+    match fading_in {
+        true => angle.sin(),
+        false => angle.cos(),
+    }
+}
+
+fn add_with_transition_gain(
+    buffer: &mut [f32],
+    source: &[f32],
+    start_frame: usize,
+    crossfade_frames: usize,
+    fading_in: bool,
+) {
+    for (i, (out_frame, in_frame)) in buffer
+        .chunks_exact_mut(consts::CHANNEL_COUNT)
+        .zip(source.chunks_exact(consts::CHANNEL_COUNT))
+        .enumerate()
+    {
+        let gain = transition_gain(start_frame + i, crossfade_frames, fading_in);
+        out_frame[0] += in_frame[0] * gain;
+        out_frame[1] += in_frame[1] * gain;
+    }
+}
+
+/// Plays a list of MIDI songs one after another, starting each one on the exact frame
+/// the previous one ends. With a crossfade time, the next song fades in while the notes
+/// still sounding from the previous one fade out; without one, those notes ring out
+/// naturally. When looping, the list starts again from the first song after the last.
+/// A Stop broadcast returns to the first song. The host may be sent the node ID when a
+/// list that does not loop comes to its end, through a channel given in a
+/// NotifyPlaybackComplete control event or to set_completion_sender.
+pub struct PlaylistNode {
+    node_id: u64,
+    songs: Vec<MidiSource>,
+    current_song: usize,
+    fading_song: Option<usize>,
+    looping: bool,
+    crossfade_frames: usize,
+    transition_frames: usize,
+    has_finished: bool,
+    completion_sender: Option<Sender<u64>>,
+    intermediate_buffer: Vec<f32>,
+}
+
+impl PlaylistNode {
+    pub fn new(
+        node_id: Option<u64>,
+        songs: Vec<MidiSource>,
+        crossfade_seconds: f32,
+        looping: bool,
+    ) -> Result<Self, Error> {
+        if songs.is_empty() {
+            return Err(Error::User("A playlist needs at least one song".to_owned()));
+        }
+        let crossfade_frames =
+            (crossfade_seconds.max(0.0) * consts::PLAYBACK_SAMPLE_RATE as f32) as usize;
+        Ok(Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            songs,
+            current_song: 0,
+            fading_song: None,
+            looping,
+            crossfade_frames,
+            transition_frames: crossfade_frames,
+            has_finished: false,
+            completion_sender: None,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        })
+    }
+
+    pub fn set_completion_sender(&mut self, sender: Sender<u64>) {
+        self.completion_sender = Some(sender);
+    }
+
+    /// Index of the song now playing, or the last song once a non-looping list has ended.
+    pub fn current_song(&self) -> usize {
+        self.current_song
+    }
+
+    // Fill the notes still sounding from the previous song, stopping it once it is silent
+    fn fill_fading_song(&mut self, buffer: &mut [f32], start_frame: usize) {
+        let Some(index) = self.fading_song else {
+            return;
+        };
+        let buffer_size = buffer.len();
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+        intermediate_slice.fill(0.0);
+        let song = &mut self.songs[index];
+        song.fill_channel_tails(intermediate_slice);
+        add_with_transition_gain(
+            buffer,
+            intermediate_slice,
+            start_frame,
+            self.crossfade_frames,
+            false,
+        );
+        let end_frame = start_frame + buffer_size / consts::CHANNEL_COUNT;
+        let is_silent = match self.crossfade_frames {
+            0 => !song.is_active(),
+            crossfade_frames => end_frame >= crossfade_frames,
+        };
+        if is_silent {
+            song.on_event(&NodeEvent::Broadcast(BroadcastControl::Stop));
+            self.fading_song = None;
+        }
+    }
+
+    // Move on from the song that just ended, returning whether there is a song to play
+    fn advance(&mut self) -> bool {
+        let previous_song = self.current_song;
+        let next_song = match previous_song + 1 {
+            next if next < self.songs.len() => next,
+            _ if self.looping => 0,
+            _ => {
+                self.has_finished = true;
+                return false;
+            }
+        };
+        if let Some(index) = self.fading_song.take() {
+            self.songs[index].on_event(&NodeEvent::Broadcast(BroadcastControl::Stop));
+        }
+        if next_song != previous_song {
+            self.fading_song = Some(previous_song);
+        }
+        if let Err(error) = self.songs[next_song].rewind() {
+            log_error!("Playlist", "Could not rewind song {}: {}", next_song, error);
+        }
+        self.current_song = next_song;
+        self.transition_frames = 0;
+        true
+    }
+}
+
+impl BufferConsumerNode for PlaylistNode {}
+
+impl Node for PlaylistNode {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if let NodeEvent::NodeControl {
+            node_id,
+            event: NodeControlEvent::NotifyPlaybackComplete(sender),
+        } = event
+        {
+            if *node_id == self.node_id {
+                self.completion_sender = Some(sender.clone());
+                return;
+            }
+        }
+        for song in self.songs.iter_mut() {
+            song.on_event(event);
+        }
+        if let NodeEvent::Broadcast(BroadcastControl::Stop) = event {
+            self.current_song = 0;
+            self.fading_song = None;
+            self.transition_frames = self.crossfade_frames;
+            self.has_finished = false;
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        !self.has_finished
+            || self.songs[self.current_song].is_active()
+            || self.fading_song.is_some()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        // Currently only-supported channel configuration
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        let buffer_size = buffer.len();
+        if self.has_finished {
+            self.fill_fading_song(buffer, self.transition_frames);
+            self.songs[self.current_song].fill_channel_tails(buffer);
+            self.transition_frames += buffer_size / consts::CHANNEL_COUNT;
+            return;
+        }
+
+        self.fill_fading_song(buffer, self.transition_frames);
+        let mut segment_start = 0;
+        let mut transition_start = 0;
+        let mut empty_songs = 0;
+        while segment_start < buffer_size {
+            let segment = &mut buffer[segment_start..];
+            let segment_size = segment.len();
+            let intermediate_slice = &mut self.intermediate_buffer[0..segment_size];
+            intermediate_slice.fill(0.0);
+            let song = &mut self.songs[self.current_song];
+            let data_points_played = song.fill_all_channels(intermediate_slice);
+            add_with_transition_gain(
+                segment,
+                intermediate_slice,
+                self.transition_frames,
+                self.crossfade_frames,
+                true,
+            );
+            if !song.has_finished() {
+                break;
+            }
+
+            // Guard against looping forever over a list of songs with no length
+            empty_songs = match data_points_played {
+                0 => empty_songs + 1,
+                _ => 0,
+            };
+            segment_start += data_points_played;
+            if empty_songs > self.songs.len() || !self.advance() {
+                self.has_finished = true;
+                if let Some(sender) = &self.completion_sender {
+                    let _ = sender.try_send(self.node_id);
+                }
+                self.songs[self.current_song].fill_channel_tails(&mut buffer[segment_start..]);
+                break;
+            }
+            transition_start = segment_start;
+            self.fill_fading_song(&mut buffer[segment_start..], 0);
+        }
+        self.transition_frames += (buffer_size - transition_start) / consts::CHANNEL_COUNT;
+    }
+}
+
+impl BufferConsumer for PlaylistNode {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        Err(Error::User("PlaylistNode cannot be duplicated".to_owned()))
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{
+    consts, source::noise::Xorshift32, source::tuning::NoteTuning, util, BroadcastControl,
+    BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent, NodeEvent, NoteEvent,
+};
+
+// Long enough for the period of the lowest MIDI note
+const MAX_DELAY_SAMPLES: usize = consts::PLAYBACK_SAMPLE_RATE / 8 + 1;
+const RELEASE_DECAY: f32 = 0.95;
+const SILENCE_THRESHOLD: f32 = 0.0001;
+
+/// Plucked string using the Karplus-Strong algorithm: a burst of noise circulating in a
+/// delay line one period long, low-pass filtered and damped on each pass. Decay is the
+/// gain applied on each pass, so values just below 1 ring longest, and released notes are
+/// damped further as if muted by hand. Brightness runs from 0,
+/// averaging each pair of samples for a soft nylon tone, to 1 for a bright metallic one.
+pub struct PluckedStringSource {
+    node_id: u64,
+    peak_amplitude: f32,
+    decay: f32,
+    brightness: f32,
+    is_on: bool,
+    is_held: bool,
+    current_note: u8,
+    current_voice_id: Option<u64>,
+    delay_line: Vec<f32>,
+    delay_length: usize,
+    position: usize,
+    random: Xorshift32,
+    tuning: NoteTuning,
+}
+
+impl PluckedStringSource {
+    pub fn new(node_id: Option<u64>, amplitude: f32, decay: f32, brightness: f32) -> Self {
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            peak_amplitude: amplitude,
+            decay: decay.clamp(0.0, 1.0),
+            brightness: brightness.clamp(0.0, 1.0),
+            is_on: false,
+            is_held: false,
+            current_note: 0,
+            current_voice_id: None,
+            delay_line: vec![0.0; MAX_DELAY_SAMPLES],
+            delay_length: 1,
+            position: 0,
+            random: Xorshift32::new(0x9e3779b9),
+            tuning: NoteTuning::new(),
+        }
+    }
+
+    fn pluck(&mut self, note: u8, vel: f32) {
+        let period = consts::PLAYBACK_SAMPLE_RATE as f32 / self.tuning.frequency_of(note);
+        self.delay_length = (period.round() as usize).clamp(2, MAX_DELAY_SAMPLES);
+        self.position = 0;
+        let amplitude = self.peak_amplitude * vel;
+        for sample in self.delay_line[..self.delay_length].iter_mut() {
+            *sample = self.random.next_bipolar() * amplitude;
+        }
+        self.current_note = note;
+        self.is_on = true;
+        self.is_held = true;
+    }
+}
+
+impl BufferConsumerNode for PluckedStringSource {}
+
+impl Node for PluckedStringSource {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        true
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        match event {
+            NodeEvent::Broadcast(BroadcastControl::NotesOff) => {
+                self.is_held = false;
+            }
+            NodeEvent::Broadcast(BroadcastControl::Stop) => {
+                self.is_on = false;
+                self.is_held = false;
+            }
+            NodeEvent::Broadcast(
+                BroadcastControl::SetParam { .. }
+                | BroadcastControl::RecallSnapshot { .. }
+                | BroadcastControl::Controller { .. }
+                | BroadcastControl::TransportPosition { .. },
+            ) => {}
+            NodeEvent::Broadcast(
+                control @ (BroadcastControl::Tuning(_) | BroadcastControl::MasterTune { .. }),
+            ) => {
+                self.tuning.on_broadcast(control);
+            }
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event,
+            } => match event {
+                NoteEvent::NoteOn { vel } => {
+                    self.current_voice_id = *voice_id;
+                    self.pluck(*note, *vel);
+                }
+                NoteEvent::NoteOff { vel: _ } => {
+                    if self.current_note == *note
+                        && util::is_same_voice(self.current_voice_id, *voice_id)
+                    {
+                        self.is_held = false;
+                    }
+                }
+            },
+            NodeEvent::NodeControl {
+                node_id,
+                event: NodeControlEvent::Volume(volume),
+            } => {
+                if *node_id == self.node_id {
+                    self.peak_amplitude = *volume;
+                }
+            }
+            NodeEvent::NodeControl { .. } => {}
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_on
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        if !self.is_on {
+            return;
+        }
+
+        #[cfg(debug_assertions)]
+        assert_eq!(buffer.len() % consts::CHANNEL_COUNT, 0);
+
+        // Notes ring on after release, just damped more heavily
+        let decay = match self.is_held {
+            true => self.decay,
+            false => self.decay * RELEASE_DECAY,
+        };
+        let next_weight = 0.5 * (1.0 - self.brightness);
+        let mut peak = 0.0f32;
+        for frame in buffer.chunks_exact_mut(consts::CHANNEL_COUNT) {
+            let next_position = (self.position + 1) % self.delay_length;
+            let current = self.delay_line[self.position];
+            let next = self.delay_line[next_position];
+            self.delay_line[self.position] = decay * (current + next_weight * (next - current));
+            self.position = next_position;
+            peak = peak.max(current.abs());
+            for channel in frame.iter_mut() {
+                *channel += current;
+            }
+        }
+        if peak < SILENCE_THRESHOLD {
+            self.is_on = false;
+        }
+    }
+}
+
+impl BufferConsumer for PluckedStringSource {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let mut source = Self::new(
+            Some(self.node_id),
+            self.peak_amplitude,
+            self.decay,
+            self.brightness,
+        );
+        source.tuning = self.tuning.clone();
+        Ok(Box::new(source))
+    }
+}
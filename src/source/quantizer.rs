@@ -0,0 +1,130 @@
+use crate::{
+    util, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent,
+    NodeEvent, NoteEvent, QuantizeDirection, Scale,
+};
+
+/// Snaps the notes passing through it onto a scale, so that generated or live input always
+/// lands in key. The scale and root may be changed while playing with a SetScale control
+/// event; notes already sounding are still released at the pitch they started on.
+pub struct ScaleQuantizer {
+    node_id: u64,
+    scale: Scale,
+    root: u8,
+    direction: QuantizeDirection,
+    sounding_notes: [Option<(u8, Option<u64>)>; 128],
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+}
+
+impl ScaleQuantizer {
+    pub fn new(
+        node_id: Option<u64>,
+        scale: Scale,
+        root: u8,
+        direction: QuantizeDirection,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            scale,
+            root,
+            direction,
+            sounding_notes: [None; 128],
+            consumer,
+        }
+    }
+}
+
+impl BufferConsumerNode for ScaleQuantizer {}
+
+impl Node for ScaleQuantizer {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        match event {
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event,
+            } => {
+                let Some(slot) = self.sounding_notes.get_mut(*note as usize) else {
+                    return;
+                };
+                match event {
+                    NoteEvent::NoteOn { vel } => {
+                        if let Some((previous, previous_voice_id)) = slot.take() {
+                            self.consumer.on_event(&NodeEvent::Note {
+                                note: previous,
+                                voice_id: previous_voice_id,
+                                event: NoteEvent::NoteOff { vel: 0.0 },
+                            });
+                        }
+                        if let Some(snapped) = self.scale.quantize(self.root, *note, self.direction)
+                        {
+                            *slot = Some((snapped, *voice_id));
+                            self.consumer.on_event(&NodeEvent::Note {
+                                note: snapped,
+                                voice_id: *voice_id,
+                                event: NoteEvent::NoteOn { vel: *vel },
+                            });
+                        }
+                    }
+                    NoteEvent::NoteOff { vel } => {
+                        // A note-off for a voice since retriggered leaves the new one alone
+                        let Some((snapped, held_voice_id)) = *slot else {
+                            return;
+                        };
+                        if util::is_same_voice(held_voice_id, *voice_id) {
+                            *slot = None;
+                            self.consumer.on_event(&NodeEvent::Note {
+                                note: snapped,
+                                voice_id: held_voice_id,
+                                event: NoteEvent::NoteOff { vel: *vel },
+                            });
+                        }
+                    }
+                }
+            }
+            NodeEvent::NodeControl {
+                node_id,
+                event: NodeControlEvent::SetScale { scale, root },
+            } if *node_id == self.node_id => {
+                self.scale = scale.clone();
+                self.root = *root;
+            }
+            NodeEvent::Broadcast(BroadcastControl::NotesOff | BroadcastControl::Stop) => {
+                self.sounding_notes = [None; 128];
+                self.consumer.on_event(event);
+            }
+            _ => self.consumer.on_event(event),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        self.consumer.fill_buffer(buffer);
+    }
+}
+
+impl BufferConsumer for ScaleQuantizer {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        let quantizer = Self::new(
+            Some(self.node_id),
+            self.scale.clone(),
+            self.root,
+            self.direction,
+            consumer,
+        );
+        Ok(Box::new(quantizer))
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    consts, source::tuning::NoteTuning, BroadcastControl, BufferConsumer, BufferConsumerNode,
+    EffectMix, Error, Node, NodeEvent, NoteEvent, RingModMode,
+};
+use std::f32::consts::TAU;
+
+#[inline]
+fn modulation_gain(mode: RingModMode, depth: f32, carrier: f32) -> f32 {
+    let modulator = match mode {
+        RingModMode::Ring => carrier,
+        RingModMode::Amplitude => 0.5 * (carrier + 1.0),
+    };
+    1.0 - depth + depth * modulator
+}
+
+/// Multiplies its source by a carrier, either an internal sine oscillator or a second
+/// source. Ring modulation multiplies by the carrier itself, replacing the source's
+/// partials with sum and difference tones for metallic and bell-like sounds; amplitude
+/// modulation multiplies by the carrier raised to swing between 0 and 1, keeping the
+/// source's own pitch as well. The depth blends between the dry source at 0 and full
+/// modulation at 1. The oscillator runs at a fixed frequency, or follows the pitch of
+/// each note at some ratio when note tracking is set.
+pub struct RingModNode {
+    node_id: u64,
+    effect_mix: EffectMix,
+    mode: RingModMode,
+    depth: f32,
+    frequency: f32,
+    note_ratio: Option<f32>,
+    tuning: NoteTuning,
+    phase: f32,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    carrier: Option<Box<dyn BufferConsumerNode + Send + 'static>>,
+    intermediate_buffer: Vec<f32>,
+    carrier_buffer: Vec<f32>,
+}
+
+impl RingModNode {
+    pub fn new(
+        node_id: Option<u64>,
+        mode: RingModMode,
+        depth: f32,
+        frequency: f32,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            effect_mix: EffectMix::default(),
+            mode,
+            depth: depth.clamp(0.0, 1.0),
+            frequency: frequency.max(0.0),
+            note_ratio: None,
+            tuning: NoteTuning::new(),
+            phase: 0.0,
+            consumer,
+            carrier: None,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+            carrier_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        }
+    }
+
+    /// Have the oscillator follow the pitch of each note, at a ratio of the note's frequency,
+    /// or return to its fixed frequency for None.
+    pub fn set_note_ratio(&mut self, ratio: Option<f32>) {
+        self.note_ratio = ratio.map(|ratio| ratio.max(0.0));
+    }
+
+    /// Use a second source as the carrier in place of the oscillator. It is sent the same
+    /// events as the modulated source.
+    pub fn set_carrier(&mut self, carrier: Box<dyn BufferConsumerNode + Send + 'static>) {
+        self.carrier = Some(carrier);
+    }
+}
+
+impl BufferConsumerNode for RingModNode {}
+
+impl Node for RingModNode {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+            && self
+                .carrier
+                .as_ref()
+                .is_none_or(|carrier| carrier.collect_node_ids(ids))
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if self.effect_mix.on_event(self.node_id, event) {
+            return;
+        }
+        match event {
+            NodeEvent::Note {
+                note,
+                event: NoteEvent::NoteOn { .. },
+                ..
+            } => {
+                if let Some(ratio) = self.note_ratio {
+                    self.frequency = self.tuning.frequency_of(*note) * ratio;
+                }
+            }
+            NodeEvent::Broadcast(
+                control @ (BroadcastControl::Tuning(_) | BroadcastControl::MasterTune { .. }),
+            ) => {
+                self.tuning.on_broadcast(control);
+            }
+            _ => {}
+        }
+        self.consumer.on_event(event);
+        if let Some(carrier) = self.carrier.as_mut() {
+            carrier.on_event(event);
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        let buffer_size = buffer.len();
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+        intermediate_slice.fill(0.0);
+        self.consumer.fill_buffer(intermediate_slice);
+
+        // Currently only-supported channel configuration
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        if let Some(carrier) = self.carrier.as_mut() {
+            let carrier_slice = &mut self.carrier_buffer[0..buffer_size];
+            carrier_slice.fill(0.0);
+            carrier.fill_buffer(carrier_slice);
+            for ((output, frame), carrier_frame) in buffer
+                .chunks_exact_mut(consts::CHANNEL_COUNT)
+                .zip(self.intermediate_buffer.chunks_exact(consts::CHANNEL_COUNT))
+                .zip(self.carrier_buffer.chunks_exact(consts::CHANNEL_COUNT))
+            {
+                let wet = self.effect_mix.next_wet();
+                for ((out, sample), carrier) in output.iter_mut().zip(frame).zip(carrier_frame) {
+                    let gain = modulation_gain(self.mode, self.depth, *carrier);
+                    *out += EffectMix::blend(wet, 1.0, gain) * sample;
+                }
+            }
+            return;
+        }
+
+        let phase_increment = self.frequency / consts::PLAYBACK_SAMPLE_RATE as f32;
+        for frame_index in 0..buffer_size / consts::CHANNEL_COUNT {
+            let modulated = modulation_gain(self.mode, self.depth, (self.phase * TAU).sin());
+            let gain = EffectMix::blend(self.effect_mix.next_wet(), 1.0, modulated);
+            self.phase = (self.phase + phase_increment).fract();
+            let i = frame_index * consts::CHANNEL_COUNT;
+            buffer[i] += gain * self.intermediate_buffer[i];
+            buffer[i + 1] += gain * self.intermediate_buffer[i + 1];
+        }
+    }
+}
+
+impl BufferConsumer for RingModNode {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        let mut node = Self::new(
+            Some(self.node_id),
+            self.mode,
+            self.depth,
+            self.frequency,
+            consumer,
+        );
+        node.effect_mix = self.effect_mix;
+        node.note_ratio = self.note_ratio;
+        node.tuning = self.tuning.clone();
+        if let Some(carrier) = &self.carrier {
+            node.carrier = Some(carrier.duplicate()?);
+        }
+        Ok(Box::new(node))
+    }
+}
@@ -0,0 +1,49 @@
+use crate::BufferConsumerNode;
+
+/// Index from node IDs to the children of a node that hold them, built when the node is
+/// made, so that control events addressed to a node go only down the branch containing it
+/// rather than through every branch of a large graph. Children are identified by keys,
+/// such as their index or MIDI channel. Children whose node IDs cannot be listed (see
+/// Node::collect_node_ids) are sent every control event, as they would be without an index.
+pub struct EventRoutes<K> {
+    routes: Vec<(u64, K)>,
+    unlisted: Vec<K>,
+}
+
+impl<K: Copy + Ord> EventRoutes<K> {
+    pub fn new<'a>(
+        children: impl IntoIterator<Item = (K, &'a (dyn BufferConsumerNode + Send + 'static))>,
+    ) -> Self {
+        let mut routes = vec![];
+        let mut unlisted = vec![];
+        let mut ids = vec![];
+        for (key, child) in children {
+            ids.clear();
+            if child.collect_node_ids(&mut ids) {
+                routes.extend(ids.iter().map(|id| (*id, key)));
+            } else {
+                unlisted.push(key);
+            }
+        }
+        routes.sort_unstable();
+        routes.dedup();
+        unlisted.sort_unstable();
+        Self { routes, unlisted }
+    }
+
+    /// Keys of the children that a control event for the given node should be sent to.
+    pub fn targets(&self, node_id: u64) -> impl Iterator<Item = K> + '_ {
+        let start = self.routes.partition_point(|(id, _)| *id < node_id);
+        self.routes[start..]
+            .iter()
+            .take_while(move |(id, _)| *id == node_id)
+            .map(|(_, key)| *key)
+            .chain(self.unlisted.iter().copied())
+    }
+
+    /// Add the node IDs of every listed child, returning whether all children were listed.
+    pub fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.extend(self.routes.iter().map(|(id, _)| *id));
+        self.unlisted.is_empty()
+    }
+}
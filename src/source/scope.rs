@@ -0,0 +1,137 @@
+use crate::{consts, source::buffer, BufferConsumer, BufferConsumerNode, Error, Node, NodeEvent};
+use std::sync::{
+    atomic::{AtomicU32, AtomicUsize, Ordering},
+    Arc,
+};
+
+// Ring of recent interleaved samples, written by the audio thread only
+struct ScopeBuffer {
+    samples: Vec<AtomicU32>,
+    written: AtomicUsize,
+}
+
+impl ScopeBuffer {
+    fn write(&self, data: &[f32]) {
+        let capacity = self.samples.len();
+        let start = self.written.load(Ordering::Relaxed);
+        for (i, sample) in data.iter().enumerate() {
+            self.samples[start.wrapping_add(i) % capacity]
+                .store(sample.to_bits(), Ordering::Relaxed);
+        }
+        self.written
+            .store(start.wrapping_add(data.len()), Ordering::Release);
+    }
+}
+
+/// Host-side reader of the samples most recently played through a ScopeNode, usable
+/// from any thread. Reading never blocks the audio thread, so a read that overlaps a
+/// write may mix samples from neighbouring buffers, which is harmless for display.
+#[derive(Clone)]
+pub struct ScopeReader {
+    buffer: Arc<ScopeBuffer>,
+}
+
+impl ScopeReader {
+    /// Number of frames the scope holds.
+    pub fn capacity_frames(&self) -> usize {
+        self.buffer.samples.len() / consts::CHANNEL_COUNT
+    }
+
+    /// Total number of frames written so far, wrapping on overflow. Comparing this
+    /// between reads tells whether any new output has been played.
+    pub fn frames_written(&self) -> usize {
+        self.buffer.written.load(Ordering::Acquire) / consts::CHANNEL_COUNT
+    }
+
+    /// Copy the most recent interleaved stereo samples into the output, oldest first,
+    /// filling as many whole frames as both it and the scope hold. Returns the number of
+    /// frames copied; frames from before anything was written read as silence.
+    pub fn read_latest(&self, output: &mut [f32]) -> usize {
+        let capacity = self.buffer.samples.len();
+        let frames = (output.len() / consts::CHANNEL_COUNT).min(self.capacity_frames());
+        let sample_count = frames * consts::CHANNEL_COUNT;
+        let end = self.buffer.written.load(Ordering::Acquire);
+        let start = end.wrapping_sub(sample_count);
+        for (i, sample) in output[0..sample_count].iter_mut().enumerate() {
+            let index = start.wrapping_add(i) % capacity;
+            *sample = f32::from_bits(self.buffer.samples[index].load(Ordering::Relaxed));
+        }
+        frames
+    }
+}
+
+/// Passes its source through unchanged while copying the output into a ring buffer of a
+/// fixed number of frames, which a ScopeReader can read for waveform or spectrum display.
+/// A scope cannot be duplicated, since voices writing into one ring would overwrite each
+/// other; to watch a polyphonic source, put the scope around the whole font.
+pub struct ScopeNode {
+    node_id: u64,
+    buffer: Arc<ScopeBuffer>,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    intermediate_buffer: Vec<f32>,
+}
+
+impl ScopeNode {
+    pub fn new(
+        node_id: Option<u64>,
+        capacity_frames: usize,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Result<(ScopeReader, Self), Error> {
+        if capacity_frames == 0 {
+            return Err(Error::User(
+                "A scope needs room for at least one frame".to_owned(),
+            ));
+        }
+        let sample_count = capacity_frames * consts::CHANNEL_COUNT;
+        let buffer = Arc::new(ScopeBuffer {
+            samples: (0..sample_count).map(|_| AtomicU32::new(0)).collect(),
+            written: AtomicUsize::new(0),
+        });
+        let reader = ScopeReader {
+            buffer: buffer.clone(),
+        };
+        let scope = Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            buffer,
+            consumer,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        };
+        Ok((reader, scope))
+    }
+}
+
+impl BufferConsumerNode for ScopeNode {}
+
+impl Node for ScopeNode {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        self.consumer.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        let buffer_size = buffer.len();
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+        intermediate_slice.fill(0.0);
+        self.consumer.fill_buffer(intermediate_slice);
+        self.buffer.write(intermediate_slice);
+        buffer::add_buffer(buffer, intermediate_slice);
+    }
+}
+
+impl BufferConsumer for ScopeNode {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        Err(Error::User("ScopeNode cannot be duplicated".to_owned()))
+    }
+}
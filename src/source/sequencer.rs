@@ -0,0 +1,219 @@
+use crate::{
+    consts, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node, NodeEvent,
+    NoteEvent, SequencerStep,
+};
+
+/// Plays a pattern of steps into its source at a fixed tempo, for jingles too simple to
+/// be worth a MIDI file. Swing delays every second step by a fraction of a step, where
+/// 0.33 gives a triplet feel. Non-looping patterns finish after their last step, once any
+/// notes still held have been released. A Stop broadcast rewinds to the first step.
+pub struct SequencerSource {
+    node_id: u64,
+    bpm: f32,
+    steps_per_beat: u32,
+    swing: f32,
+    looping: bool,
+    steps: Vec<SequencerStep>,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    next_step: usize,
+    samples_until_step: f64,
+    held_notes: Vec<(u8, f64)>,
+    has_finished: bool,
+}
+
+impl SequencerSource {
+    pub fn new(
+        node_id: Option<u64>,
+        bpm: f32,
+        steps_per_beat: u32,
+        swing: f32,
+        looping: bool,
+        steps: Vec<SequencerStep>,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Result<Self, Error> {
+        if bpm.is_nan() || bpm <= 0.0 || steps_per_beat == 0 {
+            return Err(Error::User(format!(
+                "Sequencer needs a positive tempo and steps per beat, but got {} BPM and {}",
+                bpm, steps_per_beat
+            )));
+        }
+        Ok(Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            bpm,
+            steps_per_beat,
+            swing: swing.clamp(0.0, 0.5),
+            looping,
+            has_finished: steps.is_empty(),
+            held_notes: Vec::with_capacity(steps.len()),
+            steps,
+            consumer,
+            next_step: 0,
+            samples_until_step: 0.0,
+        })
+    }
+
+    fn samples_per_step(&self) -> f64 {
+        let samples_per_beat = 60.0 * consts::PLAYBACK_SAMPLE_RATE as f64 / self.bpm as f64;
+        samples_per_beat / self.steps_per_beat as f64
+    }
+
+    fn release_all(&mut self) {
+        for (note, _) in self.held_notes.drain(..) {
+            self.consumer.on_event(&NodeEvent::Note {
+                note,
+                voice_id: None,
+                event: NoteEvent::NoteOff { vel: 0.0 },
+            });
+        }
+    }
+
+    fn rewind(&mut self) {
+        self.held_notes.clear();
+        self.next_step = 0;
+        self.samples_until_step = 0.0;
+        self.has_finished = self.steps.is_empty();
+    }
+
+    fn play_step(&mut self) {
+        let step_samples = self.samples_per_step();
+        let index = self.next_step;
+        if let SequencerStep::Note {
+            note,
+            velocity,
+            gate,
+        } = self.steps[index]
+        {
+            if let Some(position) = self.held_notes.iter().position(|(held, _)| *held == note) {
+                self.held_notes.remove(position);
+                self.consumer.on_event(&NodeEvent::Note {
+                    note,
+                    voice_id: None,
+                    event: NoteEvent::NoteOff { vel: 0.0 },
+                });
+            }
+            self.consumer.on_event(&NodeEvent::Note {
+                note,
+                voice_id: None,
+                event: NoteEvent::NoteOn { vel: velocity },
+            });
+            self.held_notes
+                .push((note, (gate as f64 * step_samples).max(1.0)));
+        }
+
+        // Every second step starts late, with the step after it back on the beat
+        let swing_samples = self.swing as f64 * step_samples;
+        self.samples_until_step += match index % 2 {
+            0 => step_samples + swing_samples,
+            _ => step_samples - swing_samples,
+        };
+        self.next_step += 1;
+        if self.next_step >= self.steps.len() {
+            match self.looping {
+                true => self.next_step = 0,
+                false => self.has_finished = true,
+            }
+        }
+    }
+
+    fn advance(&mut self, samples: f64) {
+        self.samples_until_step -= samples;
+        for (_, remaining) in self.held_notes.iter_mut() {
+            *remaining -= samples;
+        }
+    }
+
+    fn release_due_notes(&mut self) {
+        let mut index = 0;
+        while index < self.held_notes.len() {
+            let (note, remaining) = self.held_notes[index];
+            if remaining > 0.0 {
+                index += 1;
+                continue;
+            }
+            self.held_notes.remove(index);
+            self.consumer.on_event(&NodeEvent::Note {
+                note,
+                voice_id: None,
+                event: NoteEvent::NoteOff { vel: 0.0 },
+            });
+        }
+    }
+}
+
+impl BufferConsumerNode for SequencerSource {}
+
+impl Node for SequencerSource {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        match event {
+            NodeEvent::Broadcast(BroadcastControl::Stop) => self.rewind(),
+            NodeEvent::Broadcast(BroadcastControl::NotesOff) => self.release_all(),
+            _ => {}
+        }
+        self.consumer.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        !self.has_finished || !self.held_notes.is_empty() || self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        #[cfg(debug_assertions)]
+        assert_eq!(buffer.len() % consts::CHANNEL_COUNT, 0);
+
+        let mut remaining_buffer = buffer;
+        loop {
+            self.release_due_notes();
+            if !self.has_finished && self.samples_until_step <= 0.0 {
+                self.play_step();
+                continue;
+            }
+
+            // Render up to whichever of the next step or note release comes first
+            let mut samples_until_event = f64::MAX;
+            if !self.has_finished {
+                samples_until_event = self.samples_until_step;
+            }
+            for (_, remaining) in self.held_notes.iter() {
+                samples_until_event = samples_until_event.min(*remaining);
+            }
+            let frames_available = remaining_buffer.len() / consts::CHANNEL_COUNT;
+            let frames_until_event = samples_until_event.ceil().max(1.0);
+            if frames_until_event >= frames_available as f64 {
+                self.consumer.fill_buffer(remaining_buffer);
+                self.advance(frames_available as f64);
+                return;
+            }
+            let frames = frames_until_event as usize;
+            let (filled, rest) =
+                std::mem::take(&mut remaining_buffer).split_at_mut(frames * consts::CHANNEL_COUNT);
+            self.consumer.fill_buffer(filled);
+            self.advance(frames as f64);
+            remaining_buffer = rest;
+        }
+    }
+}
+
+impl BufferConsumer for SequencerSource {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        let sequencer = Self::new(
+            Some(self.node_id),
+            self.bpm,
+            self.steps_per_beat,
+            self.swing,
+            self.looping,
+            self.steps.clone(),
+            consumer,
+        )?;
+        Ok(Box::new(sequencer))
+    }
+}
@@ -1,16 +1,22 @@
 use crate::{
-    consts, util, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node,
-    NodeControlEvent, NodeEvent, NoteEvent,
+    consts,
+    source::{buffer, pitch::PitchTracker, sync::HardSync, unison::UnisonVoices},
+    util, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent,
+    NodeEvent, NoteEvent, OscillatorMode, PitchMotion, Unison,
 };
 
 pub struct SquareWaveSource {
     node_id: u64,
     is_on: bool,
     current_note: u8,
+    current_voice_id: Option<u64>,
     current_amplitude: f32,
-    cycle_progress_samples: f32,
-    period_samples_a440: f32,
+    pitch: PitchTracker,
+    voices: UnisonVoices,
+    reset_phase: bool,
+    sync: HardSync,
     peak_amplitude: f32,
+    oscillator: OscillatorMode,
     duty_cycle: f32,
 }
 
@@ -20,13 +26,35 @@ impl SquareWaveSource {
             node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
             is_on: false,
             current_note: 0,
+            current_voice_id: None,
             current_amplitude: 0.0,
-            cycle_progress_samples: 0.0,
-            period_samples_a440: consts::PLAYBACK_SAMPLE_RATE as f32 / 440.0,
+            pitch: PitchTracker::new(PitchMotion::NONE),
+            voices: UnisonVoices::new(Unison::NONE),
+            reset_phase: false,
+            sync: HardSync::new(),
             peak_amplitude: amplitude,
+            oscillator: OscillatorMode::Naive,
             duty_cycle,
         }
     }
+
+    pub fn set_oscillator_mode(&mut self, oscillator: OscillatorMode) {
+        self.oscillator = oscillator;
+    }
+
+    pub fn set_pitch_motion(&mut self, motion: PitchMotion) {
+        self.pitch.set_motion(motion);
+    }
+
+    pub fn set_unison(&mut self, unison: Unison) {
+        self.voices = UnisonVoices::new(unison);
+    }
+
+    /// Restart the waveform from the start of its cycle on each NoteOn, rather than
+    /// running on continuously from the last note.
+    pub fn set_phase_reset(&mut self, reset_phase: bool) {
+        self.reset_phase = reset_phase;
+    }
 }
 
 impl BufferConsumerNode for SquareWaveSource {}
@@ -36,19 +64,54 @@ impl Node for SquareWaveSource {
         self.node_id
     }
 
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        true
+    }
+
+    fn cycle_starts(&self) -> &[f32] {
+        self.sync.cycle_starts()
+    }
+
+    fn sync_to(&mut self, cycle_starts: &[f32]) {
+        self.sync.sync_to(cycle_starts);
+    }
+
     fn on_event(&mut self, event: &NodeEvent) {
         match event {
-            NodeEvent::Broadcast(BroadcastControl::NotesOff) => {
+            NodeEvent::Broadcast(BroadcastControl::NotesOff | BroadcastControl::Stop) => {
                 self.is_on = false;
             }
-            NodeEvent::Note { note, event } => match event {
+            NodeEvent::Broadcast(
+                BroadcastControl::SetParam { .. }
+                | BroadcastControl::RecallSnapshot { .. }
+                | BroadcastControl::Controller { .. }
+                | BroadcastControl::TransportPosition { .. },
+            ) => {}
+            NodeEvent::Broadcast(
+                control @ (BroadcastControl::Tuning(_) | BroadcastControl::MasterTune { .. }),
+            ) => {
+                self.pitch.on_tuning(control);
+            }
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event,
+            } => match event {
                 NoteEvent::NoteOn { vel } => {
                     self.is_on = true;
                     self.current_note = *note;
+                    self.current_voice_id = *voice_id;
                     self.current_amplitude = self.peak_amplitude * vel;
+                    self.pitch.note_on(*note);
+                    if self.reset_phase {
+                        self.voices.reset_phases();
+                    }
                 }
                 NoteEvent::NoteOff { vel: _ } => {
-                    if self.current_note != *note {
+                    if self.current_note != *note
+                        || !util::is_same_voice(self.current_voice_id, *voice_id)
+                    {
                         return;
                     }
                     self.is_on = false;
@@ -70,15 +133,18 @@ impl Node for SquareWaveSource {
         }
     }
 
+    fn is_active(&self) -> bool {
+        self.is_on
+    }
+
     fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        self.sync.begin_buffer();
         if !self.is_on {
+            self.sync.end_buffer();
             return;
         }
         let size = buffer.len();
-        let note_frequency = util::frequency_of(self.current_note);
-        let pitch_period_samples = consts::PLAYBACK_SAMPLE_RATE as f32 / note_frequency;
-        let mut stretched_progress =
-            self.cycle_progress_samples * pitch_period_samples / self.period_samples_a440;
+        let sample_rate = consts::PLAYBACK_SAMPLE_RATE as f32;
 
         #[cfg(debug_assertions)]
         assert_eq!(size % consts::CHANNEL_COUNT, 0);
@@ -87,28 +153,45 @@ impl Node for SquareWaveSource {
         #[cfg(debug_assertions)]
         assert_eq!(consts::CHANNEL_COUNT, 2);
 
-        for i in (0..size).step_by(consts::CHANNEL_COUNT) {
-            stretched_progress += 1.0;
-            if stretched_progress >= pitch_period_samples {
-                stretched_progress -= pitch_period_samples;
+        buffer::add_rendered_frames(buffer, self.current_amplitude, |frame| {
+            if self.sync.should_restart(frame) {
+                self.voices.reset_phases();
             }
-            let duty = stretched_progress / pitch_period_samples;
-            let amplitude = match duty > self.duty_cycle {
-                true => self.current_amplitude,
-                false => -self.current_amplitude,
-            };
-            buffer[i] += amplitude;
-            buffer[i + 1] += amplitude;
-        }
-
-        self.cycle_progress_samples =
-            stretched_progress * self.period_samples_a440 / pitch_period_samples;
+            let base_increment = self.pitch.next_frequency() / sample_rate;
+            let (mut left, mut right) = (0.0, 0.0);
+            for voice in 0..self.voices.count() {
+                let (duty, phase_increment) = self.voices.advance(voice, base_increment);
+                if voice == 0 {
+                    self.sync.record_wrap(frame, duty, phase_increment);
+                }
+                let mut level = match duty > self.duty_cycle {
+                    true => 1.0,
+                    false => -1.0,
+                };
+                if self.oscillator == OscillatorMode::BandLimited {
+                    // Rising edge at the duty cycle point, falling edge at the cycle start
+                    level +=
+                        util::poly_blep((duty - self.duty_cycle).rem_euclid(1.0), phase_increment);
+                    level -= util::poly_blep(duty, phase_increment);
+                }
+                let (left_gain, right_gain) = self.voices.gains(voice);
+                left += level * left_gain;
+                right += level * right_gain;
+            }
+            (left, right)
+        });
+        self.sync.end_buffer();
     }
 }
 
 impl BufferConsumer for SquareWaveSource {
     fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
-        let source = Self::new(Some(self.node_id), self.peak_amplitude, self.duty_cycle);
+        let mut source = Self::new(Some(self.node_id), self.peak_amplitude, self.duty_cycle);
+        source.set_oscillator_mode(self.oscillator);
+        source.set_pitch_motion(self.pitch.motion());
+        source.pitch.set_tuning(self.pitch.tuning().clone());
+        source.set_unison(self.voices.unison());
+        source.set_phase_reset(self.reset_phase);
         Ok(Box::new(source))
     }
 }
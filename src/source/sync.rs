@@ -0,0 +1,67 @@
+use crate::consts;
+
+// Where an oscillator began new cycles during its last buffer, and where it is to restart
+// during its next one as a hard-sync follower, in fractional frames from the buffer start.
+// Both lists hold at most a buffer's worth of frames, so neither allocates while playing.
+pub(crate) struct HardSync {
+    cycle_starts: Vec<f32>,
+    sync_points: Vec<f32>,
+    next_sync_point: usize,
+}
+
+impl HardSync {
+    pub(crate) fn new() -> Self {
+        Self {
+            cycle_starts: Vec::with_capacity(consts::BUFFER_SIZE),
+            sync_points: Vec::with_capacity(consts::BUFFER_SIZE),
+            next_sync_point: 0,
+        }
+    }
+
+    pub(crate) fn cycle_starts(&self) -> &[f32] {
+        &self.cycle_starts
+    }
+
+    pub(crate) fn sync_to(&mut self, cycle_starts: &[f32]) {
+        self.sync_points.clear();
+        let count = cycle_starts.len().min(self.sync_points.capacity());
+        self.sync_points.extend_from_slice(&cycle_starts[0..count]);
+        self.next_sync_point = 0;
+    }
+
+    // Called before filling a buffer, to start recording its cycle starts afresh
+    pub(crate) fn begin_buffer(&mut self) {
+        self.cycle_starts.clear();
+    }
+
+    // Called after filling a buffer; sync points given for it are not used again
+    pub(crate) fn end_buffer(&mut self) {
+        self.sync_points.clear();
+        self.next_sync_point = 0;
+    }
+
+    // Record that the oscillator wrapped around at some frame, if its phase is now less
+    // than one step in
+    #[inline]
+    pub(crate) fn record_wrap(&mut self, frame: usize, phase: f32, increment: f32) {
+        if phase < increment && self.cycle_starts.len() < self.cycle_starts.capacity() {
+            self.cycle_starts.push(frame as f32 - phase / increment);
+        }
+    }
+
+    // Whether the oscillator should restart its cycle at some frame, with frames asked
+    // about in order through the buffer
+    #[inline]
+    pub(crate) fn should_restart(&mut self, frame: usize) -> bool {
+        let mut should_restart = false;
+        while self
+            .sync_points
+            .get(self.next_sync_point)
+            .is_some_and(|point| *point <= frame as f32)
+        {
+            should_restart = true;
+            self.next_sync_point += 1;
+        }
+        should_restart
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{BufferConsumer, BufferConsumerNode, Error, Node, NodeEvent};
+
+/// Gives a node tags from the config, so that a control event sent to the ID of a tag, as
+/// given by util::tag_id, reaches every node with that tag as though sent to each one's own
+/// ID. Otherwise transparent, sharing the node's ID.
+pub struct TagBinding {
+    tag_ids: Vec<u64>,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+}
+
+impl TagBinding {
+    pub fn new(tag_ids: Vec<u64>, consumer: Box<dyn BufferConsumerNode + Send + 'static>) -> Self {
+        Self { tag_ids, consumer }
+    }
+}
+
+impl BufferConsumerNode for TagBinding {}
+
+impl Node for TagBinding {
+    fn get_node_id(&self) -> u64 {
+        self.consumer.get_node_id()
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.extend_from_slice(&self.tag_ids);
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if let NodeEvent::NodeControl { node_id, event } = event {
+            if self.tag_ids.contains(node_id) {
+                self.consumer.on_event(&NodeEvent::NodeControl {
+                    node_id: self.consumer.get_node_id(),
+                    event: event.clone(),
+                });
+            }
+        }
+
+        // Nodes further in may share the tag, so it is passed on as well
+        self.consumer.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        self.consumer.fill_buffer(buffer);
+    }
+}
+
+impl BufferConsumer for TagBinding {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        Ok(Box::new(Self::new(self.tag_ids.clone(), consumer)))
+    }
+}
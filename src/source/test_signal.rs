@@ -0,0 +1,241 @@
+use crate::{
+    consts,
+    mix::layout::{self, DeviceChannels},
+    source::noise::{PinkFilter, Xorshift32},
+    BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node, NodeControlEvent, NodeEvent,
+};
+
+const CHANNEL_ID_FREQUENCY: f32 = 1000.0;
+const CHANNEL_ID_BEEP_SECONDS: f32 = 0.15;
+const CHANNEL_ID_GAP_SECONDS: f32 = 0.15;
+const CHANNEL_ID_PAUSE_SECONDS: f32 = 1.0;
+const CHANNEL_ID_LEVEL_DB: f32 = -12.0;
+
+// Brings filtered pink noise to roughly unit RMS
+const PINK_NOISE_RMS_SCALE: f32 = 0.55;
+
+/// Signals produced by TestSignalSource. Levels are in dBFS; tones are calibrated by
+/// their peak and pink noise by its RMS level. A channel of None plays in all channels.
+///
+/// When played through a BaseMixer, a channel numbers the speakers of the output device in
+/// the order of its ChannelLayout, so the rear and surround speakers of quad and 5.1 can be
+/// addressed and the signal skips the mapping from stereo. Elsewhere, such as on a parallel
+/// Combiner's workers or when rendering without a mixer, it numbers the graph's stereo
+/// channels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TestSignal {
+    Off,
+    Tone {
+        frequency: f32,
+        level_db: f32,
+        channel: Option<usize>,
+    },
+    PinkNoise {
+        level_db: f32,
+        channel: Option<usize>,
+    },
+    /// Beep in each channel in turn, once for the first channel, twice for the second
+    /// and so on, so each speaker can be identified by ear. The beeps stand in for spoken
+    /// channel names, which would need recordings in every language to be of use.
+    ChannelId,
+}
+
+/// Diagnostics node for checking routing and levels on real hardware. Silent until a
+/// TestSignal control event addressed to it selects a signal to play.
+pub struct TestSignalSource {
+    node_id: u64,
+    signal: TestSignal,
+    phase: f32,
+    frames_elapsed: usize,
+    random: Xorshift32,
+    pink_filter: PinkFilter,
+}
+
+impl TestSignalSource {
+    pub fn new(node_id: Option<u64>) -> Self {
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            signal: TestSignal::Off,
+            phase: 0.0,
+            frames_elapsed: 0,
+            random: Xorshift32::new(0x12345678),
+            pink_filter: PinkFilter::default(),
+        }
+    }
+
+    pub fn set_signal(&mut self, signal: TestSignal) {
+        self.signal = signal;
+        self.phase = 0.0;
+        self.frames_elapsed = 0;
+        self.pink_filter = PinkFilter::default();
+    }
+
+    #[inline]
+    fn gain_of(level_db: f32) -> f32 {
+        10.0f32.powf(level_db / 20.0)
+    }
+
+    #[inline]
+    fn next_sine(&mut self, frequency: f32) -> f32 {
+        let sample = (self.phase * std::f32::consts::TAU).sin();
+        self.phase += frequency / consts::PLAYBACK_SAMPLE_RATE as f32;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+        }
+        sample
+    }
+
+    #[inline]
+    fn next_pink(&mut self) -> f32 {
+        let white = self.random.next_bipolar();
+        self.pink_filter.next(white) * PINK_NOISE_RMS_SCALE
+    }
+
+    // The channel currently being identified out of the given number, or None between beeps
+    fn channel_id_target(&self, channel_count: usize) -> Option<usize> {
+        let rate = consts::PLAYBACK_SAMPLE_RATE as f32;
+        let beep_frames = (CHANNEL_ID_BEEP_SECONDS * rate) as usize;
+        let gap_frames = (CHANNEL_ID_GAP_SECONDS * rate) as usize;
+        let pause_frames = (CHANNEL_ID_PAUSE_SECONDS * rate) as usize;
+        let cycle_frames: usize = (0..channel_count)
+            .map(|channel| (channel + 1) * (beep_frames + gap_frames) + pause_frames)
+            .sum();
+        let mut position = self.frames_elapsed % cycle_frames;
+        for channel in 0..channel_count {
+            let channel_frames = (channel + 1) * (beep_frames + gap_frames);
+            if position < channel_frames {
+                let is_beeping = position % (beep_frames + gap_frames) < beep_frames;
+                return is_beeping.then_some(channel);
+            }
+            position -= channel_frames;
+            if position < pause_frames {
+                return None;
+            }
+            position -= pause_frames;
+        }
+        None
+    }
+}
+
+impl BufferConsumerNode for TestSignalSource {}
+
+impl Node for TestSignalSource {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        true
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        match event {
+            NodeEvent::Broadcast(BroadcastControl::NotesOff | BroadcastControl::Stop) => {
+                self.set_signal(TestSignal::Off);
+            }
+            NodeEvent::NodeControl {
+                node_id,
+                event: NodeControlEvent::TestSignal(signal),
+            } => {
+                if *node_id != self.node_id {
+                    return;
+                }
+                self.set_signal(*signal);
+            }
+            _ => {}
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.signal != TestSignal::Off
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        #[cfg(debug_assertions)]
+        assert_eq!(buffer.len() % consts::CHANNEL_COUNT, 0);
+
+        match self.signal {
+            TestSignal::Off => {}
+            TestSignal::Tone {
+                frequency,
+                level_db,
+                channel,
+            } => {
+                let gain = Self::gain_of(level_db);
+                layout::with_device_channels(|mut device| {
+                    for (index, frame) in buffer.chunks_exact_mut(consts::CHANNEL_COUNT).enumerate()
+                    {
+                        let sample = self.next_sine(frequency) * gain;
+                        add_to_channels(frame, device.as_deref_mut(), index, channel, sample);
+                    }
+                });
+            }
+            TestSignal::PinkNoise { level_db, channel } => {
+                let gain = Self::gain_of(level_db);
+                layout::with_device_channels(|mut device| {
+                    for (index, frame) in buffer.chunks_exact_mut(consts::CHANNEL_COUNT).enumerate()
+                    {
+                        let sample = self.next_pink() * gain;
+                        add_to_channels(frame, device.as_deref_mut(), index, channel, sample);
+                    }
+                });
+            }
+            TestSignal::ChannelId => {
+                let gain = Self::gain_of(CHANNEL_ID_LEVEL_DB);
+                layout::with_device_channels(|mut device| {
+                    let channel_count = device
+                        .as_ref()
+                        .map_or(consts::CHANNEL_COUNT, |device| device.channel_count());
+                    for (index, frame) in buffer.chunks_exact_mut(consts::CHANNEL_COUNT).enumerate()
+                    {
+                        let sample = self.next_sine(CHANNEL_ID_FREQUENCY) * gain;
+                        if let Some(channel) = self.channel_id_target(channel_count) {
+                            add_to_channels(
+                                frame,
+                                device.as_deref_mut(),
+                                index,
+                                Some(channel),
+                                sample,
+                            );
+                        }
+                        self.frames_elapsed += 1;
+                    }
+                });
+            }
+        }
+    }
+}
+
+// Add a sample to one channel, of the device if one was lent and else of the stereo frame,
+// or to every channel of the stereo frame
+#[inline]
+fn add_to_channels(
+    frame: &mut [f32],
+    device: Option<&mut DeviceChannels>,
+    frame_index: usize,
+    channel: Option<usize>,
+    sample: f32,
+) {
+    match (channel, device) {
+        (Some(channel), Some(device)) => device.add(frame_index, channel, sample),
+        (Some(channel), None) => {
+            if let Some(output) = frame.get_mut(channel) {
+                *output += sample;
+            }
+        }
+        (None, _) => {
+            for output in frame.iter_mut() {
+                *output += sample;
+            }
+        }
+    }
+}
+
+impl BufferConsumer for TestSignalSource {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let mut source = Self::new(Some(self.node_id));
+        source.set_signal(self.signal);
+        Ok(Box::new(source))
+    }
+}
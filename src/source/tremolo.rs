@@ -0,0 +1,92 @@
+use crate::{consts, BufferConsumer, BufferConsumerNode, EffectMix, Error, Node, NodeEvent};
+use std::f32::consts::TAU;
+
+/// Pulses the volume of its source with a sine wave, as a shortcut for the most common
+/// use of an LFO. The rate is in Hz, and the depth from 0 to 1 is how far the volume dips
+/// at the bottom of each pulse.
+pub struct TremoloNode {
+    node_id: u64,
+    effect_mix: EffectMix,
+    rate: f32,
+    depth: f32,
+    phase: f32,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    intermediate_buffer: Vec<f32>,
+}
+
+impl TremoloNode {
+    pub fn new(
+        node_id: Option<u64>,
+        rate: f32,
+        depth: f32,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            effect_mix: EffectMix::default(),
+            rate: rate.max(0.0),
+            depth: depth.clamp(0.0, 1.0),
+            phase: 0.0,
+            consumer,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        }
+    }
+}
+
+impl BufferConsumerNode for TremoloNode {}
+
+impl Node for TremoloNode {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if self.effect_mix.on_event(self.node_id, event) {
+            return;
+        }
+        self.consumer.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        let buffer_size = buffer.len();
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+        intermediate_slice.fill(0.0);
+        self.consumer.fill_buffer(intermediate_slice);
+
+        // Currently only-supported channel configuration
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        let phase_increment = self.rate / consts::PLAYBACK_SAMPLE_RATE as f32;
+        for frame_index in 0..buffer_size / consts::CHANNEL_COUNT {
+            let wet = self.effect_mix.next_wet();
+            let gain = EffectMix::blend(
+                wet,
+                1.0,
+                1.0 - self.depth * 0.5 * (1.0 - (self.phase * TAU).cos()),
+            );
+            self.phase = (self.phase + phase_increment).fract();
+            let i = frame_index * consts::CHANNEL_COUNT;
+            buffer[i] += gain * self.intermediate_buffer[i];
+            buffer[i + 1] += gain * self.intermediate_buffer[i + 1];
+        }
+    }
+}
+
+impl BufferConsumer for TremoloNode {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        let mut tremolo = Self::new(Some(self.node_id), self.rate, self.depth, consumer);
+        tremolo.effect_mix = self.effect_mix;
+        Ok(Box::new(tremolo))
+    }
+}
@@ -0,0 +1,181 @@
+use crate::{util, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, Node, NodeEvent};
+use std::sync::{Arc, LazyLock};
+
+static EQUAL_TEMPERAMENT: LazyLock<Arc<TuningTable>> =
+    LazyLock::new(|| Arc::new(TuningTable::equal_temperament()));
+
+/// The frequency played for each MIDI key. Tone generators and samplers look up the notes
+/// they play here, so that a graph or font may be tuned to any scale rather than the usual
+/// twelve-tone equal temperament. Load one from Scala files with util::tuning_from_scala_files.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TuningTable {
+    frequencies: [f32; 128],
+}
+
+impl TuningTable {
+    /// Twelve-tone equal temperament with A4 (key 69) at 440 Hz.
+    pub fn equal_temperament() -> Self {
+        let mut frequencies = [0.0; 128];
+        for (key, frequency) in frequencies.iter_mut().enumerate() {
+            *frequency = util::frequency_of(key as u8);
+        }
+        Self { frequencies }
+    }
+
+    /// A table with a frequency in Hz given for every key, each of which must be positive.
+    pub fn from_frequencies(frequencies: [f32; 128]) -> Result<Self, Error> {
+        if frequencies
+            .iter()
+            .any(|frequency| !frequency.is_finite() || *frequency <= 0.0)
+        {
+            return Err(Error::User(
+                "Tuning frequencies must all be positive".to_owned(),
+            ));
+        }
+        Ok(Self { frequencies })
+    }
+
+    /// A table offsetting keys from equal temperament by some cents. Either 12 offsets are
+    /// given, repeating every octave from C, or 128 with one for every key.
+    pub fn from_cents(cents: &[f32]) -> Result<Self, Error> {
+        if cents.len() != 12 && cents.len() != 128 {
+            return Err(Error::User(format!(
+                "Tuning needs 12 or 128 cents offsets, but {} were given",
+                cents.len()
+            )));
+        }
+        let mut frequencies = [0.0; 128];
+        for (key, frequency) in frequencies.iter_mut().enumerate() {
+            let offset = cents[key % cents.len()];
+            *frequency = util::frequency_of(key as u8) * 2.0f32.powf(offset / 1200.0);
+        }
+        Self::from_frequencies(frequencies)
+    }
+
+    // The equal-tempered table shared by every generator not given another
+    pub(crate) fn shared_default() -> Arc<Self> {
+        EQUAL_TEMPERAMENT.clone()
+    }
+
+    /// The frequency in Hz played for a key.
+    #[inline]
+    pub fn frequency_of(&self, key: u8) -> f32 {
+        self.frequencies[key.min(127) as usize]
+    }
+
+    /// The frequency in Hz played for a pitch in fractional keys, as reached part way
+    /// through a glide, found between its neighbouring keys in equal steps of pitch.
+    /// Pitches beyond the keyboard continue in equal-tempered semitones from its ends.
+    #[inline]
+    pub fn frequency_of_pitch(&self, pitch: f32) -> f32 {
+        if pitch <= 0.0 {
+            return self.frequencies[0] * 2.0f32.powf(pitch / 12.0);
+        }
+        if pitch >= 127.0 {
+            return self.frequencies[127] * 2.0f32.powf((pitch - 127.0) / 12.0);
+        }
+        let key = pitch.floor() as usize;
+        let fraction = pitch - key as f32;
+        let below = self.frequencies[key];
+        if fraction == 0.0 {
+            return below;
+        }
+        let above = self.frequencies[key + 1];
+        below * (above / below).powf(fraction)
+    }
+}
+
+impl Default for TuningTable {
+    fn default() -> Self {
+        Self::equal_temperament()
+    }
+}
+
+// The tuning a generator plays its notes in, from a table raised or lowered by any master
+// tune, following the Tuning and MasterTune broadcasts it is sent
+#[derive(Clone)]
+pub(crate) struct NoteTuning {
+    table: Arc<TuningTable>,
+    ratio: f32,
+}
+
+impl NoteTuning {
+    pub(crate) fn new() -> Self {
+        Self {
+            table: TuningTable::shared_default(),
+            ratio: 1.0,
+        }
+    }
+
+    pub(crate) fn on_broadcast(&mut self, control: &BroadcastControl) {
+        match control {
+            BroadcastControl::Tuning(table) => self.table = table.clone(),
+            BroadcastControl::MasterTune { cents } => self.ratio = 2.0f32.powf(cents / 1200.0),
+            _ => {}
+        }
+    }
+
+    #[inline]
+    pub(crate) fn frequency_of(&self, key: u8) -> f32 {
+        self.table.frequency_of(key) * self.ratio
+    }
+
+    #[inline]
+    pub(crate) fn frequency_of_pitch(&self, pitch: f32) -> f32 {
+        self.table.frequency_of_pitch(pitch) * self.ratio
+    }
+}
+
+/// Tunes every generator within a node to a table, by sending it a Tuning broadcast when
+/// built. Tunings broadcast from further out are not passed on, so that a font or part of a
+/// graph tuned here keeps its own tuning. Otherwise transparent, sharing the node's ID.
+pub struct TuningNode {
+    tuning: Arc<TuningTable>,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+}
+
+impl TuningNode {
+    pub fn new(
+        tuning: Arc<TuningTable>,
+        mut consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        consumer.on_event(&NodeEvent::Broadcast(BroadcastControl::Tuning(
+            tuning.clone(),
+        )));
+        Self { tuning, consumer }
+    }
+}
+
+impl BufferConsumerNode for TuningNode {}
+
+impl Node for TuningNode {
+    fn get_node_id(&self) -> u64 {
+        self.consumer.get_node_id()
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if let NodeEvent::Broadcast(BroadcastControl::Tuning(_)) = event {
+            return;
+        }
+        self.consumer.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        self.consumer.fill_buffer(buffer);
+    }
+}
+
+impl BufferConsumer for TuningNode {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        Ok(Box::new(Self::new(self.tuning.clone(), consumer)))
+    }
+}
@@ -0,0 +1,75 @@
+use crate::Unison;
+
+pub const MAX_UNISON_VOICES: usize = 8;
+
+// Phases of several copies of an oscillator, detuned and panned evenly across the ranges
+// given by a Unison config. Voice gains are scaled so that the sum stays within range.
+#[derive(Clone, Copy)]
+pub(crate) struct UnisonVoices {
+    unison: Unison,
+    count: usize,
+    ratios: [f32; MAX_UNISON_VOICES],
+    gains: [(f32, f32); MAX_UNISON_VOICES],
+    phases: [f32; MAX_UNISON_VOICES],
+}
+
+impl UnisonVoices {
+    pub(crate) fn new(unison: Unison) -> Self {
+        let count = (unison.voices as usize).clamp(1, MAX_UNISON_VOICES);
+        let mut ratios = [1.0; MAX_UNISON_VOICES];
+        let mut gains = [(1.0, 1.0); MAX_UNISON_VOICES];
+        let scale = 1.0 / count as f32;
+        for voice in 0..count {
+            // Position from -1 to 1 across the voices, or 0 for a single voice
+            let position = match count {
+                1 => 0.0,
+                _ => 2.0 * voice as f32 / (count - 1) as f32 - 1.0,
+            };
+            let cents = position * unison.detune_cents;
+            ratios[voice] = 2.0f32.powf(cents / 1200.0);
+            let pan = position * unison.spread.clamp(0.0, 1.0);
+            gains[voice] = ((1.0 - pan).min(1.0) * scale, (1.0 + pan).min(1.0) * scale);
+        }
+        let mut voices = Self {
+            unison,
+            count,
+            ratios,
+            gains,
+            phases: [0.0; MAX_UNISON_VOICES],
+        };
+        voices.reset_phases();
+        voices
+    }
+
+    // Return every voice to its start phase, such as to restart the waveform. Staggered
+    // start phases avoid the voices all starting in step.
+    pub(crate) fn reset_phases(&mut self) {
+        for voice in 0..self.count {
+            self.phases[voice] = (voice as f32 * 0.618).fract();
+        }
+    }
+
+    pub(crate) fn unison(&self) -> Unison {
+        self.unison
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.count
+    }
+
+    // Move a voice on by one sample, returning its new phase and its phase increment
+    #[inline]
+    pub(crate) fn advance(&mut self, voice: usize, base_increment: f32) -> (f32, f32) {
+        let increment = base_increment * self.ratios[voice];
+        let phase = &mut self.phases[voice];
+        *phase += increment;
+        *phase -= phase.floor();
+        (*phase, increment)
+    }
+
+    // Left and right gains of a voice
+    #[inline]
+    pub(crate) fn gains(&self, voice: usize) -> (f32, f32) {
+        self.gains[voice]
+    }
+}
@@ -16,3 +16,28 @@ pub fn frequency_of(key: u8) -> f32 {
     let relative_pitch = relative_pitch_of(key);
     440.0 * 2.0f32.powf(relative_pitch / 12.0)
 }
+
+// PolyBLEP correction for a unit step at phase zero of an oscillator, where phase is in
+// the range 0 to 1 and phase_increment is the oscillator frequency over the sample rate
+#[inline]
+pub fn poly_blep(phase: f32, phase_increment: f32) -> f32 {
+    if phase < phase_increment {
+        let t = phase / phase_increment;
+        2.0 * t - t * t - 1.0
+    } else if phase > 1.0 - phase_increment {
+        let t = (phase - 1.0) / phase_increment;
+        t * t + 2.0 * t + 1.0
+    } else {
+        0.0
+    }
+}
+
+// Whether a NoteOff with the given voice ID ends the voice started with the held voice ID.
+// A note without a voice ID matches any voice.
+#[inline]
+pub fn is_same_voice(held_voice_id: Option<u64>, voice_id: Option<u64>) -> bool {
+    match (held_voice_id, voice_id) {
+        (Some(held), Some(released)) => held == released,
+        _ => true,
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{consts, BufferConsumer, BufferConsumerNode, EffectMix, Error, Node, NodeEvent};
+use std::f32::consts::TAU;
+
+/// Wobbles the pitch of its source with a sine wave by reading it back through a delay
+/// line whose length swings with the wave, so it works on any source, samples and whole
+/// mixes included. The rate is in Hz, and the depth is how far the pitch swings either
+/// side of the original, in cents. The source is delayed by up to a few milliseconds.
+pub struct VibratoNode {
+    node_id: u64,
+    effect_mix: EffectMix,
+    rate: f32,
+    depth_cents: f32,
+    sweep_frames: f32,
+    phase: f32,
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    intermediate_buffer: Vec<f32>,
+    delay_line: Vec<f32>,
+    write_frame: usize,
+}
+
+impl VibratoNode {
+    pub fn new(
+        node_id: Option<u64>,
+        rate: f32,
+        depth_cents: f32,
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    ) -> Self {
+        let rate = rate.max(0.0);
+        let depth_cents = depth_cents.max(0.0);
+
+        // A delay swinging by A frames at f Hz bends the pitch by up to 2 pi f A / rate
+        let max_ratio = 2.0f32.powf(depth_cents / 1200.0);
+        let sweep_frames = match rate > 0.0 {
+            true => (max_ratio - 1.0) * consts::PLAYBACK_SAMPLE_RATE as f32 / (TAU * rate),
+            false => 0.0,
+        };
+        let delay_frames = (2.0 * sweep_frames).ceil() as usize + 2;
+        Self {
+            node_id: node_id.unwrap_or_else(<Self as Node>::new_node_id),
+            effect_mix: EffectMix::default(),
+            rate,
+            depth_cents,
+            sweep_frames,
+            phase: 0.0,
+            consumer,
+            intermediate_buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+            delay_line: vec![0.0; delay_frames * consts::CHANNEL_COUNT],
+            write_frame: 0,
+        }
+    }
+
+    // Read a channel from the delay line some fractional number of frames behind the
+    // frame last written
+    #[inline]
+    fn read_delayed(&self, delay_frames: f32, channel: usize) -> f32 {
+        let frame_count = self.delay_line.len() / consts::CHANNEL_COUNT;
+        let whole_frames = delay_frames.floor();
+        let fraction = delay_frames - whole_frames;
+        let newer = (self.write_frame + frame_count - whole_frames as usize) % frame_count;
+        let older = (newer + frame_count - 1) % frame_count;
+        let newer_sample = self.delay_line[newer * consts::CHANNEL_COUNT + channel];
+        let older_sample = self.delay_line[older * consts::CHANNEL_COUNT + channel];
+        newer_sample + (older_sample - newer_sample) * fraction
+    }
+}
+
+impl BufferConsumerNode for VibratoNode {}
+
+impl Node for VibratoNode {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        self.consumer.collect_node_ids(ids)
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if self.effect_mix.on_event(self.node_id, event) {
+            return;
+        }
+        self.consumer.on_event(event);
+    }
+
+    fn is_active(&self) -> bool {
+        self.consumer.is_active()
+    }
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        let buffer_size = buffer.len();
+        let intermediate_slice = &mut self.intermediate_buffer[0..buffer_size];
+        intermediate_slice.fill(0.0);
+        self.consumer.fill_buffer(intermediate_slice);
+
+        // Currently only-supported channel configuration
+        #[cfg(debug_assertions)]
+        assert_eq!(consts::CHANNEL_COUNT, 2);
+
+        let phase_increment = self.rate / consts::PLAYBACK_SAMPLE_RATE as f32;
+        let frame_count = self.delay_line.len() / consts::CHANNEL_COUNT;
+        for frame_index in 0..buffer_size / consts::CHANNEL_COUNT {
+            let i = frame_index * consts::CHANNEL_COUNT;
+            self.write_frame = (self.write_frame + 1) % frame_count;
+            let write_index = self.write_frame * consts::CHANNEL_COUNT;
+            self.delay_line[write_index] = self.intermediate_buffer[i];
+            self.delay_line[write_index + 1] = self.intermediate_buffer[i + 1];
+
+            let delay = self.sweep_frames * (1.0 + (self.phase * TAU).sin());
+            self.phase = (self.phase + phase_increment).fract();
+            let wet = self.effect_mix.next_wet();
+            let (left, right) = (self.intermediate_buffer[i], self.intermediate_buffer[i + 1]);
+            buffer[i] += EffectMix::blend(wet, left, self.read_delayed(delay, 0));
+            buffer[i + 1] += EffectMix::blend(wet, right, self.read_delayed(delay, 1));
+        }
+    }
+}
+
+impl BufferConsumer for VibratoNode {
+    fn duplicate(&self) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, Error> {
+        let consumer = self.consumer.duplicate()?;
+        let mut vibrato = Self::new(Some(self.node_id), self.rate, self.depth_cents, consumer);
+        vibrato.effect_mix = self.effect_mix;
+        Ok(Box::new(vibrato))
+    }
+}
@@ -1,9 +1,23 @@
 use crate::{
-    consts, util, BufferConsumer, BufferConsumerNode, Error, LoopRange, Node, NodeControlEvent,
-    NodeEvent, NoteEvent,
+    consts,
+    source::{noise::PlaybackVariation, tuning::NoteTuning},
+    util, BroadcastControl, BufferConsumer, BufferConsumerNode, Error, LoopMode, LoopRange, Node,
+    NodeControlEvent, NodeEvent, NoteEvent, Retrigger, Variation,
 };
 use hound::{SampleFormat, WavSpec};
 use soundfont::raw::{SampleHeader, SampleLink};
+use std::sync::Arc;
+
+const MAX_OVERLAPPING_TAILS: usize = 4;
+
+// A previous playback left to ring out after an overlapping retrigger
+#[derive(Clone, Copy)]
+struct Tail {
+    data_position: usize,
+    note: u8,
+    pitch_variation: f64,
+    gain_variation: f32,
+}
 
 pub struct WavSource {
     node_id: u64,
@@ -14,13 +28,25 @@ pub struct WavSource {
     loop_end_data_position: usize,
     data_position: usize,
     current_note: u8,
+    current_voice_id: Option<u64>,
     volume: f32,
-    source_data: Vec<f32>,
+    source_data: Arc<[f32]>,
     playback_scale: f64,
+    retrigger: Retrigger,
+    loop_mode: LoopMode,
+    variation: PlaybackVariation,
+    pitch_variation: f64,
+    gain_variation: f32,
+    tails: Vec<Tail>,
+    tuning: NoteTuning,
 }
 
 impl WavSource {
-    pub fn new_from_raw_sf2_data(header: &SampleHeader, data: Vec<f32>) -> Result<Self, Error> {
+    pub fn new_from_raw_sf2_data(
+        header: &SampleHeader,
+        data: impl Into<Arc<[f32]>>,
+    ) -> Result<Self, Error> {
+        let data = data.into();
         Self::validate_header(header)?;
         let source_channel_count = match header.sample_type {
             SampleLink::MonoSample => 1,
@@ -47,16 +73,18 @@ impl WavSource {
         ))
     }
 
-    /// Make a new WavSource holding the given sample data.
+    /// Make a new WavSource holding the given sample data, which may be shared with other
+    /// sources and is shared with duplicates.
     /// Data in the spec will be checked for compatibility.
     /// The note is a MIDI key, where A440 is 69.
     pub fn new_from_data(
         spec: WavSpec,
         source_note: u8,
-        data: Vec<f32>,
+        data: impl Into<Arc<[f32]>>,
         loop_range: Option<LoopRange>,
         node_id: Option<u64>,
     ) -> Result<Self, Error> {
+        let data = data.into();
         Self::validate_spec(&spec)?;
         if let Some(range) = &loop_range {
             Self::validate_loop_range(&data, spec.channels as usize, range)?;
@@ -81,7 +109,7 @@ impl WavSource {
         channels: usize,
         source_note: u8,
         loop_range: LoopRange,
-        data: Vec<f32>,
+        data: Arc<[f32]>,
     ) -> Self {
         let playback_scale = consts::PLAYBACK_SAMPLE_RATE as f64 / sample_rate as f64;
         Self {
@@ -93,12 +121,99 @@ impl WavSource {
             loop_end_data_position: loop_range.end_frame * channels,
             data_position: data.len(),
             current_note: 0,
+            current_voice_id: None,
             volume: 1.0,
             source_data: data,
             playback_scale,
+            retrigger: Retrigger::default(),
+            loop_mode: LoopMode::default(),
+            variation: PlaybackVariation::new(Variation::NONE),
+            pitch_variation: 1.0,
+            gain_variation: 1.0,
+            tails: Vec::with_capacity(MAX_OVERLAPPING_TAILS),
+            tuning: NoteTuning::new(),
         }
     }
 
+    pub fn set_retrigger(&mut self, retrigger: Retrigger) {
+        self.retrigger = retrigger;
+    }
+
+    /// Set how the loop is played; samples loop only while their note is held by default.
+    pub fn set_loop_mode(&mut self, loop_mode: LoopMode) {
+        self.loop_mode = loop_mode;
+    }
+
+    /// Vary the pitch and level of each note played at random.
+    pub fn set_variation(&mut self, variation: Variation) {
+        self.variation = PlaybackVariation::new(variation);
+    }
+
+    // Whether playback returns to the loop start on reaching the loop end
+    fn is_looping(&self) -> bool {
+        match self.loop_mode {
+            LoopMode::Off => false,
+            LoopMode::Continuous => self.is_playing(),
+            LoopMode::UntilRelease => self.is_on,
+        }
+    }
+
+    // Playback rate for a note relative to the sample's own, which was recorded at the
+    // equal-tempered pitch of its source note
+    fn pitch_ratio_of(&self, note: u8) -> f64 {
+        (self.tuning.frequency_of(note) / util::frequency_of(self.source_note)) as f64
+    }
+
+    fn is_playing(&self) -> bool {
+        self.data_position < self.source_data.len()
+    }
+
+    fn note_on(&mut self, note: u8) {
+        if self.is_playing() {
+            match self.retrigger {
+                Retrigger::Restart => {}
+                Retrigger::Continue => {
+                    if self.is_on {
+                        return;
+                    }
+                }
+                Retrigger::Overlap => {
+                    if self.tails.len() == MAX_OVERLAPPING_TAILS {
+                        self.tails.remove(0);
+                    }
+                    self.tails.push(Tail {
+                        data_position: self.data_position,
+                        note: self.current_note,
+                        pitch_variation: self.pitch_variation,
+                        gain_variation: self.gain_variation,
+                    });
+                }
+            }
+        }
+        self.is_on = true;
+        self.data_position = 0;
+        self.current_note = note;
+        (self.pitch_variation, self.gain_variation) = self.variation.next();
+    }
+
+    // Play released tails through to the end of the data, without looping
+    fn fill_tails(&mut self, buffer: &mut [f32]) {
+        let mut tails = std::mem::take(&mut self.tails);
+        for tail in tails.iter_mut() {
+            let relative_pitch = self.pitch_ratio_of(tail.note) * tail.pitch_variation;
+            let (src_data_points_advanced, _) = self.stretch_buffer(
+                &self.source_data[tail.data_position..],
+                self.source_channel_count,
+                buffer,
+                relative_pitch * self.playback_scale,
+                tail.gain_variation,
+            );
+            tail.data_position += src_data_points_advanced;
+        }
+        tails.retain(|tail| tail.data_position < self.source_data.len());
+        self.tails = tails;
+    }
+
     fn validate_header(header: &SampleHeader) -> Result<(), Error> {
         match header.sample_type {
             SampleLink::MonoSample => Ok(()),
@@ -154,19 +269,21 @@ impl WavSource {
         src_channels: usize,
         dst: &mut [f32],
         source_frames_per_output_frame: f64,
+        gain: f32,
     ) -> (usize, usize) {
+        let volume = self.volume * gain;
         let mut src_index = 0;
         let mut dst_index = 0;
         while src_index < src.len() && dst_index < dst.len() {
             match src_channels {
                 1 => {
-                    let sample = src[src_index] * self.volume;
+                    let sample = src[src_index] * volume;
                     dst[dst_index] += sample;
                     dst[dst_index + 1] += sample;
                 }
                 2 => {
-                    dst[dst_index] += src[src_index] * self.volume;
-                    dst[dst_index + 1] += src[src_index + 1] * self.volume;
+                    dst[dst_index] += src[src_index] * volume;
+                    dst[dst_index + 1] += src[src_index + 1] * volume;
                 }
                 _ => {}
             }
@@ -187,21 +304,42 @@ impl Node for WavSource {
         self.node_id
     }
 
+    fn collect_node_ids(&self, ids: &mut Vec<u64>) -> bool {
+        ids.push(self.node_id);
+        true
+    }
+
     fn on_event(&mut self, event: &NodeEvent) {
         match event {
-            NodeEvent::Note { note, event } => match event {
+            NodeEvent::Note {
+                note,
+                voice_id,
+                event,
+            } => match event {
                 NoteEvent::NoteOn { vel: _ } => {
-                    self.is_on = true;
-                    self.data_position = 0;
-                    self.current_note = *note;
+                    self.current_voice_id = *voice_id;
+                    self.note_on(*note);
                 }
                 NoteEvent::NoteOff { vel: _ } => {
-                    if self.current_note != *note || !self.is_on {
+                    if self.current_note != *note
+                        || !self.is_on
+                        || !util::is_same_voice(self.current_voice_id, *voice_id)
+                    {
                         return;
                     }
                     self.is_on = false;
                 }
             },
+            NodeEvent::Broadcast(BroadcastControl::Stop) => {
+                self.is_on = false;
+                self.data_position = self.source_data.len();
+                self.tails.clear();
+            }
+            NodeEvent::Broadcast(
+                control @ (BroadcastControl::Tuning(_) | BroadcastControl::MasterTune { .. }),
+            ) => {
+                self.tuning.on_broadcast(control);
+            }
             NodeEvent::NodeControl {
                 node_id,
                 event: NodeControlEvent::Volume(volume),
@@ -215,18 +353,25 @@ impl Node for WavSource {
         }
     }
 
+    fn is_active(&self) -> bool {
+        self.is_playing() || !self.tails.is_empty()
+    }
+
     fn fill_buffer(&mut self, buffer: &mut [f32]) {
         if buffer.is_empty() {
             return;
         }
 
-        if self.is_on && self.data_position >= self.loop_end_data_position {
+        if !self.tails.is_empty() {
+            self.fill_tails(buffer);
+        }
+
+        if self.is_looping() && self.data_position >= self.loop_end_data_position {
             self.data_position -= self.loop_end_data_position - self.loop_start_data_position;
         }
 
         // Scaling
-        let relative_pitch =
-            util::relative_pitch_ratio_of(self.current_note, self.source_note) as f64;
+        let relative_pitch = self.pitch_ratio_of(self.current_note) * self.pitch_variation;
         let source_frames_per_output_frame = relative_pitch * self.playback_scale;
 
         #[cfg(debug_assertions)]
@@ -239,7 +384,7 @@ impl Node for WavSource {
                 return;
             }
 
-            let source_end_point = match self.is_on {
+            let source_end_point = match self.is_looping() {
                 true => self.source_data.len().min(self.loop_end_data_position),
                 false => self.source_data.len(),
             };
@@ -249,6 +394,7 @@ impl Node for WavSource {
                 self.source_channel_count,
                 remaining_buffer,
                 source_frames_per_output_frame,
+                self.gain_variation,
             );
 
             self.data_position += src_data_points_advanced;
@@ -256,7 +402,7 @@ impl Node for WavSource {
             if self.data_position != source_end_point {
                 break;
             }
-            if self.is_on && source_end_point == self.loop_end_data_position {
+            if self.is_looping() && source_end_point == self.loop_end_data_position {
                 self.data_position = self.loop_start_data_position;
                 let remaining_dst_data_points = remaining_buffer.len() - dst_data_points_advanced;
                 let dst_buffer_index = buffer.len() - remaining_dst_data_points;
@@ -276,7 +422,7 @@ impl BufferConsumer for WavSource {
             self.loop_start_data_position / self.source_channel_count,
             self.loop_end_data_position / self.source_channel_count,
         );
-        let source = Self::new(
+        let mut source = Self::new(
             Some(self.node_id),
             sample_rate,
             self.source_channel_count,
@@ -284,6 +430,10 @@ impl BufferConsumer for WavSource {
             loop_range,
             self.source_data.clone(),
         );
+        source.retrigger = self.retrigger;
+        source.loop_mode = self.loop_mode;
+        source.set_variation(self.variation.variation());
+        source.tuning = self.tuning.clone();
         Ok(Box::new(source))
     }
 }
@@ -1,11 +1,54 @@
+use crate::graph::{combiner, font, one_shot, param, sample, square, Graph};
+#[cfg(feature = "driver-cpal")]
+use crate::mix::clock::StreamClock;
+#[cfg(feature = "driver-cpal")]
+use crate::mix::conditioning::{set_flush_to_zero, DcBlocker};
+#[cfg(feature = "driver-cpal")]
+use crate::mix::root_swap::{RootFader, RootReplacement};
+#[cfg(feature = "driver-cpal")]
+use crate::mix::stats::RenderStatsRecorder;
+#[cfg(feature = "driver-cpal")]
+use crate::mix::{
+    garbage::{Garbage, GarbageSender},
+    layers::{LayerCommand, LayerSet},
+};
+use crate::quick::midi_with_sf2_config;
 use crate::{
-    util::{midi_builder_from_file, wav_from_file},
-    BaseMixer, NoteRange, SoundFontBuilder, SquareWaveSource,
+    asset_paths, consts, load_source_lenient, prewarm_sample_cache, register_node_type,
+    util::{
+        self, add_interleaved, add_scaled_buffer, get_sequence_count, get_timed_cues,
+        midi_builder_from_bytes, midi_builder_from_file, midi_sequence_builder_from_bytes, name_id,
+        one_shot_from_bytes, one_shot_from_bytes_with_policy, param_id, peak_of, snapshot_id,
+        tag_id, tuning_from_scala_bytes, wav_data_from_bytes, wav_data_from_bytes_with_policy,
+        wav_from_file, BadSamplePolicy,
+    },
+    AbCompareSource, AdditiveSource, AssetLoader, Breakpoint, BroadcastControl, BufferConsumer,
+    BufferConsumerNode, ChannelLayout, ChokeGroup, ClockOffset, ColoredNoiseSource, CombinerSource,
+    Config, ConfigFormat, ConvolutionNode, CrossfadeSource, Cue, DuckSource, Envelope,
+    EnvelopeCurve, EnvelopeRetrigger, Error, EventRoutes, ExternalClock, FadeChain, FadeStep,
+    Fader, FaderHandle, FileGraphLoader, FmAlgorithm, FmOperator, FmSynthSource, FontSource,
+    GateExpanderNode, GateNode, GraphExporter, GraphLoader, HostEvent, InlineData, LoopMode,
+    LoopRange, MemoryAssetLoader, MixerHandle, ModMatrix, ModRoute, ModSource, ModulationTarget,
+    MultiStageEnvelope, Node, NodeConfig, NodeControlEvent, NodeEvent, NoiseColor, NoteEvent,
+    NoteMap, NoteMapping, NoteRange, NullSource, OneShotSource, OscillatorMode,
+    ParallelCombinerSource, ParamTarget, PitchMotion, PitchShiftNode, PlaylistNode,
+    PluckedStringSource, QuantizeDirection, Retrigger, RingModMode, RingModNode, SampleHoldSource,
+    SawtoothWaveSource, Scale, ScaleQuantizer, ScopeNode, SequencerSource, SequencerStep,
+    SoundEffectPoolBuilder, SoundFont, SoundFontBuilder, SoundSource, SoundingNote,
+    SquareWaveSource, SyncFollower, SyncLeader, SyncTransport, TestSignal, TestSignalSource,
+    TimeSignature, TimedCue, TremoloNode, TriangleWaveSource, TuningTable, Unison, Variation,
+    VibratoNode, VoiceStealing, WavSource, CURRENT_CONFIG_VERSION,
 };
-use std::time::Duration;
+#[cfg(feature = "driver-cpal")]
+use crate::{BaseMixer, RenderStats};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use hound::{SampleFormat, WavSpec};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
-const MIDI_FILE: &'static str = "resources/sample-in-c.mid";
-const WAV_FILE: &'static str = "resources/guitar-a2-48k-stereo.wav";
+const MIDI_FILE: &str = "resources/sample-in-c.mid";
+const WAV_FILE: &str = "resources/guitar-a2-48k-stereo.wav";
 
 #[test]
 fn can_decode_midi_file() {
@@ -20,6 +63,42 @@ fn can_decode_wav_file() {
 }
 
 #[test]
+fn gain_reduction_meter_reports_in_decibels() {
+    let reader = crate::GainReductionMeter::new();
+    let meter = reader.for_new_voice();
+    assert_eq!(reader.reduction_db(), 0.0);
+
+    // A tenth of the gain is 20dB down, and the peak holds until it is read
+    meter.record(0.1, 0.01);
+    meter.record(1.0, 0.5);
+    assert!(reader.reduction_db().abs() < 0.001);
+    assert!((reader.take_peak_reduction_db() - 40.0).abs() < 0.001);
+    assert_eq!(reader.take_peak_reduction_db(), 0.0);
+}
+
+#[test]
+fn gain_reduction_meter_keeps_each_voice_separate() {
+    let reader = crate::GainReductionMeter::new();
+    let voices = [reader.for_new_voice(), reader.for_new_voice()];
+    assert_eq!(reader.voice_count(), 2);
+
+    // Each voice's reading is its own, and the meter reports the greatest
+    crate::take_gain_reduction();
+    for (voice, gain) in voices.iter().zip([0.5, 0.1]) {
+        crate::report_gain_reduction(voice.record(gain, gain));
+    }
+    assert!((reader.voice_reduction_db(0).unwrap() - 6.02).abs() < 0.01);
+    assert!((reader.voice_reduction_db(1).unwrap() - 20.0).abs() < 0.01);
+    assert!(reader.voice_reduction_db(2).is_none());
+    assert_eq!(reader.reduction_db(), reader.voice_reduction_db(1).unwrap());
+
+    // The greatest reduction reported on this thread is taken once
+    assert!((crate::take_gain_reduction() - 20.0).abs() < 0.01);
+    assert_eq!(crate::take_gain_reduction(), 0.0);
+}
+
+#[test]
+#[cfg(feature = "driver-cpal")]
 fn can_play_square_stream() {
     let midi = midi_builder_from_file(None, MIDI_FILE)
         .unwrap()
@@ -44,6 +123,7 @@ fn can_play_square_stream() {
 }
 
 #[test]
+#[cfg(feature = "driver-cpal")]
 fn can_play_wav_stream() {
     let midi = midi_builder_from_file(None, MIDI_FILE)
         .unwrap()
@@ -66,3 +146,3717 @@ fn can_play_wav_stream() {
 
     std::thread::sleep(Duration::from_secs(3));
 }
+
+#[test]
+fn ab_compare_outputs_only_selected_source() {
+    let mut source = AbCompareSource::new(
+        Some(1),
+        false,
+        Box::new(SquareWaveSource::new(None, 0.25, 0.5)),
+        Box::new(NullSource::new(None)),
+    );
+    source.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 256];
+    source.fill_buffer(&mut buffer);
+    assert!(buffer.iter().any(|sample| *sample != 0.0));
+
+    source.on_event(&NodeEvent::NodeControl {
+        node_id: 1,
+        event: NodeControlEvent::AbToggle,
+    });
+
+    // Switching cross-fades over 10ms, then the other source is heard alone
+    let mut buffer = vec![0.0; 2048];
+    source.fill_buffer(&mut buffer);
+    assert!(peak_of(&buffer[0..64]) > 0.2);
+    assert!(peak_of(&buffer[800..960]) < peak_of(&buffer[0..160]));
+    assert!(buffer[960..].iter().all(|sample| *sample == 0.0));
+    buffer.fill(0.0);
+    source.fill_buffer(&mut buffer);
+    assert!(buffer.iter().all(|sample| *sample == 0.0));
+}
+
+#[test]
+fn blind_ab_compare_hides_which_source_is_which() {
+    let (mut source, mapping) = AbCompareSource::new_blind(
+        Some(1),
+        Box::new(SquareWaveSource::new(None, 0.25, 0.5)),
+        Box::new(NullSource::new(None)),
+    );
+    assert_eq!(format!("{mapping:?}"), "AbBlindMapping { .. }");
+    source.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 256];
+    source.fill_buffer(&mut buffer);
+    let a_is_heard = peak_of(&buffer) > 0.0;
+
+    // Selecting A played the square wave unless the pairing was swapped
+    assert_eq!(mapping.reveal(), !a_is_heard);
+}
+
+#[test]
+#[cfg(feature = "driver-cpal")]
+fn render_stats_track_load_and_underruns() {
+    let recorder = RenderStatsRecorder::default();
+    assert!(!recorder.record(0.005, 0.01));
+    assert!(recorder.record(0.02, 0.01));
+    let stats = recorder.snapshot();
+    assert_eq!(stats.callbacks, 2);
+    assert_eq!(stats.underruns, 1);
+    assert_eq!(stats.worst_render_seconds, 0.02);
+    assert!(stats.load_percent > 50.0 && stats.load_percent < 200.0);
+    assert_eq!(stats.gain_reduction_db, 0.0);
+    recorder.record_gain_reduction(6.0);
+    assert_eq!(recorder.snapshot().gain_reduction_db, 6.0);
+
+    recorder.reset();
+    assert_eq!(recorder.snapshot(), RenderStats::default());
+}
+
+#[test]
+#[cfg(feature = "driver-cpal")]
+fn layers_mix_independently_and_retire_off_thread() {
+    let (commands, command_receiver) = crossbeam_channel::bounded(4);
+    let (retired_sender, retired) = crossbeam_channel::bounded(4);
+    let mut layers = LayerSet::new(command_receiver, GarbageSender::with_sender(retired_sender));
+    let note_on = NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    };
+    for layer_id in 0..2 {
+        let mut layer = SquareWaveSource::new(None, 0.25, 0.5);
+        layer.on_event(&note_on);
+        commands
+            .send(LayerCommand::Add(layer_id, Box::new(layer)))
+            .unwrap();
+    }
+    layers.apply_commands();
+    let mut buffer = vec![0.0; 64];
+    layers.fill_buffer(&mut buffer);
+    assert_eq!(buffer[0].abs(), 0.5);
+
+    commands.send(LayerCommand::Remove(0)).unwrap();
+    commands.send(LayerCommand::Remove(7)).unwrap();
+    layers.apply_commands();
+    let mut buffer = vec![0.0; 64];
+    layers.fill_buffer(&mut buffer);
+    assert_eq!(buffer[0].abs(), 0.25);
+    assert!(matches!(retired.try_recv(), Ok(Garbage::Node(_))));
+    assert!(retired.is_empty());
+}
+
+#[test]
+#[cfg(feature = "driver-cpal")]
+fn mixer_builder_checks_its_graph_and_options_before_starting() {
+    let missing = BaseMixer::builder()
+        .config_path("resources/missing.ron")
+        .start();
+    assert!(matches!(missing, Err(Error::Io(_))));
+    let no_buffer = BaseMixer::builder()
+        .root(Box::new(NullSource::new(None)))
+        .buffer_frames(0)
+        .start();
+    assert!(matches!(no_buffer, Err(Error::User(_))));
+}
+
+#[test]
+#[cfg(feature = "driver-cpal")]
+fn root_replacement_crossfades_and_retires_old_root() {
+    let (replacements, replacement_receiver) = crossbeam_channel::bounded(4);
+    let (retired_sender, retired) = crossbeam_channel::bounded(4);
+    let mut fader = RootFader::new(
+        replacement_receiver,
+        GarbageSender::with_sender(retired_sender),
+    );
+    let mut old_root: Box<dyn BufferConsumerNode + Send + 'static> =
+        Box::new(SquareWaveSource::new(None, 0.25, 0.5));
+    old_root.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let consumer = std::sync::atomic::AtomicPtr::new(Box::into_raw(Box::new(old_root)));
+    replacements
+        .send(RootReplacement {
+            root: Box::new(Box::new(NullSource::new(None))),
+            fade_frames: 64,
+        })
+        .unwrap();
+    fader.apply_replacements(&consumer);
+    let root = unsafe { &mut **consumer.load(std::sync::atomic::Ordering::SeqCst) };
+
+    let mut buffer = vec![0.0; 64];
+    fader.fill_buffer(root, &mut buffer);
+    assert_eq!(buffer[0].abs(), 0.25);
+    assert!(buffer[62].abs() > 0.125 && buffer[62].abs() < 0.25);
+    assert!(retired.is_empty());
+
+    let mut buffer = vec![0.0; 64];
+    fader.fill_buffer(root, &mut buffer);
+    assert!(buffer[62].abs() > 0.0 && buffer[62].abs() < 0.125);
+    assert!(matches!(retired.try_recv(), Ok(Garbage::Root(_))));
+
+    let mut buffer = vec![0.0; 64];
+    fader.fill_buffer(root, &mut buffer);
+    assert!(buffer.iter().all(|sample| *sample == 0.0));
+    unsafe {
+        drop(Box::from_raw(
+            consumer.load(std::sync::atomic::Ordering::SeqCst),
+        ));
+    }
+}
+
+#[test]
+fn scope_node_keeps_latest_output() {
+    let (reader, mut scope) =
+        ScopeNode::new(None, 64, Box::new(SquareWaveSource::new(None, 0.25, 0.5))).unwrap();
+    let mut latest = vec![1.0; 256];
+    assert_eq!(reader.read_latest(&mut latest), 64);
+    assert!(latest[0..128].iter().all(|sample| *sample == 0.0));
+
+    scope.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 512];
+    scope.fill_buffer(&mut buffer);
+    assert_eq!(reader.frames_written(), 256);
+    let mut latest = vec![0.0; 32];
+    assert_eq!(reader.read_latest(&mut latest), 16);
+    assert_eq!(latest, buffer[480..]);
+}
+
+#[test]
+fn scope_node_watches_every_voice_of_a_font() {
+    let voice = || {
+        Box::new(SquareWaveSource::new(None, 0.25, 0.5))
+            as Box<dyn BufferConsumerNode + Send + 'static>
+    };
+    let (_, scope) = ScopeNode::new(None, 64, voice()).unwrap();
+    assert!(SoundFontBuilder::new(None)
+        .add_range(NoteRange::new_full_range(), Box::new(scope))
+        .is_err());
+
+    let font = SoundFontBuilder::new(None)
+        .add_range(NoteRange::new_full_range(), voice())
+        .unwrap()
+        .build();
+    let (reader, mut scope) = ScopeNode::new(None, 64, Box::new(font)).unwrap();
+    for note in [57, 69, 76] {
+        scope.on_event(&NodeEvent::Note {
+            note,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        });
+    }
+    let mut buffer = vec![0.0; 512];
+    scope.fill_buffer(&mut buffer);
+    assert!(peak_of(&buffer) > 0.5);
+    let mut latest = vec![0.0; 128];
+    assert_eq!(reader.read_latest(&mut latest), 64);
+    assert_eq!(latest, buffer[384..]);
+}
+
+#[test]
+fn duplicated_wav_sources_share_sample_data() {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 48000,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let data: Arc<[f32]> = vec![0.5; 4800].into();
+    let source = WavSource::new_from_data(spec, 69, data.clone(), None, None).unwrap();
+    let duplicate = source.duplicate().unwrap();
+    assert_eq!(Arc::strong_count(&data), 3);
+    drop(duplicate);
+    assert_eq!(Arc::strong_count(&data), 2);
+}
+
+#[test]
+fn sound_effect_pool_recycles_finished_voices() {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 48000,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let (pool_handle, mut pool) = SoundEffectPoolBuilder::new(None, 1)
+        .add_effect("blip", spec, vec![1.0; 64])
+        .unwrap()
+        .build()
+        .unwrap();
+    assert!(pool_handle.play("missing", 1.0, 0.0).is_err());
+
+    pool_handle.play("blip", 0.5, -1.0).unwrap();
+    let mut buffer = vec![0.0; 256];
+    pool.fill_buffer(&mut buffer);
+    assert_eq!(buffer[0], 0.5);
+    assert_eq!(buffer[1], 0.0);
+    assert_eq!(buffer[128], 0.0);
+
+    pool_handle.play("blip", 1.0, 0.0).unwrap();
+    buffer.fill(0.0);
+    pool.fill_buffer(&mut buffer);
+    assert_eq!(buffer[0], 1.0);
+    assert_eq!(buffer[1], 1.0);
+}
+
+#[test]
+fn envelope_release_tail_sounds_until_finished() {
+    let mut envelope = Envelope::from_adsr(
+        None,
+        0.01,
+        0.01,
+        0.5,
+        0.05,
+        Box::new(SquareWaveSource::new(None, 0.25, 0.5)),
+    );
+    assert!(!envelope.is_active());
+    envelope.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 2048];
+    for _ in 0..4 {
+        envelope.fill_buffer(&mut buffer);
+    }
+    envelope.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOff { vel: 0.0 },
+    });
+    buffer.fill(0.0);
+    envelope.fill_buffer(&mut buffer);
+    assert!(buffer.iter().any(|sample| *sample != 0.0));
+    assert!(envelope.is_active());
+
+    for _ in 0..4 {
+        envelope.fill_buffer(&mut buffer);
+    }
+    assert!(!envelope.is_active());
+}
+
+#[test]
+fn envelope_times_track_key_and_velocity() {
+    // Frames taken to reach full level, with a 0.1 second attack unscaled at middle C
+    let attack_frames = |note: u8, vel: f32| {
+        let mut envelope = Envelope::from_adsr(
+            None,
+            0.1,
+            0.001,
+            1.0,
+            0.1,
+            Box::new(SquareWaveSource::new(None, 1.0, 0.0)),
+        );
+        envelope.set_tracking(1.0, 0.5);
+        envelope.on_event(&NodeEvent::Note {
+            note,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel },
+        });
+        let mut buffer = vec![0.0; 12288];
+        for chunk in buffer.chunks_mut(4096) {
+            envelope.fill_buffer(chunk);
+        }
+        buffer
+            .iter()
+            .step_by(2)
+            .position(|sample| sample.abs() > 0.99 * vel)
+            .unwrap()
+    };
+    assert!((4750..=4850).contains(&attack_frames(60, 0.5)));
+    assert!((2350..=2450).contains(&attack_frames(72, 0.5)));
+    assert!((3350..=3450).contains(&attack_frames(60, 1.0)));
+}
+
+#[test]
+fn envelope_curves_and_retrigger_modes_shape_levels() {
+    let note_on = NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    };
+    let make_envelope = |curve, retrigger| {
+        let mut envelope = Envelope::from_adsr(
+            None,
+            0.01,
+            0.01,
+            1.0,
+            0.01,
+            Box::new(SquareWaveSource::new(None, 1.0, 0.0)),
+        );
+        envelope.set_curves(curve, curve, curve);
+        envelope.set_retrigger(retrigger);
+        envelope.on_event(&note_on);
+        envelope
+    };
+
+    // Halfway through the attack, an exponential rise is well below a linear one
+    let mut buffer = vec![0.0; 480];
+    let mut linear = make_envelope(EnvelopeCurve::Linear, EnvelopeRetrigger::Reset);
+    linear.fill_buffer(&mut buffer);
+    assert!((buffer[478] - 0.5).abs() < 0.01);
+    buffer.fill(0.0);
+    let mut exponential = make_envelope(EnvelopeCurve::Exponential, EnvelopeRetrigger::Reset);
+    exponential.fill_buffer(&mut buffer);
+    assert!(buffer[478] < 0.2);
+
+    // Once sustaining, a retrigger drops to silence only when resetting
+    for (retrigger, restarts_from_silence) in [
+        (EnvelopeRetrigger::Reset, true),
+        (EnvelopeRetrigger::FromCurrentLevel, false),
+        (EnvelopeRetrigger::Legato, false),
+    ] {
+        let mut envelope = make_envelope(EnvelopeCurve::Linear, retrigger);
+        let mut buffer = vec![0.0; 4096];
+        envelope.fill_buffer(&mut buffer);
+        envelope.on_event(&note_on);
+        buffer.fill(0.0);
+        envelope.fill_buffer(&mut buffer);
+        assert_eq!(buffer[2] < 0.1, restarts_from_silence);
+    }
+}
+
+#[test]
+fn envelope_delay_and_hold_stages_surround_attack() {
+    let mut envelope = Envelope::from_adsr(
+        None,
+        0.01,
+        0.01,
+        0.5,
+        0.01,
+        Box::new(SquareWaveSource::new(None, 1.0, 0.0)),
+    );
+    envelope.set_delay_and_hold(0.01, 0.01);
+    envelope.on_event(&NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 4096];
+    envelope.fill_buffer(&mut buffer);
+    let level_at = |frame: usize| buffer[frame * 2];
+    assert_eq!(level_at(200), 0.0);
+    assert!((level_at(720) - 0.5).abs() < 0.01);
+    assert_eq!(level_at(1200), 1.0);
+    assert!((level_at(1680) - 0.75).abs() < 0.01);
+}
+
+#[test]
+fn multi_stage_envelope_sustains_and_modulates() {
+    let points = vec![
+        Breakpoint {
+            time: 0.01,
+            level: 1.0,
+            curve: EnvelopeCurve::Linear,
+        },
+        Breakpoint {
+            time: 0.01,
+            level: 0.5,
+            curve: EnvelopeCurve::Linear,
+        },
+        Breakpoint {
+            time: 0.01,
+            level: 0.0,
+            curve: EnvelopeCurve::Linear,
+        },
+    ];
+    let square = || Box::new(SquareWaveSource::new(None, 1.0, 0.0));
+    let note_on = NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    };
+    let note_off = NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOff { vel: 0.0 },
+    };
+
+    // Shaping amplitude, the level holds at the sustain point until the note off
+    let mut envelope =
+        MultiStageEnvelope::new(None, points.clone(), Some(1), None, square()).unwrap();
+    envelope.on_event(&note_on);
+    let mut buffer = vec![0.0; 4096];
+    envelope.fill_buffer(&mut buffer);
+    assert_eq!(buffer[4094], 0.5);
+    envelope.on_event(&note_off);
+    assert!(envelope.is_active());
+    buffer.fill(0.0);
+    envelope.fill_buffer(&mut buffer);
+    assert!(!envelope.is_active());
+    assert_eq!(buffer[4094], 0.0);
+
+    // Modulating, the level drives the volume of a fader while the audio passes through
+    let target = ModulationTarget {
+        node_id: 5,
+        control: ParamTarget::FaderVolume,
+    };
+    let fader = Box::new(Fader::new(Some(5), 1.0, square()));
+    let mut envelope = MultiStageEnvelope::new(None, points, Some(1), Some(target), fader).unwrap();
+    envelope.on_event(&note_on);
+    buffer.fill(0.0);
+    envelope.fill_buffer(&mut buffer);
+    assert_eq!(buffer[4094], 0.5);
+    assert!(envelope.is_active());
+}
+
+#[test]
+fn fader_runs_chained_steps_and_reports_completion() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let mut fader = Fader::new(
+        Some(7),
+        1.0,
+        Box::new(SquareWaveSource::new(None, 1.0, 0.0)),
+    );
+    fader.on_event(&NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    fader.on_event(&NodeEvent::NodeControl {
+        node_id: 7,
+        event: NodeControlEvent::NotifyFadeComplete(sender),
+    });
+    fader.on_event(&NodeEvent::NodeControl {
+        node_id: 7,
+        event: NodeControlEvent::FadeChain(
+            FadeChain::new([
+                FadeStep::Fade {
+                    from: 1.0,
+                    to: 0.0,
+                    seconds: 0.01,
+                },
+                FadeStep::Broadcast(BroadcastControl::NotesOff),
+                FadeStep::Fade {
+                    from: 0.0,
+                    to: 1.0,
+                    seconds: 0.01,
+                },
+            ])
+            .unwrap(),
+        ),
+    });
+    let mut buffer = vec![0.0; 4096];
+    fader.fill_buffer(&mut buffer);
+    assert!((buffer[2 * 240] - 0.5).abs() < 0.01);
+    assert_eq!(buffer[2 * 700], 0.0);
+    assert!(!fader.is_active());
+    assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![7, 7]);
+}
+
+#[test]
+fn fader_completes_fades_under_a_silent_source() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let mut fader = Fader::new(
+        Some(7),
+        1.0,
+        Box::new(SquareWaveSource::new(None, 1.0, 0.0)),
+    );
+    fader.set_completion_sender(sender);
+    fader.on_event(&NodeEvent::NodeControl {
+        node_id: 7,
+        event: NodeControlEvent::Fade {
+            from: 1.0,
+            to: 0.0,
+            seconds: 0.01,
+        },
+    });
+    assert!(fader.is_active());
+
+    // The combiner skips inactive children, so the fader must stay active to finish
+    let mut combiner = CombinerSource::new(None, vec![Box::new(fader)]);
+    let mut buffer = vec![0.0; 4096];
+    combiner.fill_buffer(&mut buffer);
+    assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![7]);
+    assert!(!combiner.is_active());
+}
+
+#[test]
+fn fader_reports_completion_of_instant_fades() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let mut fader = Fader::new(
+        Some(7),
+        1.0,
+        Box::new(SquareWaveSource::new(None, 1.0, 0.0)),
+    );
+    fader.set_completion_sender(sender);
+    fader.on_event(&NodeEvent::NodeControl {
+        node_id: 7,
+        event: NodeControlEvent::Fade {
+            from: 1.0,
+            to: 0.5,
+            seconds: 0.0,
+        },
+    });
+    assert!(fader.is_active());
+    let mut buffer = vec![0.0; 4096];
+    fader.fill_buffer(&mut buffer);
+    assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![7]);
+
+    // Each step of a chain is reported, however short
+    fader.on_event(&NodeEvent::NodeControl {
+        node_id: 7,
+        event: NodeControlEvent::FadeChain(
+            FadeChain::new([
+                FadeStep::Fade {
+                    from: 0.5,
+                    to: 0.0,
+                    seconds: 0.0,
+                },
+                FadeStep::Fade {
+                    from: 0.0,
+                    to: 1.0,
+                    seconds: 0.0,
+                },
+            ])
+            .unwrap(),
+        ),
+    });
+    fader.fill_buffer(&mut buffer);
+    assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![7, 7]);
+    assert!(!fader.is_active());
+}
+
+#[test]
+fn crossfade_follows_equal_power_law() {
+    let mut crossfade = CrossfadeSource::new(
+        Some(3),
+        0.0,
+        Box::new(SquareWaveSource::new(None, 1.0, 0.0)),
+        Box::new(SquareWaveSource::new(None, 0.5, 0.0)),
+    );
+    crossfade.on_event(&NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    crossfade.on_event(&NodeEvent::NodeControl {
+        node_id: 3,
+        event: NodeControlEvent::Crossfade {
+            to: 1.0,
+            seconds: 0.01,
+        },
+    });
+    let mut buffer = vec![0.0; 4096];
+    crossfade.fill_buffer(&mut buffer);
+    assert_eq!(buffer[0], 1.0);
+    let halfway = std::f32::consts::FRAC_1_SQRT_2 * 1.5;
+    assert!((buffer[2 * 240] - halfway).abs() < 0.01);
+    assert!((buffer[4094] - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn duck_lowers_music_while_priority_is_heard_or_held() {
+    let note_on = NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    };
+    let music = || Box::new(SquareWaveSource::new(None, 1.0, 0.0));
+
+    // Held by a control event, then released once the hold is over
+    let mut duck = DuckSource::new(
+        Some(4),
+        0.5,
+        0.01,
+        0.01,
+        0.01,
+        music(),
+        Box::new(NullSource::new(None)),
+    );
+    duck.on_event(&note_on);
+    duck.on_event(&NodeEvent::NodeControl {
+        node_id: 4,
+        event: NodeControlEvent::Duck { seconds: 0.02 },
+    });
+    let mut buffer = vec![0.0; 4096];
+    duck.fill_buffer(&mut buffer);
+    assert!((buffer[2 * 240] - 0.75).abs() < 0.01);
+    assert!((buffer[2 * 700] - 0.5).abs() < 1e-6);
+    assert!((buffer[2 * 2000] - 1.0).abs() < 1e-6);
+
+    // Triggered by the priority source being heard
+    let priority = Box::new(SquareWaveSource::new(None, 0.25, 0.0));
+    let mut duck = DuckSource::new(None, 0.5, 0.01, 0.01, 0.01, music(), priority);
+    duck.on_event(&note_on);
+    buffer.fill(0.0);
+    duck.fill_buffer(&mut buffer);
+    assert!((buffer[4094] - 0.75).abs() < 1e-6);
+}
+
+#[test]
+fn wav_source_overlapping_retrigger_plays_both_notes() {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 48000,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut source = WavSource::new_from_data(spec, 69, vec![0.5; 60], None, None).unwrap();
+    source.set_retrigger(Retrigger::Overlap);
+    let note_on = NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    };
+    source.on_event(&note_on);
+    let mut buffer = vec![0.0; 80];
+    source.fill_buffer(&mut buffer);
+    assert!(buffer.iter().all(|sample| *sample == 0.5));
+
+    source.on_event(&note_on);
+    buffer.fill(0.0);
+    source.fill_buffer(&mut buffer);
+    assert!(buffer[0..40].iter().all(|sample| *sample == 1.0));
+    assert!(buffer[40..].iter().all(|sample| *sample == 0.5));
+
+    source.set_retrigger(Retrigger::Continue);
+    source.on_event(&note_on);
+    for _ in 0..2 {
+        source.fill_buffer(&mut buffer);
+    }
+    assert!(!source.is_active());
+}
+
+#[test]
+fn test_signal_levels_are_calibrated() {
+    let mut source = TestSignalSource::new(Some(7));
+    assert!(!source.is_active());
+    let mut buffer = vec![0.0; 4800];
+    source.on_event(&NodeEvent::NodeControl {
+        node_id: 7,
+        event: NodeControlEvent::TestSignal(TestSignal::Tone {
+            frequency: 1000.0,
+            level_db: -6.0,
+            channel: Some(1),
+        }),
+    });
+    source.fill_buffer(&mut buffer);
+    let left_peak = buffer.iter().step_by(2).fold(0.0f32, |a, b| a.max(b.abs()));
+    assert_eq!(left_peak, 0.0);
+    assert!((peak_of(&buffer) - 0.501).abs() < 0.01);
+
+    source.on_event(&NodeEvent::NodeControl {
+        node_id: 7,
+        event: NodeControlEvent::TestSignal(TestSignal::PinkNoise {
+            level_db: 0.0,
+            channel: None,
+        }),
+    });
+    let mut buffer = vec![0.0; 96000];
+    source.fill_buffer(&mut buffer);
+    let rms =
+        (buffer.iter().map(|sample| sample * sample).sum::<f32>() / buffer.len() as f32).sqrt();
+    assert!((rms - 1.0).abs() < 0.1, "{}", rms);
+}
+
+#[test]
+fn channel_layouts_map_stereo_output() {
+    let stereo = [1.0, 0.5, 0.2, 0.0];
+    let mut mono = [0.0; 2];
+    ChannelLayout::Mono.write_from_stereo(&stereo, &mut mono, &[1.0]);
+    assert_eq!(mono, [0.75, 0.1]);
+
+    let layout = ChannelLayout::from_channel_count(6).unwrap();
+    let mut surround = [0.0; 12];
+    layout.write_from_stereo(&stereo, &mut surround, &[1.0, 1.0, 1.0, 1.0, 0.5, 0.5]);
+    assert_eq!(&surround[0..6], &[1.0, 0.5, 0.75, 0.0, 0.5, 0.25]);
+
+    let mut device_channels = [0.0; 12];
+    device_channels[4] = 1.0;
+    device_channels[9] = -1.0;
+    layout.add_device_channels(
+        &device_channels,
+        &mut surround,
+        &[1.0, 1.0, 1.0, 0.5, 0.5, 0.5],
+    );
+    assert_eq!(&surround[0..6], &[1.0, 0.5, 0.75, 0.0, 1.0, 0.25]);
+    assert_eq!(surround[9], -0.5);
+}
+
+#[cfg(feature = "driver-cpal")]
+#[test]
+fn test_signal_identifies_every_device_channel() {
+    let layout = ChannelLayout::Surround51;
+    let channel_count = layout.channel_count();
+    let mut source = TestSignalSource::new(Some(7));
+    source.on_event(&NodeEvent::NodeControl {
+        node_id: 7,
+        event: NodeControlEvent::TestSignal(TestSignal::ChannelId),
+    });
+
+    // Render a full cycle of beeps, lending device channels as the mixer's callback does,
+    // and count the beeps heard in each channel
+    let mut stereo = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+    let mut device_channels = vec![0.0; consts::BUFFER_SIZE * channel_count];
+    let mut beeps = vec![0; channel_count];
+    let mut was_beeping = vec![false; channel_count];
+    for _ in 0..(12 * consts::PLAYBACK_SAMPLE_RATE / consts::BUFFER_SIZE) {
+        device_channels.fill(0.0);
+        crate::mix::layout::lend_device_channels(&mut device_channels, channel_count);
+        source.fill_buffer(&mut stereo);
+        crate::mix::layout::return_device_channels(&mut device_channels);
+        for frame in device_channels.chunks_exact(channel_count) {
+            for channel in 0..channel_count {
+                let is_beeping = frame[channel] != 0.0;
+                if is_beeping && !was_beeping[channel] {
+                    beeps[channel] += 1;
+                }
+                was_beeping[channel] = is_beeping;
+            }
+        }
+    }
+    assert_eq!(peak_of(&stereo), 0.0);
+    assert_eq!(beeps, vec![1, 2, 3, 4, 5, 6]);
+}
+
+#[test]
+fn config_reads_preferred_output_device() {
+    let config =
+        Config::from_bytes(b"(root: TestSignal(), output_device: Some(\"USB Audio\"))").unwrap();
+    assert_eq!(config.output_device.as_deref(), Some("USB Audio"));
+    let config = Config::from_bytes(b"(root: TestSignal())").unwrap();
+    assert!(config.output_device.is_none());
+}
+
+#[test]
+fn quick_midi_with_sf2_config_plays_every_channel() {
+    let config = midi_with_sf2_config(MIDI_FILE, "resources/demo-font.sf2", 0).unwrap();
+    assert_eq!(
+        asset_paths(&config.root),
+        vec![MIDI_FILE.to_owned(), "resources/demo-font.sf2".to_owned()]
+    );
+    assert!(config.validate(Some(&FileGraphLoader)).is_ok());
+    let SoundSource::Midi { channels, .. } = &config.root else {
+        panic!("Expected a MIDI root");
+    };
+    assert_eq!(channels.len(), 16);
+    let (_, mut graph) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+    let mut buffer = vec![0.0; 4096];
+    let mut peak: f32 = 0.0;
+    for _ in 0..48 {
+        buffer.fill(0.0);
+        graph.fill_buffer(&mut buffer);
+        peak = peak.max(peak_of(&buffer));
+    }
+    assert!(peak > 0.0);
+}
+
+#[test]
+fn asset_paths_are_collected_from_nested_sources() {
+    let config = FileGraphLoader
+        .config_from_file("resources/example.ron")
+        .unwrap();
+    let paths = asset_paths(&config.root);
+    assert!(paths.contains(&"resources/sample-in-c.mid".to_owned()));
+    let mut deduplicated = paths.clone();
+    deduplicated.dedup();
+    assert_eq!(paths, deduplicated);
+}
+
+#[test]
+fn memory_asset_loader_builds_graph_from_embedded_assets() {
+    const MIDI_BYTES: &[u8] = include_bytes!("../resources/sample-in-c.mid");
+    let loader = MemoryAssetLoader::new().with_static("song.mid", MIDI_BYTES);
+    let config = Config::from_bytes(
+        b"(root: Midi(source: FilePath(\"song.mid\"), channels: { 0: SquareWave() }))",
+    )
+    .unwrap();
+    assert!(loader.load_source_recursive(&config.root).is_ok());
+    let missing = SoundSource::OneShotFilePath {
+        node_id: None,
+        path: "missing.wav".to_owned(),
+        retrigger: Retrigger::Restart,
+        choke_group: None,
+        variation: Variation::NONE,
+        bad_samples: BadSamplePolicy::Fail,
+        gain: 0.0,
+    };
+    assert!(loader.load_source_recursive(&missing).is_err());
+}
+
+#[test]
+fn lenient_loading_replaces_missing_assets() {
+    let loader = MemoryAssetLoader::new();
+    let root = combiner([sample("missing.wav", 69, None), square(0.25, 0.5)]);
+    assert!(loader.load_source_recursive(&root).is_err());
+
+    let (_, mut source, warnings) = load_source_lenient(&loader, &root).unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("missing.wav"));
+    source.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 256];
+    source.fill_buffer(&mut buffer);
+    assert!(peak_of(&buffer) > 0.0);
+}
+
+#[test]
+fn truncated_wav_data_fails_or_truncates() {
+    let bytes = std::fs::read(WAV_FILE).unwrap();
+    let (_, full) = wav_data_from_bytes(&bytes).unwrap();
+    let cut = &bytes[..bytes.len() - 5];
+    assert!(wav_data_from_bytes(cut).is_err());
+    let (spec, data) = wav_data_from_bytes_with_policy(cut, BadSamplePolicy::Truncate).unwrap();
+    assert!(!data.is_empty() && data.len() < full.len());
+    assert_eq!(data.len() % spec.channels as usize, 0);
+    assert_eq!(data[..], full[..data.len()]);
+}
+
+#[test]
+fn configs_choose_how_bad_samples_are_handled() {
+    let bytes = std::fs::read(WAV_FILE).unwrap();
+    let cut = &bytes[..bytes.len() - 5];
+    assert!(one_shot_from_bytes(cut, None).is_err());
+    assert!(one_shot_from_bytes_with_policy(cut, None, BadSamplePolicy::Truncate).is_ok());
+
+    let mut loader = MemoryAssetLoader::new();
+    loader.insert("cut.wav", cut.to_vec());
+    let failing = Config::from_bytes(b"(root: OneShotFilePath(path: \"cut.wav\"))").unwrap();
+    assert!(loader.load_source_recursive(&failing.root).is_err());
+    let truncating =
+        Config::from_bytes(b"(root: OneShotFilePath(path: \"cut.wav\", bad_samples: Truncate))")
+            .unwrap();
+    assert!(loader.load_source_recursive(&truncating.root).is_ok());
+
+    // The failing config still fails after the truncated decode has been cached
+    assert!(loader.load_source_recursive(&failing.root).is_err());
+}
+
+#[test]
+fn sample_cache_decodes_each_file_once() {
+    let mut loader = MemoryAssetLoader::new();
+    loader.insert("guitar.wav", std::fs::read(WAV_FILE).unwrap());
+    let root = combiner([
+        sample("guitar.wav", 45, None),
+        sample("guitar.wav", 57, None),
+        one_shot("guitar.wav"),
+    ]);
+    let cache = loader.sample_cache().unwrap();
+    prewarm_sample_cache(&loader, &root).unwrap();
+    assert_eq!(cache.len(), 1);
+    assert!(loader.load_source_recursive(&root).is_ok());
+    assert_eq!(cache.len(), 1);
+
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn replacing_an_asset_in_memory_drops_its_decoded_data() {
+    let mut loader = MemoryAssetLoader::new();
+    loader.insert("sound.wav", std::fs::read(WAV_FILE).unwrap());
+    let root = one_shot("sound.wav");
+    let render = |loader: &MemoryAssetLoader| {
+        let (_, mut graph) = loader.load_source_recursive(&root).unwrap();
+        graph.on_event(&NodeEvent::Note {
+            note: 69,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        });
+        let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+        graph.fill_buffer(&mut buffer);
+        peak_of(&buffer)
+    };
+    assert!(render(&loader) > 0.0);
+    assert_eq!(loader.sample_cache().unwrap().len(), 1);
+
+    // The same file with every sample silenced
+    let (spec, data) = wav_data_from_bytes(&std::fs::read(WAV_FILE).unwrap()).unwrap();
+    let mut silent = std::io::Cursor::new(vec![]);
+    let mut writer = hound::WavWriter::new(&mut silent, spec).unwrap();
+    for _ in data.iter() {
+        writer.write_sample(0i16).unwrap();
+    }
+    writer.finalize().unwrap();
+    assert!(loader.insert("sound.wav", silent.into_inner()));
+    assert!(loader.sample_cache().unwrap().is_empty());
+    assert_eq!(render(&loader), 0.0);
+}
+
+#[test]
+fn changed_files_are_decoded_again() {
+    let path = std::env::temp_dir().join(format!("midi-graph-cache-{}.wav", std::process::id()));
+    let path_str = path.to_str().unwrap().to_owned();
+    std::fs::write(&path, std::fs::read(WAV_FILE).unwrap()).unwrap();
+    let root = one_shot(&path_str);
+    let render = || {
+        let (_, mut graph) = FileGraphLoader.load_source_recursive(&root).unwrap();
+        graph.on_event(&NodeEvent::Note {
+            note: 69,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        });
+        let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+        graph.fill_buffer(&mut buffer);
+        peak_of(&buffer)
+    };
+    assert!(render() > 0.0);
+
+    // Rewrite the file silenced, marked as changed later than the first version
+    let (spec, data) = wav_data_from_bytes(&std::fs::read(WAV_FILE).unwrap()).unwrap();
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for _ in data.iter() {
+        writer.write_sample(0i16).unwrap();
+    }
+    writer.finalize().unwrap();
+    let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+    std::fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(later)
+        .unwrap();
+    assert_eq!(render(), 0.0);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn graph_loads_on_worker_thread() {
+    let mut loader = MemoryAssetLoader::new();
+    loader.insert("guitar.wav", std::fs::read(WAV_FILE).unwrap());
+    let root = combiner([sample("guitar.wav", 45, None), one_shot("missing.wav")]);
+    let handle = loader.load_async(root);
+    assert_eq!(handle.progress().asset_count, 2);
+    assert!(handle.wait().is_err());
+
+    let mut loader = MemoryAssetLoader::new();
+    loader.insert("guitar.wav", std::fs::read(WAV_FILE).unwrap());
+    let mut handle = loader.load_async(sample("guitar.wav", 45, None));
+    let waker = std::task::Waker::noop();
+    let mut context = std::task::Context::from_waker(waker);
+    let result = loop {
+        if let std::task::Poll::Ready(result) = std::pin::Pin::new(&mut handle).poll(&mut context) {
+            break result;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    };
+    assert!(result.is_ok());
+    assert_eq!(handle.progress().fraction(), 1.0);
+}
+
+#[test]
+fn inline_asset_data_decodes_base64_and_bytes() {
+    let config = Config::from_bytes(
+        b"(root: Combiner(sources: [
+            OneShotInline(data: Base64(\"not base64!\")),
+        ]))",
+    )
+    .unwrap();
+    assert!(FileGraphLoader.load_source_recursive(&config.root).is_err());
+
+    let encoded = InlineData::Base64("AAEC/w==".to_owned());
+    assert_eq!(encoded.decode().unwrap().as_ref(), &[0, 1, 2, 255]);
+    let config = Config::from_bytes(b"(root: OneShotInline(data: Bytes([1, 2, 3])))").unwrap();
+    let SoundSource::OneShotInline { data, .. } = &config.root else {
+        panic!("Expected an inline one-shot");
+    };
+    assert_eq!(data.decode().unwrap().as_ref(), &[1, 2, 3]);
+}
+
+#[cfg(all(feature = "json", feature = "yaml"))]
+#[test]
+fn config_reads_json_and_yaml() {
+    let json = br#"{"params": {"volume": 0.5}, "root": {"Fader": {"initial_volume": {"Param": "volume"}, "source": {"SquareWave": {}}}}}"#;
+    let config = Config::from_bytes_in_format(json, ConfigFormat::Json).unwrap();
+    assert!(matches!(config.root, SoundSource::Fader { .. }));
+
+    let yaml = b"params:\n  duty: 0.25\nroot: !Midi\n  source: !FilePath song.mid\n  channels:\n    0: !SquareWave\n      duty_cycle: !Param duty\n";
+    let config = Config::from_bytes_in_format(yaml, ConfigFormat::Yaml).unwrap();
+    assert_eq!(asset_paths(&config.root), vec!["song.mid".to_owned()]);
+    assert_eq!(
+        ConfigFormat::from_path("graphs/demo.YML"),
+        ConfigFormat::Yaml
+    );
+}
+
+#[test]
+fn exported_config_reads_back() {
+    let source = br#"(root: Midi(
+        source: FilePath("song.mid"),
+        channels: {0: SampleFilePath(path: "kick.wav", base_note: 36, looping: Some((start: 0, end: 10)))},
+    ))"#;
+    let config = Config::from_bytes(source).unwrap();
+    let text = GraphExporter::new(ConfigFormat::Ron)
+        .to_string(&config)
+        .unwrap();
+    let exported = Config::from_bytes(text.as_bytes()).unwrap();
+    assert_eq!(exported.version, CURRENT_CONFIG_VERSION);
+    assert_eq!(
+        asset_paths(&exported.root),
+        vec!["song.mid".to_owned(), "kick.wav".to_owned()]
+    );
+}
+
+#[test]
+fn config_validation_reports_field_paths() {
+    let source = br#"(root: Midi(
+        source: FilePath("missing.mid"),
+        channels: {1: Font(config: Ranges([
+            (lower: 0, upper: 127, source: SquareWave()),
+            (lower: 60, upper: 40, source: SquareWave(duty_cycle: 1.5)),
+        ]))},
+    ))"#;
+    let config = Config::from_bytes(source).unwrap();
+    assert!(config.validate(None).is_err());
+    let paths: Vec<String> = config
+        .validation_errors(Some(&MemoryAssetLoader::new()))
+        .into_iter()
+        .map(|error| error.path)
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            "root.Midi.source.FilePath",
+            "root.Midi.channels[1].Font.config.Ranges[1]",
+            "root.Midi.channels[1].Font.config.Ranges[1].source.SquareWave.duty_cycle",
+        ]
+    );
+}
+
+#[test]
+fn config_includes_resolve_relative_to_including_file() {
+    let loader = MemoryAssetLoader::new()
+        .with_static(
+            "songs/song.ron",
+            br#"(
+                root: Midi(source: FilePath("song.mid"), channels: {0: Ref("lead"), 1: Ref("lead")}),
+                definitions: {"lead": Include("../instruments/lead.ron")},
+            )"#,
+        )
+        .with_static(
+            "instruments/lead.ron",
+            br#"(root: Ref("square"), definitions: {"square": SquareWave(duty_cycle: 0.25)})"#,
+        )
+        .with_static("instruments/loop.ron", br#"(root: Include("./loop.ron"))"#);
+
+    let bytes = loader.load_asset_data("songs/song.ron").unwrap();
+    let mut config = Config::from_bytes(&bytes).unwrap();
+    config.resolve_includes("songs/song.ron", &loader).unwrap();
+    let SoundSource::Midi { channels, .. } = &config.root else {
+        panic!("Expected a MIDI source");
+    };
+    for channel in channels.values() {
+        assert!(matches!(
+            channel,
+            SoundSource::SquareWave { duty_cycle, .. } if duty_cycle.value().unwrap() == 0.25
+        ));
+    }
+
+    let mut cyclic = Config::from_bytes(br#"(root: Include("loop.ron"))"#).unwrap();
+    assert!(cyclic
+        .resolve_includes("instruments/main.ron", &loader)
+        .is_err());
+}
+
+#[test]
+fn config_params_resolve_and_rebind_at_runtime() {
+    let config = Config::from_bytes(
+        br#"(
+            params: {"master_volume": 0.5, "lead_duty": 0.25},
+            root: Fader(
+                initial_volume: Param("master_volume"),
+                source: SquareWave(duty_cycle: Param("lead_duty")),
+            ),
+        )"#,
+    )
+    .unwrap();
+    let SoundSource::Fader { source, .. } = &config.root else {
+        panic!("Expected a fader");
+    };
+    assert!(matches!(
+        source.as_ref(),
+        SoundSource::SquareWave { duty_cycle, .. } if duty_cycle.value().unwrap() == 0.25
+    ));
+    assert!(Config::from_bytes(
+        b"(root: Fader(initial_volume: Param(\"missing\"), source: SquareWave()))"
+    )
+    .is_err());
+
+    let (_, mut graph) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+    graph.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 2048];
+    graph.fill_buffer(&mut buffer);
+    assert!((peak_of(&buffer) - 0.25).abs() < 0.001);
+    graph.on_event(&NodeEvent::Broadcast(BroadcastControl::SetParam {
+        param_id: param_id("master_volume"),
+        value: 1.0,
+    }));
+    buffer.fill(0.0);
+    graph.fill_buffer(&mut buffer);
+    assert!((peak_of(&buffer) - 0.5).abs() < 0.001);
+}
+
+#[test]
+fn config_snapshots_recall_param_values_over_time() {
+    let config = Config::from_bytes(
+        br#"(
+            params: {"music_volume": 1.0},
+            snapshots: {"underwater": {"music_volume": 0.25}},
+            root: Fader(
+                initial_volume: Param("music_volume"),
+                source: SquareWave(amplitude: 1.0),
+            ),
+        )"#,
+    )
+    .unwrap();
+    assert!(Config::from_bytes(
+        b"(snapshots: {\"quiet\": {\"missing\": 0.0}}, root: SquareWave())"
+    )
+    .is_err());
+
+    let (_, mut graph) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+    graph.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    graph.on_event(&NodeEvent::Broadcast(BroadcastControl::RecallSnapshot {
+        snapshot_id: snapshot_id("underwater"),
+        seconds: 0.02,
+    }));
+    let mut buffer = vec![0.0; 4096];
+    graph.fill_buffer(&mut buffer);
+    assert!(peak_of(&buffer[0..128]) > 0.9);
+    assert!((peak_of(&buffer[4000..]) - 0.25).abs() < 0.001);
+}
+
+#[test]
+fn snapshot_recall_moves_params_of_silent_nodes() {
+    let config = Config::from_bytes(
+        br#"(
+            params: {"music_volume": 1.0},
+            snapshots: {"underwater": {"music_volume": 0.25}},
+            root: Combiner(sources: [
+                Fader(initial_volume: Param("music_volume"), source: SquareWave(amplitude: 1.0)),
+            ]),
+        )"#,
+    )
+    .unwrap();
+    let (_, mut graph) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+    graph.on_event(&NodeEvent::Broadcast(BroadcastControl::RecallSnapshot {
+        snapshot_id: snapshot_id("underwater"),
+        seconds: 0.02,
+    }));
+    assert!(graph.is_active());
+    let mut buffer = vec![0.0; 4096];
+    graph.fill_buffer(&mut buffer);
+    graph.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    buffer.fill(0.0);
+    graph.fill_buffer(&mut buffer);
+    assert!((peak_of(&buffer) - 0.25).abs() < 0.001);
+}
+
+#[test]
+fn control_events_reach_every_node_with_a_tag() {
+    let config = Config::from_bytes(
+        br#"(
+            root: Combiner(sources: [
+                Tagged(tags: ["sfx"], source: Fader(initial_volume: 1.0, source: SquareWave(amplitude: 0.25))),
+                Tagged(tags: ["ui", "sfx"], source: Fader(initial_volume: 1.0, source: SquareWave(amplitude: 0.25))),
+                Fader(initial_volume: 1.0, source: SquareWave(amplitude: 0.25)),
+            ]),
+        )"#,
+    )
+    .unwrap();
+    let (_, mut graph) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+    graph.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 256];
+    graph.fill_buffer(&mut buffer);
+    assert!((peak_of(&buffer) - 0.75).abs() < 0.001);
+    graph.on_event(&NodeEvent::NodeControl {
+        node_id: tag_id("sfx"),
+        event: NodeControlEvent::Fade {
+            from: 0.0,
+            to: 0.0,
+            seconds: 0.0,
+        },
+    });
+    buffer.fill(0.0);
+    graph.fill_buffer(&mut buffer);
+    assert!((peak_of(&buffer) - 0.25).abs() < 0.001);
+}
+
+#[test]
+fn named_sources_take_control_events_by_name() {
+    let config = Config::from_bytes(
+        br#"(
+            root: EventReceiver(source: Combiner(sources: [
+                Named(name: "lead_fader", source: Fader(initial_volume: 1.0, source: SquareWave(amplitude: 0.25))),
+                Fader(initial_volume: 1.0, source: SquareWave(amplitude: 0.25)),
+            ])),
+        )"#,
+    )
+    .unwrap();
+    let (channels, names, mut graph) = FileGraphLoader
+        .load_source_with_names(&config.root)
+        .unwrap();
+    assert_eq!(names.node_id("lead_fader").unwrap(), name_id("lead_fader"));
+    assert!(names.node_id("bass_fader").is_err());
+    channels[0]
+        .send(NodeEvent::Note {
+            note: 69,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        })
+        .unwrap();
+    names
+        .send(
+            &channels,
+            "lead_fader",
+            NodeControlEvent::Fade {
+                from: 0.0,
+                to: 0.0,
+                seconds: 0.0,
+            },
+        )
+        .unwrap();
+    let mut buffer = vec![0.0; 256];
+    graph.fill_buffer(&mut buffer);
+    assert!((peak_of(&buffer) - 0.25).abs() < 0.001);
+
+    let duplicated = Config::from_bytes(
+        br#"(
+            root: Combiner(sources: [
+                Named(name: "lead", source: SquareWave()),
+                Named(name: "lead", source: SquareWave()),
+            ]),
+        )"#,
+    )
+    .unwrap();
+    assert!(duplicated.validate(None).is_err());
+}
+
+#[test]
+fn host_events_written_as_data_reach_the_graph() {
+    let config = Config::from_bytes(
+        br#"(
+            root: Named(name: "lead_fader", source: Fader(initial_volume: 1.0, source: SquareWave(amplitude: 0.25))),
+        )"#,
+    )
+    .unwrap();
+    let (_, names, mut graph) = FileGraphLoader
+        .load_source_with_names(&config.root)
+        .unwrap();
+    let apply = |graph: &mut Box<dyn BufferConsumerNode + Send + 'static>, event: &str| {
+        let event: HostEvent = ron::de::from_str(event).unwrap();
+        graph.on_event(&event.to_node_event(&names).unwrap());
+    };
+    apply(&mut graph, "NoteOn(note: 69, vel: 1.0)");
+    apply(
+        &mut graph,
+        r#"Control(target: Name("lead_fader"), event: Fade(from: 0.5, to: 0.5, seconds: 0.0))"#,
+    );
+    let mut buffer = vec![0.0; 256];
+    graph.fill_buffer(&mut buffer);
+    assert!((peak_of(&buffer) - 0.125).abs() < 0.001);
+
+    let unknown: HostEvent =
+        ron::de::from_str(r#"Control(target: Name("bass_fader"), event: Volume(0.5))"#).unwrap();
+    assert!(unknown.to_node_event(&names).is_err());
+    let stop: HostEvent = ron::de::from_str("Stop").unwrap();
+    assert!(matches!(
+        stop.to_node_event(&names).unwrap(),
+        NodeEvent::Broadcast(BroadcastControl::Stop)
+    ));
+}
+
+#[test]
+fn typed_handles_check_node_types_and_send_controls() {
+    let config = Config::from_bytes(
+        br#"(
+            root: EventReceiver(source: Fader(
+                node_id: Some(9),
+                initial_volume: 1.0,
+                source: SquareWave(amplitude: 0.5),
+            )),
+        )"#,
+    )
+    .unwrap();
+    let (channels, mut graph) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+    assert!(MixerHandle::new(&channels[0], &config.root, 9).is_err());
+    assert!(FaderHandle::new(&channels[0], &config.root, 10).is_err());
+    let fader = FaderHandle::new(&channels[0], &config.root, 9).unwrap();
+
+    channels[0]
+        .send(NodeEvent::Note {
+            note: 69,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        })
+        .unwrap();
+    fader.set_volume(0.5).unwrap();
+    let mut buffer = vec![0.0; 256];
+    graph.fill_buffer(&mut buffer);
+    assert!((peak_of(&buffer) - 0.25).abs() < 0.001);
+}
+
+#[test]
+fn graph_builder_produces_loadable_config() {
+    let config = Graph::new()
+        .midi("resources/sample-in-c.mid")
+        .param("lead_volume", 0.125)
+        .channel(
+            1,
+            font(|f| {
+                f.range(..51, square(param("lead_volume"), 0.5))
+                    .range(51.., square(param("lead_volume"), 0.875))
+            }),
+        )
+        .build()
+        .unwrap();
+    let SoundSource::Midi { channels, .. } = &config.root else {
+        panic!("Expected a MIDI source");
+    };
+    let SoundSource::Font {
+        config: FontSource::Ranges(ranges),
+        ..
+    } = &channels[&1]
+    else {
+        panic!("Expected a font");
+    };
+    let bounds: Vec<(u8, u8)> = ranges
+        .iter()
+        .map(|range| (range.lower, range.upper))
+        .collect();
+    assert_eq!(bounds, vec![(0, 50), (51, 255)]);
+    assert!(FileGraphLoader.load_source_recursive(&config.root).is_ok());
+    assert!(Graph::new().build().is_err());
+}
+
+#[derive(serde_derive::Deserialize)]
+struct TestGainConfig {
+    gain: f32,
+}
+
+impl NodeConfig for TestGainConfig {
+    fn build(
+        &self,
+        node_id: Option<u64>,
+        sources: Vec<Box<dyn BufferConsumerNode + Send + 'static>>,
+    ) -> Result<Box<dyn BufferConsumerNode + Send + 'static>, crate::Error> {
+        let inputs = Box::new(CombinerSource::new(None, sources));
+        Ok(Box::new(Fader::new(node_id, self.gain, inputs)))
+    }
+}
+
+#[test]
+fn registered_custom_nodes_load_from_config() {
+    let config = Config::from_bytes(
+        br#"(root: Custom(name: "TestGain", config: (gain: 0.5), sources: [SquareWave()]))"#,
+    )
+    .unwrap();
+    assert!(config.validate(None).is_err());
+    register_node_type::<TestGainConfig>("TestGain");
+    assert!(config.validate(None).is_ok());
+
+    let (_, mut graph) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+    graph.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 2048];
+    graph.fill_buffer(&mut buffer);
+    assert!((peak_of(&buffer) - 0.25).abs() < 0.001);
+}
+
+// Node written as a downstream crate would, implementing only the required methods
+struct TestConstantNode {
+    node_id: u64,
+    level: f32,
+}
+
+impl BufferConsumerNode for TestConstantNode {}
+
+impl Node for TestConstantNode {
+    fn get_node_id(&self) -> u64 {
+        self.node_id
+    }
+
+    fn on_event(&mut self, _event: &NodeEvent) {}
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        for sample in buffer.iter_mut() {
+            *sample += self.level;
+        }
+    }
+}
+
+impl BufferConsumer for TestConstantNode {}
+
+#[test]
+fn custom_nodes_need_only_required_methods() {
+    let node = TestConstantNode {
+        node_id: 5,
+        level: 0.25,
+    };
+    assert!(node.is_active());
+    assert!(node.duplicate().is_err());
+
+    let mut graph = CombinerSource::new(None, vec![Box::new(node)]);
+    let mut buffer = vec![0.0; 64];
+    graph.fill_buffer(&mut buffer);
+    assert!(buffer.iter().all(|sample| *sample == 0.25));
+    assert!(graph.duplicate().is_err());
+}
+
+#[test]
+fn control_events_route_only_to_branches_holding_their_target() {
+    let square = |node_id| -> Box<dyn BufferConsumerNode + Send + 'static> {
+        let mut square = SquareWaveSource::new(Some(node_id), 0.25, 0.5);
+        square.on_event(&NodeEvent::Note {
+            note: 69,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        });
+        Box::new(square)
+    };
+    let children: Vec<Box<dyn BufferConsumerNode + Send + 'static>> = vec![
+        Box::new(Fader::new(Some(10), 1.0, square(20))),
+        Box::new(Fader::new(Some(11), 1.0, square(21))),
+        Box::new(TestConstantNode {
+            node_id: 5,
+            level: 0.0,
+        }),
+    ];
+    let routes = EventRoutes::new(
+        children
+            .iter()
+            .enumerate()
+            .map(|(index, child)| (index, child.as_ref())),
+    );
+    assert_eq!(routes.targets(11).collect::<Vec<_>>(), vec![1, 2]);
+    assert_eq!(routes.targets(20).collect::<Vec<_>>(), vec![0, 2]);
+    assert_eq!(routes.targets(99).collect::<Vec<_>>(), vec![2]);
+
+    let mut graph = CombinerSource::new(Some(1), children);
+    let mut ids = vec![];
+    assert!(!graph.collect_node_ids(&mut ids));
+    assert!([1, 10, 11, 20, 21].iter().all(|id| ids.contains(id)));
+    graph.on_event(&NodeEvent::NodeControl {
+        node_id: 11,
+        event: NodeControlEvent::Fade {
+            from: 0.0,
+            to: 0.0,
+            seconds: 0.0,
+        },
+    });
+    let mut buffer = vec![0.0; 64];
+    graph.fill_buffer(&mut buffer);
+    assert_eq!(buffer[0].abs(), 0.25);
+}
+
+#[test]
+fn band_limited_oscillators_reduce_high_frequency_content() {
+    fn high_frequency_energy(mut source: Box<dyn BufferConsumerNode + Send + 'static>) -> f32 {
+        source.on_event(&NodeEvent::Note {
+            note: 100,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        });
+        let mut buffer = vec![0.0; 4096];
+        source.fill_buffer(&mut buffer);
+        let left: Vec<f32> = buffer.iter().step_by(2).copied().collect();
+        left.windows(2)
+            .map(|pair| (pair[1] - pair[0]).powi(2))
+            .sum()
+    }
+    let square_energy = |mode| {
+        let mut square = SquareWaveSource::new(None, 0.5, 0.25);
+        square.set_oscillator_mode(mode);
+        high_frequency_energy(Box::new(square))
+    };
+    let sawtooth_energy = |mode| {
+        let mut sawtooth = SawtoothWaveSource::new(None, 0.5);
+        sawtooth.set_oscillator_mode(mode);
+        high_frequency_energy(Box::new(sawtooth))
+    };
+    assert!(
+        square_energy(OscillatorMode::BandLimited) < 0.8 * square_energy(OscillatorMode::Naive)
+    );
+    assert!(
+        sawtooth_energy(OscillatorMode::BandLimited) < 0.8 * sawtooth_energy(OscillatorMode::Naive)
+    );
+}
+
+#[test]
+fn fm_synth_plays_and_releases() {
+    let config = Config::from_bytes(b"(root: FmSynth(amplitude: 0.5))").unwrap();
+    let (_, mut synth) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+    synth.on_event(&NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 4096];
+    synth.fill_buffer(&mut buffer);
+    let peak = peak_of(&buffer);
+    assert!(peak > 0.1 && peak <= 0.5);
+
+    synth.on_event(&NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOff { vel: 1.0 },
+    });
+    for _ in 0..10 {
+        buffer.fill(0.0);
+        synth.fill_buffer(&mut buffer);
+    }
+    assert!(!synth.is_active());
+
+    let operators = [FmOperator::default(); 5];
+    assert!(FmSynthSource::new(None, 0.5, FmAlgorithm::Stack, &operators).is_err());
+}
+
+#[test]
+fn unison_voices_spread_across_stereo() {
+    let mut saw = SawtoothWaveSource::new(None, 0.5);
+    saw.set_unison(Unison {
+        voices: 5,
+        detune_cents: 20.0,
+        spread: 1.0,
+    });
+    saw.on_event(&NodeEvent::Note {
+        note: 45,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 8192];
+    saw.fill_buffer(&mut buffer);
+    assert!(peak_of(&buffer) <= 0.5);
+    let difference: f32 = buffer.chunks_exact(2).map(|f| (f[0] - f[1]).abs()).sum();
+    assert!(difference > 10.0);
+}
+
+#[test]
+fn glide_slides_between_notes() {
+    let crossings = |buffer: &[f32]| {
+        let left: Vec<f32> = buffer.iter().step_by(2).copied().collect();
+        left.windows(2).filter(|w| w[0] * w[1] < 0.0).count()
+    };
+    let mut lead = TriangleWaveSource::new(None, 0.5);
+    lead.set_pitch_motion(PitchMotion {
+        glide_time: 0.2,
+        ..PitchMotion::NONE
+    });
+    for note in [57, 69] {
+        lead.on_event(&NodeEvent::Note {
+            note,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        });
+    }
+
+    // 1/20 second windows hold 11 cycles of A220 and 22 of A440, each crossing zero twice
+    let window = consts::PLAYBACK_SAMPLE_RATE / 20 * consts::CHANNEL_COUNT;
+    let mut buffer = vec![0.0; window];
+    lead.fill_buffer(&mut buffer);
+    let start = crossings(&buffer);
+    for _ in 0..4 {
+        buffer.fill(0.0);
+        lead.fill_buffer(&mut buffer);
+    }
+    let end = crossings(&buffer);
+    assert!((22..=28).contains(&start));
+    assert!((43..=45).contains(&end));
+}
+
+#[test]
+fn additive_synth_stays_within_its_amplitude() {
+    let mut organ = AdditiveSource::new(None, 0.5, &[1.0, 0.5, 0.25, 0.5], &[0.0, 3.0]).unwrap();
+    organ.on_event(&NodeEvent::Note {
+        note: 48,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 4096];
+    organ.fill_buffer(&mut buffer);
+    let peak = peak_of(&buffer);
+    assert!(peak > 0.2 && peak <= 0.5);
+    assert!(AdditiveSource::new(None, 0.5, &[], &[]).is_err());
+    assert!(AdditiveSource::new(None, 0.5, &[1.0], &[0.0, 1.0]).is_err());
+}
+
+#[test]
+fn plucked_string_rings_and_dies_away() {
+    let mut string = PluckedStringSource::new(None, 0.5, 0.99, 0.5);
+    string.on_event(&NodeEvent::Note {
+        note: 57,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 2048];
+    string.fill_buffer(&mut buffer);
+    let first_peak = peak_of(&buffer);
+    assert!(first_peak > 0.1 && first_peak <= 0.5);
+    buffer.fill(0.0);
+    string.fill_buffer(&mut buffer);
+    assert!(peak_of(&buffer) < first_peak);
+    string.on_event(&NodeEvent::Note {
+        note: 57,
+        voice_id: None,
+        event: NoteEvent::NoteOff { vel: 1.0 },
+    });
+    for _ in 0..50 {
+        string.fill_buffer(&mut buffer);
+    }
+    assert!(!string.is_active());
+}
+
+#[test]
+fn darker_noise_colors_have_less_high_frequency_energy() {
+    // Ratio of the energy in the first difference, dominated by high frequencies, to the total
+    let brightness = |color| {
+        let mut noise = ColoredNoiseSource::new(None, 0.5, color);
+        noise.on_event(&NodeEvent::Note {
+            note: 60,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        });
+        let mut buffer = vec![0.0; 8192];
+        noise.fill_buffer(&mut buffer);
+        let left: Vec<f32> = buffer.iter().step_by(2).copied().collect();
+        let total: f32 = left.iter().map(|x| x * x).sum();
+        let diff: f32 = left.windows(2).map(|w| (w[1] - w[0]).powi(2)).sum();
+        diff / total
+    };
+    let white = brightness(NoiseColor::White);
+    let pink = brightness(NoiseColor::Pink);
+    let brown = brightness(NoiseColor::Brown);
+    assert!(white > pink && pink > brown);
+}
+
+#[test]
+fn sample_hold_changes_pitch_at_its_rate() {
+    // Count zero crossings in each 1/10 second step; the pitch should not stay constant
+    let mut arp = SampleHoldSource::new(None, 0.5, 10.0, 12);
+    arp.on_event(&NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let step_frames = consts::PLAYBACK_SAMPLE_RATE / 10;
+    let mut crossings = vec![];
+    for _ in 0..8 {
+        let mut buffer = vec![0.0; step_frames * consts::CHANNEL_COUNT];
+        arp.fill_buffer(&mut buffer);
+        let left: Vec<f32> = buffer.iter().step_by(2).copied().collect();
+        assert!(peak_of(&left) > 0.4);
+        crossings.push(left.windows(2).filter(|w| w[0] * w[1] < 0.0).count());
+    }
+    assert!(crossings.iter().any(|count| *count != crossings[0]));
+}
+
+#[test]
+fn note_map_transposes_and_filters_notes() {
+    let mapping = NoteMapping {
+        transpose: 12,
+        scale: Scale::Major,
+        ..Default::default()
+    };
+    let inner = Box::new(TriangleWaveSource::new(None, 0.5));
+    let mut note_map = NoteMap::new(None, mapping, inner);
+    let note_on = |note| NodeEvent::Note {
+        note,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    };
+
+    // C# is outside C major once transposed, but A is inside it
+    note_map.on_event(&note_on(61));
+    assert!(!note_map.is_active());
+    note_map.on_event(&note_on(57));
+    assert!(note_map.is_active());
+    let mut buffer = vec![0.0; consts::PLAYBACK_SAMPLE_RATE / 10 * consts::CHANNEL_COUNT];
+    note_map.fill_buffer(&mut buffer);
+    let left: Vec<f32> = buffer.iter().step_by(2).copied().collect();
+    let crossings = left.windows(2).filter(|w| w[0] * w[1] < 0.0).count();
+    assert!((87..=89).contains(&crossings));
+
+    // Notes are released at the pitch they started on after the mapping changes
+    note_map.on_event(&NodeEvent::NodeControl {
+        node_id: note_map.get_node_id(),
+        event: NodeControlEvent::NoteMap(NoteMapping::default()),
+    });
+    note_map.on_event(&NodeEvent::Note {
+        note: 57,
+        voice_id: None,
+        event: NoteEvent::NoteOff { vel: 1.0 },
+    });
+    assert!(!note_map.is_active());
+}
+
+#[test]
+fn scale_quantizer_snaps_notes_into_key() {
+    let blues = Scale::Blues;
+    assert_eq!(blues.quantize(9, 62, QuantizeDirection::Nearest), Some(62));
+    assert_eq!(blues.quantize(9, 65, QuantizeDirection::Nearest), Some(64));
+    assert_eq!(blues.quantize(9, 61, QuantizeDirection::Nearest), Some(60));
+    assert_eq!(blues.quantize(9, 61, QuantizeDirection::Up), Some(62));
+    assert_eq!(blues.quantize(9, 58, QuantizeDirection::Down), Some(57));
+    assert_eq!(
+        Scale::Custom(vec![]).quantize(0, 60, QuantizeDirection::Up),
+        None
+    );
+
+    // C# snaps up to D in C major, then releases from D
+    let inner = Box::new(TriangleWaveSource::new(None, 0.5));
+    let mut quantizer = ScaleQuantizer::new(None, Scale::Major, 0, QuantizeDirection::Up, inner);
+    quantizer.on_event(&NodeEvent::Note {
+        note: 61,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    assert!(quantizer.is_active());
+    quantizer.on_event(&NodeEvent::Note {
+        note: 61,
+        voice_id: None,
+        event: NoteEvent::NoteOff { vel: 1.0 },
+    });
+    assert!(!quantizer.is_active());
+}
+
+#[test]
+fn sequencer_plays_steps_with_gate_lengths() {
+    // At 120 BPM with four steps to the beat, each step lasts 6000 frames
+    let note = SequencerStep::Note {
+        note: 69,
+        velocity: 1.0,
+        gate: 0.5,
+    };
+    let steps = vec![note, SequencerStep::Rest, note];
+    let inner = Box::new(TriangleWaveSource::new(None, 0.5));
+    let mut sequencer = SequencerSource::new(None, 120.0, 4, 0.0, false, steps, inner).unwrap();
+    let mut buffer = vec![0.0; 18000 * consts::CHANNEL_COUNT];
+    sequencer.fill_buffer(&mut buffer);
+    let window_peak = |start: usize, end: usize| {
+        peak_of(&buffer[start * consts::CHANNEL_COUNT..end * consts::CHANNEL_COUNT])
+    };
+    assert!(window_peak(100, 2900) > 0.4);
+    assert_eq!(window_peak(3100, 11900), 0.0);
+    assert!(window_peak(12100, 14900) > 0.4);
+    assert_eq!(window_peak(15100, 18000), 0.0);
+    assert!(!sequencer.is_active());
+}
+
+#[test]
+fn config_migrates_to_current_version() {
+    let config = Config::from_bytes(b"(root: TestSignal())").unwrap();
+    assert_eq!(config.version, CURRENT_CONFIG_VERSION);
+    let mut config = config.clone();
+    assert!(config.migrate().unwrap().is_empty());
+
+    // A version 0 file, without a version field, loads unchanged
+    let mut old = Config::from_bytes_in_format(
+        br#"(
+            root: Envelope(
+                attack_time: 0.0,
+                decay_time: 0.0,
+                sustain_multiplier: 0.25,
+                source: SquareWave(),
+            ),
+        )"#,
+        ConfigFormat::Ron,
+    )
+    .unwrap();
+    assert_eq!(old.version, CURRENT_CONFIG_VERSION);
+    assert!(old.migrate().unwrap().is_empty());
+    let SoundSource::Envelope {
+        sustain_multiplier, ..
+    } = &old.root
+    else {
+        panic!("Expected an envelope");
+    };
+    assert_eq!(sustain_multiplier.value().unwrap(), 0.25);
+    let exported = GraphExporter::new(ConfigFormat::Ron)
+        .to_string(&old)
+        .unwrap();
+    assert!(exported.contains("sustain_multiplier"));
+    config.version = CURRENT_CONFIG_VERSION + 1;
+    assert!(config.migrate().is_err());
+}
+
+#[test]
+fn stop_silences_envelope_without_release() {
+    let mut envelope = Envelope::from_adsr(
+        None,
+        0.01,
+        0.01,
+        0.5,
+        0.5,
+        Box::new(SquareWaveSource::new(None, 0.25, 0.5)),
+    );
+    envelope.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 2048];
+    envelope.fill_buffer(&mut buffer);
+    envelope.on_event(&NodeEvent::Broadcast(BroadcastControl::Stop));
+    assert!(!envelope.is_active());
+    buffer.fill(0.0);
+    envelope.fill_buffer(&mut buffer);
+    assert!(buffer.iter().all(|sample| *sample == 0.0));
+}
+
+#[cfg(feature = "rodio")]
+#[test]
+fn graph_source_yields_stereo_samples_for_rodio() {
+    use crate::GraphSource;
+    use rodio::Source;
+
+    let mut square = SquareWaveSource::new(None, 0.25, 0.5);
+    square.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut source = GraphSource::new(Box::new(square));
+    assert_eq!(source.channels(), 2);
+    let samples: Vec<f32> = source.by_ref().take(100).collect();
+    assert!(samples.iter().all(|sample| sample.abs() == 0.25));
+    assert_eq!(source.current_frame_len(), Some(4096 - 100));
+}
+
+#[test]
+fn clock_offset_maps_server_time_to_local_time() {
+    let sent = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+    let received = sent + Duration::from_millis(40);
+    let server_time = sent + Duration::from_millis(520);
+    let offset = ClockOffset::from_round_trip(sent, server_time, received);
+    let server_start = server_time + Duration::from_secs(2);
+    let local_start = offset.to_local_time(server_start);
+    assert_eq!(local_start, sent + Duration::from_millis(2020));
+}
+
+// Delivers whatever one instance sends to every other, as a broadcast would
+struct TestSyncTransport {
+    peers: Vec<Sender<Vec<u8>>>,
+    inbox: Receiver<Vec<u8>>,
+}
+
+impl SyncTransport for TestSyncTransport {
+    fn send(&mut self, message: &[u8]) -> Result<(), Error> {
+        for peer in self.peers.iter() {
+            let _ = peer.send(message.to_vec());
+        }
+        Ok(())
+    }
+
+    fn try_receive(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.inbox.try_recv().ok())
+    }
+}
+
+fn test_sync_network(instances: usize) -> Vec<TestSyncTransport> {
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0..instances).map(|_| unbounded()).unzip();
+    receivers
+        .into_iter()
+        .enumerate()
+        .map(|(index, inbox)| TestSyncTransport {
+            peers: senders
+                .iter()
+                .enumerate()
+                .filter(|(peer, _)| *peer != index)
+                .map(|(_, sender)| sender.clone())
+                .collect(),
+            inbox,
+        })
+        .collect()
+}
+
+#[test]
+fn sync_followers_learn_the_start_from_the_leader() {
+    let mut network = test_sync_network(3);
+    let mut late_follower = SyncFollower::new(network.pop().unwrap(), 2);
+    let mut follower = SyncFollower::new(network.pop().unwrap(), 1);
+    let mut leader = SyncLeader::new(network.pop().unwrap());
+    assert!(follower.start_time().is_none());
+
+    // The first follower hears the start announced, and the second, joining later, is told
+    // it along with the time
+    let start = leader.schedule_start(Duration::from_secs(2)).unwrap();
+    follower.poll().unwrap();
+    leader.poll().unwrap();
+    follower.poll().unwrap();
+    assert!(late_follower.start_time().is_none());
+    late_follower.poll().unwrap();
+    leader.poll().unwrap();
+    late_follower.poll().unwrap();
+
+    // Sharing one clock, each should find the leader's start within a millisecond
+    for follower in [&follower, &late_follower] {
+        let local_start = follower.start_time().unwrap();
+        let difference = match local_start > start {
+            true => local_start.duration_since(start).unwrap(),
+            false => start.duration_since(local_start).unwrap(),
+        };
+        assert!(difference < Duration::from_millis(1), "{:?}", difference);
+    }
+}
+
+#[test]
+#[cfg(feature = "driver-cpal")]
+fn stream_clock_follows_the_wall_clock() {
+    let clock = StreamClock::default();
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+    let tolerance = Duration::from_millis(5);
+    clock.mark_callback(start);
+    assert_eq!(clock.advance(4096), Some(0));
+
+    // A sound card running fast has rendered 960 frames more than a second's worth in a
+    // second, so the program is held back by as much
+    while clock.frames_rendered() < 48960 {
+        clock.advance(2 * (48960 - clock.frames_rendered()).min(2048) as usize);
+    }
+    let second_later = start + Duration::from_secs(1);
+    clock.mark_callback(second_later);
+    assert_eq!(clock.correct_drift(second_later, start, 0, tolerance), 960);
+    assert_eq!(clock.advance(4800), Some(2 * 960));
+    assert_eq!(clock.advance(4800), Some(0));
+    assert_eq!(clock.take_skip_frames(2048), 0);
+
+    // Once held back, it is back in step
+    let now = second_later + Duration::from_millis(100);
+    clock.mark_callback(now);
+    assert_eq!(clock.correct_drift(now, start, 0, tolerance), 0);
+
+    // A slow one is skipped ahead, at most a buffer at a time
+    let now = now + Duration::from_millis(100);
+    clock.mark_callback(now);
+    assert_eq!(clock.correct_drift(now, start, 0, tolerance), -4800);
+    assert_eq!(clock.take_skip_frames(2048), 2048);
+    assert_eq!(clock.take_skip_frames(2048), 2048);
+    assert_eq!(clock.take_skip_frames(2048), 704);
+    assert_eq!(clock.take_skip_frames(2048), 0);
+}
+
+#[test]
+#[cfg(feature = "driver-cpal")]
+fn output_conditioning_removes_dc_and_denormals() {
+    let mut blocker = DcBlocker::default();
+    let mut buffer = vec![0.5; 4096];
+    for _ in 0..24 {
+        buffer.fill(0.5);
+        blocker.process(&mut buffer);
+    }
+    assert!(peak_of(&buffer[4000..]) < 0.01);
+
+    let denormal = std::hint::black_box(f32::MIN_POSITIVE);
+    set_flush_to_zero(true);
+    let flushed = std::hint::black_box(denormal) / 4.0;
+    set_flush_to_zero(false);
+    let kept = std::hint::black_box(denormal) / 4.0;
+    assert_eq!(flushed, 0.0);
+    assert!(kept > 0.0);
+}
+
+#[test]
+fn buffer_helpers_handle_remainders() {
+    let mut dst = vec![1.0; 11];
+    let src: Vec<f32> = (0..11).map(|i| i as f32).collect();
+    add_scaled_buffer(&mut dst, &src, -0.5);
+    assert_eq!(dst[0], 1.0);
+    assert_eq!(dst[10], -4.0);
+    assert_eq!(peak_of(&dst), 4.0);
+}
+
+#[test]
+fn add_interleaved_pairs_channels_into_frames() {
+    // Five frames, so that one is left over after a full set of lanes
+    let left: Vec<f32> = (0..5).map(|i| i as f32).collect();
+    let right: Vec<f32> = (0..5).map(|i| -(i as f32)).collect();
+    let mut dst = vec![1.0; 12];
+    add_interleaved(&mut dst, &left, &right, 2.0);
+    assert_eq!(
+        dst,
+        vec![1.0, 1.0, 3.0, -1.0, 5.0, -3.0, 7.0, -5.0, 9.0, -7.0, 1.0, 1.0]
+    );
+}
+
+#[test]
+fn parallel_combiner_sums_worker_output() {
+    let mut combiner = ParallelCombinerSource::new(
+        None,
+        vec![
+            Box::new(SquareWaveSource::new(None, 0.25, 0.5)),
+            Box::new(SquareWaveSource::new(None, 0.25, 0.5)),
+        ],
+    )
+    .unwrap();
+    assert!(!combiner.is_active());
+    combiner.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    assert!(combiner.is_active());
+    let mut buffer = vec![0.0; 256];
+    combiner.fill_buffer(&mut buffer);
+    assert!(buffer.iter().all(|sample: &f32| sample.abs() == 0.5));
+}
+
+// Node taking far longer to fill its first buffer than the buffer lasts
+struct TestStallingNode {
+    has_stalled: bool,
+}
+
+impl BufferConsumerNode for TestStallingNode {}
+
+impl Node for TestStallingNode {
+    fn get_node_id(&self) -> u64 {
+        0
+    }
+
+    fn on_event(&mut self, _event: &NodeEvent) {}
+
+    fn fill_buffer(&mut self, buffer: &mut [f32]) {
+        if !self.has_stalled {
+            self.has_stalled = true;
+            std::thread::sleep(Duration::from_millis(500));
+        }
+        for sample in buffer.iter_mut() {
+            *sample += 0.25;
+        }
+    }
+}
+
+impl BufferConsumer for TestStallingNode {}
+
+#[test]
+fn parallel_combiner_never_drops_a_late_subtree() {
+    for threads in [0, 1, 3] {
+        let mut combiner = ParallelCombinerSource::with_threads(
+            None,
+            vec![
+                Box::new(TestStallingNode { has_stalled: false }),
+                Box::new(TestConstantNode {
+                    node_id: 1,
+                    level: 0.5,
+                }),
+                Box::new(TestConstantNode {
+                    node_id: 2,
+                    level: 0.125,
+                }),
+            ],
+            threads,
+        )
+        .unwrap();
+
+        // Whichever thread renders each subtree, and however long it takes, all are heard
+        let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+        for _ in 0..4 {
+            buffer.fill(0.0);
+            combiner.fill_buffer(&mut buffer);
+            assert!(buffer.iter().all(|sample| *sample == 0.875));
+        }
+    }
+}
+
+#[test]
+fn parallel_combiner_passes_on_every_event() {
+    let mut combiner = ParallelCombinerSource::with_threads(
+        None,
+        vec![
+            Box::new(SquareWaveSource::new(None, 0.25, 0.5)),
+            Box::new(SquareWaveSource::new(None, 0.25, 0.5)),
+        ],
+        1,
+    )
+    .unwrap();
+
+    // Far more events between two buffers than any queue would hold, ending on note off
+    for _ in 0..1000 {
+        combiner.on_event(&NodeEvent::Note {
+            note: 69,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        });
+    }
+    combiner.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOff { vel: 0.0 },
+    });
+    assert!(!combiner.is_active());
+    let mut buffer = vec![0.0; 256];
+    combiner.fill_buffer(&mut buffer);
+    assert!(buffer.iter().all(|sample| *sample == 0.0));
+}
+
+#[test]
+fn combiner_renders_in_parallel_when_configured() {
+    let config = Config::from_bytes(
+        br#"(
+            root: Combiner(
+                sources: [
+                    SquareWave(amplitude: 0.25, duty_cycle: 0.5),
+                    SquareWave(amplitude: 0.25, duty_cycle: 0.5),
+                ],
+                parallel: true,
+            ),
+        )"#,
+    )
+    .unwrap();
+    let (_, mut graph) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+    graph.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 256];
+    graph.fill_buffer(&mut buffer);
+    assert!(buffer.iter().all(|sample: &f32| sample.abs() == 0.5));
+    assert!(graph.duplicate().is_err());
+
+    // Parallel sources cannot be synced to one another
+    let config = Config::from_bytes(
+        br#"(
+            root: Combiner(
+                sources: [SquareWave(), SquareWave()],
+                hard_sync: true,
+                parallel: true,
+            ),
+        )"#,
+    )
+    .unwrap();
+    let errors = config.validation_errors(None);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, "root.Combiner.parallel");
+}
+
+// A MIDI file holding one note lasting a beat, which at 120 BPM is 24000 frames
+const ONE_NOTE_SONG: &[u8] = &[
+    b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96, b'M', b'T', b'r', b'k', 0, 0, 0, 19, 0,
+    0xff, 0x51, 3, 0x07, 0xa1, 0x20, 0, 0x90, 69, 127, 96, 0x80, 69, 64, 0, 0xff, 0x2f, 0,
+];
+
+#[test]
+fn playlist_advances_between_songs_and_loops() {
+    let song = || {
+        midi_builder_from_bytes(None, ONE_NOTE_SONG)
+            .unwrap()
+            .add_channel_source(0, Box::new(SquareWaveSource::new(None, 0.5, 0.5)))
+            .build()
+            .unwrap()
+    };
+    let render = |playlist: &mut PlaylistNode, frames: usize| {
+        let mut buffer = vec![0.0; frames * consts::CHANNEL_COUNT];
+        for chunk in buffer.chunks_mut(consts::BUFFER_SIZE * consts::CHANNEL_COUNT) {
+            playlist.fill_buffer(chunk);
+        }
+        buffer
+    };
+    let window_peak = |buffer: &[f32], start: usize, end: usize| {
+        peak_of(&buffer[start * consts::CHANNEL_COUNT..end * consts::CHANNEL_COUNT])
+    };
+
+    let mut playlist = PlaylistNode::new(None, vec![song(), song()], 0.0, false).unwrap();
+    let buffer = render(&mut playlist, 60000);
+    assert!(window_peak(&buffer, 100, 23900) > 0.4);
+    assert!(window_peak(&buffer, 24100, 47900) > 0.4);
+    assert_eq!(window_peak(&buffer, 48100, 60000), 0.0);
+    assert_eq!(playlist.current_song(), 1);
+    assert!(!playlist.is_active());
+
+    playlist.on_event(&NodeEvent::Broadcast(BroadcastControl::Stop));
+    assert_eq!(playlist.current_song(), 0);
+    assert!(playlist.is_active());
+
+    let mut looping = PlaylistNode::new(None, vec![song(), song()], 0.0, true).unwrap();
+    let buffer = render(&mut looping, 60000);
+    assert!(window_peak(&buffer, 48100, 60000) > 0.4);
+    assert_eq!(looping.current_song(), 0);
+}
+
+#[test]
+fn midi_and_one_shot_sources_report_playback_complete() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let mut midi = midi_builder_from_bytes(Some(3), ONE_NOTE_SONG)
+        .unwrap()
+        .build()
+        .unwrap();
+    midi.on_event(&NodeEvent::NodeControl {
+        node_id: 3,
+        event: NodeControlEvent::NotifyPlaybackComplete(sender.clone()),
+    });
+    let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+    for _ in 0..12 {
+        midi.fill_buffer(&mut buffer);
+    }
+    assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![3]);
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: consts::PLAYBACK_SAMPLE_RATE as u32,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut one_shot = OneShotSource::new_from_data(spec, vec![0.5; 1000], Some(4)).unwrap();
+    one_shot.set_completion_sender(sender);
+    one_shot.on_event(&NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut duplicate = one_shot.duplicate().unwrap();
+    duplicate.on_event(&NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    one_shot.fill_buffer(&mut buffer[0..1000]);
+    assert!(receiver.try_recv().is_err());
+    one_shot.fill_buffer(&mut buffer);
+    one_shot.fill_buffer(&mut buffer);
+    duplicate.fill_buffer(&mut buffer);
+    assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![4, 4]);
+}
+
+#[test]
+fn midi_playback_rate_scales_track_length() {
+    let play_until_finished = |rate: f32| {
+        let mut midi = midi_builder_from_bytes(Some(5), ONE_NOTE_SONG)
+            .unwrap()
+            .build()
+            .unwrap();
+        midi.on_event(&NodeEvent::NodeControl {
+            node_id: 5,
+            event: NodeControlEvent::PlaybackRate { rate, seconds: 0.0 },
+        });
+        let mut buffer = vec![0.0; 256 * consts::CHANNEL_COUNT];
+        let mut frames = 0;
+        while midi.is_active() && frames < 100000 {
+            midi.fill_buffer(&mut buffer);
+            frames += 256;
+        }
+        frames
+    };
+    assert!((23900..24500).contains(&play_until_finished(1.0)));
+    assert!((11900..12500).contains(&play_until_finished(2.0)));
+    assert!((47900..48500).contains(&play_until_finished(0.5)));
+}
+
+// Records the bar and tempo of each TransportPosition a MIDI source sends it
+struct TestTransportRecorder {
+    positions: Arc<Mutex<Vec<(f64, f64)>>>,
+}
+
+impl BufferConsumerNode for TestTransportRecorder {}
+
+impl Node for TestTransportRecorder {
+    fn get_node_id(&self) -> u64 {
+        0
+    }
+
+    fn on_event(&mut self, event: &NodeEvent) {
+        if let NodeEvent::Broadcast(BroadcastControl::TransportPosition { bar, bpm, .. }) = event {
+            self.positions.lock().unwrap().push((*bar, *bpm));
+        }
+    }
+
+    fn fill_buffer(&mut self, _buffer: &mut [f32]) {}
+}
+
+impl BufferConsumer for TestTransportRecorder {}
+
+#[test]
+fn midi_tempo_ramps_from_the_next_bar() {
+    // One note held for 24 beats at 120 BPM, in 4/4 and 96 ticks per beat
+    const LONG_NOTE_SONG: &[u8] = &[
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96, b'M', b'T', b'r', b'k', 0, 0, 0, 20,
+        0, 0xff, 0x51, 3, 0x07, 0xa1, 0x20, 0, 0x90, 69, 127, 0x92, 0x00, 0x80, 69, 64, 0, 0xff,
+        0x2f, 0,
+    ];
+    let positions = Arc::new(Mutex::new(vec![]));
+    let recorder = TestTransportRecorder {
+        positions: Arc::clone(&positions),
+    };
+    let mut midi = midi_builder_from_bytes(Some(8), LONG_NOTE_SONG)
+        .unwrap()
+        .add_channel_source(0, Box::new(recorder))
+        .build()
+        .unwrap();
+    let mut buffer = vec![0.0; 1024 * consts::CHANNEL_COUNT];
+    midi.fill_buffer(&mut buffer);
+    let tempo_ramp = |bpm: f32| NodeEvent::NodeControl {
+        node_id: 8,
+        event: NodeControlEvent::TempoRamp { bpm, bars: 2 },
+    };
+    midi.on_event(&tempo_ramp(240.0));
+    for buffer_index in 0..400 {
+        if !midi.is_active() {
+            break;
+        }
+        midi.fill_buffer(&mut buffer);
+
+        // Once the ramp is done, tempos that are not above zero are ignored
+        if buffer_index == 250 {
+            assert!(positions.lock().unwrap().last().unwrap().0 > 3.0);
+            for bpm in [0.0, -60.0, f32::NAN] {
+                midi.on_event(&tempo_ramp(bpm));
+            }
+        }
+    }
+    let positions = positions.lock().unwrap();
+    assert!(positions.last().unwrap().0 > 5.5);
+    for (bar, bpm) in positions.iter() {
+        match *bar {
+            bar if bar <= 1.0 => assert!((bpm - 120.0).abs() < 0.01),
+            bar if bar < 3.0 => assert!(*bpm > 120.0 && *bpm < 240.0),
+            _ => assert!((bpm - 240.0).abs() < 0.01),
+        }
+    }
+    assert!(positions.iter().any(|(bar, _)| *bar > 1.0 && *bar < 3.0));
+}
+
+#[test]
+fn midi_swing_and_humanize_delay_notes() {
+    // Two sixteenth notes on the beat and on the off-beat eighth, in 96 ticks per beat
+    const OFF_BEAT_SONG: &[u8] = &[
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96, b'M', b'T', b'r', b'k', 0, 0, 0, 27,
+        0, 0xff, 0x51, 3, 0x07, 0xa1, 0x20, 0, 0x90, 69, 127, 24, 0x80, 69, 64, 24, 0x90, 69, 127,
+        48, 0x80, 69, 64, 0, 0xff, 0x2f, 0,
+    ];
+    let render = |swing: f32, humanize_time: f32| {
+        let mut midi = midi_builder_from_bytes(None, OFF_BEAT_SONG)
+            .unwrap()
+            .add_channel_source(0, Box::new(SquareWaveSource::new(None, 0.5, 0.5)))
+            .swing(swing)
+            .humanize(humanize_time, 0.0)
+            .build()
+            .unwrap();
+        let mut buffer = vec![0.0; 24000 * consts::CHANNEL_COUNT];
+        for chunk in buffer.chunks_mut(consts::BUFFER_SIZE * consts::CHANNEL_COUNT) {
+            midi.fill_buffer(chunk);
+        }
+        buffer
+    };
+    let window_peak = |buffer: &[f32], start: usize, end: usize| {
+        peak_of(&buffer[start * consts::CHANNEL_COUNT..end * consts::CHANNEL_COUNT])
+    };
+
+    // Straight, the off-beat note starts at frame 12000; with full swing, at 18000
+    let straight = render(0.0, 0.0);
+    assert!(window_peak(&straight, 12500, 17500) > 0.4);
+    let swung = render(0.5, 0.0);
+    assert_eq!(window_peak(&swung, 9500, 17900), 0.0);
+    assert!(window_peak(&swung, 18100, 23900) > 0.4);
+
+    // Humanized notes start late by up to 2400 frames, but never early
+    let humanized = render(0.0, 0.05);
+    assert_eq!(window_peak(&humanized, 6500, 11900), 0.0);
+    assert!(window_peak(&humanized, 14500, 17900) > 0.4);
+}
+
+#[test]
+fn midi_activity_reports_sounding_notes() {
+    let mut midi = midi_builder_from_bytes(None, ONE_NOTE_SONG)
+        .unwrap()
+        .build()
+        .unwrap();
+    let activity = midi.activity();
+    let mut buffer = vec![0.0; 1000 * consts::CHANNEL_COUNT];
+    midi.fill_buffer(&mut buffer);
+    assert_eq!(
+        activity.sounding_notes(),
+        vec![SoundingNote {
+            channel: 0,
+            note: 69,
+            velocity: 1.0,
+            start_seconds: 0.0,
+        }]
+    );
+    assert!(activity.sounding_notes_on(1).is_empty());
+    assert!((activity.position_seconds() - 1000.0 / 48000.0).abs() < 1e-6);
+    for _ in 0..24 {
+        midi.fill_buffer(&mut buffer);
+    }
+    assert!(activity.sounding_notes().is_empty());
+}
+
+#[test]
+fn timed_cues_resolve_tick_beat_and_second_positions() {
+    let bytes = std::fs::read("resources/LoopingMidi.mid").unwrap();
+    let smf = midly::Smf::parse(&bytes).unwrap();
+    let cues = get_timed_cues(&smf).unwrap();
+    assert_eq!(cues.len(), 9);
+    assert_eq!(
+        cues[0],
+        TimedCue {
+            event_index: 8,
+            cue: Cue::Anchor(0),
+            tick: 3840,
+            beats: Some(4.0),
+            seconds: 2.0,
+        }
+    );
+    assert_eq!(cues[4].cue, Cue::Seek(0));
+    assert_eq!(cues[4].seconds, 10.0);
+    let midi = midi_builder_from_bytes(None, &bytes)
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_eq!(midi.timed_cues().unwrap(), cues);
+}
+
+#[cfg(feature = "midir")]
+#[test]
+fn midi_output_node_sends_timed_messages() {
+    let sent = Arc::new(std::sync::Mutex::new(vec![]));
+    let worker_sent = sent.clone();
+    let mut output = crate::MidiOutputNode::new(None, 1, 0.0, move |bytes| {
+        let mut sent = worker_sent.lock().unwrap();
+        sent.push((std::time::Instant::now(), bytes.to_vec()));
+    })
+    .unwrap();
+    output.on_event(&NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 4800 * consts::CHANNEL_COUNT];
+    output.fill_buffer(&mut buffer);
+    output.on_event(&NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOff { vel: 0.0 },
+    });
+    assert!(buffer.iter().all(|sample| *sample == 0.0));
+    drop(output);
+
+    for _ in 0..100 {
+        if sent.lock().unwrap().len() == 3 {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let sent = sent.lock().unwrap();
+    let messages: Vec<Vec<u8>> = sent.iter().map(|(_, bytes)| bytes.clone()).collect();
+    assert_eq!(
+        messages,
+        vec![vec![0x91, 60, 127], vec![0x81, 60, 0], vec![0xb1, 123, 0]]
+    );
+    assert!(sent[1].0 - sent[0].0 >= Duration::from_millis(90));
+}
+
+#[test]
+fn midi_source_follows_external_clock_tempo() {
+    // Pulses at 24 per beat and 240 BPM are 10417 microseconds apart
+    let clock = ExternalClock::new();
+    assert_eq!(clock.bpm(), None);
+    clock.receive_message(&[0xfa], 0);
+    for pulse in 0..48 {
+        clock.receive_message(&[0xf8], pulse * 10417);
+    }
+    assert!(clock.is_running());
+    assert!((clock.bpm().unwrap() - 240.0).abs() < 0.1);
+
+    let mut midi = midi_builder_from_bytes(Some(6), ONE_NOTE_SONG)
+        .unwrap()
+        .build()
+        .unwrap();
+    midi.on_event(&NodeEvent::NodeControl {
+        node_id: 6,
+        event: NodeControlEvent::FollowClock(Some(clock.clone())),
+    });
+    let mut buffer = vec![0.0; 256 * consts::CHANNEL_COUNT];
+    let mut frames = 0;
+    while midi.is_active() && frames < 100000 {
+        midi.fill_buffer(&mut buffer);
+        frames += 256;
+    }
+    assert!((11900..12500).contains(&frames));
+
+    clock.receive_message(&[0xfc], 500000);
+    assert!(!clock.is_running());
+}
+
+#[test]
+fn mod_matrix_routes_sources_to_node_controls() {
+    let routes = vec![
+        ModRoute {
+            source: ModSource::Controller(1),
+            target: ModulationTarget {
+                node_id: 7,
+                control: ParamTarget::FaderVolume,
+            },
+            depth: 0.5,
+            offset: 0.0,
+        },
+        ModRoute {
+            source: ModSource::Velocity,
+            target: ModulationTarget {
+                node_id: 7,
+                control: ParamTarget::FaderVolume,
+            },
+            depth: 0.25,
+            offset: 0.0,
+        },
+    ];
+    let square = SquareWaveSource::new(None, 1.0, 0.5);
+    let fader = Fader::new(Some(7), 0.0, Box::new(square));
+    let mut matrix = ModMatrix::new(None, routes, Box::new(fader));
+    matrix.on_event(&NodeEvent::Broadcast(BroadcastControl::Controller {
+        controller: 1,
+        value: 1.0,
+    }));
+    matrix.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 512 * consts::CHANNEL_COUNT];
+    matrix.fill_buffer(&mut buffer);
+    assert!((peak_of(&buffer) - 0.75).abs() < 0.01);
+
+    matrix.on_event(&NodeEvent::Broadcast(BroadcastControl::Controller {
+        controller: 1,
+        value: 0.0,
+    }));
+    buffer.fill(0.0);
+    matrix.fill_buffer(&mut buffer);
+    assert!((peak_of(&buffer) - 0.25).abs() < 0.01);
+}
+
+#[test]
+fn ring_mod_multiplies_source_by_carrier() {
+    let render = |mode: RingModMode, depth: f32| {
+        let square = SquareWaveSource::new(None, 0.5, 0.5);
+        let mut ring_mod = RingModNode::new(None, mode, depth, 100.0, Box::new(square));
+        ring_mod.on_event(&NodeEvent::Note {
+            note: 69,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        });
+        let mut buffer = vec![0.0; 480 * consts::CHANNEL_COUNT];
+        ring_mod.fill_buffer(&mut buffer);
+        buffer
+    };
+
+    // A 100Hz sine carrier crosses zero at frame 240, where ring modulation is silent
+    let ring = render(RingModMode::Ring, 1.0);
+    assert!(ring[240 * consts::CHANNEL_COUNT].abs() < 0.01);
+    assert!((peak_of(&ring) - 0.5).abs() < 0.01);
+    let amplitude = render(RingModMode::Amplitude, 1.0);
+    assert!((amplitude[240 * consts::CHANNEL_COUNT].abs() - 0.25).abs() < 0.01);
+    let dry = render(RingModMode::Ring, 0.0);
+    assert!(dry.iter().all(|sample| sample.abs() == 0.5));
+
+    let square = SquareWaveSource::new(None, 0.5, 0.5);
+    let mut tracked = RingModNode::new(None, RingModMode::Ring, 1.0, 0.0, Box::new(square));
+    tracked.set_note_ratio(Some(1.0));
+    tracked.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 480 * consts::CHANNEL_COUNT];
+    tracked.fill_buffer(&mut buffer);
+    assert!(peak_of(&buffer) > 0.4);
+}
+
+#[test]
+fn pitch_shift_raises_frequency_of_any_source() {
+    let crossings_per_second = |semitones: f32| {
+        let mut tone = TestSignalSource::new(Some(1));
+        tone.on_event(&NodeEvent::NodeControl {
+            node_id: 1,
+            event: NodeControlEvent::TestSignal(TestSignal::Tone {
+                frequency: 440.0,
+                level_db: 0.0,
+                channel: None,
+            }),
+        });
+        let mut shifter = PitchShiftNode::new(Some(2), 0.0, 0.0, 0.05, Box::new(tone));
+        shifter.on_event(&NodeEvent::NodeControl {
+            node_id: 2,
+            event: NodeControlEvent::PitchShift {
+                semitones,
+                cents: 0.0,
+            },
+        });
+        let mut buffer = vec![0.0; 48000 * consts::CHANNEL_COUNT];
+        for chunk in buffer.chunks_mut(consts::BUFFER_SIZE * consts::CHANNEL_COUNT) {
+            shifter.fill_buffer(chunk);
+        }
+        buffer
+            .iter()
+            .step_by(consts::CHANNEL_COUNT)
+            .skip(4800)
+            .collect::<Vec<_>>()
+            .windows(2)
+            .filter(|pair| *pair[0] < 0.0 && *pair[1] >= 0.0)
+            .count() as f32
+            / 0.9
+    };
+    let unshifted = crossings_per_second(0.0);
+    assert!((unshifted - 440.0).abs() < 5.0);
+    let octave_up = crossings_per_second(12.0);
+    assert!((octave_up - 880.0).abs() < 40.0, "{}", octave_up);
+    let fifth_down = crossings_per_second(-7.0);
+    assert!((fifth_down - 293.7).abs() < 20.0, "{}", fifth_down);
+}
+
+#[test]
+fn tremolo_and_vibrato_modulate_their_source() {
+    let square = SquareWaveSource::new(None, 0.5, 0.5);
+    let mut tremolo = TremoloNode::new(None, 10.0, 1.0, Box::new(square));
+    tremolo.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; 4800 * consts::CHANNEL_COUNT];
+    for chunk in buffer.chunks_mut(consts::BUFFER_SIZE * consts::CHANNEL_COUNT) {
+        tremolo.fill_buffer(chunk);
+    }
+    let window_peak = |buffer: &[f32], start: usize, end: usize| {
+        peak_of(&buffer[start * consts::CHANNEL_COUNT..end * consts::CHANNEL_COUNT])
+    };
+    assert!(window_peak(&buffer, 0, 100) > 0.49);
+    assert!(window_peak(&buffer, 2350, 2450) < 0.01);
+
+    // The pitch is low for the first quarter of each 2.5Hz cycle and high for the middle half
+    let mut tone = TestSignalSource::new(Some(1));
+    tone.on_event(&NodeEvent::NodeControl {
+        node_id: 1,
+        event: NodeControlEvent::TestSignal(TestSignal::Tone {
+            frequency: 440.0,
+            level_db: 0.0,
+            channel: None,
+        }),
+    });
+    let mut vibrato = VibratoNode::new(None, 2.5, 200.0, Box::new(tone));
+    let mut buffer = vec![0.0; 19200 * consts::CHANNEL_COUNT];
+    for chunk in buffer.chunks_mut(consts::BUFFER_SIZE * consts::CHANNEL_COUNT) {
+        vibrato.fill_buffer(chunk);
+    }
+    let crossings = |start: usize, end: usize| {
+        buffer[start * consts::CHANNEL_COUNT..end * consts::CHANNEL_COUNT]
+            .iter()
+            .step_by(consts::CHANNEL_COUNT)
+            .collect::<Vec<_>>()
+            .windows(2)
+            .filter(|pair| *pair[0] < 0.0 && *pair[1] >= 0.0)
+            .count()
+    };
+    assert!(crossings(0, 4800) < 43);
+    assert!(crossings(4800, 14400) > 91);
+}
+
+#[test]
+fn convolution_delays_and_scales_by_impulse_response() {
+    let tone = || {
+        let mut tone = TestSignalSource::new(Some(1));
+        tone.on_event(&NodeEvent::NodeControl {
+            node_id: 1,
+            event: NodeControlEvent::TestSignal(TestSignal::Tone {
+                frequency: 440.0,
+                level_db: 0.0,
+                channel: None,
+            }),
+        });
+        Box::new(tone)
+    };
+    let render = |node: &mut dyn BufferConsumerNode| {
+        let mut buffer = vec![0.0; 9600 * consts::CHANNEL_COUNT];
+        for chunk in buffer.chunks_mut(consts::BUFFER_SIZE * consts::CHANNEL_COUNT) {
+            node.fill_buffer(chunk);
+        }
+        buffer
+    };
+    let dry = render(tone().as_mut());
+
+    // Left is an echo at half level two frames late, right passes straight through
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 48000,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let impulse = [0.0, 1.0, 0.0, 0.0, 0.5, 0.0];
+    let mut wet = ConvolutionNode::new(None, spec, &impulse, 1.0, 0.001, tone()).unwrap();
+    let wet = render(&mut wet);
+    let latency = 256 + 48;
+    for frame in latency + 2..9600 {
+        let left = wet[frame * 2];
+        let right = wet[frame * 2 + 1];
+        assert!((left - 0.5 * dry[(frame - latency - 2) * 2]).abs() < 1e-4);
+        assert!((right - dry[(frame - latency) * 2 + 1]).abs() < 1e-4);
+    }
+
+    // The dry signal is delayed to stay in line with the convolved one
+    let mut blend = ConvolutionNode::new(None, spec, &impulse, 0.0, 0.0, tone()).unwrap();
+    let blend = render(&mut blend);
+    for frame in 256..9600 {
+        assert!((blend[frame * 2] - dry[(frame - 256) * 2]).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn gate_expander_silences_only_quiet_sources() {
+    let gated_peak = |level_db: f32| {
+        let mut tone = TestSignalSource::new(Some(1));
+        tone.on_event(&NodeEvent::NodeControl {
+            node_id: 1,
+            event: NodeControlEvent::TestSignal(TestSignal::Tone {
+                frequency: 440.0,
+                level_db,
+                channel: None,
+            }),
+        });
+        let mut expander = GateExpanderNode::new(None, 0.01, 10.0, 0.001, 0.05, Box::new(tone));
+        let mut buffer = vec![0.0; 24000 * consts::CHANNEL_COUNT];
+        for chunk in buffer.chunks_mut(consts::BUFFER_SIZE * consts::CHANNEL_COUNT) {
+            expander.fill_buffer(chunk);
+        }
+        peak_of(&buffer[4800 * consts::CHANNEL_COUNT..])
+    };
+    assert!((gated_peak(0.0) - 1.0).abs() < 0.01);
+    assert!(gated_peak(-20.0) > 0.09);
+    assert!(gated_peak(-60.0) < 1e-6);
+}
+
+#[test]
+fn gate_expander_meters_its_gain_reduction() {
+    let config = Config::from_bytes(
+        br#"(
+            root: EventReceiver(source: GateExpander(
+                node_id: Some(2),
+                threshold: 0.5,
+                ratio: 2.0,
+                attack_time: 0.0,
+                release_time: 0.0,
+                source: TestSignal(node_id: Some(1)),
+            )),
+        )"#,
+    )
+    .unwrap();
+    let (channels, mut graph) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+    assert!(crate::GateExpanderHandle::new(&channels[0], &config.root, 1).is_err());
+    let meter = crate::GateExpanderHandle::new(&channels[0], &config.root, 2)
+        .unwrap()
+        .meter()
+        .unwrap();
+    let mut render_at = |level_db: f32| {
+        channels[0]
+            .send(NodeEvent::NodeControl {
+                node_id: 1,
+                event: NodeControlEvent::TestSignal(TestSignal::Tone {
+                    frequency: 440.0,
+                    level_db,
+                    channel: None,
+                }),
+            })
+            .unwrap();
+        let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+        for _ in 0..4 {
+            buffer.fill(0.0);
+            graph.fill_buffer(&mut buffer);
+        }
+    };
+
+    // Well above the threshold, nothing is taken away
+    render_at(0.0);
+    assert!(meter.reduction_db() < 0.01);
+
+    // At a quarter of the threshold, 12 dB below it, a ratio of 2 takes away 12 dB more
+    render_at(-18.06);
+    assert!((meter.reduction_db() - 12.0).abs() < 1.5);
+    assert!(meter.take_peak_reduction_db() >= meter.reduction_db());
+
+    // The peak is held until read, and then starts again from nothing
+    render_at(0.0);
+    assert!(meter.reduction_db() < 0.01);
+    meter.take_peak_reduction_db();
+    render_at(0.0);
+    assert!(meter.take_peak_reduction_db() < 0.01);
+}
+
+#[test]
+fn gate_expander_meters_each_voice_separately() {
+    let expander_over = |level_db: f32| {
+        let mut tone = TestSignalSource::new(Some(1));
+        tone.set_signal(TestSignal::Tone {
+            frequency: 440.0,
+            level_db,
+            channel: None,
+        });
+        GateExpanderNode::new(Some(2), 0.5, 2.0, 0.0, 0.0, Box::new(tone))
+    };
+    let mut loud = expander_over(0.0);
+    let meter = loud.meter();
+
+    // Voices made from one node meter into the same meter, each in a place of its own
+    let mut quiet_template = expander_over(-18.06);
+    quiet_template.on_event(&NodeEvent::NodeControl {
+        node_id: 2,
+        event: NodeControlEvent::MeterGainReduction(meter.clone()),
+    });
+    let mut quiet = quiet_template.duplicate().unwrap();
+    assert_eq!(meter.voice_count(), 3);
+
+    let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+    for _ in 0..4 {
+        crate::take_gain_reduction();
+        loud.fill_buffer(&mut buffer);
+        quiet.fill_buffer(&mut buffer);
+    }
+    assert!(meter.voice_reduction_db(0).unwrap() < 0.01);
+    assert_eq!(meter.voice_reduction_db(1), Some(0.0));
+    assert!((meter.voice_reduction_db(2).unwrap() - 12.0).abs() < 1.5);
+    assert!(meter.voice_reduction_db(3).is_none());
+    assert_eq!(meter.reduction_db(), meter.voice_reduction_db(2).unwrap());
+
+    // The most reduction applied anywhere is reported, with no meter needed
+    assert!((crate::take_gain_reduction() - 12.0).abs() < 1.5);
+    assert_eq!(crate::take_gain_reduction(), 0.0);
+}
+
+#[test]
+fn effects_can_be_bypassed_and_blended() {
+    let mut tone = TestSignalSource::new(Some(1));
+    tone.on_event(&NodeEvent::NodeControl {
+        node_id: 1,
+        event: NodeControlEvent::TestSignal(TestSignal::Tone {
+            frequency: 440.0,
+            level_db: 0.0,
+            channel: None,
+        }),
+    });
+
+    // A ring modulator with a still carrier at zero silences its source entirely
+    let mut ring_mod = RingModNode::new(Some(2), RingModMode::Ring, 1.0, 0.0, Box::new(tone));
+    let mut peak_after = |event: NodeControlEvent| {
+        ring_mod.on_event(&NodeEvent::NodeControl { node_id: 2, event });
+        let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+        ring_mod.fill_buffer(&mut buffer);
+        peak_of(&buffer[960 * consts::CHANNEL_COUNT..])
+    };
+    assert!(peak_after(NodeControlEvent::SetWet(1.0)) < 1e-6);
+    assert!((peak_after(NodeControlEvent::SetWet(0.5)) - 0.5).abs() < 0.01);
+    assert!((peak_after(NodeControlEvent::SetBypass(true)) - 1.0).abs() < 0.01);
+    assert!((peak_after(NodeControlEvent::SetBypass(false)) - 0.5).abs() < 0.01);
+}
+
+#[test]
+fn gate_follows_midi_transport() {
+    // The gate's own tempo is half the track's, so only the track's tempo fits the checks
+    let square = SquareWaveSource::new(None, 0.5, 0.5);
+    let gate = GateNode::new(None, vec![1.0, 0.0], 4, 60.0, 0.0, 0.0, Box::new(square)).unwrap();
+    let mut midi = midi_builder_from_bytes(None, ONE_NOTE_SONG)
+        .unwrap()
+        .add_channel_source(0, Box::new(gate))
+        .build()
+        .unwrap();
+    let mut buffer = vec![0.0; 24000 * consts::CHANNEL_COUNT];
+    for chunk in buffer.chunks_mut(consts::BUFFER_SIZE * consts::CHANNEL_COUNT) {
+        midi.fill_buffer(chunk);
+    }
+    let window_peak = |start: usize, end: usize| {
+        peak_of(&buffer[start * consts::CHANNEL_COUNT..end * consts::CHANNEL_COUNT])
+    };
+    assert!(window_peak(100, 5900) > 0.4);
+    assert_eq!(window_peak(6100, 11900), 0.0);
+    assert!(window_peak(12100, 17900) > 0.4);
+    assert_eq!(window_peak(18100, 23900), 0.0);
+}
+
+#[test]
+#[cfg(feature = "driver-cpal")]
+fn note_off_releases_only_the_voice_with_its_id() {
+    let mut font = SoundFontBuilder::new(None)
+        .add_range(
+            NoteRange::new_full_range(),
+            Box::new(Envelope::from_adsr(
+                None,
+                0.0,
+                0.0,
+                1.0,
+                0.01,
+                Box::new(SquareWaveSource::new(None, 0.25, 0.5)),
+            )),
+        )
+        .unwrap()
+        .build();
+    for voice_id in [1, 2] {
+        font.on_event(&NodeEvent::Note {
+            note: 69,
+            voice_id: Some(voice_id),
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        });
+    }
+    font.on_event(&NodeEvent::Note {
+        note: 69,
+        voice_id: Some(1),
+        event: NoteEvent::NoteOff { vel: 0.0 },
+    });
+    let mut buffer = vec![0.0; 4096];
+    font.fill_buffer(&mut buffer);
+    buffer.fill(0.0);
+    font.fill_buffer(&mut buffer);
+    assert!(buffer.iter().any(|sample| sample.abs() > 0.2));
+
+    font.on_event(&NodeEvent::note_off(69, 0.0).with_voice_id(2));
+    buffer.fill(0.0);
+    font.fill_buffer(&mut buffer);
+    buffer.fill(0.0);
+    font.fill_buffer(&mut buffer);
+    assert!(buffer.iter().all(|sample| *sample == 0.0));
+}
+
+#[test]
+fn font_makes_voices_up_to_its_limit_and_steals_beyond() {
+    // Every voice plays a rising ramp, so a voice that was restarted plays lower
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: consts::PLAYBACK_SAMPLE_RATE as u32,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let ramp: Vec<f32> = (1..=48000).map(|frame| frame as f32 * 1e-5).collect();
+    let voice = OneShotSource::new_from_data(spec, ramp, None).unwrap();
+    let font_with = |voice_stealing: VoiceStealing| {
+        SoundFontBuilder::new(Some(4))
+            .with_voice_stealing(voice_stealing)
+            .add_range(NoteRange::new_full_range(), voice.duplicate().unwrap())
+            .unwrap()
+            .build()
+    };
+    let render = |font: &mut SoundFont| {
+        let mut buffer = vec![0.0; 1000 * consts::CHANNEL_COUNT];
+        font.fill_buffer(&mut buffer);
+        buffer[0]
+    };
+
+    // Twelve notes sound at once once the font holds twelve voices, rather than eight
+    let mut font = font_with(VoiceStealing::None);
+    assert!(font.set_max_voices(0).is_err());
+    font.set_max_voices(12).unwrap();
+    for note in 40..60 {
+        font.on_event(&NodeEvent::note_on(note, 1.0));
+    }
+    let one_voice = 1e-5;
+    assert!((render(&mut font) - 12.0 * one_voice).abs() < 1e-6);
+
+    // A limit above the voices held cannot be met on the audio thread, and is ignored
+    let max_voices = |count: usize| NodeEvent::NodeControl {
+        node_id: 4,
+        event: NodeControlEvent::MaxVoices(count),
+    };
+    let play_third_note = |font: &mut SoundFont| {
+        font.on_event(&NodeEvent::Broadcast(BroadcastControl::NotesOff));
+        font.on_event(&max_voices(100));
+        font.on_event(&max_voices(2));
+        font.on_event(&NodeEvent::note_on(60, 1.0));
+        font.on_event(&NodeEvent::note_on(62, 1.0));
+        render(font);
+        font.on_event(&NodeEvent::note_on(64, 1.0));
+        render(font)
+    };
+
+    // Without stealing the third note is dropped; otherwise it restarts the oldest voice
+    let both_continue = 2.0 * 1001.0 * one_voice;
+    let one_restarted = 1001.0 * one_voice + one_voice;
+    assert!((play_third_note(&mut font) - both_continue).abs() < 1e-6);
+    let mut font = font_with(VoiceStealing::Oldest);
+    assert!((play_third_note(&mut font) - one_restarted).abs() < 1e-6);
+}
+
+#[test]
+fn type_2_midi_sequences_play_independently() {
+    // Two sequences of one note each, the second at twice the tempo of the first
+    const TWO_SEQUENCE_SONG: &[u8] = &[
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 2, 0, 2, 0, 96, b'M', b'T', b'r', b'k', 0, 0, 0, 19,
+        0, 0xff, 0x51, 3, 0x07, 0xa1, 0x20, 0, 0x90, 69, 127, 96, 0x80, 69, 64, 0, 0xff, 0x2f, 0,
+        b'M', b'T', b'r', b'k', 0, 0, 0, 19, 0, 0xff, 0x51, 3, 0x03, 0xd0, 0x90, 0, 0x90, 64, 127,
+        96, 0x80, 64, 64, 0, 0xff, 0x2f, 0,
+    ];
+    let smf = midly::Smf::parse(TWO_SEQUENCE_SONG).unwrap();
+    assert_eq!(get_sequence_count(&smf), 2);
+    let play_until_finished = |sequence_index: usize| {
+        let mut midi = midi_sequence_builder_from_bytes(None, TWO_SEQUENCE_SONG, sequence_index)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut buffer = vec![0.0; 256 * consts::CHANNEL_COUNT];
+        let mut frames = 0;
+        while midi.is_active() && frames < 100000 {
+            midi.fill_buffer(&mut buffer);
+            frames += 256;
+        }
+        frames
+    };
+    assert!((23900..24500).contains(&play_until_finished(0)));
+    assert!((11900..12500).contains(&play_until_finished(1)));
+    assert!(midi_sequence_builder_from_bytes(None, TWO_SEQUENCE_SONG, 2).is_err());
+    assert!(midi_sequence_builder_from_bytes(None, ONE_NOTE_SONG, 1).is_err());
+}
+
+#[test]
+fn midi_source_follows_time_signature_changes() {
+    // Two bars of 3/4 followed by a bar of 2/4, at 96 ticks per beat
+    const CHANGING_METER_SONG: &[u8] = &[
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96, b'M', b'T', b'r', b'k', 0, 0, 0, 37,
+        0, 0xff, 0x51, 3, 0x07, 0xa1, 0x20, 0, 0xff, 0x58, 4, 3, 2, 24, 8, 0, 0x90, 69, 127, 0x84,
+        0x40, 0xff, 0x58, 4, 2, 2, 24, 8, 0x81, 0x40, 0x80, 69, 64, 0, 0xff, 0x2f, 0,
+    ];
+    let mut midi = midi_builder_from_bytes(None, CHANGING_METER_SONG)
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_eq!(midi.ticks_per_beat(), Some(96.0));
+    let three_four = TimeSignature {
+        numerator: 3,
+        denominator: 4,
+    };
+    assert_eq!(midi.time_signature(), Some(three_four));
+    assert_eq!(three_four.quarter_notes_per_bar(), 3.0);
+
+    // Two seconds is four beats in, still in the first meter
+    let mut buffer = vec![0.0; 24000 * consts::CHANNEL_COUNT];
+    for _ in 0..4 {
+        midi.fill_buffer(&mut buffer);
+    }
+    assert_eq!(midi.time_signature(), Some(three_four));
+    for _ in 0..4 {
+        midi.fill_buffer(&mut buffer);
+    }
+    assert_eq!(
+        midi.time_signature(),
+        Some(TimeSignature {
+            numerator: 2,
+            denominator: 4,
+        })
+    );
+}
+
+#[test]
+fn oscillators_reset_phase_and_hard_sync() {
+    let note_on = |note: u8| NodeEvent::Note {
+        note,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    };
+    let mut buffer = vec![0.0; 2048 * consts::CHANNEL_COUNT];
+
+    // Each note starts its waveform over, however far the last one had reached
+    let mut saw = SawtoothWaveSource::new(None, 0.5);
+    saw.set_phase_reset(true);
+    saw.on_event(&note_on(69));
+    saw.fill_buffer(&mut buffer[0..200]);
+    let first_start = buffer[0];
+    buffer.fill(0.0);
+    saw.on_event(&note_on(69));
+    saw.fill_buffer(&mut buffer[0..2]);
+    assert_eq!(buffer[0], first_start);
+
+    // The follower restarts whenever the leader begins a cycle, at 110 Hz
+    let mut leader = SquareWaveSource::new(None, 0.5, 0.5);
+    let mut follower = SawtoothWaveSource::new(None, 0.5);
+    leader.on_event(&note_on(45));
+    follower.on_event(&note_on(76));
+    buffer.fill(0.0);
+    leader.fill_buffer(&mut buffer);
+    let cycle_starts = leader.cycle_starts().to_vec();
+    assert!((4..=5).contains(&cycle_starts.len()));
+    buffer.fill(0.0);
+    follower.sync_to(&cycle_starts);
+    follower.fill_buffer(&mut buffer);
+    for start in cycle_starts {
+        let frame = start.ceil() as usize;
+        assert!(buffer[frame * consts::CHANNEL_COUNT] < -0.45);
+    }
+    assert!(follower.cycle_starts().len() > 5);
+}
+
+#[test]
+fn chip_drums_sound_briefly_and_die_away() {
+    let drum_peaks = |kind: &str| {
+        let text = format!("(root: ChipDrum(kind: {}, amplitude: 0.5))", kind);
+        let config = Config::from_bytes(text.as_bytes()).unwrap();
+        let (_, mut drum) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+        drum.on_event(&NodeEvent::Note {
+            note: 48,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        });
+        let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+        (0..20)
+            .map(|_| {
+                buffer.fill(0.0);
+                drum.fill_buffer(&mut buffer);
+                peak_of(&buffer)
+            })
+            .collect::<Vec<f32>>()
+    };
+    for kind in ["Kick", "Snare", "Hat"] {
+        let peaks = drum_peaks(kind);
+        assert!(peaks[0] > 0.1, "{} is silent", kind);
+        assert!(peaks[0] <= 0.5, "{} is too loud", kind);
+        assert!(peaks[19] < 0.01, "{} does not die away", kind);
+    }
+
+    // Hats are over well before kicks, within about a tenth of a second
+    assert!(drum_peaks("Hat")[2] < 0.01);
+    assert!(drum_peaks("Kick")[2] > 0.05);
+}
+
+#[test]
+fn tunings_follow_scala_files_and_cents_tables() {
+    let cents_between = |a: f32, b: f32| 1200.0 * (b / a).log2();
+
+    // Five equal steps to the octave, run up the keys from middle C without a mapping
+    let scl = b"! five.scl\nFive-tone equal temperament\n 5\n!\n240.0\n480.0\n720.0\n960.0\n2/1\n";
+    let tuning = tuning_from_scala_bytes(scl, None).unwrap();
+    assert!((tuning.frequency_of(60) - 261.6256).abs() < 0.01);
+    assert!((cents_between(tuning.frequency_of(60), tuning.frequency_of(61)) - 240.0).abs() < 0.01);
+    assert!(
+        (cents_between(tuning.frequency_of(60), tuning.frequency_of(65)) - 1200.0).abs() < 0.01
+    );
+    assert!(
+        (cents_between(tuning.frequency_of(55), tuning.frequency_of(60)) - 1200.0).abs() < 0.01
+    );
+
+    // A mapping retunes A to 432 Hz and leaves C# out, which keeps its usual pitch
+    let scl =
+        b"12-TET\n12\n100.\n200.\n300.\n400.\n500.\n600.\n700.\n800.\n900.\n1000.\n1100.\n2/1\n";
+    let kbm = b"12\n0\n127\n60\n69\n432.0\n12\n0\nx\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n";
+    let tuning = tuning_from_scala_bytes(scl, Some(kbm)).unwrap();
+    assert!((tuning.frequency_of(69) - 432.0).abs() < 0.01);
+    assert!((tuning.frequency_of(57) - 216.0).abs() < 0.01);
+    assert!((tuning.frequency_of(61) - util::frequency_of(61)).abs() < 0.01);
+    assert!(tuning_from_scala_bytes(b"Empty\n0\n", None).is_err());
+
+    // Cents tables repeat every octave when given for 12 keys
+    let mut cents = [0.0; 12];
+    cents[4] = -13.7;
+    let tuning = TuningTable::from_cents(&cents).unwrap();
+    assert!((cents_between(util::frequency_of(76), tuning.frequency_of(76)) + 13.7).abs() < 0.01);
+    assert_eq!(tuning.frequency_of(77), util::frequency_of(77));
+    assert!(TuningTable::from_cents(&[0.0; 7]).is_err());
+
+    // Generators in a Tuned source play its tuning, ignoring any tuning from further out
+    let loader = MemoryAssetLoader::new().with_static(
+        "five.scl",
+        b"Five-tone equal temperament\n5\n240.0\n480.0\n720.0\n960.0\n2/1\n",
+    );
+    let text = "(root: Tuned(tuning: Cents([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]), \
+        source: Tuned(tuning: ScalaFilePath(scl: \"five.scl\"), source: SquareWave())))";
+    let config = Config::from_bytes(text.as_bytes()).unwrap();
+    config.validate(Some(&loader)).unwrap();
+    assert_eq!(asset_paths(&config.root), vec!["five.scl".to_owned()]);
+    let (_, mut source) = loader.load_source_recursive(&config.root).unwrap();
+    source.on_event(&NodeEvent::Note {
+        note: 65,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    });
+    let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+    source.fill_buffer(&mut buffer);
+    let rises = buffer
+        .chunks_exact(consts::CHANNEL_COUNT)
+        .collect::<Vec<_>>()
+        .windows(2)
+        .filter(|frames| frames[0][0] <= 0.0 && frames[1][0] > 0.0)
+        .count();
+
+    // An octave above middle C makes about 22 cycles in the buffer, where F would make 15
+    assert!((21..=23).contains(&rises), "{} cycles", rises);
+}
+
+#[test]
+fn transpose_and_master_tune_shift_midi_and_fonts() {
+    let cycles_in = |buffer: &[f32]| {
+        buffer
+            .chunks_exact(consts::CHANNEL_COUNT)
+            .collect::<Vec<_>>()
+            .windows(2)
+            .filter(|frames| frames[0][0] <= 0.0 && frames[1][0] > 0.0)
+            .count()
+    };
+    let control = |node_id: u64, event: NodeControlEvent| NodeEvent::NodeControl { node_id, event };
+    let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+
+    // The song's A440 is played an octave down, then back up by a master tune of an octave
+    // once the transpose is undone, which ends the note all the same
+    let mut midi = midi_builder_from_bytes(Some(3), ONE_NOTE_SONG)
+        .unwrap()
+        .add_channel_source(0, Box::new(SquareWaveSource::new(None, 0.5, 0.5)))
+        .build()
+        .unwrap();
+    midi.on_event(&control(3, NodeControlEvent::Transpose(-12)));
+    midi.fill_buffer(&mut buffer);
+    assert!((9..=10).contains(&cycles_in(&buffer)));
+    midi.on_event(&control(3, NodeControlEvent::Transpose(0)));
+    midi.on_event(&control(3, NodeControlEvent::MasterTune(1200.0)));
+    buffer.fill(0.0);
+    midi.fill_buffer(&mut buffer);
+    assert!((18..=19).contains(&cycles_in(&buffer)));
+    for _ in 0..11 {
+        midi.fill_buffer(&mut buffer);
+    }
+    assert!(!midi.is_active());
+
+    // A font chooses its range by the transposed note, dropping notes beyond the keyboard
+    let text = "(root: Font(node_id: Some(5), config: Ranges([
+        (source: SquareWave(), lower: 0, upper: 60),
+        (source: SquareWave(duty_cycle: 0.75), lower: 61, upper: 127),
+    ])))";
+    let config = Config::from_bytes(text.as_bytes()).unwrap();
+    let (_, mut font) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+    let note = |note: u8, event: NoteEvent| NodeEvent::Note {
+        note,
+        voice_id: None,
+        event,
+    };
+    font.on_event(&control(5, NodeControlEvent::Transpose(12)));
+    font.on_event(&note(120, NoteEvent::NoteOn { vel: 1.0 }));
+    assert!(!font.is_active());
+    font.on_event(&note(57, NoteEvent::NoteOn { vel: 1.0 }));
+    buffer.fill(0.0);
+    font.fill_buffer(&mut buffer);
+    assert!((18..=19).contains(&cycles_in(&buffer)));
+    let high = buffer.iter().filter(|sample| **sample > 0.0).count();
+    assert!(high < buffer.len() / 3);
+    font.on_event(&control(5, NodeControlEvent::Transpose(0)));
+    font.on_event(&note(57, NoteEvent::NoteOff { vel: 1.0 }));
+    assert!(!font.is_active());
+}
+
+#[test]
+fn font_ranges_crossfade_across_their_split() {
+    let level_of = |lower_amplitude: f32, upper_amplitude: f32, crossfade: u8, note: u8| {
+        let config = font(|f| {
+            f.range(..51, square(lower_amplitude, 0.5))
+                .range(51.., square(upper_amplitude, 0.5))
+                .crossfade(crossfade)
+        });
+        let (_, mut font) = FileGraphLoader.load_source_recursive(&config).unwrap();
+        font.on_event(&NodeEvent::Note {
+            note,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        });
+        let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+        font.fill_buffer(&mut buffer);
+        buffer
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+    };
+
+    // The last note of the lower range sounds in both, at levels that keep its power
+    let lower = level_of(0.5, 0.0, 4, 50) / 0.5;
+    let upper = level_of(0.0, 0.5, 4, 50) / 0.5;
+    assert!(lower > 0.7 && upper > 0.4 && upper < lower);
+    assert!((lower * lower + upper * upper - 1.0).abs() < 0.01);
+
+    // Notes clear of the crossfade, or in a font without one, play in one range only
+    assert!((level_of(0.5, 0.0, 4, 40) - 0.5).abs() < 0.01);
+    assert_eq!(level_of(0.0, 0.5, 4, 40), 0.0);
+    assert!((level_of(0.5, 0.0, 0, 50) - 0.5).abs() < 0.01);
+    assert_eq!(level_of(0.0, 0.5, 0, 50), 0.0);
+}
+
+#[test]
+fn font_release_layers_sound_as_notes_end() {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: consts::PLAYBACK_SAMPLE_RATE as u32,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let release_peaks = |level: f32| {
+        let release = OneShotSource::new_from_data(spec, vec![0.5; 1000], None).unwrap();
+        let mut font = SoundFontBuilder::new(None)
+            .add_range_with_release(
+                NoteRange::new_full_range(),
+                Box::new(SquareWaveSource::new(None, 0.0, 0.5)),
+                Box::new(release),
+                level,
+            )
+            .unwrap()
+            .build();
+        let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+        let mut peaks = vec![];
+        for event in [
+            NoteEvent::NoteOn { vel: 1.0 },
+            NoteEvent::NoteOff { vel: 0.0 },
+        ] {
+            font.on_event(&NodeEvent::Note {
+                note: 60,
+                voice_id: None,
+                event,
+            });
+            buffer.fill(0.0);
+            font.fill_buffer(&mut buffer);
+            peaks.push(peak_of(&buffer));
+        }
+        buffer.fill(0.0);
+        font.fill_buffer(&mut buffer);
+        assert!(!font.is_active());
+        peaks
+    };
+
+    // Silent while the note is held, then the release plays through at its own level
+    let full = release_peaks(1.0);
+    let half = release_peaks(0.5);
+    assert_eq!(full[0], 0.0);
+    assert!(full[1] > 0.1);
+    assert!((half[1] - full[1] * 0.5).abs() < 0.001);
+
+    // Ranges in configs take a release source too
+    let text = "(root: Font(config: Ranges([(
+        source: SquareWave(),
+        lower: 0,
+        upper: 127,
+        release: Some((source: ChipDrum(kind: Kick))),
+    )])))";
+    let config = Config::from_bytes(text.as_bytes()).unwrap();
+    assert!(FileGraphLoader.load_source_recursive(&config.root).is_ok());
+}
+
+#[test]
+fn sample_loop_modes_loop_while_held_or_continuously() {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: consts::PLAYBACK_SAMPLE_RATE as u32,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let note = |event: NoteEvent| NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event,
+    };
+    let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+
+    // Activity after the note has been held through several buffers, then after release
+    let mut activity = |mode: LoopMode| {
+        let loop_range = LoopRange::new_frame_range(100, 200);
+        let mut source =
+            WavSource::new_from_data(spec, 69, vec![0.5; 1000], Some(loop_range), None).unwrap();
+        source.set_loop_mode(mode);
+        source.on_event(&note(NoteEvent::NoteOn { vel: 1.0 }));
+        for _ in 0..4 {
+            source.fill_buffer(&mut buffer);
+        }
+        let held = source.is_active();
+        source.on_event(&note(NoteEvent::NoteOff { vel: 1.0 }));
+        source.fill_buffer(&mut buffer);
+        let released = source.is_active();
+        source.on_event(&NodeEvent::Broadcast(BroadcastControl::Stop));
+        source.fill_buffer(&mut buffer);
+        assert!(!source.is_active());
+        (held, released)
+    };
+    assert_eq!(activity(LoopMode::Off), (false, false));
+    assert_eq!(activity(LoopMode::UntilRelease), (true, false));
+    assert_eq!(activity(LoopMode::Continuous), (true, true));
+
+    let config = Config::from_bytes(
+        b"(root: SampleFilePath(path: \"a.wav\", base_note: 60, looping: Some((start: 0, end: 8))))",
+    )
+    .unwrap();
+    let SoundSource::SampleFilePath {
+        looping: Some(looping),
+        ..
+    } = &config.root
+    else {
+        panic!("Expected a looping sample");
+    };
+    assert_eq!(looping.mode, LoopMode::UntilRelease);
+}
+
+#[test]
+fn one_shots_overlap_restart_and_choke_each_other() {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: consts::PLAYBACK_SAMPLE_RATE as u32,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let note_on = NodeEvent::Note {
+        note: 60,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    };
+    let one_shot = |retrigger: Retrigger, choke_group: Option<ChokeGroup>| {
+        let mut source = OneShotSource::new_from_data(spec, vec![0.25; 3000], None).unwrap();
+        source.set_retrigger(retrigger);
+        source.set_choke_group(choke_group);
+        source
+    };
+    let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+
+    // Overlapping retriggers sound together, where restarting ones replace each other
+    let mut peaks = vec![];
+    for retrigger in [Retrigger::Restart, Retrigger::Overlap] {
+        let mut source = one_shot(retrigger, None);
+        source.on_event(&note_on);
+        source.fill_buffer(&mut buffer);
+        source.on_event(&note_on);
+        buffer.fill(0.0);
+        source.fill_buffer(&mut buffer);
+        peaks.push(peak_of(&buffer));
+    }
+    assert_eq!(peaks, vec![0.25, 0.5]);
+
+    // A closed hat cuts off an open one in its group, whether set up in code or by event
+    let mut open_hat = one_shot(Retrigger::Overlap, None);
+    let group = ChokeGroup::new();
+    open_hat.on_event(&NodeEvent::NodeControl {
+        node_id: open_hat.get_node_id(),
+        event: NodeControlEvent::ChokeGroup(Some(group.clone())),
+    });
+    let mut closed_hat = one_shot(Retrigger::Restart, Some(group));
+    open_hat.on_event(&note_on);
+    open_hat.fill_buffer(&mut buffer);
+    closed_hat.on_event(&note_on);
+    buffer.fill(0.0);
+    open_hat.fill_buffer(&mut buffer);
+    assert!(!open_hat.is_active());
+    assert_eq!(peak_of(&buffer), 0.0);
+    closed_hat.fill_buffer(&mut buffer);
+    assert!(closed_hat.is_active());
+
+    // Groups named in configs are shared between the one-shots naming them
+    let text =
+        "(root: OneShotInline(data: Bytes([]), retrigger: Overlap, choke_group: Some(\"hats\")))";
+    let config = Config::from_bytes(text.as_bytes()).unwrap();
+    let SoundSource::OneShotInline {
+        retrigger,
+        choke_group,
+        ..
+    } = &config.root
+    else {
+        panic!("Expected a one-shot");
+    };
+    assert_eq!(*retrigger, Retrigger::Overlap);
+    assert_eq!(choke_group.as_deref(), Some("hats"));
+    let mut first = one_shot(Retrigger::Restart, Some(ChokeGroup::named("hats")));
+    first.on_event(&note_on);
+    let mut second = one_shot(Retrigger::Restart, Some(ChokeGroup::named("hats")));
+    second.on_event(&note_on);
+    first.fill_buffer(&mut buffer);
+    assert!(!first.is_active());
+}
+
+#[test]
+fn sample_playback_varies_in_pitch_and_level() {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: consts::PLAYBACK_SAMPLE_RATE as u32,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let note_on = NodeEvent::Note {
+        note: 69,
+        voice_id: None,
+        event: NoteEvent::NoteOn { vel: 1.0 },
+    };
+    let variation = Variation {
+        cents: 100.0,
+        decibels: 6.0,
+    };
+
+    // Level and length of each playback of a short one-shot and a sample
+    let mut one_shot = OneShotSource::new_from_data(spec, vec![0.25; 1000], None).unwrap();
+    one_shot.set_variation(variation);
+    let mut sample = WavSource::new_from_data(spec, 69, vec![0.25; 1000], None, None).unwrap();
+    sample.set_variation(variation);
+    let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+    let mut playbacks = |source: &mut dyn BufferConsumerNode| {
+        (0..8)
+            .map(|_| {
+                source.on_event(&note_on);
+                buffer.fill(0.0);
+                source.fill_buffer(&mut buffer);
+                let frames = buffer.iter().step_by(2).filter(|s| **s != 0.0).count();
+                (peak_of(&buffer), frames)
+            })
+            .collect::<Vec<_>>()
+    };
+    for results in [playbacks(&mut one_shot), playbacks(&mut sample)] {
+        for (peak, frames) in results.iter() {
+            assert!((0.125..=0.5).contains(peak), "peak {}", peak);
+            assert!((940..=1060).contains(frames), "{} frames", frames);
+        }
+        assert!(results.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+
+    // Playback is unchanged without variation
+    let mut plain = OneShotSource::new_from_data(spec, vec![0.25; 1000], None).unwrap();
+    assert!(playbacks(&mut plain)
+        .iter()
+        .all(|result| *result == (0.25, 1000)));
+}
+
+#[test]
+fn generator_gain_scales_output_after_volume() {
+    let peak_with = |gain: &str| {
+        let text = format!(
+            "(root: SquareWave(node_id: Some(7), amplitude: 0.5{}))",
+            gain
+        );
+        let config = Config::from_bytes(text.as_bytes()).unwrap();
+        let (_, mut source) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+        assert_eq!(source.get_node_id(), 7);
+        source.on_event(&NodeEvent::NodeControl {
+            node_id: 7,
+            event: NodeControlEvent::Volume(0.25),
+        });
+        source.on_event(&NodeEvent::Note {
+            note: 69,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        });
+        let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+        source.fill_buffer(&mut buffer);
+        peak_of(&buffer)
+    };
+
+    // Gain applies on top of any volume set by events, and leaves the node's ID alone
+    let plain = peak_with("");
+    assert!((plain - 0.25).abs() < 0.01);
+    assert!((peak_with(", gain: -6.0206") - plain * 0.5).abs() < 0.001);
+    assert!((peak_with(", gain: 6.0206") - plain * 2.0).abs() < 0.001);
+}
+
+#[cfg(feature = "alloc-audit")]
+#[global_allocator]
+static ALLOCATOR: crate::audit::AuditAllocator = crate::audit::AuditAllocator;
+
+#[test]
+#[cfg(feature = "alloc-audit")]
+fn rendering_a_graph_makes_no_allocations() {
+    let config = midi_with_sf2_config(MIDI_FILE, "resources/demo-font.sf2", 0).unwrap();
+    let (_, midi) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+    let config = FileGraphLoader
+        .config_from_file("resources/example.ron")
+        .unwrap();
+    let (_, example) = FileGraphLoader.load_source_recursive(&config.root).unwrap();
+    let note = SequencerStep::Note {
+        note: 69,
+        velocity: 1.0,
+        gate: 0.5,
+    };
+    let steps = vec![note, SequencerStep::Rest, note, note];
+    let voice = Box::new(TriangleWaveSource::new(None, 0.5));
+    let sequencer = SequencerSource::new(None, 480.0, 4, 0.25, true, steps, voice).unwrap();
+    let fader = Fader::new(
+        Some(7),
+        1.0,
+        Box::new(CombinerSource::new(
+            None,
+            vec![midi, example, Box::new(sequencer)],
+        )),
+    );
+
+    // A fade chain sent to a tag reaches the fader through the channel and the tag binding
+    let tagged = crate::TagBinding::new(vec![tag_id("music")], Box::new(fader));
+    let (channel, mut graph) = crate::AsyncEventReceiver::new(None, Box::new(tagged));
+    channel
+        .send(NodeEvent::NodeControl {
+            node_id: tag_id("music"),
+            event: NodeControlEvent::FadeChain(
+                FadeChain::new([
+                    FadeStep::Fade {
+                        from: 1.0,
+                        to: 0.5,
+                        seconds: 0.5,
+                    },
+                    FadeStep::Broadcast(BroadcastControl::NotesOff),
+                    FadeStep::Fade {
+                        from: 0.5,
+                        to: 1.0,
+                        seconds: 0.5,
+                    },
+                ])
+                .unwrap(),
+            ),
+        })
+        .unwrap();
+    let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+
+    crate::audit::set_audit_mode(crate::audit::AuditMode::Log);
+    let rendering = crate::audit::rendering_scope();
+    for _ in 0..100 {
+        buffer.fill(0.0);
+        graph.fill_buffer(&mut buffer);
+    }
+    let allocations = rendering.allocations();
+    drop(rendering);
+    assert_eq!(allocations, 0);
+    assert!(peak_of(&buffer) > 0.0);
+}
+
+#[test]
+fn effect_tails_ring_out_inside_a_combiner() {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: consts::PLAYBACK_SAMPLE_RATE as u32,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let one_shot = || Box::new(OneShotSource::new_from_data(spec, vec![0.5; 2000], None).unwrap());
+
+    // An echo a tenth of a second late, and grains reading up to 50ms behind, both sound
+    // after the one-shot has finished in the first buffer
+    let mut impulse = vec![0.0; 4801];
+    impulse[0] = 1.0;
+    impulse[4800] = 1.0;
+    let reverb = ConvolutionNode::new(None, spec, &impulse, 0.5, 0.0, one_shot()).unwrap();
+    let shifter = PitchShiftNode::new(None, 0.0, 0.0, 0.05, one_shot());
+    let effects: [Box<dyn BufferConsumerNode + Send + 'static>; 2] =
+        [Box::new(reverb), Box::new(shifter)];
+    for effect in effects {
+        let mut combiner = CombinerSource::new(None, vec![effect]);
+        combiner.on_event(&NodeEvent::Note {
+            note: 69,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel: 1.0 },
+        });
+        let mut buffer = vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT];
+        let mut later_peak: f32 = 0.0;
+        for block in 0..24 {
+            buffer.fill(0.0);
+            combiner.fill_buffer(&mut buffer);
+            if block > 0 {
+                later_peak = later_peak.max(peak_of(&buffer));
+            }
+        }
+        assert!(later_peak > 0.1, "{}", later_peak);
+        assert!(!combiner.is_active());
+    }
+}
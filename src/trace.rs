@@ -0,0 +1,47 @@
+// Logging used throughout the crate. With the tracing feature enabled, messages become
+// tracing events and spans that applications can route, filter and silence through their
+// own subscriber; without it, messages are printed to stdout as they always have been.
+
+macro_rules! log_info {
+    ($category:literal, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::info!(category = $category, $($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        println!(concat!($category, ": {}"), format_args!($($arg)+));
+    }};
+}
+
+macro_rules! log_warning {
+    ($category:literal, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::warn!(category = $category, $($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        println!(concat!("WARNING: ", $category, ": {}"), format_args!($($arg)+));
+    }};
+}
+
+macro_rules! log_error {
+    ($category:literal, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::error!(category = $category, $($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        println!(concat!("ERROR: ", $category, ": {}"), format_args!($($arg)+));
+    }};
+}
+
+// Enter a span lasting until the returned guard is dropped; does nothing without tracing
+macro_rules! trace_span {
+    ($name:literal $(, $($field:tt)+)?) => {{
+        #[cfg(feature = "tracing")]
+        let guard = $crate::trace::SpanGuard(tracing::info_span!($name $(, $($field)+)?).entered());
+        #[cfg(not(feature = "tracing"))]
+        let guard = $crate::trace::SpanGuard;
+        guard
+    }};
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) struct SpanGuard(#[allow(dead_code)] pub(crate) tracing::span::EnteredSpan);
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct SpanGuard;
@@ -1,20 +1,17 @@
-use crate::{util::midi_builder_from_bytes, BaseMixer, MidiSource, SquareWaveSource};
-use cpal::traits::StreamTrait;
-use std::time::Duration;
+use crate::{util::midi_builder_from_bytes, SquareWaveSource, WorkletRenderer};
 use wasm_bindgen::prelude::*;
 
-const MIDI_FILE: &'static [u8] = include_bytes!("../resources/dansenapolitaine.mid");
+const MIDI_FILE: &[u8] = include_bytes!("../resources/sample-in-c.mid");
 
 #[wasm_bindgen]
-pub fn play_stream() {
-    let midi_source = midi_builder_from_bytes(None, MIDI_FILE)
-        .unwrap()
-        .add_channel_source(0, Box::new(SquareWaveSource::new(None, 0.25, 0.125)))
-        .build()
-        .unwrap();
-    let mixer = BaseMixer::from_source(midi_source);
-    let stream = mixer.open_stream().expect("Could not open stream");
-    stream.play().expect("Could not play the stream");
-    std::thread::sleep(Duration::from_secs(5));
-    stream.pause().expect("Could not pause the stream");
+impl WorkletRenderer {
+    /// Demo graph, playing an embedded MIDI file with a square wave.
+    pub fn demo() -> Result<WorkletRenderer, JsError> {
+        let midi_source = midi_builder_from_bytes(None, MIDI_FILE)
+            .map_err(|e| JsError::new(&e.to_string()))?
+            .add_channel_source(0, Box::new(SquareWaveSource::new(None, 0.25, 0.125)))
+            .build()
+            .map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(Self::new(Box::new(midi_source)))
+    }
 }
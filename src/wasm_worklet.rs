@@ -0,0 +1,147 @@
+use crate::{
+    consts, BroadcastControl, BufferConsumerNode, Config, ConfigFormat, GraphLoader, HostEvent,
+    MemoryAssetLoader, NodeControlEvent, NodeEvent, NodeNames, NoteEvent,
+};
+use wasm_bindgen::prelude::*;
+
+/// Assets for a graph built inside a worklet, keyed by the paths its config uses. The
+/// worklet cannot fetch, so the main thread fetches them and passes the bytes across.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct WorkletAssets {
+    loader: MemoryAssetLoader,
+}
+
+#[wasm_bindgen]
+impl WorkletAssets {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WorkletAssets {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: &str, bytes: Vec<u8>) {
+        self.loader.insert(path, bytes);
+    }
+}
+
+/// Renders a graph inside an AudioWorkletProcessor, so that audio never blocks or waits on
+/// the main thread. The processor owns the renderer, calls render for each render quantum,
+/// and applies control events posted through its MessagePort using the event methods.
+/// The AudioContext must run at the crate's playback sample rate.
+#[wasm_bindgen]
+pub struct WorkletRenderer {
+    consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+    names: NodeNames,
+    buffer: Vec<f32>,
+}
+
+impl WorkletRenderer {
+    pub fn new(consumer: Box<dyn BufferConsumerNode + Send + 'static>) -> Self {
+        Self::with_names(consumer, NodeNames::default())
+    }
+
+    fn with_names(
+        consumer: Box<dyn BufferConsumerNode + Send + 'static>,
+        names: NodeNames,
+    ) -> Self {
+        Self {
+            consumer,
+            names,
+            buffer: vec![0.0; consts::BUFFER_SIZE * consts::CHANNEL_COUNT],
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl WorkletRenderer {
+    /// Build the graph of a config, given as the bytes of a file in the named format:
+    /// "ron", or "json" or "yaml" where those features are enabled. Assets the config names
+    /// are taken from the given assets; data inline in the config needs none.
+    pub fn from_config(
+        bytes: &[u8],
+        format: &str,
+        assets: Option<WorkletAssets>,
+    ) -> Result<WorkletRenderer, JsError> {
+        let format = match format {
+            "ron" => ConfigFormat::Ron,
+            #[cfg(feature = "json")]
+            "json" => ConfigFormat::Json,
+            #[cfg(feature = "yaml")]
+            "yaml" => ConfigFormat::Yaml,
+            _ => {
+                return Err(JsError::new(&format!(
+                    "Unsupported config format {}",
+                    format
+                )))
+            }
+        };
+        let config = Config::from_bytes_in_format(bytes, format)
+            .map_err(|e| JsError::new(&e.to_string()))?;
+        let loader = assets.unwrap_or_default().loader;
+        let (_, names, consumer) = loader
+            .load_source_with_names(&config.root)
+            .map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(Self::with_names(consumer, names))
+    }
+
+    pub fn sample_rate() -> u32 {
+        consts::PLAYBACK_SAMPLE_RATE as u32
+    }
+
+    /// Render into the two output channels of the processor, overwriting their contents.
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let frames = left.len().min(right.len()).min(consts::BUFFER_SIZE);
+        let buffer = &mut self.buffer[0..frames * consts::CHANNEL_COUNT];
+        buffer.fill(0.0);
+        self.consumer.fill_buffer(buffer);
+        for (index, frame) in buffer.chunks_exact(consts::CHANNEL_COUNT).enumerate() {
+            left[index] = frame[0];
+            right[index] = frame[1];
+        }
+    }
+
+    /// Apply an event written in RON as a HostEvent, such as
+    /// `Control(target: Name("lead_fader"), event: Fade(from: 1.0, to: 0.0, seconds: 2.0))`.
+    pub fn post_event(&mut self, event: &str) -> Result<(), JsError> {
+        let event: HostEvent =
+            ron::de::from_str(event).map_err(|e| JsError::new(&e.to_string()))?;
+        let event = event
+            .to_node_event(&self.names)
+            .map_err(|e| JsError::new(&e.to_string()))?;
+        self.consumer.on_event(&event);
+        Ok(())
+    }
+
+    pub fn note_on(&mut self, note: u8, vel: f32) {
+        self.consumer.on_event(&NodeEvent::Note {
+            note,
+            voice_id: None,
+            event: NoteEvent::NoteOn { vel },
+        });
+    }
+
+    pub fn note_off(&mut self, note: u8, vel: f32) {
+        self.consumer.on_event(&NodeEvent::Note {
+            note,
+            voice_id: None,
+            event: NoteEvent::NoteOff { vel },
+        });
+    }
+
+    pub fn notes_off(&mut self) {
+        self.consumer
+            .on_event(&NodeEvent::Broadcast(BroadcastControl::NotesOff));
+    }
+
+    pub fn stop(&mut self) {
+        self.consumer
+            .on_event(&NodeEvent::Broadcast(BroadcastControl::Stop));
+    }
+
+    pub fn set_volume(&mut self, node_id: u64, volume: f32) {
+        self.consumer.on_event(&NodeEvent::NodeControl {
+            node_id,
+            event: NodeControlEvent::Volume(volume),
+        });
+    }
+}
@@ -0,0 +1,30 @@
+// AudioWorkletGlobalScope has no TextDecoder or TextEncoder, which the wasm-bindgen glue
+// creates when it is imported. Only the UTF-8 handling it needs is provided here.
+
+if (typeof globalThis.TextDecoder === 'undefined') {
+    globalThis.TextDecoder = class {
+        decode(bytes) {
+            if (!bytes) {
+                return '';
+            }
+            let text = '';
+            for (let i = 0; i < bytes.length; i++) {
+                text += String.fromCharCode(bytes[i]);
+            }
+            return decodeURIComponent(escape(text));
+        }
+    };
+}
+
+if (typeof globalThis.TextEncoder === 'undefined') {
+    globalThis.TextEncoder = class {
+        encode(text) {
+            const binary = unescape(encodeURIComponent(text));
+            const bytes = new Uint8Array(binary.length);
+            for (let i = 0; i < binary.length; i++) {
+                bytes[i] = binary.charCodeAt(i);
+            }
+            return bytes;
+        }
+    };
+}
@@ -0,0 +1,55 @@
+import './worklet-polyfill.js';
+import { initSync, WorkletAssets, WorkletRenderer } from './pkg/midi_graph.js';
+
+class MidiGraphProcessor extends AudioWorkletProcessor {
+    constructor(options) {
+        super();
+        initSync({ module: options.processorOptions.module });
+        const { config, format, assets } = options.processorOptions;
+        this.renderer = config ? WorkletRenderer.from_config(config, format ?? 'ron', this.loadAssets(assets)) : WorkletRenderer.demo();
+        this.port.onmessage = (event) => this.onMessage(event.data);
+    }
+
+    // Assets arrive as an object mapping each path used in the config to its bytes
+    loadAssets(assets) {
+        if (!assets) {
+            return undefined;
+        }
+        const loaded = new WorkletAssets();
+        for (const [path, bytes] of Object.entries(assets)) {
+            loaded.insert(path, new Uint8Array(bytes));
+        }
+        return loaded;
+    }
+
+    onMessage(message) {
+        switch (message.type) {
+            case 'event':
+                this.renderer.post_event(message.event);
+                break;
+            case 'noteOn':
+                this.renderer.note_on(message.note, message.vel);
+                break;
+            case 'noteOff':
+                this.renderer.note_off(message.note, message.vel);
+                break;
+            case 'notesOff':
+                this.renderer.notes_off();
+                break;
+            case 'stop':
+                this.renderer.stop();
+                break;
+            case 'volume':
+                this.renderer.set_volume(BigInt(message.nodeId), message.volume);
+                break;
+        }
+    }
+
+    process(inputs, outputs) {
+        const output = outputs[0];
+        this.renderer.render(output[0], output[1]);
+        return true;
+    }
+}
+
+registerProcessor('midi-graph-processor', MidiGraphProcessor);